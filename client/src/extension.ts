@@ -12,10 +12,13 @@ import { activateLineNumbers } from "./line-numbers";
 import { activateNextPrev } from "./next-prev";
 import { activateProcSearch } from "./proc-search";
 import { activateRun } from "./run";
+import { activateScanReport } from "./scan-report";
 import {
   Executable,
   LanguageClient,
   LanguageClientOptions,
+  Location,
+  Position,
   CloseHandlerResult,
   ErrorHandlerResult,
   ErrorAction,
@@ -87,32 +90,144 @@ export async function activate(context: ExtensionContext) {
   );
   await client.start();
 
-  const scanAllCmd = commands.registerCommand("br.scanAll", async () => {
-    const result = await client.sendRequest<{ summary: string; csv: string }>("workspace/executeCommand", {
-      command: "br.scanAll",
-      arguments: [],
-    });
+  // Raised once per crossing, separately from the inline diagnostics
+  client.onNotification(
+    "br-lsp/parseHealth",
+    (params: { uri: string; syntaxErrors: number; threshold: number | null; rising: boolean }) => {
+      if (!params.rising) {
+        return;
+      }
+      const name = path.basename(Uri.parse(params.uri).fsPath);
+      window.showWarningMessage(
+        `${name} has ${params.syntaxErrors} syntax errors — wrong dialect, or not a BR program?`,
+      );
+    },
+  );
 
-    if (!result) {
-      window.showInformationMessage("No results from scan.");
-      return;
-    }
+  // Files the workspace scan left out of the index, once per scan
+  client.onNotification(
+    "br-lsp/indexParseFailures",
+    (params: { count: number; paths: string[] }) => {
+      const names = params.paths.map((p) => path.basename(p)).join(", ");
+      const more = params.count > params.paths.length ? ", …" : "";
+      window.showWarningMessage(
+        `br-lsp couldn't parse ${params.count} file(s), which are not indexed: ${names}${more}`,
+      );
+    },
+  );
 
-    const action = await window.showInformationMessage(result.summary, "Export CSV");
+  // Code lenses send LSP locations; the references peek view wants editor types
+  const showReferencesCmd = commands.registerCommand(
+    "br.showReferences",
+    (uri: string, position: Position, locations: Location[]) => {
+      const converter = client.protocol2CodeConverter;
+      return commands.executeCommand(
+        "editor.action.showReferences",
+        converter.asUri(uri),
+        converter.asPosition(position),
+        locations.map((location) => converter.asLocation(location)),
+      );
+    },
+  );
+  context.subscriptions.push(showReferencesCmd);
 
-    if (action === "Export CSV" && result.csv) {
-      const uri = await window.showSaveDialog({
-        defaultUri: Uri.joinPath(workspace.workspaceFolders?.[0]?.uri ?? Uri.file(""), "br-diagnostics.csv"),
-        filters: { "CSV files": ["csv"] },
+  // Before renaming a program, list the CHAIN, LIBRARY and RUN strings naming it
+  const programReferencesCmd = commands.registerCommand(
+    "br-lsp.findProgramReferences",
+    async (uri?: Uri) => {
+      const target = uri ?? window.activeTextEditor?.document.uri;
+      if (!target) {
+        return;
+      }
+      const locations = await client.sendRequest<Location[] | null>("workspace/executeCommand", {
+        command: "br-lsp.findProgramReferences",
+        arguments: [target.toString()],
       });
+      if (!locations?.length) {
+        const name = path.basename(target.fsPath);
+        window.showInformationMessage(`No CHAIN, LIBRARY or RUN statement names ${name}.`);
+        return;
+      }
+      const converter = client.protocol2CodeConverter;
+      return commands.executeCommand(
+        "editor.action.showReferences",
+        target,
+        converter.asPosition(Position.create(0, 0)),
+        locations.map((location) => converter.asLocation(location)),
+      );
+    },
+  );
+  context.subscriptions.push(programReferencesCmd);
 
-      if (uri) {
-        await workspace.fs.writeFile(uri, new Uint8Array(Buffer.from(result.csv, "utf-8")));
-        window.showInformationMessage(`Diagnostics exported to ${uri.fsPath}`);
+  // The server applies the change at once; the setting keeps it across sessions
+  for (const command of ["br-lsp.muteFile", "br-lsp.unmuteFile"]) {
+    const muteCmd = commands.registerCommand(command, async (uri?: Uri) => {
+      const target = uri ?? window.activeTextEditor?.document.uri;
+      if (!target) {
+        return;
       }
+      const result = await client.sendRequest<{ changed: boolean; mutedFiles: string[] }>(
+        "workspace/executeCommand",
+        { command, arguments: [target.toString()] },
+      );
+      if (result?.changed) {
+        await workspace.getConfiguration("br-lsp", target).update("mutedFiles", result.mutedFiles);
+      }
+    });
+    context.subscriptions.push(muteCmd);
+  }
+
+  // The server composes one edit and applies it through workspace/applyEdit
+  const fixAllCmd = commands.registerCommand("br-lsp.fixAll", async () => {
+    const uri = window.activeTextEditor?.document.uri;
+    if (uri) {
+      await client.sendRequest("workspace/executeCommand", {
+        command: "br-lsp.fixAll",
+        arguments: [uri.toString()],
+      });
+    }
+  });
+  context.subscriptions.push(fixAllCmd);
+
+  const fixAllWorkspaceCmd = commands.registerCommand("br-lsp.fixAllInWorkspace", () =>
+    client.sendRequest("workspace/executeCommand", {
+      command: "br-lsp.fixAll",
+      arguments: [{ workspace: true }],
+    }),
+  );
+  context.subscriptions.push(fixAllWorkspaceCmd);
+
+  // Quick fixes for duplicate or out-of-order line numbers pass the URI;
+  // from the palette it's the active editor's file
+  const renumberCmd = commands.registerCommand("br-lsp.renumberLines", async (uri?: string) => {
+    const target = uri ?? window.activeTextEditor?.document.uri.toString();
+    if (!target) {
+      return;
+    }
+    const increment = workspace
+      .getConfiguration("br", Uri.parse(target))
+      .get<number>("autoLineNumbers.increment", 10);
+    await client.sendRequest("workspace/executeCommand", {
+      command: "br-lsp.renumberLines",
+      arguments: [{ uri: target, start: increment, increment }],
+    });
+  });
+  context.subscriptions.push(renumberCmd);
+
+  const insertHeaderCmd = commands.registerCommand("br-lsp.insertHeader", async () => {
+    const uri = window.activeTextEditor?.document.uri;
+    if (!uri) {
+      return;
+    }
+    const result = await client.sendRequest<{ inserted: boolean } | null>("workspace/executeCommand", {
+      command: "br-lsp.insertHeader",
+      arguments: [uri.toString()],
+    });
+    if (result && !result.inserted) {
+      window.showInformationMessage("This file already starts with the header.");
     }
   });
-  context.subscriptions.push(scanAllCmd);
+  context.subscriptions.push(insertHeaderCmd);
 
   activateCompile(context);
   activateDebug(context);
@@ -124,6 +239,7 @@ export async function activate(context: ExtensionContext) {
   activateLineNumbers(context);
   activateNextPrev(context);
   activateProcSearch(context);
+  activateScanReport(context, client);
 }
 
 export function deactivate(): Thenable<void> | undefined {
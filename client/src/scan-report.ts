@@ -0,0 +1,180 @@
+import * as vscode from "vscode";
+import { LanguageClient } from "vscode-languageclient/node";
+
+type GroupBy = "file" | "rule";
+type Severity = "error" | "warning" | "information" | "hint" | "unknown";
+
+interface ScanDiagnostic {
+  uri: string;
+  code: string;
+  severity: Severity;
+  message: string;
+  range: { start: { line: number; character: number }; end: { line: number; character: number } };
+}
+
+interface ScanGroup {
+  key: string;
+  label: string;
+  counts: Partial<Record<Severity, number>>;
+  diagnostics: ScanDiagnostic[];
+}
+
+interface ScanAllResult {
+  summary: string;
+  csv: string;
+  groupBy: GroupBy;
+  groups: ScanGroup[];
+}
+
+const SEVERITY_ICONS: Record<Severity, [string, string]> = {
+  error: ["error", "problemsErrorIcon.foreground"],
+  warning: ["warning", "problemsWarningIcon.foreground"],
+  information: ["info", "problemsInfoIcon.foreground"],
+  hint: ["lightbulb", "problemsInfoIcon.foreground"],
+  unknown: ["circle-outline", "foreground"],
+};
+
+let groupBy: GroupBy = "file";
+let scanResultsProvider: ScanResultsProvider;
+
+// --- Tree ---
+
+function severityIcon(severity: Severity): vscode.ThemeIcon {
+  const [icon, color] = SEVERITY_ICONS[severity] ?? SEVERITY_ICONS.unknown;
+  return new vscode.ThemeIcon(icon, new vscode.ThemeColor(color));
+}
+
+/** The most severe level with a count, for the group's icon */
+function worstSeverity(counts: ScanGroup["counts"]): Severity {
+  const order: Severity[] = ["error", "warning", "information", "hint"];
+  return order.find((severity) => counts[severity]) ?? "unknown";
+}
+
+class ScanDiagnosticItem extends vscode.TreeItem {
+  constructor(diagnostic: ScanDiagnostic, showFile: boolean) {
+    super(diagnostic.message, vscode.TreeItemCollapsibleState.None);
+    const uri = vscode.Uri.parse(diagnostic.uri);
+    const { start, end } = diagnostic.range;
+    this.description = showFile
+      ? `${vscode.workspace.asRelativePath(uri)}:${start.line + 1}`
+      : `${diagnostic.code} · Ln ${start.line + 1}`;
+    this.tooltip = `${diagnostic.code}: ${diagnostic.message}`;
+    this.iconPath = severityIcon(diagnostic.severity);
+    this.command = {
+      command: "vscode.open",
+      title: "Open",
+      arguments: [
+        uri,
+        { selection: new vscode.Range(start.line, start.character, end.line, end.character) },
+      ],
+    };
+  }
+}
+
+class ScanGroupItem extends vscode.TreeItem {
+  constructor(
+    public readonly group: ScanGroup,
+    by: GroupBy,
+  ) {
+    const label = by === "file" ? vscode.workspace.asRelativePath(vscode.Uri.parse(group.key)) : group.label;
+    super(label, vscode.TreeItemCollapsibleState.Collapsed);
+    this.description = Object.entries(group.counts)
+      .map(([severity, count]) => `${count} ${severity}`)
+      .join(", ");
+    this.tooltip = group.label;
+    this.iconPath = severityIcon(worstSeverity(group.counts));
+    if (by === "file") {
+      this.resourceUri = vscode.Uri.parse(group.key);
+    }
+  }
+}
+
+class ScanResultsProvider implements vscode.TreeDataProvider<vscode.TreeItem> {
+  private _onDidChangeTreeData = new vscode.EventEmitter<vscode.TreeItem | undefined | null | void>();
+  readonly onDidChangeTreeData = this._onDidChangeTreeData.event;
+
+  private groups: ScanGroupItem[] = [];
+
+  refresh(result: ScanAllResult): void {
+    this.groups = result.groups.map((group) => new ScanGroupItem(group, result.groupBy));
+    this._onDidChangeTreeData.fire();
+  }
+
+  getTreeItem(element: vscode.TreeItem): vscode.TreeItem {
+    return element;
+  }
+
+  getChildren(element?: vscode.TreeItem): vscode.ProviderResult<vscode.TreeItem[]> {
+    if (!element) {
+      return this.groups;
+    }
+    if (element instanceof ScanGroupItem) {
+      const showFile = !element.resourceUri;
+      return element.group.diagnostics.map((diagnostic) => new ScanDiagnosticItem(diagnostic, showFile));
+    }
+    return [];
+  }
+}
+
+// --- Scanning ---
+
+async function scan(client: LanguageClient): Promise<ScanAllResult | undefined> {
+  const result = await client.sendRequest<ScanAllResult | null>("workspace/executeCommand", {
+    command: "br.scanAll",
+    arguments: [{ groupBy }],
+  });
+  if (!result) {
+    vscode.window.showInformationMessage("No results from scan.");
+    return undefined;
+  }
+  scanResultsProvider.refresh(result);
+  await vscode.commands.executeCommand("setContext", "br.scanResultsAvailable", true);
+  return result;
+}
+
+async function scanAll(client: LanguageClient): Promise<void> {
+  const result = await scan(client);
+  if (!result) {
+    return;
+  }
+  await vscode.commands.executeCommand("brScanResults.focus");
+
+  const action = await vscode.window.showInformationMessage(result.summary, "Export CSV");
+
+  if (action === "Export CSV" && result.csv) {
+    const uri = await vscode.window.showSaveDialog({
+      defaultUri: vscode.Uri.joinPath(
+        vscode.workspace.workspaceFolders?.[0]?.uri ?? vscode.Uri.file(""),
+        "br-diagnostics.csv",
+      ),
+      filters: { "CSV files": ["csv"] },
+    });
+
+    if (uri) {
+      await vscode.workspace.fs.writeFile(uri, new Uint8Array(Buffer.from(result.csv, "utf-8")));
+      vscode.window.showInformationMessage(`Diagnostics exported to ${uri.fsPath}`);
+    }
+  }
+}
+
+// --- Activation ---
+
+export function activateScanReport(context: vscode.ExtensionContext, client: LanguageClient): void {
+  scanResultsProvider = new ScanResultsProvider();
+  const treeView = vscode.window.createTreeView("brScanResults", {
+    treeDataProvider: scanResultsProvider,
+    showCollapseAll: true,
+  });
+
+  const regroup = (by: GroupBy) => {
+    groupBy = by;
+    return scan(client);
+  };
+
+  context.subscriptions.push(
+    treeView,
+    vscode.commands.registerCommand("br.scanAll", () => scanAll(client)),
+    vscode.commands.registerCommand("br-lsp.groupScanResultsByFile", () => regroup("file")),
+    vscode.commands.registerCommand("br-lsp.groupScanResultsByRule", () => regroup("rule")),
+  );
+}
@@ -0,0 +1,140 @@
+use dashmap::DashMap;
+use tower_lsp::lsp_types::{Diagnostic, DocumentSymbol, SemanticToken};
+
+/// Results computed for one version of a document.
+#[derive(Default)]
+struct Entry {
+    version: u64,
+    /// Diagnostics also depend on configuration and the workspace index, so
+    /// they carry the diagnostics epoch they were computed under.
+    diagnostics: Option<(u64, Vec<Diagnostic>)>,
+    semantic_tokens: Option<Vec<SemanticToken>>,
+    symbols: Option<Vec<DocumentSymbol>>,
+}
+
+/// Last analysis results per open document, keyed by the document's
+/// version so repeated requests against an unchanged document skip the
+/// recomputation. Storing a result for a newer version discards everything
+/// cached for older ones.
+#[derive(Default)]
+pub struct AnalysisCache {
+    entries: DashMap<String, Entry>,
+}
+
+impl AnalysisCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn get<T: Clone>(
+        &self,
+        uri: &str,
+        version: u64,
+        field: impl FnOnce(&Entry) -> Option<&T>,
+    ) -> Option<T> {
+        let entry = self.entries.get(uri)?;
+        if entry.version != version {
+            return None;
+        }
+        field(&entry).cloned()
+    }
+
+    /// Record a result for `version`. A result computed against an older
+    /// version than the one already cached is stale and dropped.
+    fn store(&self, uri: &str, version: u64, update: impl FnOnce(&mut Entry)) {
+        let mut entry = self.entries.entry(uri.to_string()).or_default();
+        if entry.version > version {
+            return;
+        }
+        if entry.version < version {
+            *entry = Entry {
+                version,
+                ..Entry::default()
+            };
+        }
+        update(&mut entry);
+    }
+
+    pub fn symbols(&self, uri: &str, version: u64) -> Option<Vec<DocumentSymbol>> {
+        self.get(uri, version, |e| e.symbols.as_ref())
+    }
+
+    pub fn store_symbols(&self, uri: &str, version: u64, symbols: Vec<DocumentSymbol>) {
+        self.store(uri, version, |e| e.symbols = Some(symbols));
+    }
+
+    pub fn semantic_tokens(&self, uri: &str, version: u64) -> Option<Vec<SemanticToken>> {
+        self.get(uri, version, |e| e.semantic_tokens.as_ref())
+    }
+
+    pub fn store_semantic_tokens(&self, uri: &str, version: u64, tokens: Vec<SemanticToken>) {
+        self.store(uri, version, |e| e.semantic_tokens = Some(tokens));
+    }
+
+    pub fn diagnostics(&self, uri: &str, version: u64, epoch: u64) -> Option<Vec<Diagnostic>> {
+        self.get(uri, version, |entry| {
+            entry
+                .diagnostics
+                .as_ref()
+                .filter(|(e, _)| *e == epoch)
+                .map(|(_, d)| d)
+        })
+    }
+
+    pub fn store_diagnostics(&self, uri: &str, version: u64, epoch: u64, diags: Vec<Diagnostic>) {
+        self.store(uri, version, |e| e.diagnostics = Some((epoch, diags)));
+    }
+
+    pub fn remove(&self, uri: &str) {
+        self.entries.remove(uri);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(line: u32) -> SemanticToken {
+        SemanticToken {
+            delta_line: line,
+            delta_start: 0,
+            length: 1,
+            token_type: 0,
+            token_modifiers_bitset: 0,
+        }
+    }
+
+    #[test]
+    fn hits_only_for_the_cached_version() {
+        let cache = AnalysisCache::new();
+        cache.store_semantic_tokens("a", 1, vec![token(0)]);
+        assert_eq!(cache.semantic_tokens("a", 1), Some(vec![token(0)]));
+        assert_eq!(cache.semantic_tokens("a", 2), None);
+        assert_eq!(cache.semantic_tokens("b", 1), None);
+    }
+
+    #[test]
+    fn newer_version_replaces_older_results() {
+        let cache = AnalysisCache::new();
+        cache.store_semantic_tokens("a", 1, vec![token(0)]);
+        cache.store_symbols("a", 2, Vec::new());
+        assert_eq!(cache.semantic_tokens("a", 1), None);
+        assert_eq!(cache.symbols("a", 2), Some(Vec::new()));
+
+        // A slow request finishing for version 1 doesn't clobber version 2
+        cache.store_semantic_tokens("a", 1, vec![token(5)]);
+        assert_eq!(cache.semantic_tokens("a", 1), None);
+        assert_eq!(cache.symbols("a", 2), Some(Vec::new()));
+    }
+
+    #[test]
+    fn diagnostics_are_keyed_by_epoch() {
+        let cache = AnalysisCache::new();
+        cache.store_diagnostics("a", 1, 7, Vec::new());
+        assert_eq!(cache.diagnostics("a", 1, 7), Some(Vec::new()));
+        assert_eq!(cache.diagnostics("a", 1, 8), None);
+
+        cache.remove("a");
+        assert_eq!(cache.diagnostics("a", 1, 7), None);
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 
@@ -5,27 +7,54 @@ use dashmap::DashMap;
 use log::{debug, error, warn};
 use rayon::prelude::*;
 use ropey::Rope;
+use serde::Deserialize;
 use serde_json::Value;
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::{notification, request, *};
 use tower_lsp::{Client, LanguageServer};
 use tree_sitter::{InputEdit, Point, Tree};
-use walkdir::WalkDir;
 
 const DIAGNOSTICS_DEBOUNCE_MS: u64 = 150;
+/// Delays between fresh full-parse attempts for a document left without a tree.
+const REPARSE_BACKOFF_MS: &[u64] = &[100, 400, 1600];
+/// How often a disabled index poll looks at its setting again.
+const POLL_SETTING_CHECK: std::time::Duration = std::time::Duration::from_secs(30);
 
-use crate::builtins;
-use crate::check;
-use crate::code_action;
+/// A file's definitions and calls, read for the workspace index.
+type IndexedFile = (Url, Vec<extract::FunctionDef>, Vec<call_graph::Call>);
+
+use crate::analysis_cache::AnalysisCache;
+use crate::builtins::BrVersion;
+use crate::call_graph;
 use crate::completions;
-use crate::definition;
-use crate::diagnostics;
+use crate::config_change::{self, Rerun};
+use crate::diagnostics::{self, VariableUsage};
+use crate::diagnostics_queue::{DiagnosticsQueue, DIAGNOSTICS_CONCURRENCY};
+use crate::dialect::{self, Dialect};
+use crate::document_links;
+use crate::export;
+use crate::external_checker;
 use crate::extract;
+use crate::files;
+use crate::generated;
+use crate::handlers;
+use crate::header;
+use crate::library_deps;
+use crate::line_numbers;
+use crate::logging::{LogLevel, Logger};
+use crate::metrics;
+use crate::on_type_formatting;
+use crate::parse_health;
 use crate::parser;
+use crate::profiles::LintProfile;
+use crate::published::PublishedDiagnostics;
 use crate::references;
-use crate::rename;
+use crate::rules;
 use crate::semantic_tokens;
-use crate::symbols;
+use crate::shadowing;
+use crate::snippets;
+use crate::structure::Structure;
+use crate::style;
 use crate::workspace::{self, WorkspaceIndex};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -37,29 +66,341 @@ pub enum DocumentKind {
 pub struct DocumentState {
     pub kind: DocumentKind,
     pub rope: Rope,
-    pub source: String,
+    /// Shared with the snapshots taken of this version
+    pub source: Arc<str>,
+    pub tree: Option<Tree>,
+    /// Bumped on every edit or reparse; keys the analysis cache.
+    pub version: u64,
+    /// The version the client last sent, for edits that must land on the
+    /// text they were computed from.
+    pub client_version: i32,
+    /// Reference counts from the last unused-variable check, kept in step
+    /// with the edits made since.
+    pub variable_usage: VariableUsage,
+}
+
+/// Immutable copy of a document taken at the start of a request, so analysis
+/// never touches `document_map` mid-request and no map guard is held across
+/// an await. Cloning a snapshot is cheap: the source is shared and a
+/// tree-sitter tree clone only bumps a reference count.
+#[derive(Clone)]
+pub struct DocumentSnapshot {
+    pub kind: DocumentKind,
+    pub source: Arc<str>,
     pub tree: Option<Tree>,
+    pub version: u64,
+    pub client_version: i32,
+}
+
+impl DocumentState {
+    pub fn snapshot(&self) -> DocumentSnapshot {
+        DocumentSnapshot {
+            kind: self.kind,
+            source: Arc::clone(&self.source),
+            tree: self.tree.clone(),
+            version: self.version,
+            client_version: self.client_version,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct DiagnosticsConfig {
+    /// Profile from `br.diagnostics.profile`, already applied to the switches below
+    pub profile: LintProfile,
     pub syntax: bool,
+    /// DEFs without FNEND, reported even with `functions` off (legacy profile)
+    pub missing_fnend: bool,
     pub functions: bool,
     pub undefined_functions: bool,
     pub unused_variables: bool,
+    pub unused_parameters: bool,
+    pub file_numbers: bool,
+    pub value_param_assignment: bool,
+    /// Error-condition clauses jumping to undefined labels or lines
+    pub error_conditions: bool,
+    /// PIC masks in FORM statements and layout files
+    pub pic_masks: bool,
+    /// Line numbers used twice or lower than the line before
+    pub line_numbers: bool,
+    /// Functions and variables named like builtins, and library functions
+    /// exported elsewhere with other parameters
+    pub shadowing: bool,
+    /// Target BR version from `br-lsp.brVersion`; `None` disables version gating
+    pub br_version: Option<BrVersion>,
+    /// Keyword case convention from `br-lsp.style.keywordCase`; `None` disables the rule
+    pub keyword_case: Option<style::KeywordCase>,
+    /// Statements, keywords and builtins only `.brs` or `.wbs` files accept,
+    /// from `br-lsp.dialects`
+    pub dialects: dialect::DialectConfig,
+    /// Naming rules from `br-lsp.style.naming`
+    pub naming: style::NamingConfig,
+    /// From `br-lsp.readOnlyWorkspace`: never propose edits to closed files
+    pub read_only_workspace: bool,
+    /// From `br-lsp.references.includeStringReferences`: references on a
+    /// program name in a CHAIN, LIBRARY or EXECUTE string find every string
+    /// naming that program
+    pub include_string_references: bool,
+    /// Virtual drive prefixes from `br-lsp.driveMappings`, which LIBRARY
+    /// statements resolve through
+    pub drive_mappings: workspace::DriveMappings,
+    /// Function size and complexity limits from `br-lsp.metrics`
+    pub metrics: metrics::MetricsLimits,
+    /// BR compiler or lexer from `br-lsp.externalChecker`, run on save
+    pub external_checker: Option<std::path::PathBuf>,
+    /// Files from `br-lsp.mutedFiles` and the mute commands that get no
+    /// diagnostics
+    pub muted_files: workspace::MutedFiles,
+    /// Files from `br-lsp.generatedFiles`, or marked by their first lines,
+    /// that get no style or dead-code diagnostics
+    pub generated_files: generated::GeneratedFiles,
+    /// Syntax error counts from `br-lsp.parseHealthThresholds`, ascending,
+    /// that send `br-lsp/parseHealth` when crossed
+    pub parse_health_thresholds: Vec<usize>,
 }
 
 impl Default for DiagnosticsConfig {
     fn default() -> Self {
         Self {
+            profile: LintProfile::Default,
             syntax: true,
+            missing_fnend: false,
             functions: true,
             undefined_functions: true,
             unused_variables: true,
+            unused_parameters: true,
+            file_numbers: true,
+            value_param_assignment: true,
+            error_conditions: true,
+            pic_masks: true,
+            line_numbers: true,
+            shadowing: true,
+            br_version: None,
+            keyword_case: None,
+            dialects: dialect::DialectConfig::default(),
+            naming: style::NamingConfig::default(),
+            read_only_workspace: false,
+            include_string_references: false,
+            drive_mappings: workspace::DriveMappings::default(),
+            metrics: metrics::MetricsLimits::default(),
+            external_checker: None,
+            muted_files: workspace::MutedFiles::default(),
+            generated_files: generated::GeneratedFiles::default(),
+            parse_health_thresholds: parse_health::DEFAULT_THRESHOLDS.to_vec(),
+        }
+    }
+}
+
+impl DiagnosticsConfig {
+    /// The diagnostics to publish for `uri`: none for a muted file.
+    pub fn published(&self, uri: &Url, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        if self.muted_files.contains(uri) {
+            Vec::new()
+        } else {
+            diagnostics
+        }
+    }
+}
+
+/// Everything pulled from the client's settings: the diagnostics and,
+/// beside them, the settings of the other features.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub diagnostics: DiagnosticsConfig,
+    /// Insert behaviour from `br-lsp.completion`
+    pub completion: completions::CompletionOptions,
+    pub code_lens: CodeLensConfig,
+    pub paths: PathConfig,
+    pub index: IndexConfig,
+    /// Program header from `br-lsp.header`, inserted by `br-lsp.insertHeader`
+    pub header: header::HeaderTemplate,
+    /// Numbering of new lines typed in numbered programs, from `br.autoLineNumbers`
+    pub auto_line_numbers: on_type_formatting::AutoLineNumbers,
+}
+
+/// Which lenses `br-lsp.codeLens` shows.
+#[derive(Debug, Clone)]
+pub struct CodeLensConfig {
+    /// From `br-lsp.codeLens.externalUsages`: count other files' references
+    /// above each DEF LIBRARY
+    pub external_usages: bool,
+    /// From `br-lsp.codeLens.metrics`: show each function's size and
+    /// complexity above its definition
+    pub metrics: bool,
+}
+
+impl Default for CodeLensConfig {
+    fn default() -> Self {
+        Self {
+            external_usages: true,
+            metrics: false,
+        }
+    }
+}
+
+/// Which files are BR programs and where program names are looked up.
+#[derive(Debug, Clone, Default)]
+pub struct PathConfig {
+    /// Extra directories from `br-lsp.programSearchPaths` for resolving
+    /// CHAIN/RUN/LIBRARY program names
+    pub program_search_paths: Vec<String>,
+    /// Detection of extensionless BR programs from `br-lsp.extensionlessFiles`
+    pub br_files: workspace::BrFileDetection,
+}
+
+/// How the workspace index is built and kept current.
+#[derive(Debug, Clone, Default)]
+pub struct IndexConfig {
+    /// Workspace scan parallelism and throttling from `br-lsp.indexing`
+    pub indexing: workspace::IndexingOptions,
+    /// From `br-lsp.index.pollIntervalSeconds`: how often to check indexed
+    /// files for changes the client's watcher missed
+    pub poll_interval: Option<std::time::Duration>,
+}
+
+/// Optional client features captured from `initialize`. Defaults assume a
+/// fully featured client until the real capabilities arrive.
+#[derive(Debug, Clone)]
+pub struct ClientFeatures {
+    pub hover_markdown: bool,
+    pub completion_markdown: bool,
+    pub signature_markdown: bool,
+    pub label_offsets: bool,
+    pub completion_resolve: bool,
+    pub completion_snippets: bool,
+    pub commit_characters: bool,
+    pub watcher_registration: bool,
+    pub change_annotations: bool,
+    pub code_action_resolve: bool,
+}
+
+impl Default for ClientFeatures {
+    fn default() -> Self {
+        Self {
+            hover_markdown: true,
+            completion_markdown: true,
+            signature_markdown: true,
+            label_offsets: true,
+            completion_resolve: true,
+            completion_snippets: true,
+            commit_characters: true,
+            watcher_registration: true,
+            change_annotations: true,
+            code_action_resolve: true,
+        }
+    }
+}
+
+impl ClientFeatures {
+    pub fn from_capabilities(caps: &ClientCapabilities) -> Self {
+        let text = caps.text_document.as_ref();
+        let supports_markdown = |formats: Option<&Vec<MarkupKind>>| {
+            formats.is_some_and(|f| f.contains(&MarkupKind::Markdown))
+        };
+
+        let completion_item = text
+            .and_then(|t| t.completion.as_ref())
+            .and_then(|c| c.completion_item.as_ref());
+        let signature_info = text
+            .and_then(|t| t.signature_help.as_ref())
+            .and_then(|s| s.signature_information.as_ref());
+
+        let code_action_resolve = text
+            .and_then(|t| t.code_action.as_ref())
+            .and_then(|c| c.resolve_support.as_ref())
+            .is_some_and(|r| r.properties.iter().any(|p| p == "edit"));
+
+        Self {
+            hover_markdown: supports_markdown(
+                text.and_then(|t| t.hover.as_ref())
+                    .and_then(|h| h.content_format.as_ref()),
+            ),
+            completion_markdown: supports_markdown(
+                completion_item.and_then(|c| c.documentation_format.as_ref()),
+            ),
+            signature_markdown: supports_markdown(
+                signature_info.and_then(|s| s.documentation_format.as_ref()),
+            ),
+            label_offsets: signature_info
+                .and_then(|s| s.parameter_information.as_ref())
+                .and_then(|p| p.label_offset_support)
+                .unwrap_or(false),
+            completion_resolve: completion_item.is_some_and(|c| c.resolve_support.is_some()),
+            completion_snippets: completion_item
+                .and_then(|c| c.snippet_support)
+                .unwrap_or(false),
+            commit_characters: completion_item
+                .and_then(|c| c.commit_characters_support)
+                .unwrap_or(false),
+            watcher_registration: caps
+                .workspace
+                .as_ref()
+                .and_then(|w| w.did_change_watched_files.as_ref())
+                .and_then(|d| d.dynamic_registration)
+                .unwrap_or(false),
+            change_annotations: caps
+                .workspace
+                .as_ref()
+                .and_then(|w| w.workspace_edit.as_ref())
+                .is_some_and(|e| {
+                    e.document_changes == Some(true) && e.change_annotation_support.is_some()
+                }),
+            code_action_resolve,
+        }
+    }
+}
+
+/// Markdown content, downgraded to plain text for clients that can't render it.
+pub(crate) fn markup_content(value: String, markdown: bool) -> MarkupContent {
+    if markdown {
+        MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }
+    } else {
+        MarkupContent {
+            kind: MarkupKind::PlainText,
+            value: markdown_to_plaintext(&value),
         }
     }
 }
 
+/// Strip the markdown this server generates: code fences, rules, bold and
+/// emphasis markers, and inline code ticks.
+fn markdown_to_plaintext(md: &str) -> String {
+    md.lines()
+        .filter(|l| !l.trim_start().starts_with("```") && l.trim() != "---")
+        .map(|l| {
+            let l = l
+                .replace("**", "")
+                .replace('`', "")
+                .replace("*@param*", "@param")
+                .replace("*@returns*", "@returns");
+            match l.strip_prefix('*').and_then(|r| r.strip_suffix('*')) {
+                Some(inner) => inner.to_string(),
+                None => l,
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Last path segment of a URI, for user-facing messages.
+pub(crate) fn file_display_name(uri: &Url) -> String {
+    uri.path_segments()
+        .and_then(|mut segs| segs.next_back().map(|s| s.to_string()))
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| uri.to_string())
+}
+
+/// Parameters of the custom `br-lsp/functionInfo` request.
+#[derive(Debug, Deserialize)]
+pub struct FunctionInfoParams {
+    pub name: String,
+    /// Document the name is used in, for resolving LIBRARY imports
+    pub uri: Option<Url>,
+}
+
 pub struct Backend {
     pub client: Client,
     pub document_map: Arc<DashMap<String, DocumentState>>,
@@ -68,28 +409,192 @@ pub struct Backend {
     pub layout_index: Arc<tokio::sync::RwLock<crate::layout::LayoutIndex>>,
     pub workspace_folders: Arc<tokio::sync::RwLock<Vec<Url>>>,
     pub indexing_complete: Arc<AtomicBool>,
+    /// Paths the workspace walks skipped (symlink loops, unreadable or too
+    /// deep directories), for `br-lsp/status`.
+    pub walk_report: Arc<tokio::sync::RwLock<workspace::WalkReport>>,
+    /// Files the workspace scans couldn't read or parse, for `br-lsp/status`.
+    pub parse_failures: Arc<tokio::sync::RwLock<Vec<parse_health::ParseFailure>>>,
     pub diagnostics_generation: Arc<DashMap<String, Arc<AtomicU64>>>,
-    pub diagnostics_config: Arc<tokio::sync::RwLock<DiagnosticsConfig>>,
-    pub symbol_cache: DashMap<String, Vec<DocumentSymbol>>,
+    pub config: Arc<tokio::sync::RwLock<Config>>,
+    pub analysis_cache: Arc<AnalysisCache>,
+    /// Bumped when diagnostics inputs other than the document itself change
+    /// (configuration, the completed workspace index), invalidating cached
+    /// diagnostics.
+    pub diagnostics_epoch: Arc<AtomicU64>,
+    /// Limits how many documents compute diagnostics at once, most recently
+    /// opened or changed first.
+    pub diagnostics_queue: Arc<DiagnosticsQueue>,
+    /// What the external checker reported at each document's last save,
+    /// published alongside the native diagnostics
+    pub external_diagnostics: Arc<DashMap<String, Vec<Diagnostic>>>,
+    /// Syntax error thresholds each open document has reached
+    pub parse_health: Arc<parse_health::ParseHealth>,
+    /// What was last sent for each document, so unchanged sets aren't resent
+    pub published_diagnostics: Arc<PublishedDiagnostics>,
+    /// Snippets from the workspace folders' `.br-lsp/snippets.json`
+    pub workspace_snippets: Arc<tokio::sync::RwLock<Vec<snippets::Snippet>>>,
+    pub client_features: Arc<tokio::sync::RwLock<ClientFeatures>>,
+    pub logger: Arc<Logger>,
+}
+
+/// What republishing the open documents' diagnostics needs, cloned out of
+/// the backend for tasks that outlive a request.
+#[derive(Clone)]
+struct Republisher {
+    client: Client,
+    document_map: Arc<DashMap<String, DocumentState>>,
+    workspace_index: Arc<tokio::sync::RwLock<WorkspaceIndex>>,
+    layout_index: Arc<tokio::sync::RwLock<crate::layout::LayoutIndex>>,
+    indexing_complete: Arc<AtomicBool>,
+    config: Arc<tokio::sync::RwLock<Config>>,
+    analysis_cache: Arc<AnalysisCache>,
+    diagnostics_epoch: Arc<AtomicU64>,
+    external_diagnostics: Arc<DashMap<String, Vec<Diagnostic>>>,
+    published_diagnostics: Arc<PublishedDiagnostics>,
+}
+
+impl Republisher {
+    /// See [`Backend::republish_diagnostics`].
+    async fn republish(&self, rerun: &Rerun) {
+        self.republish_where(rerun, |_| true).await;
+    }
+
+    /// [`Self::republish`] for the open documents whose URI `wanted` accepts.
+    async fn republish_where(&self, rerun: &Rerun, wanted: impl Fn(&str) -> bool) {
+        if *rerun == Rerun::Nothing {
+            return;
+        }
+        // Configuration or indexes changed, so cached diagnostics are stale
+        let epoch = self.diagnostics_epoch.fetch_add(1, Ordering::SeqCst) + 1;
+        let settings = self.config.read().await;
+        let config = &settings.diagnostics;
+        let index = if self.indexing_complete.load(Ordering::Acquire) {
+            Some(self.workspace_index.read().await)
+        } else {
+            None
+        };
+        let layouts = self.layout_index.read().await;
+
+        let to_publish: Vec<(String, Vec<Diagnostic>)> = self
+            .document_map
+            .iter()
+            .filter(|entry| wanted(entry.key()))
+            .filter_map(|entry| {
+                let uri_string = entry.key().clone();
+                let doc = entry.value();
+                if doc.kind == DocumentKind::Layout {
+                    return Some((uri_string, Backend::layout_diagnostics(&doc.source, config)));
+                }
+                let t = doc.tree.as_ref()?;
+                let uri = Url::parse(&uri_string).ok()?;
+                let cached = match rerun {
+                    Rerun::Rules { .. } => {
+                        self.analysis_cache
+                            .diagnostics(&uri_string, doc.version, epoch - 1)
+                    }
+                    _ => None,
+                };
+                let diags = match (rerun, cached) {
+                    (Rerun::Rules { removed, added }, Some(mut diags)) => {
+                        diags.retain(|d| match &d.code {
+                            Some(NumberOrString::String(code)) => !removed.contains(&code.as_str()),
+                            _ => true,
+                        });
+                        let mut found = rules::run_all(
+                            added,
+                            &uri,
+                            t,
+                            &doc.source,
+                            config,
+                            index.as_deref(),
+                            Some(&layouts),
+                        );
+                        if config.generated_files.contains(&uri, &doc.source) {
+                            generated::strip_style(&mut found);
+                        }
+                        diags.extend(found);
+                        diags
+                    }
+                    _ => Backend::collect_all_diagnostics(
+                        &uri,
+                        t,
+                        &doc.source,
+                        config,
+                        index.as_deref(),
+                        Some(&layouts),
+                        None,
+                    ),
+                };
+                self.analysis_cache.store_diagnostics(
+                    &uri_string,
+                    doc.version,
+                    epoch,
+                    diags.clone(),
+                );
+                Some((uri_string, diags))
+            })
+            .collect();
+
+        // Everything is computed before anything is sent, and only sets that
+        // changed are, so the client redraws once and only where needed
+        let total = to_publish.len();
+        let changed: Vec<(Url, Vec<Diagnostic>)> = to_publish
+            .into_iter()
+            .filter_map(|(uri_string, diags)| {
+                let uri = Url::parse(&uri_string).ok()?;
+                let diags =
+                    external_checker::merged(&self.external_diagnostics, &uri_string, diags);
+                let diags = config.published(&uri, diags);
+                self.published_diagnostics
+                    .record(&uri, &diags)
+                    .then_some((uri, diags))
+            })
+            .collect();
+        drop((settings, index, layouts));
+
+        debug!(
+            "republishing diagnostics: {} of {total} changed",
+            changed.len()
+        );
+        for (uri, diags) in changed {
+            self.client.publish_diagnostics(uri, diags, None).await;
+        }
+    }
 }
 
 struct TextDocumentItem {
     uri: Url,
     text: String,
     language_id: String,
+    version: i32,
+}
+
+/// Char index of an LSP position. Columns count UTF-16 code units. As the
+/// spec asks, a column past the end of the line (as clients that expand
+/// tabs send) means the line end, and a line past the end of the document
+/// means its end.
+fn position_to_char(rope: &Rope, position: &Position) -> usize {
+    let line = position.line as usize;
+    if line >= rope.len_lines() {
+        return rope.len_chars();
+    }
+    let text = rope.line(line);
+    let mut content = text.len_chars();
+    while content > 0 && matches!(text.char(content - 1), '\n' | '\r') {
+        content -= 1;
+    }
+    let text = text.slice(..content);
+    let column = (position.character as usize).min(text.len_utf16_cu());
+    rope.line_to_char(line) + text.utf16_cu_to_char(column)
 }
 
 /// Apply one incremental LSP change to the rope and source string, returning
-/// the corresponding tree-sitter `InputEdit`. BR source is ASCII so byte
-/// offsets equal char offsets — no UTF-16 conversion needed.
+/// the corresponding tree-sitter `InputEdit`.
 fn apply_change(rope: &mut Rope, source: &mut String, range: &Range, new_text: &str) -> InputEdit {
-    let start_line = range.start.line as usize;
-    let start_col = range.start.character as usize;
-    let end_line = range.end.line as usize;
-    let end_col = range.end.character as usize;
-
-    let start_char = rope.line_to_char(start_line) + start_col;
-    let end_char = rope.line_to_char(end_line) + end_col;
+    let start_char = position_to_char(rope, &range.start);
+    let end_char = position_to_char(rope, &range.end).max(start_char);
+    let start_line = rope.char_to_line(start_char);
+    let end_line = rope.char_to_line(end_char);
 
     // tree-sitter InputEdit and String::replace_range need byte offsets.
     // LSP character offsets equal byte offsets only for ASCII; CP437 high
@@ -133,33 +638,283 @@ fn apply_change(rope: &mut Rope, source: &mut String, range: &Range, new_text: &
 }
 
 impl Backend {
-    fn is_layout_doc(&self, uri: &str) -> bool {
+    pub fn new(client: Client) -> Self {
+        Self {
+            logger: Logger::new(client.clone()),
+            client,
+            document_map: Arc::new(DashMap::new()),
+            parser: Mutex::new(parser::new_parser()),
+            workspace_index: Arc::new(tokio::sync::RwLock::new(WorkspaceIndex::new())),
+            layout_index: Arc::new(tokio::sync::RwLock::new(crate::layout::LayoutIndex::new())),
+            workspace_folders: Arc::new(tokio::sync::RwLock::new(Vec::new())),
+            indexing_complete: Arc::new(AtomicBool::new(false)),
+            walk_report: Arc::new(tokio::sync::RwLock::new(workspace::WalkReport::default())),
+            parse_failures: Arc::new(tokio::sync::RwLock::new(Vec::new())),
+            diagnostics_generation: Arc::new(DashMap::new()),
+            config: Arc::new(tokio::sync::RwLock::new(Config::default())),
+            analysis_cache: Arc::new(AnalysisCache::new()),
+            diagnostics_epoch: Arc::new(AtomicU64::new(0)),
+            diagnostics_queue: Arc::new(DiagnosticsQueue::new(DIAGNOSTICS_CONCURRENCY)),
+            external_diagnostics: Arc::new(DashMap::new()),
+            parse_health: Arc::new(parse_health::ParseHealth::default()),
+            published_diagnostics: Arc::new(PublishedDiagnostics::default()),
+            workspace_snippets: Arc::new(tokio::sync::RwLock::new(Vec::new())),
+            client_features: Arc::new(tokio::sync::RwLock::new(ClientFeatures::default())),
+        }
+    }
+
+    /// Custom `br-lsp/status` request: server and grammar versions, index
+    /// sizes, indexing state, files the scans skipped and parse errors per
+    /// open BR document.
+    pub async fn status(&self) -> Result<Value> {
+        let language: tree_sitter::Language = tree_sitter_br::LANGUAGE.into();
+        let grammar_version = language.metadata().map(|m| {
+            format!(
+                "{}.{}.{}",
+                m.major_version, m.minor_version, m.patch_version
+            )
+        });
+
+        let (function_count, indexed_files) = {
+            let index = self.workspace_index.read().await;
+            let symbols = index.all_symbols();
+            let files: HashSet<&Url> = symbols.iter().map(|s| &s.uri).collect();
+            (symbols.len(), files.len())
+        };
+        let layout_count = self.layout_index.read().await.all_layouts().count();
+
+        let skipped = {
+            let report = self.walk_report.read().await;
+            serde_json::json!({
+                "paths": report
+                    .skipped
+                    .iter()
+                    .map(|s| serde_json::json!({
+                        "path": s.path.display().to_string(),
+                        "reason": s.reason.to_string(),
+                    }))
+                    .collect::<Vec<_>>(),
+                "truncated": report
+                    .truncated
+                    .iter()
+                    .map(|t| t.display().to_string())
+                    .collect::<Vec<_>>(),
+            })
+        };
+
+        let parse_failures: Vec<Value> = self
+            .parse_failures
+            .read()
+            .await
+            .iter()
+            .map(|f| {
+                serde_json::json!({
+                    "path": f.path.display().to_string(),
+                    "reason": f.reason,
+                })
+            })
+            .collect();
+
+        let mut documents: Vec<Value> = self
+            .document_map
+            .iter()
+            .filter(|entry| entry.kind == DocumentKind::Br)
+            .map(|entry| {
+                let parse_errors = entry
+                    .tree
+                    .as_ref()
+                    .map(|tree| parser::collect_diagnostics(tree, &entry.source).len());
+                serde_json::json!({
+                    "uri": entry.key(),
+                    "parsed": entry.tree.is_some(),
+                    "parseErrors": parse_errors,
+                })
+            })
+            .collect();
+        documents.sort_by(|a, b| a["uri"].as_str().cmp(&b["uri"].as_str()));
+
+        Ok(serde_json::json!({
+            "serverVersion": env!("CARGO_PKG_VERSION"),
+            "grammar": {
+                "version": grammar_version,
+                "abiVersion": language.abi_version(),
+            },
+            "indexingComplete": self.indexing_complete.load(Ordering::Acquire),
+            "index": {
+                "files": indexed_files,
+                "functions": function_count,
+                "layouts": layout_count,
+            },
+            "skipped": skipped,
+            "parseFailures": parse_failures,
+            "documents": documents,
+        }))
+    }
+
+    /// Custom `br-lsp/functionInfo` request: signature, documentation and
+    /// parameters of a function, resolved as hover resolves it in `uri`, for
+    /// tools that document or review BR code.
+    pub async fn function_info(&self, params: FunctionInfoParams) -> Result<Value> {
+        let ctx = match &params.uri {
+            Some(uri) => handlers::RequestContext::new(self, "functionInfo", uri),
+            None => handlers::RequestContext::for_command(self, "functionInfo"),
+        };
+        let mut library_links = ctx
+            .with_tree(|tree, source| Some(extract::extract_library_links(tree, source)))
+            .unwrap_or_default();
+        let mappings = ctx.config().await.diagnostics.drive_mappings;
+        let folders = ctx.workspace_folders().await;
+        mappings.map_library_links(&mut library_links, &folders);
+        let uri = params.uri.as_ref().map_or("", Url::as_str);
+        Ok(match ctx.workspace_index().await {
+            Some(index) => {
+                let defs = index.lookup_prioritized_with_links(
+                    &params.name,
+                    uri,
+                    &library_links,
+                    &folders,
+                );
+                export::function_info(&params.name, &defs)
+            }
+            None => {
+                let local = ctx.local_definitions(&params.name);
+                export::function_info(&params.name, &local.iter().collect::<Vec<_>>())
+            }
+        })
+    }
+
+    /// Custom `br-lsp/peekDefinition` request: the source of the definition
+    /// at a position with a few lines around it, for clients that render a
+    /// peek without opening the file.
+    pub async fn peek_definition(
+        &self,
+        params: handlers::peek::PeekDefinitionParams,
+    ) -> Result<Value> {
+        handlers::peek::peek_definition(self, params).await
+    }
+
+    /// Snapshot of an open document. The map guard is released before this
+    /// returns, so the snapshot can be held across awaits.
+    pub fn snapshot(&self, uri: &str) -> Option<DocumentSnapshot> {
+        self.document_map.get(uri).map(|doc| doc.snapshot())
+    }
+
+    /// The source of every open BR document, keyed by URI, for workspace
+    /// scans to read instead of disk.
+    pub(crate) fn open_sources(&self) -> std::collections::HashMap<String, Arc<str>> {
         self.document_map
-            .get(uri)
-            .map(|d| d.kind == DocumentKind::Layout)
-            .unwrap_or(false)
+            .iter()
+            .filter(|e| e.value().kind == DocumentKind::Br)
+            .map(|e| (e.key().clone(), e.value().source.clone()))
+            .collect()
     }
 
-    async fn pull_diagnostics_config(&self) {
-        let items = vec![ConfigurationItem {
-            scope_uri: None,
-            section: Some("br.diagnostics".to_string()),
-        }];
+    async fn pull_config(&self) {
+        let items = vec![
+            ConfigurationItem {
+                scope_uri: None,
+                section: Some("br.diagnostics".to_string()),
+            },
+            ConfigurationItem {
+                scope_uri: None,
+                section: Some("br-lsp".to_string()),
+            },
+            ConfigurationItem {
+                scope_uri: None,
+                section: Some("br.autoLineNumbers".to_string()),
+            },
+        ];
 
         let values = match self.client.configuration(items).await {
             Ok(v) => v,
             Err(e) => {
-                warn!("Failed to pull diagnostics config: {e}");
+                warn!("Failed to pull config: {e}");
                 return;
             }
         };
 
-        let val = match values.into_iter().next() {
+        let mut values = values.into_iter();
+        let val = match values.next() {
             Some(v) => v,
             None => return,
         };
 
-        let mut config = self.diagnostics_config.write().await;
+        let mut settings = self.config.write().await;
+        let settings = &mut *settings;
+        let config = &mut settings.diagnostics;
+        if let Some(section) = values.next() {
+            config.br_version = section
+                .get("brVersion")
+                .and_then(|v| v.as_str())
+                .and_then(BrVersion::parse);
+            config.keyword_case = section
+                .pointer("/style/keywordCase")
+                .and_then(|v| v.as_str())
+                .and_then(style::KeywordCase::parse);
+            config.naming = style::NamingConfig::from_settings(section.pointer("/style/naming"));
+            config.dialects = dialect::DialectConfig::from_settings(section.get("dialects"));
+            self.logger.set_level(LogLevel::from_setting(
+                section.get("logLevel").and_then(|v| v.as_str()),
+            ));
+            config.read_only_workspace = section
+                .get("readOnlyWorkspace")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            config.include_string_references = section
+                .pointer("/references/includeStringReferences")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            settings.completion =
+                completions::CompletionOptions::from_settings(section.get("completion"));
+            settings.paths.program_search_paths = section
+                .get("programSearchPaths")
+                .and_then(|v| v.as_array())
+                .map(|paths| {
+                    paths
+                        .iter()
+                        .filter_map(|p| p.as_str().map(str::to_string))
+                        .collect()
+                })
+                .unwrap_or_default();
+            config.drive_mappings =
+                workspace::DriveMappings::from_settings(section.get("driveMappings"));
+            settings.paths.br_files =
+                workspace::BrFileDetection::from_settings(section.get("extensionlessFiles"));
+            settings.paths.br_files.respect_gitignore = section
+                .get("respectGitignore")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(true);
+            settings.index.indexing =
+                workspace::IndexingOptions::from_settings(section.get("indexing"));
+            settings.index.poll_interval = section
+                .pointer("/index/pollIntervalSeconds")
+                .and_then(|v| v.as_u64())
+                .filter(|&secs| secs > 0)
+                .map(std::time::Duration::from_secs);
+            settings.code_lens.external_usages = section
+                .pointer("/codeLens/externalUsages")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(true);
+            settings.code_lens.metrics = section
+                .pointer("/codeLens/metrics")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            config.metrics = metrics::MetricsLimits::from_settings(section.get("metrics"));
+            config.external_checker = section
+                .get("externalChecker")
+                .and_then(|v| v.as_str())
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(std::path::PathBuf::from);
+            config.muted_files = workspace::MutedFiles::from_settings(section.get("mutedFiles"));
+            config.generated_files =
+                generated::GeneratedFiles::from_settings(section.get("generatedFiles"));
+            config.parse_health_thresholds =
+                parse_health::thresholds_from_settings(section.get("parseHealthThresholds"));
+            settings.header = header::HeaderTemplate::from_settings(section.get("header"));
+        }
+        settings.auto_line_numbers =
+            on_type_formatting::AutoLineNumbers::from_settings(values.next().as_ref());
         if let Some(obj) = val.as_object() {
             if let Some(v) = obj.get("syntax").and_then(|v| v.as_bool()) {
                 config.syntax = v;
@@ -173,44 +928,221 @@ impl Backend {
             if let Some(v) = obj.get("unusedVariables").and_then(|v| v.as_bool()) {
                 config.unused_variables = v;
             }
+            if let Some(v) = obj.get("unusedParameters").and_then(|v| v.as_bool()) {
+                config.unused_parameters = v;
+            }
+            if let Some(v) = obj.get("fileNumbers").and_then(|v| v.as_bool()) {
+                config.file_numbers = v;
+            }
+            if let Some(v) = obj
+                .get("valueParameterAssignment")
+                .and_then(|v| v.as_bool())
+            {
+                config.value_param_assignment = v;
+            }
+            if let Some(v) = obj.get("errorConditions").and_then(|v| v.as_bool()) {
+                config.error_conditions = v;
+            }
+            if let Some(v) = obj.get("picMasks").and_then(|v| v.as_bool()) {
+                config.pic_masks = v;
+            }
+            if let Some(v) = obj.get("lineNumbers").and_then(|v| v.as_bool()) {
+                config.line_numbers = v;
+            }
+            if let Some(v) = obj.get("shadowing").and_then(|v| v.as_bool()) {
+                config.shadowing = v;
+            }
         }
+        let profile = val
+            .get("profile")
+            .and_then(|v| v.as_str())
+            .and_then(LintProfile::parse)
+            .unwrap_or_default();
+        profile.apply(config);
 
-        debug!("diagnostics config updated: {config:?}");
+        debug!("config updated: {settings:?}");
     }
 
     async fn republish_all_diagnostics(&self) {
-        let config = self.diagnostics_config.read().await;
-        let index = if self.indexing_complete.load(Ordering::Acquire) {
-            Some(self.workspace_index.read().await)
+        self.republish_diagnostics(&Rerun::Everything).await;
+    }
+
+    /// Bring every open file's diagnostics up to date with a settings or
+    /// index change, rerunning only what `rerun` asks for where the file's
+    /// cached diagnostics are current.
+    async fn republish_diagnostics(&self, rerun: &Rerun) {
+        self.republisher().republish(rerun).await;
+    }
+
+    fn republisher(&self) -> Republisher {
+        Republisher {
+            client: self.client.clone(),
+            document_map: self.document_map.clone(),
+            workspace_index: self.workspace_index.clone(),
+            layout_index: self.layout_index.clone(),
+            indexing_complete: self.indexing_complete.clone(),
+            config: self.config.clone(),
+            analysis_cache: self.analysis_cache.clone(),
+            diagnostics_epoch: self.diagnostics_epoch.clone(),
+            external_diagnostics: self.external_diagnostics.clone(),
+            published_diagnostics: self.published_diagnostics.clone(),
+        }
+    }
+    /// Drop a file's definitions (or layout) from the workspace indexes.
+    async fn remove_file_from_indexes(&self, uri: &Url, file_path: &std::path::Path) {
+        if crate::layout::is_layout_file(file_path) {
+            let mut idx = self.layout_index.write().await;
+            idx.remove(uri.as_ref());
         } else {
-            None
-        };
+            let mut index = self.workspace_index.write().await;
+            index.remove_file(uri);
+        }
+    }
 
-        let to_publish: Vec<(String, Vec<Diagnostic>)> = self
-            .document_map
-            .iter()
-            .filter_map(|entry| {
-                let uri_string = entry.key().clone();
-                let doc = entry.value();
-                let t = doc.tree.as_ref()?;
-                let diags =
-                    Self::collect_all_diagnostics(t, &doc.source, &config, index.as_deref());
-                Some((uri_string, diags))
+    /// The indexed programs and layouts at or under `path`.
+    async fn indexed_files_under(&self, path: &std::path::Path) -> Vec<Url> {
+        let under = |uri: &Url| uri.to_file_path().is_ok_and(|p| p.starts_with(path));
+        let mut files: Vec<Url> = self
+            .workspace_index
+            .read()
+            .await
+            .indexed_files()
+            .map(|(uri, _)| uri)
+            .filter(|uri| under(uri))
+            .cloned()
+            .collect();
+        files.extend(
+            self.layout_index
+                .read()
+                .await
+                .iter()
+                .filter_map(|(uri, _)| Url::parse(uri).ok())
+                .filter(under),
+        );
+        files
+    }
+
+    /// Recheck the open documents that call or import one of `names`
+    /// (lowercase) after files defining them came or went, or every open
+    /// document when layouts did, since any of them may open one.
+    async fn recheck_callers(&self, names: &HashSet<String>, layouts_changed: bool) {
+        if layouts_changed {
+            self.republish_all_diagnostics().await;
+            return;
+        }
+        if names.is_empty() {
+            return;
+        }
+        let mut callers: HashSet<String> = self
+            .workspace_index
+            .read()
+            .await
+            .calls()
+            .filter(|(uri, calls)| {
+                self.document_map.contains_key(uri.as_str())
+                    && calls
+                        .iter()
+                        .any(|c| names.contains(&c.callee.to_ascii_lowercase()))
             })
+            .map(|(uri, _)| uri.to_string())
             .collect();
+        // LIBRARY statements name functions without calling them
+        for entry in self.document_map.iter() {
+            let Some(tree) = entry.value().tree.as_ref() else {
+                continue;
+            };
+            let links = extract::extract_library_links(tree, &entry.value().source);
+            if links.keys().any(|name| names.contains(name)) {
+                callers.insert(entry.key().clone());
+            }
+        }
+        if callers.is_empty() {
+            return;
+        }
+        self.republisher()
+            .republish_where(&Rerun::Everything, |uri| callers.contains(uri))
+            .await;
+    }
+
+    /// Reread the workspace folders' snippets files, logging any that
+    /// can't be used.
+    async fn reload_snippets(&self) {
+        let folders = self.workspace_folders.read().await.clone();
+        let (found, errors) = snippets::load(&folders);
+        for e in errors {
+            self.logger
+                .log(MessageType::WARNING, format!("snippets: {e}"))
+                .await;
+        }
+        self.logger
+            .log(
+                MessageType::LOG,
+                format!("snippets: {} loaded", found.len()),
+            )
+            .await;
+        *self.workspace_snippets.write().await = found;
+    }
+
+    /// Read a file from disk and (re)index its definitions or layout.
+    pub(crate) async fn index_file_from_disk(&self, uri: &Url, file_path: &std::path::Path) {
+        let detection = self.config.read().await.paths.br_files.clone();
+        if crate::layout::is_layout_file(file_path) {
+            let source = match crate::layout::read_layout_file(file_path) {
+                Ok(s) => s,
+                Err(e) => {
+                    error!("Failed to read layout {}: {e}", file_path.display());
+                    return;
+                }
+            };
+            if let Some(layout) = crate::layout::parse(&source) {
+                let mut idx = self.layout_index.write().await;
+                idx.update(uri.as_ref(), layout);
+            }
+        } else if detection.is_br_file(file_path) {
+            let source = match workspace::read_br_file(file_path) {
+                Ok(s) => s,
+                Err(e) => {
+                    error!("Failed to read {}: {e}", file_path.display());
+                    return;
+                }
+            };
+
+            let tree = {
+                let mut parser = self.parser.lock().unwrap();
+                parser::parse(&mut parser, &source, None)
+            };
 
-        for (uri_string, diags) in to_publish {
-            if let Ok(uri) = Url::parse(&uri_string) {
-                self.client.publish_diagnostics(uri, diags, None).await;
+            if let Some(t) = tree {
+                let defs = extract::extract_definitions(&t, &source);
+                let calls = call_graph::collect_calls(&t, &source);
+                let mut index = self.workspace_index.write().await;
+                index.update_file(uri, defs);
+                index.set_calls(uri, calls);
             }
+        } else if detection.enabled && file_path.extension().is_none() {
+            // An extensionless file that no longer looks like BR source
+            self.workspace_index.write().await.remove_file(uri);
         }
     }
 
-    fn collect_all_diagnostics(
+    fn layout_diagnostics(source: &str, config: &DiagnosticsConfig) -> Vec<Diagnostic> {
+        let mut diagnostics = if config.pic_masks {
+            crate::layout::check_pic_masks(source)
+        } else {
+            Vec::new()
+        };
+        diagnostics::stamp_source(&mut diagnostics);
+        diagnostics
+    }
+
+    pub(crate) fn collect_all_diagnostics(
+        uri: &Url,
         tree: &Tree,
         source: &str,
         config: &DiagnosticsConfig,
         index: Option<&WorkspaceIndex>,
+        layouts: Option<&crate::layout::LayoutIndex>,
+        usage: Option<&mut VariableUsage>,
     ) -> Vec<Diagnostic> {
         let mut diagnostics = if config.syntax {
             parser::collect_diagnostics(tree, source)
@@ -224,28 +1156,141 @@ impl Backend {
             &nodes.library_statements,
             source,
         );
+        let structure = Structure::from_root(nodes.root, source);
 
         if config.functions {
             diagnostics.extend(diagnostics::collect_function_diagnostics(
-                &nodes, source, &defs,
+                &nodes, &structure, source, &defs, uri,
             ));
+        } else if config.missing_fnend {
+            diagnostics.extend(diagnostics::check_missing_fnend(&structure, source));
         }
 
         if config.unused_variables {
-            diagnostics.extend(diagnostics::check_unused_variables(&nodes, source, &defs));
-        }
-
-        if config.undefined_functions {
+            diagnostics.extend(match usage {
+                Some(usage) => diagnostics::check_unused_variables_cached(
+                    &nodes, &structure, source, &defs, usage,
+                ),
+                None => diagnostics::check_unused_variables(&nodes, &structure, source, &defs),
+            });
             if let Some(idx) = index {
-                diagnostics.extend(diagnostics::check_undefined_functions(
-                    &nodes.function_calls,
+                library_deps::link_unused_imports(
+                    &mut diagnostics,
+                    tree,
                     source,
                     idx,
-                    &defs,
-                ));
+                    &config.drive_mappings,
+                );
+            }
+        }
+
+        if config.unused_parameters {
+            diagnostics.extend(diagnostics::check_unused_parameters(
+                &nodes, &structure, source,
+            ));
+        }
+
+        if config.file_numbers {
+            let usage = files::collect_file_usage(tree, source);
+            diagnostics.extend(diagnostics::check_file_numbers(&usage, uri));
+            if let Some(layouts) = layouts {
+                diagnostics.extend(diagnostics::check_record_lengths(&usage, layouts));
+                diagnostics.extend(diagnostics::check_key_fields(&usage, layouts));
+            }
+        }
+
+        if config.functions {
+            diagnostics.extend(diagnostics::check_deprecated_calls(
+                &nodes.function_calls,
+                source,
+                &defs,
+                index,
+                uri,
+            ));
+        }
+
+        if config.value_param_assignment {
+            diagnostics.extend(diagnostics::check_value_param_assignments(
+                &nodes, &structure, source,
+            ));
+        }
+
+        if config.error_conditions {
+            diagnostics.extend(diagnostics::check_error_condition_targets(tree, source));
+        }
+
+        if config.pic_masks {
+            diagnostics.extend(diagnostics::check_pic_masks(tree, source));
+        }
+
+        if config.line_numbers {
+            diagnostics.extend(line_numbers::check_line_numbers(tree, source, uri));
+        }
+
+        if config.shadowing {
+            diagnostics.extend(shadowing::check_builtin_shadowing(&nodes, source, &defs));
+            if let Some(idx) = index {
+                diagnostics.extend(shadowing::check_library_shadowing(&defs, idx, uri));
+            }
+        }
+
+        if config.naming.is_enabled() {
+            diagnostics.extend(style::check_naming(tree, source, &defs, &config.naming));
+        }
+
+        if let Some(case) = config.keyword_case {
+            diagnostics.extend(style::check_keyword_case(tree, source, case));
+        }
+
+        if config.metrics.is_enabled() {
+            diagnostics.extend(metrics::check_metrics(tree, source, &config.metrics));
+        }
+
+        if let Some(version) = config.br_version {
+            diagnostics.extend(diagnostics::check_builtin_versions(
+                &nodes.function_calls,
+                source,
+                version,
+            ));
+            diagnostics.extend(diagnostics::check_statement_versions(tree, source, version));
+        }
+
+        if config.dialects.is_enabled() {
+            diagnostics.extend(dialect::check_dialect(
+                tree,
+                source,
+                Dialect::of(uri.path()),
+                &config.dialects,
+            ));
+        }
+
+        if config.functions {
+            if let Some(idx) = index {
+                diagnostics.extend(library_deps::check_library_functions(
+                    tree,
+                    source,
+                    idx,
+                    &config.drive_mappings,
+                ));
+            }
+        }
+
+        if config.undefined_functions {
+            if let Some(idx) = index {
+                diagnostics.extend(diagnostics::check_undefined_functions(
+                    &nodes.function_calls,
+                    source,
+                    idx,
+                    &defs,
+                ));
             }
         }
 
+        if config.generated_files.contains(uri, source) {
+            generated::strip_style(&mut diagnostics);
+        }
+
+        diagnostics::stamp_source(&mut diagnostics);
         diagnostics
     }
 
@@ -266,19 +1311,25 @@ impl Backend {
                 idx.update(&uri_string, layout);
             }
 
+            let diagnostics = {
+                let config = &self.config.read().await.diagnostics;
+                config.published(&params.uri, Self::layout_diagnostics(&params.text, config))
+            };
             self.document_map.insert(
                 uri_string,
                 DocumentState {
                     kind,
                     rope,
-                    source: params.text,
+                    source: params.text.into(),
                     tree: None,
+                    version: 0,
+                    client_version: params.version,
+                    variable_usage: VariableUsage::default(),
                 },
             );
 
-            // Publish empty diagnostics for layout files
-            self.client
-                .publish_diagnostics(params.uri, vec![], None)
+            self.published_diagnostics
+                .publish(&self.client, params.uri, diagnostics)
                 .await;
             return;
         }
@@ -295,42 +1346,85 @@ impl Backend {
         // Update workspace index with definitions from this file
         if let Some(t) = tree.as_ref() {
             let defs = extract::extract_definitions(t, &params.text);
+            let calls = call_graph::collect_calls(t, &params.text);
             let mut index = self.workspace_index.write().await;
             index.update_file(&params.uri, defs);
+            index.set_calls(&params.uri, calls);
         }
 
+        let mut variable_usage = VariableUsage::default();
         let diagnostics = if let Some(t) = tree.as_ref() {
-            let config = self.diagnostics_config.read().await;
+            let settings = self.config.read().await;
+            let config = &settings.diagnostics;
             let index = if self.indexing_complete.load(Ordering::Acquire) {
                 Some(self.workspace_index.read().await)
             } else {
                 None
             };
-            Self::collect_all_diagnostics(t, &params.text, &config, index.as_deref())
+            let layouts = self.layout_index.read().await;
+            Self::collect_all_diagnostics(
+                &params.uri,
+                t,
+                &params.text,
+                config,
+                index.as_deref(),
+                Some(&layouts),
+                Some(&mut variable_usage),
+            )
         } else {
             Vec::new()
         };
 
         let uri_string = params.uri.to_string();
-        self.symbol_cache.remove(&uri_string);
+        let parsed = tree.is_some();
+        let health_tree = tree.clone();
+        self.analysis_cache.remove(&uri_string);
+        if parsed {
+            self.analysis_cache.store_diagnostics(
+                &uri_string,
+                0,
+                self.diagnostics_epoch.load(Ordering::SeqCst),
+                diagnostics.clone(),
+            );
+        }
         self.document_map.insert(
-            uri_string,
+            uri_string.clone(),
             DocumentState {
                 kind,
                 rope,
-                source: params.text.clone(),
+                source: params.text.as_str().into(),
                 tree,
+                version: 0,
+                client_version: params.version,
+                variable_usage,
             },
         );
 
         let total_elapsed = start.elapsed();
 
-        self.client
-            .publish_diagnostics(params.uri, diagnostics, None)
+        let diagnostics = self
+            .config
+            .read()
+            .await
+            .diagnostics
+            .published(&params.uri, diagnostics);
+        self.published_diagnostics
+            .publish(&self.client, params.uri.clone(), diagnostics)
+            .await;
+        if let Some(t) = health_tree.as_ref() {
+            Self::report_parse_health(
+                &self.client,
+                &self.parse_health,
+                &self.config.read().await.diagnostics,
+                &params.uri,
+                t,
+                &params.text,
+            )
             .await;
+        }
 
-        self.client
-            .log_message(
+        self.logger
+            .log(
                 MessageType::LOG,
                 format!(
                     "on_change (full parse): {} bytes, parse {parse_elapsed:.1?}, total {total_elapsed:.1?}",
@@ -338,21 +1432,58 @@ impl Backend {
                 ),
             )
             .await;
+
+        if !parsed {
+            // Diagnostics task retries the parse with backoff
+            self.schedule_diagnostics(params.uri, uri_string);
+        }
+    }
+
+    /// Send `br-lsp/parseHealth` when the document's syntax error count
+    /// crossed one of the configured thresholds. Muted files stay quiet.
+    async fn report_parse_health(
+        client: &Client,
+        parse_health: &parse_health::ParseHealth,
+        config: &DiagnosticsConfig,
+        uri: &Url,
+        tree: &Tree,
+        source: &str,
+    ) {
+        if config.muted_files.contains(uri) {
+            return;
+        }
+        let syntax_errors = parser::collect_diagnostics(tree, source).len();
+        if let Some(params) =
+            parse_health.update(uri, syntax_errors, &config.parse_health_thresholds)
+        {
+            client
+                .send_notification::<parse_health::ParseHealthNotification>(params)
+                .await;
+        }
     }
 
-    fn schedule_diagnostics(&self, uri: Url, uri_string: String) {
+    pub(crate) fn schedule_diagnostics(&self, uri: Url, uri_string: String) {
         let generation = self
             .diagnostics_generation
             .entry(uri_string.clone())
             .or_insert_with(|| Arc::new(AtomicU64::new(0)))
             .clone();
         let my_gen = generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let priority = self.diagnostics_queue.next_priority();
 
         let client = self.client.clone();
         let document_map = self.document_map.clone();
         let workspace_index = self.workspace_index.clone();
+        let layout_index = self.layout_index.clone();
         let indexing_complete = self.indexing_complete.clone();
-        let diagnostics_config = self.diagnostics_config.clone();
+        let settings = self.config.clone();
+        let analysis_cache = self.analysis_cache.clone();
+        let external_diagnostics = self.external_diagnostics.clone();
+        let parse_health = self.parse_health.clone();
+        let published_diagnostics = self.published_diagnostics.clone();
+        let diagnostics_epoch = self.diagnostics_epoch.clone();
+        let diagnostics_queue = self.diagnostics_queue.clone();
+        let logger = self.logger.clone();
 
         tokio::spawn(async move {
             tokio::time::sleep(std::time::Duration::from_millis(DIAGNOSTICS_DEBOUNCE_MS)).await;
@@ -361,41 +1492,137 @@ impl Backend {
                 return; // stale — a newer change superseded us
             }
 
+            let _permit = diagnostics_queue.acquire(priority).await;
+            if generation.load(Ordering::SeqCst) != my_gen {
+                return; // superseded while queued
+            }
+
             let start = std::time::Instant::now();
 
-            let (source, tree) = {
+            let (source, tree, version, mut variable_usage) = {
                 let doc = match document_map.get(&uri_string) {
                     Some(d) => d,
                     None => return, // document was closed
                 };
-                (doc.source.clone(), doc.tree.clone())
+                (
+                    doc.source.clone(),
+                    doc.tree.clone(),
+                    doc.version,
+                    doc.variable_usage.clone(),
+                )
             };
 
-            let tree = match tree {
-                Some(t) => t,
-                None => return,
+            let (source, tree, version) = match tree {
+                Some(t) => (source, t, version),
+                None => {
+                    match Backend::reparse_with_backoff(
+                        &document_map,
+                        &uri_string,
+                        &generation,
+                        my_gen,
+                    )
+                    .await
+                    {
+                        Some(parsed) => parsed,
+                        None => {
+                            // Only report if we weren't superseded and the document is still open
+                            if generation.load(Ordering::SeqCst) == my_gen
+                                && document_map.contains_key(&uri_string)
+                            {
+                                client
+                                    .show_message(
+                                        MessageType::WARNING,
+                                        format!(
+                                            "br-lsp could not parse {}; most language features are unavailable for it. Edit the file or run `br-lsp.reparse` to retry.",
+                                            file_display_name(&uri)
+                                        ),
+                                    )
+                                    .await;
+                            }
+                            return;
+                        }
+                    }
+                }
             };
 
+            Backend::report_parse_health(
+                &client,
+                &parse_health,
+                &settings.read().await.diagnostics,
+                &uri,
+                &tree,
+                &source,
+            )
+            .await;
+
+            let epoch = diagnostics_epoch.load(Ordering::SeqCst);
+            if let Some(diagnostics) = analysis_cache.diagnostics(&uri_string, version, epoch) {
+                let count = diagnostics.len();
+                let diagnostics =
+                    external_checker::merged(&external_diagnostics, &uri_string, diagnostics);
+                let diagnostics = settings
+                    .read()
+                    .await
+                    .diagnostics
+                    .published(&uri, diagnostics);
+                published_diagnostics
+                    .publish(&client, uri, diagnostics)
+                    .await;
+                logger
+                    .log(
+                        MessageType::LOG,
+                        format!(
+                            "diagnostics (debounced): {count} diagnostics, cached ({:.1?})",
+                            start.elapsed()
+                        ),
+                    )
+                    .await;
+                return;
+            }
+
             let defs = extract::extract_definitions(&tree, &source);
+            let calls = call_graph::collect_calls(&tree, &source);
             {
                 let mut index = workspace_index.write().await;
                 index.update_file(&uri, defs);
+                index.set_calls(&uri, calls);
             }
 
-            let config = diagnostics_config.read().await;
+            let settings = settings.read().await;
+            let config = &settings.diagnostics;
             let index = if indexing_complete.load(Ordering::Acquire) {
                 Some(workspace_index.read().await)
             } else {
                 None
             };
-            let diagnostics =
-                Backend::collect_all_diagnostics(&tree, &source, &config, index.as_deref());
+            let layouts = layout_index.read().await;
+            let diagnostics = Backend::collect_all_diagnostics(
+                &uri,
+                &tree,
+                &source,
+                config,
+                index.as_deref(),
+                Some(&layouts),
+                Some(&mut variable_usage),
+            );
+            analysis_cache.store_diagnostics(&uri_string, version, epoch, diagnostics.clone());
+            // Edits made meanwhile were applied to the document's own copy
+            if let Some(mut doc) = document_map.get_mut(&uri_string) {
+                if doc.version == version {
+                    doc.variable_usage = variable_usage;
+                }
+            }
 
             let count = diagnostics.len();
-            client.publish_diagnostics(uri, diagnostics, None).await;
+            let diagnostics =
+                external_checker::merged(&external_diagnostics, &uri_string, diagnostics);
+            let diagnostics = config.published(&uri, diagnostics);
+            published_diagnostics
+                .publish(&client, uri, diagnostics)
+                .await;
 
-            client
-                .log_message(
+            logger
+                .log(
                     MessageType::LOG,
                     format!(
                         "diagnostics (debounced): {count} diagnostics, {} bytes ({:.1?})",
@@ -407,10 +1634,51 @@ impl Backend {
         });
     }
 
+    /// Retry a fresh full parse of a document that has no tree, backing off
+    /// between attempts. Stores and returns the tree on success; gives up
+    /// early if the document is closed, edited or superseded by a newer
+    /// diagnostics run.
+    async fn reparse_with_backoff(
+        document_map: &DashMap<String, DocumentState>,
+        uri_string: &str,
+        generation: &AtomicU64,
+        my_gen: u64,
+    ) -> Option<(Arc<str>, Tree, u64)> {
+        for &delay in REPARSE_BACKOFF_MS {
+            tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+            if generation.load(Ordering::SeqCst) != my_gen {
+                return None;
+            }
+
+            let source = document_map.get(uri_string)?.source.clone();
+            let Some(tree) = parser::parse_fresh(&source) else {
+                continue;
+            };
+
+            let mut doc = document_map.get_mut(uri_string)?;
+            if doc.source != source {
+                return None; // edited meanwhile; did_change will reparse
+            }
+            doc.tree = Some(tree.clone());
+            doc.version += 1;
+            return Some((source, tree, doc.version));
+        }
+        None
+    }
+
+    /// Definitions of every BR file under `folder`, empty for files that
+    /// define nothing. The file names the files' OPEN statements use are
+    /// added to `open_names`, for finding layouts kept next to their data
+    /// files.
     fn scan_workspace_folder(
         folder: &Url,
+        detection: &workspace::BrFileDetection,
+        indexing: &workspace::IndexingOptions,
         files_scanned: &mut usize,
-    ) -> Vec<(Url, Vec<extract::FunctionDef>)> {
+        open_names: &mut HashSet<String>,
+        report: &mut workspace::WalkReport,
+        failures: &mut Vec<parse_health::ParseFailure>,
+    ) -> Vec<IndexedFile> {
         let path = match folder.to_file_path() {
             Ok(p) => p,
             Err(()) => {
@@ -419,48 +1687,229 @@ impl Backend {
             }
         };
 
-        // Collect file paths first (walkdir is single-threaded)
-        let file_paths: Vec<_> = WalkDir::new(&path)
-            .follow_links(true)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file() && workspace::is_br_file(e.path()))
-            .map(|e| e.into_path())
-            .collect();
+        // Collect file paths first (the walk is single-threaded)
+        let file_paths = workspace::walk_br_files_reporting(&path, detection, report);
 
         *files_scanned += file_paths.len();
 
-        // Parse in parallel — each thread gets its own parser
-        file_paths
-            .par_iter()
-            .filter_map(|file_path| {
-                let source = match workspace::read_br_file(file_path) {
-                    Ok(s) => s,
-                    Err(e) => {
-                        warn!("Failed to read {}: {e}", file_path.display());
-                        return None;
+        // Parse in batches on the indexing pool — each thread gets its own
+        // parser
+        let scanned = indexing.scan(&file_paths, |file_path| {
+            let failure = |reason: String| {
+                Some(Err(parse_health::ParseFailure {
+                    path: file_path.to_path_buf(),
+                    reason,
+                }))
+            };
+            let source = match workspace::read_br_file(file_path) {
+                Ok(s) => s,
+                Err(e) => return failure(e.to_string()),
+            };
+
+            let mut parser = parser::new_parser();
+            let Some(tree) = parser::parse(&mut parser, &source, None) else {
+                return failure("the parser gave up".to_string());
+            };
+            if parse_health::unparsable(&tree) {
+                return failure("nothing in it parses as BR".to_string());
+            }
+            let names: Vec<String> = files::collect_file_usage(&tree, &source)
+                .opens
+                .into_iter()
+                .filter_map(|open| open.name)
+                .collect();
+            // Files defining nothing are still indexed so LIBRARY paths
+            // naming them resolve
+            let defs = extract::extract_definitions(&tree, &source);
+            let calls = call_graph::collect_calls(&tree, &source);
+            let file_defs = Url::from_file_path(file_path)
+                .ok()
+                .map(|uri| (uri, defs, calls));
+            Some(Ok((file_defs, names)))
+        });
+
+        let mut result = Vec::new();
+        for scanned in scanned {
+            match scanned {
+                Ok((file_defs, names)) => {
+                    result.extend(file_defs);
+                    open_names.extend(names);
+                }
+                Err(failure) => failures.push(failure),
+            }
+        }
+        result
+    }
+
+    /// Reindex files and layouts changed on disk, and drop deleted ones,
+    /// every `br-lsp.index.pollIntervalSeconds`, republishing the open
+    /// documents' diagnostics when anything changed. Open documents are
+    /// skipped: the editor's content takes precedence, as in
+    /// `did_change_watched_files`.
+    async fn poll_index_changes(republisher: Republisher, logger: Arc<Logger>) {
+        let mut poller = workspace::ChangePoller::default();
+        loop {
+            let interval = republisher.config.read().await.index.poll_interval;
+            let Some(interval) = interval else {
+                // Off; look at the setting again later
+                poller = workspace::ChangePoller::default();
+                tokio::time::sleep(POLL_SETTING_CHECK).await;
+                continue;
+            };
+            tokio::time::sleep(interval).await;
+            if !republisher.indexing_complete.load(Ordering::Acquire) {
+                continue;
+            }
+
+            let is_closed = |uri: &Url| !republisher.document_map.contains_key(uri.as_str());
+            let mut files: Vec<(Url, std::time::SystemTime)> = republisher
+                .workspace_index
+                .read()
+                .await
+                .indexed_files()
+                .filter(|(uri, _)| is_closed(uri))
+                .map(|(uri, at)| (uri.clone(), at))
+                .collect();
+            // Layouts carry no index time, so a layout's first poll only
+            // notes when it was last modified
+            let now = std::time::SystemTime::now();
+            files.extend(
+                republisher
+                    .layout_index
+                    .read()
+                    .await
+                    .iter()
+                    .filter_map(|(uri, _)| Url::parse(uri).ok())
+                    .filter(is_closed)
+                    .map(|uri| (uri, now)),
+            );
+            let Ok((changes, updated, layouts, returned)) =
+                tokio::task::spawn_blocking(move || {
+                    let changes = poller.poll(&files);
+                    let mut parser = parser::new_parser();
+                    let mut updated: Vec<IndexedFile> = Vec::new();
+                    let mut layouts = Vec::new();
+                    for uri in &changes.changed {
+                        let Ok(path) = uri.to_file_path() else {
+                            continue;
+                        };
+                        if crate::layout::is_layout_file(&path) {
+                            let layout = crate::layout::read_layout_file(&path)
+                                .ok()
+                                .and_then(|source| crate::layout::parse(&source));
+                            if let Some(layout) = layout {
+                                layouts.push((uri.clone(), layout));
+                            }
+                            continue;
+                        }
+                        let Ok(source) = workspace::read_br_file(&path) else {
+                            continue;
+                        };
+                        if let Some(tree) = parser::parse(&mut parser, &source, None) {
+                            updated.push((
+                                uri.clone(),
+                                extract::extract_definitions(&tree, &source),
+                                call_graph::collect_calls(&tree, &source),
+                            ));
+                        }
                     }
-                };
+                    (changes, updated, layouts, poller)
+                })
+                .await
+            else {
+                return;
+            };
+            poller = returned;
+            if changes.changed.is_empty() && changes.deleted.is_empty() {
+                continue;
+            }
 
-                let mut parser = parser::new_parser();
-                let tree = parser::parse(&mut parser, &source, None)?;
-                let defs = extract::extract_definitions(&tree, &source);
-                if defs.is_empty() {
-                    return None;
+            {
+                let mut idx = republisher.workspace_index.write().await;
+                let mut layout_idx = republisher.layout_index.write().await;
+                for uri in &changes.deleted {
+                    idx.remove_file(uri);
+                    layout_idx.remove(uri.as_str());
+                }
+                for (uri, defs, calls) in updated {
+                    idx.update_file(&uri, defs);
+                    idx.set_calls(&uri, calls);
+                }
+                for (uri, layout) in layouts {
+                    layout_idx.update(uri.as_str(), layout);
                 }
+            }
+            logger
+                .log(
+                    MessageType::LOG,
+                    format!(
+                        "index poll: {} changed, {} deleted",
+                        changes.changed.len(),
+                        changes.deleted.len()
+                    ),
+                )
+                .await;
+            republisher.republish(&Rerun::Everything).await;
+        }
+    }
 
-                let uri = Url::from_file_path(file_path).ok()?;
-                Some((uri, defs))
-            })
-            .collect()
+    /// Log each path a workspace walk skipped and summarize them in a window
+    /// message, so files missing from the index aren't a mystery.
+    async fn report_skipped_paths(
+        client: &Client,
+        logger: &Arc<Logger>,
+        report: &workspace::WalkReport,
+    ) {
+        if report.is_empty() {
+            return;
+        }
+        for skipped in &report.skipped {
+            logger
+                .log(
+                    MessageType::WARNING,
+                    format!("Skipped {}: {}", skipped.path.display(), skipped.reason),
+                )
+                .await;
+        }
+        client
+            .show_message(
+                MessageType::WARNING,
+                format!(
+                    "br-lsp indexing: {}. See the output log or `br-lsp/status` for the paths.",
+                    report.summary()
+                ),
+            )
+            .await;
+    }
+
+    /// Log each file a workspace scan couldn't read or parse and send
+    /// `br-lsp/indexParseFailures` summarizing them.
+    async fn report_parse_failures(
+        client: &Client,
+        logger: &Arc<Logger>,
+        failures: &[parse_health::ParseFailure],
+    ) {
+        for failure in failures {
+            logger
+                .log(
+                    MessageType::WARNING,
+                    format!("Not indexed {}: {}", failure.path.display(), failure.reason),
+                )
+                .await;
+        }
+        if let Some(params) = parse_health::failure_summary(failures) {
+            client
+                .send_notification::<parse_health::IndexParseFailuresNotification>(params)
+                .await;
+        }
     }
 
     /// Search all workspace files (open + closed) for references to a function name.
-    async fn search_workspace_for_function_refs(&self, name: &str) -> Vec<Location> {
+    pub(crate) async fn search_workspace_for_function_refs(&self, name: &str) -> Vec<Location> {
         let mut locations = Vec::new();
 
         // 1. Open documents
-        let mut open_uris = std::collections::HashSet::new();
+        let mut open_uris = HashSet::new();
         for entry in self.document_map.iter() {
             let uri_string = entry.key().clone();
             open_uris.insert(uri_string.clone());
@@ -480,7 +1929,9 @@ impl Backend {
 
         // 2. Closed files — parallel walk of workspace folders
         let folders = self.workspace_folders.read().await.clone();
+        let detection = self.config.read().await.paths.br_files.clone();
         let name_owned = name.to_string();
+        let name_lower = name.to_ascii_lowercase();
         let open_uris_clone = open_uris;
 
         let closed_locations = tokio::task::spawn_blocking(move || {
@@ -491,13 +1942,7 @@ impl Backend {
                     Err(()) => continue,
                 };
 
-                let file_paths: Vec<_> = WalkDir::new(&path)
-                    .follow_links(true)
-                    .into_iter()
-                    .filter_map(|e| e.ok())
-                    .filter(|e| e.file_type().is_file() && workspace::is_br_file(e.path()))
-                    .map(|e| e.into_path())
-                    .collect();
+                let file_paths = workspace::walk_br_files(&path, &detection);
 
                 let folder_results: Vec<Location> = file_paths
                     .par_iter()
@@ -507,6 +1952,10 @@ impl Backend {
                             return None;
                         }
                         let source = workspace::read_br_file(file_path).ok()?;
+                        // Most files never mention the name; skip parsing them
+                        if !source.to_ascii_lowercase().contains(&name_lower) {
+                            return None;
+                        }
                         let mut parser = parser::new_parser();
                         let tree = parser::parse(&mut parser, &source, None)?;
                         let refs =
@@ -537,10 +1986,14 @@ impl Backend {
         locations
     }
 
-    fn scan_workspace_diagnostics(
+    /// Run `analyze` on every BR file under `folder`, reading open documents
+    /// from `open_sources` and the rest from disk.
+    pub(crate) fn scan_workspace_files<T: Send>(
         folder: &Url,
-        config: &DiagnosticsConfig,
-    ) -> Vec<(Url, Vec<Diagnostic>)> {
+        detection: &workspace::BrFileDetection,
+        open_sources: &std::collections::HashMap<String, Arc<str>>,
+        analyze: impl Fn(&Tree, &str) -> T + Sync,
+    ) -> Vec<(Url, T)> {
         let path = match folder.to_file_path() {
             Ok(p) => p,
             Err(()) => {
@@ -549,13 +2002,124 @@ impl Backend {
             }
         };
 
-        let file_paths: Vec<_> = WalkDir::new(&path)
-            .follow_links(true)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file() && workspace::is_br_file(e.path()))
-            .map(|e| e.into_path())
+        let file_paths = workspace::walk_br_files(&path, detection);
+
+        file_paths
+            .par_iter()
+            .filter_map(|file_path| {
+                let uri = Url::from_file_path(file_path).ok()?;
+                let source = match open_sources.get(uri.as_str()) {
+                    Some(s) => s.clone(),
+                    None => match workspace::read_br_file(file_path) {
+                        Ok(s) => Arc::from(s),
+                        Err(e) => {
+                            warn!("Failed to read {}: {e}", file_path.display());
+                            return None;
+                        }
+                    },
+                };
+
+                let mut ts_parser = parser::new_parser();
+                let tree = parser::parse(&mut ts_parser, &source, None)?;
+                Some((uri, analyze(&tree, &source)))
+            })
+            .collect()
+    }
+
+    /// The source file a CHAIN, LIBRARY or EXECUTE name in `document`
+    /// opens, resolved as its document link is.
+    pub(crate) async fn resolve_program_name(&self, document: &Url, name: &str) -> Option<PathBuf> {
+        let folders: Vec<PathBuf> = self
+            .workspace_folders
+            .read()
+            .await
+            .iter()
+            .filter_map(|folder| folder.to_file_path().ok())
             .collect();
+        let config = self.config.read().await;
+        let document_path = document.to_file_path().ok();
+        let bases = document_links::search_bases(
+            &folders,
+            &config.paths.program_search_paths,
+            document_path.as_deref().and_then(|p| p.parent()),
+        );
+        document_links::resolve_program(name, &bases, &config.diagnostics.drive_mappings, &folders)
+    }
+
+    /// Every CHAIN, LIBRARY and `EXECUTE "RUN ..."` string in the workspace
+    /// naming the program at `target`, sorted by file and position.
+    pub(crate) async fn find_program_references(&self, target: PathBuf) -> Vec<Location> {
+        let folders = self.workspace_folders.read().await.clone();
+        let (detection, search_paths, mappings) = {
+            let config = self.config.read().await;
+            (
+                config.paths.br_files.clone(),
+                config.paths.program_search_paths.clone(),
+                config.diagnostics.drive_mappings.clone(),
+            )
+        };
+        let open_sources = self.open_sources();
+
+        tokio::task::spawn_blocking(move || {
+            let folder_paths: Vec<PathBuf> = folders
+                .iter()
+                .filter_map(|folder| folder.to_file_path().ok())
+                .collect();
+            let mut locations = Vec::new();
+            for folder in &folders {
+                let files = Self::scan_workspace_files(
+                    folder,
+                    &detection,
+                    &open_sources,
+                    document_links::collect_program_references,
+                );
+                for (uri, refs) in files {
+                    let document_path = uri.to_file_path().ok();
+                    let bases = document_links::search_bases(
+                        &folder_paths,
+                        &search_paths,
+                        document_path.as_deref().and_then(|p| p.parent()),
+                    );
+                    locations.extend(
+                        document_links::references_to(
+                            &refs,
+                            &target,
+                            &bases,
+                            &mappings,
+                            &folder_paths,
+                        )
+                        .map(|r| Location::new(uri.clone(), r.range)),
+                    );
+                }
+            }
+            locations.sort_by(|a, b| {
+                a.uri
+                    .as_str()
+                    .cmp(b.uri.as_str())
+                    .then(a.range.start.cmp(&b.range.start))
+            });
+            locations.dedup();
+            locations
+        })
+        .await
+        .unwrap_or_default()
+    }
+
+    pub(crate) fn scan_workspace_diagnostics(
+        folder: &Url,
+        config: &Config,
+        workspace_folders: &[Url],
+    ) -> Vec<(Url, Vec<Diagnostic>, library_deps::FileLibraries)> {
+        let path = match folder.to_file_path() {
+            Ok(p) => p,
+            Err(()) => {
+                warn!("Cannot convert workspace folder URI to path: {folder}");
+                return Vec::new();
+            }
+        };
+
+        let file_paths = workspace::walk_br_files(&path, &config.paths.br_files);
+        let config = &config.diagnostics;
 
         file_paths
             .par_iter()
@@ -571,10 +2135,16 @@ impl Backend {
                 let mut ts_parser = parser::new_parser();
                 let tree = parser::parse(&mut ts_parser, &source, None)?;
 
-                let diags = Self::collect_all_diagnostics(&tree, &source, config, None);
-
                 let uri = Url::from_file_path(file_path).ok()?;
-                Some((uri, diags))
+                let diags =
+                    Self::collect_all_diagnostics(&uri, &tree, &source, config, None, None, None);
+
+                let defs = extract::extract_definitions(&tree, &source);
+                let link_path = workspace::uri_to_link_path(&uri, workspace_folders);
+                let mut libraries =
+                    library_deps::FileLibraries::new(uri.clone(), link_path, &tree, &source, &defs);
+                libraries.map_drives(&config.drive_mappings, workspace_folders);
+                Some((uri, diags, libraries))
             })
             .collect()
     }
@@ -583,6 +2153,11 @@ impl Backend {
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
     async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        let features = ClientFeatures::from_capabilities(&params.capabilities);
+        let completion_resolve = features.completion_resolve;
+        debug!("client features: {features:?}");
+        *self.client_features.write().await = features;
+
         // Capture workspace folders
         let mut folders = self.workspace_folders.write().await;
         if let Some(wf) = params.workspace_folders {
@@ -611,7 +2186,7 @@ impl LanguageServer for Backend {
                     },
                 )),
                 completion_provider: Some(CompletionOptions {
-                    resolve_provider: Some(true),
+                    resolve_provider: Some(completion_resolve),
                     trigger_characters: None,
                     work_done_progress_options: Default::default(),
                     all_commit_characters: None,
@@ -638,11 +2213,30 @@ impl LanguageServer for Backend {
                 workspace_symbol_provider: Some(OneOf::Left(true)),
                 code_action_provider: Some(CodeActionProviderCapability::Options(
                     CodeActionOptions {
-                        code_action_kinds: Some(vec![CodeActionKind::QUICKFIX]),
+                        code_action_kinds: Some(vec![
+                            CodeActionKind::QUICKFIX,
+                            CodeActionKind::SOURCE_ORGANIZE_IMPORTS,
+                        ]),
+                        resolve_provider: Some(true),
                         ..Default::default()
                     },
                 )),
                 document_highlight_provider: Some(OneOf::Left(true)),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: Some(true),
+                }),
+                document_link_provider: Some(DocumentLinkOptions {
+                    resolve_provider: Some(false),
+                    work_done_progress_options: Default::default(),
+                }),
+                inline_value_provider: Some(OneOf::Left(true)),
+                document_formatting_provider: Some(OneOf::Left(true)),
+                inlay_hint_provider: Some(OneOf::Left(true)),
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+                document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+                    first_trigger_character: "\n".to_string(),
+                    more_trigger_character: None,
+                }),
                 rename_provider: Some(OneOf::Right(RenameOptions {
                     prepare_provider: Some(true),
                     work_done_progress_options: Default::default(),
@@ -652,7 +2246,11 @@ impl LanguageServer for Backend {
                         supported: Some(true),
                         change_notifications: Some(OneOf::Left(true)),
                     }),
-                    file_operations: None,
+                    file_operations: Some(WorkspaceFileOperationsServerCapabilities {
+                        did_create: Some(br_file_operation_options()),
+                        did_delete: Some(br_file_operation_options()),
+                        ..Default::default()
+                    }),
                 }),
                 ..ServerCapabilities::default()
             },
@@ -662,41 +2260,61 @@ impl LanguageServer for Backend {
     async fn initialized(&self, _: InitializedParams) {
         debug!("initialized!");
 
+        // Pull initial settings from the client
+        self.pull_config().await;
+
         // Register file watcher for .brs, .wbs, .lay, and filelay/* files
+        let mut watchers = vec![
+            FileSystemWatcher {
+                glob_pattern: GlobPattern::String("**/*.brs".to_string()),
+                kind: Some(WatchKind::all()),
+            },
+            FileSystemWatcher {
+                glob_pattern: GlobPattern::String("**/*.wbs".to_string()),
+                kind: Some(WatchKind::all()),
+            },
+            FileSystemWatcher {
+                glob_pattern: GlobPattern::String("**/*.lay".to_string()),
+                kind: Some(WatchKind::all()),
+            },
+            FileSystemWatcher {
+                glob_pattern: GlobPattern::String("**/filelay/*".to_string()),
+                kind: Some(WatchKind::all()),
+            },
+            FileSystemWatcher {
+                glob_pattern: GlobPattern::String(format!("**/{}", snippets::SNIPPETS_FILE)),
+                kind: Some(WatchKind::all()),
+            },
+        ];
+        // Extensionless programs can't be matched by name, so watch
+        // everything and let `index_file_from_disk` sniff the contents
+        if self.config.read().await.paths.br_files.enabled {
+            watchers.push(FileSystemWatcher {
+                glob_pattern: GlobPattern::String("**/*".to_string()),
+                kind: Some(WatchKind::all()),
+            });
+        }
         let registrations = vec![Registration {
             id: "br-file-watcher".to_string(),
             method: "workspace/didChangeWatchedFiles".to_string(),
             register_options: Some(
-                serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
-                    watchers: vec![
-                        FileSystemWatcher {
-                            glob_pattern: GlobPattern::String("**/*.brs".to_string()),
-                            kind: Some(WatchKind::all()),
-                        },
-                        FileSystemWatcher {
-                            glob_pattern: GlobPattern::String("**/*.wbs".to_string()),
-                            kind: Some(WatchKind::all()),
-                        },
-                        FileSystemWatcher {
-                            glob_pattern: GlobPattern::String("**/*.lay".to_string()),
-                            kind: Some(WatchKind::all()),
-                        },
-                        FileSystemWatcher {
-                            glob_pattern: GlobPattern::String("**/filelay/*".to_string()),
-                            kind: Some(WatchKind::all()),
-                        },
-                    ],
-                })
-                .unwrap(),
+                serde_json::to_value(DidChangeWatchedFilesRegistrationOptions { watchers })
+                    .unwrap(),
             ),
         }];
 
-        if let Err(e) = self.client.register_capability(registrations).await {
+        if !self.client_features.read().await.watcher_registration {
+            debug!("client does not support dynamic watcher registration");
+        } else if let Err(e) = self.client.register_capability(registrations).await {
             warn!("Failed to register file watcher: {e}");
         }
 
-        // Pull initial diagnostics config from the client
-        self.pull_diagnostics_config().await;
+        self.reload_snippets().await;
+
+        tokio::spawn(Self::poll_index_changes(
+            self.republisher(),
+            self.logger.clone(),
+        ));
 
         // Spawn background workspace scan
         let folders = self.workspace_folders.read().await.clone();
@@ -705,7 +2323,14 @@ impl LanguageServer for Backend {
         let client = self.client.clone();
         let indexing_complete = self.indexing_complete.clone();
         let document_map = self.document_map.clone();
-        let diagnostics_config = self.diagnostics_config.clone();
+        let settings = self.config.clone();
+        let analysis_cache = self.analysis_cache.clone();
+        let external_diagnostics = self.external_diagnostics.clone();
+        let published_diagnostics = self.published_diagnostics.clone();
+        let diagnostics_epoch = self.diagnostics_epoch.clone();
+        let logger = self.logger.clone();
+        let walk_report = self.walk_report.clone();
+        let parse_failures = self.parse_failures.clone();
 
         tokio::spawn(async move {
             let token = NumberOrString::String("workspace-indexing".to_string());
@@ -735,22 +2360,52 @@ impl LanguageServer for Backend {
             let start = std::time::Instant::now();
             let mut total = 0usize;
             let mut total_files_scanned = 0usize;
+            let mut open_names = HashSet::new();
+            let mut report = workspace::WalkReport::default();
+            let mut failures = Vec::new();
+            let (detection, indexing) = {
+                let config = settings.read().await;
+                (config.paths.br_files.clone(), config.index.indexing)
+            };
 
             for folder in &folders {
-                let file_defs = Self::scan_workspace_folder(folder, &mut total_files_scanned);
-                let count = file_defs.len();
+                // Low-impact mode sleeps between batches; keep that off the
+                // runtime's other tasks
+                let file_defs = tokio::task::block_in_place(|| {
+                    Self::scan_workspace_folder(
+                        folder,
+                        &detection,
+                        &indexing,
+                        &mut total_files_scanned,
+                        &mut open_names,
+                        &mut report,
+                        &mut failures,
+                    )
+                });
+                let count = file_defs
+                    .iter()
+                    .filter(|(_, defs, _)| !defs.is_empty())
+                    .count();
 
                 let mut idx = index.write().await;
-                for (uri, defs) in file_defs {
+                for (uri, defs, calls) in file_defs {
                     idx.add_file(&uri, defs);
+                    idx.set_calls(&uri, calls);
                 }
                 total += count;
             }
 
-            // Scan for layout files
+            // Scan for layout files, then for layouts stored next to the
+            // data files OPEN statements name
             let mut layout_count = 0usize;
             for folder in &folders {
-                let layouts = crate::layout::scan_workspace_layouts(folder);
+                let mut layouts =
+                    crate::layout::scan_workspace_layouts(folder, detection.respect_gitignore);
+                layouts.extend(crate::layout::scan_linked_layouts(
+                    folder,
+                    &open_names,
+                    detection.respect_gitignore,
+                ));
                 layout_count += layouts.len();
                 let mut lidx = layout_index.write().await;
                 for (uri, layout) in layouts {
@@ -775,36 +2430,60 @@ impl LanguageServer for Backend {
                 })
                 .await;
 
-            client
-                .log_message(
+            logger
+                .log(
                     MessageType::INFO,
                     format!("Workspace indexing complete: {summary}"),
                 )
                 .await;
+            Self::report_skipped_paths(&client, &logger, &report).await;
+            *walk_report.write().await = report;
+            Self::report_parse_failures(&client, &logger, &failures).await;
+            *parse_failures.write().await = failures;
 
             indexing_complete.store(true, Ordering::Release);
 
             // Re-publish diagnostics for all open documents now that the
             // workspace index is available for undefined-function checks.
+            let epoch = diagnostics_epoch.fetch_add(1, Ordering::SeqCst) + 1;
             let to_publish: Vec<(String, Vec<Diagnostic>)> = {
-                let config = diagnostics_config.read().await;
+                let settings = settings.read().await;
+                let config = &settings.diagnostics;
                 let idx = index.read().await;
+                let layouts = layout_index.read().await;
                 document_map
                     .iter()
                     .filter_map(|entry| {
                         let uri_string = entry.key().clone();
                         let doc = entry.value();
                         let t = doc.tree.as_ref()?;
-                        let diags =
-                            Backend::collect_all_diagnostics(t, &doc.source, &config, Some(&idx));
+                        let uri = Url::parse(&uri_string).ok()?;
+                        let diags = Backend::collect_all_diagnostics(
+                            &uri,
+                            t,
+                            &doc.source,
+                            config,
+                            Some(&idx),
+                            Some(&layouts),
+                            None,
+                        );
+                        analysis_cache.store_diagnostics(
+                            &uri_string,
+                            doc.version,
+                            epoch,
+                            diags.clone(),
+                        );
                         Some((uri_string, diags))
                     })
                     .collect()
             };
 
+            let config = &settings.read().await.diagnostics;
             for (uri_string, diags) in to_publish {
                 if let Ok(uri) = Url::parse(&uri_string) {
-                    client.publish_diagnostics(uri, diags, None).await;
+                    let diags = external_checker::merged(&external_diagnostics, &uri_string, diags);
+                    let diags = config.published(&uri, diags);
+                    published_diagnostics.publish(&client, uri, diags).await;
                 }
             }
         });
@@ -819,6 +2498,7 @@ impl LanguageServer for Backend {
             uri: params.text_document.uri,
             text: params.text_document.text,
             language_id: params.text_document.language_id,
+            version: params.text_document.version,
         })
         .await;
         debug!("file opened!");
@@ -827,6 +2507,7 @@ impl LanguageServer for Backend {
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
         let start = std::time::Instant::now();
         let uri = params.text_document.uri;
+        let client_version = params.text_document.version;
         let uri_string = uri.to_string();
         let change_count = params.content_changes.len();
 
@@ -842,6 +2523,7 @@ impl LanguageServer for Backend {
                     uri,
                     text: change.text,
                     language_id: lang.to_string(),
+                    version: client_version,
                 })
                 .await;
             }
@@ -855,25 +2537,38 @@ impl LanguageServer for Backend {
                 ref mut source,
                 ..
             } = *doc;
+            let mut text = source.to_string();
             for change in params.content_changes {
                 match change.range {
                     Some(range) => {
-                        apply_change(rope, source, &range, &change.text);
+                        apply_change(rope, &mut text, &range, &change.text);
                     }
                     None => {
                         *rope = Rope::from_str(&change.text);
-                        *source = change.text;
+                        text = change.text;
                     }
                 }
             }
+            *source = Arc::from(text);
 
+            doc.version += 1;
+            doc.client_version = client_version;
             let source = doc.source.clone();
             drop(doc);
 
             if let Some(layout) = crate::layout::parse(&source) {
                 let mut idx = self.layout_index.write().await;
                 idx.update(&uri_string, layout);
+                // Record-length checks read the layout index
+                self.diagnostics_epoch.fetch_add(1, Ordering::SeqCst);
             }
+            let diagnostics = {
+                let config = &self.config.read().await.diagnostics;
+                config.published(&uri, Self::layout_diagnostics(&source, config))
+            };
+            self.published_diagnostics
+                .publish(&self.client, uri, diagnostics)
+                .await;
             return;
         }
 
@@ -882,26 +2577,33 @@ impl LanguageServer for Backend {
             ref mut rope,
             ref mut source,
             ref mut tree,
+            ref mut variable_usage,
             ..
         } = *doc;
 
         let had_old_tree = tree.is_some();
+        // Edited as a String, then shared again; snapshots of the old
+        // version keep theirs
+        let mut text = source.to_string();
         for change in params.content_changes {
             match change.range {
                 Some(range) => {
-                    let edit = apply_change(rope, source, &range, &change.text);
+                    let edit = apply_change(rope, &mut text, &range, &change.text);
                     if let Some(t) = tree.as_mut() {
                         t.edit(&edit);
                     }
+                    variable_usage.edit(&edit);
                 }
                 None => {
                     // Full replacement — reset everything
                     *rope = Rope::from_str(&change.text);
-                    *source = change.text;
+                    text = change.text;
                     *tree = None;
+                    *variable_usage = VariableUsage::default();
                 }
             }
         }
+        *source = Arc::from(text);
         let edit_elapsed = start.elapsed();
 
         // Reparse (incremental if we have an old tree)
@@ -915,11 +2617,12 @@ impl LanguageServer for Backend {
         let source_len = doc.source.len();
         doc.tree = tree;
 
+        doc.version += 1;
+        doc.client_version = client_version;
+
         // Drop the DashMap RefMut before awaiting (it's not Send)
         drop(doc);
 
-        self.symbol_cache.remove(&uri_string);
-
         let total_elapsed = start.elapsed();
 
         let mode = if incremental {
@@ -929,8 +2632,8 @@ impl LanguageServer for Backend {
         } else {
             "full (no prior tree)"
         };
-        self.client
-            .log_message(
+        self.logger
+            .log(
                 MessageType::LOG,
                 format!(
                     "did_change ({mode}): {source_len} bytes, {change_count} change(s), edit {edit_elapsed:.1?}, parse {parse_elapsed:.1?}, total {total_elapsed:.1?}"
@@ -942,813 +2645,217 @@ impl LanguageServer for Backend {
         self.schedule_diagnostics(uri, uri_string);
     }
 
-    async fn did_save(&self, _params: DidSaveTextDocumentParams) {
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
         debug!("file saved!");
-    }
-
-    async fn did_close(&self, params: DidCloseTextDocumentParams) {
-        let uri = params.text_document.uri.to_string();
-        let was_layout = self
-            .document_map
-            .get(&uri)
-            .map(|d| d.kind == DocumentKind::Layout)
-            .unwrap_or(false);
-        self.document_map.remove(&uri);
-        self.symbol_cache.remove(&uri);
-        if was_layout {
-            let mut idx = self.layout_index.write().await;
-            idx.remove(&uri);
-        }
-        self.client
-            .publish_diagnostics(params.text_document.uri, vec![], None)
-            .await;
-        debug!("file closed!");
-    }
-
-    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
-        let start = std::time::Instant::now();
-        let uri = params.text_document_position.text_document.uri.to_string();
-        let position = params.text_document_position.position;
-
-        if self.is_layout_doc(&uri) {
-            return Ok(None);
-        }
-
-        let index = self.workspace_index.read().await;
-        let layout_index = self.layout_index.read().await;
-        let items = match self.document_map.get(&uri) {
-            Some(doc) => completions::get_completions(&doc, &uri, position, &index, &layout_index),
-            None => return Ok(None),
-        };
-
-        let count = items.len();
-        let result = if items.is_empty() {
-            Ok(None)
-        } else {
-            Ok(Some(CompletionResponse::Array(items)))
-        };
-
-        self.client
-            .log_message(
-                MessageType::LOG,
-                format!("completion: {count} items ({:.1?})", start.elapsed()),
-            )
-            .await;
-
-        result
-    }
-
-    async fn completion_resolve(&self, mut item: CompletionItem) -> Result<CompletionItem> {
-        let data = match item
-            .data
-            .as_ref()
-            .and_then(|v| serde_json::from_value::<completions::CompletionData>(v.clone()).ok())
-        {
-            Some(d) => d,
-            None => return Ok(item),
-        };
-
-        let docs = match data {
-            completions::CompletionData::Builtin { ref name, overload } => {
-                let entries = builtins::lookup(name);
-                entries.get(overload).map(completions::format_builtin_docs)
-            }
-            completions::CompletionData::Local { ref name, ref uri } => {
-                self.document_map.get(uri).and_then(|doc| {
-                    let tree = doc.tree.as_ref()?;
-                    let defs = extract::extract_definitions(tree, &doc.source);
-                    defs.into_iter()
-                        .find(|d| d.name.eq_ignore_ascii_case(name))
-                        .map(|d| completions::format_function_docs(&d))
-                })
-            }
-            completions::CompletionData::Workspace { ref name } => {
-                let index = self.workspace_index.read().await;
-                index
-                    .lookup_best(name, "")
-                    .map(|e| completions::format_function_docs(&e.def))
-            }
+        let checker = self
+            .config
+            .read()
+            .await
+            .diagnostics
+            .external_checker
+            .clone();
+        let Some(checker) = checker else {
+            return;
         };
-
-        if let Some(md) = docs {
-            item.documentation = Some(Documentation::MarkupContent(MarkupContent {
-                kind: MarkupKind::Markdown,
-                value: md,
-            }));
-        }
-
-        Ok(item)
-    }
-
-    async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
-        let start = std::time::Instant::now();
-        let uri = params.text_document_position.text_document.uri.clone();
-        let uri_string = uri.to_string();
-        let position = params.text_document_position.position;
-
-        if self.is_layout_doc(&uri_string) {
-            return Ok(None);
-        }
-
-        // Check if cursor is on a user function name (cross-file candidate)
-        let fn_name = self.document_map.get(&uri_string).and_then(|doc| {
-            let tree = doc.tree.as_ref()?;
-            let name = references::resolve_function_name_at(
-                tree,
-                &doc.source,
-                position.line as usize,
-                position.character as usize,
-            )?;
-            if !builtins::lookup(&name).is_empty() {
-                return None; // system function — stay single-file
-            }
-            Some(name)
-        });
-
-        if let Some(name) = fn_name {
-            // Only search cross-file if the function is a library function.
-            // If the current file defines it as a non-library function, stay local
-            // even if other files have a library function with the same name.
-            let is_library_fn = {
-                let index = self.workspace_index.read().await;
-                let defs = index.lookup(&name);
-                let local_non_library = defs.iter().any(|d| {
-                    d.uri.as_str() == uri_string && !d.def.is_import_only && !d.def.is_library
-                });
-                !local_non_library && defs.iter().any(|d| d.def.is_library)
-            };
-
-            if is_library_fn {
-                // Cross-file search for library function references
-                let locations = self.search_workspace_for_function_refs(&name).await;
-                let count = locations.len();
-                self.client
-                    .log_message(
-                        MessageType::LOG,
-                        format!(
-                            "references (cross-file, \"{name}\"): {count} locations ({:.1?})",
-                            start.elapsed()
-                        ),
-                    )
-                    .await;
-                if locations.is_empty() {
-                    return Ok(None);
-                }
-                return Ok(Some(locations));
-            }
-            // Non-library function: fall through to single-file search
-        }
-
-        // Non-function symbols: single-file references
-        let locations = self.document_map.get(&uri_string).and_then(|doc| {
-            let tree = doc.tree.as_ref()?;
-            let refs = references::find_references(
-                tree,
-                &doc.source,
-                position.line as usize,
-                position.character as usize,
-            );
-            if refs.is_empty() {
-                None
-            } else {
-                Some(
-                    refs.into_iter()
-                        .map(|range| Location {
-                            uri: uri.clone(),
-                            range,
-                        })
-                        .collect(),
-                )
-            }
-        });
-
-        let count = locations.as_ref().map_or(0, |v: &Vec<Location>| v.len());
-        self.client
-            .log_message(
-                MessageType::LOG,
-                format!(
-                    "references (local): {count} locations ({:.1?})",
-                    start.elapsed()
-                ),
-            )
-            .await;
-
-        Ok(locations)
-    }
-
-    async fn document_highlight(
-        &self,
-        params: DocumentHighlightParams,
-    ) -> Result<Option<Vec<DocumentHighlight>>> {
-        let uri_string = params
-            .text_document_position_params
-            .text_document
-            .uri
-            .to_string();
-        let position = params.text_document_position_params.position;
-
-        if self.is_layout_doc(&uri_string) {
-            return Ok(None);
-        }
-
-        let highlights = self.document_map.get(&uri_string).and_then(|doc| {
-            let tree = doc.tree.as_ref()?;
-            let refs = references::find_references(
-                tree,
-                &doc.source,
-                position.line as usize,
-                position.character as usize,
-            );
-            if refs.is_empty() {
-                None
-            } else {
-                Some(
-                    refs.into_iter()
-                        .map(|range| DocumentHighlight {
-                            range,
-                            kind: Some(DocumentHighlightKind::TEXT),
-                        })
-                        .collect(),
-                )
-            }
-        });
-
-        Ok(highlights)
-    }
-
-    async fn prepare_rename(
-        &self,
-        params: TextDocumentPositionParams,
-    ) -> Result<Option<PrepareRenameResponse>> {
-        let uri_string = params.text_document.uri.to_string();
-        if self.is_layout_doc(&uri_string) {
-            return Ok(None);
-        }
-        let result = self.document_map.get(&uri_string).and_then(|doc| {
-            let tree = doc.tree.as_ref()?;
-            let r = rename::prepare_rename(
-                tree,
-                &doc.source,
-                params.position.line as usize,
-                params.position.character as usize,
-            )?;
-            Some(PrepareRenameResponse::RangeWithPlaceholder {
-                range: r.range,
-                placeholder: r.placeholder,
-            })
-        });
-        Ok(result)
-    }
-
-    async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
-        let start = std::time::Instant::now();
-        let uri = params.text_document_position.text_document.uri.clone();
-        let uri_string = uri.to_string();
-        let position = params.text_document_position.position;
-
-        if self.is_layout_doc(&uri_string) {
-            return Ok(None);
-        }
-
-        // Check if cursor is on a user function name (cross-file candidate)
-        let fn_name = self.document_map.get(&uri_string).and_then(|doc| {
-            let tree = doc.tree.as_ref()?;
-            let name = references::resolve_function_name_at(
-                tree,
-                &doc.source,
-                position.line as usize,
-                position.character as usize,
-            )?;
-            if !builtins::lookup(&name).is_empty() {
-                return None; // system function — rejected by prepare_rename
-            }
-            Some(name)
-        });
-
-        if let Some(name) = fn_name {
-            // Only search cross-file if the function is a library function.
-            // If the current file defines it as a non-library function, stay local
-            // even if other files have a library function with the same name.
-            let is_library_fn = {
-                let index = self.workspace_index.read().await;
-                let defs = index.lookup(&name);
-                let local_non_library = defs.iter().any(|d| {
-                    d.uri.as_str() == uri_string && !d.def.is_import_only && !d.def.is_library
-                });
-                !local_non_library && defs.iter().any(|d| d.def.is_library)
-            };
-
-            if is_library_fn {
-                // Cross-file rename for library functions
-                let locations = self.search_workspace_for_function_refs(&name).await;
-                if locations.is_empty() {
-                    self.client
-                        .log_message(
-                            MessageType::LOG,
-                            format!(
-                                "rename (cross-file, \"{name}\" -> \"{}\"): 0 edits ({:.1?})",
-                                params.new_name,
-                                start.elapsed()
-                            ),
-                        )
-                        .await;
-                    return Ok(None);
-                }
-                let edit_count = locations.len();
-                let mut changes: std::collections::HashMap<Url, Vec<TextEdit>> =
-                    std::collections::HashMap::new();
-                for loc in locations {
-                    changes.entry(loc.uri).or_default().push(TextEdit {
-                        range: loc.range,
-                        new_text: params.new_name.clone(),
-                    });
-                }
-                let file_count = changes.len();
-                self.client
-                    .log_message(
-                        MessageType::LOG,
-                        format!(
-                            "rename (cross-file, \"{name}\" -> \"{}\"): {edit_count} edits across {file_count} files ({:.1?})",
-                            params.new_name,
-                            start.elapsed()
-                        ),
-                    )
-                    .await;
-                return Ok(Some(WorkspaceEdit {
-                    changes: Some(changes),
-                    ..Default::default()
-                }));
-            }
-            // Non-library function: fall through to single-file rename
-        }
-
-        // Non-function symbols: single-file rename
-        let edits = self.document_map.get(&uri_string).and_then(|doc| {
-            let tree = doc.tree.as_ref()?;
-            let text_edits = rename::compute_renames(
-                tree,
-                &doc.source,
-                position.line as usize,
-                position.character as usize,
-                &params.new_name,
-            );
-            if text_edits.is_empty() {
-                None
-            } else {
-                Some(text_edits)
-            }
-        });
-
-        let count = edits.as_ref().map_or(0, |v| v.len());
-        self.client
-            .log_message(
-                MessageType::LOG,
-                format!("rename (local): {count} edits ({:.1?})", start.elapsed()),
-            )
-            .await;
-
-        match edits {
-            Some(text_edits) => {
-                let mut changes = std::collections::HashMap::new();
-                changes.insert(uri, text_edits);
-                Ok(Some(WorkspaceEdit {
-                    changes: Some(changes),
-                    ..Default::default()
-                }))
-            }
-            None => Ok(None),
-        }
-    }
-
-    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
         let uri = params.text_document.uri;
         let uri_string = uri.to_string();
-        if self.is_layout_doc(&uri_string) {
-            return Ok(None);
-        }
-        let doc = match self.document_map.get(&uri_string) {
-            Some(d) => d,
-            None => return Ok(None),
-        };
-        let tree = match doc.tree.as_ref() {
-            Some(t) => t,
-            None => return Ok(None),
+        let Some(doc) = self.snapshot(&uri_string) else {
+            return;
         };
-
-        let mut actions = Vec::new();
-        for diag in &params.context.diagnostics {
-            if let Some(action) =
-                code_action::create_function_stub_action(&uri, diag, tree, &doc.source)
-            {
-                actions.push(CodeActionOrCommand::CodeAction(action));
-            }
-        }
-
-        Ok(if actions.is_empty() {
-            None
-        } else {
-            Some(actions)
-        })
-    }
-
-    async fn goto_definition(
-        &self,
-        params: GotoDefinitionParams,
-    ) -> Result<Option<GotoDefinitionResponse>> {
-        let start = std::time::Instant::now();
-        let uri = params
-            .text_document_position_params
-            .text_document
-            .uri
-            .clone();
-        let uri_string = uri.to_string();
-        let position = params.text_document_position_params.position;
-
-        if self.is_layout_doc(&uri_string) {
-            return Ok(None);
+        if doc.kind == DocumentKind::Layout {
+            return;
         }
+        let Ok(path) = uri.to_file_path() else {
+            return;
+        };
+        let source = params.text.unwrap_or_else(|| doc.source.to_string());
 
-        let result = self.document_map.get(&uri_string).and_then(|doc| {
-            let tree = doc.tree.as_ref()?;
-            Some(definition::find_definition(
-                tree,
-                &doc.source,
-                position.line as usize,
-                position.character as usize,
-            ))
-        });
-
-        let response = match result {
-            Some(definition::DefinitionResult::Found(range)) => {
-                self.client
-                    .log_message(
-                        MessageType::LOG,
-                        format!("definition (local): found ({:.1?})", start.elapsed()),
-                    )
-                    .await;
-                Ok(Some(GotoDefinitionResponse::Scalar(Location {
-                    uri,
-                    range,
-                })))
-            }
-            Some(definition::DefinitionResult::LookupFunction(name)) => {
-                // Extract library links from the current doc's tree before awaiting locks
-                let library_links = self
-                    .document_map
-                    .get(&uri_string)
-                    .and_then(|doc| {
-                        let tree = doc.tree.as_ref()?;
-                        Some(extract::extract_library_links(tree, &doc.source))
-                    })
-                    .unwrap_or_default();
-
-                let folders = self.workspace_folders.read().await;
-                let index = self.workspace_index.read().await;
-                let def = index
-                    .lookup_prioritized_with_links(&name, &uri_string, &library_links, &folders)
-                    .into_iter()
-                    .next();
-                if let Some(def) = def {
-                    self.client
-                        .log_message(
-                            MessageType::LOG,
-                            format!(
-                                "definition (workspace, \"{name}\"): found ({:.1?})",
-                                start.elapsed()
-                            ),
-                        )
-                        .await;
-                    Ok(Some(GotoDefinitionResponse::Scalar(Location {
-                        uri: def.uri.clone(),
-                        range: def.def.selection_range,
-                    })))
-                } else {
-                    self.client
-                        .log_message(
-                            MessageType::LOG,
-                            format!(
-                                "definition (workspace, \"{name}\"): not found ({:.1?})",
-                                start.elapsed()
-                            ),
-                        )
+        let client = self.client.clone();
+        let document_map = self.document_map.clone();
+        let analysis_cache = self.analysis_cache.clone();
+        let diagnostics_epoch = self.diagnostics_epoch.clone();
+        let settings = self.config.clone();
+        let external_diagnostics = self.external_diagnostics.clone();
+        let published_diagnostics = self.published_diagnostics.clone();
+        let logger = self.logger.clone();
+
+        // The checker can take seconds; don't hold up the notification queue
+        tokio::spawn(async move {
+            let start = std::time::Instant::now();
+            let found = match external_checker::check(&checker, &path, &source).await {
+                Ok(found) => found,
+                Err(e) => {
+                    logger
+                        .log(MessageType::WARNING, format!("external checker: {e}"))
                         .await;
-                    Ok(None)
+                    return;
                 }
-            }
-            _ => Ok(None),
-        };
-
-        response
-    }
-
-    async fn document_symbol(
-        &self,
-        params: DocumentSymbolParams,
-    ) -> Result<Option<DocumentSymbolResponse>> {
-        let start = std::time::Instant::now();
-        let uri_string = params.text_document.uri.to_string();
-        if self.is_layout_doc(&uri_string) {
-            return Ok(None);
-        }
+            };
+            logger
+                .log(
+                    MessageType::LOG,
+                    format!(
+                        "external checker: {} diagnostics ({:.1?})",
+                        found.len(),
+                        start.elapsed()
+                    ),
+                )
+                .await;
 
-        // Check cache first
-        if let Some(cached) = self.symbol_cache.get(&uri_string) {
-            let syms = cached.value().clone();
-            if !syms.is_empty() {
-                self.client
-                    .log_message(
-                        MessageType::LOG,
-                        format!(
-                            "document_symbol: {} symbols ({:.1?}, cached)",
-                            syms.len(),
-                            start.elapsed()
-                        ),
-                    )
+            let Some(version) = document_map.get(&uri_string).map(|d| d.version) else {
+                return; // closed meanwhile
+            };
+            external_diagnostics.insert(uri_string.clone(), found);
+            // Without cached native diagnostics a debounced run is pending
+            // and publishes the merge itself
+            let epoch = diagnostics_epoch.load(Ordering::SeqCst);
+            if let Some(native) = analysis_cache.diagnostics(&uri_string, version, epoch) {
+                let diagnostics =
+                    external_checker::merged(&external_diagnostics, &uri_string, native);
+                let diagnostics = settings
+                    .read()
+                    .await
+                    .diagnostics
+                    .published(&uri, diagnostics);
+                published_diagnostics
+                    .publish(&client, uri, diagnostics)
                     .await;
-                return Ok(Some(DocumentSymbolResponse::Nested(syms)));
             }
-            return Ok(None);
-        }
-
-        let result = self.document_map.get(&uri_string).and_then(|doc| {
-            let tree = doc.tree.as_ref()?;
-            Some(symbols::collect_document_symbols(tree, &doc.source))
         });
-        match result {
-            Some(syms) if !syms.is_empty() => {
-                self.symbol_cache.insert(uri_string, syms.clone());
-                self.client
-                    .log_message(
-                        MessageType::LOG,
-                        format!(
-                            "document_symbol: {} symbols ({:.1?})",
-                            syms.len(),
-                            start.elapsed()
-                        ),
-                    )
-                    .await;
-                Ok(Some(DocumentSymbolResponse::Nested(syms)))
-            }
-            _ => Ok(None),
-        }
     }
 
-    async fn semantic_tokens_full(
-        &self,
-        params: SemanticTokensParams,
-    ) -> Result<Option<SemanticTokensResult>> {
-        let start = std::time::Instant::now();
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
         let uri = params.text_document.uri.to_string();
-        let tokens = self.document_map.get(&uri).map(|doc| match doc.kind {
-            DocumentKind::Layout => crate::layout::collect_layout_tokens(&doc.source),
-            DocumentKind::Br => match doc.tree.as_ref() {
-                Some(tree) => semantic_tokens::collect_tokens(tree, &doc.source),
-                None => Vec::new(),
-            },
-        });
-        let result = match tokens {
-            Some(t) if !t.is_empty() => {
-                let count = t.len();
-                self.client
-                    .log_message(
-                        MessageType::LOG,
-                        format!("semantic_tokens: {count} tokens ({:.1?})", start.elapsed()),
-                    )
-                    .await;
-                Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
-                    result_id: None,
-                    data: t,
-                })))
-            }
-            _ => Ok(None),
-        };
-        result
-    }
-
-    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
-        let uri_string = params
-            .text_document_position_params
-            .text_document
-            .uri
-            .to_string();
-        let position = params.text_document_position_params.position;
-
-        if self.is_layout_doc(&uri_string) {
-            return Ok(None);
-        }
-
-        // Extract everything we need from the DashMap ref, then drop it
-        enum HoverKind {
-            Builtin(String),
-            User(String, std::collections::HashMap<String, String>),
+        let was_layout = self
+            .document_map
+            .get(&uri)
+            .map(|d| d.kind == DocumentKind::Layout)
+            .unwrap_or(false);
+        self.document_map.remove(&uri);
+        self.analysis_cache.remove(&uri);
+        self.external_diagnostics.remove(&uri);
+        self.parse_health.forget(&uri);
+        if was_layout {
+            let mut idx = self.layout_index.write().await;
+            idx.remove(&uri);
         }
+        self.client
+            .publish_diagnostics(params.text_document.uri, vec![], None)
+            .await;
+        self.published_diagnostics.forget(&uri);
+        debug!("file closed!");
+    }
 
-        let (hover_kind, fn_name_range) = {
-            let doc = match self.document_map.get(&uri_string) {
-                Some(d) => d,
-                None => return Ok(None),
-            };
-            let tree = match doc.tree.as_ref() {
-                Some(t) => t,
-                None => return Ok(None),
-            };
-
-            // Find function_name node at cursor
-            let mut node = match parser::node_at_position(
-                tree,
-                position.line as usize,
-                position.character as usize,
-            ) {
-                Some(n) => n,
-                None => return Ok(None),
-            };
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        handlers::completion::completion(self, params).await
+    }
 
-            // Walk up to find a function_name node
-            loop {
-                if node.kind() == "function_name" {
-                    break;
-                }
-                match node.parent() {
-                    Some(p) => node = p,
-                    None => return Ok(None),
-                }
-            }
+    async fn completion_resolve(&self, item: CompletionItem) -> Result<CompletionItem> {
+        handlers::completion::completion_resolve(self, item).await
+    }
 
-            let fn_name = match node.utf8_text(doc.source.as_bytes()) {
-                Ok(s) => s.to_string(),
-                Err(_) => return Ok(None),
-            };
-            let fn_name_range = parser::node_range(node);
+    async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+        handlers::references::references(self, params).await
+    }
 
-            let parent = match node.parent() {
-                Some(p) => p,
-                None => return Ok(None),
-            };
+    async fn document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> Result<Option<Vec<DocumentHighlight>>> {
+        handlers::highlight::document_highlight(self, params).await
+    }
 
-            let kind = match parent.kind() {
-                "numeric_system_function" | "string_system_function" => HoverKind::Builtin(fn_name),
-                _ => {
-                    let library_links = extract::extract_library_links(tree, &doc.source);
-                    HoverKind::User(fn_name, library_links)
-                }
-            };
+    async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+        handlers::code_lens::code_lens(self, params).await
+    }
 
-            (kind, fn_name_range)
-        }; // doc dropped here
+    async fn code_lens_resolve(&self, params: CodeLens) -> Result<CodeLens> {
+        handlers::code_lens::code_lens_resolve(self, params).await
+    }
 
-        let markdown = match hover_kind {
-            HoverKind::Builtin(ref fn_name) => {
-                let builtins = builtins::lookup(fn_name);
-                if builtins.is_empty() {
-                    return Ok(None);
-                }
-                format_builtin_hover(builtins)
-            }
-            HoverKind::User(ref fn_name, ref library_links) => {
-                let folders = self.workspace_folders.read().await;
-                let index = self.workspace_index.read().await;
-                let defs = index.lookup_prioritized_with_links(
-                    fn_name,
-                    &uri_string,
-                    library_links,
-                    &folders,
-                );
-                if defs.is_empty() {
-                    return Ok(None);
-                }
-                format_user_hover_multi(&defs)
-            }
-        };
+    async fn document_link(&self, params: DocumentLinkParams) -> Result<Option<Vec<DocumentLink>>> {
+        handlers::document_link::document_link(self, params).await
+    }
 
-        Ok(Some(Hover {
-            contents: HoverContents::Markup(MarkupContent {
-                kind: MarkupKind::Markdown,
-                value: markdown,
-            }),
-            range: Some(fn_name_range),
-        }))
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        handlers::formatting::formatting(self, params).await
     }
 
-    async fn signature_help(&self, params: SignatureHelpParams) -> Result<Option<SignatureHelp>> {
-        let uri_string = params
-            .text_document_position_params
-            .text_document
-            .uri
-            .to_string();
-        let position = params.text_document_position_params.position;
+    async fn on_type_formatting(
+        &self,
+        params: DocumentOnTypeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        handlers::formatting::on_type_formatting(self, params).await
+    }
 
-        if self.is_layout_doc(&uri_string) {
-            return Ok(None);
-        }
+    async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        handlers::inlay_hint::inlay_hint(self, params).await
+    }
 
-        let doc = match self.document_map.get(&uri_string) {
-            Some(d) => d,
-            None => return Ok(None),
-        };
+    async fn inline_value(&self, params: InlineValueParams) -> Result<Option<Vec<InlineValue>>> {
+        handlers::inline_value::inline_value(self, params).await
+    }
 
-        // Try tree-based approach first
-        let call_ctx = doc
-            .tree
-            .as_ref()
-            .and_then(|tree| {
-                let cursor_node = parser::node_at_position(
-                    tree,
-                    position.line as usize,
-                    position.character as usize,
-                )?;
-
-                // Walk up to find an arguments node
-                let mut node = cursor_node;
-                let args_node = loop {
-                    if node.kind() == "arguments" {
-                        break node;
-                    }
-                    node = node.parent()?;
-                };
+    async fn folding_range(&self, params: FoldingRangeParams) -> Result<Option<Vec<FoldingRange>>> {
+        handlers::folding_range::folding_range(self, params).await
+    }
 
-                let call_node = args_node.parent()?;
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>> {
+        handlers::rename::prepare_rename(self, params).await
+    }
 
-                let mut cursor = call_node.walk();
-                let fn_name_node = call_node
-                    .children(&mut cursor)
-                    .find(|c| c.kind() == "function_name")?;
+    async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        handlers::rename::rename(self, params).await
+    }
 
-                let fn_name = fn_name_node.utf8_text(doc.source.as_bytes()).ok()?;
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        handlers::code_action::code_action(self, params).await
+    }
 
-                // Count commas before cursor to determine active parameter
-                let mut count = 0u32;
-                let mut cursor = args_node.walk();
-                for child in args_node.children(&mut cursor) {
-                    if !child.is_named()
-                        && child.utf8_text(doc.source.as_bytes()).ok() == Some(",")
-                        && child.end_position().column as u32 <= position.character
-                        && child.end_position().row as u32 <= position.line
-                    {
-                        count += 1;
-                    }
-                }
+    async fn code_action_resolve(&self, action: CodeAction) -> Result<CodeAction> {
+        handlers::code_action::code_action_resolve(self, action).await
+    }
 
-                Some(parser::CallContext {
-                    name: fn_name.to_string(),
-                    active_param: count,
-                })
-            })
-            // Fall back to text-based scanning when tree walk fails
-            .or_else(|| {
-                parser::find_function_call_context(
-                    &doc.source,
-                    position.line as usize,
-                    position.character as usize,
-                )
-            });
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        handlers::definition::goto_definition(self, params).await
+    }
 
-        let call_ctx = match call_ctx {
-            Some(ctx) => ctx,
-            None => return Ok(None),
-        };
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> Result<Option<DocumentSymbolResponse>> {
+        handlers::symbols::document_symbol(self, params).await
+    }
 
-        // Extract library links before dropping the DashMap ref
-        let library_links = doc
-            .tree
-            .as_ref()
-            .map(|tree| extract::extract_library_links(tree, &doc.source))
-            .unwrap_or_default();
-        drop(doc);
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> Result<Option<SemanticTokensResult>> {
+        handlers::semantic_tokens::semantic_tokens_full(self, params).await
+    }
 
-        let signatures = {
-            let builtins = builtins::lookup(&call_ctx.name);
-            if !builtins.is_empty() {
-                build_builtin_signatures(builtins, call_ctx.active_param)
-            } else {
-                let folders = self.workspace_folders.read().await;
-                let index = self.workspace_index.read().await;
-                match index
-                    .lookup_prioritized_with_links(
-                        &call_ctx.name,
-                        &uri_string,
-                        &library_links,
-                        &folders,
-                    )
-                    .into_iter()
-                    .next()
-                {
-                    Some(d) => build_user_signatures(&d.def, call_ctx.active_param),
-                    None => return Ok(None),
-                }
-            }
-        };
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        handlers::hover::hover(self, params).await
+    }
 
-        Ok(Some(SignatureHelp {
-            signatures,
-            active_signature: Some(0),
-            active_parameter: Some(call_ctx.active_param),
-        }))
+    async fn signature_help(&self, params: SignatureHelpParams) -> Result<Option<SignatureHelp>> {
+        handlers::signature_help::signature_help(self, params).await
     }
 
     async fn did_change_configuration(&self, _: DidChangeConfigurationParams) {
         debug!("configuration changed!");
-        self.pull_diagnostics_config().await;
-        self.republish_all_diagnostics().await;
+        let old = self.config.read().await.clone();
+        self.pull_config().await;
+        let rerun =
+            config_change::rerun_for(&old.diagnostics, &self.config.read().await.diagnostics);
+        debug!("rerunning after configuration change: {rerun:?}");
+        self.republish_diagnostics(&rerun).await;
     }
 
     async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
@@ -1761,8 +2868,15 @@ impl LanguageServer for Backend {
             let mut folders = self.workspace_folders.write().await;
             let mut index = self.workspace_index.write().await;
 
+            let mut walk_report = self.walk_report.write().await;
+            let mut parse_failures = self.parse_failures.write().await;
+
             for removed in &event.removed {
                 folders.retain(|f| f != &removed.uri);
+                if let Ok(path) = removed.uri.to_file_path() {
+                    walk_report.forget_under(&path);
+                    parse_failures.retain(|f| !f.path.starts_with(&path));
+                }
 
                 // Remove all indexed definitions under this folder
                 let folder_str = removed.uri.as_str();
@@ -1790,485 +2904,214 @@ impl LanguageServer for Backend {
 
             let index = self.workspace_index.clone();
             let client = self.client.clone();
+            let logger = self.logger.clone();
+            let walk_report = self.walk_report.clone();
+            let parse_failures = self.parse_failures.clone();
+            let (detection, indexing) = {
+                let config = self.config.read().await;
+                (config.paths.br_files.clone(), config.index.indexing)
+            };
 
             tokio::spawn(async move {
                 let start = std::time::Instant::now();
                 let mut total = 0usize;
                 let mut total_files_scanned = 0usize;
+                let mut report = workspace::WalkReport::default();
+                let mut failures = Vec::new();
 
                 for folder in &new_folders {
-                    let file_defs = Self::scan_workspace_folder(folder, &mut total_files_scanned);
-                    let count = file_defs.len();
+                    let file_defs = tokio::task::block_in_place(|| {
+                        Self::scan_workspace_folder(
+                            folder,
+                            &detection,
+                            &indexing,
+                            &mut total_files_scanned,
+                            &mut HashSet::new(),
+                            &mut report,
+                            &mut failures,
+                        )
+                    });
+                    let count = file_defs
+                        .iter()
+                        .filter(|(_, defs, _)| !defs.is_empty())
+                        .count();
 
                     let mut idx = index.write().await;
-                    for (uri, defs) in file_defs {
+                    for (uri, defs, calls) in file_defs {
                         idx.add_file(&uri, defs);
+                        idx.set_calls(&uri, calls);
                     }
                     total += count;
                 }
 
                 let elapsed = start.elapsed();
-                client
-                    .log_message(
+                logger
+                    .log(
                         MessageType::INFO,
                         format!(
                             "Workspace folder scan complete in {elapsed:.1?}: scanned {total_files_scanned} files, {total} contain definitions"
                         ),
                     )
                     .await;
+                Self::report_skipped_paths(&client, &logger, &report).await;
+                walk_report.write().await.extend(report);
+                Self::report_parse_failures(&client, &logger, &failures).await;
+                parse_failures.write().await.extend(failures);
             });
         }
+
+        self.reload_snippets().await;
     }
 
     async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
         debug!("watched files have changed!");
 
+        let mut snippets_changed = false;
         for change in params.changes {
             let file_path = match change.uri.to_file_path() {
                 Ok(p) => p,
                 Err(()) => continue,
             };
-
-            let is_layout = crate::layout::is_layout_file(&file_path);
+            if snippets::is_snippets_file(&file_path) {
+                snippets_changed = true;
+                continue;
+            }
 
             match change.typ {
                 FileChangeType::DELETED => {
-                    if is_layout {
-                        let mut idx = self.layout_index.write().await;
-                        idx.remove(change.uri.as_ref());
-                    } else {
-                        let mut index = self.workspace_index.write().await;
-                        index.remove_file(&change.uri);
-                    }
+                    self.remove_file_from_indexes(&change.uri, &file_path).await;
                 }
                 FileChangeType::CREATED | FileChangeType::CHANGED => {
                     // Skip if the file is currently open — editor content takes precedence
                     if self.document_map.contains_key(change.uri.as_str()) {
                         continue;
                     }
-
-                    if is_layout {
-                        let source = match crate::layout::read_layout_file(&file_path) {
-                            Ok(s) => s,
-                            Err(e) => {
-                                error!("Failed to read layout {}: {e}", file_path.display());
-                                continue;
-                            }
-                        };
-                        if let Some(layout) = crate::layout::parse(&source) {
-                            let mut idx = self.layout_index.write().await;
-                            idx.update(change.uri.as_ref(), layout);
-                        }
-                    } else {
-                        let source = match workspace::read_br_file(&file_path) {
-                            Ok(s) => s,
-                            Err(e) => {
-                                error!("Failed to read {}: {e}", file_path.display());
-                                continue;
-                            }
-                        };
-
-                        let tree = {
-                            let mut parser = self.parser.lock().unwrap();
-                            parser::parse(&mut parser, &source, None)
-                        };
-
-                        if let Some(t) = tree {
-                            let defs = extract::extract_definitions(&t, &source);
-                            let mut index = self.workspace_index.write().await;
-                            index.update_file(&change.uri, defs);
-                        }
-                    }
+                    self.index_file_from_disk(&change.uri, &file_path).await;
                 }
                 _ => {}
             }
         }
-    }
-
-    async fn symbol(
-        &self,
-        params: WorkspaceSymbolParams,
-    ) -> Result<Option<Vec<SymbolInformation>>> {
-        let start = std::time::Instant::now();
-        let index = self.workspace_index.read().await;
-        let query = params.query.to_ascii_lowercase();
-
-        let symbols: Vec<SymbolInformation> = index
-            .all_symbols()
-            .into_iter()
-            .filter(|s| query.is_empty() || s.def.name.to_ascii_lowercase().contains(&query))
-            .map(|s| {
-                #[allow(deprecated)]
-                SymbolInformation {
-                    name: s.def.name.clone(),
-                    kind: SymbolKind::FUNCTION,
-                    tags: None,
-                    deprecated: None,
-                    location: Location {
-                        uri: s.uri.clone(),
-                        range: s.def.selection_range,
-                    },
-                    container_name: None,
-                }
-            })
-            .collect();
-
-        self.client
-            .log_message(
-                MessageType::LOG,
-                format!(
-                    "workspace_symbol (\"{}\"): {} symbols ({:.1?})",
-                    params.query,
-                    symbols.len(),
-                    start.elapsed()
-                ),
-            )
-            .await;
 
-        if symbols.is_empty() {
-            Ok(None)
-        } else {
-            Ok(Some(symbols))
+        if snippets_changed {
+            self.reload_snippets().await;
         }
     }
 
-    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
-        if params.command != "br.inspectNode" {
-            debug!("command executed: {}", params.command);
-        }
-
-        if params.command == "br.scanAll" {
-            let start = std::time::Instant::now();
-            let folders = self.workspace_folders.read().await.clone();
-            let config = self.diagnostics_config.read().await.clone();
-
-            let results = tokio::task::spawn_blocking(move || {
-                let mut all_results: Vec<(Url, Vec<Diagnostic>)> = Vec::new();
-                for folder in &folders {
-                    all_results.extend(Self::scan_workspace_diagnostics(folder, &config));
-                }
-                all_results
-            })
-            .await
-            .unwrap_or_default();
-
-            for (uri, diags) in &results {
-                self.client
-                    .publish_diagnostics(uri.clone(), diags.clone(), None)
-                    .await;
-            }
-
-            let total_files = results.len();
-            let files_with_errors = results.iter().filter(|(_, d)| !d.is_empty()).count();
-            let total_diags: usize = results.iter().map(|(_, d)| d.len()).sum();
-            let summary = format!("Scanned {total_files} files, {files_with_errors} with errors");
-
-            self.client
-                .log_message(
-                    MessageType::INFO,
-                    format!(
-                        "scanAll: {total_files} files, {total_diags} diagnostics, {files_with_errors} files with errors ({:.1?})",
-                        start.elapsed()
-                    ),
-                )
-                .await;
-
-            let csv = check::diagnostics_to_csv(&results);
-
-            return Ok(Some(serde_json::json!({
-                "summary": summary,
-                "csv": csv,
-            })));
-        }
-
-        if params.command == "br.inspectNode" {
-            let args = params.arguments;
-            let uri_str = args.first().and_then(|v| v.as_str()).unwrap_or_default();
-            let line = args.get(1).and_then(|v| v.as_u64()).unwrap_or(0) as usize;
-            let character = args.get(2).and_then(|v| v.as_u64()).unwrap_or(0) as usize;
-
-            let doc = match self.document_map.get(uri_str) {
-                Some(d) => d,
-                None => return Ok(None),
-            };
-            let tree = match doc.tree.as_ref() {
-                Some(t) => t,
-                None => return Ok(None),
-            };
-
-            let point = Point::new(line, character);
-
-            // Deepest node (including anonymous like keywords/operators)
-            let node = match tree.root_node().descendant_for_point_range(point, point) {
-                Some(n) => n,
-                None => return Ok(None),
+    async fn did_create_files(&self, params: CreateFilesParams) {
+        let detection = self.config.read().await.paths.br_files.clone();
+        let mut programs = HashSet::new();
+        let mut layouts_changed = false;
+        for file in params.files {
+            let Ok(uri) = Url::parse(&file.uri) else {
+                continue;
             };
-
-            let kind = node.kind().to_string();
-            let is_named = node.is_named();
-            let text = node
-                .utf8_text(doc.source.as_bytes())
-                .unwrap_or("")
-                .to_string();
-            let text = if text.len() > 80 {
-                format!("{}...", &text[..80])
-            } else {
-                text
+            let Ok(file_path) = uri.to_file_path() else {
+                continue;
             };
-
-            let range = parser::node_range(node);
-
-            // Build ancestor chain
-            let mut ancestors = Vec::new();
-            let mut current = node.parent();
-            while let Some(n) = current {
-                ancestors.push(n.kind().to_string());
-                current = n.parent();
-            }
-            ancestors.reverse();
-
-            // Named ancestor (for anonymous nodes)
-            let named_ancestor = if !is_named {
-                let mut p = node.parent();
-                while let Some(n) = p {
-                    if n.is_named() {
-                        break;
-                    }
-                    p = n.parent();
-                }
-                p.filter(|n| n.is_named()).map(|n| {
-                    serde_json::json!({
-                        "kind": n.kind(),
-                        "range": {
-                            "start": { "line": parser::node_range(n).start.line, "character": parser::node_range(n).start.character },
-                            "end": { "line": parser::node_range(n).end.line, "character": parser::node_range(n).end.character }
-                        }
-                    })
+            // A created folder brings the programs and layouts in it
+            let files: Vec<(Url, PathBuf)> = if file_path.is_dir() {
+                let detection = detection.clone();
+                tokio::task::spawn_blocking(move || {
+                    workspace::walk_files(&file_path, detection.respect_gitignore)
+                        .filter(|p| crate::layout::is_layout_file(p) || detection.is_br_file(p))
+                        .filter_map(|p| Some((Url::from_file_path(&p).ok()?, p)))
+                        .collect()
                 })
+                .await
+                .unwrap_or_default()
             } else {
-                None
-            };
-
-            // Semantic token classification via the named node at position
-            let semantic_token = {
-                let named_node = parser::node_at_position(tree, line, character);
-                named_node
-                    .and_then(|nn| {
-                        let in_parameter = semantic_tokens::is_inside(nn, "parameter_list")
-                            || semantic_tokens::is_inside(nn, "required_parameter")
-                            || semantic_tokens::is_inside(nn, "optional_parameter")
-                            || semantic_tokens::is_inside(nn, "parameter");
-                        let in_dim = semantic_tokens::is_inside(nn, "dim_statement");
-                        semantic_tokens::classify_node(
-                            nn.kind(),
-                            nn.is_named(),
-                            nn,
-                            in_parameter,
-                            in_dim,
-                        )
-                        .map(|(type_idx, mod_bits)| {
-                            let type_name = semantic_tokens::TOKEN_TYPES
-                                .get(type_idx as usize)
-                                .map(|t| t.as_str().to_string())
-                                .unwrap_or_else(|| type_idx.to_string());
-                            let modifiers: Vec<String> = semantic_tokens::TOKEN_MODIFIERS
-                                .iter()
-                                .enumerate()
-                                .filter(|(i, _)| mod_bits & (1 << i) != 0)
-                                .map(|(_, m)| m.as_str().to_string())
-                                .collect();
-                            serde_json::json!({ "type": type_name, "modifiers": modifiers })
-                        })
-                    })
-                    // Also try classifying the deepest (possibly anonymous) node directly
-                    .or_else(|| {
-                        let in_parameter = semantic_tokens::is_inside(node, "parameter_list")
-                            || semantic_tokens::is_inside(node, "required_parameter")
-                            || semantic_tokens::is_inside(node, "optional_parameter")
-                            || semantic_tokens::is_inside(node, "parameter");
-                        let in_dim = semantic_tokens::is_inside(node, "dim_statement");
-                        semantic_tokens::classify_node(&kind, is_named, node, in_parameter, in_dim)
-                            .map(|(type_idx, mod_bits)| {
-                                let type_name = semantic_tokens::TOKEN_TYPES
-                                    .get(type_idx as usize)
-                                    .map(|t| t.as_str().to_string())
-                                    .unwrap_or_else(|| type_idx.to_string());
-                                let modifiers: Vec<String> = semantic_tokens::TOKEN_MODIFIERS
-                                    .iter()
-                                    .enumerate()
-                                    .filter(|(i, _)| mod_bits & (1 << i) != 0)
-                                    .map(|(_, m)| m.as_str().to_string())
-                                    .collect();
-                                serde_json::json!({ "type": type_name, "modifiers": modifiers })
-                            })
-                    })
+                vec![(uri, file_path)]
             };
-
-            return Ok(Some(serde_json::json!({
-                "kind": kind,
-                "isNamed": is_named,
-                "text": text,
-                "range": {
-                    "start": { "line": range.start.line, "character": range.start.character },
-                    "end": { "line": range.end.line, "character": range.end.character }
-                },
-                "ancestors": ancestors,
-                "childCount": node.child_count(),
-                "namedChildCount": node.named_child_count(),
-                "isError": node.is_error(),
-                "isMissing": node.is_missing(),
-                "hasError": node.has_error(),
-                "namedAncestor": named_ancestor,
-                "semanticToken": semantic_token,
-            })));
+            for (uri, file_path) in files {
+                if self.document_map.contains_key(uri.as_str()) {
+                    continue;
+                }
+                self.index_file_from_disk(&uri, &file_path).await;
+                if crate::layout::is_layout_file(&file_path) {
+                    layouts_changed = true;
+                } else {
+                    programs.insert(uri);
+                }
+            }
         }
 
-        Ok(None)
+        // New definitions may resolve previously undefined calls
+        let added = self
+            .workspace_index
+            .read()
+            .await
+            .names_defined_in(&programs);
+        self.recheck_callers(&added, layouts_changed).await;
     }
-}
-
-fn build_builtin_signatures(
-    builtins: &[builtins::BuiltinFunction],
-    active_param: u32,
-) -> Vec<SignatureInformation> {
-    builtins
-        .iter()
-        .map(|b| {
-            let (label, offsets) = b.format_signature_with_offsets();
-            let parameters: Vec<ParameterInformation> = b
-                .params
-                .iter()
-                .zip(offsets.iter())
-                .map(|(p, off)| ParameterInformation {
-                    label: ParameterLabel::LabelOffsets(*off),
-                    documentation: p.documentation.as_ref().map(|d| {
-                        Documentation::MarkupContent(MarkupContent {
-                            kind: MarkupKind::Markdown,
-                            value: d.clone(),
-                        })
-                    }),
-                })
-                .collect();
-            SignatureInformation {
-                label,
-                documentation: b.documentation.as_ref().map(|d| {
-                    Documentation::MarkupContent(MarkupContent {
-                        kind: MarkupKind::Markdown,
-                        value: d.clone(),
-                    })
-                }),
-                parameters: Some(parameters),
-                active_parameter: Some(active_param),
-            }
-        })
-        .collect()
-}
 
-fn build_user_signatures(
-    def: &extract::FunctionDef,
-    active_param: u32,
-) -> Vec<SignatureInformation> {
-    let (label, offsets) = def.format_signature_with_offsets();
-    let parameters: Vec<ParameterInformation> = def
-        .params
-        .iter()
-        .zip(offsets.iter())
-        .map(|(p, off)| ParameterInformation {
-            label: ParameterLabel::LabelOffsets(*off),
-            documentation: p.documentation.as_ref().map(|d| {
-                Documentation::MarkupContent(MarkupContent {
-                    kind: MarkupKind::Markdown,
-                    value: d.clone(),
-                })
-            }),
-        })
-        .collect();
-    vec![SignatureInformation {
-        label,
-        documentation: def.documentation.as_ref().map(|d| {
-            Documentation::MarkupContent(MarkupContent {
-                kind: MarkupKind::Markdown,
-                value: d.clone(),
-            })
-        }),
-        parameters: Some(parameters),
-        active_parameter: Some(active_param),
-    }]
-}
+    async fn did_delete_files(&self, params: DeleteFilesParams) {
+        let mut removed = HashSet::new();
+        for file in params.files {
+            let Ok(uri) = Url::parse(&file.uri) else {
+                continue;
+            };
+            let Ok(file_path) = uri.to_file_path() else {
+                continue;
+            };
+            // A deleted folder is gone from disk, so what it held comes
+            // from the indexes
+            removed.extend(self.indexed_files_under(&file_path).await);
+            removed.insert(uri);
+        }
 
-fn format_builtin_hover(builtins: &[builtins::BuiltinFunction]) -> String {
-    let mut parts = Vec::new();
-    for b in builtins {
-        let sig = b.format_signature();
-        let mut md = format!("```br\n{sig}\n```");
-        if let Some(doc) = &b.documentation {
-            md.push_str("\n\n---\n\n");
-            md.push_str(doc);
-        }
-        if !b.params.is_empty() {
-            let param_docs: Vec<String> = b
-                .params
-                .iter()
-                .filter(|p| p.documentation.is_some())
-                .map(|p| {
-                    format!(
-                        "*@param* `{}` \u{2014} {}",
-                        p.name,
-                        p.documentation.as_deref().unwrap()
-                    )
-                })
-                .collect();
-            if !param_docs.is_empty() {
-                md.push_str("\n\n");
-                md.push_str(&param_docs.join("\n\n"));
-            }
+        let deleted = self.workspace_index.read().await.names_defined_in(&removed);
+        let mut layouts_changed = false;
+        for uri in &removed {
+            let Ok(file_path) = uri.to_file_path() else {
+                continue;
+            };
+            layouts_changed |= crate::layout::is_layout_file(&file_path);
+            self.remove_file_from_indexes(uri, &file_path).await;
+            self.analysis_cache.remove(uri.as_str());
+            self.diagnostics_generation.remove(uri.as_str());
+            self.client
+                .publish_diagnostics(uri.clone(), Vec::new(), None)
+                .await;
+            self.published_diagnostics.forget(uri.as_str());
         }
-        parts.push(md);
-    }
-    parts.join("\n\n---\n\n")
-}
 
-fn format_user_hover_multi(defs: &[&workspace::IndexedFunctionDef]) -> String {
-    // Show only the first (highest priority) non-import-only definition
-    let best = defs.iter().find(|d| !d.def.is_import_only).or(defs.first());
-    match best {
-        Some(d) => format_user_hover(&d.def),
-        None => String::new(),
+        // Open documents calling functions from the deleted files need their
+        // undefined-function diagnostics recomputed.
+        self.recheck_callers(&deleted, layouts_changed).await;
     }
-}
 
-fn format_user_hover(def: &extract::FunctionDef) -> String {
-    let sig = def.format_signature();
-    let mut md = format!("```br\n{sig}\n```");
-
-    if let Some(doc) = &def.documentation {
-        md.push_str("\n\n---\n\n");
-        md.push_str(doc);
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> Result<Option<Vec<SymbolInformation>>> {
+        handlers::workspace_symbol::workspace_symbol(self, params).await
     }
 
-    let param_docs: Vec<String> = def
-        .params
-        .iter()
-        .filter(|p| p.documentation.is_some())
-        .map(|p| {
-            format!(
-                "*@param* `{}` \u{2014} {}",
-                p.format_label(),
-                p.documentation.as_deref().unwrap()
-            )
-        })
-        .collect();
-    if !param_docs.is_empty() {
-        md.push_str("\n\n");
-        md.push_str(&param_docs.join("\n\n"));
+    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+        handlers::commands::execute_command(self, params).await
     }
+}
 
-    if let Some(ret) = &def.return_documentation {
-        md.push_str("\n\n");
-        md.push_str(&format!("*@returns* \u{2014} {ret}"));
+/// File operation filters for BR sources and layouts, used for the
+/// `workspace/didCreateFiles` and `workspace/didDeleteFiles` registrations.
+fn br_file_operation_options() -> FileOperationRegistrationOptions {
+    let filter = |glob: &str| FileOperationFilter {
+        scheme: Some("file".to_string()),
+        pattern: FileOperationPattern {
+            glob: glob.to_string(),
+            matches: Some(FileOperationPatternKind::File),
+            options: Some(FileOperationPatternOptions {
+                ignore_case: Some(true),
+            }),
+        },
+    };
+    FileOperationRegistrationOptions {
+        filters: vec![
+            filter("**/*.{brs,wbs}"),
+            filter("**/*.lay"),
+            filter("**/filelay/*"),
+        ],
     }
-
-    md
 }
 
 #[cfg(test)]
@@ -2333,6 +3176,27 @@ mod tests {
         assert_eq!(edit.new_end_position, Point::new(1, 9));
     }
 
+    #[test]
+    fn apply_change_clamps_columns_past_the_line_end() {
+        // A client expanding the tab to 4 columns sees this line as 13 wide
+        let original = "\tlet x = 1\nprint x\n";
+        let mut rope = Rope::from_str(original);
+        let mut source = original.to_string();
+
+        let range = Range::new(Position::new(0, 13), Position::new(0, 13));
+        let edit = apply_change(&mut rope, &mut source, &range, "0");
+        assert_eq!(source, "\tlet x = 10\nprint x\n");
+        assert_eq!(rope.to_string(), source);
+        assert_eq!(edit.start_byte, 10);
+        assert_eq!(edit.start_position, Point::new(0, 10));
+
+        // Past the last line appends to the document
+        let range = Range::new(Position::new(5, 0), Position::new(5, 0));
+        let edit = apply_change(&mut rope, &mut source, &range, "end\n");
+        assert_eq!(source, "\tlet x = 10\nprint x\nend\n");
+        assert_eq!(edit.start_position, Point::new(2, 0));
+    }
+
     #[test]
     fn incremental_parse_matches_full_parse() {
         let original = "let x = 1\n";
@@ -2401,4 +3265,69 @@ mod tests {
         assert_eq!(edit.old_end_position, Point::new(0, 5));
         assert_eq!(edit.new_end_position, Point::new(0, 5));
     }
+
+    #[test]
+    fn client_features_default_to_plain_client() {
+        let features = ClientFeatures::from_capabilities(&ClientCapabilities::default());
+        assert!(!features.hover_markdown);
+        assert!(!features.label_offsets);
+        assert!(!features.completion_resolve);
+        assert!(!features.completion_snippets);
+        assert!(!features.watcher_registration);
+        assert!(!features.change_annotations);
+        assert!(!features.code_action_resolve);
+    }
+
+    #[test]
+    fn client_features_from_capabilities() {
+        let caps: ClientCapabilities = serde_json::from_value(serde_json::json!({
+            "textDocument": {
+                "hover": { "contentFormat": ["markdown", "plaintext"] },
+                "completion": {
+                    "completionItem": {
+                        "documentationFormat": ["plaintext"],
+                        "resolveSupport": { "properties": ["documentation"] },
+                        "snippetSupport": true,
+                        "commitCharactersSupport": true
+                    }
+                },
+                "signatureHelp": {
+                    "signatureInformation": {
+                        "parameterInformation": { "labelOffsetSupport": true }
+                    }
+                },
+                "codeAction": { "resolveSupport": { "properties": ["edit"] } }
+            },
+            "workspace": {
+                "didChangeWatchedFiles": { "dynamicRegistration": true },
+                "workspaceEdit": {
+                    "documentChanges": true,
+                    "changeAnnotationSupport": { "groupsOnLabel": true }
+                }
+            }
+        }))
+        .unwrap();
+        let features = ClientFeatures::from_capabilities(&caps);
+        assert!(features.hover_markdown);
+        assert!(!features.completion_markdown);
+        assert!(!features.signature_markdown);
+        assert!(features.label_offsets);
+        assert!(features.completion_resolve);
+        assert!(features.completion_snippets);
+        assert!(features.commit_characters);
+        assert!(features.watcher_registration);
+        assert!(features.change_annotations);
+        assert!(features.code_action_resolve);
+    }
+
+    #[test]
+    fn markdown_downgraded_to_plaintext() {
+        let md = "```br\nStr$(<number>)\n```\n\n---\n\n**File #1** uses `X`\n\n*@param* `A` \u{2014} first\n\n*Available since BR 4.3*";
+        let content = markup_content(md.to_string(), false);
+        assert_eq!(content.kind, MarkupKind::PlainText);
+        assert_eq!(
+            content.value,
+            "Str$(<number>)\n\n\nFile #1 uses X\n\n@param A \u{2014} first\n\nAvailable since BR 4.3"
+        );
+    }
 }
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::fmt;
 use std::sync::LazyLock;
 
 use serde::Deserialize;
@@ -10,6 +11,32 @@ pub struct BuiltinFunction {
     pub name: String,
     pub documentation: Option<String>,
     pub params: Vec<BuiltinParam>,
+    /// First BR version providing this function (e.g. `"4.3"`); absent means always available
+    #[serde(default)]
+    pub since: Option<String>,
+}
+
+/// A BR release, as configured by `br-lsp.brVersion` (e.g. `4.2`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BrVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl BrVersion {
+    pub fn parse(s: &str) -> Option<Self> {
+        let (major, minor) = s.trim().split_once('.').unwrap_or((s.trim(), "0"));
+        Some(Self {
+            major: major.parse().ok()?,
+            minor: minor.parse().ok()?,
+        })
+    }
+}
+
+impl fmt::Display for BrVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -89,6 +116,19 @@ pub fn all() -> impl Iterator<Item = &'static BuiltinFunction> {
 }
 
 impl BuiltinFunction {
+    pub fn since_version(&self) -> Option<BrVersion> {
+        self.since.as_deref().and_then(BrVersion::parse)
+    }
+
+    /// Whether this function exists in `version`. With no configured
+    /// version every function is considered available.
+    pub fn available_in(&self, version: Option<BrVersion>) -> bool {
+        match (version, self.since_version()) {
+            (Some(version), Some(since)) => since <= version,
+            _ => true,
+        }
+    }
+
     pub fn format_signature(&self) -> String {
         if self.params.is_empty() {
             self.name.clone()
@@ -165,6 +205,29 @@ mod tests {
         assert!(results.is_empty());
     }
 
+    #[test]
+    fn parse_br_version() {
+        assert_eq!(
+            BrVersion::parse("4.2"),
+            Some(BrVersion { major: 4, minor: 2 })
+        );
+        assert_eq!(
+            BrVersion::parse(" 5 "),
+            Some(BrVersion { major: 5, minor: 0 })
+        );
+        assert_eq!(BrVersion::parse("latest"), None);
+        assert!(BrVersion::parse("4.2") < BrVersion::parse("4.10"));
+    }
+
+    #[test]
+    fn available_in_version() {
+        let encrypt = &lookup("Encrypt$")[0];
+        assert!(!encrypt.available_in(BrVersion::parse("4.2")));
+        assert!(encrypt.available_in(BrVersion::parse("4.3")));
+        assert!(encrypt.available_in(None));
+        assert!(lookup("Val")[0].available_in(BrVersion::parse("4.0")));
+    }
+
     #[test]
     fn format_signature_no_params() {
         let results = lookup("Bell");
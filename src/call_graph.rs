@@ -0,0 +1,286 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+use serde_json::{json, Value};
+use tower_lsp::lsp_types::Url;
+use tree_sitter::Tree;
+
+use crate::diagnostics::{function_name_node, is_inline_def};
+use crate::parser;
+use crate::workspace::WorkspaceIndex;
+
+/// A directed call graph between user-defined functions. Calls made outside
+/// any function body are attributed to a node named after the file, so the
+/// main program of each file shows up as an entry point.
+#[derive(Debug, Default)]
+pub struct CallGraph {
+    /// Lowercase name -> display name (first spelling seen)
+    names: HashMap<String, String>,
+    /// Lowercase caller -> lowercase callees
+    edges: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// A user function call found in a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Call {
+    /// The function the call is made from, `None` in the main program
+    pub caller: Option<String>,
+    pub callee: String,
+}
+
+struct FunctionSpan {
+    name: String,
+    start_byte: usize,
+    end_byte: usize,
+}
+
+/// Collect every user function call in a file.
+pub fn collect_calls(tree: &Tree, source: &str) -> Vec<Call> {
+    let nodes = parser::collect_diagnostic_nodes(tree, source);
+    let bytes = source.as_bytes();
+
+    let mut fnends: Vec<usize> = nodes
+        .fnend_statements
+        .iter()
+        .map(|n| n.start_byte())
+        .collect();
+    fnends.sort_unstable();
+
+    let spans: Vec<FunctionSpan> = nodes
+        .def_statements
+        .iter()
+        .filter_map(|&def| {
+            let name = function_name_node(def)?.utf8_text(bytes).ok()?.to_string();
+            let end_byte = if is_inline_def(def) {
+                def.end_byte()
+            } else {
+                fnends
+                    .iter()
+                    .copied()
+                    .find(|&b| b > def.start_byte())
+                    .unwrap_or(source.len())
+            };
+            Some(FunctionSpan {
+                name,
+                start_byte: def.start_byte(),
+                end_byte,
+            })
+        })
+        .collect();
+
+    nodes
+        .function_calls
+        .iter()
+        .filter(|n| matches!(n.kind(), "numeric_user_function" | "string_user_function"))
+        .filter_map(|&call| {
+            let callee = call
+                .children(&mut call.walk())
+                .find(|c| c.kind() == "function_name")?
+                .utf8_text(bytes)
+                .ok()?
+                .to_string();
+            let caller = spans
+                .iter()
+                .rev()
+                .find(|s| call.start_byte() >= s.start_byte && call.start_byte() <= s.end_byte)
+                .map(|s| s.name.clone());
+            Some(Call { caller, callee })
+        })
+        .collect()
+}
+
+impl CallGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The graph of every call in the index, with each file's main program
+    /// named by `label`.
+    pub fn from_index(index: &WorkspaceIndex, label: impl Fn(&Url) -> String) -> Self {
+        let mut graph = Self::new();
+        for (uri, calls) in index.calls() {
+            for call in calls {
+                match &call.caller {
+                    Some(caller) => graph.add_call(caller, &call.callee),
+                    None => graph.add_call(&label(uri), &call.callee),
+                }
+            }
+        }
+        graph
+    }
+
+    pub fn add_call(&mut self, caller: &str, callee: &str) {
+        let caller_key = self.intern(caller);
+        let callee_key = self.intern(callee);
+        self.edges.entry(caller_key).or_default().insert(callee_key);
+    }
+
+    fn intern(&mut self, name: &str) -> String {
+        let key = name.to_ascii_lowercase();
+        self.names
+            .entry(key.clone())
+            .or_insert_with(|| name.to_string());
+        key
+    }
+
+    fn display<'a>(&'a self, key: &'a str) -> &'a str {
+        self.names.get(key).map(|s| s.as_str()).unwrap_or(key)
+    }
+
+    /// Returns the edges reachable from `root` within `depth` levels (all
+    /// edges when `root` is `None`), sorted by caller then callee.
+    pub fn edges(&self, root: Option<&str>, depth: Option<usize>) -> Vec<(&str, &str)> {
+        let Some(root) = root else {
+            return self
+                .edges
+                .iter()
+                .flat_map(|(from, tos)| tos.iter().map(move |to| (from.as_str(), to.as_str())))
+                .collect();
+        };
+
+        let mut visited = BTreeSet::new();
+        let mut result = BTreeSet::new();
+        let mut queue = VecDeque::from([(root.to_ascii_lowercase(), 0usize)]);
+
+        while let Some((key, level)) = queue.pop_front() {
+            if !visited.insert(key.clone()) || depth.is_some_and(|d| level >= d) {
+                continue;
+            }
+            let Some((from, tos)) = self.edges.get_key_value(&key) else {
+                continue;
+            };
+            for to in tos {
+                result.insert((from.as_str(), to.as_str()));
+                queue.push_back((to.clone(), level + 1));
+            }
+        }
+
+        result.into_iter().collect()
+    }
+
+    pub fn to_dot(&self, root: Option<&str>, depth: Option<usize>) -> String {
+        let mut out = String::from("digraph calls {\n    node [shape=box];\n");
+        for (from, to) in self.edges(root, depth) {
+            out.push_str(&format!(
+                "    \"{}\" -> \"{}\";\n",
+                dot_escape(self.display(from)),
+                dot_escape(self.display(to))
+            ));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn to_json(&self, root: Option<&str>, depth: Option<usize>) -> Value {
+        let edges = self.edges(root, depth);
+        let nodes: BTreeSet<&str> = edges.iter().flat_map(|(a, b)| [*a, *b]).collect();
+        json!({
+            "nodes": nodes.into_iter().map(|n| self.display(n)).collect::<Vec<_>>(),
+            "edges": edges
+                .iter()
+                .map(|(from, to)| json!({ "from": self.display(from), "to": self.display(to) }))
+                .collect::<Vec<_>>(),
+        })
+    }
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph_from(files: &[(&str, &str)]) -> CallGraph {
+        let mut index = WorkspaceIndex::new();
+        for (path, source) in files {
+            let uri = Url::parse(&format!("file:///ws/{path}")).unwrap();
+            let mut p = parser::new_parser();
+            let tree = parser::parse(&mut p, source, None).unwrap();
+            index.add_file(&uri, Vec::new());
+            index.set_calls(&uri, collect_calls(&tree, source));
+        }
+        CallGraph::from_index(&index, |uri| {
+            uri.path().trim_start_matches("/ws/").to_string()
+        })
+    }
+
+    #[test]
+    fn attributes_calls_to_enclosing_function() {
+        let source = "\
+let X = fnOuter(1)
+def fnOuter(A)
+  let X = fnInner(A) + fnHelper(A)
+fnend
+def fnInner(B) = fnHelper(B)
+def fnHelper(C) = C
+";
+        let graph = graph_from(&[("src/main.brs", source)]);
+        let edges = graph.edges(None, None);
+        assert_eq!(
+            edges,
+            vec![
+                ("fninner", "fnhelper"),
+                ("fnouter", "fnhelper"),
+                ("fnouter", "fninner"),
+                ("src/main.brs", "fnouter"),
+            ]
+        );
+    }
+
+    #[test]
+    fn main_programs_of_same_named_files_stay_apart() {
+        let graph = graph_from(&[
+            ("ar/menu.brs", "let X = fnPost(1)\n"),
+            ("gl/menu.brs", "let X = fnClose(1)\n"),
+        ]);
+        assert_eq!(
+            graph.edges(None, None),
+            vec![("ar/menu.brs", "fnpost"), ("gl/menu.brs", "fnclose")]
+        );
+    }
+
+    #[test]
+    fn system_functions_are_ignored() {
+        let graph = graph_from(&[("main.brs", "let X$ = str$(val(\"1\"))\n")]);
+        assert!(graph.edges(None, None).is_empty());
+    }
+
+    #[test]
+    fn root_and_depth_limit() {
+        let mut graph = CallGraph::new();
+        graph.add_call("fnA", "fnB");
+        graph.add_call("fnB", "fnC");
+        graph.add_call("fnC", "fnD");
+        graph.add_call("fnX", "fnY");
+
+        assert_eq!(graph.edges(Some("FNA"), Some(1)), vec![("fna", "fnb")]);
+        assert_eq!(graph.edges(Some("fnA"), None).len(), 3);
+        assert!(graph.edges(Some("fnMissing"), None).is_empty());
+    }
+
+    #[test]
+    fn recursive_calls_terminate() {
+        let mut graph = CallGraph::new();
+        graph.add_call("fnA", "fnB");
+        graph.add_call("fnB", "fnA");
+        assert_eq!(graph.edges(Some("fnA"), None).len(), 2);
+    }
+
+    #[test]
+    fn dot_and_json_use_display_names() {
+        let mut graph = CallGraph::new();
+        graph.add_call("fnMain", "fnGet$");
+        graph.add_call("FNMAIN", "fnget$");
+
+        let dot = graph.to_dot(None, None);
+        assert_eq!(
+            dot,
+            "digraph calls {\n    node [shape=box];\n    \"fnMain\" -> \"fnGet$\";\n}\n"
+        );
+
+        let json = graph.to_json(None, None);
+        assert_eq!(json["nodes"], json!(["fnGet$", "fnMain"]));
+        assert_eq!(json["edges"][0]["from"], "fnMain");
+    }
+}
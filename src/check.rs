@@ -1,9 +1,10 @@
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
 use rayon::prelude::*;
-use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, Range, Url};
-use walkdir::WalkDir;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Range, Url};
 
+use crate::structure::Structure;
 use crate::{diagnostics, extract, parser, workspace};
 
 /// A diagnostic decoupled from LSP types, usable from both CLI and server paths.
@@ -57,8 +58,16 @@ pub fn check_file(path: &Path) -> Vec<FileDiagnostic> {
         &nodes.library_statements,
         &source,
     );
+    // Related information isn't printed, so any URI will do when the path
+    // can't be made absolute
+    let uri = path
+        .canonicalize()
+        .ok()
+        .and_then(|p| Url::from_file_path(p).ok())
+        .unwrap_or_else(|| Url::parse("file:///").unwrap());
+    let structure = Structure::from_root(nodes.root, &source);
     lsp_diags.extend(diagnostics::collect_function_diagnostics(
-        &nodes, &source, &defs,
+        &nodes, &structure, &source, &defs, &uri,
     ));
 
     let file_str = path.display().to_string();
@@ -81,23 +90,33 @@ pub fn check_file(path: &Path) -> Vec<FileDiagnostic> {
 }
 
 /// Resolve paths (files and directories) into BR files and check them all in parallel.
+/// Directories the walk skips are reported on stderr.
 pub fn check_paths(paths: &[PathBuf]) -> Vec<FileDiagnostic> {
+    let mut report = workspace::WalkReport::default();
     let file_paths: Vec<PathBuf> = paths
         .iter()
         .flat_map(|p| {
             if p.is_dir() {
-                WalkDir::new(p)
-                    .follow_links(true)
-                    .into_iter()
-                    .filter_map(|e| e.ok())
-                    .filter(|e| e.file_type().is_file() && workspace::is_br_file(e.path()))
-                    .map(|e| e.into_path())
-                    .collect::<Vec<_>>()
+                workspace::walk_files_reporting(
+                    p,
+                    false,
+                    workspace::WalkBudget::default(),
+                    &mut report,
+                )
+                .into_iter()
+                .filter(|path| workspace::is_br_file(path))
+                .collect::<Vec<_>>()
             } else {
                 vec![p.clone()]
             }
         })
         .collect();
+    for skipped in &report.skipped {
+        eprintln!("skipped {}: {}", skipped.path.display(), skipped.reason);
+    }
+    for root in &report.truncated {
+        eprintln!("stopped early in {}: too many files", root.display());
+    }
 
     let mut results: Vec<FileDiagnostic> = file_paths
         .par_iter()
@@ -117,7 +136,7 @@ pub fn check_paths(paths: &[PathBuf]) -> Vec<FileDiagnostic> {
 
 /// Escape a value for CSV output. Wraps in quotes if the value contains
 /// commas, quotes, or newlines. Doubles any existing quotes.
-fn csv_escape(value: &str) -> String {
+pub(crate) fn csv_escape(value: &str) -> String {
     if value.contains(',') || value.contains('"') || value.contains('\n') {
         let escaped = value.replace('"', "\"\"");
         format!("\"{escaped}\"")
@@ -199,6 +218,113 @@ pub fn diagnostics_to_csv(results: &[(Url, Vec<Diagnostic>)]) -> String {
     format_csv(&file_diags)
 }
 
+/// Fixes attached to the diagnostics (see `diagnostics::Fix`) as JSON, one
+/// entry per fixable diagnostic, for tools that apply them in bulk.
+pub fn fixes_to_json(results: &[(Url, Vec<Diagnostic>)]) -> serde_json::Value {
+    let fixes: Vec<serde_json::Value> = results
+        .iter()
+        .flat_map(|(uri, diags)| {
+            diags.iter().filter_map(move |d| {
+                let fix = diagnostics::Fix::from_diagnostic(d)?;
+                let code = match &d.code {
+                    Some(NumberOrString::String(code)) => Some(code.clone()),
+                    _ => None,
+                };
+                Some(serde_json::json!({
+                    "uri": uri,
+                    "code": code,
+                    "message": d.message,
+                    "title": fix.title,
+                    "edits": fix.edits,
+                }))
+            })
+        })
+        .collect();
+    serde_json::Value::Array(fixes)
+}
+
+/// How `br.scanAll` groups its structured results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GroupBy {
+    #[default]
+    File,
+    Rule,
+}
+
+impl GroupBy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "file" => Some(Self::File),
+            "rule" => Some(Self::Rule),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::File => "file",
+            Self::Rule => "rule",
+        }
+    }
+}
+
+/// Group label for diagnostics without a code
+const NO_CODE: &str = "other";
+
+/// Diagnostics grouped by file or by rule, for clients that show them as a
+/// tree. Each group has a `key` (the file's URI or the rule code), a
+/// `label` (the file's path or the rule code), counts by severity and its
+/// diagnostics with 0-based ranges. Files without diagnostics are left out.
+pub fn diagnostics_to_groups(
+    results: &[(Url, Vec<Diagnostic>)],
+    group_by: GroupBy,
+) -> serde_json::Value {
+    // key -> (label, counts by severity, diagnostics)
+    type Group<'a> = (String, BTreeMap<&'a str, usize>, Vec<serde_json::Value>);
+    let mut groups: BTreeMap<String, Group> = BTreeMap::new();
+    for (uri, diags) in results {
+        let path = uri
+            .to_file_path()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|_| uri.to_string());
+        for d in diags {
+            let code = match &d.code {
+                Some(NumberOrString::String(code)) => code.clone(),
+                Some(NumberOrString::Number(code)) => code.to_string(),
+                None => NO_CODE.to_string(),
+            };
+            let (key, label) = match group_by {
+                GroupBy::File => (uri.to_string(), path.clone()),
+                GroupBy::Rule => (code.clone(), code.clone()),
+            };
+            let severity = severity_str(d.severity);
+            let (_, counts, diagnostics) = groups
+                .entry(key)
+                .or_insert_with(|| (label, BTreeMap::new(), Vec::new()));
+            *counts.entry(severity).or_default() += 1;
+            diagnostics.push(serde_json::json!({
+                "uri": uri,
+                "code": code,
+                "severity": severity,
+                "message": d.message,
+                "range": d.range,
+            }));
+        }
+    }
+    let groups: Vec<serde_json::Value> = groups
+        .into_iter()
+        .map(|(key, (label, counts, diagnostics))| {
+            serde_json::json!({
+                "key": key,
+                "label": label,
+                "counts": counts,
+                "diagnostics": diagnostics,
+            })
+        })
+        .collect();
+    serde_json::Value::Array(groups)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -270,6 +396,58 @@ mod tests {
         assert!(lines[1].ends_with("\"Function 'fnFoo' expects 2 parameter(s), but 1 provided\""));
     }
 
+    #[test]
+    fn groups_by_file_or_rule() {
+        let diag = |line: u32, code: &str, severity: DiagnosticSeverity| Diagnostic {
+            range: Range::new(
+                tower_lsp::lsp_types::Position::new(line, 0),
+                tower_lsp::lsp_types::Position::new(line, 4),
+            ),
+            severity: Some(severity),
+            code: Some(NumberOrString::String(code.to_string())),
+            message: format!("{code} on {line}"),
+            ..Default::default()
+        };
+        let a = Url::parse("file:///ws/a.brs").unwrap();
+        let b = Url::parse("file:///ws/b.brs").unwrap();
+        let results = vec![
+            (
+                a.clone(),
+                vec![
+                    diag(1, "unused-variable", DiagnosticSeverity::HINT),
+                    diag(3, "syntax-error", DiagnosticSeverity::ERROR),
+                ],
+            ),
+            (
+                b.clone(),
+                vec![diag(0, "syntax-error", DiagnosticSeverity::ERROR)],
+            ),
+            (Url::parse("file:///ws/clean.brs").unwrap(), vec![]),
+        ];
+
+        let by_file = diagnostics_to_groups(&results, GroupBy::File);
+        let by_file = by_file.as_array().unwrap();
+        assert_eq!(by_file.len(), 2);
+        assert_eq!(by_file[0]["key"], a.as_str());
+        assert_eq!(
+            by_file[0]["counts"],
+            serde_json::json!({"error": 1, "hint": 1})
+        );
+        assert_eq!(by_file[0]["diagnostics"][1]["code"], "syntax-error");
+        assert_eq!(by_file[0]["diagnostics"][1]["range"]["start"]["line"], 3);
+
+        let by_rule = diagnostics_to_groups(&results, GroupBy::Rule);
+        let by_rule = by_rule.as_array().unwrap();
+        let labels: Vec<&str> = by_rule
+            .iter()
+            .map(|g| g["label"].as_str().unwrap())
+            .collect();
+        assert_eq!(labels, vec!["syntax-error", "unused-variable"]);
+        assert_eq!(by_rule[0]["diagnostics"][1]["uri"], b.as_str());
+        assert_eq!(GroupBy::parse("Rule"), Some(GroupBy::Rule));
+        assert_eq!(GroupBy::parse("folder"), None);
+    }
+
     #[test]
     fn check_file_with_errors() {
         let dir = tempfile::tempdir().unwrap();
@@ -302,6 +480,59 @@ mod tests {
         assert!(diags.iter().all(|d| d.file.contains("a.brs")));
     }
 
+    /// Golden-file regression tests: each BR file in
+    /// `tests/fixtures/diagnostics` is checked and its diagnostics compared
+    /// with the `.diagnostics` file beside it. To add a case, drop in a
+    /// fixture and run `BLESS=1 cargo test diagnostic_fixtures` to write
+    /// its golden file, then review the result.
+    #[test]
+    fn diagnostic_fixtures() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/diagnostics");
+        let bless = std::env::var_os("BLESS").is_some();
+        let mut fixtures: Vec<PathBuf> = std::fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| workspace::is_br_file(p))
+            .collect();
+        fixtures.sort();
+        assert!(!fixtures.is_empty(), "no fixtures in {}", dir.display());
+
+        let mut failures = Vec::new();
+        for fixture in &fixtures {
+            let mut diags = check_file(fixture);
+            diags.sort_by_key(|d| (d.line, d.column));
+            let actual: String = diags
+                .iter()
+                .map(|d| {
+                    format!(
+                        "{}:{}-{}:{} {}: {}\n",
+                        d.line, d.column, d.end_line, d.end_column, d.severity, d.message
+                    )
+                })
+                .collect();
+
+            let golden = fixture.with_extension("diagnostics");
+            if bless {
+                std::fs::write(&golden, &actual).unwrap();
+                continue;
+            }
+            match std::fs::read_to_string(&golden) {
+                Ok(expected) if expected.replace("\r\n", "\n") == actual => {}
+                Ok(expected) => failures.push(format!(
+                    "{}\n--- expected\n{expected}--- actual\n{actual}",
+                    fixture.display()
+                )),
+                Err(_) => failures.push(format!("{}: no golden file", fixture.display())),
+            }
+        }
+        assert!(
+            failures.is_empty(),
+            "{}\nRun with BLESS=1 to update the golden files.",
+            failures.join("\n")
+        );
+    }
+
     #[test]
     fn run_check_no_args() {
         assert_eq!(run_check(&[]), 2);
@@ -340,4 +571,32 @@ mod tests {
         };
         assert_eq!(range_to_1based(&range), (1, 1, 6, 11));
     }
+
+    #[test]
+    fn fixes_to_json_lists_fixable_diagnostics() {
+        use tower_lsp::lsp_types::{Position, TextEdit};
+        let uri = Url::parse("file:///ws/a.brs").unwrap();
+        let mut fixable = Diagnostic {
+            code: Some(NumberOrString::String("keyword-case".to_string())),
+            message: "'print' should be written 'PRINT'".to_string(),
+            ..Default::default()
+        };
+        diagnostics::Fix {
+            title: "Change to 'PRINT'".to_string(),
+            edits: vec![TextEdit {
+                range: Range::new(Position::new(0, 0), Position::new(0, 5)),
+                new_text: "PRINT".to_string(),
+            }],
+        }
+        .attach(&mut fixable);
+        let results = vec![(uri, vec![fixable, Diagnostic::default()])];
+
+        let fixes = fixes_to_json(&results);
+        let fixes = fixes.as_array().unwrap();
+        assert_eq!(fixes.len(), 1);
+        assert_eq!(fixes[0]["uri"], "file:///ws/a.brs");
+        assert_eq!(fixes[0]["code"], "keyword-case");
+        assert_eq!(fixes[0]["edits"][0]["newText"], "PRINT");
+        assert_eq!(fixes[0]["edits"][0]["range"]["end"]["character"], 5);
+    }
 }
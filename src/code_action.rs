@@ -1,10 +1,12 @@
 use std::collections::HashMap;
 
+use serde::{Deserialize, Serialize};
 use tower_lsp::lsp_types::*;
 use tree_sitter::{Node, Tree};
 
 use crate::diagnostics;
-use crate::extract::ParamKind;
+use crate::extract::{self, ParamKind};
+use crate::organize_libraries;
 use crate::parser;
 
 /// If the diagnostic is an undefined-function warning, generate a code action
@@ -15,13 +17,7 @@ pub fn create_function_stub_action(
     tree: &Tree,
     source: &str,
 ) -> Option<CodeAction> {
-    // Only handle our undefined-function diagnostic
-    match &diagnostic.code {
-        Some(NumberOrString::String(code)) if code == "undefined-function" => {}
-        _ => return None,
-    }
-
-    let fn_name = extract_function_name(&diagnostic.message)?;
+    let fn_name = stub_function_name(diagnostic)?;
 
     // Find the call-site node to inspect arguments
     let call_node = find_call_node(tree, source, diagnostic.range.start)?;
@@ -55,7 +51,7 @@ pub fn create_function_stub_action(
     changes.insert(uri.clone(), vec![text_edit]);
 
     Some(CodeAction {
-        title: format!("Generate function stub for '{fn_name}'"),
+        title: stub_title(&fn_name),
         kind: Some(CodeActionKind::QUICKFIX),
         diagnostics: Some(vec![diagnostic.clone()]),
         edit: Some(WorkspaceEdit {
@@ -66,6 +62,19 @@ pub fn create_function_stub_action(
     })
 }
 
+/// The undefined function an `undefined-function` diagnostic is about.
+fn stub_function_name(diagnostic: &Diagnostic) -> Option<String> {
+    match &diagnostic.code {
+        Some(NumberOrString::String(code)) if code == "undefined-function" => {}
+        _ => return None,
+    }
+    extract_function_name(&diagnostic.message)
+}
+
+fn stub_title(fn_name: &str) -> String {
+    format!("Generate function stub for '{fn_name}'")
+}
+
 /// Extract the function name from the diagnostic message.
 /// Message format: "Function 'fnName' is not defined in the workspace"
 fn extract_function_name(message: &str) -> Option<String> {
@@ -254,10 +263,554 @@ fn generate_stub(fn_name: &str, params: &[ParamInfo], start_ln: i64) -> String {
     )
 }
 
+/// Turn the fix a diagnostic carries in its `data` (see `diagnostics::Fix`)
+/// into a quickfix, e.g. for keyword-case hints.
+pub fn create_fix_action(uri: &Url, diagnostic: &Diagnostic) -> Option<CodeAction> {
+    let fix = diagnostics::Fix::from_diagnostic(diagnostic)?;
+
+    let mut changes = HashMap::new();
+    changes.insert(uri.clone(), fix.edits);
+
+    Some(CodeAction {
+        title: fix.title,
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }),
+        is_preferred: Some(true),
+        ..Default::default()
+    })
+}
+
+/// For duplicate or out-of-order line numbers, a quickfix running
+/// `br-lsp.renumberLines` on the file. The client command fills in the
+/// increment and padding from `br.autoLineNumbers`.
+pub fn create_renumber_action(uri: &Url, diagnostic: &Diagnostic) -> Option<CodeAction> {
+    let code = match &diagnostic.code {
+        Some(NumberOrString::String(code)) => code.as_str(),
+        _ => return None,
+    };
+    if code != "duplicate-line-number" && code != "line-number-order" {
+        return None;
+    }
+
+    Some(CodeAction {
+        title: "Renumber lines".to_string(),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        command: Some(Command {
+            title: "Renumber lines".to_string(),
+            command: "br-lsp.renumberLines".to_string(),
+            arguments: Some(vec![serde_json::json!(uri.to_string())]),
+        }),
+        ..Default::default()
+    })
+}
+
+/// The edits of every fix the diagnostics carry, for applying them at once,
+/// and how many fixes they make up. A fix overlapping one already taken is
+/// left out; running fix-all again picks it up against the new text.
+pub fn fix_all_edits(diagnostics: &[Diagnostic]) -> (Vec<TextEdit>, usize) {
+    let overlaps = |a: &Range, b: &Range| {
+        (a.start < b.end && b.start < a.end) || (a.start == b.start && a.end == b.end)
+    };
+    let mut edits: Vec<TextEdit> = Vec::new();
+    let mut fixes = 0;
+    for fix in diagnostics
+        .iter()
+        .filter_map(diagnostics::Fix::from_diagnostic)
+    {
+        let clashes = fix
+            .edits
+            .iter()
+            .any(|new| edits.iter().any(|taken| overlaps(&new.range, &taken.range)));
+        if fix.edits.is_empty() || clashes {
+            continue;
+        }
+        edits.extend(fix.edits);
+        fixes += 1;
+    }
+    edits.sort_by_key(|e| (e.range.start, e.range.end));
+    (edits, fixes)
+}
+
+/// For doc-comment drift diagnostics, generate a code action that rewrites the
+/// comment's `@param` tags to match the function's parameter list. Existing
+/// descriptions are kept for parameters whose names still match.
+pub fn create_doc_params_action(
+    uri: &Url,
+    diagnostic: &Diagnostic,
+    tree: &Tree,
+    source: &str,
+) -> Option<CodeAction> {
+    let def_node = documented_def(diagnostic, tree)?;
+    let comment = extract::doc_comment_node(def_node)?;
+    let def_range = parser::node_range(def_node);
+    let defs = extract::extract_definitions(tree, source);
+    let def = defs.iter().find(|d| d.range == def_range)?;
+    let raw = comment.utf8_text(source.as_bytes()).ok()?;
+
+    let text_edit = TextEdit {
+        range: parser::node_range(comment),
+        new_text: regenerate_doc_params(raw, &def.params),
+    };
+
+    let mut changes = HashMap::new();
+    changes.insert(uri.clone(), vec![text_edit]);
+
+    Some(CodeAction {
+        title: doc_params_title(&def.name),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+/// The DEF a `doc-param-unknown` or `doc-param-missing` diagnostic is about.
+fn documented_def<'t>(diagnostic: &Diagnostic, tree: &'t Tree) -> Option<Node<'t>> {
+    match &diagnostic.code {
+        Some(NumberOrString::String(code))
+            if code == "doc-param-unknown" || code == "doc-param-missing" => {}
+        _ => return None,
+    }
+    find_documented_def(tree, diagnostic.range.start)
+}
+
+fn doc_params_title(fn_name: &str) -> String {
+    format!("Update @param tags for '{fn_name}'")
+}
+
+/// Name of the function a `def_statement` defines.
+fn def_name<'s>(def_node: Node, source: &'s str) -> Option<&'s str> {
+    let mut cursor = def_node.walk();
+    let definition = def_node.children(&mut cursor).find(|c| {
+        matches!(
+            c.kind(),
+            "numeric_function_definition" | "string_function_definition"
+        )
+    })?;
+    let mut cursor = definition.walk();
+    let name = definition
+        .children(&mut cursor)
+        .find(|c| c.kind() == "function_name")?;
+    name.utf8_text(source.as_bytes()).ok()
+}
+
+/// The def_statement at `pos`, or the one documented by the doc comment at `pos`.
+fn find_documented_def(tree: &Tree, pos: Position) -> Option<Node<'_>> {
+    let mut current = parser::node_at_position(tree, pos.line as usize, pos.character as usize)?;
+    loop {
+        match current.kind() {
+            "def_statement" => return Some(current),
+            "doc_comment" => {
+                let next_line = current.parent()?.next_sibling()?;
+                let mut cursor = next_line.walk();
+                let def = next_line
+                    .children(&mut cursor)
+                    .find(|c| c.kind() == "def_statement");
+                return def;
+            }
+            _ => current = current.parent()?,
+        }
+    }
+}
+
+/// Rebuild a raw doc comment with one `@param` line per parameter, in
+/// parameter order, placed where the first `@param` tag was (or before
+/// `@returns` / the closing line when there were none).
+fn regenerate_doc_params(raw: &str, params: &[extract::ParamInfo]) -> String {
+    let eol = if raw.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut lines: Vec<String> = raw.split(eol).map(str::to_string).collect();
+
+    // Tags sharing a line with `/**` or `*/` are dropped from that line, and
+    // single-line comments are split so the tags get lines of their own
+    let single_line = lines.len() == 1;
+    if let Some(i) = lines[0].find("@param") {
+        lines[0] = lines[0][..i].trim_end().to_string();
+    } else if single_line {
+        lines[0] = lines[0]
+            .trim_end()
+            .trim_end_matches("*/")
+            .trim_end()
+            .to_string();
+    }
+    let last = lines.len() - 1;
+    if single_line || (last > 0 && lines[last].contains("@param")) {
+        lines.push("  */".to_string());
+        if !single_line {
+            lines.swap_remove(last);
+        }
+    }
+
+    let tags = extract::doc_param_tags(raw);
+    let docs: HashMap<String, &str> = tags
+        .iter()
+        .map(|t| (t.name.to_ascii_lowercase(), t.documentation))
+        .collect();
+    let prefix = tags
+        .first()
+        .and_then(|t| lines.get(t.line))
+        .and_then(|l| l.find("@param").map(|i| l[..i].to_string()))
+        .filter(|p| !p.contains("/**") && !p.contains("*/"))
+        .unwrap_or_else(|| "  * ".to_string());
+
+    let is_param_line = |l: &str| l.contains("@param");
+    let insert_at = lines
+        .iter()
+        .skip(1)
+        .position(|l| is_param_line(l) || l.contains("@return"))
+        .map_or(lines.len() - 1, |i| i + 1);
+    let mut kept: Vec<String> = lines[..insert_at].to_vec();
+    kept.extend(
+        params
+            .iter()
+            .map(|p| match docs.get(&p.name.to_ascii_lowercase()) {
+                Some(doc) if !doc.is_empty() => format!("{prefix}@param {} {doc}", p.name),
+                _ => format!("{prefix}@param {}", p.name),
+            }),
+    );
+    kept.extend(
+        lines[insert_at..]
+            .iter()
+            .filter(|l| !is_param_line(l))
+            .cloned(),
+    );
+    kept.join(eol)
+}
+
+/// For an unused-parameter hint, remove the parameter from its DEF along
+/// with the matching argument at each call of the function in this file.
+/// Callers in other files are left alone.
+pub fn create_remove_parameter_action(
+    uri: &Url,
+    diagnostic: &Diagnostic,
+    tree: &Tree,
+    source: &str,
+) -> Option<CodeAction> {
+    let (identifier, param) = unused_parameter(diagnostic, tree)?;
+    let list = param.parent()?;
+    let index = list_slots(list, source)
+        .iter()
+        .position(|slot| slot.contains(&param.start_byte()))?;
+    let bytes = source.as_bytes();
+    let param_name = identifier.utf8_text(bytes).ok()?.to_string();
+    let definition = list.parent()?;
+    let mut cursor = definition.walk();
+    let fn_name = definition
+        .children(&mut cursor)
+        .find(|c| c.kind() == "function_name")?
+        .utf8_text(bytes)
+        .ok()?
+        .to_string();
+
+    let mut removals = vec![slot_removal(list, index, source)?];
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        if matches!(
+            node.kind(),
+            "numeric_user_function" | "string_user_function"
+        ) {
+            let mut cursor = node.walk();
+            let is_call = node
+                .children(&mut cursor)
+                .find(|c| c.kind() == "function_name")
+                .and_then(|n| n.utf8_text(bytes).ok())
+                .is_some_and(|name| name.eq_ignore_ascii_case(&fn_name));
+            if let Some(args) = node.child_by_field_name("arguments").filter(|_| is_call) {
+                removals.extend(slot_removal(args, index, source));
+            }
+        }
+        let mut cursor = node.walk();
+        stack.extend(node.children(&mut cursor));
+    }
+
+    // Drop removals inside a larger one, e.g. a call passed as the removed argument
+    removals.sort_by_key(|(range, _)| range.start);
+    let mut edits: Vec<TextEdit> = Vec::new();
+    let mut covered_to = 0;
+    for (span, range) in removals {
+        if span.start < covered_to {
+            continue;
+        }
+        covered_to = span.end;
+        edits.push(TextEdit {
+            range,
+            new_text: String::new(),
+        });
+    }
+
+    let mut changes = HashMap::new();
+    changes.insert(uri.clone(), edits);
+
+    Some(CodeAction {
+        title: remove_parameter_title(&param_name),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+/// The identifier an `unused-parameter` diagnostic is about, and the
+/// `required_parameter` or `optional_parameter` holding it.
+fn unused_parameter<'t>(diagnostic: &Diagnostic, tree: &'t Tree) -> Option<(Node<'t>, Node<'t>)> {
+    match &diagnostic.code {
+        Some(NumberOrString::String(code)) if code == "unused-parameter" => {}
+        _ => return None,
+    }
+    let start = diagnostic.range.start;
+    let identifier = parser::node_at_position(tree, start.line as usize, start.character as usize)?;
+    let mut param = identifier;
+    while !matches!(param.kind(), "required_parameter" | "optional_parameter") {
+        param = param.parent()?;
+    }
+    Some((identifier, param))
+}
+
+fn remove_parameter_title(param_name: &str) -> String {
+    format!("Remove unused parameter '{param_name}'")
+}
+
+const ORGANIZE_LIBRARIES_TITLE: &str = "Organize LIBRARY statements";
+
+/// Organize the document's LIBRARY statements (see
+/// `organize_libraries::organize_libraries`), offered as the
+/// `source.organizeImports` action.
+pub fn create_organize_libraries_action(
+    uri: &Url,
+    tree: &Tree,
+    source: &str,
+) -> Option<CodeAction> {
+    let edits = organize_libraries::organize_libraries(tree, source)?;
+
+    let mut changes = HashMap::new();
+    changes.insert(uri.clone(), edits);
+
+    Some(CodeAction {
+        title: ORGANIZE_LIBRARIES_TITLE.to_string(),
+        kind: Some(CodeActionKind::SOURCE_ORGANIZE_IMPORTS),
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+/// Code actions whose edits are only computed on `codeAction/resolve`, so
+/// the menu lists them without running the refactoring first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DeferredAction {
+    FunctionStub,
+    DocParams,
+    RemoveParameter,
+    OrganizeLibraries,
+}
+
+/// What a deferred action carries in its `data` for resolving.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActionData {
+    pub action: DeferredAction,
+    pub uri: Url,
+    /// The document version the action was offered for
+    pub version: u64,
+    /// The diagnostic a quickfix was offered for
+    pub diagnostic: Option<Diagnostic>,
+}
+
+impl ActionData {
+    pub fn from_action(action: &CodeAction) -> Option<Self> {
+        serde_json::from_value(action.data.clone()?).ok()
+    }
+}
+
+impl DeferredAction {
+    /// The actions offered for a diagnostic.
+    pub const QUICK_FIXES: [Self; 3] = [Self::FunctionStub, Self::DocParams, Self::RemoveParameter];
+
+    /// The action without its edit, if it applies. Only cheap checks are
+    /// made, so a listed action may resolve to no edit.
+    pub fn prepare(
+        self,
+        uri: &Url,
+        version: u64,
+        diagnostic: Option<&Diagnostic>,
+        tree: &Tree,
+        source: &str,
+    ) -> Option<CodeAction> {
+        let (title, kind) = match self {
+            Self::FunctionStub => (
+                stub_title(&stub_function_name(diagnostic?)?),
+                CodeActionKind::QUICKFIX,
+            ),
+            Self::DocParams => (
+                doc_params_title(def_name(documented_def(diagnostic?, tree)?, source)?),
+                CodeActionKind::QUICKFIX,
+            ),
+            Self::RemoveParameter => {
+                let (identifier, _) = unused_parameter(diagnostic?, tree)?;
+                (
+                    remove_parameter_title(identifier.utf8_text(source.as_bytes()).ok()?),
+                    CodeActionKind::QUICKFIX,
+                )
+            }
+            Self::OrganizeLibraries => {
+                if !organize_libraries::has_library_statements(tree) {
+                    return None;
+                }
+                (
+                    ORGANIZE_LIBRARIES_TITLE.to_string(),
+                    CodeActionKind::SOURCE_ORGANIZE_IMPORTS,
+                )
+            }
+        };
+        let data = ActionData {
+            action: self,
+            uri: uri.clone(),
+            version,
+            diagnostic: diagnostic.cloned(),
+        };
+        Some(CodeAction {
+            title,
+            kind: Some(kind),
+            diagnostics: diagnostic.map(|d| vec![d.clone()]),
+            data: serde_json::to_value(data).ok(),
+            ..Default::default()
+        })
+    }
+
+    /// The complete action, edit included.
+    pub fn create(
+        self,
+        uri: &Url,
+        diagnostic: Option<&Diagnostic>,
+        tree: &Tree,
+        source: &str,
+    ) -> Option<CodeAction> {
+        match self {
+            Self::FunctionStub => create_function_stub_action(uri, diagnostic?, tree, source),
+            Self::DocParams => create_doc_params_action(uri, diagnostic?, tree, source),
+            Self::RemoveParameter => create_remove_parameter_action(uri, diagnostic?, tree, source),
+            Self::OrganizeLibraries => create_organize_libraries_action(uri, tree, source),
+        }
+    }
+}
+
+/// Fill in the edit of an action listed by [`DeferredAction::prepare`] from
+/// the document at `version`. The action is returned unchanged when the
+/// document changed in between, since its diagnostic's range may no longer
+/// point where it did, or when the edit no longer applies.
+pub fn resolve_action(
+    mut action: CodeAction,
+    data: &ActionData,
+    version: u64,
+    tree: &Tree,
+    source: &str,
+) -> CodeAction {
+    if data.version != version {
+        return action;
+    }
+    if let Some(full) = data
+        .action
+        .create(&data.uri, data.diagnostic.as_ref(), tree, source)
+    {
+        action.edit = full.edit;
+    }
+    action
+}
+
+/// Byte spans of the slots in a parenthesized `parameter_list` or
+/// `arguments` node, split at its top-level `,` and `;` separators. Empty
+/// parentheses have no slots.
+fn list_slots(list: Node, source: &str) -> Vec<std::ops::Range<usize>> {
+    let boundaries = list_boundaries(list, source);
+    if boundaries.len() == 2 && list.named_child_count() == 0 {
+        return Vec::new();
+    }
+    boundaries
+        .windows(2)
+        .map(|pair| pair[0].end_byte()..pair[1].start_byte())
+        .collect()
+}
+
+/// The opening parenthesis, the separators and the closing parenthesis of a
+/// list, in order.
+fn list_boundaries<'a>(list: Node<'a>, source: &str) -> Vec<Node<'a>> {
+    let mut cursor = list.walk();
+    let found = list
+        .children(&mut cursor)
+        .filter(|c| {
+            !c.is_named() && matches!(c.utf8_text(source.as_bytes()), Ok("(" | ")" | "," | ";"))
+        })
+        .collect();
+    found
+}
+
+/// The span to delete to remove slot `index` from a list, together with a
+/// separator so the rest stays well-formed. A comma is preferred over a `;`
+/// so optional parameters stay optional; removing the only slot removes the
+/// parentheses too.
+fn slot_removal(list: Node, index: usize, source: &str) -> Option<(std::ops::Range<usize>, Range)> {
+    let slots = list_slots(list, source);
+    if index >= slots.len() {
+        return None;
+    }
+    if slots.len() == 1 {
+        return Some((list.byte_range(), parser::node_range(list)));
+    }
+
+    let boundaries = list_boundaries(list, source);
+    let is_comma = |node: Node| node.utf8_text(source.as_bytes()) == Ok(",");
+    let before = (index > 0).then(|| boundaries[index]);
+    let after = (index + 1 < slots.len()).then(|| boundaries[index + 1]);
+    let use_before = match (before, after) {
+        (Some(b), _) if is_comma(b) => true,
+        (_, Some(a)) if is_comma(a) => false,
+        (b, _) => b.is_some(),
+    };
+
+    let start_of = |node: Node| (node.start_byte(), node.start_position());
+    let end_of = |node: Node| (node.end_byte(), node.end_position());
+    let (start, end) = if use_before {
+        (start_of(before?), start_of(boundaries[index + 1]))
+    } else {
+        let separator = after?;
+        let start = boundaries[index]
+            .next_sibling()
+            .filter(|item| item.start_byte() < separator.start_byte())
+            .map_or(end_of(boundaries[index]), start_of);
+        // Take the space after the separator with it
+        let end = separator
+            .next_sibling()
+            .filter(|next| next.start_byte() < boundaries[index + 2].start_byte())
+            .map_or(end_of(separator), start_of);
+        (start, end)
+    };
+    let position = |point: tree_sitter::Point| Position::new(point.row as u32, point.column as u32);
+    Some((
+        start.0..end.0,
+        Range {
+            start: position(start.1),
+            end: position(end.1),
+        },
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::parser;
+    use crate::structure::Structure;
 
     fn parse(source: &str) -> Tree {
         let mut p = parser::new_parser();
@@ -274,6 +827,38 @@ mod tests {
         }
     }
 
+    fn fixable(range: Range, new_text: &str) -> Diagnostic {
+        let mut diagnostic = Diagnostic {
+            range,
+            ..Default::default()
+        };
+        diagnostics::Fix {
+            title: format!("Change to '{new_text}'"),
+            edits: vec![TextEdit {
+                range,
+                new_text: new_text.to_string(),
+            }],
+        }
+        .attach(&mut diagnostic);
+        diagnostic
+    }
+
+    #[test]
+    fn fix_all_skips_overlapping_fixes() {
+        let span = |line, from, to| Range::new(Position::new(line, from), Position::new(line, to));
+        let diagnostics = vec![
+            fixable(span(1, 0, 5), "PRINT"),
+            Diagnostic::default(),
+            fixable(span(0, 0, 3), "LET"),
+            fixable(span(1, 2, 8), "other"),
+            fixable(span(1, 0, 5), "PRINT"),
+        ];
+        let (edits, fixes) = fix_all_edits(&diagnostics);
+        assert_eq!(fixes, 2);
+        let texts: Vec<&str> = edits.iter().map(|e| e.new_text.as_str()).collect();
+        assert_eq!(texts, vec!["LET", "PRINT"]);
+    }
+
     #[test]
     fn numeric_function_stub() {
         let source = "00010 let X = fnFoo(A, B)\n";
@@ -414,6 +999,25 @@ mod tests {
         assert!(action.is_none());
     }
 
+    #[test]
+    fn renumber_action_runs_the_command() {
+        let uri = Url::parse("file:///src/prog.brs").unwrap();
+        let mut diagnostic = Diagnostic {
+            code: Some(NumberOrString::String("line-number-order".to_string())),
+            ..Default::default()
+        };
+        let action = create_renumber_action(&uri, &diagnostic).unwrap();
+        let command = action.command.unwrap();
+        assert_eq!(command.command, "br-lsp.renumberLines");
+        assert_eq!(
+            command.arguments,
+            Some(vec![serde_json::json!(uri.to_string())])
+        );
+
+        diagnostic.code = Some(NumberOrString::String("unused-variable".to_string()));
+        assert!(create_renumber_action(&uri, &diagnostic).is_none());
+    }
+
     #[test]
     fn line_number_calculation() {
         let source = "00010 let X = 1\n00020 let Y = 2\n00100 let Z = 3\n";
@@ -498,4 +1102,177 @@ mod tests {
             Some("fnBar$".to_string())
         );
     }
+
+    fn doc_params_fix(source: &str, diag_pos: Position, code: &str) -> Option<String> {
+        let tree = parse(source);
+        let uri = Url::parse("file:///test.brs").unwrap();
+        let diag = Diagnostic {
+            range: Range::new(diag_pos, diag_pos),
+            code: Some(NumberOrString::String(code.to_string())),
+            ..Default::default()
+        };
+        let action = create_doc_params_action(&uri, &diag, &tree, source)?;
+        let mut changes = action.edit?.changes?;
+        Some(changes.remove(&uri)?.remove(0).new_text)
+    }
+
+    #[test]
+    fn doc_params_fix_renames_and_adds() {
+        let source = "/** Adds\n  * @param A first\n  * @param Bee second\n  * @returns the sum\n  */\ndef fnAdd(A, B, C) = A + B + C\n";
+        let text = doc_params_fix(source, Position::new(2, 12), "doc-param-unknown").unwrap();
+        assert_eq!(
+            text,
+            "/** Adds\n  * @param A first\n  * @param B\n  * @param C\n  * @returns the sum\n  */"
+        );
+    }
+
+    #[test]
+    fn doc_params_fix_from_def_line() {
+        let source = "/** Adds\n  * @returns the sum\n  */\ndef fnAdd(A) = A\n";
+        let text = doc_params_fix(source, Position::new(3, 5), "doc-param-missing").unwrap();
+        assert_eq!(text, "/** Adds\n  * @param A\n  * @returns the sum\n  */");
+    }
+
+    #[test]
+    fn doc_params_fix_single_line_comment() {
+        let source = "/** Adds @param X */\ndef fnAdd(A) = A\n";
+        let text = doc_params_fix(source, Position::new(1, 5), "doc-param-missing").unwrap();
+        assert_eq!(text, "/** Adds\n  * @param A\n  */");
+    }
+
+    #[test]
+    fn doc_params_fix_ignores_other_codes() {
+        let source = "/** Adds\n  */\ndef fnAdd(A) = A\n";
+        assert!(doc_params_fix(source, Position::new(2, 5), "undefined-function").is_none());
+    }
+
+    #[test]
+    fn keyword_case_fix() {
+        let uri = Url::parse("file:///test.brs").unwrap();
+        let source = "print 1\n";
+        let tree = parse(source);
+        let diags =
+            crate::style::check_keyword_case(&tree, source, crate::style::KeywordCase::Upper);
+        let action = create_fix_action(&uri, &diags[0]).unwrap();
+        assert_eq!(action.title, "Change to 'PRINT'");
+        let changes = action.edit.unwrap().changes.unwrap();
+        assert_eq!(changes[&uri][0].new_text, "PRINT");
+        assert_eq!(changes[&uri][0].range, diags[0].range);
+    }
+
+    #[test]
+    fn fix_survives_client_round_trip() {
+        let uri = Url::parse("file:///test.brs").unwrap();
+        let source = "def fnFoo(X)\nlet X = 1\nfnend\n";
+        let tree = parse(source);
+        let nodes = parser::collect_diagnostic_nodes(&tree, source);
+        let diag = diagnostics::check_value_param_assignments(
+            &nodes,
+            &Structure::of(&tree, source),
+            source,
+        )
+        .remove(0);
+        // Clients send diagnostics back as JSON in the code action context
+        let echoed: Diagnostic =
+            serde_json::from_value(serde_json::to_value(&diag).unwrap()).unwrap();
+        let action = create_fix_action(&uri, &echoed).unwrap();
+        let changes = action.edit.unwrap().changes.unwrap();
+        assert_eq!(changes[&uri][0].new_text, "&");
+        assert_eq!(changes[&uri][0].range.start, Position::new(0, 10));
+    }
+
+    #[test]
+    fn no_fix_action_without_data() {
+        let uri = Url::parse("file:///test.brs").unwrap();
+        let diag = make_undefined_diagnostic(Range::default(), "fnFoo");
+        assert!(create_fix_action(&uri, &diag).is_none());
+    }
+
+    fn remove_parameter(source: &str, param: &str) -> Option<String> {
+        let tree = parse(source);
+        let uri = Url::parse("file:///test.brs").unwrap();
+        let nodes = parser::collect_diagnostic_nodes(&tree, source);
+        let diag =
+            diagnostics::check_unused_parameters(&nodes, &Structure::of(&tree, source), source)
+                .into_iter()
+                .find(|d| d.message.contains(&format!("'{param}'")))?;
+        let action = create_remove_parameter_action(&uri, &diag, &tree, source)?;
+        let mut edits = action.edit?.changes?.remove(&uri)?;
+        // Apply from the end so earlier offsets stay valid
+        edits.sort_by_key(|e| std::cmp::Reverse((e.range.start.line, e.range.start.character)));
+        let mut lines: Vec<String> = source.lines().map(str::to_string).collect();
+        for edit in edits {
+            assert_eq!(edit.range.start.line, edit.range.end.line);
+            let line = &mut lines[edit.range.start.line as usize];
+            line.replace_range(
+                edit.range.start.character as usize..edit.range.end.character as usize,
+                &edit.new_text,
+            );
+        }
+        Some(lines.join("\n") + "\n")
+    }
+
+    #[test]
+    fn remove_parameter_updates_calls() {
+        let source = "def fnAdd(A, B, C)\nlet fnAdd = A + C\nfnend\nlet X = fnAdd(1, fnAdd(2, 3, 4), 5)\nlet Y = fnAdd(1)\n";
+        assert_eq!(
+            remove_parameter(source, "B").unwrap(),
+            "def fnAdd(A, C)\nlet fnAdd = A + C\nfnend\nlet X = fnAdd(1, 5)\nlet Y = fnAdd(1)\n"
+        );
+    }
+
+    #[test]
+    fn remove_first_and_only_parameters() {
+        let source = "def fnTwo(A, B)\nlet fnTwo = B\nfnend\nlet X = fnTwo(1, 2)\n";
+        assert_eq!(
+            remove_parameter(source, "A").unwrap(),
+            "def fnTwo(B)\nlet fnTwo = B\nfnend\nlet X = fnTwo(2)\n"
+        );
+        let source = "def fnOne(A)\nlet fnOne = 1\nfnend\nlet X = fnOne(7)\n";
+        assert_eq!(
+            remove_parameter(source, "A").unwrap(),
+            "def fnOne\nlet fnOne = 1\nfnend\nlet X = fnOne\n"
+        );
+    }
+
+    #[test]
+    fn remove_parameter_keeps_optional_separator() {
+        let source = "def fnOpt(A; B, C)\nlet fnOpt = A + C\nfnend\nlet X = fnOpt(1, 2, 3)\n";
+        assert_eq!(
+            remove_parameter(source, "B").unwrap(),
+            "def fnOpt(A; C)\nlet fnOpt = A + C\nfnend\nlet X = fnOpt(1, 3)\n"
+        );
+    }
+
+    #[test]
+    fn deferred_actions_resolve_to_the_full_edit() {
+        let source = "def fnAdd(A, B)\nlet fnAdd = A\nfnend\nlet X = fnAdd(1, 2)\n";
+        let tree = parse(source);
+        let uri = Url::parse("file:///test.brs").unwrap();
+        let nodes = parser::collect_diagnostic_nodes(&tree, source);
+        let diag =
+            diagnostics::check_unused_parameters(&nodes, &Structure::of(&tree, source), source)
+                .remove(0);
+
+        let action = DeferredAction::RemoveParameter;
+        let prepared = action.prepare(&uri, 3, Some(&diag), &tree, source).unwrap();
+        let full = action.create(&uri, Some(&diag), &tree, source).unwrap();
+        assert_eq!(prepared.title, full.title);
+        assert!(prepared.edit.is_none());
+
+        let data = ActionData::from_action(&prepared).unwrap();
+        assert_eq!(data.action, action);
+        let stale = resolve_action(prepared.clone(), &data, 4, &tree, source);
+        assert!(stale.edit.is_none());
+        let resolved = resolve_action(prepared, &data, 3, &tree, source);
+        assert_eq!(resolved.edit, full.edit);
+
+        // Other quick fixes don't apply to this diagnostic
+        for other in [DeferredAction::FunctionStub, DeferredAction::DocParams] {
+            assert!(other.prepare(&uri, 3, Some(&diag), &tree, source).is_none());
+        }
+        assert!(DeferredAction::OrganizeLibraries
+            .prepare(&uri, 3, None, &tree, source)
+            .is_none());
+    }
 }
@@ -1,31 +1,104 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 
 use serde::{Deserialize, Serialize};
 use tower_lsp::lsp_types::*;
 
-use crate::backend::DocumentState;
+use crate::backend::DocumentSnapshot;
 use crate::builtins;
+use crate::error_conditions;
 use crate::extract;
 use crate::parser;
+use crate::snippets::{self, Snippet};
+use crate::statements;
+use crate::symbols;
 use crate::workspace::WorkspaceIndex;
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "kind")]
 pub enum CompletionData {
     #[serde(rename = "builtin")]
-    Builtin { name: String, overload: usize },
+    /// `overloads` index into `builtins::lookup(name)`
+    Builtin { name: String, overloads: Vec<usize> },
     #[serde(rename = "local")]
     Local { name: String, uri: String },
     #[serde(rename = "workspace")]
     Workspace { name: String },
 }
 
+/// What completions offer and how accepted ones are inserted, from the
+/// `br-lsp.completion` settings.
+#[derive(Debug, Clone)]
+pub struct CompletionOptions {
+    /// Add `(` after functions that take arguments and open signature help,
+    /// and after arrays that are being indexed
+    pub auto_parens: bool,
+    /// Let typing a separator accept the selected item
+    pub commit_characters: bool,
+    pub statements: bool,
+    /// Snippets from the workspace's `.br-lsp/snippets.json`
+    pub snippets: bool,
+    /// Keywords, including PRINT's and error conditions
+    pub keywords: bool,
+    pub builtins: bool,
+    /// Library functions defined in other workspace files
+    pub workspace_functions: bool,
+    /// Subscripts from layout files
+    pub layout_fields: bool,
+    /// Most items in one response, 0 for no limit
+    pub max_items: usize,
+}
+
+impl Default for CompletionOptions {
+    fn default() -> Self {
+        Self {
+            auto_parens: true,
+            commit_characters: true,
+            statements: true,
+            snippets: true,
+            keywords: true,
+            builtins: true,
+            workspace_functions: true,
+            layout_fields: true,
+            max_items: 0,
+        }
+    }
+}
+
+impl CompletionOptions {
+    pub fn from_settings(settings: Option<&serde_json::Value>) -> Self {
+        let defaults = Self::default();
+        let flag = |key: &str, default: bool| {
+            settings
+                .and_then(|s| s.get(key))
+                .and_then(|v| v.as_bool())
+                .unwrap_or(default)
+        };
+        Self {
+            auto_parens: flag("autoParens", defaults.auto_parens),
+            commit_characters: flag("commitCharacters", defaults.commit_characters),
+            statements: flag("statements", defaults.statements),
+            snippets: flag("snippets", defaults.snippets),
+            keywords: flag("keywords", defaults.keywords),
+            builtins: flag("builtins", defaults.builtins),
+            workspace_functions: flag("workspaceFunctions", defaults.workspace_functions),
+            layout_fields: flag("layoutFields", defaults.layout_fields),
+            max_items: settings
+                .and_then(|s| s.get("maxItems"))
+                .and_then(|v| v.as_u64())
+                .map_or(defaults.max_items, |n| n as usize),
+        }
+    }
+}
+
 pub fn format_builtin_docs(b: &builtins::BuiltinFunction) -> String {
     let sig = b.format_signature();
     let mut md_parts = vec![format!("```br\n{sig}\n```")];
     if let Some(doc) = &b.documentation {
         md_parts.push(doc.clone());
     }
+    if let Some(since) = b.since_version() {
+        md_parts.push(format!("*Available since BR {since}*"));
+    }
     let param_docs: Vec<String> = b
         .params
         .iter()
@@ -41,9 +114,28 @@ pub fn format_builtin_docs(b: &builtins::BuiltinFunction) -> String {
     md_parts.join("\n\n")
 }
 
+/// Markdown notice for a function marked `@deprecated`.
+pub fn format_deprecation(reason: &str) -> String {
+    if reason.is_empty() {
+        "**Deprecated**".to_string()
+    } else {
+        format!("**Deprecated** \u{2014} {reason}")
+    }
+}
+
+/// `CompletionItemTag::DEPRECATED` for functions marked `@deprecated`.
+fn deprecation_tags(d: &extract::FunctionDef) -> Option<Vec<CompletionItemTag>> {
+    d.deprecated
+        .as_ref()
+        .map(|_| vec![CompletionItemTag::DEPRECATED])
+}
+
 pub fn format_function_docs(d: &extract::FunctionDef) -> String {
     let sig = d.format_signature();
     let mut md_parts = vec![format!("```br\n{sig}\n```")];
+    if let Some(reason) = &d.deprecated {
+        md_parts.push(format_deprecation(reason));
+    }
     if let Some(doc) = &d.documentation {
         md_parts.push(doc.clone());
     }
@@ -65,392 +157,219 @@ pub fn format_function_docs(d: &extract::FunctionDef) -> String {
     md_parts.join("\n\n")
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn get_completions(
-    doc: &DocumentState,
+    doc: &DocumentSnapshot,
     uri: &str,
     position: Position,
     workspace_index: &WorkspaceIndex,
     layout_index: &crate::layout::LayoutIndex,
+    br_version: Option<builtins::BrVersion>,
+    options: &CompletionOptions,
+    snippets: &[Snippet],
 ) -> Vec<CompletionItem> {
-    let mut items = Vec::new();
-    items.extend(statement_completions());
-    items.extend(keyword_completions());
-    items.extend(builtin_function_completions());
+    // Inside a `/** */` comment only doc tags make sense
+    if let Some(items) = doc_comment_completions(doc, position) {
+        return items;
+    }
+    // After `MAT` only array names make sense
+    if let Some(items) = mat_array_completions(doc, position, layout_index) {
+        return items;
+    }
+
+    // After the comma ending an error-condition clause only another
+    // condition can follow; after a comma in an I/O list one may
+    let conditions = doc
+        .tree
+        .as_ref()
+        .and_then(|tree| error_condition_context(tree, &doc.source, position));
+    if let Some((statement, true)) = &conditions {
+        return context_keyword_completions(&error_conditions::valid_conditions(statement));
+    }
+
+    // OPEN clauses and FORM specs are the only thing valid in those lists;
+    // PRINT trades the generic keywords for its own
+    let context = doc
+        .tree
+        .as_ref()
+        .and_then(|tree| statement_context(tree, &doc.source, position));
+    match context {
+        Some(StatementContext::Open) => {
+            return context_keyword_completions(statements::clauses("open_statement"))
+        }
+        Some(StatementContext::Form) => {
+            return context_keyword_completions(statements::clauses("form_statement"))
+        }
+        _ => {}
+    }
 
+    // The document's own names come first so `max_items` cuts the rest
+    let mut items = Vec::new();
     if let Some(tree) = doc.tree.as_ref() {
-        items.extend(local_variable_completions(tree, &doc.source, position));
+        let subscripts = subscript_context(tree, &doc.source, position);
+        items.extend(local_variable_completions(
+            tree,
+            &doc.source,
+            position,
+            subscripts,
+        ));
         items.extend(local_function_completions(tree, &doc.source, uri));
     }
 
-    items.extend(library_function_completions(uri, workspace_index));
-    items.extend(layout_subscript_completions(layout_index));
+    if options.statements {
+        items.extend(statement_completions(br_version));
+    }
+    if options.snippets {
+        items.extend(snippets::completion_items(snippets));
+    }
+    if options.keywords {
+        if context == Some(StatementContext::Print) {
+            items.extend(context_keyword_completions(statements::clauses(
+                "print_statement",
+            )));
+        } else {
+            items.extend(keyword_completions());
+        }
+        if let Some((statement, false)) = &conditions {
+            items.extend(context_keyword_completions(
+                &error_conditions::valid_conditions(statement),
+            ));
+        }
+    }
+    if options.builtins {
+        items.extend(builtin_function_completions(br_version));
+    }
+    if options.workspace_functions {
+        items.extend(library_function_completions(uri, workspace_index));
+    }
+    if options.layout_fields {
+        items.extend(layout_subscript_completions(layout_index));
+    }
     items
 }
 
+/// Cut `items` to `max_items` (0 for no limit), keeping only those that
+/// start with the word being typed. Returns whether the list was cut, in
+/// which case it should go out marked incomplete so the client asks again
+/// as the word grows.
+pub fn limit_items(
+    items: &mut Vec<CompletionItem>,
+    source: &str,
+    position: Position,
+    max_items: usize,
+) -> bool {
+    if max_items == 0 || items.len() <= max_items {
+        return false;
+    }
+    let (_, line, column) = line_at(source, position);
+    let prefix = &line[..column];
+    let word_start = prefix
+        .char_indices()
+        .rev()
+        .find(|&(_, c)| !(c.is_ascii_alphanumeric() || c == '_' || c == '$'))
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let word = prefix[word_start..].to_ascii_lowercase();
+    items.retain(|item| {
+        item.filter_text
+            .as_deref()
+            .unwrap_or(&item.label)
+            .to_ascii_lowercase()
+            .starts_with(&word)
+    });
+    items.truncate(max_items);
+    true
+}
+
 // ---------------------------------------------------------------------------
-// Statements (#9)
+// Insert behaviour
 // ---------------------------------------------------------------------------
 
-struct StatementEntry {
-    name: &'static str,
-    description: &'static str,
-    documentation: &'static str,
-    doc_url: &'static str,
-    example: &'static str,
+const TRIGGER_PARAMETER_HINTS: &str = "editor.action.triggerParameterHints";
+
+/// Statements and keywords are always followed by a space.
+const KEYWORD_COMMIT_CHARACTERS: &[&str] = &[" "];
+/// Characters that can follow a variable or subscript name.
+const IDENTIFIER_COMMIT_CHARACTERS: &[&str] = &[",", ")", ";"];
+/// Functions inserted without parentheses are committed by typing one.
+const FUNCTION_COMMIT_CHARACTERS: &[&str] = &["("];
+
+/// Insert `name(` with the cursor inside the parentheses, then open
+/// signature help. Only for functions that take arguments; BR calls the
+/// others without parentheses.
+fn with_call_parens(mut item: CompletionItem, takes_args: bool) -> CompletionItem {
+    if takes_args {
+        item.insert_text = Some(format!("{}($0)", item.label));
+        item.insert_text_format = Some(InsertTextFormat::SNIPPET);
+        item.command = Some(Command {
+            title: "Trigger Parameter Hints".to_string(),
+            command: TRIGGER_PARAMETER_HINTS.to_string(),
+            arguments: None,
+        });
+    }
+    item
 }
 
-const STATEMENTS: &[StatementEntry] = &[
-    StatementEntry {
-        name: "do",
-        description: "",
-        documentation: "",
-        doc_url: "",
-        example: "",
-    },
-    StatementEntry {
-        name: "loop",
-        description: "",
-        documentation: "",
-        doc_url: "",
-        example: "",
-    },
-    StatementEntry {
-        name: "if",
-        description: "",
-        documentation: "",
-        doc_url: "",
-        example: "",
-    },
-    StatementEntry {
-        name: "end if",
-        description: "",
-        documentation: "",
-        doc_url: "",
-        example: "",
-    },
-    StatementEntry {
-        name: "def",
-        description: "Def Statement",
-        documentation: "Defines function.",
-        doc_url: "http://www.brwiki.com/index.php?title=Def",
-        example: "def fnfoo(bar)\n\t! body\nfnend",
-    },
-    StatementEntry {
-        name: "def library",
-        description: "Def Library Fn ... fnend",
-        documentation: "Define library function",
-        doc_url: "http://www.brwiki.com/index.php?title=Def",
-        example: "",
-    },
-    StatementEntry {
-        name: "Chain",
-        description: "Chain {<program name>|\"PROC=<name>\"|\"SUPROC=<name>\"} ...",
-        documentation: "Loads and Runs the target program, immediately ending the current program. Optionally passes variables and files into the called program.",
-        doc_url: "http://brwiki2.brulescorp.com/index.php?title=Chain",
-        example: "",
-    },
-    StatementEntry {
-        name: "Close",
-        description: "Close {#<file/window number>} [,Free|Drop] [, ...] :",
-        documentation: "The Close (CL) statement deactivates access to a data or window file for input or output.",
-        doc_url: "http://www.brwiki.com/index.php?search=Close",
-        example: "",
-    },
-    StatementEntry {
-        name: "Continue",
-        description: "Continue Statement",
-        documentation: "Jumps to the line following the line that had the most recent error. Used to continue in an Error Handler.",
-        doc_url: "http://www.brwiki.com/index.php?search=Continue",
-        example: "",
-    },
-    StatementEntry {
-        name: "Data",
-        description: "Data {\"<string constant>\"|<numeric constant>}[,...]",
-        documentation: "The Data statement can be used to populate the values of variables.",
-        doc_url: "http://www.brwiki.com/index.php?search=Data",
-        example: "",
-    },
-    StatementEntry {
-        name: "Delete",
-        description: "Delete",
-        documentation: "Deletes the currently locked record from the identified data file..",
-        doc_url: "http://brwiki2.brulescorp.com/index.php?title=Delete_(statement)",
-        example: "",
-    },
-    StatementEntry {
-        name: "Dim",
-        description: "Dim",
-        documentation: "Declares Variables and Arrays. Arrays must be declared if they have other then 10 messages.",
-        doc_url: "http://www.brwiki.com/index.php?search=Dim",
-        example: "",
-    },
-    StatementEntry {
-        name: "Display",
-        description: "Display [Menu|Buttons] ...",
-        documentation: "Display or Update the Windows Menu, or the Button Rows.",
-        doc_url: "http://www.brwiki.com/index.php?search=Display",
-        example: "",
-    },
-    StatementEntry {
-        name: "End",
-        description: "End",
-        documentation: "Ends your program (continuing with any proc files that ran your program, or stopping if your program wasn't run from a proc.)",
-        doc_url: "http://www.brwiki.com/index.php?search=End",
-        example: "",
-    },
-    StatementEntry {
-        name: "Execute",
-        description: "Execute \"BR Command\"",
-        documentation: "Executes a Command from within one of your programs.",
-        doc_url: "http://www.brwiki.com/index.php?search=Execute",
-        example: "",
-    },
-    StatementEntry {
-        name: "Exit",
-        description: "Exit <error condition line ref>[,...]",
-        documentation: "Works in conjunction with the Exit error condition to list a bunch of error handlers in one place.",
-        doc_url: "http://brwiki2.brulescorp.com/index.php?title=Exit",
-        example: "",
-    },
-    StatementEntry {
-        name: "Exit Do",
-        description: "Exit Do Statement",
-        documentation: "Jumps out of a do loop to the line following the loop.",
-        doc_url: "http://brwiki2.brulescorp.com/index.php?title=Exit_do",
-        example: "",
-    },
-    StatementEntry {
-        name: "Fnend",
-        description: "Fnend Statement",
-        documentation: "The FnEnd (FN) and End Def statements indicates the end of a definition of a multi-lined user defined function.",
-        doc_url: "http://brwiki2.brulescorp.com/index.php?title=Fnend",
-        example: "",
-    },
-    StatementEntry {
-        name: "Print",
-        description: "Print Statement",
-        documentation: "Prints a line to the console, or to a specific file.",
-        doc_url: "http://www.brwiki.com/index.php?search=Print",
-        example: "",
-    },
-    StatementEntry {
-        name: "Input",
-        description: "Input <Variables>",
-        documentation: "Reads text from the user or from a display file (like a text file). It can also read text from a proc file, if the program is called from a proc.",
-        doc_url: "http://www.brwiki.com/index.php?search=Input",
-        example: "",
-    },
-    StatementEntry {
-        name: "Linput",
-        description: "Linput <StringVariable>",
-        documentation: "Reads a line of text from a display file. This is useful for parsing CSV files and other files generated by external applications.",
-        doc_url: "http://www.brwiki.com/index.php?search=Linput",
-        example: "",
-    },
-    StatementEntry {
-        name: "Input",
-        description: "Input Fields",
-        documentation: "Activates a bunch of controls on the screen and pauses execution, allowing the user to interact with them. This is the primary way that BR programs interact with the User.",
-        doc_url: "http://brwiki2.brulescorp.com/index.php?title=Input_Fields",
-        example: "",
-    },
-    StatementEntry {
-        name: "Rinput",
-        description: "Rinput Fields",
-        documentation: "Updates and then activates a bunch of controls on the screen and pauses execution, allowing the user to interact with them. This is the primary way that BR programs interact with the User.",
-        doc_url: "http://www.brwiki.com/index.php?search=Rinput",
-        example: "",
-    },
-    StatementEntry {
-        name: "Input",
-        description: "Input Select",
-        documentation: "Activates a bunch of controls and allows the user to select one of them.",
-        doc_url: "http://brwiki2.brulescorp.com/index.php?title=Input_Select",
-        example: "",
-    },
-    StatementEntry {
-        name: "Rinput",
-        description: "Rinput Select",
-        documentation: "Activates and Displays a bunch of controls and allows the user to select one of them.",
-        doc_url: "http://brwiki2.brulescorp.com/index.php?title=Rinput_select",
-        example: "",
-    },
-    StatementEntry {
-        name: "For",
-        description: "Form",
-        documentation: "The Form statement is used in conjunction with PRINT, WRITE, REWRITE, READ or REREAD statements to format input or output. FORM controls the size, location, field length and format of input or output.",
-        doc_url: "http://www.brwiki.com/index.php?search=Form",
-        example: "",
-    },
-    StatementEntry {
-        name: "Gosub",
-        description: "Gosub <LineLabel/LineNumber>",
-        documentation: "Calls a subroutine, which runs until it encounters a return statement, at which point it returns here.",
-        doc_url: "http://www.brwiki.com/index.php?search=Gosub",
-        example: "",
-    },
-    StatementEntry {
-        name: "Goto",
-        description: "Goto <LineLabel/LineNumber>",
-        documentation: "Jumps to the target line and continues running from there. (Try not to use Goto Statements. This is not the 80s.).",
-        doc_url: "http://www.brwiki.com/index.php?search=Goto",
-        example: "",
-    },
-    StatementEntry {
-        name: "Library",
-        description: "Library \"<Library>\" : <fnFunction1> [, fnFunction2] [, ...]",
-        documentation: "Loads a BR Libary, allowing access to the library functions in it.",
-        doc_url: "http://www.brwiki.com/index.php?search=Library",
-        example: "",
-    },
-    StatementEntry {
-        name: "Mat",
-        description: "Mat <array name> [(<dimension>[,...])] = ....",
-        documentation: "The Mat statement is used for working with Arrays. Its used to resize arrays, sort them (in conjunction with AIDX or DIDX), copy them, and process them in lots of other ways.",
-        doc_url: "http://www.brwiki.com/index.php?search=Mat",
-        example: "",
-    },
-    StatementEntry {
-        name: "On",
-        description: "On Statement",
-        documentation: "",
-        doc_url: "",
-        example: "",
-    },
-    StatementEntry {
-        name: "Open",
-        description: "Open #<FileNumber> \"Name=...\"",
-        documentation: "Opens a file or window or http connection or comm port.",
-        doc_url: "http://www.brwiki.com/index.php?search=Open",
-        example: "",
-    },
-    StatementEntry {
-        name: "Pause",
-        description: "Pause",
-        documentation: "Pauses program execution allows the programmer to interact with the program in the Command Console.",
-        doc_url: "http://brwiki2.brulescorp.com/index.php?title=Pause",
-        example: "",
-    },
-    StatementEntry {
-        name: "Randomize",
-        description: "Randomize",
-        documentation: "Generates a new Random Number Seed for the Random Number Generator (based on the system clock so as to be truly random).",
-        doc_url: "http://www.brwiki.com/index.php?search=Randomize",
-        example: "",
-    },
-    StatementEntry {
-        name: "Read",
-        description: "Read Statement",
-        documentation: "Reads data",
-        doc_url: "http://www.brwiki.com/index.php?search=Read",
-        example: "",
-    },
-    StatementEntry {
-        name: "Reread",
-        description: "Reread  #<file number> [, USING {<formStatement>}] : <Variables>",
-        documentation: "Rereads the previous record read again, in the selected data file or data statements, storing the information in the variables provided.",
-        doc_url: "http://www.brwiki.com/index.php?search=Reread",
-        example: "",
-    },
-    StatementEntry {
-        name: "Write",
-        description: "Write  #<file number> [, USING {<formStatement>}] : <Variables>",
-        documentation: "Adds a record to the file containing the information from the variables you list.",
-        doc_url: "http://www.brwiki.com/index.php?search=Write",
-        example: "",
-    },
-    StatementEntry {
-        name: "Rewrite",
-        description: "Rewrite  #<file number> [, USING {<formStatement>}] : <Variables>",
-        documentation: "Updates the record that is locked in the file (usually the last record read), with the data in the variables now.",
-        doc_url: "http://www.brwiki.com/index.php?search=Rewrite",
-        example: "",
-    },
-    StatementEntry {
-        name: "Restore",
-        description: "Restore  #<file number> [,<Key|Rec|Pos|Search> = <SearchValue|Position>:",
-        documentation: "Jumps to the beginning (or other specified point) in the targeted file.",
-        doc_url: "http://www.brwiki.com/index.php?search=Restore",
-        example: "",
-    },
-    StatementEntry {
-        name: "Retry",
-        description: "Retry",
-        documentation: "Jumps to the line that had the most recent error. Used to try again in an Error Handler.",
-        doc_url: "http://www.brwiki.com/index.php?search=Retry",
-        example: "",
-    },
-    StatementEntry {
-        name: "Return",
-        description: "Return",
-        documentation: "Exits a Subroutine and returns control back up to the code following the Gosub statement.",
-        doc_url: "http://www.brwiki.com/index.php?search=Return",
-        example: "",
-    },
-    StatementEntry {
-        name: "Scr_Freeze",
-        description: "Scr_Freeze",
-        documentation: "Stops the screen from updating, significantly increasing the speed of the programs. The screen starts running again at the next Input Statement or Scr_Thaw statement.",
-        doc_url: "http://www.brwiki.com/index.php?search=Scr_freeze",
-        example: "",
-    },
-    StatementEntry {
-        name: "Scr_Thaw",
-        description: "Scr_Thaw",
-        documentation: "Causes the screen to refresh and begin updating again after it was frozen with a Scr_Freeze command.",
-        doc_url: "http://www.brwiki.com/index.php?search=Scr_thaw",
-        example: "",
-    },
-    StatementEntry {
-        name: "Stop",
-        description: "Stop",
-        documentation: "Ends your program (continuing with any proc files that ran your program, or stopping if your program wasn't run from a proc.)",
-        doc_url: "http://www.brwiki.com/index.php?search=Stop",
-        example: "",
-    },
-    StatementEntry {
-        name: "Trace",
-        description: "Trace [On|Off|Print]",
-        documentation: "Displays or outputs the line numbers as they're executed. Used for debugging code, but the modern debugging tools are much better.",
-        doc_url: "http://www.brwiki.com/index.php?search=Trace",
-        example: "",
-    },
-];
+fn has_call_parens(item: &CompletionItem) -> bool {
+    item.command
+        .as_ref()
+        .is_some_and(|c| c.command == TRIGGER_PARAMETER_HINTS)
+}
+
+/// Adjust completions to the settings and what the client supports: drop
+/// the auto-parens snippet when disabled or unsupported, and attach commit
+/// characters by item kind.
+pub fn apply_insert_options(
+    items: &mut [CompletionItem],
+    options: &CompletionOptions,
+    snippets: bool,
+    commit_characters: bool,
+) {
+    for item in items {
+        // Workspace snippets are only offered to clients that support them
+        let snippet = item.insert_text_format == Some(InsertTextFormat::SNIPPET)
+            && item.kind != Some(CompletionItemKind::SNIPPET);
+        if snippet && !(options.auto_parens && snippets) {
+            item.insert_text = None;
+            item.insert_text_format = None;
+            item.command = None;
+        }
+        if !(options.commit_characters && commit_characters) {
+            continue;
+        }
+        let characters = match item.kind {
+            Some(CompletionItemKind::KEYWORD) => KEYWORD_COMMIT_CHARACTERS,
+            Some(CompletionItemKind::FUNCTION) if has_call_parens(item) => &[],
+            Some(CompletionItemKind::FUNCTION) => FUNCTION_COMMIT_CHARACTERS,
+            Some(CompletionItemKind::VARIABLE) if item.insert_text.is_some() => &[],
+            Some(CompletionItemKind::VARIABLE) => IDENTIFIER_COMMIT_CHARACTERS,
+            _ => &[],
+        };
+        if !characters.is_empty() {
+            item.commit_characters = Some(characters.iter().map(|c| c.to_string()).collect());
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Statements (#9)
+// ---------------------------------------------------------------------------
 
-fn statement_completions() -> Vec<CompletionItem> {
-    STATEMENTS
+/// Statements available in `br_version` (all of them when no version is set).
+fn statement_completions(br_version: Option<builtins::BrVersion>) -> Vec<CompletionItem> {
+    statements::STATEMENTS
         .iter()
-        .map(|s| {
-            let mut md_parts = Vec::new();
-            if !s.documentation.is_empty() {
-                md_parts.push(s.documentation.to_string());
-            }
-            if !s.doc_url.is_empty() {
-                md_parts.push(format!("[Documentation]({})", s.doc_url));
-            }
-            if !s.example.is_empty() {
-                md_parts.push(format!("```br\n{}\n```", s.example));
-            }
-            let documentation = if md_parts.is_empty() {
-                None
-            } else {
-                Some(Documentation::MarkupContent(MarkupContent {
+        .filter(|s| s.available_in(br_version))
+        .map(|s| CompletionItem {
+            label: s.keyword.to_string(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            detail: (!s.signature.is_empty()).then(|| s.signature.to_string()),
+            documentation: s.markdown().map(|value| {
+                Documentation::MarkupContent(MarkupContent {
                     kind: MarkupKind::Markdown,
-                    value: md_parts.join("\n\n"),
-                }))
-            };
-
-            CompletionItem {
-                label: s.name.to_string(),
-                kind: Some(CompletionItemKind::KEYWORD),
-                detail: if s.description.is_empty() {
-                    None
-                } else {
-                    Some(s.description.to_string())
-                },
-                documentation,
-                ..Default::default()
-            }
+                    value,
+                })
+            }),
+            ..Default::default()
         })
         .collect()
 }
@@ -502,36 +421,225 @@ fn keyword_completions() -> Vec<CompletionItem> {
         .collect()
 }
 
+// ---------------------------------------------------------------------------
+// Statement-specific keywords
+// ---------------------------------------------------------------------------
+
+/// A statement whose clause list has its own keyword set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StatementContext {
+    Open,
+    Form,
+    Print,
+}
+
+fn context_keyword_completions(keywords: &[(&str, &str)]) -> Vec<CompletionItem> {
+    keywords
+        .iter()
+        .map(|&(name, description)| CompletionItem {
+            label: name.to_string(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            detail: Some(description.to_string()),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn string_or_comment(kind: &str) -> bool {
+    matches!(
+        kind,
+        "string" | "template_string" | "comment" | "multiline_comment" | "doc_comment"
+    )
+}
+
+/// The OPEN, FORM or PRINT statement whose clause list the cursor is in,
+/// found from the tree node just before the word being typed. Incomplete
+/// statements often parse as ERROR nodes, which still start with the
+/// statement keyword. OPEN only counts after the `:` that starts its
+/// clauses.
+fn statement_context(
+    tree: &tree_sitter::Tree,
+    source: &str,
+    position: Position,
+) -> Option<StatementContext> {
+    let (line_start, line, column) = line_at(source, position);
+    let prefix = &line[..column];
+    let token_start = prefix
+        .char_indices()
+        .rev()
+        .find(|&(_, c)| !(c.is_ascii_alphanumeric() || c == '_'))
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let before = prefix[..token_start].trim_end();
+    if before.is_empty() {
+        return None;
+    }
+
+    let point = tree_sitter::Point::new(position.line as usize, before.len() - 1);
+    let cursor = tree_sitter::Point::new(position.line as usize, column);
+    let mut node = tree.root_node().descendant_for_point_range(point, point)?;
+    loop {
+        if string_or_comment(node.kind()) && node.end_position() > cursor {
+            return None;
+        }
+        let context = match node.kind() {
+            "open_statement" => Some(StatementContext::Open),
+            "form_statement" => Some(StatementContext::Form),
+            "print_statement" => Some(StatementContext::Print),
+            "ERROR" | "line" => {
+                let keyword = node.child(0).filter(|c| c.kind() == "statement");
+                match keyword.and_then(|k| k.utf8_text(source.as_bytes()).ok()) {
+                    Some(k) if k.eq_ignore_ascii_case("open") => Some(StatementContext::Open),
+                    Some(k) if k.eq_ignore_ascii_case("form") => Some(StatementContext::Form),
+                    Some(k) if k.eq_ignore_ascii_case("print") => Some(StatementContext::Print),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        if let Some(context) = context {
+            let keyword = node.child(0)?;
+            // The word being typed may still be the statement keyword itself
+            if keyword.start_position().row != position.line as usize
+                || keyword.end_byte() > line_start + token_start
+            {
+                return None;
+            }
+            if context == StatementContext::Open {
+                let clauses = &source[keyword.end_byte()..line_start + column];
+                if !outside_quotes(clauses).contains(':') {
+                    return None;
+                }
+            }
+            return Some(context);
+        }
+        node = node.parent()?;
+    }
+}
+
+/// `text` with everything inside double quotes removed.
+fn outside_quotes(text: &str) -> String {
+    text.split('"').step_by(2).collect()
+}
+
+/// For a cursor after a comma in an I/O statement's data list or error
+/// conditions, the statement's kind and whether only an error condition can
+/// follow (the comma ends a condition clause rather than a list item).
+fn error_condition_context(
+    tree: &tree_sitter::Tree,
+    source: &str,
+    position: Position,
+) -> Option<(String, bool)> {
+    let (line_start, line, column) = line_at(source, position);
+    let prefix = &line[..column];
+    let token_start = prefix
+        .char_indices()
+        .rev()
+        .find(|&(_, c)| !(c.is_ascii_alphanumeric() || c == '_'))
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let before = prefix[..token_start].trim_end();
+    if !before.ends_with(',') {
+        return None;
+    }
+    let comma = line_start + before.len() - 1;
+
+    // The comma must come after the statement's colon, in the data list
+    let after_colon = |statement: tree_sitter::Node| {
+        source
+            .get(statement.start_byte()..comma)
+            .is_some_and(|text| outside_quotes(text).contains(':'))
+    };
+
+    let point = tree_sitter::Point::new(position.line as usize, before.len() - 1);
+    let mut node = tree.root_node().descendant_for_point_range(point, point)?;
+    loop {
+        let kind = node.kind();
+        if string_or_comment(kind) {
+            return None;
+        }
+        if kind == "error_condition_list" {
+            return Some((node.parent()?.kind().to_string(), true));
+        }
+        if error_conditions::is_io_statement(kind) {
+            return after_colon(node).then(|| (kind.to_string(), false));
+        }
+        match kind {
+            // A trailing comma doesn't parse, leaving it in an ERROR node
+            // next to the statement it follows
+            "ERROR" => {
+                if let Some(statement) = node
+                    .prev_sibling()
+                    .filter(|s| error_conditions::is_io_statement(s.kind()))
+                {
+                    let ends_conditions = statement
+                        .named_child(statement.named_child_count().checked_sub(1)?)
+                        .is_some_and(|last| last.kind() == "error_condition_list");
+                    return after_colon(statement)
+                        .then(|| (statement.kind().to_string(), ends_conditions));
+                }
+                let keyword = node.child(0).filter(|c| c.kind() == "statement")?;
+                let statement = format!(
+                    "{}_statement",
+                    keyword
+                        .utf8_text(source.as_bytes())
+                        .ok()?
+                        .to_ascii_lowercase()
+                );
+                return (error_conditions::is_io_statement(&statement) && after_colon(node))
+                    .then_some((statement, false));
+            }
+            "line" | "source_file" => return None,
+            _ => node = node.parent()?,
+        }
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Built-in functions (#11)
 // ---------------------------------------------------------------------------
 
-fn builtin_function_completions() -> Vec<CompletionItem> {
-    let mut overload_counts: HashMap<String, usize> = HashMap::new();
+/// Builtins available in `br_version` (all of them when no version is set).
+/// One item per builtin name; overloads share it, with every available
+/// signature in the detail.
+fn builtin_function_completions(br_version: Option<builtins::BrVersion>) -> Vec<CompletionItem> {
+    let mut seen: HashSet<String> = HashSet::new();
 
     builtins::all()
-        .map(|b| {
-            let sig = b.format_signature();
-            let detail = format!("(built-in) {sig}");
+        .filter_map(|b| {
+            if !seen.insert(b.name.to_ascii_lowercase()) {
+                return None;
+            }
+            // Indices cover every overload so they line up with `builtins::lookup`
+            let available: Vec<(usize, &builtins::BuiltinFunction)> = builtins::lookup(&b.name)
+                .iter()
+                .enumerate()
+                .filter(|(_, o)| o.available_in(br_version))
+                .collect();
+            if available.is_empty() {
+                return None;
+            }
 
-            let key = b.name.to_ascii_lowercase();
-            let overload = *overload_counts.get(&key).unwrap_or(&0);
-            *overload_counts.entry(key).or_insert(0) += 1;
+            let sigs: Vec<String> = available
+                .iter()
+                .map(|(_, o)| o.format_signature())
+                .collect();
+            let detail = format!("(built-in) {}", sigs.join(" | "));
 
             let data = serde_json::to_value(CompletionData::Builtin {
                 name: b.name.clone(),
-                overload,
+                overloads: available.iter().map(|(i, _)| *i).collect(),
             })
             .ok();
 
-            CompletionItem {
+            let item = CompletionItem {
                 label: b.name.clone(),
                 kind: Some(CompletionItemKind::FUNCTION),
                 detail: Some(detail),
                 documentation: None,
                 data,
                 ..Default::default()
-            }
+            };
+            let takes_args = available.iter().any(|(_, o)| !o.params.is_empty());
+            Some(with_call_parens(item, takes_args))
         })
         .collect()
 }
@@ -540,24 +648,93 @@ fn builtin_function_completions() -> Vec<CompletionItem> {
 // Local variables (#12)
 // ---------------------------------------------------------------------------
 
+/// Query, type label, and the DIM entry kind declaring such a variable
+const ARRAY_QUERIES: &[(&str, &str, &str)] = &[
+    (
+        "(stringarray name: (_) @name)",
+        "string array",
+        "stringarray",
+    ),
+    (
+        "(numberarray name: (_) @name)",
+        "number array",
+        "numberarray",
+    ),
+];
+
+const SCALAR_QUERIES: &[(&str, &str, &str)] = &[
+    (
+        "(stringreference name: (_) @name)",
+        "string",
+        "stringreference",
+    ),
+    (
+        "(numberreference name: (_) @name)",
+        "number",
+        "numberreference",
+    ),
+];
+
+/// Variables used in the document. With `subscripts`, arrays insert
+/// `name(` with the cursor inside the parentheses.
 fn local_variable_completions(
     tree: &tree_sitter::Tree,
     source: &str,
     position: Position,
+    subscripts: bool,
 ) -> Vec<CompletionItem> {
-    let root = tree.root_node();
+    let mut items = variable_completions(tree, source, position, ARRAY_QUERIES);
+    if subscripts {
+        for item in &mut items {
+            item.insert_text = Some(format!("{}($0)", item.label));
+            item.insert_text_format = Some(InsertTextFormat::SNIPPET);
+        }
+    }
+    items.extend(variable_completions(tree, source, position, SCALAR_QUERIES));
+    items
+}
+
+/// Whether an array completed at `position` is indexed: anywhere but in a
+/// DIM statement, a parameter list, a MAT statement, or before a `(`
+/// already typed.
+fn subscript_context(tree: &tree_sitter::Tree, source: &str, position: Position) -> bool {
+    let (_, line, column) = line_at(source, position);
+    if line[column..].starts_with('(') {
+        return false;
+    }
+    let row = position.line as usize;
+    let col = position.character as usize;
+    let Some(node) = parser::node_at_position(tree, row, col.saturating_sub(1)) else {
+        return true;
+    };
+    let mut current = Some(node);
+    while let Some(n) = current {
+        if matches!(
+            n.kind(),
+            "dim_statement" | "parameter_list" | "mat_statement"
+        ) {
+            return false;
+        }
+        current = n.parent();
+    }
+    true
+}
 
-    let queries: &[(&str, &str)] = &[
-        ("(stringarray name: (_) @name)", "string array"),
-        ("(numberarray name: (_) @name)", "number array"),
-        ("(stringreference name: (_) @name)", "string"),
-        ("(numberreference name: (_) @name)", "number"),
-    ];
+/// Variables matching `queries`, with the DIM declaration in the detail
+/// when there is one.
+fn variable_completions(
+    tree: &tree_sitter::Tree,
+    source: &str,
+    position: Position,
+    queries: &[(&str, &str, &str)],
+) -> Vec<CompletionItem> {
+    let root = tree.root_node();
+    let dims = symbols::dim_declarations(tree, source);
 
     let mut seen = HashSet::new();
     let mut items = Vec::new();
 
-    for &(query_str, type_label) in queries {
+    for &(query_str, type_label, dim_kind) in queries {
         let results = parser::run_query(query_str, root, source);
         for r in results {
             // Exclude the token at cursor position
@@ -574,10 +751,14 @@ fn local_variable_completions(
                 continue;
             }
 
+            let detail = match dims.get(&(r.text.to_ascii_lowercase(), dim_kind)) {
+                Some(declaration) => format!("{type_label}: DIM {declaration}"),
+                None => type_label.to_string(),
+            };
             items.push(CompletionItem {
                 label: r.text,
                 kind: Some(CompletionItemKind::VARIABLE),
-                detail: Some(type_label.to_string()),
+                detail: Some(detail),
                 ..Default::default()
             });
         }
@@ -586,6 +767,106 @@ fn local_variable_completions(
     items
 }
 
+// ---------------------------------------------------------------------------
+// MAT array names
+// ---------------------------------------------------------------------------
+
+/// Byte offset where the cursor's line starts, the line's text, and the
+/// cursor's byte column within it.
+fn line_at(source: &str, position: Position) -> (usize, &str, usize) {
+    let line_start: usize = source
+        .split_inclusive('\n')
+        .take(position.line as usize)
+        .map(str::len)
+        .sum();
+    let line = source[line_start..].lines().next().unwrap_or("");
+    let column = line
+        .char_indices()
+        .nth(position.character as usize)
+        .map_or(line.len(), |(i, _)| i);
+    (line_start, line, column)
+}
+
+/// Completions for a cursor right after `MAT ` (optionally with part of a
+/// name typed): the arrays used in the document plus the string and
+/// numeric arrays of each known layout, which are conventionally named
+/// after the layout file. Items replace only the partial name, so `MAT`
+/// isn't inserted again. Returns `None` anywhere else.
+fn mat_array_completions(
+    doc: &DocumentSnapshot,
+    position: Position,
+    layout_index: &crate::layout::LayoutIndex,
+) -> Option<Vec<CompletionItem>> {
+    let (_, line, column) = line_at(&doc.source, position);
+    let prefix = &line[..column];
+    let token_start = prefix
+        .char_indices()
+        .rev()
+        .find(|&(_, c)| !(c.is_ascii_alphanumeric() || c == '_' || c == '$'))
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let before = &prefix[..token_start];
+    let keyword = before.trim_end();
+    let split = keyword.len().checked_sub(3)?;
+    let head = keyword.get(..split)?;
+    let mat = keyword.get(split..)?;
+    if keyword.len() == before.len()
+        || !mat.eq_ignore_ascii_case("mat")
+        || head.ends_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return None;
+    }
+
+    let token_range = Range {
+        start: Position {
+            line: position.line,
+            character: prefix[..token_start].chars().count() as u32,
+        },
+        end: position,
+    };
+
+    let mut items = match doc.tree.as_ref() {
+        Some(tree) => variable_completions(tree, &doc.source, position, ARRAY_QUERIES),
+        None => Vec::new(),
+    };
+    let mut seen: HashSet<String> = items.iter().map(|i| i.label.to_ascii_lowercase()).collect();
+
+    let mut layouts: Vec<(&str, &crate::layout::Layout)> = layout_index.iter().collect();
+    layouts.sort_by_key(|(uri, _)| *uri);
+    for (uri, layout) in layouts {
+        let file = uri.rsplit('/').next().unwrap_or(uri);
+        let name = file.strip_suffix(".lay").unwrap_or(file);
+        if name.is_empty() {
+            continue;
+        }
+        for (label, type_label) in [
+            (format!("{name}$"), "string array"),
+            (name.to_string(), "number array"),
+        ] {
+            if !seen.insert(label.to_ascii_lowercase()) {
+                continue;
+            }
+            items.push(CompletionItem {
+                label,
+                kind: Some(CompletionItemKind::VARIABLE),
+                detail: Some(format!("(layout) {type_label}")),
+                label_details: Some(CompletionItemLabelDetails {
+                    description: Some(layout.path.clone()),
+                    detail: None,
+                }),
+                ..Default::default()
+            });
+        }
+    }
+
+    for item in &mut items {
+        item.text_edit = Some(CompletionTextEdit::Edit(TextEdit {
+            range: token_range,
+            new_text: item.label.clone(),
+        }));
+    }
+    Some(items)
+}
+
 // ---------------------------------------------------------------------------
 // Local functions (#13)
 // ---------------------------------------------------------------------------
@@ -607,15 +888,19 @@ fn local_function_completions(
                 uri: uri.to_string(),
             })
             .ok();
+            let tags = deprecation_tags(&d);
+            let takes_args = !d.params.is_empty();
 
-            CompletionItem {
+            let item = CompletionItem {
                 label: d.name,
                 kind: Some(CompletionItemKind::FUNCTION),
                 detail: Some(detail),
+                tags,
                 documentation: None,
                 data,
                 ..Default::default()
-            }
+            };
+            with_call_parens(item, takes_args)
         })
         .collect()
 }
@@ -645,7 +930,15 @@ fn layout_subscript_completions(layout_index: &crate::layout::LayoutIndex) -> Ve
             items.push(CompletionItem {
                 label,
                 kind: Some(CompletionItemKind::VARIABLE),
-                detail: Some(format!("(subscript) {} {}", sub.name, sub.format)),
+                detail: Some(match sub.position().zip(sub.length) {
+                    Some((pos, len)) => format!(
+                        "(subscript) {} {} @ {pos}-{}",
+                        sub.name,
+                        sub.format,
+                        pos + len - 1
+                    ),
+                    None => format!("(subscript) {} {}", sub.name, sub.format),
+                }),
                 documentation: if sub.description.is_empty() {
                     None
                 } else {
@@ -686,7 +979,7 @@ fn library_function_completions(current_uri: &str, index: &WorkspaceIndex) -> Ve
             })
             .ok();
 
-            CompletionItem {
+            let item = CompletionItem {
                 label: s.def.name.clone(),
                 kind: Some(CompletionItemKind::FUNCTION),
                 detail: Some(detail),
@@ -694,14 +987,122 @@ fn library_function_completions(current_uri: &str, index: &WorkspaceIndex) -> Ve
                     description: Some(filename),
                     detail: None,
                 }),
+                tags: deprecation_tags(&s.def),
                 documentation: None,
                 data,
                 ..Default::default()
-            }
+            };
+            with_call_parens(item, !s.def.params.is_empty())
         })
         .collect()
 }
 
+// ---------------------------------------------------------------------------
+// Doc comment tags
+// ---------------------------------------------------------------------------
+
+const DOC_TAGS: &[(&str, &str)] = &[
+    ("@param", "Document a parameter"),
+    ("@returns", "Document the return value"),
+    ("@deprecated", "Mark the function as deprecated"),
+];
+
+/// Completions for a cursor inside a `/** */` comment: `@param`,
+/// `@returns` and `@deprecated` tags, and after `@param` the parameters of
+/// the DEF following the comment that are not documented yet. Returns `None`
+/// outside doc comments.
+fn doc_comment_completions(
+    doc: &DocumentSnapshot,
+    position: Position,
+) -> Option<Vec<CompletionItem>> {
+    let source: &str = &doc.source;
+    let (line_start, line, column) = line_at(source, position);
+    let offset = line_start + column;
+
+    let comment_start = source[..offset].rfind("/**")?;
+    if source[comment_start..offset].contains("*/") {
+        return None;
+    }
+    let comment_end = source[offset..].find("*/").map(|i| offset + i + 2);
+
+    let prefix = &line[..column];
+    let token_start = prefix
+        .rfind(|c: char| c.is_whitespace() || c == '*')
+        .map_or(0, |i| i + 1);
+    let token = &prefix[token_start..];
+    let token_range = Range {
+        start: Position {
+            line: position.line,
+            character: prefix[..token_start].chars().count() as u32,
+        },
+        end: position,
+    };
+
+    let after_param = prefix[..token_start]
+        .trim_end()
+        .to_ascii_lowercase()
+        .ends_with("@param");
+    if !after_param {
+        if !token.is_empty() && !token.starts_with('@') {
+            return Some(Vec::new());
+        }
+        return Some(
+            DOC_TAGS
+                .iter()
+                .map(|&(tag, description)| CompletionItem {
+                    label: tag.to_string(),
+                    kind: Some(CompletionItemKind::KEYWORD),
+                    detail: Some(description.to_string()),
+                    filter_text: Some(tag.to_string()),
+                    text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                        range: token_range,
+                        new_text: format!("{tag} "),
+                    })),
+                    ..Default::default()
+                })
+                .collect(),
+        );
+    }
+
+    // The DEF must start on the line right after the comment closes
+    let Some(end) = comment_end else {
+        return Some(Vec::new());
+    };
+    let def_line = source[..end].matches('\n').count() as u32 + 1;
+    let Some(tree) = doc.tree.as_ref() else {
+        return Some(Vec::new());
+    };
+    let defs = extract::extract_definitions(tree, source);
+    let Some(def) = defs.iter().find(|d| d.range.start.line == def_line) else {
+        return Some(Vec::new());
+    };
+
+    // Names already documented, ignoring the line being typed
+    let first_line = source[..comment_start].matches('\n').count() as u32;
+    let documented: HashSet<String> = source[comment_start..end]
+        .lines()
+        .enumerate()
+        .filter(|&(i, _)| first_line + i as u32 != position.line)
+        .filter_map(|(_, l)| {
+            let rest = l.split_once("@param")?.1;
+            rest.split_whitespace().next().map(str::to_ascii_lowercase)
+        })
+        .collect();
+
+    Some(
+        def.params
+            .iter()
+            .filter(|p| !documented.contains(&p.name.to_ascii_lowercase()))
+            .map(|p| CompletionItem {
+                label: p.name.clone(),
+                kind: Some(CompletionItemKind::VARIABLE),
+                detail: Some(format!("parameter of {}", def.name)),
+                ..Default::default()
+            })
+            .collect(),
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -711,7 +1112,7 @@ mod tests {
 
     #[test]
     fn statement_completions_not_empty() {
-        let items = statement_completions();
+        let items = statement_completions(None);
         assert!(!items.is_empty());
         assert!(items
             .iter()
@@ -720,7 +1121,7 @@ mod tests {
 
     #[test]
     fn statement_completions_includes_known_entries() {
-        let items = statement_completions();
+        let items = statement_completions(None);
         let names: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
         assert!(names.contains(&"def"));
         assert!(names.contains(&"Print"));
@@ -730,8 +1131,12 @@ mod tests {
 
     #[test]
     fn statement_completions_count() {
-        let items = statement_completions();
-        assert_eq!(items.len(), STATEMENTS.len());
+        let items = statement_completions(None);
+        assert_eq!(items.len(), statements::STATEMENTS.len());
+
+        let older = statement_completions(builtins::BrVersion::parse("4.1"));
+        assert!(older.len() < items.len());
+        assert!(!older.iter().any(|i| i.label == "Scr_Freeze"));
     }
 
     #[test]
@@ -752,20 +1157,90 @@ mod tests {
 
     #[test]
     fn builtin_completions_count() {
-        let items = builtin_function_completions();
-        assert_eq!(items.len(), 115);
+        // One item per name; overloads share it
+        let items = builtin_function_completions(None);
+        assert_eq!(items.len(), 109);
         assert!(items
             .iter()
             .all(|i| i.kind == Some(CompletionItemKind::FUNCTION)));
     }
 
+    #[test]
+    fn builtin_completions_gated_by_version() {
+        let items = builtin_function_completions(builtins::BrVersion::parse("4.2"));
+        assert!(!items.iter().any(|i| i.label == "Encrypt$"));
+        assert!(items.iter().any(|i| i.label == "Str2Mat"));
+
+        // Overloads share one item, with indices that line up with builtins::lookup
+        let items = builtin_function_completions(builtins::BrVersion::parse("4.3"));
+        let decrypt: Vec<&CompletionItem> =
+            items.iter().filter(|i| i.label == "Decrypt$").collect();
+        assert_eq!(decrypt.len(), 1);
+        let data = serde_json::from_value(decrypt[0].data.clone().unwrap()).unwrap();
+        assert!(
+            matches!(data, CompletionData::Builtin { ref overloads, .. } if overloads == &[0, 1])
+        );
+        assert_eq!(
+            decrypt[0].detail.as_ref().unwrap().matches(" | ").count(),
+            1
+        );
+    }
+
     #[test]
     fn builtin_completions_detail() {
-        let items = builtin_function_completions();
+        let items = builtin_function_completions(None);
         let val = items.iter().find(|i| i.label == "Val").unwrap();
         assert!(val.detail.as_ref().unwrap().starts_with("(built-in)"));
     }
 
+    #[test]
+    fn functions_with_args_insert_parens() {
+        let items = builtin_function_completions(None);
+        let val = items.iter().find(|i| i.label == "Val").unwrap();
+        assert_eq!(val.insert_text.as_deref(), Some("Val($0)"));
+        assert_eq!(val.insert_text_format, Some(InsertTextFormat::SNIPPET));
+        assert_eq!(
+            val.command.as_ref().map(|c| c.command.as_str()),
+            Some(TRIGGER_PARAMETER_HINTS)
+        );
+        let bell = items.iter().find(|i| i.label == "Bell").unwrap();
+        assert!(bell.insert_text.is_none() && bell.command.is_none());
+    }
+
+    #[test]
+    fn insert_options_follow_settings_and_client() {
+        let mut items = builtin_function_completions(None);
+        items.extend(keyword_completions());
+        apply_insert_options(&mut items, &CompletionOptions::default(), true, true);
+        let val = items.iter().find(|i| i.label == "Val").unwrap();
+        assert!(val.command.is_some());
+        assert!(val.commit_characters.is_none());
+        let bell = items.iter().find(|i| i.label == "Bell").unwrap();
+        assert_eq!(bell.commit_characters, Some(vec!["(".to_string()]));
+        let keyword = items
+            .iter()
+            .find(|i| i.kind == Some(CompletionItemKind::KEYWORD))
+            .unwrap();
+        assert_eq!(keyword.commit_characters, Some(vec![" ".to_string()]));
+
+        // No snippet support: plain names, committed with `(`
+        let mut items = builtin_function_completions(None);
+        apply_insert_options(&mut items, &CompletionOptions::default(), false, true);
+        let val = items.iter().find(|i| i.label == "Val").unwrap();
+        assert!(val.insert_text.is_none() && val.command.is_none());
+        assert_eq!(val.commit_characters, Some(vec!["(".to_string()]));
+
+        let options = CompletionOptions::from_settings(Some(&serde_json::json!({
+            "autoParens": false,
+            "commitCharacters": false
+        })));
+        let mut items = builtin_function_completions(None);
+        apply_insert_options(&mut items, &options, true, true);
+        assert!(items
+            .iter()
+            .all(|i| i.command.is_none() && i.commit_characters.is_none()));
+    }
+
     #[test]
     fn local_variable_basics() {
         let source = "let X$ = \"hello\"\nlet Y = 42\nlet Z$ = X$\n";
@@ -775,7 +1250,7 @@ mod tests {
             line: 99,
             character: 0,
         };
-        let items = local_variable_completions(&tree, source, pos);
+        let items = local_variable_completions(&tree, source, pos, false);
         assert!(!items.is_empty());
         assert!(items
             .iter()
@@ -791,7 +1266,7 @@ mod tests {
             line: 99,
             character: 0,
         };
-        let items = local_variable_completions(&tree, source, pos);
+        let items = local_variable_completions(&tree, source, pos, false);
         let x_count = items
             .iter()
             .filter(|i| i.label.eq_ignore_ascii_case("X$"))
@@ -799,6 +1274,48 @@ mod tests {
         assert_eq!(x_count, 1, "X$ should appear exactly once");
     }
 
+    #[test]
+    fn local_variable_dim_detail() {
+        let source = "dim A$(10)*30, N$*20\nlet X = 1\nprint A$(1), N$, X\n";
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        let items = local_variable_completions(&tree, source, Position::new(99, 0), false);
+        let detail = |label: &str| {
+            items
+                .iter()
+                .find(|i| i.label == label)
+                .and_then(|i| i.detail.clone())
+                .unwrap()
+        };
+        assert_eq!(detail("A$"), "string array: DIM A$(10)*30");
+        assert_eq!(detail("N$"), "string: DIM N$*20");
+        assert_eq!(detail("X"), "number");
+    }
+
+    #[test]
+    fn arrays_insert_subscript_in_expressions() {
+        let source = "dim Names$(5), Totals(3)\nlet X = \nlet Y = T(1)\n";
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        assert!(subscript_context(&tree, source, Position::new(1, 8)));
+        assert!(!subscript_context(&tree, source, Position::new(0, 10)));
+        assert!(!subscript_context(&tree, source, Position::new(2, 9)));
+
+        let mut items = local_variable_completions(&tree, source, Position::new(1, 8), true);
+        let names = items.iter().find(|i| i.label == "Names$").unwrap();
+        assert_eq!(names.insert_text.as_deref(), Some("Names$($0)"));
+        let x = items.iter().find(|i| i.label == "X").unwrap();
+        assert!(x.insert_text.is_none());
+
+        apply_insert_options(&mut items, &CompletionOptions::default(), true, true);
+        let names = items.iter().find(|i| i.label == "Names$").unwrap();
+        assert!(names.commit_characters.is_none());
+        apply_insert_options(&mut items, &CompletionOptions::default(), false, true);
+        let names = items.iter().find(|i| i.label == "Names$").unwrap();
+        assert!(names.insert_text.is_none());
+        assert!(names.commit_characters.is_some());
+    }
+
     #[test]
     fn local_function_extraction() {
         let source = "def fnAdd(A, B) = A + B\ndef library fnCalc$(X$)\nfnend\n";
@@ -878,11 +1395,12 @@ mod tests {
         let source = "let X$ = \"hello\"\ndef fnFoo(A) = A\n";
         let mut p = parser::new_parser();
         let tree = parser::parse(&mut p, source, None);
-        let doc = DocumentState {
+        let doc = DocumentSnapshot {
             kind: crate::backend::DocumentKind::Br,
-            rope: ropey::Rope::from_str(source),
-            source: source.to_string(),
+            source: source.into(),
             tree,
+            version: 0,
+            client_version: 0,
         };
         let index = WorkspaceIndex::new();
         let layout_index = crate::layout::LayoutIndex::new();
@@ -890,14 +1408,276 @@ mod tests {
             line: 99,
             character: 0,
         };
-        let items = get_completions(&doc, "file:///test.brs", pos, &index, &layout_index);
+        let items = get_completions(
+            &doc,
+            "file:///test.brs",
+            pos,
+            &index,
+            &layout_index,
+            None,
+            &CompletionOptions::default(),
+            &[],
+        );
         // Should have statements + keywords + builtins + local vars + local fns
         assert!(items.len() > 100);
     }
 
+    fn completions_with(
+        source: &str,
+        position: Position,
+        options: &CompletionOptions,
+    ) -> Vec<CompletionItem> {
+        let mut p = parser::new_parser();
+        let doc = DocumentSnapshot {
+            kind: crate::backend::DocumentKind::Br,
+            source: source.into(),
+            tree: parser::parse(&mut p, source, None),
+            version: 0,
+            client_version: 0,
+        };
+        let index = WorkspaceIndex::new();
+        let layout_index = crate::layout::LayoutIndex::new();
+        get_completions(
+            &doc,
+            "file:///test.brs",
+            position,
+            &index,
+            &layout_index,
+            None,
+            options,
+            &[],
+        )
+    }
+
+    #[test]
+    fn completion_categories_can_be_turned_off() {
+        let options = CompletionOptions {
+            statements: false,
+            keywords: false,
+            builtins: false,
+            ..CompletionOptions::default()
+        };
+        let items = completions_with(
+            "let X$ = \"hello\"\ndef fnFoo(A) = A\n",
+            Position::new(99, 0),
+            &options,
+        );
+        let labels: Vec<String> = items.iter().map(|i| i.label.to_ascii_lowercase()).collect();
+        assert!(labels.contains(&"x$".to_string()));
+        assert!(labels.contains(&"fnfoo".to_string()));
+        assert!(!labels.contains(&"print".to_string()));
+        assert!(!labels.contains(&"val".to_string()));
+    }
+
+    #[test]
+    fn workspace_snippets_complete_where_statements_do() {
+        let snippets = crate::snippets::parse(
+            r#"{"Error handler": {"prefix": "onerr", "body": "on error goto ${1:Handler}"}}"#,
+        )
+        .unwrap();
+        let complete = |source: &str, position: Position| {
+            let mut p = parser::new_parser();
+            let doc = DocumentSnapshot {
+                kind: crate::backend::DocumentKind::Br,
+                source: source.into(),
+                tree: parser::parse(&mut p, source, None),
+                version: 0,
+                client_version: 0,
+            };
+            get_completions(
+                &doc,
+                "file:///test.brs",
+                position,
+                &WorkspaceIndex::new(),
+                &crate::layout::LayoutIndex::new(),
+                None,
+                &CompletionOptions::default(),
+                &snippets,
+            )
+        };
+
+        let mut items = complete("print 1\n", Position::new(1, 0));
+        let options = CompletionOptions {
+            auto_parens: false,
+            ..CompletionOptions::default()
+        };
+        apply_insert_options(&mut items, &options, true, true);
+        let onerr = items.iter().find(|i| i.label == "onerr").unwrap();
+        assert_eq!(onerr.kind, Some(CompletionItemKind::SNIPPET));
+        assert_eq!(
+            onerr.insert_text.as_deref(),
+            Some("on error goto ${1:Handler}")
+        );
+
+        let source = "open #1: \"name=x\", ";
+        let items = complete(source, Position::new(0, source.len() as u32));
+        assert!(!items.iter().any(|i| i.label == "onerr"));
+    }
+
+    #[test]
+    fn max_items_keeps_matches_for_the_word_being_typed() {
+        let source = "let Total = 1\nlet Tax = 2\nprint ta";
+        let position = Position::new(2, 8);
+        let mut items = completions_with(source, position, &CompletionOptions::default());
+        assert!(!limit_items(&mut items.clone(), source, position, 0));
+
+        assert!(limit_items(&mut items, source, position, 2));
+        assert_eq!(items.len(), 2);
+        assert!(items
+            .iter()
+            .all(|i| i.label.to_ascii_lowercase().starts_with("ta")));
+    }
+
+    fn mat_completions(
+        source: &str,
+        line: u32,
+        character: u32,
+        layout_index: &crate::layout::LayoutIndex,
+    ) -> Option<Vec<CompletionItem>> {
+        let mut p = parser::new_parser();
+        let doc = DocumentSnapshot {
+            kind: crate::backend::DocumentKind::Br,
+            source: source.into(),
+            tree: parser::parse(&mut p, source, None),
+            version: 0,
+            client_version: 0,
+        };
+        mat_array_completions(&doc, Position { line, character }, layout_index)
+    }
+
+    #[test]
+    fn mat_offers_only_arrays() {
+        let source = "dim Names$(5), Totals(3)\nlet X = 1\nmat \n";
+        let layouts = crate::layout::LayoutIndex::new();
+        let items = mat_completions(source, 2, 4, &layouts).unwrap();
+        let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(labels, vec!["Names$", "Totals"]);
+
+        // Only the partial name is replaced, so MAT isn't inserted again
+        let source = "dim Names$(5)\nMAT Na\n";
+        let items = mat_completions(source, 1, 6, &layouts).unwrap();
+        let Some(CompletionTextEdit::Edit(edit)) = &items[0].text_edit else {
+            panic!("expected text edit");
+        };
+        assert_eq!(edit.new_text, "Names$");
+        assert_eq!(edit.range.start, Position::new(1, 4));
+    }
+
+    #[test]
+    fn mat_includes_layout_arrays() {
+        let mut layouts = crate::layout::LayoutIndex::new();
+        layouts.add(
+            "file:///ws/filelay/customer",
+            crate::layout::Layout {
+                path: "data/customer.dat".to_string(),
+                prefix: "CU_".to_string(),
+                version: None,
+                keys: Vec::new(),
+                subscripts: Vec::new(),
+                record_length: None,
+            },
+        );
+        let items = mat_completions("mat \n", 0, 4, &layouts).unwrap();
+        let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(labels, vec!["customer$", "customer"]);
+    }
+
+    #[test]
+    fn mat_context_requires_keyword() {
+        let layouts = crate::layout::LayoutIndex::new();
+        assert!(mat_completions("print \n", 0, 6, &layouts).is_none());
+        assert!(mat_completions("format \n", 0, 7, &layouts).is_none());
+        assert!(mat_completions("mat\n", 0, 3, &layouts).is_none());
+    }
+
+    fn conditions_at(source: &str, character: u32) -> Option<(String, bool)> {
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        error_condition_context(&tree, source, Position { line: 0, character })
+    }
+
+    #[test]
+    fn error_conditions_after_comma() {
+        // Ending a condition clause: only conditions may follow
+        let source = "read #1,using 100: a$ nokey 300, \n";
+        assert_eq!(
+            conditions_at(source, 33),
+            Some(("read_statement".to_string(), true))
+        );
+        // After a data item: a condition or another item
+        let source = "read #1,using 100: a$, \n";
+        assert_eq!(
+            conditions_at(source, 23),
+            Some(("read_statement".to_string(), false))
+        );
+        // Before the colon the commas separate READ's own clauses
+        assert_eq!(conditions_at("read #1, \n", 9), None);
+        // Not an I/O statement
+        assert_eq!(conditions_at("let x=max(1, \n", 13), None);
+    }
+
+    fn context_at(source: &str, line: u32, character: u32) -> Option<StatementContext> {
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        statement_context(&tree, source, Position { line, character })
+    }
+
+    #[test]
+    fn open_clauses_after_colon() {
+        let source = "open #1: \"name=x\", \n";
+        assert_eq!(context_at(source, 0, 19), Some(StatementContext::Open));
+        let source = "open #1: \"name=x\", inte\n";
+        assert_eq!(context_at(source, 0, 23), Some(StatementContext::Open));
+        // Before the colon is the channel, not the clause list
+        assert_eq!(context_at("open #\n", 0, 6), None);
+        // Inside the file name string
+        assert_eq!(context_at("open #1: \"name=x\", \n", 0, 12), None);
+    }
+
+    #[test]
+    fn form_specs_and_print_keywords() {
+        assert_eq!(
+            context_at("form pos 1, \n", 0, 12),
+            Some(StatementContext::Form)
+        );
+        assert_eq!(context_at("print \n", 0, 6), Some(StatementContext::Print));
+        assert_eq!(context_at("print\n", 0, 5), None);
+        assert_eq!(context_at("let X = 1\n", 0, 8), None);
+    }
+
+    #[test]
+    fn open_context_replaces_full_list() {
+        let source = "open #1: \"name=x\", \n";
+        let mut p = parser::new_parser();
+        let doc = DocumentSnapshot {
+            kind: crate::backend::DocumentKind::Br,
+            source: source.into(),
+            tree: parser::parse(&mut p, source, None),
+            version: 0,
+            client_version: 0,
+        };
+        let index = WorkspaceIndex::new();
+        let layout_index = crate::layout::LayoutIndex::new();
+        let items = get_completions(
+            &doc,
+            "file:///test.brs",
+            Position::new(0, 19),
+            &index,
+            &layout_index,
+            None,
+            &CompletionOptions::default(),
+            &[],
+        );
+        let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            vec!["internal", "external", "input", "output", "outin", "relative", "keyed"]
+        );
+    }
+
     #[test]
     fn builtin_completions_no_docs() {
-        let items = builtin_function_completions();
+        let items = builtin_function_completions(None);
         assert!(
             items.iter().all(|i| i.documentation.is_none()),
             "builtin completions should defer docs to resolve"
@@ -906,7 +1686,7 @@ mod tests {
 
     #[test]
     fn builtin_completions_have_data() {
-        let items = builtin_function_completions();
+        let items = builtin_function_completions(None);
         let val = items.iter().find(|i| i.label == "Val").unwrap();
         let data: CompletionData = serde_json::from_value(val.data.clone().unwrap()).unwrap();
         assert!(matches!(data, CompletionData::Builtin { ref name, .. } if name == "Val"));
@@ -924,6 +1704,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn local_function_deprecated_tag() {
+        let source =
+            "/** @deprecated Use fnSum */\ndef fnAdd(A, B) = A + B\ndef fnSum(A, B) = A + B\n";
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        let items = local_function_completions(&tree, source, "file:///test.brs");
+        assert_eq!(items[0].tags, Some(vec![CompletionItemTag::DEPRECATED]));
+        assert_eq!(items[1].tags, None);
+    }
+
     #[test]
     fn library_dedup_by_name() {
         let mut index = WorkspaceIndex::new();
@@ -982,6 +1773,46 @@ mod tests {
             has_param_substitution: false,
             documentation: None,
             return_documentation: None,
+            deprecated: None,
         }
     }
+
+    fn doc_completions_at(source: &str, line: u32, character: u32) -> Option<Vec<String>> {
+        let mut p = parser::new_parser();
+        let doc = DocumentSnapshot {
+            kind: crate::backend::DocumentKind::Br,
+            source: source.into(),
+            tree: parser::parse(&mut p, source, None),
+            version: 0,
+            client_version: 0,
+        };
+        doc_comment_completions(&doc, Position { line, character })
+            .map(|items| items.into_iter().map(|i| i.label).collect())
+    }
+
+    #[test]
+    fn doc_comment_tags() {
+        let source = "/** Adds\n  * @\n  */\ndef fnAdd(A, B) = A + B\n";
+        let labels = doc_completions_at(source, 1, 5).unwrap();
+        assert_eq!(labels, vec!["@param", "@returns", "@deprecated"]);
+    }
+
+    #[test]
+    fn doc_comment_undocumented_params() {
+        let source = "/** Adds\n  * @param A first\n  * @param \n  */\ndef fnAdd(A, B$) = A\n";
+        let labels = doc_completions_at(source, 2, 11).unwrap();
+        assert_eq!(labels, vec!["B$"]);
+    }
+
+    #[test]
+    fn doc_comment_description_text_has_no_completions() {
+        let source = "/** Adds numb\n  */\ndef fnAdd(A, B) = A + B\n";
+        assert_eq!(doc_completions_at(source, 0, 13), Some(Vec::new()));
+    }
+
+    #[test]
+    fn outside_doc_comment_not_handled() {
+        let source = "/** Adds */\ndef fnAdd(A, B) = A + B\nlet X = 1\n";
+        assert!(doc_completions_at(source, 2, 4).is_none());
+    }
 }
@@ -0,0 +1,228 @@
+//! What a settings change means for the diagnostics already published.
+//! Most changes only switch a few rules on or off, and rerunning just
+//! those on each open file is much cheaper than a full republish, which
+//! reparses nothing but still runs every check, including the workspace
+//! ones, on every file.
+
+use crate::backend::DiagnosticsConfig;
+use crate::rules::{self, Rule};
+
+/// Whether a setting differs between two configurations.
+type Changed = fn(&DiagnosticsConfig, &DiagnosticsConfig) -> bool;
+
+/// Settings whose rule reruns whenever they change. The rule finds nothing
+/// while its setting is unset, so it can run either way.
+const SETTINGS: &[(Changed, &str)] = &[
+    (
+        |a, b| a.br_version != b.br_version,
+        "unavailable-in-version",
+    ),
+    (|a, b| a.keyword_case != b.keyword_case, "keyword-case"),
+    (|a, b| a.dialects != b.dialects, "wrong-dialect"),
+];
+
+/// The work a settings change calls for.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Rerun {
+    /// Nothing published depends on what changed
+    Nothing,
+    /// Drop the `removed` codes from each file's diagnostics and add what
+    /// the `added` rules find. With both empty, only muted files changed,
+    /// and the diagnostics are sent again as they are.
+    Rules {
+        removed: Vec<&'static str>,
+        added: Vec<Rule>,
+    },
+    /// Recompute every file's diagnostics
+    Everything,
+}
+
+/// What changing the settings from `old` to `new` needs rerunning.
+pub fn rerun_for(old: &DiagnosticsConfig, new: &DiagnosticsConfig) -> Rerun {
+    // Naming and metrics rules have no switch of their own, drive mappings
+    // change where every LIBRARY statement points, and generated files get
+    // a different set of rules
+    if old.naming != new.naming
+        || old.metrics != new.metrics
+        || old.drive_mappings != new.drive_mappings
+        || old.generated_files != new.generated_files
+    {
+        return Rerun::Everything;
+    }
+
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    for (rule, enabled) in rules::switched() {
+        match (enabled(old), enabled(new)) {
+            (true, false) => removed.push(rule.code),
+            (false, true) => added.push(rule),
+            _ => {}
+        }
+    }
+    for (changed, code) in SETTINGS {
+        if changed(old, new) {
+            removed.push(*code);
+            added.extend(Rule::parse(code));
+        }
+    }
+
+    if removed.is_empty() && added.is_empty() && old.muted_files == new.muted_files {
+        Rerun::Nothing
+    } else {
+        Rerun::Rules { removed, added }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::BrVersion;
+    use crate::generated::GeneratedFiles;
+    use crate::profiles::LintProfile;
+    use crate::style::{KeywordCase, NamingConfig};
+    use crate::workspace::{DriveMappings, MutedFiles};
+
+    fn codes(rules: &[Rule]) -> Vec<&'static str> {
+        rules.iter().map(|r| r.code).collect()
+    }
+
+    fn rerun(change: impl FnOnce(&mut DiagnosticsConfig)) -> Rerun {
+        let old = DiagnosticsConfig::default();
+        let mut new = old.clone();
+        change(&mut new);
+        rerun_for(&old, &new)
+    }
+
+    #[test]
+    fn unrelated_settings_need_nothing() {
+        assert_eq!(rerun(|_| {}), Rerun::Nothing);
+        assert_eq!(
+            rerun(|c| {
+                c.read_only_workspace = true;
+                c.include_string_references = true;
+                c.parse_health_thresholds.clear();
+            }),
+            Rerun::Nothing
+        );
+    }
+
+    #[test]
+    fn switching_a_rule_off_only_removes_its_codes() {
+        assert_eq!(
+            rerun(|c| c.unused_variables = false),
+            Rerun::Rules {
+                removed: vec!["unused-variable", "unused-import"],
+                added: Vec::new(),
+            }
+        );
+        assert_eq!(
+            rerun(|c| c.line_numbers = false),
+            Rerun::Rules {
+                removed: vec!["duplicate-line-number", "line-number-order"],
+                added: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn switching_a_rule_on_only_runs_it() {
+        let old = DiagnosticsConfig {
+            unused_parameters: false,
+            shadowing: false,
+            ..Default::default()
+        };
+        let Rerun::Rules { removed, added } = rerun_for(&old, &DiagnosticsConfig::default()) else {
+            panic!("expected rules to rerun");
+        };
+        assert!(removed.is_empty());
+        assert_eq!(
+            codes(&added),
+            vec!["unused-parameter", "builtin-shadowing", "library-shadowing"]
+        );
+    }
+
+    #[test]
+    fn missing_fnend_stays_while_either_switch_is_on() {
+        let legacy = DiagnosticsConfig {
+            functions: false,
+            missing_fnend: true,
+            ..Default::default()
+        };
+        let Rerun::Rules { removed, added } = rerun_for(&legacy, &DiagnosticsConfig::default())
+        else {
+            panic!("expected rules to rerun");
+        };
+        assert!(removed.is_empty());
+        assert!(!codes(&added).contains(&"missing-fnend"));
+        assert!(codes(&added).contains(&"duplicate-function"));
+
+        let Rerun::Rules { removed, .. } = rerun(|c| c.functions = false) else {
+            panic!("expected rules to rerun");
+        };
+        assert!(removed.contains(&"missing-fnend"));
+        assert!(removed.contains(&"library-missing-function"));
+    }
+
+    #[test]
+    fn changed_settings_rerun_their_rule() {
+        assert_eq!(
+            rerun(|c| c.keyword_case = Some(KeywordCase::Upper)),
+            Rerun::Rules {
+                removed: vec!["keyword-case"],
+                added: vec![Rule::parse("keyword-case").unwrap()],
+            }
+        );
+        let Rerun::Rules { removed, added } = rerun(|c| c.br_version = BrVersion::parse("4.2"))
+        else {
+            panic!("expected rules to rerun");
+        };
+        assert_eq!(removed, vec!["unavailable-in-version"]);
+        assert_eq!(codes(&added), vec!["unavailable-in-version"]);
+    }
+
+    #[test]
+    fn muting_files_republishes_without_running_rules() {
+        let muted = serde_json::json!(["vendor/**"]);
+        assert_eq!(
+            rerun(|c| c.muted_files = MutedFiles::from_settings(Some(&muted))),
+            Rerun::Rules {
+                removed: Vec::new(),
+                added: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn naming_metrics_mappings_and_generated_files_rerun_everything() {
+        let naming = serde_json::json!({ "functions": { "enabled": true } });
+        assert_eq!(
+            rerun(|c| c.naming = NamingConfig::from_settings(Some(&naming))),
+            Rerun::Everything
+        );
+        assert_eq!(rerun(|c| c.metrics.max_lines = 50), Rerun::Everything);
+        let mappings = serde_json::json!({ "VOL002": "lib" });
+        assert_eq!(
+            rerun(|c| c.drive_mappings = DriveMappings::from_settings(Some(&mappings))),
+            Rerun::Everything
+        );
+        let generated = serde_json::json!(["gen/**"]);
+        assert_eq!(
+            rerun(|c| c.generated_files = GeneratedFiles::from_settings(Some(&generated))),
+            Rerun::Everything
+        );
+    }
+
+    #[test]
+    fn profiles_switch_rules_together() {
+        let mut legacy = DiagnosticsConfig::default();
+        LintProfile::Legacy.apply(&mut legacy);
+        let Rerun::Rules { removed, added } = rerun_for(&DiagnosticsConfig::default(), &legacy)
+        else {
+            panic!("expected rules to rerun");
+        };
+        assert!(added.is_empty());
+        assert!(removed.contains(&"unused-variable"));
+        assert!(!removed.contains(&"syntax-error"));
+        assert!(!removed.contains(&"missing-fnend"));
+    }
+}
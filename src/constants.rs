@@ -0,0 +1,333 @@
+use tower_lsp::lsp_types::Range;
+use tree_sitter::{Node, Tree};
+
+use crate::parser;
+use crate::workspace;
+
+/// Names for the error codes programs most often test `ERR` against. Not
+/// exhaustive; codes missing here get no hover.
+const ERROR_CODES: &[(u32, &str)] = &[
+    (54, "End of file"),
+    (61, "Record locked by another user"),
+    (4148, "File is in use (sharing violation)"),
+    (4152, "File not found"),
+    (4270, "Key not found"),
+];
+
+/// Control characters worth naming in a `CHR$` hover.
+const CONTROL_NAMES: &[(u32, &str)] = &[
+    (0, "NUL"),
+    (7, "BEL (bell)"),
+    (8, "BS (backspace)"),
+    (9, "TAB"),
+    (10, "LF (line feed)"),
+    (12, "FF (form feed)"),
+    (13, "CR (carriage return)"),
+    (27, "ESC (escape)"),
+    (127, "DEL"),
+];
+
+/// Day number of 1970-01-01 in BR's count, where 1900-01-01 is day 1.
+pub(crate) const UNIX_EPOCH_DAY: i64 = 25568;
+
+/// Markdown describing the numeric literal at a position, when where it
+/// appears gives it a meaning beyond its value: an error code compared with
+/// `ERR`, a character code passed to `CHR$`, a day number passed to
+/// `DATE`/`DATE$`, or a date passed to `DAYS` with a literal format.
+pub fn describe_number_at(
+    tree: &Tree,
+    source: &str,
+    line: usize,
+    character: usize,
+) -> Option<(String, Range)> {
+    let node = parser::node_at_position(tree, line, character)?;
+    if node.kind() != "number" {
+        return None;
+    }
+    let text = node.utf8_text(source.as_bytes()).ok()?;
+    let value: u32 = text.parse().ok()?;
+
+    let markdown = if let Some((function, index, arguments)) = call_argument(node, source) {
+        match (function.as_str(), index) {
+            ("chr$", 0) => describe_char(value)?,
+            ("date$" | "date", 0) => {
+                let (y, m, d) = date_from_day(value as i64)?;
+                format!("Day {value} \u{2014} {y:04}-{m:02}-{d:02}")
+            }
+            ("days", 0) => {
+                let format = arguments
+                    .named_child(1)
+                    .and_then(|arg| crate::extract::extract_string_literal(arg, source))?;
+                let (y, m, d) = parse_date(text, &format)?;
+                let day = day_from_date(y, m, d)?;
+                format!("{y:04}-{m:02}-{d:02} \u{2014} day {day}")
+            }
+            _ => return None,
+        }
+    } else if compared_with_err(node, source) {
+        let name = error_name(value)?;
+        format!("**Error {value:04}** \u{2014} {name}")
+    } else {
+        return None;
+    };
+    Some((markdown, parser::node_range(node)))
+}
+
+/// Name of a BR error code, if known.
+pub fn error_name(code: u32) -> Option<&'static str> {
+    ERROR_CODES
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, name)| *name)
+}
+
+fn describe_char(code: u32) -> Option<String> {
+    let byte = u8::try_from(code).ok()?;
+    let shown = match CONTROL_NAMES.iter().find(|(c, _)| *c == code) {
+        Some((_, name)) => (*name).to_string(),
+        None if byte < 32 => "control character".to_string(),
+        None => format!("`{}`", workspace::cp437_to_char(byte)),
+    };
+    Some(format!("`CHR$({code})` \u{2014} {shown}"))
+}
+
+/// For a number that is a whole argument of a system function call: the
+/// lowercase function name, the argument's index and the `arguments` node.
+fn call_argument<'t>(node: Node<'t>, source: &str) -> Option<(String, usize, Node<'t>)> {
+    let mut current = node;
+    loop {
+        let parent = current.parent()?;
+        match parent.kind() {
+            "numeric_primary_expression" | "numeric_expression" | "expression" => {
+                current = parent;
+            }
+            "argument" => {
+                current = parent;
+                break;
+            }
+            _ => return None,
+        }
+    }
+    let arguments = current.parent()?;
+    if arguments.kind() != "arguments" {
+        return None;
+    }
+    let function = arguments.parent()?;
+    if !matches!(
+        function.kind(),
+        "numeric_system_function" | "string_system_function"
+    ) {
+        return None;
+    }
+    let name = function
+        .children(&mut function.walk())
+        .find(|c| c.kind() == "function_name")?
+        .utf8_text(source.as_bytes())
+        .ok()?
+        .to_ascii_lowercase();
+    let mut cursor = arguments.walk();
+    let index = arguments
+        .named_children(&mut cursor)
+        .position(|arg| arg.id() == current.id())?;
+    Some((name, index, arguments))
+}
+
+/// Whether the number is one side of a comparison whose other side is `ERR`.
+fn compared_with_err(node: Node, source: &str) -> bool {
+    let Some(side) = node.parent() else {
+        return false;
+    };
+    let Some(side) = side
+        .parent()
+        .filter(|p| p.kind() == "conditional_expression")
+    else {
+        return false;
+    };
+    let Some(comparison) = side
+        .parent()
+        .filter(|p| p.kind() == "conditional_binary_expression")
+    else {
+        return false;
+    };
+    let mut cursor = comparison.walk();
+    let found = comparison
+        .named_children(&mut cursor)
+        .filter(|c| c.kind() == "conditional_expression" && c.id() != side.id())
+        .any(|other| is_err_call(other, source));
+    found
+}
+
+fn is_err_call(expr: Node, source: &str) -> bool {
+    let mut node = expr;
+    while node.named_child_count() == 1 {
+        node = node.named_child(0).unwrap();
+        if node.kind() == "numeric_system_function" {
+            return node
+                .children(&mut node.walk())
+                .find(|c| c.kind() == "function_name")
+                .and_then(|n| n.utf8_text(source.as_bytes()).ok())
+                .is_some_and(|name| name.eq_ignore_ascii_case("err"));
+        }
+    }
+    false
+}
+
+/// Split a date written as digits according to a BR date format such as
+/// `"mdy"` or `"ccyymmdd"`. A single letter stands for two digits, a run of
+/// letters for one digit each. Without a century, two-digit years below 50
+/// are taken as 20xx.
+fn parse_date(digits: &str, format: &str) -> Option<(i64, u32, u32)> {
+    let format = format.to_ascii_lowercase();
+    let mut fields: Vec<(char, usize)> = Vec::new();
+    for c in format.chars() {
+        if !matches!(c, 'c' | 'y' | 'm' | 'd') {
+            return None;
+        }
+        match fields.last_mut() {
+            Some((last, run)) if *last == c => *run += 1,
+            _ => fields.push((c, 1)),
+        }
+    }
+    let width: usize = fields.iter().map(|(_, run)| (*run).max(2)).sum();
+    if fields.is_empty() || digits.len() > width {
+        return None;
+    }
+    let padded = format!("{digits:0>width$}");
+
+    let (mut century, mut year, mut month, mut day) = (None, None, None, None);
+    let mut offset = 0;
+    for (field, run) in fields {
+        let len = run.max(2);
+        let value: u32 = padded[offset..offset + len].parse().ok()?;
+        offset += len;
+        match field {
+            'c' => century = Some(value),
+            'y' => year = Some((value, len)),
+            'm' => month = Some(value),
+            _ => day = Some(value),
+        }
+    }
+    let (year, year_len) = year?;
+    let year = year as i64;
+    let year = match century {
+        Some(c) => c as i64 * 100 + year,
+        None if year_len >= 4 => year,
+        None if year < 50 => 2000 + year,
+        None => 1900 + year,
+    };
+    let (month, day) = (month?, day?);
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        _ => return None,
+    };
+    (1..=days_in_month)
+        .contains(&day)
+        .then_some((year, month, day))
+}
+
+/// Calendar date of a BR day number (1900-01-01 is day 1).
+pub(crate) fn date_from_day(day: i64) -> Option<(i64, u32, u32)> {
+    if day < 1 {
+        return None;
+    }
+    // Days-to-civil conversion, counting from 1970-01-01
+    let z = day - UNIX_EPOCH_DAY + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    Some((y, m, d))
+}
+
+/// BR day number of a calendar date, the inverse of [`date_from_day`].
+fn day_from_date(year: i64, month: u32, day: u32) -> Option<i64> {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let result = era * 146_097 + doe - 719_468 + UNIX_EPOCH_DAY;
+    (result >= 1).then_some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hover_at(source: &str, needle: &str) -> Option<String> {
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        let (line, text) = source
+            .lines()
+            .enumerate()
+            .find(|(_, l)| l.contains(needle))
+            .unwrap();
+        let col = text.find(needle).unwrap();
+        describe_number_at(&tree, source, line, col).map(|(md, _)| md)
+    }
+
+    #[test]
+    fn error_codes_compared_with_err() {
+        let source = "00100 if err=4152 then goto 300\n00200 if 4270=err then goto 300\n00300 if x=4152 then goto 100\n";
+        assert_eq!(
+            hover_at(source, "4152").as_deref(),
+            Some("**Error 4152** \u{2014} File not found")
+        );
+        assert_eq!(
+            hover_at(source, "4270").as_deref(),
+            Some("**Error 4270** \u{2014} Key not found")
+        );
+        assert!(hover_at("00300 if x=4152 then goto 100\n", "4152").is_none());
+    }
+
+    #[test]
+    fn char_codes() {
+        assert_eq!(
+            hover_at("00100 let x$=chr$(27)\n", "27").as_deref(),
+            Some("`CHR$(27)` \u{2014} ESC (escape)")
+        );
+        assert_eq!(
+            hover_at("00100 let x$=chr$(65)\n", "65").as_deref(),
+            Some("`CHR$(65)` \u{2014} `A`")
+        );
+    }
+
+    #[test]
+    fn day_numbers_and_dates() {
+        assert_eq!(
+            hover_at("00100 print date$(1)\n", "1)").as_deref(),
+            Some("Day 1 \u{2014} 1900-01-01")
+        );
+        assert_eq!(
+            hover_at("00100 let d=days(20240115,\"ccyymmdd\")\n", "2024").as_deref(),
+            Some("2024-01-15 \u{2014} day 45305")
+        );
+        assert_eq!(
+            hover_at("00100 let d=days(011524,\"mdy\")\n", "0115").as_deref(),
+            Some("2024-01-15 \u{2014} day 45305")
+        );
+        assert_eq!(
+            hover_at("00100 let d=days(20240115,\"yyyymmdd\")\n", "2024").as_deref(),
+            Some("2024-01-15 \u{2014} day 45305")
+        );
+        assert!(hover_at("00100 let d=days(20240115)\n", "2024").is_none());
+        assert!(hover_at("00100 let d=days(20241345,\"ccyymmdd\")\n", "2024").is_none());
+    }
+
+    #[test]
+    fn day_conversion_round_trips() {
+        for day in [1, 59, 60, 61, 25568, 45305, 73000] {
+            let (y, m, d) = date_from_day(day).unwrap();
+            assert_eq!(day_from_date(y, m, d), Some(day));
+        }
+        assert_eq!(date_from_day(25568), Some((1970, 1, 1)));
+    }
+}
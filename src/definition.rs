@@ -1,12 +1,16 @@
-use tower_lsp::lsp_types::Range;
+use tower_lsp::lsp_types::{Position, Range};
 use tree_sitter::Tree;
 
+use crate::files;
 use crate::parser::{node_at_position, run_query};
 use crate::references::{
     escape_for_query, find_matching_identifier_range, get_function_ranges, in_function,
 };
+use crate::structure::Structure;
+use crate::symbols::{self, DeclarationKind, VariableDeclaration};
 
 const SUPPORTED_KINDS: &[&str] = &[
+    "error_condition",
     "function_name",
     "label_reference",
     "line_reference",
@@ -26,6 +30,15 @@ pub fn find_definition(
     line: usize,
     character: usize,
 ) -> DefinitionResult {
+    // `#n` file numbers jump to the OPEN statement for that number
+    let position = Position::new(line as u32, character as u32);
+    if files::is_file_number_at(tree, position) {
+        let usage = files::collect_file_usage(tree, source);
+        if let Some(open) = usage.reference_at(position).and_then(|r| usage.open_for(r)) {
+            return DefinitionResult::Found(open.range);
+        }
+    }
+
     let mut node = match node_at_position(tree, line, character) {
         Some(n) => n,
         None => return DefinitionResult::None,
@@ -53,6 +66,21 @@ pub fn find_definition(
             let name = node.utf8_text(source.as_bytes()).unwrap_or("");
             find_function_def(tree, source, name)
         }
+        "error_condition" => {
+            // `NOKEY 300` — the keyword jumps to where its target does
+            let Some(target) = node
+                .next_named_sibling()
+                .filter(|n| matches!(n.kind(), "label_reference" | "line_reference"))
+            else {
+                return DefinitionResult::None;
+            };
+            let text = target.utf8_text(source.as_bytes()).unwrap_or("");
+            if target.kind() == "label_reference" {
+                find_label_def(tree, source, text)
+            } else {
+                find_line_def(tree, source, text)
+            }
+        }
         "label_reference" => {
             let name = node.utf8_text(source.as_bytes()).unwrap_or("");
             find_label_def(tree, source, name)
@@ -62,12 +90,9 @@ pub fn find_definition(
             find_line_def(tree, source, text)
         }
         "stringidentifier" | "numberidentifier" => {
-            let name = node.utf8_text(source.as_bytes()).unwrap_or("");
-            let result = find_param_def(node, tree, source);
-            if matches!(result, DefinitionResult::None) {
-                find_dim_def(tree, source, name)
-            } else {
-                result
+            match find_variable_declaration(node, tree, source) {
+                Some(declaration) => DefinitionResult::Found(declaration.range),
+                None => DefinitionResult::None,
             }
         }
         _ => DefinitionResult::None,
@@ -164,24 +189,49 @@ fn find_param_def(node: tree_sitter::Node, tree: &Tree, source: &str) -> Definit
     DefinitionResult::None
 }
 
-fn find_dim_def(tree: &Tree, source: &str, name: &str) -> DefinitionResult {
-    let escaped = escape_for_query(name);
-    let query = format!(
-        concat!(
-            "(dim_statement [(stringreference name: (_) @name)",
-            " (numberreference name: (_) @name)",
-            " (stringarray name: (_) @name)",
-            " (numberarray name: (_) @name)]",
-            " (#match? @name \"^{escaped}$\"))",
-        ),
-        escaped = escaped,
-    );
-    let results = run_query(&query, tree.root_node(), source);
-    if let Some(r) = results.first() {
-        DefinitionResult::Found(r.range)
-    } else {
-        DefinitionResult::None
+/// Where the variable `node` names is declared: the parameter of the
+/// function it's used in, its DIM, or its first assignment.
+pub fn find_variable_declaration(
+    node: tree_sitter::Node,
+    tree: &Tree,
+    source: &str,
+) -> Option<VariableDeclaration> {
+    if let DefinitionResult::Found(range) = find_param_def(node, tree, source) {
+        return Some(VariableDeclaration {
+            kind: DeclarationKind::Parameter,
+            range,
+            dim: None,
+        });
     }
+    let name = node.utf8_text(source.as_bytes()).ok()?;
+    symbols::variable_declaration(tree, source, name)
+}
+
+/// Extend a range starting on a multi-line function's DEF statement through
+/// its FNEND. Other ranges, one-line functions and a DEF missing its FNEND
+/// are returned unchanged.
+pub fn function_extent(tree: &Tree, source: &str, range: Range) -> Range {
+    Structure::of(tree, source)
+        .functions
+        .iter()
+        .find(|f| f.def.start_position().row == range.start.line as usize)
+        .filter(|f| f.end.is_some())
+        .map_or(range, |f| Range::new(range.start, f.range().end))
+}
+
+/// The lines `range` covers plus `context` lines either side, and the
+/// number of the first one, for showing a definition without opening its
+/// file.
+pub fn peek_lines(source: &str, range: Range, context: u32) -> (u32, String) {
+    let first = range.start.line.saturating_sub(context);
+    let last = range.end.line.saturating_add(context);
+    let text = source
+        .lines()
+        .skip(first as usize)
+        .take((last - first) as usize + 1)
+        .collect::<Vec<_>>()
+        .join("\n");
+    (first, text)
 }
 
 #[cfg(test)]
@@ -260,6 +310,35 @@ mod tests {
         }
     }
 
+    #[test]
+    fn error_condition_goes_to_target() {
+        let source = "00100 read #1: a$ nokey 300 eof Done\n00300 stop\nDone: end\n";
+        let line0 = source.lines().next().unwrap();
+        for (needle, expected_line) in [("nokey", 1), ("eof", 2)] {
+            let col = line0.find(needle).unwrap();
+            match parse_and_find(source, 0, col) {
+                DefinitionResult::Found(range) => {
+                    assert_eq!(range.start.line, expected_line);
+                }
+                _ => panic!("Expected Found for {needle}"),
+            }
+        }
+    }
+
+    #[test]
+    fn file_number_goes_to_open() {
+        let source = "open #1: \"name=x\", internal, input\nread #1: X\nclose #1:\n";
+        for line in [1, 2] {
+            let col = source.lines().nth(line).unwrap().find("#1").unwrap() + 1;
+            match parse_and_find(source, line, col) {
+                DefinitionResult::Found(range) => {
+                    assert_eq!(range.start.line, 0);
+                }
+                _ => panic!("Expected Found"),
+            }
+        }
+    }
+
     #[test]
     fn dim_variable_def() {
         let source = "dim X$*30\nprint X$\n";
@@ -272,6 +351,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn undimensioned_variable_jumps_to_first_assignment() {
+        let source = "print X\nlet X = 1\nX = 2\n";
+        match parse_and_find(source, 0, 6) {
+            DefinitionResult::Found(range) => assert_eq!(range.start, Position::new(1, 4)),
+            _ => panic!("Expected Found"),
+        }
+    }
+
     #[test]
     fn system_function_returns_none() {
         let source = "let x = Str$(42)\n";
@@ -346,9 +434,44 @@ let Z = X + 2
 ";
         // Cursor on X outside the function (line 4)
         let col = source.lines().nth(4).unwrap().find('X').unwrap();
+        // Not the parameter: the first assignment, outside the function
         match parse_and_find(source, 4, col) {
-            DefinitionResult::None => {}
-            _ => panic!("Expected None for non-param variable outside function"),
+            DefinitionResult::Found(range) => assert_eq!(range.start.line, 0),
+            _ => panic!("Expected the first assignment for non-param variable"),
         }
     }
+
+    #[test]
+    fn function_extent_reaches_fnend() {
+        let source = "def fnA\n  let fnA = 1\nfnend\ndef fnB = 2\ndef fnC\nprint 1\n";
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        let line = |n| Range::new(Position::new(n, 0), Position::new(n, 7));
+
+        let a = function_extent(&tree, source, line(0));
+        assert_eq!(a.start, Position::new(0, 0));
+        assert_eq!(a.end, Position::new(2, 5));
+        // One-line function, and a DEF with no FNEND before the end
+        assert_eq!(function_extent(&tree, source, line(3)), line(3));
+        assert_eq!(function_extent(&tree, source, line(4)), line(4));
+        assert_eq!(function_extent(&tree, source, line(5)), line(5));
+    }
+
+    #[test]
+    fn peek_lines_add_context_within_the_file() {
+        let source = "print 1\ndef fnA\n  let fnA = 1\nfnend\nprint 2\n";
+        let range = Range::new(Position::new(1, 0), Position::new(3, 5));
+        assert_eq!(
+            peek_lines(source, range, 1),
+            (
+                0,
+                "print 1\ndef fnA\n  let fnA = 1\nfnend\nprint 2".to_string()
+            )
+        );
+        assert_eq!(
+            peek_lines(source, range, 0),
+            (1, "def fnA\n  let fnA = 1\nfnend".to_string())
+        );
+        assert_eq!(peek_lines(source, range, 10).0, 0);
+    }
 }
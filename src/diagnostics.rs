@@ -1,24 +1,91 @@
 use std::collections::{HashMap, HashSet};
+use std::ops::Range as ByteRange;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, DiagnosticTag, Location,
+    NumberOrString, Position, Range, TextEdit, Url,
+};
+use tree_sitter::{InputEdit, Node, Tree};
+
+use crate::error_conditions::{self, DefinedTargets, TargetKind};
+use crate::layout::LayoutIndex;
+use crate::structure::Structure;
+use crate::workspace::WorkspaceIndex;
+use crate::{builtins, extract, extract::ParamKind, files, parser, pic, statements};
 
-use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, DiagnosticTag, NumberOrString};
-use tree_sitter::Node;
+/// `source` of every diagnostic the server publishes.
+pub const SOURCE: &str = "br-lsp";
 
-use crate::workspace::WorkspaceIndex;
-use crate::{builtins, extract, extract::ParamKind, parser};
+/// Mark diagnostics as coming from this server, leaving any source a check
+/// set itself.
+pub fn stamp_source(diagnostics: &mut [Diagnostic]) {
+    for diagnostic in diagnostics {
+        diagnostic.source.get_or_insert_with(|| SOURCE.to_string());
+    }
+}
+
+/// Related information pointing at `range` in `uri`, e.g. the definition a
+/// diagnostic refers to.
+pub(crate) fn related(
+    uri: &Url,
+    range: Range,
+    message: String,
+) -> Vec<DiagnosticRelatedInformation> {
+    vec![DiagnosticRelatedInformation {
+        location: Location {
+            uri: uri.clone(),
+            range,
+        },
+        message,
+    }]
+}
+
+/// A quickfix carried in `Diagnostic::data` as `{"fix": ...}`, so code
+/// actions and external tools can apply it without re-running the check.
+/// The edits apply to the diagnostic's own document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fix {
+    pub title: String,
+    pub edits: Vec<TextEdit>,
+}
+
+impl Fix {
+    pub fn attach(self, diagnostic: &mut Diagnostic) {
+        diagnostic.data = Some(serde_json::json!({ "fix": self }));
+    }
+
+    pub fn from_diagnostic(diagnostic: &Diagnostic) -> Option<Self> {
+        let fix = diagnostic.data.as_ref()?.get("fix")?;
+        serde_json::from_value(fix.clone()).ok()
+    }
+}
 
 pub fn collect_function_diagnostics(
     nodes: &parser::DiagnosticNodes,
+    structure: &Structure,
     source: &str,
     defs: &[extract::FunctionDef],
+    uri: &Url,
 ) -> Vec<Diagnostic> {
-    let mut diagnostics = check_missing_fnend(
+    let mut diagnostics = check_missing_fnend(structure, source);
+    diagnostics.extend(check_duplicate_functions(
+        &nodes.def_statements,
+        source,
+        uri,
+    ));
+    diagnostics.extend(check_parameter_count(
+        &nodes.function_calls,
+        source,
+        defs,
+        uri,
+    ));
+    diagnostics.extend(check_doc_comment_params(
         &nodes.def_statements,
-        &nodes.fnend_statements,
-        &nodes.end_def_statements,
         source,
-    );
-    diagnostics.extend(check_duplicate_functions(&nodes.def_statements, source));
-    diagnostics.extend(check_parameter_count(&nodes.function_calls, source, defs));
+        defs,
+    ));
     diagnostics
 }
 
@@ -73,8 +140,76 @@ pub fn check_undefined_functions(
     diagnostics
 }
 
+/// Strike through calls to functions marked `@deprecated`. A definition in
+/// this file (`uri`) takes precedence over the workspace index.
+pub fn check_deprecated_calls(
+    call_nodes: &[Node],
+    source: &str,
+    defs: &[extract::FunctionDef],
+    index: Option<&WorkspaceIndex>,
+    uri: &Url,
+) -> Vec<Diagnostic> {
+    let bytes = source.as_bytes();
+    let mut diagnostics = Vec::new();
+
+    for &call_node in call_nodes {
+        let kind = call_node.kind();
+        if kind != "numeric_user_function" && kind != "string_user_function" {
+            continue;
+        }
+        let Some(name_node) = call_node
+            .children(&mut call_node.walk())
+            .find(|c| c.kind() == "function_name")
+        else {
+            continue;
+        };
+        let Ok(fn_name) = name_node.utf8_text(bytes) else {
+            continue;
+        };
+
+        let local = defs
+            .iter()
+            .find(|d| !d.is_import_only && d.name.eq_ignore_ascii_case(fn_name))
+            .map(|d| (uri, d));
+        let def = local.or_else(|| {
+            index?
+                .lookup(fn_name)
+                .iter()
+                .find(|d| !d.def.is_import_only)
+                .map(|d| (&d.uri, &d.def))
+        });
+        let Some((def_uri, def)) = def else {
+            continue;
+        };
+        let Some(reason) = def.deprecated.as_deref() else {
+            continue;
+        };
+
+        let message = if reason.is_empty() {
+            format!("'{fn_name}' is deprecated")
+        } else {
+            format!("'{fn_name}' is deprecated: {reason}")
+        };
+        diagnostics.push(Diagnostic {
+            range: parser::node_range(name_node),
+            severity: Some(DiagnosticSeverity::HINT),
+            code: Some(NumberOrString::String("deprecated-function".to_string())),
+            tags: Some(vec![DiagnosticTag::DEPRECATED]),
+            message,
+            related_information: Some(related(
+                def_uri,
+                def.selection_range,
+                format!("'{}' is defined here", def.name),
+            )),
+            ..Default::default()
+        });
+    }
+
+    diagnostics
+}
+
 /// Extract the `function_name` child node from a `def_statement` node.
-fn function_name_node(def_node: Node) -> Option<Node> {
+pub(crate) fn function_name_node(def_node: Node) -> Option<Node> {
     let mut cursor = def_node.walk();
     for child in def_node.children(&mut cursor) {
         let kind = child.kind();
@@ -92,7 +227,7 @@ fn function_name_node(def_node: Node) -> Option<Node> {
 
 /// Check whether a `def_statement` is an inline function (has `assignment_op`
 /// in its function definition child, e.g. `DEF fnFoo(X)=X*2`).
-fn is_inline_def(def_node: Node) -> bool {
+pub(crate) fn is_inline_def(def_node: Node) -> bool {
     let mut cursor = def_node.walk();
     for child in def_node.children(&mut cursor) {
         let kind = child.kind();
@@ -108,85 +243,214 @@ fn is_inline_def(def_node: Node) -> bool {
     false
 }
 
-fn check_missing_fnend(
-    def_nodes: &[Node],
-    fnend_nodes: &[Node],
-    enddef_nodes: &[Node],
+/// Flag system function calls that don't exist in the configured BR version.
+/// Overloads are considered together: a call is only flagged when no
+/// overload of the name is available.
+pub fn check_builtin_versions(
+    call_nodes: &[Node],
     source: &str,
+    version: builtins::BrVersion,
 ) -> Vec<Diagnostic> {
-    enum Entry {
-        Def {
-            range: tower_lsp::lsp_types::Range,
-            name: String,
-        },
-        Close,
-    }
+    let bytes = source.as_bytes();
+    let mut diagnostics = Vec::new();
+
+    for &call_node in call_nodes {
+        let kind = call_node.kind();
+        if kind != "numeric_system_function" && kind != "string_system_function" {
+            continue;
+        }
 
-    let mut entries: Vec<(usize, Entry)> = Vec::new();
+        let name_node = match call_node
+            .children(&mut call_node.walk())
+            .find(|c| c.kind() == "function_name")
+        {
+            Some(n) => n,
+            None => continue,
+        };
+        let fn_name = match name_node.utf8_text(bytes) {
+            Ok(s) => s,
+            Err(_) => continue,
+        };
 
-    for &node in def_nodes {
-        if is_inline_def(node) {
-            entries.push((node.start_byte(), Entry::Close));
+        let overloads = builtins::lookup(fn_name);
+        if overloads.is_empty() || overloads.iter().any(|f| f.available_in(Some(version))) {
             continue;
         }
-        let name = function_name_node(node)
-            .and_then(|n| n.utf8_text(source.as_bytes()).ok())
-            .unwrap_or("")
-            .to_string();
-        entries.push((
-            node.start_byte(),
-            Entry::Def {
-                range: parser::node_range(node),
-                name,
-            },
-        ));
+        let since = overloads
+            .iter()
+            .filter_map(|f| f.since_version())
+            .min()
+            .map(|v| format!(" (requires {v})"))
+            .unwrap_or_default();
+
+        diagnostics.push(Diagnostic {
+            range: parser::node_range(name_node),
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String("unavailable-in-version".to_string())),
+            message: format!("'{fn_name}' is not available in BR {version}{since}"),
+            ..Default::default()
+        });
     }
 
-    for &node in fnend_nodes {
-        entries.push((node.start_byte(), Entry::Close));
+    diagnostics
+}
+
+/// Statements newer than `version`, flagged like builtins with the
+/// `unavailable-in-version` code.
+pub fn check_statement_versions(
+    tree: &Tree,
+    source: &str,
+    version: builtins::BrVersion,
+) -> Vec<Diagnostic> {
+    if statements::STATEMENTS
+        .iter()
+        .all(|s| s.available_in(Some(version)))
+    {
+        return Vec::new();
     }
 
-    for &node in enddef_nodes {
-        entries.push((node.start_byte(), Entry::Close));
+    let mut diagnostics = Vec::new();
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        let mut cursor = node.walk();
+        stack.extend(node.named_children(&mut cursor));
+        if !node.kind().ends_with("_statement") {
+            continue;
+        }
+        let entries = statements::for_statement_node(node, source);
+        if entries.is_empty() || entries.iter().any(|s| s.available_in(Some(version))) {
+            continue;
+        }
+        // The keyword, or the whole statement when it parsed as a LET
+        let keyword = match node.child(0) {
+            Some(first) if node.kind() != "let_statement" => first,
+            _ => node,
+        };
+        let text = keyword.utf8_text(source.as_bytes()).unwrap_or("");
+        let since = entries
+            .iter()
+            .filter_map(|s| s.since_version())
+            .min()
+            .map(|v| format!(" (requires {v})"))
+            .unwrap_or_default();
+        diagnostics.push(Diagnostic {
+            range: parser::node_range(keyword),
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String("unavailable-in-version".to_string())),
+            message: format!("'{text}' is not available in BR {version}{since}"),
+            ..Default::default()
+        });
     }
+    diagnostics.sort_by_key(|d| (d.range.start.line, d.range.start.character));
+    diagnostics
+}
 
-    entries.sort_by_key(|(byte, _)| *byte);
+/// Multi-line DEFs with no FNEND or END DEF before the next DEF or the
+/// end of the file.
+pub fn check_missing_fnend(structure: &Structure, source: &str) -> Vec<Diagnostic> {
+    structure
+        .functions
+        .iter()
+        .filter(|f| f.is_missing_end())
+        .map(|f| {
+            let name = function_name_node(f.def)
+                .and_then(|n| n.utf8_text(source.as_bytes()).ok())
+                .unwrap_or("");
+            Diagnostic {
+                range: parser::node_range(f.def),
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String("missing-fnend".to_string())),
+                message: format!("Function '{name}' is missing FNEND"),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
 
+/// Compare each doc comment's `@param` tags against the parameter list of
+/// the function it documents. Comments without any `@param` tags are not
+/// checked, so a plain description never produces warnings.
+fn check_doc_comment_params(
+    def_nodes: &[Node],
+    source: &str,
+    defs: &[extract::FunctionDef],
+) -> Vec<Diagnostic> {
     let mut diagnostics = Vec::new();
-    let mut open_def: Option<(tower_lsp::lsp_types::Range, String)> = None;
 
-    for (_, entry) in entries {
-        match entry {
-            Entry::Def { range, name } => {
-                if let Some((prev_range, prev_name)) = open_def.take() {
-                    diagnostics.push(Diagnostic {
-                        range: prev_range,
-                        severity: Some(DiagnosticSeverity::ERROR),
-                        message: format!("Function '{prev_name}' is missing FNEND"),
-                        ..Default::default()
-                    });
-                }
-                open_def = Some((range, name));
+    for &def_node in def_nodes {
+        let range = parser::node_range(def_node);
+        let Some(def) = defs.iter().find(|d| d.range == range) else {
+            continue;
+        };
+        let Some(comment) = extract::doc_comment_node(def_node) else {
+            continue;
+        };
+        let Ok(raw) = comment.utf8_text(source.as_bytes()) else {
+            continue;
+        };
+        let tags = extract::doc_param_tags(raw);
+        if tags.is_empty() {
+            continue;
+        }
+
+        let start = comment.start_position();
+        let lines: Vec<&str> = raw.split('\n').collect();
+        for tag in &tags {
+            if def
+                .params
+                .iter()
+                .any(|p| p.name.eq_ignore_ascii_case(tag.name))
+            {
+                continue;
             }
-            Entry::Close => {
-                open_def = None;
+            let line_text = lines[tag.line];
+            let mut character = line_text[..tag.column].chars().count() as u32;
+            if tag.line == 0 {
+                character += start.column as u32;
             }
+            let line = (start.row + tag.line) as u32;
+            diagnostics.push(Diagnostic {
+                range: Range {
+                    start: Position { line, character },
+                    end: Position {
+                        line,
+                        character: character + tag.name.chars().count() as u32,
+                    },
+                },
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String("doc-param-unknown".to_string())),
+                message: format!(
+                    "@param '{}' does not match any parameter of {}",
+                    tag.name, def.name
+                ),
+                ..Default::default()
+            });
         }
-    }
 
-    if let Some((range, name)) = open_def {
-        diagnostics.push(Diagnostic {
-            range,
-            severity: Some(DiagnosticSeverity::ERROR),
-            message: format!("Function '{name}' is missing FNEND"),
-            ..Default::default()
-        });
+        for param in &def.params {
+            if tags
+                .iter()
+                .any(|t| t.name.eq_ignore_ascii_case(&param.name))
+            {
+                continue;
+            }
+            diagnostics.push(Diagnostic {
+                range: def.selection_range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String("doc-param-missing".to_string())),
+                message: format!(
+                    "Parameter '{}' of {} has no @param in its doc comment",
+                    param.name, def.name
+                ),
+                ..Default::default()
+            });
+        }
     }
 
     diagnostics
 }
 
-fn check_duplicate_functions(def_nodes: &[Node], source: &str) -> Vec<Diagnostic> {
+fn check_duplicate_functions(def_nodes: &[Node], source: &str, uri: &Url) -> Vec<Diagnostic> {
     // Collect (lowercase_name, display_name, function_name_range) in document order
     let mut functions: Vec<(String, String, tower_lsp::lsp_types::Range)> = Vec::new();
 
@@ -205,18 +469,24 @@ fn check_duplicate_functions(def_nodes: &[Node], source: &str) -> Vec<Diagnostic
     }
 
     let mut diagnostics = Vec::new();
-    let mut seen: std::collections::HashMap<String, bool> = std::collections::HashMap::new();
+    let mut first: HashMap<&str, Range> = HashMap::new();
 
     for (key, name, range) in &functions {
-        if seen.contains_key(key) {
+        if let Some(first_range) = first.get(key.as_str()) {
             diagnostics.push(Diagnostic {
                 range: *range,
                 severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String("duplicate-function".to_string())),
                 message: format!("Function '{name}' is already defined in this file"),
+                related_information: Some(related(
+                    uri,
+                    *first_range,
+                    format!("'{name}' is first defined here"),
+                )),
                 ..Default::default()
             });
         } else {
-            seen.insert(key.clone(), true);
+            first.insert(key, *range);
         }
     }
 
@@ -321,7 +591,7 @@ fn types_compatible(expected: ParamKind, actual: ParamKind) -> bool {
     )
 }
 
-fn format_param_kind(kind: ParamKind) -> &'static str {
+pub(crate) fn format_param_kind(kind: ParamKind) -> &'static str {
     match kind {
         ParamKind::Numeric => "numeric",
         ParamKind::String => "string",
@@ -334,6 +604,7 @@ fn check_parameter_count(
     call_nodes: &[Node],
     source: &str,
     defs: &[extract::FunctionDef],
+    uri: &Url,
 ) -> Vec<Diagnostic> {
     // Build a map of local function definitions (lowercase name -> def)
     let mut def_map: HashMap<String, &extract::FunctionDef> = HashMap::new();
@@ -397,6 +668,13 @@ fn check_parameter_count(
             }
             let required = def.params.iter().filter(|p| !p.is_optional).count();
             let total = def.params.len();
+            let defined_here = || {
+                Some(related(
+                    uri,
+                    def.selection_range,
+                    format!("'{}' is defined here", def.name),
+                ))
+            };
 
             if arg_count < required || arg_count > total {
                 let expected = if required == total {
@@ -405,11 +683,13 @@ fn check_parameter_count(
                     format!("{required}-{total}")
                 };
                 diagnostics.push(Diagnostic {
-                    range: parser::node_range(call_node),
+                    range: parser::node_range(name_node),
                     severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(NumberOrString::String("parameter-count".to_string())),
                     message: format!(
                         "Function '{fn_name}' expects {expected} parameter(s), but {arg_count} provided"
                     ),
+                    related_information: defined_here(),
                     ..Default::default()
                 });
             } else if let Some(args) = args_node {
@@ -432,12 +712,14 @@ fn check_parameter_count(
                         diagnostics.push(Diagnostic {
                             range: parser::node_range(arg),
                             severity: Some(DiagnosticSeverity::WARNING),
+                            code: Some(NumberOrString::String("argument-type".to_string())),
                             message: format!(
                                 "Expected {} argument at position {}, got {}",
                                 format_param_kind(param.kind),
                                 pos + 1,
                                 format_param_kind(actual)
                             ),
+                            related_information: defined_here(),
                             ..Default::default()
                         });
                     }
@@ -468,8 +750,9 @@ fn check_parameter_count(
                     format!("{req}-{tot}")
                 };
                 diagnostics.push(Diagnostic {
-                    range: parser::node_range(call_node),
+                    range: parser::node_range(name_node),
                     severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(NumberOrString::String("parameter-count".to_string())),
                     message: format!(
                         "Function '{}' expects {expected} parameter(s), but {arg_count} provided",
                         overloads[0].name
@@ -504,6 +787,7 @@ fn check_parameter_count(
                             diagnostics.push(Diagnostic {
                                 range: parser::node_range(arg),
                                 severity: Some(DiagnosticSeverity::WARNING),
+                                code: Some(NumberOrString::String("argument-type".to_string())),
                                 message: format!(
                                     "Expected {} argument at position {}, got {}",
                                     format_param_kind(expected),
@@ -522,55 +806,279 @@ fn check_parameter_count(
     diagnostics
 }
 
+/// Root-level lines per chunk when the parameter checks run in parallel.
+/// Programs no longer than one chunk are checked on the calling thread,
+/// where rayon's overhead would outweigh the gain.
+const PARALLEL_CHUNK_LINES: usize = 2000;
+
+/// Byte ranges of consecutive runs of `chunk_lines` root-level lines, in
+/// document order. Together they cover the whole file.
+fn line_chunks(root: Node, chunk_lines: usize) -> Vec<ByteRange<usize>> {
+    let mut cursor = root.walk();
+    let starts: Vec<usize> = root
+        .children(&mut cursor)
+        .step_by(chunk_lines.max(1))
+        .map(|line| line.start_byte())
+        .skip(1)
+        .collect();
+    std::iter::once(0)
+        .chain(starts.iter().copied())
+        .zip(starts.iter().copied().chain(std::iter::once(usize::MAX)))
+        .map(|(start, end)| start..end)
+        .collect()
+}
+
+/// The run of `items`, sorted by `start`, that starts inside `range`.
+fn within<'a, T>(items: &'a [T], range: &ByteRange<usize>, start: impl Fn(&T) -> usize) -> &'a [T] {
+    let first = items.partition_point(|item| start(item) < range.start);
+    let last = items.partition_point(|item| start(item) < range.end);
+    &items[first..last]
+}
+
+/// `items`, sorted by `start`, split at the chunk boundaries, so each
+/// chunk's task gets its own items instead of filtering all of them.
+fn split_by_chunk<'a, T>(
+    items: &'a [T],
+    chunks: &[ByteRange<usize>],
+    start: impl Fn(&T) -> usize + Copy,
+) -> Vec<&'a [T]> {
+    chunks
+        .iter()
+        .map(|chunk| within(items, chunk, start))
+        .collect()
+}
+
+/// Run `check` for every chunk's items, in parallel when there is more
+/// than one chunk, and concatenate the results in chunk order.
+fn run_chunked<I: Sync, T: Send>(
+    parts: &[I],
+    check: impl Fn(&I) -> Vec<T> + Sync + Send,
+) -> Vec<T> {
+    if parts.len() <= 1 {
+        return parts.iter().flat_map(check).collect();
+    }
+    let results: Vec<Vec<T>> = parts.par_iter().map(check).collect();
+    results.into_iter().flatten().collect()
+}
+
 pub fn check_unused_variables(
     nodes: &parser::DiagnosticNodes,
+    structure: &Structure,
+    source: &str,
+    defs: &[extract::FunctionDef],
+) -> Vec<Diagnostic> {
+    check_unused_variables_cached(
+        nodes,
+        structure,
+        source,
+        defs,
+        &mut VariableUsage::default(),
+    )
+}
+
+/// [`check_unused_variables`] for a document being edited: reference
+/// counts come from `usage`, recounting only the functions edited since
+/// the last check, and `usage` is left holding this check's counts.
+pub fn check_unused_variables_cached(
+    nodes: &parser::DiagnosticNodes,
+    structure: &Structure,
     source: &str,
     defs: &[extract::FunctionDef],
+    usage: &mut VariableUsage,
 ) -> Vec<Diagnostic> {
-    let mut diagnostics =
-        check_unused_dim_variables(&nodes.var_ref_names, &nodes.dim_var_ref_names, source);
+    let mut diagnostics = if nodes.dim_var_ref_names.is_empty() {
+        Vec::new()
+    } else {
+        let total_counts = usage.recount(nodes, structure, source);
+        let dims: Vec<Node> = nodes
+            .dim_var_ref_names
+            .iter()
+            .copied()
+            .filter(|&dim| !names_parameter(dim, nodes, structure, source))
+            .collect();
+        unused_dim_entries(&dims, source, &total_counts)
+    };
     diagnostics.extend(check_unused_library_imports(
         &nodes.function_names,
         source,
         defs,
     ));
-    diagnostics.extend(check_unused_parameters(
-        &nodes.param_ident_names,
-        &nodes.var_ref_names,
-        &nodes.def_statements,
-        &nodes.fnend_statements,
-        source,
-    ));
     diagnostics
 }
 
-fn check_unused_dim_variables(
-    var_ref_names: &[Node],
-    dim_var_ref_names: &[Node],
+/// Whether `node` names a parameter of the function it's in. BR variables
+/// are global apart from a function's parameters, so main code and the
+/// subroutines it GOSUBs share every variable, while a parameter named
+/// like a DIM'd variable is the function's own.
+fn names_parameter(
+    node: Node,
+    nodes: &parser::DiagnosticNodes,
+    structure: &Structure,
     source: &str,
-) -> Vec<Diagnostic> {
-    if dim_var_ref_names.is_empty() {
-        return Vec::new();
+) -> bool {
+    let at = node.start_byte();
+    let functions = &structure.functions;
+    let Some(function) = functions
+        .partition_point(|f| f.def.start_byte() <= at)
+        .checked_sub(1)
+        .map(|i| &functions[i])
+    else {
+        return false;
+    };
+    if !function.bytes().contains(&at) {
+        return false;
     }
-
     let bytes = source.as_bytes();
+    let Ok(name) = node.utf8_text(bytes) else {
+        return false;
+    };
+    within(
+        &nodes.param_ident_names,
+        &function.def.byte_range(),
+        Node::start_byte,
+    )
+    .iter()
+    .any(|param| {
+        param
+            .utf8_text(bytes)
+            .is_ok_and(|param| param.eq_ignore_ascii_case(name))
+    })
+}
 
-    // Count total references per lowercase name
-    let mut total_counts: HashMap<String, usize> = HashMap::new();
-    for &node in var_ref_names {
-        if let Ok(text) = node.utf8_text(bytes) {
-            *total_counts.entry(text.to_ascii_lowercase()).or_default() += 1;
+/// Reference counts of global variables, by lowercase name, for one
+/// function (DEF to FNEND) or the code between two functions. References
+/// to the function's own parameters aren't counted.
+#[derive(Debug, Clone)]
+struct RegionUsage {
+    bytes: ByteRange<usize>,
+    counts: HashMap<String, usize>,
+}
+
+/// Per-region reference counts from a document's last unused-variable
+/// check. Edits since then move the regions after them and drop the ones
+/// they touch, so typing inside one function of a large file only
+/// recounts that function.
+#[derive(Debug, Clone, Default)]
+pub struct VariableUsage {
+    regions: Vec<RegionUsage>,
+}
+
+impl VariableUsage {
+    /// Follow an edit made to the document. An edit at a region's edge
+    /// counts as touching it.
+    pub fn edit(&mut self, edit: &InputEdit) {
+        self.regions.retain_mut(|region| {
+            if region.bytes.end < edit.start_byte {
+                true
+            } else if region.bytes.start > edit.old_end_byte {
+                let shift = |byte: usize| byte - edit.old_end_byte + edit.new_end_byte;
+                region.bytes = shift(region.bytes.start)..shift(region.bytes.end);
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    /// Total counts of global variable references for the document,
+    /// reusing regions that are still where they were and counting the
+    /// rest, each on its own thread.
+    fn recount(
+        &mut self,
+        nodes: &parser::DiagnosticNodes,
+        structure: &Structure,
+        source: &str,
+    ) -> HashMap<String, usize> {
+        let mut cached: HashMap<ByteRange<usize>, HashMap<String, usize>> = self
+            .regions
+            .drain(..)
+            .map(|region| (region.bytes, region.counts))
+            .collect();
+        let regions: Vec<_> = usage_regions(structure, source.len())
+            .into_iter()
+            .map(|bytes| {
+                let counts = cached.remove(&bytes);
+                (bytes, counts)
+            })
+            .collect();
+
+        let bytes = source.as_bytes();
+        self.regions = regions
+            .into_par_iter()
+            .map(|(range, counts)| {
+                let counts = counts.unwrap_or_else(|| {
+                    let mut counts: HashMap<String, usize> = HashMap::new();
+                    for &node in within(&nodes.var_ref_names, &range, Node::start_byte) {
+                        if names_parameter(node, nodes, structure, source) {
+                            continue;
+                        }
+                        if let Ok(text) = node.utf8_text(bytes) {
+                            *counts.entry(text.to_ascii_lowercase()).or_default() += 1;
+                        }
+                    }
+                    counts
+                });
+                RegionUsage {
+                    bytes: range,
+                    counts,
+                }
+            })
+            .collect();
+
+        let mut total_counts: HashMap<String, usize> = HashMap::new();
+        for region in &self.regions {
+            for (name, count) in &region.counts {
+                *total_counts.entry(name.clone()).or_default() += count;
+            }
+        }
+        total_counts
+    }
+}
+
+/// Split `0..len` into the multi-line functions and the stretches between
+/// them.
+fn usage_regions(structure: &Structure, len: usize) -> Vec<ByteRange<usize>> {
+    let mut regions = Vec::new();
+    let mut start = 0;
+    for function in closed_functions(structure) {
+        if start < function.start {
+            regions.push(start..function.start);
         }
+        start = function.end;
+        regions.push(function);
     }
+    regions.push(start..len.max(start));
+    regions
+}
+
+/// The bytes of each multi-line function with an FNEND, DEF through FNEND,
+/// in source order.
+fn closed_functions(structure: &Structure) -> Vec<ByteRange<usize>> {
+    structure
+        .functions
+        .iter()
+        .filter(|f| f.end.is_some())
+        .map(|f| f.bytes())
+        .collect()
+}
+
+/// DIM entries whose names `total_counts` counts no references for besides
+/// the DIM entries themselves.
+fn unused_dim_entries(
+    dim_var_ref_names: &[Node],
+    source: &str,
+    total_counts: &HashMap<String, usize>,
+) -> Vec<Diagnostic> {
+    let bytes = source.as_bytes();
 
     // Group dim entries by lowercase name
-    let mut dim_entries: HashMap<String, Vec<(&str, tower_lsp::lsp_types::Range)>> = HashMap::new();
+    let mut dim_entries: HashMap<String, Vec<(&str, Node)>> = HashMap::new();
     for &node in dim_var_ref_names {
         if let Ok(text) = node.utf8_text(bytes) {
             dim_entries
                 .entry(text.to_ascii_lowercase())
                 .or_default()
-                .push((text, parser::node_range(node)));
+                .push((text, node));
         }
     }
 
@@ -579,14 +1087,19 @@ fn check_unused_dim_variables(
         let dim_count = entries.len();
         let total = total_counts.get(key).copied().unwrap_or(0);
         if total == dim_count {
-            for (name, range) in entries {
-                diagnostics.push(Diagnostic {
-                    range: *range,
+            for &(name, node) in entries {
+                let mut diagnostic = Diagnostic {
+                    range: parser::node_range(node),
                     severity: Some(DiagnosticSeverity::HINT),
+                    code: Some(NumberOrString::String("unused-variable".to_string())),
                     tags: Some(vec![DiagnosticTag::UNNECESSARY]),
                     message: format!("'{name}' is declared but never used"),
                     ..Default::default()
-                });
+                };
+                if let Some(fix) = dim_entry_removal(node, name, source) {
+                    fix.attach(&mut diagnostic);
+                }
+                diagnostics.push(diagnostic);
             }
         }
     }
@@ -594,126 +1107,210 @@ fn check_unused_dim_variables(
     diagnostics
 }
 
-fn check_unused_parameters(
-    param_ident_names: &[Node],
-    var_ref_names: &[Node],
-    def_nodes: &[Node],
-    fnend_nodes: &[Node],
+/// Hint at function parameters the body never references. Parameters from
+/// a `___` placeholder on are locals, not part of the signature, and aren't
+/// reported.
+pub fn check_unused_parameters(
+    nodes: &parser::DiagnosticNodes,
+    structure: &Structure,
     source: &str,
 ) -> Vec<Diagnostic> {
-    if param_ident_names.is_empty() {
-        return Vec::new();
-    }
-
-    // Compute function ranges from pre-collected def + fnend nodes
-    let fn_ranges = compute_function_ranges(def_nodes, fnend_nodes);
-    if fn_ranges.is_empty() {
-        return Vec::new();
-    }
-
-    let bytes = source.as_bytes();
-
-    // Pre-lowercase all ref texts for efficient comparison
-    let refs_lower: Vec<(String, usize)> = var_ref_names
-        .iter()
-        .filter_map(|&node| {
-            node.utf8_text(bytes)
-                .ok()
-                .map(|t| (t.to_ascii_lowercase(), node.start_byte()))
-        })
-        .collect();
+    check_unused_parameters_chunked(
+        &nodes.param_ident_names,
+        &nodes.var_ref_names,
+        structure,
+        source,
+        &line_chunks(nodes.root, PARALLEL_CHUNK_LINES),
+    )
+}
 
-    // Pre-extract param ident info
-    let param_info: Vec<(&str, usize, tower_lsp::lsp_types::Range)> = param_ident_names
-        .iter()
-        .filter_map(|&node| {
-            node.utf8_text(bytes)
-                .ok()
-                .map(|t| (t, node.start_byte(), parser::node_range(node)))
+/// Fix deleting the DIM entry whose name is `name_node`, with one of the
+/// commas around it. The last entry takes the whole statement, and the
+/// whole line when nothing else is on it.
+fn dim_entry_removal(name_node: Node, name: &str, source: &str) -> Option<Fix> {
+    if let Some(error) = name_node.parent().filter(|p| p.is_error()) {
+        return misparsed_dim_entry_removal(error, name_node, name, source);
+    }
+    let entry = name_node.parent()?;
+    let dim = entry.parent().filter(|p| p.kind() == "dim_statement")?;
+    let mut cursor = dim.walk();
+    let entries: Vec<Node> = dim
+        .named_children(&mut cursor)
+        .filter(|c| {
+            matches!(
+                c.kind(),
+                "stringreference" | "numberreference" | "stringarray" | "numberarray"
+            )
         })
         .collect();
-
-    let mut diagnostics = Vec::new();
-
-    for fr in &fn_ranges {
-        // Collect parameter identifiers in this function
-        let params: Vec<_> = param_info
-            .iter()
-            .filter(|(_, sb, _)| *sb >= fr.def_start_byte && *sb < fr.body_end_byte)
-            .collect();
-
-        if params.is_empty() {
-            continue;
-        }
-
-        // Build set of ref names within this function body (excluding param declarations)
-        let param_bytes: HashSet<usize> = params.iter().map(|(_, sb, _)| *sb).collect();
-        let mut body_ref_names: HashSet<String> = HashSet::new();
-        for (name, byte) in &refs_lower {
-            if *byte >= fr.def_start_byte && *byte < fr.body_end_byte && !param_bytes.contains(byte)
-            {
-                body_ref_names.insert(name.clone());
-            }
-        }
-
-        for (text, _, range) in &params {
-            let param_name = text.to_ascii_lowercase();
-            if !body_ref_names.contains(&param_name) {
-                diagnostics.push(Diagnostic {
-                    range: *range,
-                    severity: Some(DiagnosticSeverity::HINT),
-                    tags: Some(vec![DiagnosticTag::UNNECESSARY]),
-                    message: format!("'{text}' is declared but never used"),
-                    ..Default::default()
-                });
+    let index = entries.iter().position(|e| e.id() == entry.id())?;
+
+    if entries.len() == 1 {
+        // A DIM followed by `:` parses inside an ERROR of its own; the
+        // separators are siblings of that
+        let statement = dim
+            .parent()
+            .filter(|p| p.is_error() && p.named_child_count() == 1)
+            .unwrap_or(dim);
+        let line = statement.parent().filter(|line| {
+            let mut cursor = line.walk();
+            let only_dim = line
+                .named_children(&mut cursor)
+                .all(|c| c.id() == statement.id() || c.kind() == "line_number");
+            line.kind() == "line" && only_dim
+        });
+        let range = match line {
+            Some(line) => parser::node_range(line),
+            None => {
+                let start = |n: Node| parser::node_range(n).start;
+                let end = |n: Node| parser::node_range(n).end;
+                let next = statement.next_sibling();
+                let prev = statement.prev_sibling();
+                match (prev, next) {
+                    // Take the separator to the following statement with it
+                    (_, Some(sep)) if parser::is_statement_separator(sep) => {
+                        Range::new(start(statement), sep.next_sibling().map_or(end(sep), start))
+                    }
+                    // or the one from the statement before
+                    (Some(sep), _) if parser::is_statement_separator(sep) => Range::new(
+                        sep.prev_sibling().map_or(start(sep), end),
+                        next.map_or(end(statement), start),
+                    ),
+                    // Keep a trailing comment where it was
+                    _ => Range::new(start(statement), next.map_or(end(statement), start)),
+                }
             }
-        }
+        };
+        return Some(Fix {
+            title: "Remove unused DIM statement".to_string(),
+            edits: vec![TextEdit {
+                range,
+                new_text: String::new(),
+            }],
+        });
     }
 
-    diagnostics
+    let (start, end) = if index > 0 {
+        (entries[index - 1].end_position(), entry.end_position())
+    } else {
+        (entry.start_position(), entries[1].start_position())
+    };
+    Some(Fix {
+        title: format!("Remove '{name}' from DIM"),
+        edits: vec![TextEdit {
+            range: Range::new(
+                Position::new(start.row as u32, start.column as u32),
+                Position::new(end.row as u32, end.column as u32),
+            ),
+            new_text: String::new(),
+        }],
+    })
 }
 
-struct FunctionRange {
-    def_start_byte: usize,
-    body_end_byte: usize,
+/// [`dim_entry_removal`] for a DIM after a separator, which the grammar
+/// misparses (see [`parser::misparsed_dim_entries`]). The last entry takes
+/// the statement and the separator before it.
+fn misparsed_dim_entry_removal(
+    error: Node,
+    name_node: Node,
+    name: &str,
+    source: &str,
+) -> Option<Fix> {
+    let entries = parser::misparsed_dim_entries(error, source);
+    let index = entries.iter().position(|(n, _)| n.id() == name_node.id())?;
+    let start = |n: Node| parser::node_range(n).start;
+    let end = |n: Node| parser::node_range(n).end;
+
+    let (title, range) = if entries.len() == 1 {
+        let separator = error.prev_sibling()?.prev_sibling()?;
+        let from = separator.prev_sibling().map_or(start(separator), end);
+        (
+            "Remove unused DIM statement".to_string(),
+            Range::new(from, end(error)),
+        )
+    } else {
+        let range = if index > 0 {
+            Range::new(end(entries[index - 1].1), end(entries[index].1))
+        } else {
+            Range::new(start(entries[0].0), start(entries[1].0))
+        };
+        (format!("Remove '{name}' from DIM"), range)
+    };
+    Some(Fix {
+        title,
+        edits: vec![TextEdit {
+            range,
+            new_text: String::new(),
+        }],
+    })
 }
 
-fn compute_function_ranges(def_nodes: &[Node], fnend_nodes: &[Node]) -> Vec<FunctionRange> {
-    // Merge def and fnend nodes by start_byte, pair them up
-    enum Entry {
-        Def(usize),
-        Fnend(usize),
+fn check_unused_parameters_chunked(
+    param_ident_names: &[Node],
+    var_ref_names: &[Node],
+    structure: &Structure,
+    source: &str,
+    chunks: &[ByteRange<usize>],
+) -> Vec<Diagnostic> {
+    if param_ident_names.is_empty() {
+        return Vec::new();
     }
 
-    let mut entries: Vec<Entry> = Vec::new();
-    for &node in def_nodes {
-        entries.push(Entry::Def(node.start_byte()));
-    }
-    for &node in fnend_nodes {
-        entries.push(Entry::Fnend(node.start_byte()));
-    }
-    entries.sort_by_key(|e| match e {
-        Entry::Def(b) | Entry::Fnend(b) => *b,
-    });
+    let functions = closed_functions(structure);
+    // Each chunk checks the functions whose DEF it contains
+    run_chunked(
+        &split_by_chunk(&functions, chunks, |f| f.start),
+        |functions| {
+            functions
+                .iter()
+                .flat_map(|f| {
+                    unused_parameters_in(
+                        within(param_ident_names, f, Node::start_byte),
+                        within(var_ref_names, f, Node::start_byte),
+                        source,
+                    )
+                })
+                .collect()
+        },
+    )
+}
 
-    let mut ranges = Vec::new();
-    let mut pending_def: Option<usize> = None;
-    for entry in &entries {
-        match entry {
-            Entry::Def(byte) => {
-                pending_def = Some(*byte);
-            }
-            Entry::Fnend(byte) => {
-                if let Some(def_byte) = pending_def.take() {
-                    ranges.push(FunctionRange {
-                        def_start_byte: def_byte,
-                        body_end_byte: *byte,
-                    });
-                }
-            }
-        }
+/// The parameters of one function, `params`, that none of its `refs` use.
+fn unused_parameters_in(params: &[Node], refs: &[Node], source: &str) -> Vec<Diagnostic> {
+    let bytes = source.as_bytes();
+    let params: Vec<(&str, Node)> = params
+        .iter()
+        .filter_map(|&node| Some((node.utf8_text(bytes).ok()?, node)))
+        .collect();
+    if params.is_empty() {
+        return Vec::new();
     }
-    ranges
+    let visible = params
+        .iter()
+        .position(|(text, _)| text.starts_with("___"))
+        .unwrap_or(params.len());
+
+    // Names referenced in the body, not counting the parameter declarations
+    let param_bytes: HashSet<usize> = params.iter().map(|(_, n)| n.start_byte()).collect();
+    let body_ref_names: HashSet<String> = refs
+        .iter()
+        .filter(|n| !param_bytes.contains(&n.start_byte()))
+        .filter_map(|n| n.utf8_text(bytes).ok())
+        .map(|name| name.to_ascii_lowercase())
+        .collect();
+
+    params[..visible]
+        .iter()
+        .filter(|(text, _)| !body_ref_names.contains(&text.to_ascii_lowercase()))
+        .map(|(text, node)| Diagnostic {
+            range: parser::node_range(*node),
+            severity: Some(DiagnosticSeverity::HINT),
+            code: Some(NumberOrString::String("unused-parameter".to_string())),
+            tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+            message: format!("'{text}' is declared but never used"),
+            ..Default::default()
+        })
+        .collect()
 }
 
 fn check_unused_library_imports(
@@ -742,16 +1339,450 @@ fn check_unused_library_imports(
         let key = import.name.to_ascii_lowercase();
         let count = fn_counts.get(&key).copied().unwrap_or(0);
         if count <= 1 {
-            diagnostics.push(Diagnostic {
+            let mut diagnostic = Diagnostic {
                 range: import.selection_range,
                 severity: Some(DiagnosticSeverity::HINT),
                 tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+                code: Some(NumberOrString::String("unused-import".to_string())),
                 message: format!("'{}' is imported but never used", import.name),
                 ..Default::default()
-            });
+            };
+            let removal = fn_name_nodes
+                .iter()
+                .find(|n| parser::node_range(**n) == import.selection_range)
+                .and_then(|&n| import_removal(n));
+            if let Some(range) = removal {
+                Fix {
+                    title: format!("Remove '{}' from the LIBRARY statement", import.name),
+                    edits: vec![TextEdit {
+                        range,
+                        new_text: String::new(),
+                    }],
+                }
+                .attach(&mut diagnostic);
+            }
+            diagnostics.push(diagnostic);
+        }
+    }
+
+    diagnostics
+}
+
+/// What to delete to drop an imported name from its LIBRARY statement's
+/// list: the name and the comma after it, or before it for the last name.
+/// The only name takes the whole line with it when the statement is alone
+/// there; otherwise there's no fix, since an empty list doesn't parse.
+fn import_removal(name: Node) -> Option<Range> {
+    let list = name
+        .parent()
+        .filter(|p| p.kind() == "library_function_list")?;
+    let mut cursor = list.walk();
+    let names: Vec<Node> = list
+        .children(&mut cursor)
+        .filter(|c| c.kind() == "function_name")
+        .collect();
+    let i = names.iter().position(|n| n.id() == name.id())?;
+    if let Some(&next) = names.get(i + 1) {
+        return Some(Range::new(
+            parser::node_range(name).start,
+            parser::node_range(next).start,
+        ));
+    }
+    if i > 0 {
+        return Some(Range::new(
+            parser::node_range(names[i - 1]).end,
+            parser::node_range(name).end,
+        ));
+    }
+
+    let statement = list.parent()?;
+    let line = statement.parent().filter(|p| p.kind() == "line")?;
+    let mut cursor = line.walk();
+    let alone = line
+        .named_children(&mut cursor)
+        .filter(|c| c.kind() != "line_number")
+        .all(|c| c.id() == statement.id());
+    let row = line.start_position().row as u32;
+    alone.then(|| Range::new(Position::new(row, 0), Position::new(row + 1, 0)))
+}
+
+/// File numbers BR always has available: the console and the printer.
+const IMPLICIT_FILE_NUMBERS: &[&str] = &["0", "255"];
+
+/// Check OPEN/CLOSE pairing for literal file numbers. Control flow is not
+/// followed, so "never opened" only fires when no OPEN of the number exists
+/// anywhere in the file, and re-opens are judged in source order.
+pub fn check_file_numbers(usage: &files::FileUsage, uri: &Url) -> Vec<Diagnostic> {
+    let is_checked = |key: &str| {
+        key.bytes().all(|b| b.is_ascii_digit()) && !IMPLICIT_FILE_NUMBERS.contains(&key)
+    };
+
+    let mut diagnostics = Vec::new();
+
+    enum Event<'a> {
+        Open(&'a files::OpenFile),
+        Use(&'a files::FileReference),
+    }
+    let mut events: Vec<(usize, Event)> = usage
+        .opens
+        .iter()
+        .map(|o| (o.start_byte, Event::Open(o)))
+        .chain(
+            usage
+                .references
+                .iter()
+                .map(|r| (r.start_byte, Event::Use(r))),
+        )
+        .collect();
+    events.sort_by_key(|(byte, _)| *byte);
+
+    let mut open_now: HashMap<&str, &files::OpenFile> = HashMap::new();
+    for (_, event) in &events {
+        match event {
+            Event::Open(open) => {
+                if !is_checked(&open.file_number) {
+                    continue;
+                }
+                let Some(previous) = open_now.insert(&open.file_number, open) else {
+                    continue;
+                };
+                // Across a branch or label the earlier OPEN may not have run,
+                // or its CLOSE may sit on the other path
+                if previous.block == open.block {
+                    diagnostics.push(Diagnostic {
+                        range: open.range,
+                        severity: Some(DiagnosticSeverity::WARNING),
+                        code: Some(NumberOrString::String("file-already-open".to_string())),
+                        message: format!(
+                            "File #{} is already open (opened on line {} without a CLOSE)",
+                            open.label,
+                            previous.range.start.line + 1
+                        ),
+                        related_information: Some(related(
+                            uri,
+                            previous.range,
+                            format!("File #{} is opened here", previous.label),
+                        )),
+                        ..Default::default()
+                    });
+                }
+            }
+            Event::Use(reference) => {
+                if !is_checked(&reference.file_number) {
+                    continue;
+                }
+                if reference.statement == "close_statement" {
+                    open_now.remove(reference.file_number.as_str());
+                }
+                let opened_anywhere = usage
+                    .opens
+                    .iter()
+                    .any(|o| o.file_number == reference.file_number);
+                if !opened_anywhere {
+                    let action = if reference.statement == "close_statement" {
+                        "closed"
+                    } else {
+                        "used"
+                    };
+                    diagnostics.push(Diagnostic {
+                        range: reference.range,
+                        severity: Some(DiagnosticSeverity::WARNING),
+                        code: Some(NumberOrString::String("file-not-opened".to_string())),
+                        message: format!(
+                            "File #{} is {action} but never opened in this file",
+                            reference.label
+                        ),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+    }
+
+    diagnostics
+}
+
+/// Flag error-condition clauses (`NOKEY 300`, `EOF Done`) that jump to a
+/// label or line number the file doesn't define.
+pub fn check_error_condition_targets(tree: &Tree, source: &str) -> Vec<Diagnostic> {
+    let conditions = error_conditions::collect_error_conditions(tree, source);
+    if conditions.is_empty() {
+        return Vec::new();
+    }
+    let defined = DefinedTargets::collect(tree, source);
+    conditions
+        .iter()
+        .filter_map(|condition| {
+            let target = condition.target.as_ref()?;
+            if defined.contains(target) {
+                return None;
+            }
+            let what = match target.kind {
+                TargetKind::Label => format!("label `{}`", target.text),
+                TargetKind::Line => format!("line {}", target.text.trim()),
+            };
+            Some(Diagnostic {
+                range: target.range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String(
+                    "undefined-condition-target".to_string(),
+                )),
+                message: format!(
+                    "{} jumps to {what}, which is not defined in this file",
+                    condition.keyword.to_ascii_uppercase()
+                ),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Flag PIC masks in FORM statements with characters PIC doesn't know, a
+/// second decimal point, or more digits than a BR number holds.
+pub fn check_pic_masks(tree: &Tree, source: &str) -> Vec<Diagnostic> {
+    parser::run_query("(pic_spec) @pic", tree.root_node(), source)
+        .iter()
+        .filter_map(|spec| {
+            let (offset, mask) = pic::mask_of(&spec.text)?;
+            let start = Position::new(
+                spec.range.start.line,
+                spec.range.start.character + offset as u32,
+            );
+            Some(
+                pic::validate(mask)
+                    .iter()
+                    .map(|problem| problem.to_diagnostic(start))
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .flatten()
+        .collect()
+}
+
+/// Flag OPEN statements whose `RecL=` disagrees with the record length in
+/// the layout of the file they open. Layouts without a `recl=` line are
+/// checked against the length their fields add up to, which the record may
+/// exceed but not fall short of. Opening with the wrong length is a common
+/// way BR data files get corrupted.
+pub fn check_record_lengths(usage: &files::FileUsage, layouts: &LayoutIndex) -> Vec<Diagnostic> {
+    usage
+        .opens
+        .iter()
+        .filter_map(|open| {
+            let recl = open.record_length?;
+            let layout = layouts.find_for_open(open.name.as_deref()?)?;
+            let message = match (layout.record_length, layout.computed_record_length()) {
+                (Some(layout_recl), _) if layout_recl != recl => format!(
+                    "RecL={recl} does not match the record length {layout_recl} in the layout for `{}`",
+                    layout.path
+                ),
+                (None, Some(fields)) if fields > recl => format!(
+                    "RecL={recl} is shorter than the {fields} bytes of fields in the layout for `{}`",
+                    layout.path
+                ),
+                _ => return None,
+            };
+            Some(Diagnostic {
+                range: open.range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String("record-length-mismatch".to_string())),
+                message,
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Flag keyed OPEN statements whose `KPs=`/`KLn=` disagree with the key
+/// in the layout whose index file is their `KFName=`. Key positions in the
+/// layout come from the field widths; OPENs giving only one of `KPs=` and
+/// `KLn=` are skipped.
+pub fn check_key_fields(usage: &files::FileUsage, layouts: &LayoutIndex) -> Vec<Diagnostic> {
+    let join = |parts: &[u32]| {
+        parts
+            .iter()
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>()
+            .join("/")
+    };
+    usage
+        .opens
+        .iter()
+        .filter_map(|open| {
+            if open.key_positions.is_empty() || open.key_positions.len() != open.key_lengths.len() {
+                return None;
+            }
+            let layout = layouts.find_for_open(open.name.as_deref()?)?;
+            let key = layout.key_for(open.kfname.as_deref()?)?;
+            let expected = layout.key_spec(key)?;
+            let actual = crate::layout::KeySpec::new(&open.key_positions, &open.key_lengths);
+            if actual == expected {
+                return None;
+            }
+            Some(Diagnostic {
+                range: open.range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String("key-mismatch".to_string())),
+                message: format!(
+                    "KPs={},KLn={} does not match key `{}` in the layout (KPs={},KLn={} from {})",
+                    join(&open.key_positions),
+                    join(&open.key_lengths),
+                    key.path,
+                    join(&expected.positions),
+                    join(&expected.lengths),
+                    key.key_fields.join(", ")
+                ),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Whether a parameter name node belongs to a scalar parameter declared
+/// without `&`. Arrays are always passed by reference in BR.
+fn is_value_param(name_node: Node) -> bool {
+    let Some(reference) = name_node.parent() else {
+        return false;
+    };
+    if !matches!(reference.kind(), "numberreference" | "stringreference") {
+        return false;
+    }
+    let Some(param) = reference.parent().and_then(|p| p.parent()) else {
+        return false;
+    };
+    let mut cursor = param.walk();
+    let by_reference = param.children(&mut cursor).any(|c| c.kind() == "&");
+    param.kind() == "parameter" && !by_reference
+}
+
+/// The assignment a variable name node is the target of, if any. Only
+/// statement-level assignments (`LET X = ...`, `X = ...`) and the explicit
+/// assignment operators (`:=`, `+=`, ...) count; a bare `=` inside an
+/// expression is a comparison in BR.
+pub fn assignment_target_of(name_node: Node) -> Option<Node> {
+    let reference = name_node.parent()?;
+    let assignment = match reference.kind() {
+        "numberreference" => reference.parent()?,
+        "stringreference" => {
+            let primary = reference.parent()?;
+            let expression = primary.parent()?;
+            if primary.kind() != "string_primary_expression"
+                || expression.kind() != "string_expression"
+            {
+                return None;
+            }
+            expression.parent()?
+        }
+        _ => return None,
+    };
+    if !matches!(
+        assignment.kind(),
+        "numeric_forced_assignment_expression" | "string_assignment"
+    ) || assignment.named_child(0)?.start_byte() != reference.start_byte()
+    {
+        return None;
+    }
+
+    let mut cursor = assignment.walk();
+    let explicit_op = assignment.named_children(&mut cursor).any(|c| {
+        c.kind() == "numeric_forced_assignment_op"
+            && c.named_child(0)
+                .is_some_and(|op| op.kind() == "conditional_numeric_forced_assignment_op")
+    });
+    let statement_level = assignment
+        .parent()
+        .filter(|p| matches!(p.kind(), "numeric_expression" | "string_expression"))
+        .and_then(|p| p.parent())
+        .is_some_and(|p| p.kind() == "let_statement");
+    (explicit_op || statement_level).then_some(assignment)
+}
+
+/// Warn when a function body assigns to a parameter declared without `&`.
+/// The assignment only changes the function's local copy, which is often
+/// not what the author intended.
+pub fn check_value_param_assignments(
+    nodes: &parser::DiagnosticNodes,
+    structure: &Structure,
+    source: &str,
+) -> Vec<Diagnostic> {
+    check_value_param_assignments_chunked(
+        nodes,
+        structure,
+        source,
+        &line_chunks(nodes.root, PARALLEL_CHUNK_LINES),
+    )
+}
+
+fn check_value_param_assignments_chunked(
+    nodes: &parser::DiagnosticNodes,
+    structure: &Structure,
+    source: &str,
+    chunks: &[ByteRange<usize>],
+) -> Vec<Diagnostic> {
+    let functions = closed_functions(structure);
+    run_chunked(
+        &split_by_chunk(&functions, chunks, |f| f.start),
+        |functions| {
+            functions
+                .iter()
+                .flat_map(|f| {
+                    value_param_assignments_in(
+                        within(&nodes.param_ident_names, f, Node::start_byte),
+                        within(&nodes.var_ref_names, f, Node::start_byte),
+                        source,
+                    )
+                })
+                .collect()
+        },
+    )
+}
+
+/// Assignments among one function's `refs` to its value `params`.
+fn value_param_assignments_in(params: &[Node], refs: &[Node], source: &str) -> Vec<Diagnostic> {
+    let bytes = source.as_bytes();
+    // Lowercase name -> where the parameter is declared
+    let value_params: HashMap<String, Position> = params
+        .iter()
+        .filter(|n| is_value_param(**n))
+        .filter_map(|n| {
+            let name = n.utf8_text(bytes).ok()?.to_ascii_lowercase();
+            Some((name, parser::node_range(*n).start))
+        })
+        .collect();
+    if value_params.is_empty() {
+        return Vec::new();
+    }
+
+    let mut diagnostics = Vec::new();
+    for &node in refs {
+        let Ok(name) = node.utf8_text(bytes) else {
+            continue;
+        };
+        let Some(&declared) = value_params.get(&name.to_ascii_lowercase()) else {
+            continue;
+        };
+        if assignment_target_of(node).is_none() {
+            continue;
+        }
+        let mut diagnostic = Diagnostic {
+            range: parser::node_range(node),
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String("value-param-assignment".to_string())),
+            message: format!(
+                "Assignment to '{name}' only changes the local copy; declare it as '&{name}' to update the caller's variable"
+            ),
+            ..Default::default()
+        };
+        Fix {
+            title: format!("Pass '{name}' by reference"),
+            edits: vec![TextEdit {
+                range: Range::new(declared, declared),
+                new_text: "&".to_string(),
+            }],
         }
+        .attach(&mut diagnostic);
+        diagnostics.push(diagnostic);
     }
-
     diagnostics
 }
 
@@ -759,24 +1790,27 @@ fn check_unused_library_imports(
 mod tests {
     use super::*;
     use crate::parser;
-    use tree_sitter::Tree;
+
+    /// A single chunk covering the whole file.
+    const WHOLE_FILE: &[ByteRange<usize>] = &[ByteRange {
+        start: 0,
+        end: usize::MAX,
+    }];
 
     fn parse(source: &str) -> Tree {
         let mut p = parser::new_parser();
         parser::parse(&mut p, source, None).expect("parse failed")
     }
 
+    fn test_uri() -> Url {
+        Url::parse("file:///test.brs").unwrap()
+    }
+
     #[test]
     fn missing_fnend_basic() {
         let source = "def fnFoo(X)\nlet Y=X*2\n";
         let tree = parse(source);
-        let nodes = parser::collect_diagnostic_nodes(&tree, source);
-        let diags = check_missing_fnend(
-            &nodes.def_statements,
-            &nodes.fnend_statements,
-            &nodes.end_def_statements,
-            source,
-        );
+        let diags = check_missing_fnend(&Structure::of(&tree, source), source);
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("fnFoo"));
         assert!(diags[0].message.contains("missing FNEND"));
@@ -787,13 +1821,7 @@ mod tests {
     fn inline_function_no_diagnostic() {
         let source = "def fnFoo(X)=X*2\n";
         let tree = parse(source);
-        let nodes = parser::collect_diagnostic_nodes(&tree, source);
-        let diags = check_missing_fnend(
-            &nodes.def_statements,
-            &nodes.fnend_statements,
-            &nodes.end_def_statements,
-            source,
-        );
+        let diags = check_missing_fnend(&Structure::of(&tree, source), source);
         assert!(diags.is_empty(), "inline function should not need FNEND");
     }
 
@@ -801,13 +1829,7 @@ mod tests {
     fn fnend_closes_function() {
         let source = "def fnFoo(X)\nlet Y=X*2\nfnend\n";
         let tree = parse(source);
-        let nodes = parser::collect_diagnostic_nodes(&tree, source);
-        let diags = check_missing_fnend(
-            &nodes.def_statements,
-            &nodes.fnend_statements,
-            &nodes.end_def_statements,
-            source,
-        );
+        let diags = check_missing_fnend(&Structure::of(&tree, source), source);
         assert!(diags.is_empty(), "FNEND should close the function");
     }
 
@@ -815,13 +1837,7 @@ mod tests {
     fn end_def_closes_function() {
         let source = "def fnFoo(X)\nlet Y=X*2\nend def\n";
         let tree = parse(source);
-        let nodes = parser::collect_diagnostic_nodes(&tree, source);
-        let diags = check_missing_fnend(
-            &nodes.def_statements,
-            &nodes.fnend_statements,
-            &nodes.end_def_statements,
-            source,
-        );
+        let diags = check_missing_fnend(&Structure::of(&tree, source), source);
         assert!(diags.is_empty(), "END DEF should close the function");
     }
 
@@ -829,13 +1845,7 @@ mod tests {
     fn nested_missing_fnend() {
         let source = "def fnFoo(X)\nlet Y=X\ndef fnBar(Z)\nlet W=Z\nfnend\n";
         let tree = parse(source);
-        let nodes = parser::collect_diagnostic_nodes(&tree, source);
-        let diags = check_missing_fnend(
-            &nodes.def_statements,
-            &nodes.fnend_statements,
-            &nodes.end_def_statements,
-            source,
-        );
+        let diags = check_missing_fnend(&Structure::of(&tree, source), source);
         assert_eq!(diags.len(), 1);
         assert!(
             diags[0].message.contains("fnFoo"),
@@ -848,10 +1858,13 @@ mod tests {
         let source = "def fnFoo(X)=X\ndef fnFoo(Y)=Y\n";
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
-        let diags = check_duplicate_functions(&nodes.def_statements, source);
+        let diags = check_duplicate_functions(&nodes.def_statements, source, &test_uri());
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("fnFoo"));
         assert!(diags[0].message.contains("already defined"));
+        let related = diags[0].related_information.as_ref().unwrap();
+        assert_eq!(related[0].location.uri, test_uri());
+        assert_eq!(related[0].location.range.start, Position::new(0, 4));
     }
 
     #[test]
@@ -859,7 +1872,7 @@ mod tests {
         let source = "def fnFoo(X)=X\ndef FNFOO(Y)=Y\n";
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
-        let diags = check_duplicate_functions(&nodes.def_statements, source);
+        let diags = check_duplicate_functions(&nodes.def_statements, source, &test_uri());
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("already defined"));
     }
@@ -869,7 +1882,7 @@ mod tests {
         let source = "def fnFoo(X)=X\ndef fnBar(Y)=Y\n";
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
-        let diags = check_duplicate_functions(&nodes.def_statements, source);
+        let diags = check_duplicate_functions(&nodes.def_statements, source, &test_uri());
         assert!(diags.is_empty());
     }
 
@@ -881,12 +1894,20 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("fnFoo"));
         assert!(diags[0].message.contains("2"));
         assert!(diags[0].message.contains("1 provided"));
         assert_eq!(diags[0].severity, Some(DiagnosticSeverity::WARNING));
+        // Reported on the name, linked to the DEF
+        assert_eq!(
+            diags[0].range,
+            Range::new(Position::new(1, 6), Position::new(1, 11))
+        );
+        let related = diags[0].related_information.as_ref().unwrap();
+        assert_eq!(related[0].location.range.start, Position::new(0, 4));
+        assert_eq!(related[0].message, "'fnFoo' is defined here");
     }
 
     #[test]
@@ -895,7 +1916,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("fnFoo"));
         assert!(diags[0].message.contains("2 provided"));
@@ -907,7 +1928,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert!(diags.is_empty());
     }
 
@@ -917,7 +1938,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert!(diags.is_empty(), "1 arg is within 1-2 range");
     }
 
@@ -927,7 +1948,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("2-3"));
         assert!(diags[0].message.contains("1 provided"));
@@ -939,7 +1960,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("0 provided"));
     }
@@ -950,7 +1971,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("fnConst"));
         assert!(diags[0].message.contains("1 provided"));
@@ -962,7 +1983,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert!(diags.is_empty());
     }
 
@@ -972,7 +1993,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("Val"));
         assert!(diags[0].message.contains("2 provided"));
@@ -984,7 +2005,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert!(diags.is_empty(), "Date$ has optional second param");
     }
 
@@ -996,7 +2017,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert!(diags.is_empty(), "should match at least one overload");
     }
 
@@ -1006,7 +2027,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert!(diags.is_empty(), "(,) counts as 2 positions");
     }
 
@@ -1016,7 +2037,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("fnName$"));
         assert!(diags[0].message.contains("2 provided"));
@@ -1029,7 +2050,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert!(
             diags.is_empty(),
             "udim with inline args should not trigger diagnostic"
@@ -1044,7 +2065,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("numeric"));
         assert!(diags[0].message.contains("string"));
@@ -1057,7 +2078,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("string"));
         assert!(diags[0].message.contains("numeric"));
@@ -1069,7 +2090,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert!(diags.is_empty());
     }
 
@@ -1079,7 +2100,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("numeric array"));
         assert!(diags[0].message.contains("string array"));
@@ -1091,7 +2112,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("numeric"));
         assert!(diags[0].message.contains("numeric array"));
@@ -1104,7 +2125,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert!(
             diags.is_empty(),
             "scalar string for string array should be OK"
@@ -1118,7 +2139,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("string array"));
         assert!(diags[0].message.contains("numeric"));
@@ -1130,7 +2151,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert!(diags.is_empty());
     }
 
@@ -1140,7 +2161,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert!(diags.is_empty());
     }
 
@@ -1150,7 +2171,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("string"));
         assert!(diags[0].message.contains("numeric"));
@@ -1162,7 +2183,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert!(diags.is_empty());
     }
 
@@ -1172,7 +2193,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("numeric"));
         assert!(diags[0].message.contains("string"));
@@ -1185,7 +2206,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_parameter_count(&nodes.function_calls, source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, source, &defs, &test_uri());
         assert!(
             diags.is_empty(),
             "Mat2Str should accept string arrays: {diags:?}"
@@ -1204,7 +2225,7 @@ mod tests {
         let tree = parser::parse(&mut p, &source, None).expect("parse failed");
         let nodes = parser::collect_diagnostic_nodes(&tree, &source);
         let defs = extract::extract_definitions(&tree, &source);
-        let diags = check_parameter_count(&nodes.function_calls, &source, &defs);
+        let diags = check_parameter_count(&nodes.function_calls, &source, &defs, &test_uri());
 
         for d in &diags {
             let line = d.range.start.line + 1;
@@ -1260,6 +2281,7 @@ mod tests {
                 has_param_substitution: false,
                 documentation: None,
                 return_documentation: None,
+                deprecated: None,
             }],
         );
 
@@ -1327,8 +2349,7 @@ mod tests {
         let source = "dim A$(10)*30\n";
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
-        let diags =
-            check_unused_dim_variables(&nodes.var_ref_names, &nodes.dim_var_ref_names, source);
+        let diags = check_unused_variables(&nodes, &Structure::of(&tree, source), source, &[]);
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("declared but never used"));
         assert_eq!(diags[0].severity, Some(DiagnosticSeverity::HINT));
@@ -1340,8 +2361,7 @@ mod tests {
         let source = "dim A$(10)*30\nlet A$(1)=\"hello\"\n";
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
-        let diags =
-            check_unused_dim_variables(&nodes.var_ref_names, &nodes.dim_var_ref_names, source);
+        let diags = check_unused_variables(&nodes, &Structure::of(&tree, source), source, &[]);
         assert!(diags.is_empty(), "used dim variable should not be flagged");
     }
 
@@ -1350,8 +2370,7 @@ mod tests {
         let source = "dim X(5)\n";
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
-        let diags =
-            check_unused_dim_variables(&nodes.var_ref_names, &nodes.dim_var_ref_names, source);
+        let diags = check_unused_variables(&nodes, &Structure::of(&tree, source), source, &[]);
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("declared but never used"));
     }
@@ -1361,8 +2380,7 @@ mod tests {
         let source = "dim X(5)\nlet X(1)=42\n";
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
-        let diags =
-            check_unused_dim_variables(&nodes.var_ref_names, &nodes.dim_var_ref_names, source);
+        let diags = check_unused_variables(&nodes, &Structure::of(&tree, source), source, &[]);
         assert!(diags.is_empty());
     }
 
@@ -1371,8 +2389,7 @@ mod tests {
         let source = "dim MyVar$(3)*10\nlet MYVAR$(1)=\"test\"\n";
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
-        let diags =
-            check_unused_dim_variables(&nodes.var_ref_names, &nodes.dim_var_ref_names, source);
+        let diags = check_unused_variables(&nodes, &Structure::of(&tree, source), source, &[]);
         assert!(
             diags.is_empty(),
             "case-insensitive match should count as used"
@@ -1384,8 +2401,7 @@ mod tests {
         let source = "dim A$(10)*30\ndim B$(5)*20\nlet A$(1)=\"hi\"\n";
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
-        let diags =
-            check_unused_dim_variables(&nodes.var_ref_names, &nodes.dim_var_ref_names, source);
+        let diags = check_unused_variables(&nodes, &Structure::of(&tree, source), source, &[]);
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("B$"));
     }
@@ -1395,11 +2411,75 @@ mod tests {
         let source = "let X=1\nprint X\n";
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
-        let diags =
-            check_unused_dim_variables(&nodes.var_ref_names, &nodes.dim_var_ref_names, source);
+        let diags = check_unused_variables(&nodes, &Structure::of(&tree, source), source, &[]);
         assert!(diags.is_empty());
     }
 
+    /// Source after applying the fix on the unused-variable hint for `name`.
+    fn remove_dim_entry(source: &str, name: &str) -> String {
+        let tree = parse(source);
+        let nodes = parser::collect_diagnostic_nodes(&tree, source);
+        let diags = check_unused_variables(&nodes, &Structure::of(&tree, source), source, &[]);
+        let diag = diags
+            .iter()
+            .find(|d| d.message.contains(&format!("'{name}'")))
+            .unwrap();
+        let fix = Fix::from_diagnostic(diag).unwrap();
+        let edit = &fix.edits[0];
+        let offset = |pos: Position| {
+            source
+                .split_inclusive('\n')
+                .take(pos.line as usize)
+                .map(str::len)
+                .sum::<usize>()
+                + pos.character as usize
+        };
+        let mut fixed = source.to_string();
+        fixed.replace_range(offset(edit.range.start)..offset(edit.range.end), "");
+        fixed
+    }
+
+    #[test]
+    fn unused_dim_fix_removes_entry_and_comma() {
+        let source = "dim A$*30, B(10), C\nlet X = B(1) + C\n";
+        assert_eq!(
+            remove_dim_entry(source, "A$"),
+            "dim B(10), C\nlet X = B(1) + C\n"
+        );
+        let source = "dim A, B(10), C\nlet X = A + C\n";
+        assert_eq!(remove_dim_entry(source, "B"), "dim A, C\nlet X = A + C\n");
+    }
+
+    #[test]
+    fn unused_dim_fix_removes_lone_statement() {
+        let source = "00100 dim X\n00110 print 1\n";
+        assert_eq!(remove_dim_entry(source, "X"), "00110 print 1\n");
+        let source = "00100 dim X ! scratch\n00110 print 1\n";
+        assert_eq!(
+            remove_dim_entry(source, "X"),
+            "00100 ! scratch\n00110 print 1\n"
+        );
+    }
+
+    #[test]
+    fn unused_dim_fix_leaves_other_statements() {
+        let source = "00100 dim X : print 1\n";
+        assert_eq!(remove_dim_entry(source, "X"), "00100 print 1\n");
+        let source = "00100 print 1 !: dim X\n";
+        assert_eq!(remove_dim_entry(source, "X"), "00100 print 1\n");
+        let source = "00100 dim X !: print 1\n";
+        assert_eq!(remove_dim_entry(source, "X"), "00100 print 1\n");
+        let source = "00100 print Y$ : dim X, Y$*20, Z(5)\n";
+        assert_eq!(
+            remove_dim_entry(source, "X"),
+            "00100 print Y$ : dim Y$*20, Z(5)\n"
+        );
+        assert_eq!(
+            remove_dim_entry(source, "Z"),
+            "00100 print Y$ : dim X, Y$*20\n"
+        );
+    }
+
     // --- Unused library import tests ---
 
     #[test]
@@ -1450,6 +2530,44 @@ mod tests {
         );
     }
 
+    #[test]
+    fn unused_import_fix_edits_the_list() {
+        let removed = |source: &str| -> Vec<Option<String>> {
+            let tree = parse(source);
+            let nodes = parser::collect_diagnostic_nodes(&tree, source);
+            let defs = extract::extract_definitions(&tree, source);
+            check_unused_library_imports(&nodes.function_names, source, &defs)
+                .iter()
+                .map(|d| {
+                    let edit = Fix::from_diagnostic(d)?.edits.remove(0);
+                    let mut rope = ropey::Rope::from_str(source);
+                    let char_at =
+                        |p: Position| rope.line_to_char(p.line as usize) + p.character as usize;
+                    let (start, end) = (char_at(edit.range.start), char_at(edit.range.end));
+                    rope.remove(start..end);
+                    Some(rope.to_string())
+                })
+                .collect()
+        };
+        let calls = "let X=fnB$(1)\n";
+        assert_eq!(
+            removed(&format!(
+                "library \"u\": fnA, fnB$ ,fnC\nlibrary \"x\": fnD\n{calls}"
+            )),
+            vec![
+                Some(format!(
+                    "library \"u\": fnB$ ,fnC\nlibrary \"x\": fnD\n{calls}"
+                )),
+                Some(format!(
+                    "library \"u\": fnA, fnB$\nlibrary \"x\": fnD\n{calls}"
+                )),
+                Some(format!("library \"u\": fnA, fnB$ ,fnC\n{calls}")),
+            ]
+        );
+        // An empty list doesn't parse, so a shared line keeps its only name
+        assert_eq!(removed("library \"x\": fnD : print 1\n"), vec![None]);
+    }
+
     #[test]
     fn no_library_imports_no_diagnostic() {
         let source = "let X=1\n";
@@ -1468,7 +2586,7 @@ mod tests {
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
         let defs = extract::extract_definitions(&tree, source);
-        let diags = check_unused_variables(&nodes, source, &defs);
+        let diags = check_unused_variables(&nodes, &Structure::of(&tree, source), source, &defs);
         // A$ is unused dim, fnCalc is used
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("A$"));
@@ -1481,12 +2599,12 @@ mod tests {
         let source = "def fnFoo(X)\nlet Y = 1\nfnend\n";
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
-        let diags = check_unused_parameters(
+        let diags = check_unused_parameters_chunked(
             &nodes.param_ident_names,
             &nodes.var_ref_names,
-            &nodes.def_statements,
-            &nodes.fnend_statements,
+            &Structure::of(&tree, source),
             source,
+            WHOLE_FILE,
         );
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("X"));
@@ -1500,12 +2618,12 @@ mod tests {
         let source = "def fnFoo(X)\nlet Y = X + 1\nfnend\n";
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
-        let diags = check_unused_parameters(
+        let diags = check_unused_parameters_chunked(
             &nodes.param_ident_names,
             &nodes.var_ref_names,
-            &nodes.def_statements,
-            &nodes.fnend_statements,
+            &Structure::of(&tree, source),
             source,
+            WHOLE_FILE,
         );
         assert!(diags.is_empty(), "used parameter should not be flagged");
     }
@@ -1515,12 +2633,12 @@ mod tests {
         let source = "def fnFoo(X, Y)\nlet Z = X + 1\nfnend\n";
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
-        let diags = check_unused_parameters(
+        let diags = check_unused_parameters_chunked(
             &nodes.param_ident_names,
             &nodes.var_ref_names,
-            &nodes.def_statements,
-            &nodes.fnend_statements,
+            &Structure::of(&tree, source),
             source,
+            WHOLE_FILE,
         );
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("Y"));
@@ -1531,12 +2649,12 @@ mod tests {
         let source = "def fnFoo$(A$)\nlet B$ = \"hello\"\nfnend\n";
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
-        let diags = check_unused_parameters(
+        let diags = check_unused_parameters_chunked(
             &nodes.param_ident_names,
             &nodes.var_ref_names,
-            &nodes.def_statements,
-            &nodes.fnend_statements,
+            &Structure::of(&tree, source),
             source,
+            WHOLE_FILE,
         );
         assert_eq!(diags.len(), 1);
         assert!(diags[0].message.contains("A$"));
@@ -1547,12 +2665,12 @@ mod tests {
         let source = "def fnFoo$(A$)\nlet B$ = A$\nfnend\n";
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
-        let diags = check_unused_parameters(
+        let diags = check_unused_parameters_chunked(
             &nodes.param_ident_names,
             &nodes.var_ref_names,
-            &nodes.def_statements,
-            &nodes.fnend_statements,
+            &Structure::of(&tree, source),
             source,
+            WHOLE_FILE,
         );
         assert!(diags.is_empty());
     }
@@ -1562,31 +2680,582 @@ mod tests {
         let source = "let X = 1\nprint X\n";
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
-        let diags = check_unused_parameters(
+        let diags = check_unused_parameters_chunked(
             &nodes.param_ident_names,
             &nodes.var_ref_names,
-            &nodes.def_statements,
-            &nodes.fnend_statements,
+            &Structure::of(&tree, source),
             source,
+            WHOLE_FILE,
         );
         assert!(diags.is_empty());
     }
 
+    #[test]
+    fn params_after_placeholder_not_flagged() {
+        let source = "def fnFoo(X, Y, ___, Z)\nlet W = X\nfnend\n";
+        let tree = parse(source);
+        let nodes = parser::collect_diagnostic_nodes(&tree, source);
+        let diags = check_unused_parameters(&nodes, &Structure::of(&tree, source), source);
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].message.contains("'Y'"));
+        assert_eq!(
+            diags[0].code,
+            Some(NumberOrString::String("unused-parameter".to_string()))
+        );
+    }
+
+    #[test]
+    fn unused_params_not_reported_as_unused_variables() {
+        let source = "def fnFoo(X)\nlet Y = 1\nfnend\n";
+        let tree = parse(source);
+        let nodes = parser::collect_diagnostic_nodes(&tree, source);
+        let defs = extract::extract_definitions(&tree, source);
+        assert!(
+            check_unused_variables(&nodes, &Structure::of(&tree, source), source, &defs).is_empty()
+        );
+    }
+
     #[test]
     fn unused_param_case_insensitive() {
         let source = "def fnFoo(X)\nlet Y = x + 1\nfnend\n";
         let tree = parse(source);
         let nodes = parser::collect_diagnostic_nodes(&tree, source);
-        let diags = check_unused_parameters(
+        let diags = check_unused_parameters_chunked(
             &nodes.param_ident_names,
             &nodes.var_ref_names,
-            &nodes.def_statements,
-            &nodes.fnend_statements,
+            &Structure::of(&tree, source),
             source,
+            WHOLE_FILE,
         );
         assert!(
             diags.is_empty(),
             "case-insensitive reference should count as used"
         );
     }
+
+    // --- Error condition tests ---
+
+    #[test]
+    fn error_condition_targets_checked() {
+        let source = "00100 read #1: a$ nokey 300 eof Finish ioerr 100\n00200 let x=val(a$) conv done\nDone: stop\n";
+        let tree = parse(source);
+        let diags = check_error_condition_targets(&tree, source);
+        let messages: Vec<&str> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "NOKEY jumps to line 300, which is not defined in this file",
+                "EOF jumps to label `Finish`, which is not defined in this file",
+            ]
+        );
+        assert_eq!(diags[0].range.start.character, 24);
+    }
+
+    #[test]
+    fn pic_masks_checked() {
+        let source = "00100 form pos 1,pic(zzq.##),c 10\n00200 form pic(zzz,zz#.##)\n";
+        let tree = parse(source);
+        let diags = check_pic_masks(&tree, source);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].message, "`q` is not a PIC mask character");
+        assert_eq!(
+            diags[0].range,
+            Range::new(Position::new(0, 23), Position::new(0, 24))
+        );
+    }
+
+    // --- File number tests ---
+
+    fn file_number_diags(source: &str) -> Vec<Diagnostic> {
+        let tree = parse(source);
+        check_file_numbers(&files::collect_file_usage(&tree, source), &test_uri())
+    }
+
+    #[test]
+    fn file_open_read_close_ok() {
+        let source = "open #1: \"name=x\", internal, input\nread #1: X\nclose #1:\nopen #1: \"name=y\", internal, input\n";
+        assert!(file_number_diags(source).is_empty());
+    }
+
+    #[test]
+    fn file_opened_twice_flagged() {
+        let source = "open #1: \"name=x\", internal, input\nopen #1: \"name=y\", internal, input\n";
+        let diags = file_number_diags(source);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].range.start.line, 1);
+        assert!(diags[0].message.contains("opened on line 1"));
+        let related = diags[0].related_information.as_ref().unwrap();
+        assert_eq!(related[0].location.range.start.line, 0);
+    }
+
+    #[test]
+    fn file_opened_in_if_and_else_ok() {
+        let source = "if X then\n  open #1: \"name=x\", internal, input\nelse\n  open #1: \"name=y\", internal, input\nend if\n";
+        assert!(file_number_diags(source).is_empty());
+        let source = "if X then open #1: \"name=x\", internal, input else open #1: \"name=y\", internal, input\n";
+        assert!(file_number_diags(source).is_empty());
+    }
+
+    #[test]
+    fn file_reopened_by_ioerr_retry_ok() {
+        let source = "open #1: \"name=x\", internal, input ioerr Retry\nread #1: X\nRetry: open #1: \"name=y\", internal, input\n";
+        assert!(file_number_diags(source).is_empty());
+        let source = "00100 open #1: \"name=x\", internal, input ioerr 200\n00200 open #1: \"name=y\", internal, input\n";
+        assert!(file_number_diags(source).is_empty());
+    }
+
+    #[test]
+    fn file_opened_twice_on_numbered_lines_flagged_unless_jumped_to() {
+        let source = "00100 open #1: \"name=x\", internal, input\n00200 open #1: \"name=y\", internal, input\n00300 goto 200\n";
+        assert!(file_number_diags(source).is_empty());
+        let source = "00100 open #1: \"name=x\", internal, input\n00200 open #1: \"name=y\", internal, input\n";
+        assert_eq!(file_number_diags(source).len(), 1);
+    }
+
+    #[test]
+    fn file_close_never_opened_flagged() {
+        let diags = file_number_diags("close #3:\n");
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].message,
+            "File #3 is closed but never opened in this file"
+        );
+    }
+
+    #[test]
+    fn file_read_never_opened_flagged() {
+        let diags = file_number_diags("read #2: X\n");
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].message.contains("is used but never opened"));
+    }
+
+    #[test]
+    fn file_open_after_use_not_flagged() {
+        // Subroutines commonly OPEN below the code that uses the file
+        let source = "gosub OpenFiles\nread #1: X\nOpenFiles: open #1: \"name=x\", internal, input\nreturn\n";
+        assert!(file_number_diags(source).is_empty());
+    }
+
+    #[test]
+    fn file_implicit_and_variable_numbers_ignored() {
+        let source = "print #0: \"x\"\nprint #255: \"y\"\nread #Cust: X\n";
+        assert!(file_number_diags(source).is_empty());
+    }
+
+    fn record_length_diags(source: &str) -> Vec<Diagnostic> {
+        let mut layouts = LayoutIndex::new();
+        let layout =
+            crate::layout::parse("customer.dat, CU_, 1\nrecl=128\n----------\nNAME$, Name, C 30\n")
+                .unwrap();
+        layouts.add("file:///filelay/customer", layout);
+        let tree = parse(source);
+        check_record_lengths(&files::collect_file_usage(&tree, source), &layouts)
+    }
+
+    #[test]
+    fn record_length_mismatch_flagged() {
+        let diags =
+            record_length_diags("open #1: \"name=data/customer.dat,recl=100\", internal, outin\n");
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].code,
+            Some(NumberOrString::String("record-length-mismatch".to_string()))
+        );
+        assert!(diags[0].message.contains("RecL=100"));
+        assert!(diags[0].message.contains("128"));
+    }
+
+    fn key_field_diags(source: &str) -> Vec<Diagnostic> {
+        let mut layouts = LayoutIndex::new();
+        let layout = crate::layout::parse(
+            "customer.dat, CU_, 1\ncustomer.ix1, CU_ID$\ncustomer.ix2, CU_NAME$, CU_ID$\n----------\nID$, Id, C 8\nNAME$, Name, C 30\n",
+        )
+        .unwrap();
+        layouts.add("file:///filelay/customer", layout);
+        let tree = parse(source);
+        check_key_fields(&files::collect_file_usage(&tree, source), &layouts)
+    }
+
+    #[test]
+    fn key_fields_mismatch_flagged() {
+        let diags = key_field_diags(
+            "open #1: \"name=customer.dat,kfname=customer.ix1,kps=1,kln=10\", internal, outin, keyed\n",
+        );
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].message,
+            "KPs=1,KLn=10 does not match key `customer.ix1` in the layout (KPs=1,KLn=8 from CU_ID$)"
+        );
+    }
+
+    #[test]
+    fn key_fields_matching_or_partial_ok() {
+        let ok = [
+            "open #1: \"name=customer.dat,kfname=customer.ix1,kps=1,kln=8\", internal, outin, keyed\n",
+            "open #1: \"name=customer.dat,kfname=customer.ix2,kps=9/1,kln=30/8\", internal, outin, keyed\n",
+            "open #1: \"name=customer.dat,kfname=customer.ix1,kps=5\", internal, outin, keyed\n",
+            "open #1: \"name=customer.dat,kfname=customer.ix9,kps=5,kln=2\", internal, outin, keyed\n",
+        ];
+        for source in ok {
+            assert!(key_field_diags(source).is_empty(), "{source}");
+        }
+    }
+
+    #[test]
+    fn record_length_checked_against_fields_without_recl_line() {
+        let mut layouts = LayoutIndex::new();
+        let layout = crate::layout::parse(
+            "vendor.dat, VE_, 1\n----------\nNAME$, Name, C 30\nBAL, Bal, PD 6.2\n",
+        )
+        .unwrap();
+        layouts.add("file:///filelay/vendor", layout);
+        let check = |source: &str| {
+            let tree = parse(source);
+            check_record_lengths(&files::collect_file_usage(&tree, source), &layouts)
+        };
+        let diags = check("open #1: \"name=vendor.dat,recl=32\", internal, outin\n");
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].message.contains("shorter than the 36 bytes"));
+        assert!(check("open #1: \"name=vendor.dat,recl=64\", internal, outin\n").is_empty());
+    }
+
+    #[test]
+    fn record_length_matching_or_unknown_ok() {
+        assert!(
+            record_length_diags("open #1: \"name=customer.dat,recl=128\", internal, outin\n")
+                .is_empty()
+        );
+        assert!(
+            record_length_diags("open #1: \"name=customer.dat\", internal, input\n").is_empty()
+        );
+        assert!(
+            record_length_diags("open #1: \"name=vendor.dat,recl=64\", internal, outin\n")
+                .is_empty()
+        );
+    }
+
+    fn version_diags(source: &str, version: &str) -> Vec<Diagnostic> {
+        let tree = parse(source);
+        let nodes = parser::collect_diagnostic_nodes(&tree, source);
+        let version = builtins::BrVersion::parse(version).unwrap();
+        check_builtin_versions(&nodes.function_calls, source, version)
+    }
+
+    #[test]
+    fn builtin_newer_than_version_flagged() {
+        let diags = version_diags("let X$=Encrypt$(\"abc\")\n", "4.2");
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].message,
+            "'Encrypt$' is not available in BR 4.2 (requires 4.3)"
+        );
+        assert_eq!(
+            diags[0].code,
+            Some(NumberOrString::String("unavailable-in-version".to_string()))
+        );
+    }
+
+    #[test]
+    fn builtin_available_in_version_ok() {
+        assert!(version_diags("let X$=Encrypt$(\"abc\")\n", "4.3").is_empty());
+        assert!(version_diags("let X$=Str$(1)\n", "4.0").is_empty());
+    }
+
+    #[test]
+    fn statements_newer_than_version_flagged() {
+        let source = "00100 SCR_FREEZE\n00110 if X then scr_thaw\n00120 print 1\n";
+        let tree = parse(source);
+        let version = |v: &str| builtins::BrVersion::parse(v).unwrap();
+        let diags = check_statement_versions(&tree, source, version("4.1"));
+        let messages: Vec<&str> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "'SCR_FREEZE' is not available in BR 4.1 (requires 4.2)",
+                "'scr_thaw' is not available in BR 4.1 (requires 4.2)",
+            ]
+        );
+        assert_eq!(
+            diags[0].range,
+            Range::new(Position::new(0, 6), Position::new(0, 16))
+        );
+        assert!(check_statement_versions(&tree, source, version("4.2")).is_empty());
+    }
+
+    fn doc_param_diags(source: &str) -> Vec<Diagnostic> {
+        let tree = parse(source);
+        let nodes = parser::collect_diagnostic_nodes(&tree, source);
+        let defs = extract::extract_definitions(&tree, source);
+        check_doc_comment_params(&nodes.def_statements, source, &defs)
+    }
+
+    #[test]
+    fn doc_params_matching_ok() {
+        let source =
+            "/** Adds\n  * @param a first\n  * @param B second\n  */\ndef fnAdd(A, B) = A + B\n";
+        assert!(doc_param_diags(source).is_empty());
+    }
+
+    #[test]
+    fn doc_without_params_not_checked() {
+        let source = "/** Adds two numbers */\ndef fnAdd(A, B) = A + B\n";
+        assert!(doc_param_diags(source).is_empty());
+    }
+
+    #[test]
+    fn doc_param_misspelled() {
+        let source =
+            "/** Adds\n  * @param A first\n  * @param Bee second\n  */\ndef fnAdd(A, B) = A + B\n";
+        let diags = doc_param_diags(source);
+        assert_eq!(diags.len(), 2);
+        assert_eq!(
+            diags[0].code,
+            Some(NumberOrString::String("doc-param-unknown".to_string()))
+        );
+        assert_eq!(diags[0].range.start, Position::new(2, 11));
+        assert_eq!(diags[0].range.end, Position::new(2, 14));
+        assert_eq!(
+            diags[1].message,
+            "Parameter 'B' of fnAdd has no @param in its doc comment"
+        );
+        assert_eq!(diags[1].range.start.line, 4);
+    }
+
+    fn value_param_diags(source: &str) -> Vec<Diagnostic> {
+        let tree = parse(source);
+        let nodes = parser::collect_diagnostic_nodes(&tree, source);
+        check_value_param_assignments(&nodes, &Structure::of(&tree, source), source)
+    }
+
+    #[test]
+    fn value_param_assignment_flagged() {
+        let source = "def fnFoo(X, N$)
+let X = 1
+N$ = \"a\"
+fnend
+";
+        let diags = value_param_diags(source);
+        assert_eq!(diags.len(), 2);
+        assert_eq!(
+            diags[0].code,
+            Some(NumberOrString::String("value-param-assignment".to_string()))
+        );
+        assert_eq!(diags[0].range.start, Position::new(1, 4));
+        assert!(diags[1].message.contains("'&N$'"));
+
+        let fix = Fix::from_diagnostic(&diags[1]).unwrap();
+        assert_eq!(fix.title, "Pass 'N$' by reference");
+        assert_eq!(fix.edits[0].range.start, Position::new(0, 13));
+        assert_eq!(fix.edits[0].new_text, "&");
+    }
+
+    #[test]
+    fn reference_param_assignment_ok() {
+        let source = "def fnFoo(&X, &N$)
+let X = 1
+N$ = \"a\"
+fnend
+";
+        assert!(value_param_diags(source).is_empty());
+    }
+
+    #[test]
+    fn value_param_read_or_compared_ok() {
+        let source = "def fnFoo(X)
+let Y = X
+print X = 1
+fnend
+let X = 2
+";
+        assert!(value_param_diags(source).is_empty());
+    }
+
+    #[test]
+    fn deprecated_call_struck_through() {
+        let source = "/** @deprecated Use fnSum */\ndef fnAdd(A, B) = A + B\nlet X = fnAdd(1, 2)\n";
+        let tree = parse(source);
+        let nodes = parser::collect_diagnostic_nodes(&tree, source);
+        let defs = extract::extract_definitions(&tree, source);
+        let diags = check_deprecated_calls(&nodes.function_calls, source, &defs, None, &test_uri());
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].message, "'fnAdd' is deprecated: Use fnSum");
+        assert_eq!(diags[0].tags, Some(vec![DiagnosticTag::DEPRECATED]));
+        assert_eq!(diags[0].range.start, Position::new(2, 8));
+    }
+
+    #[test]
+    fn deprecated_call_from_index() {
+        let lib = "/** @deprecated */\ndef library fnOld\nfnend\n";
+        let lib_tree = parse(lib);
+        let mut index = WorkspaceIndex::new();
+        index.add_file(
+            &tower_lsp::lsp_types::Url::parse("file:///lib.brs").unwrap(),
+            extract::extract_definitions(&lib_tree, lib),
+        );
+
+        let source = "let X = fnOld\n";
+        let tree = parse(source);
+        let nodes = parser::collect_diagnostic_nodes(&tree, source);
+        let diags = check_deprecated_calls(
+            &nodes.function_calls,
+            source,
+            &[],
+            Some(&index),
+            &test_uri(),
+        );
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].message, "'fnOld' is deprecated");
+        let related = diags[0].related_information.as_ref().unwrap();
+        assert_eq!(related[0].location.uri.as_str(), "file:///lib.brs");
+    }
+
+    #[test]
+    fn line_chunks_cover_file_in_order() {
+        let source = "print 1\nprint 2\nprint 3\n";
+        let tree = parse(source);
+        let chunks = line_chunks(tree.root_node(), 2);
+        assert_eq!(chunks, vec![0..16, 16..usize::MAX]);
+        assert_eq!(
+            line_chunks(tree.root_node(), PARALLEL_CHUNK_LINES),
+            vec![0..usize::MAX]
+        );
+    }
+
+    #[test]
+    fn unused_variables_share_main_code_and_subroutines_but_not_parameters() {
+        let unused = |source: &str| -> Vec<String> {
+            let tree = parse(source);
+            let nodes = parser::collect_diagnostic_nodes(&tree, source);
+            let structure = Structure::of(&tree, source);
+            let mut cached: Vec<String> = check_unused_variables_cached(
+                &nodes,
+                &structure,
+                source,
+                &[],
+                &mut VariableUsage::default(),
+            )
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+            let mut messages: Vec<String> = check_unused_variables(&nodes, &structure, source, &[])
+                .into_iter()
+                .map(|d| d.message)
+                .collect();
+            messages.sort();
+            cached.sort();
+            assert_eq!(messages, cached);
+            messages
+        };
+
+        // Set in main code, read only in the GOSUB'd subroutine
+        let gosub = "dim Total
+let Total = 5
+gosub Show
+stop
+Show: print Total
+return
+";
+        assert!(unused(gosub).is_empty());
+
+        // Only a parameter of the same name uses it
+        let shadowed = "dim Name$*30, Rate
+def fnGreet$(&Name$)
+  dim Name$*40
+  let fnGreet$ = \"Hi \" & Name$
+fnend
+def fnPct(Rate) = Rate / 100
+";
+        assert_eq!(
+            unused(shadowed),
+            [
+                "'Name$' is declared but never used",
+                "'Rate' is declared but never used"
+            ]
+        );
+    }
+
+    #[test]
+    fn cached_unused_variables_recount_only_edited_functions() {
+        let unused = |source: &str, usage: Option<&mut VariableUsage>| -> Vec<String> {
+            let tree = parse(source);
+            let nodes = parser::collect_diagnostic_nodes(&tree, source);
+            let structure = Structure::of(&tree, source);
+            let diags = match usage {
+                Some(usage) => {
+                    check_unused_variables_cached(&nodes, &structure, source, &[], usage)
+                }
+                None => check_unused_variables(&nodes, &structure, source, &[]),
+            };
+            let mut messages: Vec<String> = diags.into_iter().map(|d| d.message).collect();
+            messages.sort();
+            messages
+        };
+        let edit = |start: usize, old_len: usize, new_len: usize| InputEdit {
+            start_byte: start,
+            old_end_byte: start + old_len,
+            new_end_byte: start + new_len,
+            start_position: tree_sitter::Point::default(),
+            old_end_position: tree_sitter::Point::default(),
+            new_end_position: tree_sitter::Point::default(),
+        };
+
+        let before = "dim A, B\ndef fnX\nlet A = 1\nfnend\ndef fnY\nlet Z = 2\nfnend\n";
+        let mut usage = VariableUsage::default();
+        assert_eq!(
+            unused(before, Some(&mut usage)),
+            ["'B' is declared but never used"]
+        );
+        let regions = usage.regions.len();
+
+        // Typing inside fnY only drops fnY
+        let after = before.replace("let Z", "let B");
+        usage.edit(&edit(before.find('Z').unwrap(), 1, 1));
+        assert_eq!(usage.regions.len(), regions - 1);
+        assert!(unused(&after, Some(&mut usage)).is_empty());
+        assert_eq!(usage.regions.len(), regions);
+
+        // An edit above the functions moves them along
+        let grown = after.replacen("dim A, B", "dim A, B, C", 1);
+        usage.edit(&edit("dim A, B".len(), 0, ", C".len()));
+        assert_eq!(usage.regions.len(), regions - 1);
+        assert_eq!(unused(&grown, Some(&mut usage)), unused(&grown, None));
+        assert_eq!(
+            unused(&grown, Some(&mut usage)),
+            ["'C' is declared but never used"]
+        );
+    }
+
+    #[test]
+    fn chunked_checks_match_single_pass() {
+        let source = "dim Unused$*10\n\
+                      def fnA(X, Y)\nlet Y = 1\nfnend\n\
+                      def fnB(P)\nlet P = 2\nfnend\n\
+                      def fnC(Q)\nprint 1\nfnend\n";
+        let tree = parse(source);
+        let nodes = parser::collect_diagnostic_nodes(&tree, source);
+        let structure = Structure::of(&tree, source);
+        let chunks = line_chunks(tree.root_node(), 1);
+        assert!(chunks.len() > 1);
+
+        let params = |chunks: &[ByteRange<usize>]| {
+            check_unused_parameters_chunked(
+                &nodes.param_ident_names,
+                &nodes.var_ref_names,
+                &structure,
+                source,
+                chunks,
+            )
+        };
+        assert_eq!(params(&chunks), params(WHOLE_FILE));
+        assert_eq!(params(&chunks).len(), 2);
+
+        assert_eq!(
+            check_value_param_assignments_chunked(&nodes, &structure, source, &chunks),
+            check_value_param_assignments_chunked(&nodes, &structure, source, WHOLE_FILE)
+        );
+        assert_eq!(
+            check_value_param_assignments_chunked(&nodes, &structure, source, &chunks).len(),
+            2
+        );
+    }
 }
@@ -0,0 +1,154 @@
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::Notify;
+
+/// How many documents compute diagnostics at once. Enough to keep a second
+/// file moving while one is slow, few enough that the file being edited
+/// isn't queued behind every document the editor restored.
+pub const DIAGNOSTICS_CONCURRENCY: usize = 2;
+
+#[derive(Default)]
+struct State {
+    running: usize,
+    waiting: BTreeSet<u64>,
+}
+
+/// Admits diagnostics tasks a few at a time, newest first. Each scheduling
+/// takes a priority from [`DiagnosticsQueue::next_priority`] when the
+/// document is opened or changed, so when many documents are restored at
+/// startup the last one opened, usually the visible one, goes ahead of the
+/// rest, and a document being edited goes ahead of all of them.
+pub struct DiagnosticsQueue {
+    limit: usize,
+    next: AtomicU64,
+    state: Mutex<State>,
+    notify: Notify,
+}
+
+impl DiagnosticsQueue {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit: limit.max(1),
+            next: AtomicU64::new(0),
+            state: Mutex::new(State::default()),
+            notify: Notify::new(),
+        }
+    }
+
+    /// A priority above every one handed out before.
+    pub fn next_priority(&self) -> u64 {
+        self.next.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Wait until fewer than `limit` tasks are running and no waiting task
+    /// has a higher priority. The slot is freed when the permit drops.
+    pub async fn acquire(self: &Arc<Self>, priority: u64) -> DiagnosticsPermit {
+        self.state.lock().unwrap().waiting.insert(priority);
+        // Leaves the queue if this future is dropped while waiting
+        let mut waiting = Waiting {
+            queue: self,
+            priority,
+            admitted: false,
+        };
+        loop {
+            // Registered before checking, so a release in between still wakes us
+            let released = self.notify.notified();
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.running < self.limit && state.waiting.last() == Some(&priority) {
+                    state.waiting.remove(&priority);
+                    state.running += 1;
+                    waiting.admitted = true;
+                    return DiagnosticsPermit {
+                        queue: Arc::clone(self),
+                    };
+                }
+            }
+            released.await;
+        }
+    }
+}
+
+struct Waiting<'a> {
+    queue: &'a DiagnosticsQueue,
+    priority: u64,
+    admitted: bool,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        if !self.admitted {
+            self.queue
+                .state
+                .lock()
+                .unwrap()
+                .waiting
+                .remove(&self.priority);
+            self.queue.notify.notify_waiters();
+        }
+    }
+}
+
+/// A running slot in the [`DiagnosticsQueue`].
+pub struct DiagnosticsPermit {
+    queue: Arc<DiagnosticsQueue>,
+}
+
+impl Drop for DiagnosticsPermit {
+    fn drop(&mut self) {
+        self.queue.state.lock().unwrap().running -= 1;
+        self.queue.notify.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn newest_waiting_task_runs_first() {
+        let queue = Arc::new(DiagnosticsQueue::new(1));
+        let first = queue.acquire(queue.next_priority()).await;
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let priorities: Vec<u64> = (0..3).map(|_| queue.next_priority()).collect();
+        for &priority in &priorities {
+            let queue = queue.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let _permit = queue.acquire(priority).await;
+                tx.send(priority).unwrap();
+            });
+        }
+        drop(tx);
+        // Let every task join the queue before the slot frees up
+        while queue.state.lock().unwrap().waiting.len() < priorities.len() {
+            tokio::task::yield_now().await;
+        }
+        drop(first);
+
+        let mut order = Vec::new();
+        while let Some(priority) = rx.recv().await {
+            order.push(priority);
+        }
+        let mut expected = priorities;
+        expected.reverse();
+        assert_eq!(order, expected);
+    }
+
+    #[tokio::test]
+    async fn abandoned_waiters_leave_the_queue() {
+        let queue = Arc::new(DiagnosticsQueue::new(1));
+        let held = queue.acquire(1).await;
+        let abandoned =
+            tokio::time::timeout(std::time::Duration::from_millis(10), queue.acquire(3));
+        assert!(abandoned.await.is_err());
+        drop(held);
+
+        // Priority 2 isn't stuck behind the abandoned 3
+        let _permit = queue.acquire(2).await;
+        assert!(queue.state.lock().unwrap().waiting.is_empty());
+    }
+}
@@ -0,0 +1,247 @@
+//! `.brs` and `.wbs` dialects. Each file's dialect comes from its extension;
+//! `br-lsp.dialects` lists the statements, keywords and builtins only one
+//! of them accepts, so the other can flag them and leave them out of
+//! completions.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+use serde_json::Value;
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, Diagnostic, DiagnosticSeverity, NumberOrString,
+};
+use tree_sitter::{Node, Tree};
+
+use crate::parser::node_range;
+use crate::style::is_styled_token;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    Brs,
+    Wbs,
+}
+
+impl Dialect {
+    /// The dialect of a path or URI: `.wbs` files are Web BR, everything
+    /// else (including extensionless programs) is `.brs`.
+    pub fn of(path: &str) -> Self {
+        let wbs = Path::new(path)
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("wbs"));
+        if wbs {
+            Self::Wbs
+        } else {
+            Self::Brs
+        }
+    }
+
+    fn other(self) -> Self {
+        match self {
+            Self::Brs => Self::Wbs,
+            Self::Wbs => Self::Brs,
+        }
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Brs => ".brs",
+            Self::Wbs => ".wbs",
+        })
+    }
+}
+
+/// Words only one dialect accepts, lowercased.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Exclusive {
+    /// Statements, keywords and error conditions
+    keywords: HashSet<String>,
+    builtins: HashSet<String>,
+}
+
+impl Exclusive {
+    fn from_settings(value: Option<&Value>) -> Self {
+        let words = |key: &str| -> HashSet<String> {
+            value
+                .and_then(|v| v.get(key))
+                .and_then(|v| v.as_array())
+                .map(|words| {
+                    words
+                        .iter()
+                        .filter_map(|w| w.as_str())
+                        .map(|w| w.trim().to_ascii_lowercase())
+                        .filter(|w| !w.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        Self {
+            keywords: words("keywords"),
+            builtins: words("builtins"),
+        }
+    }
+
+    fn contains(&self, word: &str, builtin: bool) -> bool {
+        let word = word.to_ascii_lowercase();
+        if builtin {
+            self.builtins.contains(&word)
+        } else {
+            self.keywords.contains(&word)
+        }
+    }
+}
+
+/// The dialect-only word lists from `br-lsp.dialects`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DialectConfig {
+    brs_only: Exclusive,
+    wbs_only: Exclusive,
+}
+
+impl DialectConfig {
+    /// Read the `dialects` object of the `br-lsp` section.
+    pub fn from_settings(dialects: Option<&Value>) -> Self {
+        Self {
+            brs_only: Exclusive::from_settings(dialects.and_then(|d| d.get("brsOnly"))),
+            wbs_only: Exclusive::from_settings(dialects.and_then(|d| d.get("wbsOnly"))),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        [&self.brs_only, &self.wbs_only]
+            .iter()
+            .any(|only| !only.keywords.is_empty() || !only.builtins.is_empty())
+    }
+
+    fn only(&self, dialect: Dialect) -> &Exclusive {
+        match dialect {
+            Dialect::Brs => &self.brs_only,
+            Dialect::Wbs => &self.wbs_only,
+        }
+    }
+
+    /// The other dialect, when `word` is listed as only belonging there.
+    /// A word listed for both belongs to both.
+    fn foreign(&self, dialect: Dialect, word: &str, builtin: bool) -> Option<Dialect> {
+        let other = dialect.other();
+        (self.only(other).contains(word, builtin) && !self.only(dialect).contains(word, builtin))
+            .then_some(other)
+    }
+
+    /// Whether a statement, keyword or builtin completion can be offered in
+    /// a `dialect` file. Other completions are always allowed.
+    pub fn allows_completion(&self, dialect: Dialect, item: &CompletionItem) -> bool {
+        let builtin = match item.kind {
+            Some(CompletionItemKind::KEYWORD) => false,
+            Some(CompletionItemKind::FUNCTION) => true,
+            _ => return true,
+        };
+        self.foreign(dialect, &item.label, builtin).is_none()
+    }
+}
+
+/// Warn about statements, keywords and builtins that only the other
+/// dialect accepts.
+pub fn check_dialect(
+    tree: &Tree,
+    source: &str,
+    dialect: Dialect,
+    config: &DialectConfig,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    walk(tree.root_node(), source, dialect, config, &mut diagnostics);
+    diagnostics
+}
+
+fn walk(
+    node: Node,
+    source: &str,
+    dialect: Dialect,
+    config: &DialectConfig,
+    out: &mut Vec<Diagnostic>,
+) {
+    if is_styled_token(node) {
+        let builtin = node.kind() == "function_name";
+        if let Ok(text) = node.utf8_text(source.as_bytes()) {
+            if let Some(other) = config.foreign(dialect, text, builtin) {
+                out.push(Diagnostic {
+                    range: node_range(node),
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(NumberOrString::String("wrong-dialect".to_string())),
+                    message: format!("'{text}' is only available in {other} files"),
+                    ..Default::default()
+                });
+            }
+        }
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        walk(child, source, dialect, config, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn config() -> DialectConfig {
+        DialectConfig::from_settings(Some(&serde_json::json!({
+            "wbsOnly": { "keywords": ["Print"], "builtins": [] },
+            "brsOnly": { "builtins": ["env$"] },
+        })))
+    }
+
+    fn check(source: &str, path: &str) -> Vec<Diagnostic> {
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        check_dialect(&tree, source, Dialect::of(path), &config())
+    }
+
+    #[test]
+    fn dialect_comes_from_the_extension() {
+        assert_eq!(Dialect::of("file:///src/page.WBS"), Dialect::Wbs);
+        assert_eq!(Dialect::of("/src/prog.brs"), Dialect::Brs);
+        assert_eq!(Dialect::of("/src/prog"), Dialect::Brs);
+    }
+
+    #[test]
+    fn constructs_of_the_other_dialect_are_reported() {
+        let source = "let X$ = env$(\"HOME\")\nprint X$\n";
+
+        let brs = check(source, "prog.brs");
+        assert_eq!(brs.len(), 1);
+        assert_eq!(brs[0].message, "'print' is only available in .wbs files");
+        assert_eq!(brs[0].range.start.line, 1);
+
+        let wbs = check(source, "page.wbs");
+        assert_eq!(wbs.len(), 1);
+        assert_eq!(wbs[0].message, "'env$' is only available in .brs files");
+
+        assert!(!DialectConfig::default().is_enabled());
+        assert!(config().is_enabled());
+    }
+
+    #[test]
+    fn completions_leave_out_the_other_dialect() {
+        let item = |label: &str, kind| CompletionItem {
+            label: label.to_string(),
+            kind: Some(kind),
+            ..Default::default()
+        };
+        let config = config();
+        let print = item("print", CompletionItemKind::KEYWORD);
+        let env = item("Env$", CompletionItemKind::FUNCTION);
+        assert!(!config.allows_completion(Dialect::Brs, &print));
+        assert!(config.allows_completion(Dialect::Wbs, &print));
+        assert!(config.allows_completion(Dialect::Brs, &env));
+        assert!(!config.allows_completion(Dialect::Wbs, &env));
+        // A variable named like a keyword is still offered
+        let variable = item("print", CompletionItemKind::VARIABLE);
+        assert!(config.allows_completion(Dialect::Brs, &variable));
+    }
+}
@@ -0,0 +1,388 @@
+use std::path::{Path, PathBuf};
+
+use tower_lsp::lsp_types::{Position, Range};
+use tree_sitter::{Node, Tree};
+
+use crate::workspace::DriveMappings;
+
+/// Which statement a program reference came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgramReferenceKind {
+    Library,
+    Chain,
+    Run,
+}
+
+/// A literal program name in a LIBRARY, CHAIN or `EXECUTE "RUN ..."` statement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgramReference {
+    pub kind: ProgramReferenceKind,
+    /// The name as written, without quotes
+    pub name: String,
+    /// Range of the name inside the string literal
+    pub range: Range,
+}
+
+/// Collect the program names a document refers to. Only plain string
+/// literals are considered; names built at runtime can't be resolved.
+pub fn collect_program_references(tree: &Tree, source: &str) -> Vec<ProgramReference> {
+    let mut refs = Vec::new();
+    collect(tree.root_node(), source, &mut refs);
+    refs
+}
+
+fn collect(node: Node, source: &str, refs: &mut Vec<ProgramReference>) {
+    match node.kind() {
+        "library_statement" => {
+            if let Some(string) = node.child_by_field_name("path").and_then(literal_string) {
+                push_reference(ProgramReferenceKind::Library, string, source, None, refs);
+            }
+            return;
+        }
+        "chain_statement" => {
+            if let Some(string) = first_string_expression(node).and_then(literal_string) {
+                push_reference(ProgramReferenceKind::Chain, string, source, None, refs);
+            }
+            return;
+        }
+        "execute_statement" => {
+            if let Some(string) = first_string_expression(node).and_then(literal_string) {
+                push_reference(ProgramReferenceKind::Run, string, source, Some("run"), refs);
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect(child, source, refs);
+    }
+}
+
+fn first_string_expression(node: Node) -> Option<Node> {
+    let mut cursor = node.walk();
+    let found = node
+        .children(&mut cursor)
+        .find(|c| c.kind() == "string_expression");
+    found
+}
+
+/// The `string` node of an expression that is nothing but a string literal.
+fn literal_string(expr: Node) -> Option<Node> {
+    let primary = expr.named_child(0)?;
+    if expr.named_child_count() != 1 || primary.kind() != "string_primary_expression" {
+        return None;
+    }
+    let string = primary.named_child(0)?;
+    (primary.named_child_count() == 1 && string.kind() == "string").then_some(string)
+}
+
+/// Record the program name held by `string`. With a `command`, the string
+/// must start with that BR command and the name is the token after it.
+fn push_reference(
+    kind: ProgramReferenceKind,
+    string: Node,
+    source: &str,
+    command: Option<&str>,
+    refs: &mut Vec<ProgramReference>,
+) {
+    let Ok(text) = string.utf8_text(source.as_bytes()) else {
+        return;
+    };
+    if text.len() < 2 || string.start_position().row != string.end_position().row {
+        return;
+    }
+    let content = &text[1..text.len() - 1];
+
+    let (start, name) = match command {
+        Some(command) => {
+            let rest = content.trim_start();
+            let lead = content.len() - rest.len();
+            let Some(keyword) = rest.get(..command.len()) else {
+                return;
+            };
+            if !keyword.eq_ignore_ascii_case(command) {
+                return;
+            }
+            let after = &rest[command.len()..];
+            let args = after.trim_start();
+            if args.len() == after.len() {
+                // "RUNNER" is not "RUN NER"
+                return;
+            }
+            let name_len = args
+                .find(|c: char| c.is_whitespace() || c == ',')
+                .unwrap_or(args.len());
+            let offset = lead + command.len() + (after.len() - args.len());
+            (offset, &args[..name_len])
+        }
+        None => (0, content.trim_end()),
+    };
+    if name.is_empty() || name.contains('[') {
+        return;
+    }
+
+    let pos = string.start_position();
+    let line = pos.row as u32;
+    let first = (pos.column + 1 + start) as u32;
+    refs.push(ProgramReference {
+        kind,
+        name: name.to_string(),
+        range: Range {
+            start: Position {
+                line,
+                character: first,
+            },
+            end: Position {
+                line,
+                character: first + name.len() as u32,
+            },
+        },
+    });
+}
+
+/// The reference whose name covers `position`.
+pub fn reference_at(refs: &[ProgramReference], position: Position) -> Option<&ProgramReference> {
+    refs.iter()
+        .find(|r| r.range.start <= position && position <= r.range.end)
+}
+
+/// The references among `refs` naming the program at `target`, each
+/// resolved as [`resolve_program`] does against `bases`.
+pub fn references_to<'a>(
+    refs: &'a [ProgramReference],
+    target: &'a Path,
+    bases: &'a [PathBuf],
+    mappings: &'a DriveMappings,
+    workspace_folders: &'a [PathBuf],
+) -> impl Iterator<Item = &'a ProgramReference> {
+    refs.iter().filter(move |r| {
+        resolve_program(&r.name, bases, mappings, workspace_folders)
+            .is_some_and(|path| same_file(&path, target))
+    })
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    a == b
+        || matches!(
+            (a.canonicalize(), b.canonicalize()),
+            (Ok(a), Ok(b)) if a == b
+        )
+}
+
+/// Find the source file for a program name. A name starting with a mapped
+/// drive is looked up under the mapping's directories first; otherwise each
+/// base directory is tried in order. BR names are case-insensitive, may use
+/// backslashes and a drive prefix, and usually name the compiled `.br`/`.wb`
+/// program or leave the extension off; both are mapped to the `.brs`/`.wbs`
+/// source.
+pub fn resolve_program(
+    name: &str,
+    bases: &[PathBuf],
+    mappings: &DriveMappings,
+    workspace_folders: &[PathBuf],
+) -> Option<PathBuf> {
+    if let Some((target, rest)) = mappings.split(name) {
+        let dirs = DriveMappings::target_dirs(target, workspace_folders);
+        if let Some(found) = resolve_relative(&rest, &dirs) {
+            return Some(found);
+        }
+    }
+
+    let normalized = name.replace('\\', "/");
+    let mut relative = normalized.as_str();
+    if relative.len() >= 2 && relative.as_bytes()[1] == b':' {
+        relative = &relative[2..];
+    }
+    resolve_relative(relative, bases)
+}
+
+fn resolve_relative(relative: &str, bases: &[PathBuf]) -> Option<PathBuf> {
+    let relative = relative.trim_start_matches('/');
+    if relative.is_empty() {
+        return None;
+    }
+
+    let candidates = source_candidates(relative);
+    bases.iter().find_map(|base| {
+        candidates
+            .iter()
+            .find_map(|candidate| find_case_insensitive(base, candidate))
+    })
+}
+
+fn source_candidates(relative: &str) -> Vec<String> {
+    let (stem, ext) = match relative.rsplit_once('.') {
+        Some((stem, ext)) if !ext.contains('/') => (stem, ext.to_ascii_lowercase()),
+        _ => (relative, String::new()),
+    };
+    match ext.as_str() {
+        "brs" | "wbs" => vec![relative.to_string()],
+        "br" => vec![format!("{stem}.brs")],
+        "wb" => vec![format!("{stem}.wbs")],
+        _ => vec![format!("{relative}.brs"), format!("{relative}.wbs")],
+    }
+}
+
+/// Join `relative` onto `base`, matching each component case-insensitively
+/// when the exact spelling doesn't exist.
+fn find_case_insensitive(base: &Path, relative: &str) -> Option<PathBuf> {
+    let mut path = base.to_path_buf();
+    for component in relative.split('/').filter(|c| !c.is_empty() && *c != ".") {
+        let exact = path.join(component);
+        if exact.exists() {
+            path = exact;
+            continue;
+        }
+        let entry = std::fs::read_dir(&path).ok()?.flatten().find(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|n| n.eq_ignore_ascii_case(component))
+        })?;
+        path = entry.path();
+    }
+    path.is_file().then_some(path)
+}
+
+/// Directories to resolve program names against: the workspace folders,
+/// then the configured search paths (relative ones are tried under each
+/// workspace folder), then the referring document's own directory.
+pub fn search_bases(
+    workspace_folders: &[PathBuf],
+    search_paths: &[String],
+    document_dir: Option<&Path>,
+) -> Vec<PathBuf> {
+    let mut bases: Vec<PathBuf> = workspace_folders.to_vec();
+    for search in search_paths {
+        let search = Path::new(search);
+        if search.is_absolute() {
+            bases.push(search.to_path_buf());
+        } else {
+            bases.extend(workspace_folders.iter().map(|folder| folder.join(search)));
+        }
+    }
+    if let Some(dir) = document_dir {
+        bases.push(dir.to_path_buf());
+    }
+    bases.dedup();
+    bases
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn references(source: &str) -> Vec<ProgramReference> {
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        collect_program_references(&tree, source)
+    }
+
+    #[test]
+    fn collects_chain_run_and_library_names() {
+        let source = "00100 chain \"menu\\main\",files\n00200 execute \"RUN Reports/Daily\"\n00300 library \"custlib\": fnA\n";
+        let refs = references(source);
+        assert_eq!(refs.len(), 3);
+
+        assert_eq!(refs[0].kind, ProgramReferenceKind::Chain);
+        assert_eq!(refs[0].name, "menu\\main");
+        assert_eq!(refs[0].range.start.character, 13);
+        assert_eq!(refs[0].range.end.character, 22);
+
+        assert_eq!(refs[1].kind, ProgramReferenceKind::Run);
+        assert_eq!(refs[1].name, "Reports/Daily");
+        assert_eq!(refs[1].range.start, Position::new(1, 19));
+        assert_eq!(refs[1].range.end, Position::new(1, 32));
+
+        assert_eq!(refs[2].kind, ProgramReferenceKind::Library);
+        assert_eq!(refs[2].name, "custlib");
+    }
+
+    #[test]
+    fn skips_computed_names_and_other_commands() {
+        let source = "00100 chain \"x\"&a$\n00200 execute \"COPY a b\"\n00300 execute \"RUNNER\"\n00400 chain a$\n";
+        assert!(references(source).is_empty());
+    }
+
+    #[test]
+    fn resolves_compiled_names_to_source_case_insensitively() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("reports")).unwrap();
+        std::fs::write(dir.path().join("reports").join("daily.brs"), "").unwrap();
+        let bases = vec![dir.path().to_path_buf()];
+
+        let expected = dir.path().join("reports").join("daily.brs");
+        let none = DriveMappings::default();
+        assert_eq!(
+            resolve_program("REPORTS\\Daily.br", &bases, &none, &bases),
+            Some(expected.clone())
+        );
+        assert_eq!(
+            resolve_program("c:reports/daily", &bases, &none, &bases),
+            Some(expected)
+        );
+        assert_eq!(
+            resolve_program("reports/weekly", &bases, &none, &bases),
+            None
+        );
+    }
+
+    #[test]
+    fn finds_references_resolving_to_a_program() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("reports")).unwrap();
+        let daily = dir.path().join("reports").join("daily.brs");
+        std::fs::write(&daily, "").unwrap();
+        std::fs::write(dir.path().join("reports").join("weekly.brs"), "").unwrap();
+        let bases = vec![dir.path().to_path_buf()];
+
+        let source = "00100 chain \"reports\\daily\"\n00200 execute \"RUN reports/Weekly\"\n00300 library \"REPORTS/DAILY.BR\": fnA\n";
+        let refs = references(source);
+        let none = DriveMappings::default();
+        let lines: Vec<u32> = references_to(&refs, &daily, &bases, &none, &bases)
+            .map(|r| r.range.start.line)
+            .collect();
+        assert_eq!(lines, vec![0, 2]);
+
+        let at = reference_at(&refs, Position::new(1, 25)).unwrap();
+        assert_eq!(at.name, "reports/Weekly");
+        assert!(reference_at(&refs, Position::new(1, 8)).is_none());
+    }
+
+    #[test]
+    fn resolves_through_drive_mappings() {
+        let dir = tempfile::tempdir().unwrap();
+        let shared = dir.path().join("shared").join("vol002");
+        std::fs::create_dir_all(&shared).unwrap();
+        std::fs::write(shared.join("rtflib.brs"), "").unwrap();
+        let folders = vec![dir.path().to_path_buf()];
+        let mappings =
+            DriveMappings::from_settings(Some(&serde_json::json!({ "VOL002": "shared/vol002" })));
+
+        assert_eq!(
+            resolve_program("vol002\\RTFLib.br", &folders, &mappings, &folders),
+            Some(shared.join("rtflib.brs"))
+        );
+    }
+
+    #[test]
+    fn search_paths_are_relative_to_workspace_folders() {
+        let bases = search_bases(
+            &[PathBuf::from("/ws")],
+            &["lib".to_string(), "/shared".to_string()],
+            Some(Path::new("/ws/menu")),
+        );
+        assert_eq!(
+            bases,
+            vec![
+                PathBuf::from("/ws"),
+                PathBuf::from("/ws/lib"),
+                PathBuf::from("/shared"),
+                PathBuf::from("/ws/menu"),
+            ]
+        );
+    }
+}
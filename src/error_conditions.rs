@@ -0,0 +1,233 @@
+use std::collections::HashSet;
+
+use tower_lsp::lsp_types::Range;
+use tree_sitter::{Node, Tree};
+
+use crate::parser::node_range;
+
+/// Conditions any statement can trap.
+pub const GENERAL_CONDITIONS: &[(&str, &str)] = &[
+    ("error", "Any error not trapped by another condition"),
+    ("exit", "Use the conditions listed on an EXIT statement"),
+    ("conv", "Conversion error"),
+    ("soflow", "String overflow"),
+    ("oflow", "Numeric overflow"),
+    ("zdiv", "Division by zero"),
+];
+
+/// Conditions only file and screen I/O statements can trap.
+pub const IO_CONDITIONS: &[(&str, &str)] = &[
+    ("eof", "End of file"),
+    ("ioerr", "Any I/O error"),
+    ("nokey", "Key not found"),
+    ("norec", "Record not found"),
+    ("duprec", "Duplicate record or key"),
+    ("locked", "Record locked by another user"),
+    ("timeout", "Input timed out"),
+    ("pageoflow", "Printer page overflow"),
+];
+
+/// Statements that read or write files or the screen.
+const IO_STATEMENTS: &[&str] = &[
+    "read_statement",
+    "reread_statement",
+    "write_statement",
+    "rewrite_statement",
+    "delete_statement",
+    "restore_statement",
+    "input_statement",
+    "linput_statement",
+    "rinput_statement",
+    "print_statement",
+    "open_statement",
+    "close_statement",
+];
+
+/// Whether `kind` is a statement that does file or screen I/O.
+pub fn is_io_statement(kind: &str) -> bool {
+    IO_STATEMENTS.contains(&kind)
+}
+
+/// The conditions valid on a statement of the given kind.
+pub fn valid_conditions(statement_kind: &str) -> Vec<(&'static str, &'static str)> {
+    let mut conditions = GENERAL_CONDITIONS.to_vec();
+    if is_io_statement(statement_kind) {
+        conditions.extend_from_slice(IO_CONDITIONS);
+    }
+    conditions
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetKind {
+    Label,
+    Line,
+}
+
+/// Where a condition jumps to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JumpTarget {
+    pub kind: TargetKind,
+    pub text: String,
+    pub range: Range,
+}
+
+/// One `NOKEY 300` or `EOF Done` clause.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorCondition {
+    /// Lowercase condition keyword
+    pub keyword: String,
+    pub range: Range,
+    pub statement_kind: String,
+    pub target: Option<JumpTarget>,
+}
+
+/// Every error-condition clause in the tree, in source order.
+pub fn collect_error_conditions(tree: &Tree, source: &str) -> Vec<ErrorCondition> {
+    let mut conditions = Vec::new();
+    collect(tree.root_node(), source, &mut conditions);
+    conditions
+}
+
+fn collect(node: Node, source: &str, conditions: &mut Vec<ErrorCondition>) {
+    if node.kind() == "error_condition_list" {
+        let statement_kind = node.parent().map_or("", |p| p.kind()).to_string();
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            let Ok(text) = child.utf8_text(source.as_bytes()) else {
+                continue;
+            };
+            let kind = match child.kind() {
+                "error_condition" => {
+                    conditions.push(ErrorCondition {
+                        keyword: text.to_ascii_lowercase(),
+                        range: node_range(child),
+                        statement_kind: statement_kind.clone(),
+                        target: None,
+                    });
+                    continue;
+                }
+                "label_reference" => TargetKind::Label,
+                "line_reference" => TargetKind::Line,
+                _ => continue,
+            };
+            if let Some(last) = conditions.last_mut().filter(|c| c.target.is_none()) {
+                last.target = Some(JumpTarget {
+                    kind,
+                    text: text.to_string(),
+                    range: node_range(child),
+                });
+            }
+        }
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect(child, source, conditions);
+    }
+}
+
+/// Labels (lowercase, without the colon) and line numbers defined in a file.
+#[derive(Debug, Default)]
+pub struct DefinedTargets {
+    pub labels: HashSet<String>,
+    pub lines: HashSet<u32>,
+}
+
+impl DefinedTargets {
+    pub fn collect(tree: &Tree, source: &str) -> Self {
+        let mut targets = Self::default();
+        let mut stack = vec![tree.root_node()];
+        while let Some(node) = stack.pop() {
+            let text = || node.utf8_text(source.as_bytes()).unwrap_or("").trim();
+            match node.kind() {
+                "label" => {
+                    let name = text().trim_end_matches(':').to_ascii_lowercase();
+                    targets.labels.insert(name);
+                }
+                "line_number" => {
+                    if let Ok(n) = text().parse() {
+                        targets.lines.insert(n);
+                    }
+                }
+                _ => {
+                    let mut cursor = node.walk();
+                    stack.extend(node.children(&mut cursor));
+                }
+            }
+        }
+        targets
+    }
+
+    pub fn contains(&self, target: &JumpTarget) -> bool {
+        match target.kind {
+            TargetKind::Label => self.labels.contains(&target.text.to_ascii_lowercase()),
+            TargetKind::Line => target
+                .text
+                .trim()
+                .parse()
+                .is_ok_and(|n: u32| self.lines.contains(&n)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn parse(source: &str) -> Tree {
+        let mut p = parser::new_parser();
+        parser::parse(&mut p, source, None).unwrap()
+    }
+
+    #[test]
+    fn pairs_conditions_with_targets() {
+        let source = "00100 read #1,using 100: a$ nokey 300, eof Done ioerr 400\n00200 let x=val(a$) conv 400\nDone: stop\n";
+        let tree = parse(source);
+        let conditions = collect_error_conditions(&tree, source);
+        let summary: Vec<_> = conditions
+            .iter()
+            .map(|c| {
+                (
+                    c.keyword.as_str(),
+                    c.statement_kind.as_str(),
+                    c.target.as_ref().map(|t| (t.kind, t.text.as_str())),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("nokey", "read_statement", Some((TargetKind::Line, "300"))),
+                ("eof", "read_statement", Some((TargetKind::Label, "Done"))),
+                ("ioerr", "read_statement", Some((TargetKind::Line, "400"))),
+                ("conv", "let_statement", Some((TargetKind::Line, "400"))),
+            ]
+        );
+    }
+
+    #[test]
+    fn defined_targets_ignore_label_case() {
+        let source = "00100 goto done\nDONE: stop\n";
+        let tree = parse(source);
+        let targets = DefinedTargets::collect(&tree, source);
+        let label = |text: &str| JumpTarget {
+            kind: TargetKind::Label,
+            text: text.to_string(),
+            range: Range::default(),
+        };
+        assert!(targets.contains(&label("Done")));
+        assert!(!targets.contains(&label("Finish")));
+        assert!(targets.lines.contains(&100));
+    }
+
+    #[test]
+    fn io_statements_get_io_conditions() {
+        let read = valid_conditions("read_statement");
+        assert!(read.iter().any(|(k, _)| *k == "nokey"));
+        let assign = valid_conditions("let_statement");
+        assert!(assign.iter().any(|(k, _)| *k == "conv"));
+        assert!(!assign.iter().any(|(k, _)| *k == "eof"));
+    }
+}
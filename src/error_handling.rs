@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+
+use tower_lsp::lsp_types::Range;
+use tree_sitter::{Node, Tree};
+
+use crate::check::csv_escape;
+use crate::error_conditions;
+use crate::parser::node_range;
+use crate::references::{get_function_ranges, in_function};
+
+/// A file I/O statement that nothing traps errors for: it has no
+/// error-condition clause and no `ON ERROR GOTO`/`GOSUB` is in effect.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnhandledIo {
+    /// Statement keyword, uppercase (`READ`, `WRITE`, ...)
+    pub statement: String,
+    pub range: Range,
+    /// First line of the statement's source
+    pub text: String,
+}
+
+/// Result of checking one file.
+#[derive(Debug, Default)]
+pub struct ErrorHandlingReport {
+    /// File I/O statements seen
+    pub io_statements: usize,
+    pub unhandled: Vec<UnhandledIo>,
+}
+
+/// Find the file I/O statements in a program that would stop it with an
+/// untrapped error. An `ON ERROR GOTO`/`GOSUB` covers the statements after
+/// it in the same function (or in the main program) until an `ON ERROR
+/// SYSTEM` or `IGNORE`; functions start without a handler.
+pub fn check_error_handling(tree: &Tree, source: &str) -> ErrorHandlingReport {
+    let functions = get_function_ranges(tree, source);
+    let mut handled: HashMap<Option<usize>, bool> = HashMap::new();
+    let mut report = ErrorHandlingReport::default();
+
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        let scope = in_function(node.start_byte(), &functions);
+        match node.kind() {
+            "on_statement" if is_on_error(node, source) => {
+                let traps = children(node)
+                    .any(|c| matches!(c.kind(), "goto_statement" | "gosub_statement"));
+                handled.insert(scope, traps);
+                continue;
+            }
+            kind if is_file_io(node) => {
+                report.io_statements += 1;
+                let has_conditions = children(node).any(|c| c.kind() == "error_condition_list");
+                if !has_conditions && !handled.get(&scope).copied().unwrap_or(false) {
+                    report.unhandled.push(UnhandledIo {
+                        statement: kind.trim_end_matches("_statement").to_ascii_uppercase(),
+                        range: node_range(node),
+                        text: node
+                            .utf8_text(source.as_bytes())
+                            .unwrap_or("")
+                            .lines()
+                            .next()
+                            .unwrap_or("")
+                            .trim()
+                            .to_string(),
+                    });
+                }
+            }
+            _ => {}
+        }
+        // Children pushed in reverse so they're visited in source order
+        let mut cursor = node.walk();
+        let kids: Vec<Node> = node.children(&mut cursor).collect();
+        stack.extend(kids.into_iter().rev());
+    }
+    report
+}
+
+fn children(node: Node) -> impl Iterator<Item = Node> {
+    (0..node.child_count()).filter_map(move |i| node.child(i))
+}
+
+fn is_on_error(node: Node, source: &str) -> bool {
+    children(node)
+        .find(|c| c.kind() == "error_condition")
+        .and_then(|c| c.utf8_text(source.as_bytes()).ok())
+        .is_some_and(|c| c.eq_ignore_ascii_case("error"))
+}
+
+/// I/O statements on a file number (`READ #1`, `CLOSE #2`...); screen I/O
+/// without one rarely fails and isn't reported.
+fn is_file_io(node: Node) -> bool {
+    error_conditions::is_io_statement(node.kind())
+        && children(node).any(|c| matches!(c.kind(), "channel" | "#"))
+}
+
+/// Format unhandled statements as CSV with a header row. Lines and columns
+/// are 1-based.
+pub fn format_csv(rows: &[(String, UnhandledIo)]) -> String {
+    let mut out = String::from("file,line,column,statement,text\n");
+    for (file, io) in rows {
+        out.push_str(&format!(
+            "{},{},{},{},{}\n",
+            csv_escape(file),
+            io.range.start.line + 1,
+            io.range.start.character + 1,
+            io.statement,
+            csv_escape(&io.text)
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn unhandled(source: &str) -> Vec<(String, u32)> {
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        check_error_handling(&tree, source)
+            .unhandled
+            .into_iter()
+            .map(|io| (io.statement, io.range.start.line))
+            .collect()
+    }
+
+    #[test]
+    fn conditions_and_on_error_cover_statements() {
+        let source = "\
+00100 open #1: \"name=x\", internal, input
+00110 read #1: a$ eof 900
+00120 on error goto 900
+00130 read #1: a$
+00140 on error system
+00150 close #1:
+00160 print \"done\"
+00900 stop
+";
+        assert_eq!(
+            unhandled(source),
+            vec![("OPEN".to_string(), 0), ("CLOSE".to_string(), 5)]
+        );
+    }
+
+    #[test]
+    fn functions_have_their_own_handler() {
+        let source = "\
+00100 on error goto 900
+00110 def fnLoad
+00120   read #1: a$
+00130 fnend
+00140 write #1: a$
+00900 stop
+";
+        assert_eq!(unhandled(source), vec![("READ".to_string(), 2)]);
+    }
+
+    #[test]
+    fn csv_has_one_row_per_statement() {
+        let io = UnhandledIo {
+            statement: "READ".to_string(),
+            range: Range::default(),
+            text: "read #1,using f: a$".to_string(),
+        };
+        assert_eq!(
+            format_csv(&[("menu.brs".to_string(), io)]),
+            "file,line,column,statement,text\nmenu.brs,1,1,READ,\"read #1,using f: a$\"\n"
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use serde_json::{json, Value};
+
+use crate::builtins::{self, BuiltinFunction};
+use crate::diagnostics::format_param_kind;
+use crate::extract::{FunctionDef, ParamInfo};
+use crate::handlers::hover::format_user_hover;
+use crate::layout::{Layout, LayoutIndex};
+use crate::workspace::{IndexedFunctionDef, WorkspaceIndex};
+
+/// Build the `br-lsp.exportIndex` payload: every indexed function and layout,
+/// sorted for stable output so the result can be diffed between runs.
+pub fn export_index(index: &WorkspaceIndex, layouts: &LayoutIndex) -> Value {
+    let mut functions = index.all_symbols();
+    functions.sort_by(|a, b| {
+        a.def
+            .name
+            .to_ascii_lowercase()
+            .cmp(&b.def.name.to_ascii_lowercase())
+            .then_with(|| a.uri.as_str().cmp(b.uri.as_str()))
+            .then_with(|| a.def.range.start.line.cmp(&b.def.range.start.line))
+    });
+
+    let functions: Vec<Value> = functions
+        .into_iter()
+        .map(|f| function_json(f.uri.as_str(), &f.def))
+        .collect();
+
+    let mut layouts: Vec<(&str, &Layout)> = layouts.iter().collect();
+    layouts.sort_by(|a, b| a.0.cmp(b.0));
+    let layouts: Vec<Value> = layouts
+        .into_iter()
+        .map(|(uri, layout)| layout_json(uri, layout))
+        .collect();
+
+    json!({
+        "functions": functions,
+        "layouts": layouts,
+    })
+}
+
+pub(crate) fn function_json(uri: &str, def: &FunctionDef) -> Value {
+    json!({
+        "name": def.name,
+        "file": uri,
+        "range": def.range,
+        "selectionRange": def.selection_range,
+        "signature": def.format_signature(),
+        "isLibrary": def.is_library,
+        "isImportOnly": def.is_import_only,
+        "params": def.params.iter().map(param_json).collect::<Vec<_>>(),
+        "documentation": def.documentation,
+        "returnDocumentation": def.return_documentation,
+        "deprecated": def.deprecated,
+    })
+}
+
+/// Build the `br-lsp/functionInfo` payload for `name`: a built-in's
+/// overloads, or the workspace definitions in the order hover ranks them
+/// (`defs`), with the one hover shows as `definition`. `null` when the
+/// name is unknown.
+pub fn function_info(name: &str, defs: &[&IndexedFunctionDef]) -> Value {
+    let overloads = builtins::lookup(name);
+    if let Some(first) = overloads.first() {
+        return json!({
+            "name": first.name,
+            "kind": "builtin",
+            "overloads": overloads.iter().map(builtin_json).collect::<Vec<_>>(),
+        });
+    }
+
+    let Some(best) = defs.iter().find(|d| !d.def.is_import_only).or(defs.first()) else {
+        return Value::Null;
+    };
+    json!({
+        "name": best.def.name,
+        "kind": "user",
+        "definition": function_json(best.uri.as_str(), &best.def),
+        "definitions": defs
+            .iter()
+            .map(|d| function_json(d.uri.as_str(), &d.def))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// Build the `br-lsp.extractDocs` payload: the functions of each file, in
+/// [`WorkspaceIndex::functions_in`] order, with the markdown hover shows
+/// for them.
+pub fn extract_docs(functions: &[&IndexedFunctionDef]) -> Value {
+    let mut files: Vec<Value> = Vec::new();
+    for group in functions.chunk_by(|a, b| a.uri == b.uri) {
+        let functions: Vec<Value> = group
+            .iter()
+            .map(|f| {
+                json!({
+                    "name": f.def.name,
+                    "signature": f.def.format_signature(),
+                    "range": f.def.range,
+                    "selectionRange": f.def.selection_range,
+                    "isLibrary": f.def.is_library,
+                    "deprecated": f.def.deprecated,
+                    "markdown": format_user_hover(&f.def),
+                })
+            })
+            .collect();
+        files.push(json!({
+            "file": group[0].uri,
+            "functions": functions,
+        }));
+    }
+    json!({ "files": files })
+}
+
+fn builtin_json(builtin: &BuiltinFunction) -> Value {
+    json!({
+        "signature": builtin.format_signature(),
+        "documentation": builtin.documentation,
+        "since": builtin.since,
+        "params": builtin
+            .params
+            .iter()
+            .map(|p| {
+                json!({
+                    "name": p.name,
+                    "kind": p.kind().map(format_param_kind),
+                    "documentation": p.documentation,
+                })
+            })
+            .collect::<Vec<_>>(),
+    })
+}
+
+fn param_json(param: &ParamInfo) -> Value {
+    json!({
+        "name": param.name,
+        "kind": format_param_kind(param.kind),
+        "optional": param.is_optional,
+        "reference": param.is_reference,
+        "documentation": param.documentation,
+    })
+}
+
+fn layout_json(uri: &str, layout: &Layout) -> Value {
+    json!({
+        "file": uri,
+        "path": layout.path,
+        "prefix": layout.prefix,
+        "version": layout.version,
+        "recordLength": layout.record_length,
+        "computedRecordLength": layout.computed_record_length(),
+        "keys": layout
+            .keys
+            .iter()
+            .map(|k| json!({ "path": k.path, "keyFields": k.key_fields }))
+            .collect::<Vec<_>>(),
+        "subscripts": layout
+            .subscripts
+            .iter()
+            .map(|s| {
+                json!({
+                    "name": s.name,
+                    "description": s.description,
+                    "format": s.format,
+                    "offset": s.offset,
+                    "length": s.length,
+                })
+            })
+            .collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{extract, layout, parser};
+    use tower_lsp::lsp_types::Url;
+
+    fn index_source(index: &mut WorkspaceIndex, name: &str, source: &str) {
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        let uri = Url::parse(&format!("file:///workspace/{name}")).unwrap();
+        index.add_file(&uri, extract::extract_definitions(&tree, source));
+    }
+
+    #[test]
+    fn exports_functions_sorted_with_params() {
+        let mut index = WorkspaceIndex::new();
+        index_source(&mut index, "b.brs", "def fnZeta(X) = X\n");
+        index_source(&mut index, "a.brs", "\n\ndef fnAdd(A, &B$; C) = A\n");
+
+        let out = export_index(&index, &LayoutIndex::new());
+        let functions = out["functions"].as_array().unwrap();
+        assert_eq!(functions.len(), 2);
+        assert_eq!(functions[0]["name"], "fnAdd");
+        assert_eq!(functions[0]["file"], "file:///workspace/a.brs");
+        assert_eq!(functions[0]["range"]["start"]["line"], 2);
+        let params = functions[0]["params"].as_array().unwrap();
+        assert_eq!(params.len(), 3);
+        assert_eq!(params[1]["kind"], "string");
+        assert_eq!(params[1]["reference"], true);
+        assert_eq!(params[2]["optional"], true);
+        assert_eq!(functions[1]["name"], "fnZeta");
+        assert!(out["layouts"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn function_info_prefers_definitions_over_imports() {
+        let mut index = WorkspaceIndex::new();
+        index_source(&mut index, "main.brs", "library \"lib\": fnAdd\n");
+        index_source(
+            &mut index,
+            "lib.brs",
+            "/**\n * Adds.\n * @param A first\n */\ndef library fnAdd(A, B) = A + B\n",
+        );
+        let mut defs: Vec<&IndexedFunctionDef> = index.lookup("fnadd").iter().collect();
+        defs.sort_by_key(|d| !d.def.is_import_only);
+
+        let info = function_info("fnadd", &defs);
+        assert_eq!(info["kind"], "user");
+        assert_eq!(info["name"], "fnAdd");
+        assert_eq!(info["definition"]["file"], "file:///workspace/lib.brs");
+        assert_eq!(info["definition"]["isLibrary"], true);
+        assert_eq!(info["definition"]["params"][0]["documentation"], "first");
+        assert_eq!(info["definitions"].as_array().unwrap().len(), 2);
+
+        assert!(function_info("fnMissing", &[]).is_null());
+    }
+
+    #[test]
+    fn extracts_hover_docs_by_file() {
+        let mut index = WorkspaceIndex::new();
+        index_source(
+            &mut index,
+            "lib/b.brs",
+            "/**\n * Adds.\n * @param A first\n */\ndef library fnAdd(A, B) = A + B\n",
+        );
+        index_source(
+            &mut index,
+            "lib/a.brs",
+            "library \"b\": fnAdd\ndef fnOne = 1\ndef fnTwo = 2\n",
+        );
+        index_source(&mut index, "main.brs", "def fnMain = 0\n");
+
+        let docs = extract_docs(&index.functions_in("/workspace/lib/**"));
+        let files = docs["files"].as_array().unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0]["file"], "file:///workspace/lib/a.brs");
+        let names: Vec<&str> = files[0]["functions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["fnOne", "fnTwo"]);
+        let add = &files[1]["functions"][0];
+        assert_eq!(add["isLibrary"], true);
+        assert_eq!(
+            add["markdown"],
+            "```br\nfnAdd(A, B)\n```\n\n---\n\nAdds.\n\n*@param* `A` \u{2014} first"
+        );
+    }
+
+    #[test]
+    fn function_info_for_builtins() {
+        let info = function_info("val", &[]);
+        assert_eq!(info["kind"], "builtin");
+        assert_eq!(info["name"], "Val");
+        let overload = &info["overloads"][0];
+        assert!(overload["signature"].as_str().unwrap().starts_with("Val("));
+        assert_eq!(overload["params"][0]["kind"], "string");
+    }
+
+    #[test]
+    fn exports_layouts() {
+        let mut layouts = LayoutIndex::new();
+        let source =
+            "CUSTOMER.DAT, RCU_, 1\nCUSTOMER.IX1, RCU_ID$\n----------\nID$, Customer ID, C 10\n";
+        layouts.add(
+            "file:///workspace/filelay/customer",
+            layout::parse(source).unwrap(),
+        );
+
+        let out = export_index(&WorkspaceIndex::new(), &layouts);
+        let exported = out["layouts"].as_array().unwrap();
+        assert_eq!(exported.len(), 1);
+        assert_eq!(exported[0]["prefix"], "RCU_");
+        assert_eq!(exported[0]["keys"][0]["keyFields"][0], "RCU_ID$");
+        assert_eq!(exported[0]["subscripts"][0]["name"], "ID$");
+        assert_eq!(exported[0]["subscripts"][0]["format"], "C 10");
+        assert_eq!(exported[0]["subscripts"][0]["offset"], 0);
+        assert_eq!(exported[0]["subscripts"][0]["length"], 10);
+    }
+}
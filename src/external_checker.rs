@@ -0,0 +1,189 @@
+//! Optional external syntax checker. When `br-lsp.externalChecker` names a
+//! BR compiler or lexer, each saved program is passed to it and the errors
+//! it prints are published next to the server's own diagnostics, under
+//! their own source.
+//!
+//! Two output forms are understood, one message per line:
+//! - `path:line[:column]: [error|warning|info:] message` with 1-based
+//!   positions, as most compilers print them
+//! - BR's status line, `EEEELLLLL:CCERROR`: error code, BR line number and
+//!   clause, placed on the source line carrying that line number
+
+use std::path::Path;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use dashmap::DashMap;
+use regex::Regex;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Position, Range};
+
+use crate::constants;
+
+/// `source` of the checker's diagnostics
+pub const SOURCE: &str = "br-checker";
+
+/// Same limit the extension gives a compile
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+static LOCATED_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)^(.*?):(\d+)(?::(\d+))?:\s*(?:(error|warning|info|note)\s*:\s*)?(.+)$")
+        .unwrap()
+});
+
+static BR_ERROR_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(\d{4})(\d{5}):(\d{2})ERROR").unwrap());
+
+/// Run `checker` on the saved `file` and turn what it prints into
+/// diagnostics against `source`, the file's text.
+pub async fn check(checker: &Path, file: &Path, source: &str) -> Result<Vec<Diagnostic>, String> {
+    let output = tokio::process::Command::new(checker)
+        .arg(file)
+        .kill_on_drop(true)
+        .output();
+    let output = match tokio::time::timeout(TIMEOUT, output).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => return Err(format!("could not run {}: {e}", checker.display())),
+        Err(_) => return Err(format!("{} timed out", checker.display())),
+    };
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push('\n');
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+
+    let name = file.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    Ok(parse_output(&text, name, source))
+}
+
+/// Diagnostics from checker output. Located messages about files other
+/// than `file_name` are skipped.
+pub fn parse_output(output: &str, file_name: &str, source: &str) -> Vec<Diagnostic> {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut diagnostics = Vec::new();
+
+    for message in output.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if let Some(caps) = LOCATED_RE.captures(message) {
+            // Windows checkers print backslashed paths
+            let name = caps[1].trim().rsplit(['/', '\\']).next().unwrap_or("");
+            if !name.eq_ignore_ascii_case(file_name) {
+                continue;
+            }
+            let line = caps[2].parse::<u32>().unwrap_or(1).saturating_sub(1);
+            let column = caps
+                .get(3)
+                .and_then(|c| c.as_str().parse::<u32>().ok())
+                .map(|c| c.saturating_sub(1));
+            let severity = match caps.get(4).map(|s| s.as_str().to_ascii_lowercase()) {
+                Some(s) if s == "warning" => DiagnosticSeverity::WARNING,
+                Some(s) if s == "info" || s == "note" => DiagnosticSeverity::INFORMATION,
+                _ => DiagnosticSeverity::ERROR,
+            };
+            diagnostics.push(Diagnostic {
+                range: line_range(&lines, line, column),
+                severity: Some(severity),
+                source: Some(SOURCE.to_string()),
+                message: caps[5].trim().to_string(),
+                ..Default::default()
+            });
+        } else if let Some(caps) = BR_ERROR_RE.captures(message) {
+            let code: u32 = caps[1].parse().unwrap_or(0);
+            let br_line: u32 = caps[2].parse().unwrap_or(0);
+            let clause: u32 = caps[3].parse().unwrap_or(0);
+            let mut text = format!("Error {code}");
+            if let Some(name) = constants::error_name(code) {
+                text.push_str(&format!(": {name}"));
+            }
+            text.push_str(&format!(" at line {br_line}"));
+            if clause > 0 {
+                text.push_str(&format!(":{clause}"));
+            }
+            // Unnumbered source can't be matched to BR's numbering
+            let line = numbered_line(&lines, br_line).unwrap_or(0);
+            diagnostics.push(Diagnostic {
+                range: line_range(&lines, line, None),
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::Number(code as i32)),
+                source: Some(SOURCE.to_string()),
+                message: text,
+                ..Default::default()
+            });
+        }
+    }
+    diagnostics
+}
+
+/// The row whose leading line number is `number`.
+fn numbered_line(lines: &[&str], number: u32) -> Option<u32> {
+    lines
+        .iter()
+        .position(|line| {
+            let digits: String = line
+                .trim_start()
+                .chars()
+                .take_while(char::is_ascii_digit)
+                .collect();
+            digits.parse::<u32>().ok() == Some(number)
+        })
+        .map(|row| row as u32)
+}
+
+/// From `column` (or the first non-blank character) to the end of `line`.
+fn line_range(lines: &[&str], line: u32, column: Option<u32>) -> Range {
+    let text = lines.get(line as usize).copied().unwrap_or("");
+    let len = text.chars().count() as u32;
+    let indent = (text.len() - text.trim_start().len()) as u32;
+    let start = column.unwrap_or(indent).min(len);
+    Range::new(Position::new(line, start), Position::new(line, len))
+}
+
+/// `diagnostics` with the checker's last findings for `uri` appended.
+pub fn merged(
+    external: &DashMap<String, Vec<Diagnostic>>,
+    uri: &str,
+    mut diagnostics: Vec<Diagnostic>,
+) -> Vec<Diagnostic> {
+    if let Some(found) = external.get(uri) {
+        diagnostics.extend(found.iter().cloned());
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE_TEXT: &str = "00010 print \"a\"\n00020   let x = \n00030 end\n";
+
+    #[test]
+    fn located_messages_for_the_checked_file() {
+        let output = "C:\\src\\prog.brs:2:9: error: missing expression\n\
+                      other.brs:1: warning: elsewhere\n\
+                      PROG.BRS:3: note: ends here\n\
+                      compiling...\n";
+        let diags = parse_output(output, "prog.brs", SOURCE_TEXT);
+        assert_eq!(diags.len(), 2);
+        assert_eq!(diags[0].message, "missing expression");
+        assert_eq!(diags[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            diags[0].range,
+            Range::new(Position::new(1, 8), Position::new(1, 16))
+        );
+        assert_eq!(diags[0].source.as_deref(), Some(SOURCE));
+        assert_eq!(diags[1].severity, Some(DiagnosticSeverity::INFORMATION));
+        assert_eq!(diags[1].range.start, Position::new(2, 0));
+    }
+
+    #[test]
+    fn br_status_errors_land_on_their_numbered_line() {
+        let diags = parse_output("READY  414800020:01ERROR  prog.", "prog.brs", SOURCE_TEXT);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].message,
+            "Error 4148: File is in use (sharing violation) at line 20:1"
+        );
+        assert_eq!(diags[0].code, Some(NumberOrString::Number(4148)));
+        assert_eq!(diags[0].range.start, Position::new(1, 0));
+
+        let unnumbered = parse_output("100200050:00ERROR", "prog.brs", "print 1\n");
+        assert_eq!(unnumbered[0].message, "Error 1002 at line 50");
+        assert_eq!(unnumbered[0].range.start.line, 0);
+    }
+}
@@ -16,6 +16,8 @@ pub struct FunctionDef {
     pub has_param_substitution: bool,
     pub documentation: Option<String>,
     pub return_documentation: Option<String>,
+    /// Set by a `@deprecated` doc tag; holds the reason, empty if none given
+    pub deprecated: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -164,6 +166,7 @@ fn collect_library_imports(lib_node: Node, source: &str, defs: &mut Vec<Function
                             has_param_substitution: false,
                             documentation: None,
                             return_documentation: None,
+                            deprecated: None,
                         });
                     }
                 }
@@ -175,6 +178,13 @@ fn collect_library_imports(lib_node: Node, source: &str, defs: &mut Vec<Function
 /// Find the doc_comment text for a def_statement node by looking at the
 /// immediately preceding sibling line.
 fn find_doc_comment<'a>(def_node: Node<'a>, source: &'a str) -> Option<&'a str> {
+    doc_comment_node(def_node)?
+        .utf8_text(source.as_bytes())
+        .ok()
+}
+
+/// The doc_comment node documenting a def_statement, if any.
+pub(crate) fn doc_comment_node(def_node: Node) -> Option<Node> {
     // def_statement is inside a line node
     let line_node = def_node.parent()?;
     if line_node.kind() != "line" {
@@ -187,18 +197,52 @@ fn find_doc_comment<'a>(def_node: Node<'a>, source: &'a str) -> Option<&'a str>
     }
     // Look for a doc_comment child in that line
     let mut cursor = prev_line.walk();
-    for child in prev_line.children(&mut cursor) {
-        if child.kind() == "doc_comment" {
-            return child.utf8_text(source.as_bytes()).ok();
-        }
-    }
-    None
+    let found = prev_line
+        .children(&mut cursor)
+        .find(|c| c.kind() == "doc_comment");
+    found
+}
+
+/// An `@param` tag within a raw doc comment.
+pub(crate) struct DocParamTag<'a> {
+    /// Line within the comment (0 = the `/**` line)
+    pub line: usize,
+    /// Byte offset of the name within that line
+    pub column: usize,
+    pub name: &'a str,
+    pub documentation: &'a str,
+}
+
+pub(crate) fn doc_param_tags(raw: &str) -> Vec<DocParamTag<'_>> {
+    raw.split('\n')
+        .enumerate()
+        .filter_map(|(line, text)| {
+            let tag = text.find("@param")?;
+            let rest = &text[tag + "@param".len()..];
+            let trimmed = rest.trim_start();
+            let column = text.len() - trimmed.len();
+            let trimmed = trimmed.trim_end_matches("*/").trim_end();
+            let (name, documentation) = trimmed
+                .split_once(char::is_whitespace)
+                .unwrap_or((trimmed, ""));
+            if name.is_empty() {
+                return None;
+            }
+            Some(DocParamTag {
+                line,
+                column,
+                name,
+                documentation: documentation.trim(),
+            })
+        })
+        .collect()
 }
 
 struct DocComment {
     description: Option<String>,
     return_doc: Option<String>,
     param_docs: Vec<(String, String)>, // (name, documentation)
+    deprecated: Option<String>,
 }
 
 fn parse_doc_comment(raw: &str) -> DocComment {
@@ -208,6 +252,7 @@ fn parse_doc_comment(raw: &str) -> DocComment {
     let mut description_lines = Vec::new();
     let mut param_docs = Vec::new();
     let mut return_doc = None;
+    let mut deprecated = None;
     let mut in_tags = false;
 
     for line in inner.lines() {
@@ -230,6 +275,9 @@ fn parse_doc_comment(raw: &str) -> DocComment {
                 .trim_start_matches("@return")
                 .trim();
             return_doc = Some(rest.to_string());
+        } else if trimmed.starts_with("@deprecated") {
+            in_tags = true;
+            deprecated = Some(trimmed.trim_start_matches("@deprecated").trim().to_string());
         } else if !in_tags && !trimmed.is_empty() {
             description_lines.push(trimmed.to_string());
         }
@@ -245,6 +293,7 @@ fn parse_doc_comment(raw: &str) -> DocComment {
         description,
         return_doc,
         param_docs,
+        deprecated,
     }
 }
 
@@ -277,7 +326,7 @@ fn extract_one_def(def_node: Node, source: &str) -> Option<FunctionDef> {
     let has_param_substitution = param_list_node.is_some_and(|pl| has_substitution(pl));
 
     // Parse doc comment if present
-    let (documentation, return_documentation) =
+    let (documentation, return_documentation, deprecated) =
         if let Some(raw) = find_doc_comment(def_node, source) {
             let doc = parse_doc_comment(raw);
             // Attach param docs to matching ParamInfo entries
@@ -289,9 +338,9 @@ fn extract_one_def(def_node: Node, source: &str) -> Option<FunctionDef> {
                     param.documentation = Some(pdoc.clone());
                 }
             }
-            (doc.description, doc.return_doc)
+            (doc.description, doc.return_doc, doc.deprecated)
         } else {
-            (None, None)
+            (None, None, None)
         };
 
     Some(FunctionDef {
@@ -304,6 +353,7 @@ fn extract_one_def(def_node: Node, source: &str) -> Option<FunctionDef> {
         has_param_substitution,
         documentation,
         return_documentation,
+        deprecated,
     })
 }
 
@@ -465,7 +515,7 @@ fn collect_library_links(node: Node, source: &str, links: &mut HashMap<String, S
 }
 
 /// DFS for a `"string"` leaf node and return its text with quotes stripped.
-fn extract_string_literal(node: Node, source: &str) -> Option<String> {
+pub(crate) fn extract_string_literal(node: Node, source: &str) -> Option<String> {
     let mut stack = vec![node];
     while let Some(n) = stack.pop() {
         if n.kind() == "string" {
@@ -616,6 +666,36 @@ def fnAdd(A, B) = A + B
         );
     }
 
+    #[test]
+    fn deprecated_tag_parsed() {
+        let source = "\
+/** Old adder
+  * @deprecated Use fnSum instead
+  */
+def fnAdd(A, B) = A + B
+/** @deprecated */
+def fnSub(A, B) = A - B
+def fnMul(A, B) = A * B
+";
+        let defs = parse_and_extract(source);
+        assert_eq!(defs[0].documentation.as_deref(), Some("Old adder"));
+        assert_eq!(defs[0].deprecated.as_deref(), Some("Use fnSum instead"));
+        assert_eq!(defs[1].deprecated.as_deref(), Some(""));
+        assert!(defs[2].deprecated.is_none());
+    }
+
+    #[test]
+    fn doc_param_tag_positions() {
+        let raw = "/** Adds\n  * @param A First number\n  * @param B$ */";
+        let tags = doc_param_tags(raw);
+        assert_eq!(tags.len(), 2);
+        assert_eq!((tags[0].line, tags[0].column), (1, 11));
+        assert_eq!(tags[0].name, "A");
+        assert_eq!(tags[0].documentation, "First number");
+        assert_eq!(tags[1].name, "B$");
+        assert_eq!(tags[1].documentation, "");
+    }
+
     #[test]
     fn no_doc_comment() {
         let defs = parse_and_extract("def fnPlain(X) = X\n");
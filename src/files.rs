@@ -0,0 +1,571 @@
+use std::collections::HashSet;
+
+use tower_lsp::lsp_types::{Position, Range};
+use tree_sitter::{Node, Point, Tree};
+
+use crate::layout::Layout;
+use crate::line_numbers;
+use crate::parser::{find_descendant, node_range, node_text};
+
+/// An `OPEN #n: "..."` statement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenFile {
+    /// Normalized file number (see `file_number_key`)
+    pub file_number: String,
+    /// File number as written, e.g. `1` or `Cust`
+    pub label: String,
+    pub range: Range,
+    pub start_byte: usize,
+    pub text: String,
+    pub name: Option<String>,
+    pub kfname: Option<String>,
+    pub record_length: Option<u32>,
+    /// `KPs=` key positions, one per key part (`KPs=1/20`)
+    pub key_positions: Vec<u32>,
+    /// `KLn=` key lengths, one per key part
+    pub key_lengths: Vec<u32>,
+    /// Trailing keywords such as `internal`, `outin`, `keyed` (lowercase)
+    pub options: Vec<String>,
+    /// The straight-line stretch of code the OPEN is in. Two OPENs share
+    /// one only when no branch, label or jumped-to line falls between them.
+    pub block: usize,
+}
+
+/// A `#n` file number used by any statement other than OPEN.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileReference {
+    pub file_number: String,
+    pub label: String,
+    /// Kind of the statement using the file number, e.g. `read_statement`
+    pub statement: String,
+    pub range: Range,
+    pub start_byte: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct FileUsage {
+    pub opens: Vec<OpenFile>,
+    pub references: Vec<FileReference>,
+}
+
+impl FileUsage {
+    /// The file number reference whose range contains `position`.
+    pub fn reference_at(&self, position: Position) -> Option<&FileReference> {
+        self.references
+            .iter()
+            .find(|r| r.range.start <= position && position <= r.range.end)
+    }
+
+    /// The OPEN a reference most likely refers to: the nearest preceding OPEN
+    /// of the same file number, falling back to the first one in the file
+    /// (e.g. an OPEN in a subroutine placed after the code that calls it).
+    pub fn open_for(&self, reference: &FileReference) -> Option<&OpenFile> {
+        let same = self
+            .opens
+            .iter()
+            .filter(|o| o.file_number == reference.file_number);
+        let first = same.clone().next();
+        same.filter(|o| o.start_byte < reference.start_byte)
+            .next_back()
+            .or(first)
+    }
+}
+
+/// Whether `position` is on a `#n` file number, the only place
+/// [`FileUsage::reference_at`] finds anything. Much cheaper than collecting
+/// the usage, so hover and definition check this first.
+pub fn is_file_number_at(tree: &Tree, position: Position) -> bool {
+    let row = position.line as usize;
+    let column = position.character as usize;
+    // A reference's range ends just past the number, where the cursor may be
+    [Some(column), column.checked_sub(1)]
+        .into_iter()
+        .flatten()
+        .any(|column| {
+            let point = Point::new(row, column);
+            let mut node = tree.root_node().descendant_for_point_range(point, point);
+            while let Some(n) = node {
+                if matches!(n.kind(), "channel" | "#")
+                    || n.prev_sibling().is_some_and(|p| p.kind() == "#")
+                {
+                    return true;
+                }
+                if n.kind().ends_with("_statement") {
+                    return false;
+                }
+                node = n.parent();
+            }
+            false
+        })
+}
+
+/// Statements that branch, or that a branch lands on. Each starts a new
+/// straight-line block.
+const BRANCH_STATEMENTS: &[&str] = &[
+    "if_statement",
+    "else_statement",
+    "end_if_statement",
+    "select_case_statement",
+    "case_statement",
+    "case_else_statement",
+    "end_select_statement",
+    "do_statement",
+    "loop_statement",
+    "exit_do_statement",
+    "for_statement",
+    "next_statement",
+    "goto_statement",
+    "gosub_statement",
+    "return_statement",
+    "on_statement",
+    "def_statement",
+    "fnend_statement",
+    "end_def_statement",
+    "exit_statement",
+    "retry_statement",
+    "continue_statement",
+    "chain_statement",
+    "stop_statement",
+    "end_statement",
+];
+
+/// Collect every OPEN statement and file number reference in a document.
+pub fn collect_file_usage(tree: &Tree, source: &str) -> FileUsage {
+    let mut walker = Walker {
+        source,
+        jumped_to: jumped_to_lines(tree.root_node(), source),
+        block: 0,
+        usage: FileUsage::default(),
+    };
+    walker.walk(tree.root_node());
+    walker.usage
+}
+
+/// Line numbers something in the file jumps to.
+fn jumped_to_lines(root: Node, source: &str) -> HashSet<u32> {
+    let mut lines = HashSet::new();
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        if let Some(line) = line_numbers::referenced_line(node, source) {
+            lines.insert(line);
+            continue;
+        }
+        let mut cursor = node.walk();
+        stack.extend(node.children(&mut cursor));
+    }
+    lines
+}
+
+struct Walker<'a> {
+    source: &'a str,
+    jumped_to: HashSet<u32>,
+    /// The straight-line block being walked
+    block: usize,
+    usage: FileUsage,
+}
+
+impl Walker<'_> {
+    fn walk(&mut self, node: Node) {
+        let source = self.source;
+        let kind = node.kind();
+        let starts_block = match kind {
+            "label" | "error_condition_list" => true,
+            "line_number" => node_text(node, source)
+                .trim()
+                .parse()
+                .is_ok_and(|n: u32| self.jumped_to.contains(&n)),
+            _ => BRANCH_STATEMENTS.contains(&kind),
+        };
+        if starts_block {
+            self.block += 1;
+        }
+
+        if kind == "open_statement" {
+            if let Some(open) = open_file(node, source, self.block) {
+                self.usage.opens.push(open);
+            }
+            // An error condition on the OPEN jumps somewhere to recover,
+            // which often retries it
+            if node
+                .named_children(&mut node.walk())
+                .any(|c| c.kind() == "error_condition_list")
+            {
+                self.block += 1;
+            }
+            return;
+        }
+
+        if kind.ends_with("_statement") {
+            for (hash_start, expr) in file_number_exprs(node) {
+                self.usage.references.push(FileReference {
+                    file_number: file_number_key(expr, source),
+                    label: node_text(expr, source).trim().to_string(),
+                    statement: kind.to_string(),
+                    range: Range {
+                        start: node_range(hash_start).start,
+                        end: node_range(expr).end,
+                    },
+                    start_byte: hash_start.start_byte(),
+                });
+            }
+        }
+
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            self.walk(child);
+        }
+    }
+}
+
+/// File number expressions of a statement, paired with the node starting at
+/// the `#`. Most statements wrap these in a `channel` node (RESTORE nests it
+/// in `restore_file`); CLOSE and DELETE have `#` and the expression as direct
+/// children.
+fn file_number_exprs<'a>(statement: Node<'a>) -> Vec<(Node<'a>, Node<'a>)> {
+    let mut result = Vec::new();
+    let mut cursor = statement.walk();
+    let children: Vec<Node> = statement.children(&mut cursor).collect();
+
+    for (i, child) in children.iter().enumerate() {
+        match child.kind() {
+            "channel" => {
+                if let Some(expr) = channel_expr(*child) {
+                    result.push((*child, expr));
+                }
+            }
+            "restore_file" => {
+                let mut inner = child.walk();
+                for grandchild in child.children(&mut inner) {
+                    if grandchild.kind() == "channel" {
+                        if let Some(expr) = channel_expr(grandchild) {
+                            result.push((grandchild, expr));
+                        }
+                    }
+                }
+            }
+            "#" => {
+                if let Some(next) = children.get(i + 1) {
+                    if next.kind() == "numeric_expression" {
+                        result.push((*child, *next));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    result
+}
+
+fn channel_expr(channel: Node) -> Option<Node> {
+    channel
+        .named_children(&mut channel.walk())
+        .find(|c| c.kind() == "numeric_expression")
+}
+
+/// Normalize a file number expression so different spellings of the same
+/// number compare equal. `#(F:=fnGetFileNumber)` is keyed by the assigned
+/// variable `F`, since later statements refer to it as `#F`.
+fn file_number_key(expr: Node, source: &str) -> String {
+    let key = find_descendant(expr, "numeric_forced_assignment_expression")
+        .and_then(|assign| {
+            assign
+                .named_children(&mut assign.walk())
+                .find(|c| c.kind() == "numberreference")
+        })
+        .unwrap_or(expr);
+    node_text(key, source)
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+fn open_file(node: Node, source: &str, block: usize) -> Option<OpenFile> {
+    let mut cursor = node.walk();
+    let children: Vec<Node> = node.children(&mut cursor).collect();
+
+    let channel = children.iter().find(|c| c.kind() == "channel")?;
+    let expr = channel_expr(*channel)?;
+
+    // Only literal pieces of the open string can be inspected; values built
+    // from variables are left unknown.
+    let spec: String = children
+        .iter()
+        .find(|c| c.kind() == "string_expression")
+        .map(|s| string_literals(*s, source))
+        .unwrap_or_default();
+
+    let mut name = None;
+    let mut kfname = None;
+    let mut record_length = None;
+    let mut key_positions = Vec::new();
+    let mut key_lengths = Vec::new();
+    let key_parts = |value: &str| -> Vec<u32> {
+        value
+            .split('/')
+            .map(|p| p.trim().parse().ok())
+            .collect::<Option<_>>()
+            .unwrap_or_default()
+    };
+    for part in spec.split(',') {
+        let Some((key, value)) = part.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        if value.is_empty() {
+            continue;
+        }
+        match key.trim().to_ascii_lowercase().as_str() {
+            "name" => name = Some(value.to_string()),
+            "kfname" => kfname = Some(value.to_string()),
+            "recl" => record_length = value.parse().ok(),
+            "kps" => key_positions = key_parts(value),
+            "kln" => key_lengths = key_parts(value),
+            _ => {}
+        }
+    }
+
+    let options = children
+        .iter()
+        .filter(|c| c.kind() == "keyword")
+        .map(|c| node_text(*c, source).to_ascii_lowercase())
+        .collect();
+
+    Some(OpenFile {
+        file_number: file_number_key(expr, source),
+        label: node_text(expr, source).trim().to_string(),
+        range: node_range(node),
+        start_byte: node.start_byte(),
+        text: node_text(node, source)
+            .lines()
+            .next()
+            .unwrap_or("")
+            .to_string(),
+        name,
+        kfname,
+        record_length,
+        key_positions,
+        key_lengths,
+        options,
+        block,
+    })
+}
+
+/// Concatenate the contents of every string literal under `node`.
+fn string_literals(node: Node, source: &str) -> String {
+    let mut out = String::new();
+    let mut stack = vec![node];
+    let mut literals = Vec::new();
+    while let Some(n) = stack.pop() {
+        if n.kind() == "string" {
+            literals.push(n);
+            continue;
+        }
+        let mut cursor = n.walk();
+        for child in n.children(&mut cursor) {
+            stack.push(child);
+        }
+    }
+    literals.sort_by_key(|n| n.start_byte());
+    for lit in literals {
+        let t = node_text(lit, source);
+        let inner = t
+            .strip_prefix('"')
+            .and_then(|s| s.strip_suffix('"'))
+            .or_else(|| t.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')))
+            .unwrap_or(t);
+        out.push_str(inner);
+    }
+    out
+}
+
+/// Whether a layout describes the file opened by `name`. Only the file name
+/// is compared, since programs and layouts often spell directories
+/// differently.
+pub fn layout_matches(name: &str, layout: &Layout) -> bool {
+    let file_name = |s: &str| {
+        s.trim()
+            .replace('\\', "/")
+            .rsplit('/')
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase()
+    };
+    !layout.path.is_empty() && file_name(name) == file_name(&layout.path)
+}
+
+pub fn format_open_hover(open: &OpenFile, layout: Option<&Layout>) -> String {
+    let mut md = format!(
+        "**File #{}** opened on line {}\n\n```br\n{}\n```",
+        open.label,
+        open.range.start.line + 1,
+        open.text.trim()
+    );
+
+    let mut details = Vec::new();
+    if let Some(name) = &open.name {
+        details.push(format!("Name: `{name}`"));
+    }
+    if let Some(kfname) = &open.kfname {
+        details.push(format!("Key file: `{kfname}`"));
+    }
+    match (open.record_length, layout.and_then(|l| l.record_length)) {
+        (Some(recl), _) => details.push(format!("Record length: {recl}")),
+        (None, Some(recl)) => details.push(format!("Record length: {recl} (from layout)")),
+        _ => {}
+    }
+    if let Some(fields) = layout.and_then(|l| l.computed_record_length()) {
+        details.push(format!("Fields: {fields} bytes"));
+    }
+    if let Some(layout) = layout {
+        details.push(format!(
+            "Layout: `{}` (prefix `{}`)",
+            layout.path, layout.prefix
+        ));
+    }
+
+    if !details.is_empty() {
+        md.push_str("\n\n");
+        md.push_str(
+            &details
+                .iter()
+                .map(|d| format!("- {d}"))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+    }
+    md
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn usage(source: &str) -> FileUsage {
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        collect_file_usage(&tree, source)
+    }
+
+    #[test]
+    fn open_statement_details() {
+        let u = usage(
+            "open #1: \"name=data\\customer.dat,kfname=data\\customer.ix1,recl=128,shr\", internal, outin, keyed\n",
+        );
+        assert_eq!(u.opens.len(), 1);
+        let open = &u.opens[0];
+        assert_eq!(open.file_number, "1");
+        assert_eq!(open.name.as_deref(), Some("data\\customer.dat"));
+        assert_eq!(open.kfname.as_deref(), Some("data\\customer.ix1"));
+        assert_eq!(open.record_length, Some(128));
+        assert_eq!(open.options, vec!["internal", "outin", "keyed"]);
+        assert!(u.references.is_empty());
+    }
+
+    #[test]
+    fn open_statement_key_parts() {
+        let u = usage(
+            "open #1: \"name=x.dat,kfname=x.ix1,kps=1/31,kln=10/5\", internal, outin, keyed\nopen #2: \"name=y.dat,kps=a\", internal, input\n",
+        );
+        assert_eq!(u.opens[0].key_positions, vec![1, 31]);
+        assert_eq!(u.opens[0].key_lengths, vec![10, 5]);
+        assert!(u.opens[1].key_positions.is_empty());
+    }
+
+    #[test]
+    fn references_in_io_statements() {
+        let u = usage(
+            "open #1: \"name=x\", internal, input\nread #1, using F1: A$\nrestore #1:\nprint #255: \"x\"\nclose #1:\n",
+        );
+        let kinds: Vec<(&str, &str)> = u
+            .references
+            .iter()
+            .map(|r| (r.statement.as_str(), r.file_number.as_str()))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("read_statement", "1"),
+                ("restore_statement", "1"),
+                ("print_statement", "255"),
+                ("close_statement", "1"),
+            ]
+        );
+        // Range covers `#1`
+        assert_eq!(u.references[0].range.start, Position::new(1, 5));
+        assert_eq!(u.references[0].range.end, Position::new(1, 7));
+    }
+
+    #[test]
+    fn forced_assignment_keyed_by_variable() {
+        let u = usage("open #(Cust:=fnGetFile): \"name=x\", internal, input\nread #Cust: A$\n");
+        assert_eq!(u.opens[0].file_number, "cust");
+        assert_eq!(u.references[0].file_number, "cust");
+        assert!(u.open_for(&u.references[0]).is_some());
+    }
+
+    #[test]
+    fn open_for_prefers_nearest_preceding() {
+        let u = usage(
+            "open #1: \"name=a\", internal, input\nclose #1:\nopen #1: \"name=b\", internal, input\nread #1: X\n",
+        );
+        let read = u
+            .references
+            .iter()
+            .find(|r| r.statement == "read_statement")
+            .unwrap();
+        assert_eq!(u.open_for(read).unwrap().name.as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn open_for_falls_back_to_later_open() {
+        let u = usage("read #2: X\nopen #2: \"name=z\", internal, input\n");
+        assert_eq!(
+            u.open_for(&u.references[0]).unwrap().name.as_deref(),
+            Some("z")
+        );
+    }
+
+    #[test]
+    fn reference_at_position() {
+        let u = usage("open #1: \"name=x\", internal, input\nread #1: X\n");
+        assert!(u.reference_at(Position::new(1, 6)).is_some());
+        assert!(u.reference_at(Position::new(1, 9)).is_none());
+    }
+
+    #[test]
+    fn file_number_at_position() {
+        let source = "open #1: \"name=x\", internal, input\nread #1: X\nclose #1:\n";
+        let tree = parser::parse(&mut parser::new_parser(), source, None).unwrap();
+        assert!(is_file_number_at(&tree, Position::new(1, 5)));
+        assert!(is_file_number_at(&tree, Position::new(1, 7)));
+        assert!(is_file_number_at(&tree, Position::new(2, 7)));
+        assert!(!is_file_number_at(&tree, Position::new(1, 9)));
+        assert!(!is_file_number_at(&tree, Position::new(0, 12)));
+    }
+
+    #[test]
+    fn layout_match_by_file_name() {
+        let layout =
+            crate::layout::parse("data\\CUSTOMER.DAT, RCU_, 1\n----------\nID$, Id, C 10\n")
+                .unwrap();
+        assert!(layout_matches("data/customer.dat", &layout));
+        assert!(layout_matches("CUSTOMER.DAT", &layout));
+        assert!(!layout_matches("vendor.dat", &layout));
+    }
+
+    #[test]
+    fn hover_includes_layout_record_length() {
+        let u = usage("open #1: \"name=customer.dat\", internal, input\n");
+        let layout =
+            crate::layout::parse("CUSTOMER.DAT, RCU_, 1\nrecl=256\n----------\nID$, Id, C 10\n")
+                .unwrap();
+        let md = format_open_hover(&u.opens[0], Some(&layout));
+        assert!(md.starts_with("**File #1** opened on line 1"));
+        assert!(md.contains("- Name: `customer.dat`"));
+        assert!(md.contains("- Record length: 256 (from layout)"));
+        assert!(md.contains("- Fields: 10 bytes"));
+        assert!(md.contains("- Layout: `CUSTOMER.DAT` (prefix `RCU_`)"));
+    }
+}
@@ -0,0 +1,59 @@
+//! Folding ranges for the blocks statements open: multi-line DEF to FNEND,
+//! DO to LOOP, FOR to NEXT and IF to END IF. The closing line stays
+//! visible when folded.
+
+use tower_lsp::lsp_types::FoldingRange;
+use tree_sitter::Tree;
+
+use crate::structure::Structure;
+
+pub fn folding_ranges(tree: &Tree, source: &str) -> Vec<FoldingRange> {
+    let structure = Structure::of(tree, source);
+    let functions = structure
+        .functions
+        .iter()
+        .filter_map(|f| f.end.map(|_| f.range()))
+        .map(|range| (range.start.line, range.end.line));
+    let blocks = structure
+        .blocks
+        .iter()
+        .map(|block| (block.start_line, block.end_line));
+
+    let mut ranges: Vec<FoldingRange> = functions
+        .chain(blocks)
+        .filter(|&(start, end)| end > start + 1)
+        .map(|(start, end)| FoldingRange {
+            start_line: start,
+            end_line: end - 1,
+            ..Default::default()
+        })
+        .collect();
+    ranges.sort_by_key(|r| (r.start_line, r.end_line));
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn folds(source: &str) -> Vec<(u32, u32)> {
+        let tree = parser::parse(&mut parser::new_parser(), source, None).unwrap();
+        folding_ranges(&tree, source)
+            .iter()
+            .map(|r| (r.start_line, r.end_line))
+            .collect()
+    }
+
+    #[test]
+    fn blocks_fold_up_to_their_closer() {
+        let source = "def fnA(X)\n  do while X < 3\n    if X = 1 then\n      print X\n    end if\n    let X += 1\n  loop\nfnend\nfor I = 1 to 3\n  print I\nnext I\n";
+        assert_eq!(folds(source), vec![(0, 6), (1, 5), (2, 3), (8, 9)]);
+    }
+
+    #[test]
+    fn single_line_and_unclosed_blocks_do_not_fold() {
+        let source = "if X then print 1\ndef fnB = 2\ndo\n  print 1\nfor I = 1 to 2 : next I\n";
+        assert!(folds(source).is_empty());
+    }
+}
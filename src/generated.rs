@@ -0,0 +1,135 @@
+//! Generated programs: ones with a `! GENERATED FILE` comment near the top,
+//! or matching a `br-lsp.generatedFiles` glob. Nobody edits them by hand,
+//! so style and dead-code diagnostics are only noise there; syntax errors
+//! and the other correctness checks still show, and the files are indexed
+//! and completed like any other.
+
+use tower_lsp::lsp_types::{Diagnostic, NumberOrString, Url};
+
+use crate::rules;
+use crate::workspace::PathPattern;
+
+/// The comment marking a generated file, compared without case.
+const MARKER: &str = "generated file";
+
+/// How many lines from the top the marker comment may be on.
+const MARKER_LINES: usize = 10;
+
+/// Globs from `br-lsp.generatedFiles`, matched like `files_matching`
+/// patterns against the end of a file's path.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GeneratedFiles {
+    globs: Vec<String>,
+}
+
+impl GeneratedFiles {
+    pub fn from_settings(settings: Option<&serde_json::Value>) -> Self {
+        let globs = settings
+            .and_then(|s| s.as_array())
+            .map(|globs| {
+                globs
+                    .iter()
+                    .filter_map(|g| g.as_str())
+                    .map(str::trim)
+                    .filter(|g| !g.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { globs }
+    }
+
+    /// Whether `uri` is generated, by its path or the marker comment in
+    /// `source`.
+    pub fn contains(&self, uri: &Url, source: &str) -> bool {
+        has_marker(source)
+            || self
+                .globs
+                .iter()
+                .filter_map(|g| PathPattern::new(g))
+                .any(|p| p.matches(uri))
+    }
+}
+
+/// Whether one of the first lines is a `!` or REM comment starting with
+/// the marker, numbered or not.
+fn has_marker(source: &str) -> bool {
+    source.lines().take(MARKER_LINES).any(|line| {
+        let line = line
+            .trim_start()
+            .trim_start_matches(|c: char| c.is_ascii_digit())
+            .trim_start();
+        let comment = line.strip_prefix('!').or_else(|| {
+            line.get(..4)
+                .filter(|rem| rem.eq_ignore_ascii_case("rem "))
+                .map(|_| &line[4..])
+        });
+        comment.is_some_and(|text| {
+            text.trim_start()
+                .get(..MARKER.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(MARKER))
+        })
+    })
+}
+
+/// Drop the style and dead-code diagnostics from a generated file's.
+pub fn strip_style(diagnostics: &mut Vec<Diagnostic>) {
+    diagnostics.retain(|d| match &d.code {
+        Some(NumberOrString::String(code)) => !rules::is_style(code),
+        _ => true,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marker_comment_near_the_top_marks_a_file() {
+        assert!(has_marker("! GENERATED FILE - do not edit\nprint 1\n"));
+        assert!(has_marker("00010 ! Generated file\n00020 print 1\n"));
+        assert!(has_marker(
+            "! Report writer\nrem generated FILE by rpt.exe\n"
+        ));
+        assert!(!has_marker("! This is not a generated file\n"));
+        assert!(!has_marker("print \"! generated file\"\n"));
+        let late = format!("{}! generated file\n", "print 1\n".repeat(MARKER_LINES));
+        assert!(!has_marker(&late));
+    }
+
+    #[test]
+    fn globs_mark_files_by_path() {
+        let settings = serde_json::json!(["gen/**", "**/*_scr.brs", " "]);
+        let generated = GeneratedFiles::from_settings(Some(&settings));
+        let uri = |path: &str| Url::parse(&format!("file:///ws/{path}")).unwrap();
+        assert!(generated.contains(&uri("gen/screens/menu.brs"), ""));
+        assert!(generated.contains(&uri("src/Order_SCR.brs"), ""));
+        assert!(!generated.contains(&uri("src/order.brs"), ""));
+        assert!(GeneratedFiles::default().contains(&uri("src/order.brs"), "! generated file\n"));
+    }
+
+    #[test]
+    fn keeps_syntax_and_correctness_diagnostics() {
+        let diagnostic = |code: &str| Diagnostic {
+            code: Some(NumberOrString::String(code.to_string())),
+            ..Default::default()
+        };
+        let mut diagnostics = vec![
+            diagnostic("syntax-error"),
+            diagnostic("unused-variable"),
+            diagnostic("keyword-case"),
+            diagnostic("parameter-count"),
+            Diagnostic::default(),
+        ];
+        strip_style(&mut diagnostics);
+        let codes: Vec<_> = diagnostics.iter().map(|d| d.code.clone()).collect();
+        assert_eq!(
+            codes,
+            vec![
+                Some(NumberOrString::String("syntax-error".to_string())),
+                Some(NumberOrString::String("parameter-count".to_string())),
+                None,
+            ]
+        );
+    }
+}
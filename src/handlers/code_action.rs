@@ -0,0 +1,89 @@
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams, CodeActionResponse,
+    Diagnostic,
+};
+
+use super::RequestContext;
+use crate::backend::Backend;
+use crate::code_action::{self, ActionData, DeferredAction};
+
+pub async fn code_action(
+    backend: &Backend,
+    params: CodeActionParams,
+) -> Result<Option<CodeActionResponse>> {
+    let uri = params.text_document.uri;
+    let Some(ctx) = RequestContext::for_br_document(backend, "code_action", &uri) else {
+        return Ok(None);
+    };
+    let Some(doc) = ctx.doc.as_ref() else {
+        return Ok(None);
+    };
+    let Some(tree) = doc.tree.as_ref() else {
+        return Ok(None);
+    };
+
+    // Clients that resolve actions get them without edits; the edit is
+    // computed in code_action_resolve once one is picked
+    let lazy = ctx.features().await.code_action_resolve;
+    let offer = |action: DeferredAction, diag: Option<&Diagnostic>| {
+        if lazy {
+            action.prepare(&uri, doc.version, diag, tree, &doc.source)
+        } else {
+            action.create(&uri, diag, tree, &doc.source)
+        }
+    };
+
+    let mut actions = Vec::new();
+    for diag in &params.context.diagnostics {
+        for action in DeferredAction::QUICK_FIXES {
+            if let Some(action) = offer(action, Some(diag)) {
+                actions.push(CodeActionOrCommand::CodeAction(action));
+            }
+        }
+        // Fixes carried by the diagnostic are already computed
+        if let Some(action) = code_action::create_fix_action(&uri, diag) {
+            actions.push(CodeActionOrCommand::CodeAction(action));
+        }
+        if let Some(action) = code_action::create_renumber_action(&uri, diag) {
+            actions.push(CodeActionOrCommand::CodeAction(action));
+        }
+    }
+
+    let wants_organize = params.context.only.as_ref().is_none_or(|only| {
+        only.iter().any(|kind| {
+            CodeActionKind::SOURCE_ORGANIZE_IMPORTS
+                .as_str()
+                .starts_with(kind.as_str())
+        })
+    });
+    if wants_organize {
+        if let Some(action) = offer(DeferredAction::OrganizeLibraries, None) {
+            actions.push(CodeActionOrCommand::CodeAction(action));
+        }
+    }
+
+    ctx.log(format!("{} actions", actions.len())).await;
+    Ok((!actions.is_empty()).then_some(actions))
+}
+
+pub async fn code_action_resolve(backend: &Backend, action: CodeAction) -> Result<CodeAction> {
+    let Some(data) = ActionData::from_action(&action) else {
+        return Ok(action);
+    };
+    let ctx = RequestContext::new(backend, "code_action_resolve", &data.uri);
+    let Some(doc) = ctx.doc.as_ref() else {
+        return Ok(action);
+    };
+    let Some(tree) = doc.tree.as_ref() else {
+        return Ok(action);
+    };
+    let action = code_action::resolve_action(action, &data, doc.version, tree, &doc.source);
+    let edits = action
+        .edit
+        .as_ref()
+        .and_then(|e| e.changes.as_ref())
+        .map_or(0, |c| c.values().map(Vec::len).sum::<usize>());
+    ctx.log(format!("{:?}, {edits} edits", data.action)).await;
+    Ok(action)
+}
@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{CodeLens, CodeLensParams, Command, Location, Url};
+
+use super::RequestContext;
+use crate::backend::Backend;
+use crate::{extract, metrics};
+
+/// Client command that opens the references peek view. VS Code's
+/// `editor.action.showReferences` takes editor types, so the extension
+/// converts the JSON arguments before calling it.
+pub const SHOW_REFERENCES_COMMAND: &str = "br.showReferences";
+
+/// What a lens needs to count its references when it's resolved.
+#[derive(Serialize, Deserialize)]
+struct LensData {
+    uri: Url,
+    name: String,
+}
+
+/// Lenses above function definitions: each DEF LIBRARY's use in other
+/// files, whose counting is left to `codeLens/resolve` (which the client
+/// only sends for lenses on screen), and each function's metrics.
+pub async fn code_lens(backend: &Backend, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+    let Some(ctx) =
+        RequestContext::for_br_document(backend, "code_lens", &params.text_document.uri)
+    else {
+        return Ok(None);
+    };
+    let config = ctx.config().await;
+    if !config.code_lens.external_usages && !config.code_lens.metrics {
+        return Ok(None);
+    }
+
+    let Some(lenses) = ctx.with_tree(|tree, source| {
+        let mut lenses = Vec::new();
+        if config.code_lens.external_usages {
+            lenses.extend(
+                extract::extract_definitions(tree, source)
+                    .into_iter()
+                    .filter(|def| def.is_library && !def.is_import_only)
+                    .map(|def| CodeLens {
+                        range: def.selection_range,
+                        command: None,
+                        data: serde_json::to_value(LensData {
+                            uri: params.text_document.uri.clone(),
+                            name: def.name,
+                        })
+                        .ok(),
+                    }),
+            );
+        }
+        if config.code_lens.metrics {
+            // An empty command shows the title without making it clickable
+            lenses.extend(
+                metrics::function_metrics(tree, source)
+                    .into_iter()
+                    .map(|metrics| CodeLens {
+                        range: metrics.range,
+                        command: Some(Command {
+                            title: metrics.summary(),
+                            command: String::new(),
+                            arguments: None,
+                        }),
+                        data: None,
+                    }),
+            );
+        }
+        Some(lenses)
+    }) else {
+        return Ok(None);
+    };
+
+    ctx.log(format!("{} lenses", lenses.len())).await;
+    Ok(Some(lenses))
+}
+
+pub async fn code_lens_resolve(backend: &Backend, mut lens: CodeLens) -> Result<CodeLens> {
+    let Some(data) = lens
+        .data
+        .take()
+        .and_then(|data| serde_json::from_value::<LensData>(data).ok())
+    else {
+        return Ok(lens);
+    };
+    let ctx = RequestContext::new(backend, "code_lens_resolve", &data.uri);
+
+    let external: Vec<Location> = backend
+        .search_workspace_for_function_refs(&data.name)
+        .await
+        .into_iter()
+        .filter(|location| location.uri != data.uri)
+        .collect();
+    let mut files: Vec<&Url> = external.iter().map(|location| &location.uri).collect();
+    files.sort();
+    files.dedup();
+    let title = external_usages_title(external.len(), files.len());
+
+    ctx.log(format!("{}: {title}", data.name)).await;
+    lens.command = Some(Command {
+        title,
+        command: SHOW_REFERENCES_COMMAND.to_string(),
+        arguments: Some(vec![
+            serde_json::json!(data.uri),
+            serde_json::json!(lens.range.start),
+            serde_json::json!(external),
+        ]),
+    });
+    Ok(lens)
+}
+
+fn external_usages_title(references: usize, files: usize) -> String {
+    match (references, files) {
+        (0, _) => "no references in other files".to_string(),
+        (1, _) => "1 reference in 1 other file".to_string(),
+        (n, 1) => format!("{n} references in 1 other file"),
+        (n, f) => format!("{n} references in {f} other files"),
+    }
+}
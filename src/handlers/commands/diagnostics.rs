@@ -0,0 +1,411 @@
+//! Commands that run the diagnostics over the workspace, fix what they
+//! find, or mute a file's.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use log::warn;
+use rayon::prelude::*;
+use serde_json::Value;
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{
+    Diagnostic, DocumentChanges, MessageType, OneOf, OptionalVersionedTextDocumentIdentifier,
+    TextDocumentEdit, Url, WorkspaceEdit,
+};
+
+use super::{options, uri_option};
+use crate::backend::{file_display_name, Backend, DocumentKind, DocumentSnapshot};
+use crate::handlers::RequestContext;
+use crate::{check, code_action, library_deps, parser, rules, workspace};
+
+pub async fn scan_all(ctx: RequestContext<'_>, args: Vec<Value>) -> Result<Option<Value>> {
+    // `{ groupBy: "file" | "rule" }`, by file if omitted
+    let group_by = args
+        .first()
+        .and_then(|options| options.get("groupBy"))
+        .and_then(|v| v.as_str());
+    let group_by = match group_by {
+        Some(value) => {
+            let Some(group_by) = check::GroupBy::parse(value) else {
+                return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                    "Unknown groupBy `{value}`; expected `file` or `rule`"
+                )));
+            };
+            group_by
+        }
+        None => check::GroupBy::default(),
+    };
+    let folders = ctx.backend.workspace_folders.read().await.clone();
+    let config = ctx.config().await;
+    let muted = config.diagnostics.muted_files.clone();
+
+    let mut results = tokio::task::spawn_blocking(move || {
+        let mut all_results: Vec<(Url, Vec<Diagnostic>)> = Vec::new();
+        let mut all_libraries = Vec::new();
+        for folder in &folders {
+            for (uri, diags, libraries) in
+                Backend::scan_workspace_diagnostics(folder, &config, &folders)
+            {
+                all_results.push((uri, diags));
+                all_libraries.push(libraries);
+            }
+        }
+
+        // Cross-file LIBRARY checks need every file's imports at once
+        if config.diagnostics.functions {
+            for (uri, diag) in library_deps::check_library_dependencies(&all_libraries) {
+                if let Some((_, diags)) = all_results.iter_mut().find(|(u, _)| *u == uri) {
+                    diags.push(diag);
+                }
+            }
+        }
+        all_results
+    })
+    .await
+    .unwrap_or_default();
+    results.retain(|(uri, _)| !muted.contains(uri));
+
+    for (uri, diags) in &results {
+        ctx.backend
+            .published_diagnostics
+            .publish(&ctx.backend.client, uri.clone(), diags.clone())
+            .await;
+    }
+
+    let total_files = results.len();
+    let files_with_errors = results.iter().filter(|(_, d)| !d.is_empty()).count();
+    let total_diags: usize = results.iter().map(|(_, d)| d.len()).sum();
+    let summary = format!("Scanned {total_files} files, {files_with_errors} with errors");
+
+    ctx.log_at(
+        MessageType::INFO,
+        format!(
+            "{total_files} files, {total_diags} diagnostics, {files_with_errors} files with errors"
+        ),
+    )
+    .await;
+
+    let csv = check::diagnostics_to_csv(&results);
+    let fixes = check::fixes_to_json(&results);
+    let groups = check::diagnostics_to_groups(&results, group_by);
+
+    Ok(Some(serde_json::json!({
+        "summary": summary,
+        "csv": csv,
+        "fixes": fixes,
+        "groupBy": group_by.as_str(),
+        "groups": groups,
+    })))
+}
+
+pub async fn run_rule(ctx: RequestContext<'_>, args: Vec<Value>) -> Result<Option<Value>> {
+    // Accepts either the rule code or `{ rule, files }`
+    let options = options(&args);
+    let code = options
+        .as_str()
+        .or_else(|| options.get("rule").and_then(|v| v.as_str()))
+        .unwrap_or_default();
+    let Some(rule) = rules::Rule::parse(code) else {
+        return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+            "Unknown rule `{code}`; expected one of: {}",
+            rules::codes().collect::<Vec<_>>().join(", ")
+        )));
+    };
+    if rule.needs_index() && !ctx.backend.indexing_complete.load(Ordering::Acquire) {
+        return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+            "`{}` needs the workspace index, which is still being built",
+            rule.code
+        )));
+    }
+    // Files as URIs or absolute paths; all workspace BR files if omitted
+    let files: Option<Vec<Url>> = options
+        .get("files")
+        .and_then(|v| v.as_array())
+        .map(|files| {
+            files
+                .iter()
+                .filter_map(|v| v.as_str())
+                .filter_map(|s| {
+                    if Path::new(s).is_absolute() {
+                        Url::from_file_path(s).ok()
+                    } else {
+                        Url::parse(s).ok()
+                    }
+                })
+                .collect()
+        });
+
+    let config = ctx.config().await;
+    let folders = ctx.backend.workspace_folders.read().await.clone();
+    let open_sources = ctx.backend.open_sources();
+    // Copies, so indexing isn't held up while the rule runs
+    let index = if rule.needs_index() {
+        Some(ctx.backend.workspace_index.read().await.clone())
+    } else {
+        None
+    };
+    let layouts = ctx.backend.layout_index.read().await.clone();
+
+    let (file_count, mut results) = tokio::task::spawn_blocking(move || {
+        let targets: Vec<Url> = match files {
+            Some(files) => files,
+            None => folders
+                .iter()
+                .filter_map(|folder| folder.to_file_path().ok())
+                .flat_map(|path| workspace::walk_br_files(&path, &config.paths.br_files))
+                .filter_map(|path| Url::from_file_path(path).ok())
+                .collect(),
+        };
+        let results: Vec<(Url, Vec<Diagnostic>)> = targets
+            .par_iter()
+            .filter_map(|uri| {
+                let source = match open_sources.get(uri.as_str()) {
+                    Some(s) => s.clone(),
+                    None => {
+                        let path = uri.to_file_path().ok()?;
+                        match workspace::read_br_file(&path) {
+                            Ok(s) => Arc::from(s),
+                            Err(e) => {
+                                warn!("Failed to read {}: {e}", path.display());
+                                return None;
+                            }
+                        }
+                    }
+                };
+                let mut ts_parser = parser::new_parser();
+                let tree = parser::parse(&mut ts_parser, &source, None)?;
+                let found = rule.run(
+                    uri,
+                    &tree,
+                    &source,
+                    &config.diagnostics,
+                    index.as_ref(),
+                    Some(&layouts),
+                );
+                (!found.is_empty()).then(|| (uri.clone(), found))
+            })
+            .collect();
+        (targets.len(), results)
+    })
+    .await
+    .unwrap_or_default();
+    results.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+
+    let count: usize = results.iter().map(|(_, d)| d.len()).sum();
+    ctx.log(format!(
+        "{}, {count} diagnostics in {} of {} files",
+        rule.code,
+        results.len(),
+        file_count,
+    ))
+    .await;
+
+    let results: Vec<Value> = results
+        .into_iter()
+        .map(|(uri, diagnostics)| {
+            serde_json::json!({
+                "uri": uri,
+                "diagnostics": diagnostics,
+            })
+        })
+        .collect();
+    Ok(Some(serde_json::json!({
+        "rule": rule.code,
+        "files": file_count,
+        "count": count,
+        "results": results,
+    })))
+}
+
+pub async fn fix_all(ctx: RequestContext<'_>, args: Vec<Value>) -> Result<Option<Value>> {
+    // Accepts a document URI or `{ uri, workspace }`
+    let options = options(&args);
+    let uri = uri_option(&options);
+    let whole_workspace = options
+        .get("workspace")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    if uri.is_none() && !whole_workspace {
+        return Err(tower_lsp::jsonrpc::Error::invalid_params(
+            "fixAll needs a document URI or `workspace: true`",
+        ));
+    }
+
+    let config = ctx.config().await;
+    let folders = ctx.backend.workspace_folders.read().await.clone();
+    let open_docs: Vec<(Url, DocumentSnapshot)> = ctx
+        .backend
+        .document_map
+        .iter()
+        .filter(|e| e.value().kind == DocumentKind::Br)
+        .filter_map(|e| Some((Url::parse(e.key()).ok()?, e.value().snapshot())))
+        .filter(|(u, _)| whole_workspace || Some(u) == uri.as_ref())
+        .collect();
+
+    let files = tokio::task::spawn_blocking(move || {
+        let open: HashSet<String> = open_docs.iter().map(|(u, _)| u.to_string()).collect();
+        let mut sources: Vec<(Url, Option<i32>, String, Option<tree_sitter::Tree>)> = open_docs
+            .into_iter()
+            .map(|(u, doc)| {
+                (
+                    u,
+                    Some(doc.client_version),
+                    doc.source.to_string(),
+                    doc.tree,
+                )
+            })
+            .collect();
+        // Closed files change through the same edit, unless the
+        // workspace is read-only
+        if whole_workspace && !config.diagnostics.read_only_workspace {
+            let no_open_sources = HashMap::new();
+            for folder in &folders {
+                let closed = Backend::scan_workspace_files(
+                    folder,
+                    &config.paths.br_files,
+                    &no_open_sources,
+                    |tree, source| (tree.clone(), source.to_string()),
+                );
+                sources.extend(
+                    closed
+                        .into_iter()
+                        .filter(|(u, _)| !open.contains(u.as_str()))
+                        .map(|(u, (tree, source))| (u, None, source, Some(tree))),
+                );
+            }
+        }
+
+        sources
+            .into_par_iter()
+            .filter(|(u, ..)| !config.diagnostics.muted_files.contains(u))
+            .filter_map(|(file_uri, version, source, tree)| {
+                let tree = match tree {
+                    Some(t) => t,
+                    None => parser::parse(&mut parser::new_parser(), &source, None)?,
+                };
+                let diags = Backend::collect_all_diagnostics(
+                    &file_uri,
+                    &tree,
+                    &source,
+                    &config.diagnostics,
+                    None,
+                    None,
+                    None,
+                );
+                let (edits, fixes) = code_action::fix_all_edits(&diags);
+                (fixes > 0).then_some((file_uri, version, edits, fixes))
+            })
+            .collect::<Vec<_>>()
+    })
+    .await
+    .unwrap_or_default();
+
+    let fix_count: usize = files.iter().map(|(.., fixes)| fixes).sum();
+    let file_count = files.len();
+    let mut applied = true;
+    let mut failure_reason = None;
+    if !files.is_empty() {
+        // Open documents carry the version the fixes were computed on, so
+        // the client refuses them if the user has typed since
+        let document_edits = files
+            .into_iter()
+            .map(|(uri, version, edits, _)| TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier { uri, version },
+                edits: edits.into_iter().map(OneOf::Left).collect(),
+            })
+            .collect();
+        let edit = WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Edits(document_edits)),
+            ..Default::default()
+        };
+        match ctx.backend.client.apply_edit(edit).await {
+            Ok(response) => {
+                applied = response.applied;
+                failure_reason = response.failure_reason;
+            }
+            Err(e) => {
+                applied = false;
+                failure_reason = Some(e.to_string());
+            }
+        }
+    }
+
+    let summary = match (&failure_reason, applied) {
+        (_, true) if fix_count == 0 => "No fixes to apply".to_string(),
+        (_, true) => format!("Applied {fix_count} fixes in {file_count} files"),
+        (Some(reason), false) => format!("Fixes were not applied: {reason}"),
+        (None, false) => "Fixes were not applied".to_string(),
+    };
+    ctx.log(&summary).await;
+    ctx.backend
+        .client
+        .show_message(
+            if applied {
+                MessageType::INFO
+            } else {
+                MessageType::WARNING
+            },
+            summary,
+        )
+        .await;
+    Ok(Some(serde_json::json!({
+        "fixes": if applied { fix_count } else { 0 },
+        "files": file_count,
+        "applied": applied,
+        "failureReason": failure_reason,
+    })))
+}
+
+/// `br-lsp.muteFile` with `mute` set, `br-lsp.unmuteFile` without.
+pub async fn mute_file(
+    ctx: RequestContext<'_>,
+    args: Vec<Value>,
+    mute: bool,
+) -> Result<Option<Value>> {
+    let Some(uri) = args
+        .first()
+        .and_then(|v| v.as_str())
+        .and_then(|u| Url::parse(u).ok())
+    else {
+        return Err(tower_lsp::jsonrpc::Error::invalid_params(
+            "Expected the URI of the file to mute or unmute",
+        ));
+    };
+    let folders = ctx.backend.workspace_folders.read().await.clone();
+    let (changed, muted_files) = {
+        let mut config = ctx.backend.config.write().await;
+        let changed = if mute {
+            config.diagnostics.muted_files.insert(&uri, &folders)
+        } else {
+            config.diagnostics.muted_files.remove(&uri)
+        };
+        (changed, config.diagnostics.muted_files.entries().to_vec())
+    };
+
+    // The debounced run republishes from the analysis cache, now
+    // filtered; a closed file just has its diagnostics cleared
+    let uri_string = uri.to_string();
+    if ctx.backend.document_map.contains_key(&uri_string) {
+        ctx.backend.schedule_diagnostics(uri.clone(), uri_string);
+    } else if mute {
+        ctx.backend
+            .published_diagnostics
+            .publish(&ctx.backend.client, uri.clone(), Vec::new())
+            .await;
+    }
+
+    ctx.log(format!(
+        "{} ({} muted)",
+        file_display_name(&uri),
+        muted_files.len()
+    ))
+    .await;
+
+    // Clients persist the list as `br-lsp.mutedFiles`
+    Ok(Some(serde_json::json!({
+        "changed": changed,
+        "mutedFiles": muted_files,
+    })))
+}
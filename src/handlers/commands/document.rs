@@ -0,0 +1,227 @@
+//! Commands that look into one open document, or reparse documents.
+
+use serde_json::Value;
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{MessageType, Range, Url};
+use tree_sitter::{Point, Tree};
+
+use crate::backend::{file_display_name, DocumentKind};
+use crate::handlers::RequestContext;
+use crate::{inline_values, parser, semantic_tokens};
+
+/// The document URI sent as the first argument.
+fn document_argument(args: &[Value]) -> Option<Url> {
+    args.first()
+        .and_then(|v| v.as_str())
+        .and_then(|u| Url::parse(u).ok())
+}
+
+pub async fn line_variables(ctx: RequestContext<'_>, args: Vec<Value>) -> Result<Option<Value>> {
+    let Some(uri) = document_argument(&args) else {
+        return Ok(None);
+    };
+    let Some(range) = args
+        .get(1)
+        .and_then(|v| serde_json::from_value::<Range>(v.clone()).ok())
+    else {
+        return Ok(None);
+    };
+
+    let ctx = ctx.with_document(&uri);
+    Ok(ctx.with_tree(|tree, source| {
+        let vars = inline_values::collect_line_variables(tree, source, range);
+        serde_json::to_value(vars).ok()
+    }))
+}
+
+pub async fn reparse(ctx: RequestContext<'_>, args: Vec<Value>) -> Result<Option<Value>> {
+    // With a URI argument reparse that document; otherwise every BR
+    // document currently stuck without a tree.
+    let targets: Vec<String> = match args.first().and_then(|v| v.as_str()) {
+        Some(uri) => vec![uri.to_string()],
+        None => ctx
+            .backend
+            .document_map
+            .iter()
+            .filter(|e| e.value().kind == DocumentKind::Br && e.value().tree.is_none())
+            .map(|e| e.key().clone())
+            .collect(),
+    };
+
+    // Clear any state an earlier failed parse left in the shared parser
+    ctx.backend.parser.lock().unwrap().reset();
+
+    let mut reparsed = Vec::new();
+    let mut failed = Vec::new();
+    for uri_string in targets {
+        let source = match ctx.backend.document_map.get(&uri_string) {
+            Some(doc) if doc.kind == DocumentKind::Br => doc.source.clone(),
+            _ => continue,
+        };
+        let tree = parser::parse_fresh(&source);
+        let parsed = tree.is_some();
+        if let Some(mut doc) = ctx.backend.document_map.get_mut(&uri_string) {
+            doc.tree = tree;
+            doc.version += 1;
+        }
+
+        let Ok(uri) = Url::parse(&uri_string) else {
+            continue;
+        };
+        if parsed {
+            ctx.backend.schedule_diagnostics(uri, uri_string.clone());
+            reparsed.push(uri_string);
+        } else {
+            ctx.backend
+                .client
+                .show_message(
+                    MessageType::WARNING,
+                    format!("br-lsp could not parse {}", file_display_name(&uri)),
+                )
+                .await;
+            failed.push(uri_string);
+        }
+    }
+
+    ctx.log(format!(
+        "{} reparsed, {} failed",
+        reparsed.len(),
+        failed.len()
+    ))
+    .await;
+
+    Ok(Some(serde_json::json!({
+        "reparsed": reparsed,
+        "failed": failed,
+    })))
+}
+
+pub async fn inspect_node(ctx: RequestContext<'_>, args: Vec<Value>) -> Result<Option<Value>> {
+    let Some(uri) = document_argument(&args) else {
+        return Ok(None);
+    };
+    let line = args.get(1).and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+    let character = args.get(2).and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+
+    let ctx = ctx.with_document(&uri);
+    Ok(ctx.with_tree(|tree, source| inspect(tree, source, line, character)))
+}
+
+/// The node at `line`/`character` as the node inspector shows it: its kind,
+/// text, ancestors and semantic token.
+fn inspect(tree: &Tree, source: &str, line: usize, character: usize) -> Option<Value> {
+    let point = Point::new(line, character);
+
+    // Deepest node (including anonymous like keywords/operators)
+    let node = tree.root_node().descendant_for_point_range(point, point)?;
+
+    let kind = node.kind().to_string();
+    let is_named = node.is_named();
+    let text = node.utf8_text(source.as_bytes()).unwrap_or("").to_string();
+    let text = if text.len() > 80 {
+        format!("{}...", &text[..80])
+    } else {
+        text
+    };
+
+    let range = parser::node_range(node);
+
+    // Build ancestor chain
+    let mut ancestors = Vec::new();
+    let mut current = node.parent();
+    while let Some(n) = current {
+        ancestors.push(n.kind().to_string());
+        current = n.parent();
+    }
+    ancestors.reverse();
+
+    // Named ancestor (for anonymous nodes)
+    let named_ancestor = if !is_named {
+        let mut p = node.parent();
+        while let Some(n) = p {
+            if n.is_named() {
+                break;
+            }
+            p = n.parent();
+        }
+        p.filter(|n| n.is_named()).map(|n| {
+            serde_json::json!({
+                "kind": n.kind(),
+                "range": {
+                    "start": { "line": parser::node_range(n).start.line, "character": parser::node_range(n).start.character },
+                    "end": { "line": parser::node_range(n).end.line, "character": parser::node_range(n).end.character }
+                }
+            })
+        })
+    } else {
+        None
+    };
+
+    // Semantic token classification via the named node at position
+    let semantic_token = {
+        let named_node = parser::node_at_position(tree, line, character);
+        named_node
+            .and_then(|nn| {
+                let in_parameter = semantic_tokens::is_inside(nn, "parameter_list")
+                    || semantic_tokens::is_inside(nn, "required_parameter")
+                    || semantic_tokens::is_inside(nn, "optional_parameter")
+                    || semantic_tokens::is_inside(nn, "parameter");
+                let in_dim = semantic_tokens::is_inside(nn, "dim_statement");
+                semantic_tokens::classify_node(nn.kind(), nn.is_named(), nn, in_parameter, in_dim)
+                    .map(|(type_idx, mod_bits)| {
+                        let type_name = semantic_tokens::TOKEN_TYPES
+                            .get(type_idx as usize)
+                            .map(|t| t.as_str().to_string())
+                            .unwrap_or_else(|| type_idx.to_string());
+                        let modifiers: Vec<String> = semantic_tokens::TOKEN_MODIFIERS
+                            .iter()
+                            .enumerate()
+                            .filter(|(i, _)| mod_bits & (1 << i) != 0)
+                            .map(|(_, m)| m.as_str().to_string())
+                            .collect();
+                        serde_json::json!({ "type": type_name, "modifiers": modifiers })
+                    })
+            })
+            // Also try classifying the deepest (possibly anonymous) node directly
+            .or_else(|| {
+                let in_parameter = semantic_tokens::is_inside(node, "parameter_list")
+                    || semantic_tokens::is_inside(node, "required_parameter")
+                    || semantic_tokens::is_inside(node, "optional_parameter")
+                    || semantic_tokens::is_inside(node, "parameter");
+                let in_dim = semantic_tokens::is_inside(node, "dim_statement");
+                semantic_tokens::classify_node(&kind, is_named, node, in_parameter, in_dim).map(
+                    |(type_idx, mod_bits)| {
+                        let type_name = semantic_tokens::TOKEN_TYPES
+                            .get(type_idx as usize)
+                            .map(|t| t.as_str().to_string())
+                            .unwrap_or_else(|| type_idx.to_string());
+                        let modifiers: Vec<String> = semantic_tokens::TOKEN_MODIFIERS
+                            .iter()
+                            .enumerate()
+                            .filter(|(i, _)| mod_bits & (1 << i) != 0)
+                            .map(|(_, m)| m.as_str().to_string())
+                            .collect();
+                        serde_json::json!({ "type": type_name, "modifiers": modifiers })
+                    },
+                )
+            })
+    };
+
+    Some(serde_json::json!({
+        "kind": kind,
+        "isNamed": is_named,
+        "text": text,
+        "range": {
+            "start": { "line": range.start.line, "character": range.start.character },
+            "end": { "line": range.end.line, "character": range.end.character }
+        },
+        "ancestors": ancestors,
+        "childCount": node.child_count(),
+        "namedChildCount": node.named_child_count(),
+        "isError": node.is_error(),
+        "isMissing": node.is_missing(),
+        "hasError": node.has_error(),
+        "namedAncestor": named_ancestor,
+        "semanticToken": semantic_token,
+    }))
+}
@@ -0,0 +1,105 @@
+//! Commands that edit an open document through the client.
+
+use std::collections::HashMap;
+
+use serde_json::Value;
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{MessageType, Url, WorkspaceEdit};
+
+use super::{options, uri_option};
+use crate::backend::file_display_name;
+use crate::handlers::RequestContext;
+use crate::{header, line_numbers};
+
+pub async fn renumber_lines(ctx: RequestContext<'_>, args: Vec<Value>) -> Result<Option<Value>> {
+    // Accepts a document URI or `{ uri, start, increment, padding }`
+    let options = options(&args);
+    let Some(uri) = uri_option(&options) else {
+        return Err(tower_lsp::jsonrpc::Error::invalid_params(
+            "Expected the URI of the file to renumber",
+        ));
+    };
+    let number = |key: &str| options.get(key).and_then(|v| v.as_u64());
+    let defaults = line_numbers::RenumberOptions::default();
+    let renumber_options = line_numbers::RenumberOptions {
+        start: number("start").map_or(defaults.start, |n| n as u32),
+        increment: number("increment").map_or(defaults.increment, |n| n as u32),
+        padding: number("padding").map(|n| n as usize),
+    };
+
+    let ctx = ctx.with_document(&uri);
+    let Some(renumbered) =
+        ctx.with_tree(|tree, source| Some(line_numbers::renumber(tree, source, renumber_options)))
+    else {
+        return Ok(None);
+    };
+    let edits = match renumbered {
+        Ok(edits) => edits,
+        Err(message) => {
+            ctx.backend
+                .client
+                .show_message(MessageType::WARNING, message.clone())
+                .await;
+            return Ok(Some(serde_json::json!({
+                "applied": false,
+                "failureReason": message,
+            })));
+        }
+    };
+    let count = edits.len();
+    let mut applied = true;
+    if !edits.is_empty() {
+        let edit = WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), edits)])),
+            ..Default::default()
+        };
+        applied = ctx
+            .backend
+            .client
+            .apply_edit(edit)
+            .await
+            .is_ok_and(|response| response.applied);
+    }
+    ctx.log(format!(
+        "{}, {count} edits, applied: {applied}",
+        file_display_name(&uri)
+    ))
+    .await;
+    Ok(Some(serde_json::json!({
+        "edits": count,
+        "applied": applied,
+    })))
+}
+
+pub async fn insert_header(ctx: RequestContext<'_>, args: Vec<Value>) -> Result<Option<Value>> {
+    let Some(uri) = args
+        .first()
+        .and_then(|v| v.as_str())
+        .and_then(|u| Url::parse(u).ok())
+    else {
+        return Err(tower_lsp::jsonrpc::Error::invalid_params(
+            "Expected the URI of the file to add a header to",
+        ));
+    };
+    let ctx = ctx.with_document(&uri);
+    let Some(doc) = ctx.doc.as_ref() else {
+        return Ok(None);
+    };
+    let template = ctx.config().await.header;
+    let Some(edit) = header::header_edit(&template, &uri, &doc.source, &header::today()) else {
+        return Ok(Some(serde_json::json!({ "inserted": false })));
+    };
+
+    let edit = WorkspaceEdit {
+        changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+        ..Default::default()
+    };
+    let inserted = match ctx.backend.client.apply_edit(edit).await {
+        Ok(response) => response.applied,
+        Err(e) => {
+            ctx.log_at(MessageType::WARNING, e).await;
+            false
+        }
+    };
+    Ok(Some(serde_json::json!({ "inserted": inserted })))
+}
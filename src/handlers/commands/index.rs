@@ -0,0 +1,83 @@
+//! Commands answered from the workspace index.
+
+use serde_json::Value;
+use tower_lsp::jsonrpc::Result;
+
+use super::{format_option, options, uri_option};
+use crate::backend::file_display_name;
+use crate::handlers::RequestContext;
+use crate::{call_graph, export, workspace};
+
+pub async fn export_index(ctx: RequestContext<'_>, _args: Vec<Value>) -> Result<Option<Value>> {
+    let result = {
+        let index = ctx.backend.workspace_index.read().await;
+        let layouts = ctx.backend.layout_index.read().await;
+        export::export_index(&index, &layouts)
+    };
+    ctx.log("exported").await;
+    Ok(Some(result))
+}
+
+pub async fn functions_in(ctx: RequestContext<'_>, args: Vec<Value>) -> Result<Option<Value>> {
+    // Accepts either the path pattern or `{ path }`
+    let options = options(&args);
+    let pattern = options
+        .as_str()
+        .or_else(|| options.get("path").and_then(|v| v.as_str()))
+        .unwrap_or_default();
+    let functions: Vec<Value> = ctx
+        .backend
+        .workspace_index
+        .read()
+        .await
+        .functions_in(pattern)
+        .into_iter()
+        .map(|f| export::function_json(f.uri.as_str(), &f.def))
+        .collect();
+    Ok(Some(Value::Array(functions)))
+}
+
+pub async fn extract_docs(ctx: RequestContext<'_>, args: Vec<Value>) -> Result<Option<Value>> {
+    // Accepts a file or folder URI or `{ uri }`
+    let Some(path) = uri_option(&options(&args)).and_then(|u| u.to_file_path().ok()) else {
+        return Err(tower_lsp::jsonrpc::Error::invalid_params(
+            "Expected the URI of a file or folder to extract docs from",
+        ));
+    };
+    let pattern = if path.is_dir() {
+        format!("{}/**", path.display())
+    } else {
+        path.display().to_string()
+    };
+    let index = ctx.backend.workspace_index.read().await;
+    Ok(Some(export::extract_docs(&index.functions_in(&pattern))))
+}
+
+pub async fn export_call_graph(ctx: RequestContext<'_>, args: Vec<Value>) -> Result<Option<Value>> {
+    let options = options(&args);
+    let format = format_option(&options);
+    let root = options
+        .get("root")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+    let depth = options
+        .get("depth")
+        .and_then(|v| v.as_u64())
+        .map(|d| d as usize);
+
+    let folders = ctx.backend.workspace_folders.read().await.clone();
+    let index = ctx.backend.workspace_index.read().await;
+    let calls: usize = index.calls().map(|(_, calls)| calls.len()).sum();
+    let graph = call_graph::CallGraph::from_index(&index, |uri| {
+        workspace::relative_path(uri, &folders).unwrap_or_else(|| file_display_name(uri))
+    });
+    drop(index);
+
+    ctx.log(format!("{calls} calls")).await;
+
+    Ok(Some(if format == "dot" {
+        Value::String(graph.to_dot(root.as_deref(), depth))
+    } else {
+        graph.to_json(root.as_deref(), depth)
+    }))
+}
@@ -0,0 +1,133 @@
+//! `workspace/executeCommand`: each command's handler, looked up by name in
+//! [`COMMANDS`].
+
+use std::future::Future;
+use std::pin::Pin;
+
+use log::debug;
+use serde_json::Value;
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{ExecuteCommandParams, Url};
+
+use super::RequestContext;
+use crate::backend::Backend;
+
+mod diagnostics;
+mod document;
+mod edit;
+mod index;
+mod reports;
+mod search;
+
+type CommandFuture<'a> = Pin<Box<dyn Future<Output = Result<Option<Value>>> + Send + 'a>>;
+
+/// Runs a command with its context and the arguments it was sent.
+type CommandHandler = for<'a> fn(RequestContext<'a>, Vec<Value>) -> CommandFuture<'a>;
+
+/// Every command the server runs, by name.
+const COMMANDS: &[(&str, CommandHandler)] = &[
+    ("br.scanAll", |ctx, args| {
+        Box::pin(diagnostics::scan_all(ctx, args))
+    }),
+    ("br-lsp.runRule", |ctx, args| {
+        Box::pin(diagnostics::run_rule(ctx, args))
+    }),
+    ("br-lsp.fixAll", |ctx, args| {
+        Box::pin(diagnostics::fix_all(ctx, args))
+    }),
+    ("br-lsp.muteFile", |ctx, args| {
+        Box::pin(diagnostics::mute_file(ctx, args, true))
+    }),
+    ("br-lsp.unmuteFile", |ctx, args| {
+        Box::pin(diagnostics::mute_file(ctx, args, false))
+    }),
+    ("br-lsp.exportIndex", |ctx, args| {
+        Box::pin(index::export_index(ctx, args))
+    }),
+    ("br-lsp.functionsIn", |ctx, args| {
+        Box::pin(index::functions_in(ctx, args))
+    }),
+    ("br-lsp.extractDocs", |ctx, args| {
+        Box::pin(index::extract_docs(ctx, args))
+    }),
+    ("br-lsp.exportCallGraph", |ctx, args| {
+        Box::pin(index::export_call_graph(ctx, args))
+    }),
+    ("br-lsp.findProgramReferences", |ctx, args| {
+        Box::pin(search::find_program_references(ctx, args))
+    }),
+    ("br-lsp.findLiteral", |ctx, args| {
+        Box::pin(search::find_literal(ctx, args))
+    }),
+    ("br-lsp.replaceAll", |ctx, args| {
+        Box::pin(search::replace_all(ctx, args))
+    }),
+    ("br-lsp.renumberLines", |ctx, args| {
+        Box::pin(edit::renumber_lines(ctx, args))
+    }),
+    ("br-lsp.insertHeader", |ctx, args| {
+        Box::pin(edit::insert_header(ctx, args))
+    }),
+    ("br-lsp.errorHandlingReport", |ctx, args| {
+        Box::pin(reports::error_handling_report(ctx, args))
+    }),
+    ("br-lsp.metricsReport", |ctx, args| {
+        Box::pin(reports::metrics_report(ctx, args))
+    }),
+    ("br-lsp.orphanReport", |ctx, args| {
+        Box::pin(reports::orphan_report(ctx, args))
+    }),
+    ("br-lsp.lineVariables", |ctx, args| {
+        Box::pin(document::line_variables(ctx, args))
+    }),
+    ("br-lsp.reparse", |ctx, args| {
+        Box::pin(document::reparse(ctx, args))
+    }),
+    ("br.inspectNode", |ctx, args| {
+        Box::pin(document::inspect_node(ctx, args))
+    }),
+];
+
+pub async fn execute_command(
+    backend: &Backend,
+    params: ExecuteCommandParams,
+) -> Result<Option<Value>> {
+    // The node inspector sends one on every hover
+    if params.command != "br.inspectNode" {
+        debug!("command executed: {}", params.command);
+    }
+
+    let Some((name, handler)) = COMMANDS.iter().find(|(name, _)| *name == params.command) else {
+        return Ok(None);
+    };
+    handler(RequestContext::for_command(backend, name), params.arguments).await
+}
+
+/// The first argument, or `null` when none was sent.
+fn options(args: &[Value]) -> Value {
+    args.first().cloned().unwrap_or(Value::Null)
+}
+
+/// A URI sent as the argument itself or as its `uri` field.
+fn uri_option(options: &Value) -> Option<Url> {
+    options
+        .as_str()
+        .or_else(|| options.get("uri").and_then(|v| v.as_str()))
+        .and_then(|u| Url::parse(u).ok())
+}
+
+/// The report format asked for in `{ format }`, lowercase; `json` if none.
+fn format_option(options: &Value) -> String {
+    options
+        .get("format")
+        .and_then(|v| v.as_str())
+        .unwrap_or("json")
+        .to_ascii_lowercase()
+}
+
+/// A file as a CSV report names it: its path, or the URI if it has none.
+fn csv_file(uri: &Url) -> String {
+    uri.to_file_path()
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|()| uri.to_string())
+}
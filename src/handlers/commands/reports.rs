@@ -0,0 +1,237 @@
+//! Workspace reports, as JSON or, with `{ format: "csv" }`, CSV text.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use serde_json::Value;
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::Url;
+
+use super::{csv_file, format_option, options};
+use crate::backend::Backend;
+use crate::handlers::RequestContext;
+use crate::{document_links, error_handling, metrics, orphans};
+
+pub async fn error_handling_report(
+    ctx: RequestContext<'_>,
+    args: Vec<Value>,
+) -> Result<Option<Value>> {
+    let format = format_option(&options(&args));
+
+    let folders = ctx.backend.workspace_folders.read().await.clone();
+    let detection = ctx.backend.config.read().await.paths.br_files.clone();
+    let open_sources = ctx.backend.open_sources();
+
+    let mut reports = tokio::task::spawn_blocking(move || {
+        let mut reports = Vec::new();
+        for folder in &folders {
+            reports.extend(Backend::scan_workspace_files(
+                folder,
+                &detection,
+                &open_sources,
+                error_handling::check_error_handling,
+            ));
+        }
+        reports
+    })
+    .await
+    .unwrap_or_default();
+    reports.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+
+    let io_statements: usize = reports.iter().map(|(_, r)| r.io_statements).sum();
+    let rows: Vec<(Url, error_handling::UnhandledIo)> = reports
+        .into_iter()
+        .flat_map(|(uri, report)| {
+            report
+                .unhandled
+                .into_iter()
+                .map(move |io| (uri.clone(), io))
+        })
+        .collect();
+
+    ctx.log(format!(
+        "{} of {io_statements} I/O statements unhandled",
+        rows.len()
+    ))
+    .await;
+
+    if format == "csv" {
+        let rows: Vec<(String, error_handling::UnhandledIo)> = rows
+            .into_iter()
+            .map(|(uri, io)| (csv_file(&uri), io))
+            .collect();
+        return Ok(Some(Value::String(error_handling::format_csv(&rows))));
+    }
+
+    let unhandled: Vec<Value> = rows
+        .into_iter()
+        .map(|(uri, io)| {
+            serde_json::json!({
+                "uri": uri,
+                "range": io.range,
+                "statement": io.statement,
+                "text": io.text,
+            })
+        })
+        .collect();
+    Ok(Some(serde_json::json!({
+        "ioStatements": io_statements,
+        "unhandled": unhandled,
+    })))
+}
+
+pub async fn metrics_report(ctx: RequestContext<'_>, args: Vec<Value>) -> Result<Option<Value>> {
+    let format = format_option(&options(&args));
+
+    let folders = ctx.backend.workspace_folders.read().await.clone();
+    let config = ctx.config().await;
+    let open_sources = ctx.backend.open_sources();
+
+    let detection = config.paths.br_files.clone();
+    let mut reports = tokio::task::spawn_blocking(move || {
+        let mut reports = Vec::new();
+        for folder in &folders {
+            reports.extend(Backend::scan_workspace_files(
+                folder,
+                &detection,
+                &open_sources,
+                metrics::function_metrics,
+            ));
+        }
+        reports
+    })
+    .await
+    .unwrap_or_default();
+    reports.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+
+    let rows: Vec<(Url, metrics::FunctionMetrics)> = reports
+        .into_iter()
+        .flat_map(|(uri, functions)| functions.into_iter().map(move |f| (uri.clone(), f)))
+        .collect();
+    let exceeding = rows
+        .iter()
+        .filter(|(_, f)| !config.diagnostics.metrics.exceeded(f).is_empty())
+        .count();
+
+    ctx.log(format!(
+        "{} functions, {exceeding} past the limits",
+        rows.len()
+    ))
+    .await;
+
+    if format == "csv" {
+        let rows: Vec<(String, metrics::FunctionMetrics)> = rows
+            .into_iter()
+            .map(|(uri, f)| (csv_file(&uri), f))
+            .collect();
+        return Ok(Some(Value::String(metrics::format_csv(&rows))));
+    }
+
+    let functions: Vec<Value> = rows
+        .into_iter()
+        .map(|(uri, f)| {
+            serde_json::json!({
+                "uri": uri,
+                "name": f.name,
+                "range": f.range,
+                "lines": f.lines,
+                "complexity": f.complexity,
+                "parameters": f.parameters,
+                "exceeded": config.diagnostics.metrics.exceeded(&f),
+            })
+        })
+        .collect();
+    Ok(Some(serde_json::json!({
+        "functions": functions,
+        "exceeding": exceeding,
+    })))
+}
+
+pub async fn orphan_report(ctx: RequestContext<'_>, args: Vec<Value>) -> Result<Option<Value>> {
+    let format = format_option(&options(&args));
+
+    let (programs, libraries) = {
+        let index = ctx.backend.workspace_index.read().await;
+        let mut programs: Vec<Url> = index.indexed_files().map(|(uri, _)| uri.clone()).collect();
+        programs.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        let libraries: HashSet<Url> = index
+            .all_symbols()
+            .into_iter()
+            .filter(|s| s.def.is_library && !s.def.is_import_only)
+            .map(|s| s.uri.clone())
+            .collect();
+        (programs, libraries)
+    };
+    let folders = ctx.backend.workspace_folders.read().await.clone();
+    let config = ctx.config().await;
+    let open_sources = ctx.backend.open_sources();
+
+    let program_count = programs.len();
+    let (orphans, references) = tokio::task::spawn_blocking(move || {
+        let folder_paths: Vec<PathBuf> = folders
+            .iter()
+            .filter_map(|folder| folder.to_file_path().ok())
+            .collect();
+        let mut referenced = Vec::new();
+        for folder in &folders {
+            let files = Backend::scan_workspace_files(
+                folder,
+                &config.paths.br_files,
+                &open_sources,
+                document_links::collect_program_references,
+            );
+            for (uri, refs) in files {
+                let document_path = uri.to_file_path().ok();
+                let bases = document_links::search_bases(
+                    &folder_paths,
+                    &config.paths.program_search_paths,
+                    document_path.as_deref().and_then(|p| p.parent()),
+                );
+                referenced.extend(refs.iter().filter_map(|r| {
+                    let path = document_links::resolve_program(
+                        &r.name,
+                        &bases,
+                        &config.diagnostics.drive_mappings,
+                        &folder_paths,
+                    )?;
+                    Some((uri.clone(), path))
+                }));
+            }
+        }
+        (
+            orphans::find_orphans(&programs, &referenced),
+            referenced.len(),
+        )
+    })
+    .await
+    .unwrap_or_default();
+
+    ctx.log(format!(
+        "{} of {program_count} programs unreferenced, {references} references resolved",
+        orphans.len()
+    ))
+    .await;
+
+    if format == "csv" {
+        let rows: Vec<(String, bool)> = orphans
+            .iter()
+            .map(|uri| (csv_file(uri), libraries.contains(uri)))
+            .collect();
+        return Ok(Some(Value::String(orphans::format_csv(&rows))));
+    }
+
+    let orphans: Vec<Value> = orphans
+        .iter()
+        .map(|uri| {
+            serde_json::json!({
+                "uri": uri,
+                "library": libraries.contains(uri),
+            })
+        })
+        .collect();
+    Ok(Some(serde_json::json!({
+        "programs": program_count,
+        "references": references,
+        "orphans": orphans,
+    })))
+}
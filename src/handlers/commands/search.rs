@@ -0,0 +1,221 @@
+//! Commands that search the workspace's files, and replace what they find.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use serde_json::Value;
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{TextEdit, Url, WorkspaceEdit};
+
+use super::{options, uri_option};
+use crate::backend::Backend;
+use crate::handlers::RequestContext;
+use crate::{literals, replace, workspace};
+
+pub async fn find_program_references(
+    ctx: RequestContext<'_>,
+    args: Vec<Value>,
+) -> Result<Option<Value>> {
+    // Accepts the program's URI or `{ uri }`
+    let Some(target) = uri_option(&options(&args)).and_then(|u| u.to_file_path().ok()) else {
+        return Err(tower_lsp::jsonrpc::Error::invalid_params(
+            "Expected the URI of a program",
+        ));
+    };
+    let locations = ctx.backend.find_program_references(target).await;
+    ctx.log(format!("{} locations", locations.len())).await;
+    Ok(Some(serde_json::to_value(locations).unwrap_or_default()))
+}
+
+pub async fn find_literal(ctx: RequestContext<'_>, args: Vec<Value>) -> Result<Option<Value>> {
+    // Accepts either the search text or `{ text, cp437 }`
+    let options = options(&args);
+    let needle = options
+        .as_str()
+        .or_else(|| options.get("text").and_then(|v| v.as_str()))
+        .unwrap_or_default()
+        .to_string();
+    let cp437 = options
+        .get("cp437")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    if needle.is_empty() {
+        return Ok(Some(Value::Array(Vec::new())));
+    }
+
+    let folders = ctx.backend.workspace_folders.read().await.clone();
+    let detection = ctx.backend.config.read().await.paths.br_files.clone();
+    let open_sources = ctx.backend.open_sources();
+
+    let search = needle.clone();
+    let mut found = tokio::task::spawn_blocking(move || {
+        let mut found = Vec::new();
+        for folder in &folders {
+            let files =
+                Backend::scan_workspace_files(folder, &detection, &open_sources, |tree, src| {
+                    literals::find_literals(tree, src, &search, cp437)
+                });
+            for (uri, matches) in files {
+                found.extend(matches.into_iter().map(|m| (uri.clone(), m)));
+            }
+        }
+        found
+    })
+    .await
+    .unwrap_or_default();
+    found.sort_by(|(a, am), (b, bm)| {
+        a.as_str()
+            .cmp(b.as_str())
+            .then(am.range.start.line.cmp(&bm.range.start.line))
+            .then(am.range.start.character.cmp(&bm.range.start.character))
+    });
+
+    ctx.log(format!("{needle:?}, {} matches", found.len()))
+        .await;
+
+    let results: Vec<Value> = found
+        .into_iter()
+        .map(|(uri, m)| {
+            serde_json::json!({
+                "uri": uri,
+                "range": m.range,
+                "literal": m.literal,
+            })
+        })
+        .collect();
+    Ok(Some(Value::Array(results)))
+}
+
+pub async fn replace_all(ctx: RequestContext<'_>, args: Vec<Value>) -> Result<Option<Value>> {
+    let options: replace::ReplaceOptions =
+        serde_json::from_value(super::options(&args)).unwrap_or_default();
+    if options.search.is_empty() {
+        return Err(tower_lsp::jsonrpc::Error::invalid_params(
+            "replaceAll needs a `search` string",
+        ));
+    }
+    let matcher = options.matcher().map_err(|e| {
+        tower_lsp::jsonrpc::Error::invalid_params(format!("Invalid search pattern: {e}"))
+    })?;
+
+    let folders = ctx.backend.workspace_folders.read().await.clone();
+    let (detection, read_only) = {
+        let config = ctx.backend.config.read().await;
+        (
+            config.paths.br_files.clone(),
+            config.diagnostics.read_only_workspace,
+        )
+    };
+    let open_sources = ctx.backend.open_sources();
+    let open: HashSet<String> = open_sources.keys().cloned().collect();
+
+    let scan_options = options.clone();
+    let mut files = tokio::task::spawn_blocking(move || {
+        let mut files = Vec::new();
+        for folder in &folders {
+            let found =
+                Backend::scan_workspace_files(folder, &detection, &open_sources, |_, src| {
+                    let found = replace::find_replacements(src, &matcher, &scan_options);
+                    (!found.is_empty()).then(|| (Arc::<str>::from(src), found))
+                });
+            files.extend(found.into_iter().filter_map(|(uri, found)| {
+                let (source, found) = found?;
+                Some((uri, source, found))
+            }));
+        }
+        files
+    })
+    .await
+    .unwrap_or_default();
+    files.sort_by(|(a, ..), (b, ..)| a.as_str().cmp(b.as_str()));
+    let match_count: usize = files.iter().map(|(_, _, found)| found.len()).sum();
+    let file_count = files.len();
+
+    if options.dry_run {
+        let open = &open;
+        let locations: Vec<Value> = files
+            .iter()
+            .flat_map(|(uri, _, found)| {
+                found.iter().map(move |r| {
+                    serde_json::json!({
+                        "uri": uri,
+                        "range": r.range,
+                        "text": r.matched,
+                        "replacement": r.new_text,
+                        "open": open.contains(uri.as_str()),
+                    })
+                })
+            })
+            .collect();
+        ctx.log(format!(
+            "(dry run) {:?}, {match_count} matches in {file_count} files",
+            options.search
+        ))
+        .await;
+        return Ok(Some(serde_json::json!({
+            "files": file_count,
+            "matches": match_count,
+            "locations": locations,
+        })));
+    }
+
+    // Open files change through the returned edit, so the editor keeps
+    // undo and dirty state; closed files are written only on request
+    let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+    let mut written = Vec::new();
+    let mut pending = Vec::new();
+    let mut skipped = Vec::new();
+    for (uri, source, found) in files {
+        if open.contains(uri.as_str()) {
+            changes.insert(uri, found.iter().map(|r| r.text_edit()).collect());
+            continue;
+        }
+        if !options.write_closed_files {
+            pending.push(uri);
+            continue;
+        }
+        if read_only {
+            skipped.push(serde_json::json!({
+                "uri": uri,
+                "reason": "read-only workspace",
+            }));
+            continue;
+        }
+        let Ok(path) = uri.to_file_path() else {
+            continue;
+        };
+        match workspace::write_br_file(&path, &replace::apply(&source, &found)) {
+            Ok(()) => {
+                ctx.backend.index_file_from_disk(&uri, &path).await;
+                written.push(uri);
+            }
+            Err(e) => skipped.push(serde_json::json!({
+                "uri": uri,
+                "reason": e.to_string(),
+            })),
+        }
+    }
+
+    ctx.log(format!(
+        "{:?} -> {:?}, {match_count} matches in {file_count} files; {} open, {} written, {} pending, {} skipped",
+        options.search,
+        options.replace,
+        changes.len(),
+        written.len(),
+        pending.len(),
+        skipped.len(),
+    ))
+    .await;
+    let edit = WorkspaceEdit {
+        changes: Some(changes),
+        ..Default::default()
+    };
+    Ok(Some(serde_json::json!({
+        "files": file_count,
+        "matches": match_count,
+        "edit": edit,
+        "written": written,
+        "pending": pending,
+        "skipped": skipped,
+    })))
+}
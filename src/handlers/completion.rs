@@ -0,0 +1,148 @@
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionList, CompletionParams, CompletionResponse, Documentation,
+};
+
+use super::{read_or_skip, RequestContext};
+use crate::backend::{markup_content, Backend};
+use crate::dialect::Dialect;
+use crate::layout::LayoutIndex;
+use crate::workspace::WorkspaceIndex;
+use crate::{builtins, completions, extract};
+
+pub async fn completion(
+    backend: &Backend,
+    params: CompletionParams,
+) -> Result<Option<CompletionResponse>> {
+    let position = params.text_document_position.position;
+    let uri = params.text_document_position.text_document.uri;
+    let Some(ctx) = RequestContext::for_br_document(backend, "completion", &uri) else {
+        return Ok(None);
+    };
+
+    let config = ctx.config().await;
+    let features = ctx.features().await;
+    // Placeholders would be inserted literally without snippet support
+    let snippets = if features.completion_snippets {
+        backend.workspace_snippets.read().await.clone()
+    } else {
+        Vec::new()
+    };
+    // While a rescan holds the indexes, complete from the document alone
+    // and mark the list incomplete so the client asks again
+    let index = read_or_skip(&backend.workspace_index).await;
+    let layout_index = read_or_skip(&backend.layout_index).await;
+    let indexes_busy = index.is_none() || layout_index.is_none();
+    let Some(doc) = ctx.doc.as_ref() else {
+        return Ok(None);
+    };
+    let mut items = completions::get_completions(
+        doc,
+        uri.as_str(),
+        position,
+        index.as_deref().unwrap_or(&WorkspaceIndex::new()),
+        layout_index.as_deref().unwrap_or(&LayoutIndex::new()),
+        config.diagnostics.br_version,
+        &config.completion,
+        &snippets,
+    );
+    let dialect = Dialect::of(uri.as_str());
+    items.retain(|item| config.diagnostics.dialects.allows_completion(dialect, item));
+    let cut = completions::limit_items(
+        &mut items,
+        &doc.source,
+        position,
+        config.completion.max_items,
+    );
+    let is_incomplete = cut || indexes_busy;
+    drop(index);
+    drop(layout_index);
+
+    // Without resolve support the documentation has to be sent up front
+    completions::apply_insert_options(
+        &mut items,
+        &config.completion,
+        features.completion_snippets,
+        features.commit_characters,
+    );
+    if !features.completion_resolve {
+        for item in &mut items {
+            let Some(data) = item
+                .data
+                .take()
+                .and_then(|v| serde_json::from_value(v).ok())
+            else {
+                continue;
+            };
+            if let Some(md) = completion_docs(&ctx, &data).await {
+                item.documentation = Some(Documentation::MarkupContent(markup_content(
+                    md,
+                    features.completion_markdown,
+                )));
+            }
+        }
+    }
+
+    ctx.log(format!("{} items", items.len())).await;
+    Ok(if is_incomplete {
+        Some(CompletionResponse::List(CompletionList {
+            is_incomplete: true,
+            items,
+        }))
+    } else if items.is_empty() {
+        None
+    } else {
+        Some(CompletionResponse::Array(items))
+    })
+}
+
+pub async fn completion_resolve(
+    backend: &Backend,
+    mut item: CompletionItem,
+) -> Result<CompletionItem> {
+    let Some(data) = item
+        .data
+        .as_ref()
+        .and_then(|v| serde_json::from_value::<completions::CompletionData>(v.clone()).ok())
+    else {
+        return Ok(item);
+    };
+
+    let ctx = RequestContext::for_command(backend, "completion_resolve");
+    if let Some(md) = completion_docs(&ctx, &data).await {
+        let markdown = ctx.features().await.completion_markdown;
+        item.documentation = Some(Documentation::MarkupContent(markup_content(md, markdown)));
+    }
+    Ok(item)
+}
+
+/// Markdown documentation for a completion item, looked up from its `data`.
+async fn completion_docs(
+    ctx: &RequestContext<'_>,
+    data: &completions::CompletionData,
+) -> Option<String> {
+    match data {
+        completions::CompletionData::Builtin { name, overloads } => {
+            let entries = builtins::lookup(name);
+            let docs: Vec<String> = overloads
+                .iter()
+                .filter_map(|i| entries.get(*i))
+                .map(completions::format_builtin_docs)
+                .collect();
+            (!docs.is_empty()).then(|| docs.join("\n\n---\n\n"))
+        }
+        completions::CompletionData::Local { name, uri } => {
+            let doc = ctx.backend.snapshot(uri)?;
+            let tree = doc.tree.as_ref()?;
+            extract::extract_definitions(tree, &doc.source)
+                .into_iter()
+                .find(|d| d.name.eq_ignore_ascii_case(name))
+                .map(|d| completions::format_function_docs(&d))
+        }
+        completions::CompletionData::Workspace { name } => ctx
+            .workspace_index()
+            .await?
+            .lookup_best(name, "")
+            .map(|e| completions::format_function_docs(&e.def)),
+    }
+}
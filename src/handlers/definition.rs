@@ -0,0 +1,91 @@
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{
+    GotoDefinitionParams, GotoDefinitionResponse, Location, Position, Range, Url,
+};
+
+use super::RequestContext;
+use crate::backend::Backend;
+use crate::workspace::IndexedFunctionDef;
+use crate::{definition, extract};
+
+pub async fn goto_definition(
+    backend: &Backend,
+    params: GotoDefinitionParams,
+) -> Result<Option<GotoDefinitionResponse>> {
+    let position = params.text_document_position_params.position;
+    let Some(ctx) = RequestContext::for_br_document(
+        backend,
+        "definition",
+        &params.text_document_position_params.text_document.uri,
+    ) else {
+        return Ok(None);
+    };
+
+    Ok(resolve_definition(&ctx, position).await.map(|target| {
+        GotoDefinitionResponse::Scalar(Location {
+            uri: target.uri,
+            range: target.selection_range,
+        })
+    }))
+}
+
+/// A resolved definition: the statement that defines it and the name to
+/// put the cursor on.
+pub struct DefinitionTarget {
+    pub uri: Url,
+    pub range: Range,
+    pub selection_range: Range,
+}
+
+/// Where the symbol at `position` is defined: in the document itself, or
+/// for a function defined elsewhere, the definition the workspace index
+/// ranks first.
+pub async fn resolve_definition(
+    ctx: &RequestContext<'_>,
+    position: Position,
+) -> Option<DefinitionTarget> {
+    let uri = ctx.uri.as_ref()?;
+    // Library links are extracted alongside so the tree isn't needed across awaits
+    let result = ctx.with_tree(|tree, source| {
+        let result = definition::find_definition(
+            tree,
+            source,
+            position.line as usize,
+            position.character as usize,
+        );
+        Some((result, extract::extract_library_links(tree, source)))
+    });
+
+    match result {
+        Some((definition::DefinitionResult::Found(range), _)) => {
+            ctx.log("local, found").await;
+            Some(DefinitionTarget {
+                uri: uri.clone(),
+                range,
+                selection_range: range,
+            })
+        }
+        Some((definition::DefinitionResult::LookupFunction(name), mut library_links)) => {
+            let mappings = ctx.config().await.diagnostics.drive_mappings;
+            let folders = ctx.workspace_folders().await;
+            mappings.map_library_links(&mut library_links, &folders);
+            let target = |def: &IndexedFunctionDef| DefinitionTarget {
+                uri: def.uri.clone(),
+                range: def.def.range,
+                selection_range: def.def.selection_range,
+            };
+            let def = match ctx.workspace_index().await {
+                Some(index) => index
+                    .lookup_prioritized_with_links(&name, uri.as_str(), &library_links, &folders)
+                    .first()
+                    .map(|def| target(def)),
+                None => ctx.local_definitions(&name).first().map(target),
+            };
+
+            let found = if def.is_some() { "found" } else { "not found" };
+            ctx.log(format!("workspace \"{name}\", {found}")).await;
+            def
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,73 @@
+use std::path::PathBuf;
+
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{DocumentLink, DocumentLinkParams, Url};
+
+use super::RequestContext;
+use crate::backend::Backend;
+use crate::document_links::{self, ProgramReferenceKind};
+
+pub async fn document_link(
+    backend: &Backend,
+    params: DocumentLinkParams,
+) -> Result<Option<Vec<DocumentLink>>> {
+    let Some(ctx) =
+        RequestContext::for_br_document(backend, "document_link", &params.text_document.uri)
+    else {
+        return Ok(None);
+    };
+
+    let Some(references) = ctx
+        .with_tree(|tree, source| Some(document_links::collect_program_references(tree, source)))
+    else {
+        return Ok(None);
+    };
+    if references.is_empty() {
+        return Ok(None);
+    }
+
+    let folders: Vec<PathBuf> = backend
+        .workspace_folders
+        .read()
+        .await
+        .iter()
+        .filter_map(|folder| folder.to_file_path().ok())
+        .collect();
+    let config = ctx.config().await;
+    let document_path = params.text_document.uri.to_file_path().ok();
+    let bases = document_links::search_bases(
+        &folders,
+        &config.paths.program_search_paths,
+        document_path.as_deref().and_then(|p| p.parent()),
+    );
+
+    let total = references.len();
+    let links: Vec<DocumentLink> = references
+        .into_iter()
+        .filter_map(|reference| {
+            let path = document_links::resolve_program(
+                &reference.name,
+                &bases,
+                &config.diagnostics.drive_mappings,
+                &folders,
+            )?;
+            let target = Url::from_file_path(&path).ok()?;
+            let file_name = path.file_name()?.to_string_lossy().into_owned();
+            Some(DocumentLink {
+                range: reference.range,
+                target: Some(target),
+                tooltip: Some(match reference.kind {
+                    ProgramReferenceKind::Library => format!("Open library {file_name}"),
+                    ProgramReferenceKind::Chain | ProgramReferenceKind::Run => {
+                        format!("Open program {file_name}")
+                    }
+                }),
+                data: None,
+            })
+        })
+        .collect();
+
+    let resolved = links.len();
+    ctx.log(format!("{resolved} of {total} resolved")).await;
+    Ok(Some(links))
+}
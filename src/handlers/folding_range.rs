@@ -0,0 +1,23 @@
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{FoldingRange, FoldingRangeParams};
+
+use super::RequestContext;
+use crate::backend::Backend;
+use crate::folding;
+
+pub async fn folding_range(
+    backend: &Backend,
+    params: FoldingRangeParams,
+) -> Result<Option<Vec<FoldingRange>>> {
+    let Some(ctx) =
+        RequestContext::for_br_document(backend, "folding_range", &params.text_document.uri)
+    else {
+        return Ok(None);
+    };
+    let Some(ranges) = ctx.with_tree(|tree, source| Some(folding::folding_ranges(tree, source)))
+    else {
+        return Ok(None);
+    };
+    ctx.log(format!("{} ranges", ranges.len())).await;
+    Ok(Some(ranges))
+}
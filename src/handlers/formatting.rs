@@ -0,0 +1,46 @@
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{DocumentFormattingParams, DocumentOnTypeFormattingParams, TextEdit};
+
+use super::RequestContext;
+use crate::backend::Backend;
+use crate::{on_type_formatting, style};
+
+pub async fn formatting(
+    backend: &Backend,
+    params: DocumentFormattingParams,
+) -> Result<Option<Vec<TextEdit>>> {
+    let Some(ctx) =
+        RequestContext::for_br_document(backend, "formatting", &params.text_document.uri)
+    else {
+        return Ok(None);
+    };
+    let Some(case) = ctx.config().await.diagnostics.keyword_case else {
+        return Ok(None);
+    };
+
+    Ok(ctx.with_tree(|tree, source| Some(style::keyword_case_edits(tree, source, case))))
+}
+
+pub async fn on_type_formatting(
+    backend: &Backend,
+    params: DocumentOnTypeFormattingParams,
+) -> Result<Option<Vec<TextEdit>>> {
+    if params.ch != "\n" {
+        return Ok(None);
+    }
+    let position = params.text_document_position.position;
+    let Some(ctx) = RequestContext::for_br_document(
+        backend,
+        "on_type_formatting",
+        &params.text_document_position.text_document.uri,
+    ) else {
+        return Ok(None);
+    };
+
+    let line_numbers = ctx.config().await.auto_line_numbers;
+    Ok(ctx.with_tree(|tree, source| {
+        on_type_formatting::block_closer_edit(tree, source, position)
+            .or_else(|| on_type_formatting::line_number_edit(source, position, line_numbers))
+            .map(|e| vec![e])
+    }))
+}
@@ -0,0 +1,32 @@
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{DocumentHighlight, DocumentHighlightParams};
+
+use super::RequestContext;
+use crate::backend::Backend;
+use crate::references;
+
+pub async fn document_highlight(
+    backend: &Backend,
+    params: DocumentHighlightParams,
+) -> Result<Option<Vec<DocumentHighlight>>> {
+    let position = params.text_document_position_params.position;
+    let Some(ctx) = RequestContext::for_br_document(
+        backend,
+        "document_highlight",
+        &params.text_document_position_params.text_document.uri,
+    ) else {
+        return Ok(None);
+    };
+
+    let highlights = ctx.with_tree(|tree, source| {
+        let highlights = references::find_highlights(
+            tree,
+            source,
+            position.line as usize,
+            position.character as usize,
+        );
+        (!highlights.is_empty()).then_some(highlights)
+    });
+
+    Ok(highlights)
+}
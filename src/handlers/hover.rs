@@ -0,0 +1,315 @@
+use std::collections::HashMap;
+
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{Hover, HoverContents, HoverParams, Url};
+
+use super::RequestContext;
+use crate::backend::{markup_content, Backend, DocumentKind};
+use crate::symbols::{DeclarationKind, VariableDeclaration};
+use crate::{
+    builtins, completions, constants, definition, extract, files, parser, pic, statements,
+    workspace,
+};
+
+enum HoverKind {
+    Builtin(String),
+    User(String, HashMap<String, String>),
+}
+
+pub async fn hover(backend: &Backend, params: HoverParams) -> Result<Option<Hover>> {
+    let position = params.text_document_position_params.position;
+    let ctx = RequestContext::new(
+        backend,
+        "hover",
+        &params.text_document_position_params.text_document.uri,
+    );
+
+    // Fields in a layout file show their place in the record
+    if ctx.kind() == Some(DocumentKind::Layout) {
+        let Some((markdown, range)) = ctx
+            .doc
+            .as_ref()
+            .and_then(|doc| crate::layout::format_field_hover(&doc.source, position.line))
+        else {
+            return Ok(None);
+        };
+        let as_markdown = ctx.features().await.hover_markdown;
+        return Ok(Some(Hover {
+            contents: HoverContents::Markup(markup_content(markdown, as_markdown)),
+            range: Some(range),
+        }));
+    }
+
+    // File number references (`#1` in READ/WRITE/CLOSE...) show their OPEN
+    let file_hover = ctx.with_tree(|tree, source| {
+        if !files::is_file_number_at(tree, position) {
+            return None;
+        }
+        let usage = files::collect_file_usage(tree, source);
+        let reference = usage.reference_at(position)?;
+        Some((usage.open_for(reference).cloned(), reference.range))
+    });
+    if let Some((open, range)) = file_hover {
+        let Some(open) = open else {
+            return Ok(None);
+        };
+        let layouts = ctx.layout_index().await;
+        let layout = open
+            .name
+            .as_deref()
+            .zip(layouts.as_deref())
+            .and_then(|(name, layouts)| layouts.find_for_open(name));
+        let markdown = ctx.features().await.hover_markdown;
+        return Ok(Some(Hover {
+            contents: HoverContents::Markup(markup_content(
+                files::format_open_hover(&open, layout),
+                markdown,
+            )),
+            range: Some(range),
+        }));
+    }
+
+    // Numeric literals whose meaning depends on where they appear
+    let constant = ctx.with_tree(|tree, source| {
+        constants::describe_number_at(
+            tree,
+            source,
+            position.line as usize,
+            position.character as usize,
+        )
+    });
+    if let Some((markdown, range)) = constant {
+        let as_markdown = ctx.features().await.hover_markdown;
+        return Ok(Some(Hover {
+            contents: HoverContents::Markup(markup_content(markdown, as_markdown)),
+            range: Some(range),
+        }));
+    }
+
+    // PIC specs in FORM statements show sample numbers in the mask
+    let pic_hover = ctx.with_tree(|tree, source| {
+        let mut node =
+            parser::node_at_position(tree, position.line as usize, position.character as usize)?;
+        while node.kind() != "pic_spec" {
+            node = node.parent()?;
+        }
+        let (_, mask) = pic::mask_of(node.utf8_text(source.as_bytes()).ok()?)?;
+        Some((pic::format_hover(mask), parser::node_range(node)))
+    });
+    if let Some((markdown, range)) = pic_hover {
+        let as_markdown = ctx.features().await.hover_markdown;
+        return Ok(Some(Hover {
+            contents: HoverContents::Markup(markup_content(markdown, as_markdown)),
+            range: Some(range),
+        }));
+    }
+
+    // Statement keywords show the statement's signature and documentation
+    let statement_hover = ctx.with_tree(|tree, source| {
+        // Keywords are anonymous nodes, which `node_at_position` skips
+        let point = tree_sitter::Point::new(position.line as usize, position.character as usize);
+        let node = tree.root_node().descendant_for_point_range(point, point)?;
+        if node.kind() != "statement" || node.is_named() {
+            return None;
+        }
+        let statement = node
+            .parent()
+            .and_then(|p| statements::for_node_kind(p.kind()))
+            .or_else(|| statements::for_keyword(node.utf8_text(source.as_bytes()).ok()?))?;
+        Some((statement.hover_markdown(), parser::node_range(node)))
+    });
+    if let Some((markdown, range)) = statement_hover {
+        let as_markdown = ctx.features().await.hover_markdown;
+        return Ok(Some(Hover {
+            contents: HoverContents::Markup(markup_content(markdown, as_markdown)),
+            range: Some(range),
+        }));
+    }
+
+    // Layout subscripts (`RCU_NAME$`) show the field's place in the record;
+    // other variables where they're declared
+    let identifier = ctx.with_tree(|tree, source| {
+        let node =
+            parser::node_at_position(tree, position.line as usize, position.character as usize)?;
+        if !matches!(node.kind(), "stringidentifier" | "numberidentifier") {
+            return None;
+        }
+        let name = node.utf8_text(source.as_bytes()).ok()?.to_string();
+        let declaration = definition::find_variable_declaration(node, tree, source);
+        Some((name, parser::node_range(node), declaration))
+    });
+    if let Some((name, range, declaration)) = identifier {
+        let layouts = ctx.layout_index().await;
+        let markdown = match layouts.as_deref().and_then(|l| l.find_subscript(&name)) {
+            Some((layout, sub)) => crate::layout::format_subscript_hover(layout, sub),
+            None => match declaration {
+                Some(declaration) => format_declaration_hover(
+                    &declaration,
+                    &params.text_document_position_params.text_document.uri,
+                ),
+                None => return Ok(None),
+            },
+        };
+        drop(layouts);
+        let as_markdown = ctx.features().await.hover_markdown;
+        return Ok(Some(Hover {
+            contents: HoverContents::Markup(markup_content(markdown, as_markdown)),
+            range: Some(range),
+        }));
+    }
+
+    let Some((hover_kind, fn_name_range)) = ctx.with_tree(|tree, source| {
+        // Walk up from the cursor to a function_name node
+        let mut node =
+            parser::node_at_position(tree, position.line as usize, position.character as usize)?;
+        while node.kind() != "function_name" {
+            node = node.parent()?;
+        }
+
+        let fn_name = node.utf8_text(source.as_bytes()).ok()?.to_string();
+        let kind = match node.parent()?.kind() {
+            "numeric_system_function" | "string_system_function" => HoverKind::Builtin(fn_name),
+            _ => HoverKind::User(fn_name, extract::extract_library_links(tree, source)),
+        };
+        Some((kind, parser::node_range(node)))
+    }) else {
+        return Ok(None);
+    };
+
+    let markdown = match hover_kind {
+        HoverKind::Builtin(ref fn_name) => {
+            let builtins = builtins::lookup(fn_name);
+            if builtins.is_empty() {
+                return Ok(None);
+            }
+            format_builtin_hover(builtins)
+        }
+        HoverKind::User(ref fn_name, mut library_links) => {
+            let mappings = ctx.config().await.diagnostics.drive_mappings;
+            let folders = ctx.workspace_folders().await;
+            mappings.map_library_links(&mut library_links, &folders);
+            let local;
+            let index = ctx.workspace_index().await;
+            let defs = match index.as_deref() {
+                Some(index) => index.lookup_prioritized_with_links(
+                    fn_name,
+                    params
+                        .text_document_position_params
+                        .text_document
+                        .uri
+                        .as_str(),
+                    &library_links,
+                    &folders,
+                ),
+                None => {
+                    local = ctx.local_definitions(fn_name);
+                    local.iter().collect()
+                }
+            };
+            if defs.is_empty() {
+                return Ok(None);
+            }
+            format_user_hover_multi(&defs)
+        }
+    };
+
+    let as_markdown = ctx.features().await.hover_markdown;
+    Ok(Some(Hover {
+        contents: HoverContents::Markup(markup_content(markdown, as_markdown)),
+        range: Some(fn_name_range),
+    }))
+}
+
+/// `Declared at line N`, linked to the declaration, with the DIM entry.
+fn format_declaration_hover(declaration: &VariableDeclaration, uri: &Url) -> String {
+    let line = declaration.range.start.line + 1;
+    let link = format!("[line {line}]({uri}#L{line})");
+    match declaration.kind {
+        DeclarationKind::Dim => format!(
+            "```br\ndim {}\n```\n\nDeclared at {link}",
+            declaration.dim.as_deref().unwrap_or_default()
+        ),
+        DeclarationKind::Parameter => format!("Parameter declared at {link}"),
+        DeclarationKind::Assignment => format!("First assigned at {link}"),
+    }
+}
+
+fn format_builtin_hover(builtins: &[builtins::BuiltinFunction]) -> String {
+    let mut parts = Vec::new();
+    for b in builtins {
+        let sig = b.format_signature();
+        let mut md = format!("```br\n{sig}\n```");
+        if let Some(doc) = &b.documentation {
+            md.push_str("\n\n---\n\n");
+            md.push_str(doc);
+        }
+        if !b.params.is_empty() {
+            let param_docs: Vec<String> = b
+                .params
+                .iter()
+                .filter(|p| p.documentation.is_some())
+                .map(|p| {
+                    format!(
+                        "*@param* `{}` \u{2014} {}",
+                        p.name,
+                        p.documentation.as_deref().unwrap()
+                    )
+                })
+                .collect();
+            if !param_docs.is_empty() {
+                md.push_str("\n\n");
+                md.push_str(&param_docs.join("\n\n"));
+            }
+        }
+        parts.push(md);
+    }
+    parts.join("\n\n---\n\n")
+}
+
+fn format_user_hover_multi(defs: &[&workspace::IndexedFunctionDef]) -> String {
+    // Show only the first (highest priority) non-import-only definition
+    let best = defs.iter().find(|d| !d.def.is_import_only).or(defs.first());
+    match best {
+        Some(d) => format_user_hover(&d.def),
+        None => String::new(),
+    }
+}
+
+pub(crate) fn format_user_hover(def: &extract::FunctionDef) -> String {
+    let sig = def.format_signature();
+    let mut md = format!("```br\n{sig}\n```");
+
+    if let Some(reason) = &def.deprecated {
+        md.push_str("\n\n");
+        md.push_str(&completions::format_deprecation(reason));
+    }
+
+    if let Some(doc) = &def.documentation {
+        md.push_str("\n\n---\n\n");
+        md.push_str(doc);
+    }
+
+    let param_docs: Vec<String> = def
+        .params
+        .iter()
+        .filter(|p| p.documentation.is_some())
+        .map(|p| {
+            format!(
+                "*@param* `{}` \u{2014} {}",
+                p.format_label(),
+                p.documentation.as_deref().unwrap()
+            )
+        })
+        .collect();
+    if !param_docs.is_empty() {
+        md.push_str("\n\n");
+        md.push_str(&param_docs.join("\n\n"));
+    }
+
+    if let Some(ret) = &def.return_documentation {
+        md.push_str("\n\n");
+        md.push_str(&format!("*@returns* \u{2014} {ret}"));
+    }
+
+    md
+}
@@ -0,0 +1,58 @@
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{InlayHint, InlayHintParams};
+
+use super::RequestContext;
+use crate::backend::Backend;
+use crate::{extract, inlay_hints};
+
+pub async fn inlay_hint(
+    backend: &Backend,
+    params: InlayHintParams,
+) -> Result<Option<Vec<InlayHint>>> {
+    let Some(ctx) =
+        RequestContext::for_br_document(backend, "inlay_hint", &params.text_document.uri)
+    else {
+        return Ok(None);
+    };
+    let Some(doc) = ctx.doc.as_ref() else {
+        return Ok(None);
+    };
+    let Some(tree) = doc.tree.as_ref() else {
+        return Ok(None);
+    };
+    let source = &doc.source;
+
+    let mut library_links = extract::extract_library_links(tree, source);
+    let mappings = ctx.config().await.diagnostics.drive_mappings;
+    let folders = ctx.workspace_folders().await;
+    mappings.map_library_links(&mut library_links, &folders);
+    let index = ctx.workspace_index().await;
+    if ctx.is_cancelled() {
+        return Ok(None);
+    }
+    // Without the index, only the document's own functions are known
+    let local = if index.is_some() {
+        Vec::new()
+    } else {
+        extract::extract_definitions(tree, source)
+    };
+    let hints =
+        inlay_hints::reference_argument_hints(tree, source, params.range, |name| {
+            match index.as_deref() {
+                Some(index) => index
+                    .lookup_prioritized_with_links(
+                        name,
+                        params.text_document.uri.as_str(),
+                        &library_links,
+                        &folders,
+                    )
+                    .first()
+                    .map(|d| &d.def),
+                None => local.iter().find(|d| d.name.eq_ignore_ascii_case(name)),
+            }
+        });
+    drop(index);
+
+    ctx.log(format!("{} hints", hints.len())).await;
+    Ok(Some(hints))
+}
@@ -0,0 +1,24 @@
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{InlineValue, InlineValueParams};
+
+use super::RequestContext;
+use crate::backend::Backend;
+use crate::inline_values;
+
+pub async fn inline_value(
+    backend: &Backend,
+    params: InlineValueParams,
+) -> Result<Option<Vec<InlineValue>>> {
+    let Some(ctx) =
+        RequestContext::for_br_document(backend, "inline_value", &params.text_document.uri)
+    else {
+        return Ok(None);
+    };
+
+    let values = ctx
+        .with_tree(|tree, source| Some(inline_values::inline_values(tree, source, params.range)));
+
+    ctx.log(format!("{} values", values.as_ref().map_or(0, Vec::len)))
+        .await;
+    Ok(values)
+}
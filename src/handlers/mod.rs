@@ -0,0 +1,541 @@
+use std::fmt::Display;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use tokio::sync::{RwLock, RwLockReadGuard};
+use tower_lsp::lsp_types::{MessageType, Url};
+use tree_sitter::Tree;
+
+use crate::backend::{Backend, ClientFeatures, Config, DocumentKind, DocumentSnapshot};
+use crate::extract;
+use crate::layout::LayoutIndex;
+use crate::workspace::{IndexedFunctionDef, WorkspaceIndex};
+
+pub mod code_action;
+pub mod code_lens;
+pub mod commands;
+pub mod completion;
+pub mod definition;
+pub mod document_link;
+pub mod folding_range;
+pub mod formatting;
+pub mod highlight;
+pub mod hover;
+pub mod inlay_hint;
+pub mod inline_value;
+pub mod peek;
+pub mod references;
+pub mod rename;
+pub mod semantic_tokens;
+pub mod signature_help;
+pub mod symbols;
+pub mod workspace_symbol;
+
+/// How long an interactive request waits for an index that is being
+/// written, such as while a workspace rescan stores its results, before
+/// answering from the document alone.
+pub const INDEX_WAIT: Duration = Duration::from_millis(50);
+
+/// Read `lock`, or `None` if a writer holds it longer than [`INDEX_WAIT`].
+pub async fn read_or_skip<T>(lock: &RwLock<T>) -> Option<RwLockReadGuard<'_, T>> {
+    tokio::time::timeout(INDEX_WAIT, lock.read()).await.ok()
+}
+
+/// State shared by a single request handler: the backend, a snapshot of the
+/// document the request targets, timing for its log line, and a check for
+/// whether a newer edit has superseded it. Handlers build one with [`RequestContext::new`]
+/// and report through [`RequestContext::log`] so every feature logs the same
+/// way.
+pub struct RequestContext<'a> {
+    pub backend: &'a Backend,
+    /// The document the request targets; workspace-wide commands have none
+    pub uri: Option<Url>,
+    /// Taken when the request starts; handlers analyse this rather than
+    /// reading `document_map` again.
+    pub doc: Option<DocumentSnapshot>,
+    method: &'static str,
+    start: Instant,
+    generation: u64,
+}
+
+impl<'a> RequestContext<'a> {
+    pub fn new(backend: &'a Backend, method: &'static str, uri: &Url) -> Self {
+        Self::for_command(backend, method).with_document(uri)
+    }
+
+    /// Context for a command or other request that targets no document
+    /// until [`RequestContext::with_document`] names one.
+    pub fn for_command(backend: &'a Backend, method: &'static str) -> Self {
+        Self {
+            backend,
+            uri: None,
+            doc: None,
+            method,
+            start: Instant::now(),
+            generation: 0,
+        }
+    }
+
+    /// This context retargeted at `uri`, keeping its start time.
+    pub fn with_document(self, uri: &Url) -> Self {
+        Self {
+            generation: current_generation(self.backend, uri.as_str()),
+            doc: self.backend.snapshot(uri.as_str()),
+            uri: Some(uri.clone()),
+            ..self
+        }
+    }
+
+    /// Context for a request that only applies to BR source; `None` for
+    /// layout documents.
+    pub fn for_br_document(backend: &'a Backend, method: &'static str, uri: &Url) -> Option<Self> {
+        let ctx = Self::new(backend, method, uri);
+        (ctx.kind() != Some(DocumentKind::Layout)).then_some(ctx)
+    }
+
+    /// Kind of the target document, if it is open.
+    pub fn kind(&self) -> Option<DocumentKind> {
+        self.doc.as_ref().map(|doc| doc.kind)
+    }
+
+    /// Run `f` against the snapshot's tree and source.
+    pub fn with_tree<R>(&self, f: impl FnOnce(&Tree, &str) -> Option<R>) -> Option<R> {
+        let doc = self.doc.as_ref()?;
+        let tree = doc.tree.as_ref()?;
+        f(tree, &doc.source)
+    }
+
+    pub async fn config(&self) -> Config {
+        self.backend.config.read().await.clone()
+    }
+
+    pub async fn features(&self) -> ClientFeatures {
+        self.backend.client_features.read().await.clone()
+    }
+
+    /// The workspace index, or `None` while it's busy being written; the
+    /// request then falls back to [`RequestContext::local_definitions`].
+    pub async fn workspace_index(&self) -> Option<RwLockReadGuard<'a, WorkspaceIndex>> {
+        let index = read_or_skip(&self.backend.workspace_index).await;
+        if index.is_none() {
+            self.log("workspace index busy, using this file only").await;
+        }
+        index
+    }
+
+    /// The layout index, or `None` while it's busy being written.
+    pub async fn layout_index(&self) -> Option<RwLockReadGuard<'a, LayoutIndex>> {
+        let layouts = read_or_skip(&self.backend.layout_index).await;
+        if layouts.is_none() {
+            self.log("layout index busy, skipping layouts").await;
+        }
+        layouts
+    }
+
+    /// The workspace folders, or none while they're being changed.
+    pub async fn workspace_folders(&self) -> Vec<Url> {
+        read_or_skip(&self.backend.workspace_folders)
+            .await
+            .map(|folders| folders.clone())
+            .unwrap_or_default()
+    }
+
+    /// Functions named `name` defined in the document itself, for answering
+    /// without the workspace index.
+    pub fn local_definitions(&self, name: &str) -> Vec<IndexedFunctionDef> {
+        self.with_tree(|tree, source| {
+            let uri = self.uri.as_ref()?;
+            let defs = extract::extract_definitions(tree, source)
+                .into_iter()
+                .filter(|def| def.name.eq_ignore_ascii_case(name))
+                .map(|def| IndexedFunctionDef {
+                    uri: uri.clone(),
+                    def,
+                })
+                .collect();
+            Some(defs)
+        })
+        .unwrap_or_default()
+    }
+
+    /// Whether the document was edited after this request started, making
+    /// any result computed from the old tree stale.
+    pub fn is_cancelled(&self) -> bool {
+        let Some(uri) = &self.uri else {
+            return false;
+        };
+        current_generation(self.backend, uri.as_str()) != self.generation
+    }
+
+    /// Log `"{method}: {detail} ({elapsed})"` to the client.
+    pub async fn log(&self, detail: impl Display) {
+        self.log_at(MessageType::LOG, detail).await;
+    }
+
+    /// [`RequestContext::log`] at `level`, for results the user asked for.
+    pub async fn log_at(&self, level: MessageType, detail: impl Display) {
+        self.backend
+            .logger
+            .log(
+                level,
+                format!("{}: {detail} ({:.1?})", self.method, self.start.elapsed()),
+            )
+            .await;
+    }
+}
+
+fn current_generation(backend: &Backend, uri: &str) -> u64 {
+    backend
+        .diagnostics_generation
+        .get(uri)
+        .map(|g| g.load(Ordering::SeqCst))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::DocumentState;
+    use crate::{parser, workspace};
+    use std::sync::atomic::AtomicU64;
+    use std::sync::Arc;
+    use tower_lsp::lsp_types::{
+        CodeLensParams, DocumentOnTypeFormattingParams, FormattingOptions, HoverContents,
+        HoverParams, Position, ReferenceContext, ReferenceParams, SemanticTokensParams,
+        SignatureHelpParams, TextDocumentIdentifier, TextDocumentPositionParams,
+        WorkspaceSymbolParams,
+    };
+    use tower_lsp::LspService;
+
+    fn open(backend: &Backend, uri: &Url, kind: DocumentKind, source: &str) {
+        let tree = match kind {
+            DocumentKind::Br => parser::parse(&mut parser::new_parser(), source, None),
+            DocumentKind::Layout => None,
+        };
+        backend.document_map.insert(
+            uri.to_string(),
+            DocumentState {
+                kind,
+                rope: ropey::Rope::from_str(source),
+                source: source.into(),
+                tree,
+                version: 0,
+                client_version: 0,
+                variable_usage: Default::default(),
+            },
+        );
+    }
+
+    #[test]
+    fn layout_documents_have_no_br_context() {
+        let (service, _) = LspService::new(Backend::new);
+        let backend = service.inner();
+        let br = Url::parse("file:///ws/main.brs").unwrap();
+        let lay = Url::parse("file:///ws/filelay/customer").unwrap();
+        open(backend, &br, DocumentKind::Br, "print 1\n");
+        open(backend, &lay, DocumentKind::Layout, "customer.dat,CU_,1\n");
+
+        assert!(RequestContext::for_br_document(backend, "test", &br).is_some());
+        assert!(RequestContext::for_br_document(backend, "test", &lay).is_none());
+    }
+
+    #[test]
+    fn newer_edit_cancels_request() {
+        let (service, _) = LspService::new(Backend::new);
+        let backend = service.inner();
+        let uri = Url::parse("file:///ws/main.brs").unwrap();
+        open(backend, &uri, DocumentKind::Br, "print 1\n");
+        let generation = Arc::new(AtomicU64::new(1));
+        backend
+            .diagnostics_generation
+            .insert(uri.to_string(), generation.clone());
+
+        let ctx = RequestContext::new(backend, "test", &uri);
+        assert!(!ctx.is_cancelled());
+        generation.fetch_add(1, Ordering::SeqCst);
+        assert!(ctx.is_cancelled());
+    }
+
+    #[test]
+    fn snapshot_is_unaffected_by_later_edits() {
+        let (service, _) = LspService::new(Backend::new);
+        let backend = service.inner();
+        let uri = Url::parse("file:///ws/main.brs").unwrap();
+        open(backend, &uri, DocumentKind::Br, "print 1\n");
+
+        let ctx = RequestContext::new(backend, "test", &uri);
+        // Neither replacing nor removing the entry needs the request to let go
+        open(backend, &uri, DocumentKind::Br, "print 2\n");
+        backend.document_map.remove(&uri.to_string());
+
+        let source = ctx.with_tree(|_, source| Some(source.to_string()));
+        assert_eq!(source.as_deref(), Some("print 1\n"));
+    }
+
+    #[tokio::test]
+    async fn on_type_formatting_handler_uses_open_document() {
+        let (service, _) = LspService::new(Backend::new);
+        let backend = service.inner();
+        let uri = Url::parse("file:///ws/main.brs").unwrap();
+        open(backend, &uri, DocumentKind::Br, "do\n\n");
+
+        let params = DocumentOnTypeFormattingParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position::new(1, 0),
+            },
+            ch: "\n".to_string(),
+            options: FormattingOptions::default(),
+        };
+        let edits = formatting::on_type_formatting(backend, params)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(edits[0].new_text, "\nloop");
+    }
+
+    #[tokio::test]
+    async fn semantic_tokens_are_cached_per_version() {
+        let (service, _) = LspService::new(Backend::new);
+        let backend = service.inner();
+        let uri = Url::parse("file:///ws/main.brs").unwrap();
+        open(backend, &uri, DocumentKind::Br, "let X = 1\n");
+
+        let params = SemanticTokensParams {
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+        };
+        semantic_tokens::semantic_tokens_full(backend, params)
+            .await
+            .unwrap();
+
+        let uri_string = uri.to_string();
+        assert!(backend
+            .analysis_cache
+            .semantic_tokens(&uri_string, 0)
+            .is_some());
+        assert!(backend
+            .analysis_cache
+            .semantic_tokens(&uri_string, 1)
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn code_lens_counts_references_from_other_files() {
+        let (service, _) = LspService::new(Backend::new);
+        let backend = service.inner();
+        let lib = Url::parse("file:///ws/lib.brs").unwrap();
+        let main = Url::parse("file:///ws/main.brs").unwrap();
+        open(
+            backend,
+            &lib,
+            DocumentKind::Br,
+            "def library fnTitle$(a$)\n  let fnTitle$ = a$\nfnend\ndef fnLocal\nfnend\n",
+        );
+        open(
+            backend,
+            &main,
+            DocumentKind::Br,
+            "library \"lib\": fnTitle$\nprint fnTitle$(\"x\")\n",
+        );
+
+        let params = CodeLensParams {
+            text_document: TextDocumentIdentifier { uri: lib },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let lenses = code_lens::code_lens(backend, params)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(lenses.len(), 1);
+        assert_eq!(lenses[0].range.start, Position::new(0, 12));
+
+        let resolved = code_lens::code_lens_resolve(backend, lenses[0].clone())
+            .await
+            .unwrap();
+        let command = resolved.command.unwrap();
+        assert_eq!(command.title, "2 references in 1 other file");
+        assert_eq!(command.command, code_lens::SHOW_REFERENCES_COMMAND);
+    }
+
+    #[tokio::test]
+    async fn hover_answers_from_the_document_while_the_index_is_written() {
+        let (service, _) = LspService::new(Backend::new);
+        let backend = service.inner();
+        let uri = Url::parse("file:///ws/main.brs").unwrap();
+        open(
+            backend,
+            &uri,
+            DocumentKind::Br,
+            "def fnDouble(X)\n  let fnDouble = X * 2\nfnend\nprint fnDouble(1)\n",
+        );
+
+        let _rescan = backend.workspace_index.write().await;
+        let params = HoverParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position::new(3, 8),
+            },
+            work_done_progress_params: Default::default(),
+        };
+        let hover = hover::hover(backend, params).await.unwrap().unwrap();
+        let HoverContents::Markup(content) = hover.contents else {
+            panic!("expected markup");
+        };
+        assert!(content.value.contains("fnDouble(X)"), "{}", content.value);
+    }
+
+    #[tokio::test]
+    async fn peek_reads_closed_library_source_from_disk() {
+        let (service, _) = LspService::new(Backend::new);
+        let backend = service.inner();
+        let dir = tempfile::tempdir().unwrap();
+        let lib_path = dir.path().join("lib.brs");
+        // 0xBA is a CP437 box-drawing line
+        std::fs::write(
+            &lib_path,
+            b"print 1\ndef library fnTitle$(a$)\n  let fnTitle$ = \"\xBA\" & a$\nfnend\nprint 2\n",
+        )
+        .unwrap();
+        let lib = Url::from_file_path(&lib_path).unwrap();
+        let source = workspace::read_br_file(&lib_path).unwrap();
+        let tree = parser::parse(&mut parser::new_parser(), &source, None).unwrap();
+        backend
+            .workspace_index
+            .write()
+            .await
+            .add_file(&lib, crate::extract::extract_definitions(&tree, &source));
+
+        let main = Url::from_file_path(dir.path().join("main.brs")).unwrap();
+        open(
+            backend,
+            &main,
+            DocumentKind::Br,
+            "library \"lib\": fnTitle$\nprint fnTitle$(\"x\")\n",
+        );
+        let params = peek::PeekDefinitionParams {
+            position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri: main },
+                position: Position::new(1, 8),
+            },
+            context_lines: 0,
+        };
+        let peek = peek::peek_definition(backend, params).await.unwrap();
+        assert_eq!(peek["uri"], lib.as_str());
+        assert_eq!(peek["startLine"], 1);
+        assert_eq!(
+            peek["text"],
+            "def library fnTitle$(a$)\n  let fnTitle$ = \"\u{2551}\" & a$\nfnend"
+        );
+    }
+
+    #[tokio::test]
+    async fn references_to_a_variable_stay_in_the_document() {
+        let (service, _) = LspService::new(Backend::new);
+        let backend = service.inner();
+        let uri = Url::parse("file:///ws/main.brs").unwrap();
+        open(backend, &uri, DocumentKind::Br, "let X = 1\nprint X\n");
+
+        let params = ReferenceParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                position: Position::new(1, 6),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: ReferenceContext {
+                include_declaration: true,
+            },
+        };
+        let locations = references::references(backend, params)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(locations.len(), 2);
+        assert!(locations.iter().all(|l| l.uri == uri));
+    }
+
+    #[tokio::test]
+    async fn signature_help_marks_the_argument_under_the_cursor() {
+        let (service, _) = LspService::new(Backend::new);
+        let backend = service.inner();
+        let uri = Url::parse("file:///ws/main.brs").unwrap();
+        open(
+            backend,
+            &uri,
+            DocumentKind::Br,
+            "let A$ = Cnvrt$(\"N 5\", 1)\n",
+        );
+
+        let params = SignatureHelpParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position::new(0, 23),
+            },
+            work_done_progress_params: Default::default(),
+            context: None,
+        };
+        let help = signature_help::signature_help(backend, params)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(help.active_parameter, Some(1));
+        assert!(help.signatures[0].label.contains("Cnvrt$"));
+    }
+
+    #[tokio::test]
+    async fn signature_help_answers_from_the_document_while_the_index_is_written() {
+        let (service, _) = LspService::new(Backend::new);
+        let backend = service.inner();
+        let uri = Url::parse("file:///ws/main.brs").unwrap();
+        open(
+            backend,
+            &uri,
+            DocumentKind::Br,
+            "def fnDouble(X)\n  let fnDouble = X * 2\nfnend\nprint fnDouble(1)\n",
+        );
+
+        let _rescan = backend.workspace_index.write().await;
+        let params = SignatureHelpParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position::new(3, 15),
+            },
+            work_done_progress_params: Default::default(),
+            context: None,
+        };
+        let help = signature_help::signature_help(backend, params)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(help.signatures[0].label.contains("fnDouble(X)"));
+    }
+
+    #[tokio::test]
+    async fn workspace_symbols_match_the_query() {
+        let (service, _) = LspService::new(Backend::new);
+        let backend = service.inner();
+        let lib = Url::parse("file:///ws/lib.brs").unwrap();
+        let source = "def library fnTitle$(a$)\nfnend\ndef fnOther\nfnend\n";
+        let tree = parser::parse(&mut parser::new_parser(), source, None).unwrap();
+        backend
+            .workspace_index
+            .write()
+            .await
+            .add_file(&lib, crate::extract::extract_definitions(&tree, source));
+
+        let params = WorkspaceSymbolParams {
+            query: "TITLE".to_string(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let symbols = workspace_symbol::workspace_symbol(backend, params)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols[0].name, "fnTitle$");
+    }
+}
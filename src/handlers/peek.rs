@@ -0,0 +1,71 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::TextDocumentPositionParams;
+
+use super::definition::resolve_definition;
+use super::RequestContext;
+use crate::backend::Backend;
+use crate::{definition, parser, workspace};
+
+/// Parameters of the custom `br-lsp/peekDefinition` request.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeekDefinitionParams {
+    #[serde(flatten)]
+    pub position: TextDocumentPositionParams,
+    /// Lines to include above and below the definition
+    #[serde(default = "default_context_lines")]
+    pub context_lines: u32,
+}
+
+fn default_context_lines() -> u32 {
+    3
+}
+
+/// The text of the definition at a position, for clients that show a peek
+/// without opening the file. Open documents are read from the editor,
+/// others from disk (decoded from CP437). `null` when nothing resolves.
+pub async fn peek_definition(backend: &Backend, params: PeekDefinitionParams) -> Result<Value> {
+    let Some(ctx) = RequestContext::for_br_document(
+        backend,
+        "peek_definition",
+        &params.position.text_document.uri,
+    ) else {
+        return Ok(Value::Null);
+    };
+    let Some(target) = resolve_definition(&ctx, params.position.position).await else {
+        return Ok(Value::Null);
+    };
+
+    let source = match backend.snapshot(target.uri.as_str()) {
+        Some(doc) => doc.source.to_string(),
+        None => {
+            let Some(text) = target
+                .uri
+                .to_file_path()
+                .ok()
+                .and_then(|path| workspace::read_br_file(&path).ok())
+            else {
+                ctx.log(format!("{} unreadable", target.uri)).await;
+                return Ok(Value::Null);
+            };
+            text
+        }
+    };
+    let mut parser = parser::new_parser();
+    let range = parser::parse(&mut parser, &source, None)
+        .map(|tree| definition::function_extent(&tree, &source, target.range))
+        .unwrap_or(target.range);
+    let (start_line, text) = definition::peek_lines(&source, range, params.context_lines);
+
+    ctx.log(format!("{}, line {}", target.uri, target.range.start.line))
+        .await;
+    Ok(json!({
+        "uri": target.uri,
+        "range": range,
+        "selectionRange": target.selection_range,
+        "startLine": start_line,
+        "text": text,
+    }))
+}
@@ -0,0 +1,108 @@
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{Location, Position, ReferenceParams, Url};
+
+use super::RequestContext;
+use crate::backend::Backend;
+use crate::workspace::IndexedFunctionDef;
+use crate::{builtins, document_links, references};
+
+pub async fn references(
+    backend: &Backend,
+    params: ReferenceParams,
+) -> Result<Option<Vec<Location>>> {
+    let position = params.text_document_position.position;
+    let uri = params.text_document_position.text_document.uri;
+    let Some(ctx) = RequestContext::for_br_document(backend, "references", &uri) else {
+        return Ok(None);
+    };
+
+    // A program name in a CHAIN, LIBRARY or EXECUTE string, when string
+    // references are asked for: every string naming the same program
+    let include_strings = ctx.config().await.diagnostics.include_string_references;
+    let program = ctx
+        .with_tree(|tree, source| {
+            let refs = document_links::collect_program_references(tree, source);
+            document_links::reference_at(&refs, position).map(|r| r.name.clone())
+        })
+        .filter(|_| include_strings);
+    if let Some(name) = program {
+        let locations = match backend.resolve_program_name(&uri, &name).await {
+            Some(target) => backend.find_program_references(target).await,
+            None => Vec::new(),
+        };
+        ctx.log(format!("program \"{name}\", {} locations", locations.len()))
+            .await;
+        return Ok((!locations.is_empty()).then_some(locations));
+    }
+
+    // Library functions are searched for across the workspace
+    if let Some(name) = function_name_at(&ctx, position) {
+        if is_library_function(&ctx, &name).await {
+            let locations = backend.search_workspace_for_function_refs(&name).await;
+            ctx.log(format!(
+                "cross-file \"{name}\", {} locations",
+                locations.len()
+            ))
+            .await;
+            return Ok((!locations.is_empty()).then_some(locations));
+        }
+    }
+
+    // Everything else is found in the document itself
+    let locations: Vec<Location> = ctx
+        .with_tree(|tree, source| {
+            let refs = references::find_references(
+                tree,
+                source,
+                position.line as usize,
+                position.character as usize,
+            );
+            Some(
+                refs.into_iter()
+                    .map(|range| Location {
+                        uri: uri.clone(),
+                        range,
+                    })
+                    .collect(),
+            )
+        })
+        .unwrap_or_default();
+    ctx.log(format!("local, {} locations", locations.len()))
+        .await;
+    Ok((!locations.is_empty()).then_some(locations))
+}
+
+/// The user function named at `position`; builtins are left to the
+/// single-file search.
+pub(super) fn function_name_at(ctx: &RequestContext<'_>, position: Position) -> Option<String> {
+    ctx.with_tree(|tree, source| {
+        references::resolve_function_name_at(
+            tree,
+            source,
+            position.line as usize,
+            position.character as usize,
+        )
+    })
+    .filter(|name| builtins::lookup(name).is_empty())
+}
+
+/// Whether `name` is a library function, whose uses span the workspace. A
+/// non-library function of that name defined in the document itself wins,
+/// even when other files export one. While the index is busy, the
+/// document's own DEF LIBRARY or LIBRARY import decides.
+pub(super) async fn is_library_function(ctx: &RequestContext<'_>, name: &str) -> bool {
+    let Some(uri) = ctx.uri.as_ref() else {
+        return false;
+    };
+    match ctx.workspace_index().await {
+        Some(index) => defines_library_function(index.lookup(name), uri),
+        None => defines_library_function(&ctx.local_definitions(name), uri),
+    }
+}
+
+fn defines_library_function(defs: &[IndexedFunctionDef], uri: &Url) -> bool {
+    let local_non_library = defs
+        .iter()
+        .any(|d| d.uri == *uri && !d.def.is_import_only && !d.def.is_library);
+    !local_non_library && defs.iter().any(|d| d.def.is_library)
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{
+    MessageType, PrepareRenameResponse, RenameParams, TextDocumentPositionParams, TextEdit, Url,
+    WorkspaceEdit,
+};
+
+use super::references::{function_name_at, is_library_function};
+use super::RequestContext;
+use crate::backend::{file_display_name, Backend};
+use crate::rename;
+
+pub async fn prepare_rename(
+    backend: &Backend,
+    params: TextDocumentPositionParams,
+) -> Result<Option<PrepareRenameResponse>> {
+    let Some(ctx) =
+        RequestContext::for_br_document(backend, "prepare_rename", &params.text_document.uri)
+    else {
+        return Ok(None);
+    };
+
+    let Some(r) = ctx.with_tree(|tree, source| {
+        rename::prepare_rename(
+            tree,
+            source,
+            params.position.line as usize,
+            params.position.character as usize,
+        )
+    }) else {
+        return Ok(None);
+    };
+    // The response has no room for the pattern; rename reports it when
+    // a new name doesn't match
+    ctx.log(format!("\"{}\", {}", r.placeholder, r.kind.pattern()))
+        .await;
+    Ok(Some(PrepareRenameResponse::RangeWithPlaceholder {
+        range: r.range,
+        placeholder: r.placeholder,
+    }))
+}
+
+pub async fn rename(backend: &Backend, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+    let position = params.text_document_position.position;
+    let uri = params.text_document_position.text_document.uri;
+    let new_name = params.new_name;
+    let Some(ctx) = RequestContext::for_br_document(backend, "rename", &uri) else {
+        return Ok(None);
+    };
+
+    let validation = ctx.with_tree(|tree, source| {
+        rename::validate_new_name(
+            tree,
+            source,
+            position.line as usize,
+            position.character as usize,
+            &new_name,
+        )
+        .err()
+    });
+    if let Some(message) = validation {
+        return Err(tower_lsp::jsonrpc::Error::invalid_params(message));
+    }
+
+    // Library functions are renamed across the workspace
+    if let Some(name) = function_name_at(&ctx, position) {
+        if is_library_function(&ctx, &name).await {
+            return rename_across_workspace(&ctx, &name, &new_name).await;
+        }
+    }
+
+    // Everything else is renamed in the document itself
+    let edits = ctx
+        .with_tree(|tree, source| {
+            Some(rename::compute_renames(
+                tree,
+                source,
+                position.line as usize,
+                position.character as usize,
+                &new_name,
+            ))
+        })
+        .unwrap_or_default();
+    ctx.log(format!("local, {} edits", edits.len())).await;
+    if edits.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(WorkspaceEdit {
+        changes: Some(HashMap::from([(uri, edits)])),
+        ..Default::default()
+    }))
+}
+
+async fn rename_across_workspace(
+    ctx: &RequestContext<'_>,
+    name: &str,
+    new_name: &str,
+) -> Result<Option<WorkspaceEdit>> {
+    let backend = ctx.backend;
+    let locations = backend.search_workspace_for_function_refs(name).await;
+    if locations.is_empty() {
+        ctx.log(format!("cross-file \"{name}\" -> \"{new_name}\", 0 edits"))
+            .await;
+        return Ok(None);
+    }
+    let edit_count = locations.len();
+    let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+    for loc in locations {
+        changes.entry(loc.uri).or_default().push(TextEdit {
+            range: loc.range,
+            new_text: new_name.to_string(),
+        });
+    }
+    let file_count = changes.len();
+
+    let is_open = |u: &Url| backend.document_map.contains_key(u.as_str());
+    if ctx.config().await.diagnostics.read_only_workspace {
+        let closed = closed_files(&changes, is_open);
+        if !closed.is_empty() {
+            backend
+                .client
+                .show_message(
+                    MessageType::INFO,
+                    format!(
+                        "Read-only workspace: renaming {name} would edit {} closed file(s), so no changes were made",
+                        closed.len()
+                    ),
+                )
+                .await;
+            return Ok(None);
+        }
+    }
+
+    ctx.log(format!(
+        "cross-file \"{name}\" -> \"{new_name}\", {edit_count} edits across {file_count} files"
+    ))
+    .await;
+    let conflicts = match ctx.workspace_index().await {
+        Some(index) => rename::rename_conflicts(&changes, &index, name, new_name, is_open, |u| {
+            let path = u.to_file_path().ok()?;
+            std::fs::metadata(path).ok()?.modified().ok()
+        }),
+        // Nothing can be checked without the index, so every file asks
+        None => changes
+            .keys()
+            .map(|u| {
+                let reason = format!(
+                    "{} wasn't checked for conflicts while the workspace index was busy",
+                    file_display_name(u)
+                );
+                (u.clone(), reason)
+            })
+            .collect(),
+    };
+    if !conflicts.is_empty() && !ctx.features().await.change_annotations {
+        // No way to ask for confirmation per edit; warn instead
+        let mut reasons: Vec<&str> = conflicts.values().map(String::as_str).collect();
+        reasons.sort_unstable();
+        backend
+            .client
+            .show_message(
+                MessageType::WARNING,
+                format!("Renaming {name}: {}", reasons.join("; ")),
+            )
+            .await;
+        return Ok(Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }));
+    }
+    Ok(Some(rename::annotated_rename_edit(changes, &conflicts)))
+}
+
+/// Files a workspace edit touches that aren't open in the editor, sorted.
+fn closed_files(changes: &HashMap<Url, Vec<TextEdit>>, is_open: impl Fn(&Url) -> bool) -> Vec<Url> {
+    let mut closed: Vec<Url> = changes.keys().filter(|u| !is_open(u)).cloned().collect();
+    closed.sort();
+    closed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closed_files_lists_unopened_targets() {
+        let open = Url::parse("file:///ws/open.brs").unwrap();
+        let closed = Url::parse("file:///ws/closed.brs").unwrap();
+        let mut changes = HashMap::new();
+        changes.insert(open.clone(), vec![]);
+        changes.insert(closed.clone(), vec![]);
+
+        assert_eq!(closed_files(&changes, |u| *u == open), vec![closed]);
+        assert!(closed_files(&changes, |_| true).is_empty());
+    }
+}
@@ -0,0 +1,49 @@
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{SemanticTokens, SemanticTokensParams, SemanticTokensResult};
+
+use super::RequestContext;
+use crate::backend::{Backend, DocumentKind};
+use crate::semantic_tokens;
+
+/// Semantic tokens for BR source and for layout files alike, cached per
+/// document version.
+pub async fn semantic_tokens_full(
+    backend: &Backend,
+    params: SemanticTokensParams,
+) -> Result<Option<SemanticTokensResult>> {
+    let ctx = RequestContext::new(backend, "semantic_tokens", &params.text_document.uri);
+    let Some(doc) = ctx.doc.as_ref() else {
+        return Ok(None);
+    };
+
+    let cached = backend
+        .analysis_cache
+        .semantic_tokens(params.text_document.uri.as_str(), doc.version);
+    let is_cached = cached.is_some();
+    let tokens = cached.unwrap_or_else(|| {
+        let tokens = match doc.kind {
+            DocumentKind::Layout => crate::layout::collect_layout_tokens(&doc.source),
+            DocumentKind::Br => match doc.tree.as_ref() {
+                Some(tree) => semantic_tokens::collect_tokens(tree, &doc.source),
+                None => return Vec::new(),
+            },
+        };
+        backend.analysis_cache.store_semantic_tokens(
+            params.text_document.uri.as_str(),
+            doc.version,
+            tokens.clone(),
+        );
+        tokens
+    });
+
+    if tokens.is_empty() {
+        return Ok(None);
+    }
+    let cached_note = if is_cached { ", cached" } else { "" };
+    ctx.log(format!("{} tokens{cached_note}", tokens.len()))
+        .await;
+    Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
+        result_id: None,
+        data: tokens,
+    })))
+}
@@ -0,0 +1,212 @@
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{
+    Documentation, ParameterInformation, ParameterLabel, Position, SignatureHelp,
+    SignatureHelpParams, SignatureInformation,
+};
+use tree_sitter::Tree;
+
+use super::RequestContext;
+use crate::backend::{markup_content, Backend, ClientFeatures};
+use crate::{builtins, extract, parser};
+
+pub async fn signature_help(
+    backend: &Backend,
+    params: SignatureHelpParams,
+) -> Result<Option<SignatureHelp>> {
+    let position = params.text_document_position_params.position;
+    let uri = params.text_document_position_params.text_document.uri;
+    let Some(ctx) = RequestContext::for_br_document(backend, "signature_help", &uri) else {
+        return Ok(None);
+    };
+    let Some(doc) = ctx.doc.as_ref() else {
+        return Ok(None);
+    };
+
+    // Try tree-based approach first, falling back to text-based scanning
+    // when the tree walk fails
+    let Some(call_ctx) = doc
+        .tree
+        .as_ref()
+        .and_then(|tree| call_context(tree, &doc.source, position))
+        .or_else(|| {
+            parser::find_function_call_context(
+                &doc.source,
+                position.line as usize,
+                position.character as usize,
+            )
+        })
+    else {
+        return Ok(None);
+    };
+
+    let mut library_links = doc
+        .tree
+        .as_ref()
+        .map(|tree| extract::extract_library_links(tree, &doc.source))
+        .unwrap_or_default();
+
+    let features = ctx.features().await;
+    let builtins = builtins::lookup(&call_ctx.name);
+    let signatures = if !builtins.is_empty() {
+        build_builtin_signatures(builtins, call_ctx.active_param, &features)
+    } else {
+        let mappings = ctx.config().await.diagnostics.drive_mappings;
+        let folders = ctx.workspace_folders().await;
+        mappings.map_library_links(&mut library_links, &folders);
+        let def = match ctx.workspace_index().await {
+            Some(index) => index
+                .lookup_prioritized_with_links(
+                    &call_ctx.name,
+                    uri.as_str(),
+                    &library_links,
+                    &folders,
+                )
+                .first()
+                .map(|d| d.def.clone()),
+            None => ctx
+                .local_definitions(&call_ctx.name)
+                .into_iter()
+                .next()
+                .map(|d| d.def),
+        };
+        match def {
+            Some(def) => build_user_signatures(&def, call_ctx.active_param, &features),
+            None => return Ok(None),
+        }
+    };
+
+    ctx.log(format!(
+        "\"{}\", parameter {}",
+        call_ctx.name, call_ctx.active_param
+    ))
+    .await;
+    Ok(Some(SignatureHelp {
+        signatures,
+        active_signature: Some(0),
+        active_parameter: Some(call_ctx.active_param),
+    }))
+}
+
+/// The call whose arguments contain `position`, and which of them the
+/// cursor is on.
+fn call_context(tree: &Tree, source: &str, position: Position) -> Option<parser::CallContext> {
+    let cursor_node =
+        parser::node_at_position(tree, position.line as usize, position.character as usize)?;
+
+    // Walk up to find an arguments node
+    let mut node = cursor_node;
+    let args_node = loop {
+        if node.kind() == "arguments" {
+            break node;
+        }
+        node = node.parent()?;
+    };
+
+    let call_node = args_node.parent()?;
+
+    let mut cursor = call_node.walk();
+    let fn_name_node = call_node
+        .children(&mut cursor)
+        .find(|c| c.kind() == "function_name")?;
+
+    let fn_name = fn_name_node.utf8_text(source.as_bytes()).ok()?;
+
+    // Count commas before cursor to determine active parameter
+    let mut count = 0u32;
+    let mut cursor = args_node.walk();
+    for child in args_node.children(&mut cursor) {
+        if !child.is_named()
+            && child.utf8_text(source.as_bytes()).ok() == Some(",")
+            && child.end_position().column as u32 <= position.character
+            && child.end_position().row as u32 <= position.line
+        {
+            count += 1;
+        }
+    }
+
+    Some(parser::CallContext {
+        name: fn_name.to_string(),
+        active_param: count,
+    })
+}
+
+fn build_builtin_signatures(
+    builtins: &[builtins::BuiltinFunction],
+    active_param: u32,
+    features: &ClientFeatures,
+) -> Vec<SignatureInformation> {
+    builtins
+        .iter()
+        .map(|b| {
+            let (label, offsets) = b.format_signature_with_offsets();
+            let docs = b.params.iter().map(|p| p.documentation.as_ref());
+            SignatureInformation {
+                parameters: Some(build_parameters(&label, &offsets, docs, features)),
+                documentation: b.documentation.as_ref().map(|d| {
+                    Documentation::MarkupContent(markup_content(
+                        d.clone(),
+                        features.signature_markdown,
+                    ))
+                }),
+                label,
+                active_parameter: Some(active_param),
+            }
+        })
+        .collect()
+}
+
+fn build_user_signatures(
+    def: &extract::FunctionDef,
+    active_param: u32,
+    features: &ClientFeatures,
+) -> Vec<SignatureInformation> {
+    let (label, offsets) = def.format_signature_with_offsets();
+    let docs = def.params.iter().map(|p| p.documentation.as_ref());
+    vec![SignatureInformation {
+        parameters: Some(build_parameters(&label, &offsets, docs, features)),
+        documentation: def.documentation.as_ref().map(|d| {
+            Documentation::MarkupContent(markup_content(d.clone(), features.signature_markdown))
+        }),
+        label,
+        active_parameter: Some(active_param),
+    }]
+}
+
+/// Parameter labels as offsets into the signature label, or as the label
+/// text itself for clients without `labelOffsetSupport`.
+fn build_parameters<'a>(
+    label: &str,
+    offsets: &[[u32; 2]],
+    docs: impl Iterator<Item = Option<&'a String>>,
+    features: &ClientFeatures,
+) -> Vec<ParameterInformation> {
+    docs.zip(offsets.iter())
+        .map(|(doc, off)| ParameterInformation {
+            label: if features.label_offsets {
+                ParameterLabel::LabelOffsets(*off)
+            } else {
+                ParameterLabel::Simple(label[off[0] as usize..off[1] as usize].to_string())
+            },
+            documentation: doc.map(|d| {
+                Documentation::MarkupContent(markup_content(d.clone(), features.signature_markdown))
+            }),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simple_parameter_labels_without_offset_support() {
+        let features = ClientFeatures {
+            label_offsets: false,
+            ..Default::default()
+        };
+        let sigs = build_builtin_signatures(builtins::lookup("Cnvrt$"), 0, &features);
+        let params = sigs[0].parameters.as_ref().unwrap();
+        assert!(matches!(&params[0].label, ParameterLabel::Simple(l) if l == "<Spec$>"));
+        assert!(matches!(&params[1].label, ParameterLabel::Simple(l) if l == "<Number>"));
+    }
+}
@@ -0,0 +1,47 @@
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{DocumentSymbolParams, DocumentSymbolResponse};
+
+use super::RequestContext;
+use crate::backend::Backend;
+use crate::symbols;
+
+/// Document outline, cached per document version.
+pub async fn document_symbol(
+    backend: &Backend,
+    params: DocumentSymbolParams,
+) -> Result<Option<DocumentSymbolResponse>> {
+    let Some(ctx) =
+        RequestContext::for_br_document(backend, "document_symbol", &params.text_document.uri)
+    else {
+        return Ok(None);
+    };
+    let Some(version) = ctx.doc.as_ref().map(|doc| doc.version) else {
+        return Ok(None);
+    };
+
+    if let Some(syms) = backend
+        .analysis_cache
+        .symbols(params.text_document.uri.as_str(), version)
+    {
+        if syms.is_empty() {
+            return Ok(None);
+        }
+        ctx.log(format!("{} symbols, cached", syms.len())).await;
+        return Ok(Some(DocumentSymbolResponse::Nested(syms)));
+    }
+
+    let result =
+        ctx.with_tree(|tree, source| Some(symbols::collect_document_symbols(tree, source)));
+    match result {
+        Some(syms) if !syms.is_empty() => {
+            backend.analysis_cache.store_symbols(
+                params.text_document.uri.as_str(),
+                version,
+                syms.clone(),
+            );
+            ctx.log(format!("{} symbols", syms.len())).await;
+            Ok(Some(DocumentSymbolResponse::Nested(syms)))
+        }
+        _ => Ok(None),
+    }
+}
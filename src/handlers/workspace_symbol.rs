@@ -0,0 +1,71 @@
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{Location, SymbolInformation, SymbolKind, Url, WorkspaceSymbolParams};
+
+use super::RequestContext;
+use crate::backend::{Backend, DocumentKind, DocumentSnapshot};
+use crate::extract;
+use crate::workspace::IndexedFunctionDef;
+
+pub async fn workspace_symbol(
+    backend: &Backend,
+    params: WorkspaceSymbolParams,
+) -> Result<Option<Vec<SymbolInformation>>> {
+    let ctx = RequestContext::for_command(backend, "workspace_symbol");
+    let query = params.query.to_ascii_lowercase();
+
+    // While the index is busy, only the open documents' functions are listed
+    let open_symbols;
+    let index = ctx.workspace_index().await;
+    let all_symbols: Vec<&IndexedFunctionDef> = match &index {
+        Some(index) => index.all_symbols(),
+        None => {
+            open_symbols = open_document_functions(backend);
+            open_symbols.iter().collect()
+        }
+    };
+    let symbols: Vec<SymbolInformation> = all_symbols
+        .into_iter()
+        .filter(|s| query.is_empty() || s.def.name.to_ascii_lowercase().contains(&query))
+        .map(|s| {
+            #[allow(deprecated)]
+            SymbolInformation {
+                name: s.def.name.clone(),
+                kind: SymbolKind::FUNCTION,
+                tags: None,
+                deprecated: None,
+                location: Location {
+                    uri: s.uri.clone(),
+                    range: s.def.selection_range,
+                },
+                container_name: None,
+            }
+        })
+        .collect();
+    drop(index);
+
+    ctx.log(format!("\"{}\", {} symbols", params.query, symbols.len()))
+        .await;
+    Ok((!symbols.is_empty()).then_some(symbols))
+}
+
+/// The functions defined in open BR documents.
+fn open_document_functions(backend: &Backend) -> Vec<IndexedFunctionDef> {
+    let docs: Vec<(String, DocumentSnapshot)> = backend
+        .document_map
+        .iter()
+        .filter(|e| e.value().kind == DocumentKind::Br)
+        .map(|e| (e.key().clone(), e.value().snapshot()))
+        .collect();
+    docs.into_iter()
+        .filter_map(|(uri, doc)| {
+            let uri = Url::parse(&uri).ok()?;
+            let tree = doc.tree.as_ref()?;
+            let defs = extract::extract_definitions(tree, &doc.source);
+            Some(defs.into_iter().map(move |def| IndexedFunctionDef {
+                uri: uri.clone(),
+                def,
+            }))
+        })
+        .flatten()
+        .collect()
+}
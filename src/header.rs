@@ -0,0 +1,169 @@
+//! The `br-lsp.insertHeader` command: a comment block from
+//! `br-lsp.header.template` put at the top of a program, with
+//! `${program}`, `${file}`, `${author}` and `${date}` filled in.
+
+use serde_json::Value;
+use tower_lsp::lsp_types::{Position, Range, TextEdit, Url};
+
+use crate::constants;
+
+/// Template when the setting is absent
+pub const DEFAULT_TEMPLATE: [&str; 4] = [
+    "! ${program}",
+    "! Author: ${author}",
+    "! Created: ${date}",
+    "!",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderTemplate {
+    pub lines: Vec<String>,
+    /// From `br-lsp.header.author`; the login name when empty
+    pub author: Option<String>,
+}
+
+impl Default for HeaderTemplate {
+    fn default() -> Self {
+        Self {
+            lines: DEFAULT_TEMPLATE.iter().map(|l| l.to_string()).collect(),
+            author: None,
+        }
+    }
+}
+
+impl HeaderTemplate {
+    /// Read `br-lsp.header`: `template` is a list of lines or one string.
+    pub fn from_settings(settings: Option<&Value>) -> Self {
+        let mut header = Self::default();
+        let Some(settings) = settings else {
+            return header;
+        };
+        match settings.get("template") {
+            Some(Value::String(s)) => header.lines = s.lines().map(str::to_string).collect(),
+            Some(Value::Array(lines)) => {
+                header.lines = lines
+                    .iter()
+                    .filter_map(|l| l.as_str())
+                    .map(str::to_string)
+                    .collect()
+            }
+            _ => {}
+        }
+        header.author = settings
+            .get("author")
+            .and_then(|v| v.as_str())
+            .map(str::trim)
+            .filter(|a| !a.is_empty())
+            .map(str::to_string);
+        header
+    }
+
+    /// The header for `uri`, one template line per line, ending in `eol`.
+    /// Unknown placeholders are left as they are.
+    pub fn render(&self, uri: &Url, date: &str, eol: &str) -> String {
+        let path = uri.to_file_path().unwrap_or_else(|_| uri.path().into());
+        let file = path
+            .file_name()
+            .map(|f| f.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let program = path
+            .file_stem()
+            .map(|f| f.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let author = self.author.clone().unwrap_or_else(login_name);
+        self.lines
+            .iter()
+            .map(|line| {
+                let line = line
+                    .replace("${program}", &program)
+                    .replace("${file}", &file)
+                    .replace("${author}", &author)
+                    .replace("${date}", date);
+                format!("{line}{eol}")
+            })
+            .collect()
+    }
+}
+
+/// The edit inserting the header at the top of `source`, or `None` when
+/// the template is empty or the file already starts with it.
+pub fn header_edit(
+    template: &HeaderTemplate,
+    uri: &Url,
+    source: &str,
+    date: &str,
+) -> Option<TextEdit> {
+    let eol = if source.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let header = template.render(uri, date, eol);
+    let first = header.lines().next()?;
+    if source.lines().next().map(str::trim_end) == Some(first.trim_end()) {
+        return None;
+    }
+    Some(TextEdit {
+        range: Range::new(Position::new(0, 0), Position::new(0, 0)),
+        new_text: header,
+    })
+}
+
+/// Today's date as `YYYY-MM-DD`, in UTC.
+pub fn today() -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let day = constants::UNIX_EPOCH_DAY + (secs / 86_400) as i64;
+    constants::date_from_day(day)
+        .map(|(y, m, d)| format!("{y:04}-{m:02}-{d:02}"))
+        .unwrap_or_default()
+}
+
+fn login_name() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_placeholders_with_the_file_line_endings() {
+        let template = HeaderTemplate::from_settings(Some(&serde_json::json!({
+            "template": ["! Program: ${program} (${file})", "! By ${author} on ${date}", "! ${ticket}"],
+            "author": "J. Smith",
+        })));
+        let uri = Url::parse("file:///src/cust%20maint.brs").unwrap();
+        let edit = header_edit(&template, &uri, "print 1\r\n", "2026-10-18").unwrap();
+        assert_eq!(edit.range.start, Position::new(0, 0));
+        assert_eq!(
+            edit.new_text,
+            "! Program: cust maint (cust maint.brs)\r\n! By J. Smith on 2026-10-18\r\n! ${ticket}\r\n"
+        );
+    }
+
+    #[test]
+    fn skips_files_that_already_have_the_header() {
+        let template = HeaderTemplate::default();
+        let uri = Url::parse("file:///src/prog.brs").unwrap();
+        assert!(header_edit(
+            &template,
+            &uri,
+            "! prog\n! Author: x\nprint 1\n",
+            "2026-10-18"
+        )
+        .is_none());
+        let empty = HeaderTemplate::from_settings(Some(&serde_json::json!({ "template": [] })));
+        assert!(header_edit(&empty, &uri, "print 1\n", "2026-10-18").is_none());
+    }
+
+    #[test]
+    fn today_is_an_iso_date() {
+        let date = today();
+        assert_eq!(date.len(), 10, "{date}");
+        assert!(date.starts_with("20"));
+    }
+}
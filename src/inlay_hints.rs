@@ -0,0 +1,156 @@
+use tower_lsp::lsp_types::{InlayHint, InlayHintKind, InlayHintLabel, InlayHintTooltip, Range};
+use tree_sitter::{Node, Tree};
+
+use crate::extract::FunctionDef;
+use crate::parser;
+
+/// Node kinds for a variable an argument can hand to the callee by reference.
+const VARIABLE_KINDS: &[&str] = &[
+    "numberreference",
+    "stringreference",
+    "numberarray",
+    "stringarray",
+    "numberelement",
+    "stringelement",
+];
+
+/// The variable an argument consists of, if it is nothing but a variable
+/// (or array element) — expressions can't be modified by the callee.
+fn argument_variable(argument: Node) -> Option<Node> {
+    let mut node = argument;
+    loop {
+        if VARIABLE_KINDS.contains(&node.kind()) {
+            return Some(node);
+        }
+        if node.named_child_count() != 1 {
+            return None;
+        }
+        node = node.named_child(0)?;
+    }
+}
+
+fn call_arguments(call: Node) -> Vec<Node> {
+    let mut cursor = call.walk();
+    let Some(args) = call
+        .named_children(&mut cursor)
+        .find(|c| c.kind() == "arguments")
+    else {
+        return Vec::new();
+    };
+    let mut cursor = args.walk();
+    args.named_children(&mut cursor)
+        .filter(|c| c.kind() == "argument")
+        .collect()
+}
+
+/// An `&` hint before each variable passed to a by-reference parameter of
+/// a user function called within `range`, so it's visible which variables
+/// a call may modify. `resolve` maps a function name to its definition.
+pub fn reference_argument_hints<'a>(
+    tree: &Tree,
+    source: &str,
+    range: Range,
+    resolve: impl Fn(&str) -> Option<&'a FunctionDef>,
+) -> Vec<InlayHint> {
+    let nodes = parser::collect_diagnostic_nodes(tree, source);
+    let mut hints = Vec::new();
+
+    for call in nodes.function_calls {
+        if !matches!(
+            call.kind(),
+            "numeric_user_function" | "string_user_function"
+        ) {
+            continue;
+        }
+        let call_range = parser::node_range(call);
+        if call_range.end < range.start || call_range.start > range.end {
+            continue;
+        }
+        let Some(name) = call
+            .child_by_field_name("name")
+            .or_else(|| call.named_child(0))
+            .and_then(|n| n.utf8_text(source.as_bytes()).ok())
+        else {
+            continue;
+        };
+        let Some(def) = resolve(name) else {
+            continue;
+        };
+
+        for (argument, param) in call_arguments(call).into_iter().zip(&def.params) {
+            if !param.is_reference {
+                continue;
+            }
+            let Some(var) = argument_variable(argument) else {
+                continue;
+            };
+            let var_text = var.utf8_text(source.as_bytes()).unwrap_or_default();
+            hints.push(InlayHint {
+                position: parser::node_range(var).start,
+                label: InlayHintLabel::String("&".to_string()),
+                kind: Some(InlayHintKind::PARAMETER),
+                text_edits: None,
+                tooltip: Some(InlayHintTooltip::String(format!(
+                    "{var_text} is passed by reference to {} of {} and may be modified by the call",
+                    param.format_label(),
+                    def.name
+                ))),
+                padding_left: None,
+                padding_right: None,
+                data: None,
+            });
+        }
+    }
+
+    hints.sort_by_key(|h| (h.position.line, h.position.character));
+    hints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract;
+    use tower_lsp::lsp_types::Position;
+
+    fn hints(source: &str) -> Vec<(u32, u32)> {
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        let defs = extract::extract_definitions(&tree, source);
+        let range = Range::new(Position::new(0, 0), Position::new(u32::MAX, 0));
+        reference_argument_hints(&tree, source, range, |name| {
+            defs.iter().find(|d| d.name.eq_ignore_ascii_case(name))
+        })
+        .into_iter()
+        .map(|h| (h.position.line, h.position.character))
+        .collect()
+    }
+
+    #[test]
+    fn marks_variables_bound_to_reference_params() {
+        let source = "def fnSwap(&A, B, &C$)\nfnend\nlet X = fnSwap(Y, Z, W$)\n";
+        assert_eq!(hints(source), vec![(2, 15), (2, 21)]);
+    }
+
+    #[test]
+    fn skips_expressions_and_unknown_functions() {
+        let source = "def fnInc(&A)\nfnend\nlet X = fnInc(Y + 1)\nlet X = fnOther(Y)\n";
+        assert!(hints(source).is_empty());
+    }
+
+    #[test]
+    fn tooltip_names_parameter_and_function() {
+        let source = "def fnInc(&A)\nfnend\nlet X = fnInc(Y)\n";
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        let defs = extract::extract_definitions(&tree, source);
+        let range = Range::new(Position::new(0, 0), Position::new(3, 0));
+        let hints = reference_argument_hints(&tree, source, range, |_| defs.first());
+        let Some(InlayHintTooltip::String(tooltip)) = &hints[0].tooltip else {
+            panic!("expected tooltip");
+        };
+        assert_eq!(
+            tooltip,
+            "Y is passed by reference to &A of fnInc and may be modified by the call"
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+use tower_lsp::lsp_types::{InlineValue, InlineValueVariableLookup, Range};
+use tree_sitter::Tree;
+
+use crate::parser;
+
+/// Variable node queries and the kind each one reports.
+const VARIABLE_QUERIES: &[(&str, VariableKind)] = &[
+    ("(stringarray name: (_) @name)", VariableKind::StringArray),
+    ("(numberarray name: (_) @name)", VariableKind::NumberArray),
+    ("(stringreference name: (_) @name)", VariableKind::String),
+    ("(numberreference name: (_) @name)", VariableKind::Number),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum VariableKind {
+    String,
+    Number,
+    StringArray,
+    NumberArray,
+}
+
+/// A variable reference, as returned by the `br-lsp.lineVariables` command.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LineVariable {
+    pub name: String,
+    pub kind: VariableKind,
+    pub range: Range,
+}
+
+/// Variables referenced within `range`, one entry per name per line, in
+/// document order.
+pub fn collect_line_variables(tree: &Tree, source: &str, range: Range) -> Vec<LineVariable> {
+    let root = tree.root_node();
+    let mut seen = HashSet::new();
+    let mut vars = Vec::new();
+
+    for &(query, kind) in VARIABLE_QUERIES {
+        for r in parser::run_query(query, root, source) {
+            if r.range.start < range.start || r.range.end > range.end {
+                continue;
+            }
+            if !seen.insert((r.range.start.line, r.text.to_ascii_lowercase())) {
+                continue;
+            }
+            vars.push(LineVariable {
+                name: r.text,
+                kind,
+                range: r.range,
+            });
+        }
+    }
+
+    vars.sort_by_key(|v| (v.range.start.line, v.range.start.character));
+    vars
+}
+
+/// `textDocument/inlineValue` results for `range`. BR names are case
+/// insensitive and carry their type in the name (`$` suffix for strings),
+/// so a debugger can look each one up directly.
+pub fn inline_values(tree: &Tree, source: &str, range: Range) -> Vec<InlineValue> {
+    collect_line_variables(tree, source, range)
+        .into_iter()
+        .map(|v| {
+            InlineValue::VariableLookup(InlineValueVariableLookup {
+                range: v.range,
+                variable_name: Some(v.name),
+                case_sensitive_lookup: false,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Position;
+
+    fn vars(source: &str, start_line: u32, end_line: u32) -> Vec<(String, VariableKind, u32)> {
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        let range = Range::new(Position::new(start_line, 0), Position::new(end_line, 0));
+        collect_line_variables(&tree, source, range)
+            .into_iter()
+            .map(|v| (v.name, v.kind, v.range.start.line))
+            .collect()
+    }
+
+    #[test]
+    fn variables_per_line_with_kinds() {
+        let source = "let X = 1\nlet Name$ = \"a\"\nlet Total = X + Amt(2)\n";
+        assert_eq!(
+            vars(source, 0, 3),
+            vec![
+                ("X".to_string(), VariableKind::Number, 0),
+                ("Name$".to_string(), VariableKind::String, 1),
+                ("Total".to_string(), VariableKind::Number, 2),
+                ("X".to_string(), VariableKind::Number, 2),
+                ("Amt".to_string(), VariableKind::NumberArray, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn repeated_name_on_line_reported_once() {
+        let source = "let X = x + X\n";
+        assert_eq!(vars(source, 0, 1).len(), 1);
+    }
+
+    #[test]
+    fn outside_range_excluded() {
+        let source = "let A = 1\nlet B = 2\nlet C = 3\n";
+        let names: Vec<String> = vars(source, 1, 2).into_iter().map(|v| v.0).collect();
+        assert_eq!(names, vec!["B"]);
+    }
+}
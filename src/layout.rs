@@ -1,8 +1,9 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 
-use tower_lsp::lsp_types::SemanticToken;
+use tower_lsp::lsp_types::{Diagnostic, Position, Range, SemanticToken};
 
+use crate::pic;
 use crate::semantic_tokens::{encode_deltas, RawToken};
 
 // Token type indices (from TOKEN_TYPES in semantic_tokens.rs)
@@ -36,33 +37,137 @@ pub struct LayoutSubscript {
     pub name: String,
     pub description: String,
     pub format: String,
+    /// Byte offset of the field from the start of the record (0-based).
+    /// `None` once any earlier field has no length in its spec.
+    pub offset: Option<u32>,
+    /// Bytes the field takes, from its format spec (`BH 4.2` → 4)
+    pub length: Option<u32>,
+    /// 0-based line of the field in the layout file
+    pub line: u32,
+}
+
+impl LayoutSubscript {
+    /// 1-based record position, as `KPs=` and `POS` use.
+    pub fn position(&self) -> Option<u32> {
+        self.offset.map(|o| o + 1)
+    }
+}
+
+/// The length in a form spec, ignoring decimals: `C 30` → 30, `BH 4.2` → 4,
+/// `PIC(ZZ#.##)` → 6.
+fn spec_length(format: &str) -> Option<u32> {
+    if let Some((_, mask)) = pic::mask_of(format) {
+        return Some(mask.chars().count() as u32);
+    }
+    let rest = format
+        .trim()
+        .trim_start_matches(|c: char| c.is_ascii_alphabetic())
+        .trim_start();
+    let digits = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    rest[..digits].parse().ok()
 }
 
 #[derive(Debug, Clone)]
-#[allow(dead_code)]
 pub struct LayoutKey {
     pub path: String,
     pub key_fields: Vec<String>,
 }
 
+/// Where a key's parts sit in the record, as `KPs=`/`KLn=` give them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeySpec {
+    /// 1-based record positions, one per key part
+    pub positions: Vec<u32>,
+    pub lengths: Vec<u32>,
+}
+
+impl KeySpec {
+    /// Build a spec from parallel position/length lists, joining parts that
+    /// follow each other in the record: `KPs=1/11,KLn=10/30` and
+    /// `KPs=1,KLn=40` describe the same key.
+    pub fn new(positions: &[u32], lengths: &[u32]) -> Self {
+        let mut spec = KeySpec {
+            positions: Vec::new(),
+            lengths: Vec::new(),
+        };
+        for (&position, &length) in positions.iter().zip(lengths) {
+            match (spec.positions.last(), spec.lengths.last_mut()) {
+                (Some(&last), Some(last_len)) if last + *last_len == position => {
+                    *last_len += length;
+                }
+                _ => {
+                    spec.positions.push(position);
+                    spec.lengths.push(length);
+                }
+            }
+        }
+        spec
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Layout {
     pub path: String,
     pub prefix: String,
-    #[allow(dead_code)]
     pub version: Option<u32>,
-    #[allow(dead_code)]
     pub keys: Vec<LayoutKey>,
     pub subscripts: Vec<LayoutSubscript>,
-    #[allow(dead_code)]
     pub record_length: Option<u32>,
 }
 
+impl Layout {
+    /// A field named with or without the layout prefix.
+    pub fn subscript(&self, name: &str) -> Option<&LayoutSubscript> {
+        let name = name.trim();
+        let unprefixed = match name.get(..self.prefix.len()) {
+            Some(p) if p.eq_ignore_ascii_case(&self.prefix) => &name[self.prefix.len()..],
+            _ => name,
+        };
+        self.subscripts
+            .iter()
+            .find(|s| s.name.eq_ignore_ascii_case(name) || s.name.eq_ignore_ascii_case(unprefixed))
+    }
+
+    /// 1-based record position and length of a field. `None` if the field,
+    /// or a field before it, has no length in its spec.
+    pub fn field_position(&self, name: &str) -> Option<(u32, u32)> {
+        let subscript = self.subscript(name)?;
+        Some((subscript.position()?, subscript.length?))
+    }
+
+    /// Record length the field list adds up to, when every field has a length.
+    pub fn computed_record_length(&self) -> Option<u32> {
+        self.subscripts.iter().map(|s| s.length).sum()
+    }
+
+    /// The key whose index file is `kfname`, compared by file name.
+    pub fn key_for(&self, kfname: &str) -> Option<&LayoutKey> {
+        let wanted = file_name(kfname);
+        self.keys
+            .iter()
+            .find(|k| file_name(&k.path).eq_ignore_ascii_case(wanted))
+    }
+
+    /// Positions and lengths of `key`'s fields computed from the field list.
+    pub fn key_spec(&self, key: &LayoutKey) -> Option<KeySpec> {
+        let mut positions = Vec::new();
+        let mut lengths = Vec::new();
+        for field in &key.key_fields {
+            let (position, width) = self.field_position(field)?;
+            positions.push(position);
+            lengths.push(width);
+        }
+        Some(KeySpec::new(&positions, &lengths))
+    }
+}
+
 // ---------------------------------------------------------------------------
 // LayoutIndex
 // ---------------------------------------------------------------------------
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct LayoutIndex {
     layouts: HashMap<String, Layout>,
 }
@@ -87,6 +192,49 @@ impl LayoutIndex {
     pub fn all_layouts(&self) -> impl Iterator<Item = &Layout> {
         self.layouts.values()
     }
+
+    /// The layout describing the file an OPEN statement names: one whose
+    /// header path is that file, else one whose own file shares its stem.
+    pub fn find_for_open(&self, name: &str) -> Option<&Layout> {
+        if let Some(layout) = self
+            .all_layouts()
+            .find(|l| crate::files::layout_matches(name, l))
+        {
+            return Some(layout);
+        }
+        let stem = file_stem(name);
+        if stem.is_empty() {
+            return None;
+        }
+        self.layouts
+            .iter()
+            .find(|(uri, _)| file_stem(uri) == stem)
+            .map(|(_, layout)| layout)
+    }
+
+    /// The layout field a program variable names: the layout prefix followed
+    /// by the field name, e.g. `RCU_NAME$`.
+    pub fn find_subscript(&self, variable: &str) -> Option<(&Layout, &LayoutSubscript)> {
+        self.all_layouts().find_map(|layout| {
+            let prefix = variable.get(..layout.prefix.len())?;
+            if layout.prefix.is_empty() || !prefix.eq_ignore_ascii_case(&layout.prefix) {
+                return None;
+            }
+            let field = &variable[prefix.len()..];
+            let sub = layout
+                .subscripts
+                .iter()
+                .find(|s| s.name.eq_ignore_ascii_case(field))?;
+            Some((layout, sub))
+        })
+    }
+
+    /// Iterates over `(uri, layout)` pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Layout)> {
+        self.layouts
+            .iter()
+            .map(|(uri, layout)| (uri.as_str(), layout))
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -111,10 +259,10 @@ pub fn parse(source: &str) -> Option<Layout> {
     let mut prefix = String::new();
     let mut version: Option<u32> = None;
     let mut keys = Vec::new();
-    let mut subscripts = Vec::new();
+    let mut subscripts: Vec<LayoutSubscript> = Vec::new();
     let mut record_length: Option<u32> = None;
 
-    for line in source.lines() {
+    for (line_num, line) in source.lines().enumerate() {
         let trimmed = line.trim();
 
         if state == State::Eof {
@@ -180,7 +328,10 @@ pub fn parse(source: &str) -> Option<Layout> {
             }
             State::Fields => {
                 // Field lines: name, description, spec+length [, comment]
-                let parts: Vec<&str> = trimmed.splitn(4, ',').collect();
+                let parts: Vec<&str> = split_field_line(trimmed)
+                    .into_iter()
+                    .map(|(_, part)| part)
+                    .collect();
                 if parts.len() >= 3 {
                     let name = parts[0].trim().to_string();
                     let description = parts
@@ -191,10 +342,17 @@ pub fn parse(source: &str) -> Option<Layout> {
                         .get(2)
                         .map(|s| s.trim().to_string())
                         .unwrap_or_default();
+                    let offset = match subscripts.last() {
+                        Some(prev) => prev.offset.zip(prev.length).map(|(o, l)| o + l),
+                        None => Some(0),
+                    };
                     subscripts.push(LayoutSubscript {
+                        length: spec_length(&format),
                         name,
                         description,
                         format,
+                        offset,
+                        line: line_num as u32,
                     });
                 }
             }
@@ -216,6 +374,28 @@ pub fn parse(source: &str) -> Option<Layout> {
     })
 }
 
+/// Split a field line into name, description, spec and trailing comment,
+/// with each part's byte offset. Commas inside parentheses belong to the
+/// spec, as in `PIC(ZZZ,ZZ#.##)`.
+fn split_field_line(line: &str) -> Vec<(usize, &str)> {
+    let mut parts = Vec::with_capacity(4);
+    let mut start = 0;
+    let mut depth = 0u32;
+    for (i, c) in line.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 && parts.len() < 3 => {
+                parts.push((start, &line[start..i]));
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push((start, &line[start..]));
+    parts
+}
+
 fn is_separator(line: &str) -> bool {
     !line.is_empty() && line.chars().all(|c| c == '-' || c == '=')
 }
@@ -317,7 +497,7 @@ pub fn collect_layout_tokens(source: &str) -> Vec<SemanticToken> {
         }
     }
 
-    encode_deltas(&mut raw)
+    encode_deltas(&mut raw, source)
 }
 
 fn leading_spaces(line: &str) -> usize {
@@ -411,9 +591,8 @@ fn tokenize_recl_line(line: &str, line_num: u32, tokens: &mut Vec<RawToken>) {
 
 fn tokenize_field_line(line: &str, line_num: u32, tokens: &mut Vec<RawToken>) {
     // field name, description, spec+length [, trailing comment]
-    let mut col = 0u32;
-    for (i, part) in line.splitn(4, ',').enumerate() {
-        let start = col;
+    for (i, (offset, part)) in split_field_line(line).into_iter().enumerate() {
+        let start = offset as u32;
         let trimmed = part.trim();
         if !trimmed.is_empty() {
             let trim_start = start + (part.len() - part.trim_start().len()) as u32;
@@ -455,7 +634,6 @@ fn tokenize_field_line(line: &str, line_num: u32, tokens: &mut Vec<RawToken>) {
                 _ => {}
             }
         }
-        col = start + part.len() as u32 + 1; // +1 for comma
     }
 }
 
@@ -529,6 +707,141 @@ pub fn is_layout_file(path: &Path) -> bool {
     false
 }
 
+/// Hover text for a layout field: its spec, place in the record and file.
+pub fn format_subscript_hover(layout: &Layout, sub: &LayoutSubscript) -> String {
+    let mut md = format!("**{}{}** (subscript)", layout.prefix, sub.name);
+    if !sub.description.is_empty() {
+        md.push_str(&format!(" \u{2014} {}", sub.description));
+    }
+    let mut details = vec![format!("Form: `{}`", sub.format)];
+    if let Some((pos, len)) = sub.position().zip(sub.length) {
+        details.push(format!("Position: {pos}-{} ({len} bytes)", pos + len - 1));
+    }
+    details.push(format!("Layout: `{}`", layout.path));
+    md.push_str("\n\n");
+    md.push_str(
+        &details
+            .iter()
+            .map(|d| format!("- {d}"))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    );
+    md
+}
+
+/// Fields listed on each side of the hovered one in a layout field hover.
+const NEIGHBOR_FIELDS: usize = 2;
+
+/// Hover for the field defined on `line` of a layout file: where it sits
+/// in the record, and a map of the fields around it. Returns the markdown
+/// and the range of the field's name.
+pub fn format_field_hover(source: &str, line: u32) -> Option<(String, Range)> {
+    let layout = parse(source)?;
+    let index = layout.subscripts.iter().position(|s| s.line == line)?;
+    let sub = &layout.subscripts[index];
+
+    let mut md = format!("**{}{}**", layout.prefix, sub.name);
+    if !sub.description.is_empty() {
+        md.push_str(&format!(" \u{2014} {}", sub.description));
+    }
+    let mut details = vec![format!("Form: `{}`", sub.format)];
+    match sub.offset {
+        Some(offset) => details.push(format!("Offset: {offset}")),
+        None => details.push("Offset: unknown (an earlier field has no length)".to_string()),
+    }
+    if let Some(length) = sub.length {
+        details.push(format!("Length: {length} bytes"));
+    }
+    if let Some((pos, len)) = sub.position().zip(sub.length) {
+        details.push(format!("Position: {pos}-{}", pos + len - 1));
+    }
+    md.push_str("\n\n");
+    md.push_str(
+        &details
+            .iter()
+            .map(|d| format!("- {d}"))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    );
+
+    if let Some((_, mask)) = pic::mask_of(&sub.format) {
+        md.push_str("\n\n");
+        md.push_str(&pic::format_hover(mask));
+    }
+
+    let first = index.saturating_sub(NEIGHBOR_FIELDS);
+    let last = (index + NEIGHBOR_FIELDS).min(layout.subscripts.len() - 1);
+    md.push_str("\n\n| Field | Form | Position | Length |\n|---|---|---|---|\n");
+    for (i, field) in layout.subscripts[first..=last].iter().enumerate() {
+        let name = if first + i == index {
+            format!("**{}**", field.name)
+        } else {
+            field.name.clone()
+        };
+        let position = match field.position().zip(field.length) {
+            Some((pos, len)) => format!("{pos}-{}", pos + len - 1),
+            None => "?".to_string(),
+        };
+        let length = field.length.map_or("?".to_string(), |l| l.to_string());
+        md.push_str(&format!(
+            "| {name} | `{}` | {position} | {length} |\n",
+            field.format
+        ));
+    }
+
+    let text = source.lines().nth(line as usize)?;
+    let start = text.len() - text.trim_start().len();
+    let end = text.find(',').unwrap_or(text.len());
+    let name_end = start + text[start..end].trim_end().len();
+    let range = Range::new(
+        Position::new(line, text[..start].chars().count() as u32),
+        Position::new(line, text[..name_end].chars().count() as u32),
+    );
+    Some((md, range))
+}
+
+/// Problems with the PIC masks of a layout's fields.
+pub fn check_pic_masks(source: &str) -> Vec<Diagnostic> {
+    let Some(layout) = parse(source) else {
+        return Vec::new();
+    };
+    let lines: Vec<&str> = source.lines().collect();
+    layout
+        .subscripts
+        .iter()
+        .filter_map(|sub| {
+            let text = lines.get(sub.line as usize)?;
+            let &(offset, spec) = split_field_line(text).get(2)?;
+            let spec_start = offset + spec.len() - spec.trim_start().len();
+            let (mask_offset, mask) = pic::mask_of(spec.trim())?;
+            let column = text[..spec_start + mask_offset].chars().count() as u32;
+            let start = Position::new(sub.line, column);
+            Some(
+                pic::validate(mask)
+                    .iter()
+                    .map(|problem| problem.to_diagnostic(start))
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .flatten()
+        .collect()
+}
+
+/// The last component of a BR file name, after any directory or drive.
+fn file_name(name: &str) -> &str {
+    name.trim().rsplit(['/', '\\', ':']).next().unwrap_or("")
+}
+
+/// Lowercased file name up to its first '.', ignoring any directory or
+/// drive prefix: `[Q]\Data\Customer.dat` → `customer`.
+pub fn file_stem(name: &str) -> String {
+    file_name(name)
+        .split('.')
+        .next()
+        .unwrap_or("")
+        .to_ascii_lowercase()
+}
+
 pub fn read_layout_file(path: &Path) -> std::io::Result<String> {
     std::fs::read_to_string(path)
 }
@@ -537,26 +850,78 @@ pub fn read_layout_file(path: &Path) -> std::io::Result<String> {
 // Workspace scanning
 // ---------------------------------------------------------------------------
 
-pub fn scan_workspace_layouts(folder: &tower_lsp::lsp_types::Url) -> Vec<(String, Layout)> {
+pub fn scan_workspace_layouts(
+    folder: &tower_lsp::lsp_types::Url,
+    respect_gitignore: bool,
+) -> Vec<(String, Layout)> {
     let path = match folder.to_file_path() {
         Ok(p) => p,
         Err(()) => return Vec::new(),
     };
 
     let mut results = Vec::new();
-    for entry in walkdir::WalkDir::new(&path)
-        .follow_links(true)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file() && is_layout_file(e.path()))
+    for file_path in
+        crate::workspace::walk_files(&path, respect_gitignore).filter(|p| is_layout_file(p))
     {
-        let file_path = entry.path();
-        let source = match read_layout_file(file_path) {
+        let source = match read_layout_file(&file_path) {
             Ok(s) => s,
             Err(_) => continue,
         };
         if let Some(layout) = parse(&source) {
-            let uri = match tower_lsp::lsp_types::Url::from_file_path(file_path) {
+            let uri = match tower_lsp::lsp_types::Url::from_file_path(&file_path) {
+                Ok(u) => u.to_string(),
+                Err(()) => continue,
+            };
+            results.push((uri, layout));
+        }
+    }
+
+    results
+}
+
+/// Larger files are data, not layouts, and aren't worth reading.
+const MAX_LINKED_LAYOUT_BYTES: u64 = 256 * 1024;
+
+/// Layouts stored outside `filelay` and without a `.lay` extension, found by
+/// file stem from the names OPEN statements use: `OPEN #1: "Name=data/
+/// customer.dat"` picks up e.g. `layouts/customer.txt` or `customer` if that
+/// file parses as a layout.
+pub fn scan_linked_layouts(
+    folder: &tower_lsp::lsp_types::Url,
+    open_names: &HashSet<String>,
+    respect_gitignore: bool,
+) -> Vec<(String, Layout)> {
+    let stems: HashSet<String> = open_names
+        .iter()
+        .map(|name| file_stem(name))
+        .filter(|stem| !stem.is_empty())
+        .collect();
+    if stems.is_empty() {
+        return Vec::new();
+    }
+    let path = match folder.to_file_path() {
+        Ok(p) => p,
+        Err(()) => return Vec::new(),
+    };
+
+    let mut results = Vec::new();
+    for file_path in crate::workspace::walk_files(&path, respect_gitignore).filter(|p| {
+        !is_layout_file(p)
+            && !crate::workspace::is_br_file(p)
+            && std::fs::metadata(p)
+                .map(|m| m.len() <= MAX_LINKED_LAYOUT_BYTES)
+                .unwrap_or(false)
+    }) {
+        let stem = file_stem(&file_path.file_name().unwrap_or_default().to_string_lossy());
+        if !stems.contains(&stem) {
+            continue;
+        }
+        let source = match read_layout_file(&file_path) {
+            Ok(s) => s,
+            Err(_) => continue,
+        };
+        if let Some(layout) = parse(&source).filter(|l| !l.subscripts.is_empty()) {
+            let uri = match tower_lsp::lsp_types::Url::from_file_path(&file_path) {
                 Ok(u) => u.to_string(),
                 Err(()) => continue,
             };
@@ -666,6 +1031,16 @@ This should be ignored
         assert_eq!(tokens[key_start + 2].token_type, TT_VARIABLE); // key field 2
     }
 
+    #[test]
+    fn token_columns_after_no_break_space() {
+        // CP437 0xFF decodes to U+00A0, two bytes but one column
+        let source = "DATA.DAT, PFX_, 1\n\u{a0}----------\n";
+        let tokens = collect_raw(source);
+        let separator = tokens.last().unwrap();
+        assert_eq!(separator.token_type, TT_COMMENT);
+        assert_eq!((separator.delta_start, separator.length), (1, 10));
+    }
+
     #[test]
     fn token_recl() {
         let source = "DATA.DAT, PFX_, 1\nrecl=128\n----------\n";
@@ -784,11 +1159,17 @@ This should be ignored
                         name: "NAME$".into(),
                         description: "Customer Name".into(),
                         format: "C".into(),
+                        offset: Some(0),
+                        length: None,
+                        line: 0,
                     },
                     LayoutSubscript {
                         name: "BALANCE".into(),
                         description: "Balance".into(),
                         format: "N".into(),
+                        offset: None,
+                        length: None,
+                        line: 1,
                     },
                 ],
                 record_length: None,
@@ -832,4 +1213,173 @@ This should be ignored
         assert_eq!(layouts[0].path, "OTHER.DAT");
         assert_eq!(layouts[0].subscripts.len(), 2);
     }
+
+    #[test]
+    fn file_stem_strips_directories_and_extensions() {
+        assert_eq!(file_stem("data/Customer.dat"), "customer");
+        assert_eq!(file_stem("[Q]\\Data\\CUSTOMER.DAT"), "customer");
+        assert_eq!(file_stem("C:customer.int.dat"), "customer");
+        assert_eq!(file_stem(" vendor "), "vendor");
+    }
+
+    #[test]
+    fn find_for_open_falls_back_to_layout_file_stem() {
+        let mut idx = LayoutIndex::new();
+        let layout = parse("OTHER.DAT, CU_, 1\n----------\nNAME$, Name, C 30\n").unwrap();
+        idx.add("file:///ws/layouts/customer.txt", layout);
+        let found = idx.find_for_open("data\\customer.dat").unwrap();
+        assert_eq!(found.prefix, "CU_");
+        assert!(idx.find_for_open("other.dat").is_some());
+        assert!(idx.find_for_open("vendor.dat").is_none());
+    }
+
+    #[test]
+    fn scan_linked_layouts_finds_layouts_by_open_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let layouts = dir.path().join("layouts");
+        std::fs::create_dir(&layouts).unwrap();
+        std::fs::write(
+            layouts.join("customer.txt"),
+            "CUSTOMER.DAT, CU_, 1\n----------\nNAME$, Name, C 30\n",
+        )
+        .unwrap();
+        std::fs::write(
+            layouts.join("vendor.txt"),
+            "VENDOR.DAT, VE_, 1\n----------\nA, A, N 5\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("customer.brs"),
+            "OPEN #1: \"Name=customer.dat\", internal, input\n",
+        )
+        .unwrap();
+
+        let folder = tower_lsp::lsp_types::Url::from_file_path(dir.path()).unwrap();
+        let names = HashSet::from(["data/customer.dat".to_string()]);
+        let found = scan_linked_layouts(&folder, &names, true);
+        assert_eq!(found.len(), 1);
+        assert!(found[0].0.ends_with("customer.txt"));
+        assert_eq!(found[0].1.prefix, "CU_");
+    }
+
+    #[test]
+    fn subscript_offsets_and_lengths() {
+        let layout = parse(SAMPLE_LAYOUT).unwrap();
+        let spans: Vec<_> = layout
+            .subscripts
+            .iter()
+            .map(|s| (s.offset, s.length))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                (Some(0), Some(10)),
+                (Some(10), Some(30)),
+                (Some(40), Some(4))
+            ]
+        );
+        assert_eq!(layout.computed_record_length(), Some(44));
+    }
+
+    #[test]
+    fn subscript_lookup_and_hover() {
+        let mut idx = LayoutIndex::new();
+        idx.add("file:///customer.lay", parse(SAMPLE_LAYOUT).unwrap());
+        let (layout, sub) = idx.find_subscript("rcu_name$").unwrap();
+        assert_eq!(sub.name, "NAME$");
+        assert!(idx.find_subscript("NAME$").is_none());
+        assert!(idx.find_subscript("RCU_MISSING").is_none());
+
+        let md = format_subscript_hover(layout, sub);
+        assert!(md.starts_with("**RCU_NAME$** (subscript) \u{2014} Customer Name"));
+        assert!(md.contains("- Form: `C 30`"));
+        assert!(md.contains("- Position: 11-40 (30 bytes)"));
+        assert!(md.contains("- Layout: `CUSTOMER.DAT`"));
+    }
+
+    #[test]
+    fn field_hover_shows_offset_and_neighbors() {
+        let (md, range) = format_field_hover(SAMPLE_LAYOUT, 5).unwrap();
+        assert!(md.starts_with("**RCU_NAME$** \u{2014} Customer Name"));
+        assert!(md.contains("- Offset: 10"));
+        assert!(md.contains("- Length: 30 bytes"));
+        assert!(md.contains("- Position: 11-40"));
+        assert!(md.contains("| CUSTOMER_ID$ | `C 10` | 1-10 | 10 |"));
+        assert!(md.contains("| **NAME$** | `C 30` | 11-40 | 30 |"));
+        assert!(md.contains("| BALANCE | `BH 4.2` | 41-44 | 4 |"));
+        assert_eq!(range, Range::new(Position::new(5, 0), Position::new(5, 5)));
+
+        // Header lines aren't fields
+        assert!(format_field_hover(SAMPLE_LAYOUT, 1).is_none());
+    }
+
+    #[test]
+    fn pic_fields_keep_commas_in_mask() {
+        let source = "\
+DATA.DAT, DT_, 1
+----------
+AMOUNT, Amount, PIC(ZZZ,ZZ#.##), dollars
+BAD, Bad, PIC(ZZQ)
+NEXT$, Next, C 4
+";
+        let layout = parse(source).unwrap();
+        assert_eq!(layout.subscripts[0].format, "PIC(ZZZ,ZZ#.##)");
+        assert_eq!(layout.subscripts[0].length, Some(10));
+        assert_eq!(layout.subscripts[2].offset, Some(13));
+
+        let (md, _) = format_field_hover(source, 2).unwrap();
+        assert!(md.contains("| `1234.56` | `  1,234.56` |"));
+
+        let diagnostics = check_pic_masks(source);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(3, 16), Position::new(3, 17))
+        );
+        assert_eq!(diagnostics[0].message, "`Q` is not a PIC mask character");
+    }
+
+    #[test]
+    fn offsets_unknown_after_field_without_length() {
+        let layout =
+            parse("DATA.DAT, DT_, 1\n----------\nA$, A, C 5\nB, B, S\nC, C, N 3\n").unwrap();
+        assert_eq!(layout.subscripts[1].offset, Some(5));
+        assert_eq!(layout.subscripts[1].length, None);
+        assert_eq!(layout.subscripts[2].offset, None);
+        assert_eq!(layout.computed_record_length(), None);
+        assert_eq!(layout.field_position("DT_A$"), Some((1, 5)));
+    }
+
+    #[test]
+    fn field_positions_and_key_spec() {
+        let layout = parse(
+            "CUSTOMER.DAT, RCU_, 1\nCUSTOMER.IX1, RCU_CUSTOMER_ID$\nCUSTOMER.IX2, RCU_NAME$, RCU_CUSTOMER_ID$\n----------\nCUSTOMER_ID$, Customer ID, C 10\nNAME$, Customer Name, C 30\nBALANCE, Balance, BH 4.2\n",
+        )
+        .unwrap();
+        assert_eq!(layout.field_position("NAME$"), Some((11, 30)));
+        assert_eq!(layout.field_position("rcu_balance"), Some((41, 4)));
+        assert_eq!(layout.field_position("MISSING"), None);
+
+        let key = layout.key_for("data\\customer.ix1").unwrap();
+        assert_eq!(
+            layout.key_spec(key),
+            Some(KeySpec {
+                positions: vec![1],
+                lengths: vec![10],
+            })
+        );
+        assert!(layout.key_for("customer.ix3").is_none());
+
+        let key = layout.key_for("CUSTOMER.IX2").unwrap();
+        assert_eq!(
+            layout.key_spec(key),
+            Some(KeySpec::new(&[11, 1], &[30, 10]))
+        );
+    }
+
+    #[test]
+    fn key_spec_joins_adjacent_parts() {
+        assert_eq!(KeySpec::new(&[1, 11], &[10, 30]), KeySpec::new(&[1], &[40]));
+        assert_eq!(KeySpec::new(&[11, 1], &[30, 10]).positions, vec![11, 1]);
+    }
 }
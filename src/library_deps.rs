@@ -0,0 +1,478 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Range, Url};
+use tree_sitter::{Node, Tree};
+
+use crate::diagnostics::{related, SOURCE};
+use crate::extract::{self, FunctionDef};
+use crate::parser::node_range;
+use crate::workspace::{DriveMappings, WorkspaceIndex};
+
+/// One `LIBRARY "path": fnA, fnB` statement.
+#[derive(Debug, Clone)]
+pub struct LibraryImport {
+    /// Normalized library path (see `extract::normalize_library_path`)
+    pub path: String,
+    pub path_range: Range,
+    pub functions: Vec<(String, Range)>,
+}
+
+/// What a single file contributes to the library dependency graph.
+#[derive(Debug, Clone)]
+pub struct FileLibraries {
+    pub uri: Url,
+    /// Workspace-relative link path (see `workspace::uri_to_link_path`)
+    pub link_path: Option<String>,
+    pub imports: Vec<LibraryImport>,
+    /// Lowercase names of `DEF LIBRARY` functions defined in this file
+    pub library_defs: HashSet<String>,
+}
+
+impl FileLibraries {
+    pub fn new(
+        uri: Url,
+        link_path: Option<String>,
+        tree: &Tree,
+        source: &str,
+        defs: &[FunctionDef],
+    ) -> Self {
+        let mut imports = Vec::new();
+        collect_imports(tree.root_node(), source, &mut imports);
+        let library_defs = defs
+            .iter()
+            .filter(|d| d.is_library && !d.is_import_only)
+            .map(|d| d.name.to_ascii_lowercase())
+            .collect();
+        Self {
+            uri,
+            link_path,
+            imports,
+            library_defs,
+        }
+    }
+
+    /// Rewrite import paths that start with a mapped drive prefix so they
+    /// compare equal to the link paths of the files they name.
+    pub fn map_drives(&mut self, mappings: &DriveMappings, workspace_folders: &[Url]) {
+        if mappings.is_empty() {
+            return;
+        }
+        for import in &mut self.imports {
+            import.path = mappings.map_link_path(&import.path, workspace_folders);
+        }
+    }
+}
+
+fn collect_imports(node: Node, source: &str, imports: &mut Vec<LibraryImport>) {
+    if node.kind() == "library_statement" {
+        let Some(path_node) = node.child_by_field_name("path") else {
+            return;
+        };
+        let Some(raw) = extract::extract_string_literal(path_node, source) else {
+            return;
+        };
+        let mut functions = Vec::new();
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            if child.kind() == "library_function_list" {
+                let mut inner = child.walk();
+                for grandchild in child.children(&mut inner) {
+                    if grandchild.kind() == "function_name" {
+                        if let Ok(name) = grandchild.utf8_text(source.as_bytes()) {
+                            functions.push((name.to_string(), node_range(grandchild)));
+                        }
+                    }
+                }
+            }
+        }
+        imports.push(LibraryImport {
+            path: extract::normalize_library_path(&raw),
+            path_range: node_range(path_node),
+            functions,
+        });
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_imports(child, source, imports);
+    }
+}
+
+/// Check LIBRARY relationships across the workspace. Reports imports of
+/// functions the target file does not define as `DEF LIBRARY`, and library
+/// statements that take part in an import cycle. Imports whose target file
+/// is not part of the workspace are not checked.
+pub fn check_library_dependencies(files: &[FileLibraries]) -> Vec<(Url, Diagnostic)> {
+    let by_path: HashMap<&str, &FileLibraries> = files
+        .iter()
+        .filter_map(|f| f.link_path.as_deref().map(|p| (p, f)))
+        .collect();
+
+    let mut results = Vec::new();
+
+    for file in files {
+        for import in &file.imports {
+            let Some(target) = by_path.get(import.path.as_str()) else {
+                continue;
+            };
+            for (name, range) in &import.functions {
+                if !target.library_defs.contains(&name.to_ascii_lowercase()) {
+                    results.push((
+                        file.uri.clone(),
+                        missing_function(&import.path, name, *range, &target.uri),
+                    ));
+                }
+            }
+        }
+    }
+
+    // Adjacency between link paths, restricted to files in the workspace
+    let edges: HashMap<&str, Vec<&str>> = by_path
+        .iter()
+        .map(|(&path, file)| {
+            let mut targets: Vec<&str> = file
+                .imports
+                .iter()
+                .map(|i| i.path.as_str())
+                .filter(|p| by_path.contains_key(p))
+                .collect();
+            targets.sort_unstable();
+            targets.dedup();
+            (path, targets)
+        })
+        .collect();
+
+    for (&path, file) in &by_path {
+        for import in &file.imports {
+            if !by_path.contains_key(import.path.as_str()) {
+                continue;
+            }
+            if let Some(cycle) = find_path(&edges, &import.path, path) {
+                let mut chain = vec![path];
+                chain.extend(cycle);
+                results.push((
+                    file.uri.clone(),
+                    Diagnostic {
+                        range: import.path_range,
+                        severity: Some(DiagnosticSeverity::WARNING),
+                        code: Some(NumberOrString::String("library-cycle".to_string())),
+                        message: format!("Library import cycle: {}", chain.join(" -> ")),
+                        source: Some(SOURCE.to_string()),
+                        ..Default::default()
+                    },
+                ));
+            }
+        }
+    }
+
+    results
+}
+
+/// Check one document's LIBRARY function lists against the indexed file
+/// each path resolves to, after drive mappings, so missing functions show
+/// up while editing rather than only on a full scan. Paths that match no
+/// indexed file, or more than one, aren't checked: without knowing which
+/// file BR would load, a missing function could be a false alarm.
+pub fn check_library_functions(
+    tree: &Tree,
+    source: &str,
+    index: &WorkspaceIndex,
+    mappings: &DriveMappings,
+) -> Vec<Diagnostic> {
+    let mut imports = Vec::new();
+    collect_imports(tree.root_node(), source, &mut imports);
+
+    let mut diagnostics = Vec::new();
+    for import in &imports {
+        let pattern = index_pattern(&import.path, mappings);
+        let [target] = index.files_matching(&pattern)[..] else {
+            continue;
+        };
+        let defined = index.functions_in(&pattern);
+        for (name, range) in &import.functions {
+            let found = defined.iter().any(|d| {
+                &d.uri == target && d.def.is_library && d.def.name.eq_ignore_ascii_case(name)
+            });
+            if !found {
+                diagnostics.push(missing_function(&import.path, name, *range, target));
+            }
+        }
+    }
+    diagnostics
+}
+
+/// The index path pattern for a normalized library path.
+fn index_pattern(path: &str, mappings: &DriveMappings) -> String {
+    match mappings.split(path) {
+        Some((target, rest)) => format!("{target}/{rest}"),
+        // An unmapped drive prefix can't be resolved; match on the rest
+        None => match path.split_once(':') {
+            Some((drive, rest)) if !drive.contains('/') => rest.to_string(),
+            _ => path.to_string(),
+        },
+    }
+}
+
+/// Point each unused-import hint at the definition it imports, resolving
+/// LIBRARY paths through the index as [`check_library_functions`] does.
+pub fn link_unused_imports(
+    diagnostics: &mut [Diagnostic],
+    tree: &Tree,
+    source: &str,
+    index: &WorkspaceIndex,
+    mappings: &DriveMappings,
+) {
+    let mut imports = Vec::new();
+    collect_imports(tree.root_node(), source, &mut imports);
+
+    let unused = diagnostics
+        .iter_mut()
+        .filter(|d| d.code == Some(NumberOrString::String("unused-import".to_string())));
+    for diagnostic in unused {
+        let Some((import, name)) = imports.iter().find_map(|import| {
+            let (name, _) = import
+                .functions
+                .iter()
+                .find(|(_, range)| *range == diagnostic.range)?;
+            Some((import, name))
+        }) else {
+            continue;
+        };
+        let defined = index.functions_in(&index_pattern(&import.path, mappings));
+        if let Some(found) = defined
+            .iter()
+            .find(|d| d.def.is_library && d.def.name.eq_ignore_ascii_case(name))
+        {
+            diagnostic.related_information = Some(related(
+                &found.uri,
+                found.def.selection_range,
+                format!("'{}' is defined here", found.def.name),
+            ));
+        }
+    }
+}
+
+fn missing_function(path: &str, name: &str, range: Range, target: &Url) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::ERROR),
+        code: Some(NumberOrString::String(
+            "library-missing-function".to_string(),
+        )),
+        message: format!("Library '{path}' does not define library function '{name}'"),
+        source: Some(SOURCE.to_string()),
+        related_information: Some(related(
+            target,
+            Range::default(),
+            format!("Library '{path}'"),
+        )),
+        ..Default::default()
+    }
+}
+
+/// Shortest path from `from` to `to` (inclusive of both) by BFS.
+fn find_path<'a>(
+    edges: &HashMap<&'a str, Vec<&'a str>>,
+    from: &'a str,
+    to: &str,
+) -> Option<Vec<&'a str>> {
+    let mut prev: HashMap<&str, &str> = HashMap::new();
+    let mut queue = VecDeque::from([from]);
+    let mut seen = HashSet::from([from]);
+
+    while let Some(current) = queue.pop_front() {
+        if current == to {
+            let mut path = vec![current];
+            let mut node = current;
+            while let Some(&p) = prev.get(node) {
+                path.push(p);
+                node = p;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for &next in edges.get(current).into_iter().flatten() {
+            if seen.insert(next) {
+                prev.insert(next, current);
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use tower_lsp::lsp_types::Position;
+
+    fn file(name: &str, source: &str) -> FileLibraries {
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        let defs = extract::extract_definitions(&tree, source);
+        let uri = Url::parse(&format!("file:///workspace/{name}.brs")).unwrap();
+        FileLibraries::new(uri, Some(name.to_string()), &tree, source, &defs)
+    }
+
+    fn codes(results: &[(Url, Diagnostic)]) -> Vec<String> {
+        results
+            .iter()
+            .filter_map(|(_, d)| match &d.code {
+                Some(NumberOrString::String(s)) => Some(s.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn collects_imports_with_ranges() {
+        let f = file("main", "library \"lib\\Utils.brs\": fnA, fnB$\n");
+        assert_eq!(f.imports.len(), 1);
+        assert_eq!(f.imports[0].path, "lib/utils");
+        assert_eq!(f.imports[0].functions.len(), 2);
+        assert_eq!(f.imports[0].functions[1].0, "fnB$");
+    }
+
+    #[test]
+    fn defined_library_function_ok() {
+        let files = vec![
+            file("main", "library \"utils\": fnA\nlet X = fnA(1)\n"),
+            file("utils", "def library fnA(X)\nfnend\n"),
+        ];
+        assert!(check_library_dependencies(&files).is_empty());
+    }
+
+    #[test]
+    fn missing_library_function_flagged() {
+        let files = vec![
+            file("main", "library \"utils\": fnA, fnMissing\n"),
+            file("utils", "def library fnA(X)\nfnend\ndef fnMissing(X) = X\n"),
+        ];
+        let results = check_library_dependencies(&files);
+        assert_eq!(codes(&results), vec!["library-missing-function"]);
+        assert!(results[0].1.message.contains("fnMissing"));
+        assert_eq!(results[0].0.as_str(), "file:///workspace/main.brs");
+        let related = results[0].1.related_information.as_ref().unwrap();
+        assert_eq!(
+            related[0].location.uri.as_str(),
+            "file:///workspace/utils.brs"
+        );
+    }
+
+    #[test]
+    fn library_functions_checked_against_index() {
+        let mut index = WorkspaceIndex::new();
+        let utils = "def library fnA(X)\nfnend\ndef fnB(X) = X\n";
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, utils, None).unwrap();
+        let uri = Url::parse("file:///workspace/lib/utils.brs").unwrap();
+        index.add_file(&uri, extract::extract_definitions(&tree, utils));
+
+        let source = "library \"c:lib\\utils.br\": fnA, fnB\nlibrary \"other\": fnC\n";
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        let diags = check_library_functions(&tree, source, &index, &DriveMappings::default());
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].message.contains("'fnB'"));
+    }
+
+    #[test]
+    fn unused_imports_link_to_their_definition() {
+        let mut index = WorkspaceIndex::new();
+        let utils = "def library fnA(X)\nfnend\n";
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, utils, None).unwrap();
+        let uri = Url::parse("file:///workspace/lib/utils.brs").unwrap();
+        index.add_file(&uri, extract::extract_definitions(&tree, utils));
+
+        let source = "library \"c:lib\\utils.br\": fnA, fnB\n";
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        let unused = |col: u32, len: u32| Diagnostic {
+            range: Range::new(Position::new(0, col), Position::new(0, col + len)),
+            code: Some(NumberOrString::String("unused-import".to_string())),
+            ..Default::default()
+        };
+        let mut diags = vec![unused(26, 3), unused(31, 3)];
+        link_unused_imports(&mut diags, &tree, source, &index, &DriveMappings::default());
+        let related = diags[0].related_information.as_ref().unwrap();
+        assert_eq!(related[0].location.uri, uri);
+        assert_eq!(related[0].message, "'fnA' is defined here");
+        assert!(diags[1].related_information.is_none());
+    }
+
+    #[test]
+    fn library_functions_checked_against_resolved_file() {
+        let mut index = WorkspaceIndex::new();
+        let shared = Url::parse("file:///mnt/shared/custlib.brs").unwrap();
+        index.add_file(&shared, Vec::new());
+        let local = "def library fnMissing(X)\nfnend\n";
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, local, None).unwrap();
+        index.add_file(
+            &Url::parse("file:///workspace/custlib.brs").unwrap(),
+            extract::extract_definitions(&tree, local),
+        );
+        let mappings = DriveMappings::from_settings(Some(&serde_json::json!({
+            "VOL002": "/mnt/shared"
+        })));
+
+        let source = "library \"vol002\\custlib\": fnMissing\n";
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        let diags = check_library_functions(&tree, source, &index, &mappings);
+        assert_eq!(diags.len(), 1);
+        let related = diags[0].related_information.as_ref().unwrap();
+        assert_eq!(related[0].location.uri, shared);
+
+        // Unmapped, the path names no indexed file and isn't checked
+        let diags = check_library_functions(&tree, source, &index, &DriveMappings::default());
+        assert!(diags.is_empty());
+    }
+
+    #[test]
+    fn ambiguous_library_path_not_checked() {
+        let mut index = WorkspaceIndex::new();
+        let mut p = parser::new_parser();
+        let lib = "def library fnA(X)\nfnend\n";
+        let tree = parser::parse(&mut p, lib, None).unwrap();
+        index.add_file(
+            &Url::parse("file:///workspace/app/utils.brs").unwrap(),
+            extract::extract_definitions(&tree, lib),
+        );
+        index.add_file(
+            &Url::parse("file:///workspace/old/utils.brs").unwrap(),
+            Vec::new(),
+        );
+
+        let source = "library \"utils\": fnA\n";
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        let diags = check_library_functions(&tree, source, &index, &DriveMappings::default());
+        assert!(diags.is_empty());
+    }
+
+    #[test]
+    fn unknown_target_not_checked() {
+        let files = vec![file("main", "library \"elsewhere\": fnA\n")];
+        assert!(check_library_dependencies(&files).is_empty());
+    }
+
+    #[test]
+    fn import_cycle_flagged_on_each_statement() {
+        let files = vec![
+            file("a", "library \"b\": fnB\ndef library fnA(X)\nfnend\n"),
+            file("b", "library \"a\": fnA\ndef library fnB(X)\nfnend\n"),
+            file("c", "library \"a\": fnA\n"),
+        ];
+        let results = check_library_dependencies(&files);
+        assert_eq!(codes(&results), vec!["library-cycle", "library-cycle"]);
+        let mut messages: Vec<&str> = results.iter().map(|(_, d)| d.message.as_str()).collect();
+        messages.sort_unstable();
+        assert_eq!(
+            messages,
+            vec![
+                "Library import cycle: a -> b -> a",
+                "Library import cycle: b -> a -> b",
+            ]
+        );
+    }
+}
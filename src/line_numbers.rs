@@ -0,0 +1,303 @@
+//! Line number order. BR keeps a program's lines sorted by number, and a
+//! second line with a number already used replaces the first, so a source
+//! file whose numbers repeat or go backwards loads differently from how it
+//! reads. `check_line_numbers` flags both; `renumber` gives every numbered
+//! line a fresh number in file order, for `br-lsp.renumberLines`.
+
+use std::collections::HashMap;
+
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticSeverity, NumberOrString, Position, Range, TextEdit, Url,
+};
+use tree_sitter::{Node, Tree};
+
+use crate::diagnostics::related;
+
+/// The highest line number BR accepts
+pub const MAX_LINE_NUMBER: u32 = 99_999;
+
+/// A line's number, without the space after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Numbered {
+    value: u32,
+    text: String,
+    range: Range,
+}
+
+/// Every line number in the file, in file order.
+fn line_numbers(tree: &Tree, source: &str) -> Vec<Numbered> {
+    let mut numbers = Vec::new();
+    let mut cursor = tree.root_node().walk();
+    for line in tree.root_node().children(&mut cursor) {
+        let mut line_cursor = line.walk();
+        let Some(node) = line
+            .children(&mut line_cursor)
+            .find(|c| c.kind() == "line_number")
+        else {
+            continue;
+        };
+        if let Some(numbered) = numbered(node, source) {
+            numbers.push(numbered);
+        }
+    }
+    numbers
+}
+
+fn numbered(node: Node, source: &str) -> Option<Numbered> {
+    let text = node.utf8_text(source.as_bytes()).ok()?.trim();
+    let value = text.parse().ok()?;
+    let start = node.start_position();
+    let start = Position::new(start.row as u32, start.column as u32);
+    Some(Numbered {
+        value,
+        text: text.to_string(),
+        range: Range::new(
+            start,
+            Position::new(start.line, start.character + text.len() as u32),
+        ),
+    })
+}
+
+/// Line numbers used twice, and line numbers lower than the one on the
+/// numbered line before them.
+pub fn check_line_numbers(tree: &Tree, source: &str, uri: &Url) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut first: HashMap<u32, Range> = HashMap::new();
+    let mut previous: Option<&Numbered> = None;
+    let numbers = line_numbers(tree, source);
+    for number in &numbers {
+        if let Some(&first_range) = first.get(&number.value) {
+            diagnostics.push(Diagnostic {
+                range: number.range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String("duplicate-line-number".to_string())),
+                message: format!(
+                    "Line number {} is already used on line {}; BR keeps only the last line with a number",
+                    number.value,
+                    first_range.start.line + 1
+                ),
+                related_information: Some(related(
+                    uri,
+                    first_range,
+                    format!("Line number {} is first used here", number.value),
+                )),
+                ..Default::default()
+            });
+        } else {
+            first.insert(number.value, number.range);
+            if let Some(previous) = previous.filter(|p| p.value > number.value) {
+                diagnostics.push(Diagnostic {
+                    range: number.range,
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(NumberOrString::String("line-number-order".to_string())),
+                    message: format!(
+                        "Line number {} comes after {}; BR will move it before that line",
+                        number.value, previous.value
+                    ),
+                    related_information: Some(related(
+                        uri,
+                        previous.range,
+                        format!("Line number {} is here", previous.value),
+                    )),
+                    ..Default::default()
+                });
+            }
+        }
+        previous = Some(number);
+    }
+    diagnostics
+}
+
+/// How `renumber` numbers lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenumberOptions {
+    pub start: u32,
+    pub increment: u32,
+    /// Digits to zero-pad to; `None` keeps the padding of the first line
+    pub padding: Option<usize>,
+}
+
+impl Default for RenumberOptions {
+    fn default() -> Self {
+        Self {
+            start: 10,
+            increment: 10,
+            padding: None,
+        }
+    }
+}
+
+/// Edits numbering every numbered line `start`, `start + increment`, ... in
+/// file order, with GOTO, GOSUB, USING, error-condition and other line
+/// references following their line. A reference to a number used twice
+/// follows the last line with it, the one BR keeps. Errors when the
+/// numbers would go past 99999.
+pub fn renumber(
+    tree: &Tree,
+    source: &str,
+    options: RenumberOptions,
+) -> Result<Vec<TextEdit>, String> {
+    let numbers = line_numbers(tree, source);
+    let Some(first) = numbers.first() else {
+        return Ok(Vec::new());
+    };
+    let increment = options.increment.max(1);
+    let last = u64::from(options.start) + u64::from(increment) * (numbers.len() as u64 - 1);
+    if last > u64::from(MAX_LINE_NUMBER) {
+        return Err(format!(
+            "Renumbering {} lines from {} by {increment} would pass line {MAX_LINE_NUMBER}",
+            numbers.len(),
+            options.start
+        ));
+    }
+    let padding = options.padding.unwrap_or_else(|| padded_width(&first.text));
+
+    let mut renumbered = HashMap::new();
+    let mut edits = Vec::new();
+    for (i, number) in numbers.iter().enumerate() {
+        let value = options.start + increment * i as u32;
+        renumbered.insert(number.value, value);
+        push_edit(&mut edits, number, value, padding);
+    }
+
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        if let Some(reference) = line_reference(node, source) {
+            if let Some(&value) = renumbered.get(&reference.value) {
+                let width = padded_width(&reference.text);
+                push_edit(&mut edits, &reference, value, width);
+            }
+            continue;
+        }
+        let mut cursor = node.walk();
+        stack.extend(node.children(&mut cursor));
+    }
+    edits.sort_by_key(|e| e.range.start);
+    Ok(edits)
+}
+
+fn push_edit(edits: &mut Vec<TextEdit>, number: &Numbered, value: u32, padding: usize) {
+    let new_text = format!("{value:0padding$}");
+    if new_text != number.text {
+        edits.push(TextEdit {
+            range: number.range,
+            new_text,
+        });
+    }
+}
+
+/// The width a zero-padded number keeps; 0 for one written without padding.
+pub(crate) fn padded_width(text: &str) -> usize {
+    if text.len() > 1 && text.starts_with('0') {
+        text.len()
+    } else {
+        0
+    }
+}
+
+/// The line number `node` jumps to, if it is a jump.
+pub(crate) fn referenced_line(node: Node, source: &str) -> Option<u32> {
+    line_reference(node, source).map(|n| n.value)
+}
+
+/// The line `node` jumps to: a `line_reference`, or the bare number after
+/// THEN or ELSE, which the grammar reads as an assignment.
+fn line_reference(node: Node, source: &str) -> Option<Numbered> {
+    match node.kind() {
+        "line_reference" => numbered(node, source),
+        "let_statement"
+            if node
+                .parent()
+                .is_some_and(|p| matches!(p.kind(), "if_statement" | "else_statement")) =>
+        {
+            let text = node.utf8_text(source.as_bytes()).ok()?.trim();
+            if text.is_empty() || !text.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            numbered(node, source)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn parse(source: &str) -> Tree {
+        parser::parse(&mut parser::new_parser(), source, None).unwrap()
+    }
+
+    fn codes(diagnostics: &[Diagnostic]) -> Vec<(u32, String)> {
+        diagnostics
+            .iter()
+            .map(|d| match &d.code {
+                Some(NumberOrString::String(c)) => (d.range.start.line, c.clone()),
+                _ => (d.range.start.line, String::new()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn flags_duplicates_and_lines_out_of_order() {
+        let source = "00100 print 1\n00200 print 2\n00150 print 3\n00200 print 4\n00300 print 5\n";
+        let uri = Url::parse("file:///src/prog.brs").unwrap();
+        let found = check_line_numbers(&parse(source), source, &uri);
+        assert_eq!(
+            codes(&found),
+            vec![
+                (2, "line-number-order".to_string()),
+                (3, "duplicate-line-number".to_string()),
+            ]
+        );
+        assert_eq!(
+            found[0].range,
+            Range::new(Position::new(2, 0), Position::new(2, 5))
+        );
+        let related = found[1].related_information.as_ref().unwrap();
+        assert_eq!(related[0].location.range.start.line, 1);
+
+        let ordered = "00100 print 1\n00110 print 2\n";
+        assert!(check_line_numbers(&parse(ordered), ordered, &uri).is_empty());
+    }
+
+    #[test]
+    fn renumber_follows_references() {
+        let source = "\
+00100 goto 300
+00300 if X then 100 else 300
+00200 print using 400: X
+00400 form C 10
+";
+        let edits = renumber(&parse(source), source, RenumberOptions::default()).unwrap();
+        let mut lines: Vec<String> = source.lines().map(str::to_string).collect();
+        for edit in edits.iter().rev() {
+            let line = &mut lines[edit.range.start.line as usize];
+            line.replace_range(
+                edit.range.start.character as usize..edit.range.end.character as usize,
+                &edit.new_text,
+            );
+        }
+        assert_eq!(
+            lines,
+            vec![
+                "00010 goto 20",
+                "00020 if X then 10 else 20",
+                "00030 print using 40: X",
+                "00040 form C 10",
+            ]
+        );
+    }
+
+    #[test]
+    fn renumber_refuses_to_pass_the_last_line_number() {
+        let source = "1 print 1\n2 print 2\n";
+        let options = RenumberOptions {
+            start: 99_990,
+            increment: 10,
+            padding: Some(0),
+        };
+        assert!(renumber(&parse(source), source, options).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+use serde::Serialize;
+use tower_lsp::lsp_types::{Position, Range};
+use tree_sitter::Tree;
+
+use crate::parser;
+
+const LITERAL_QUERIES: &[&str] = &["(string) @s", "(template_string) @s"];
+
+/// One occurrence of the search text inside a string literal, as returned by
+/// the `br-lsp.findLiteral` command.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LiteralMatch {
+    /// Range of the matched text itself
+    pub range: Range,
+    /// The whole literal the match was found in, quotes included
+    pub literal: String,
+}
+
+/// Fold a character for comparison: lowercase, and with `cp437` set, the
+/// accented letters of the CP437 code page reduce to their plain ASCII
+/// letter so `"muller"` finds `"Müller"`.
+fn fold_char(c: char, cp437: bool) -> char {
+    let c = c.to_lowercase().next().unwrap_or(c);
+    if !cp437 {
+        return c;
+    }
+    match c {
+        'à' | 'á' | 'â' | 'ä' | 'å' | 'ª' => 'a',
+        'ç' => 'c',
+        'è' | 'é' | 'ê' | 'ë' => 'e',
+        'ì' | 'í' | 'î' | 'ï' => 'i',
+        'ñ' => 'n',
+        'ò' | 'ó' | 'ô' | 'ö' | 'º' => 'o',
+        'ù' | 'ú' | 'û' | 'ü' => 'u',
+        'ÿ' => 'y',
+        _ => c,
+    }
+}
+
+fn fold(s: &str, cp437: bool) -> Vec<char> {
+    s.chars().map(|c| fold_char(c, cp437)).collect()
+}
+
+fn find_all<'a>(haystack: &'a [char], needle: &'a [char]) -> impl Iterator<Item = usize> + 'a {
+    (0..(haystack.len() + 1).saturating_sub(needle.len()))
+        .filter(move |&i| haystack[i..i + needle.len()] == *needle)
+}
+
+/// Occurrences of `needle` inside string and template literals,
+/// case-insensitively, in document order.
+pub fn find_literals(tree: &Tree, source: &str, needle: &str, cp437: bool) -> Vec<LiteralMatch> {
+    let needle_folded = fold(needle, cp437);
+    if needle_folded.is_empty() {
+        return Vec::new();
+    }
+
+    let root = tree.root_node();
+    let mut matches = Vec::new();
+    for query in LITERAL_QUERIES {
+        for r in parser::run_query(query, root, source) {
+            // Byte offset of each char, so matches map back to columns
+            let offsets: Vec<usize> = r.text.char_indices().map(|(i, _)| i).collect();
+            let folded = fold(&r.text, cp437);
+            for i in find_all(&folded, &needle_folded) {
+                let start = offsets[i];
+                let end = offsets
+                    .get(i + needle_folded.len())
+                    .copied()
+                    .unwrap_or(r.text.len());
+                matches.push(LiteralMatch {
+                    range: Range::new(
+                        offset_position(r.range.start, &r.text[..start]),
+                        offset_position(r.range.start, &r.text[..end]),
+                    ),
+                    literal: r.text.clone(),
+                });
+            }
+        }
+    }
+
+    matches.sort_by_key(|m| (m.range.start.line, m.range.start.character));
+    matches
+}
+
+/// Position reached after `prefix`, starting at `start`. Columns are byte
+/// offsets, matching `parser::node_range`.
+fn offset_position(start: Position, prefix: &str) -> Position {
+    match prefix.rfind('\n') {
+        Some(nl) => Position::new(
+            start.line + prefix.matches('\n').count() as u32,
+            (prefix.len() - nl - 1) as u32,
+        ),
+        None => Position::new(start.line, start.character + prefix.len() as u32),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(source: &str, needle: &str, cp437: bool) -> Vec<(u32, u32, u32)> {
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        find_literals(&tree, source, needle, cp437)
+            .into_iter()
+            .map(|m| {
+                (
+                    m.range.start.line,
+                    m.range.start.character,
+                    m.range.end.character,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_case_insensitively_in_string_literals() {
+        let source = "open #1: \"name=data/Customer.dat\",internal,input\nlet A$ = \"CUSTOMER\"\n";
+        assert_eq!(
+            find(source, "customer", false),
+            vec![(0, 20, 28), (1, 10, 18)]
+        );
+    }
+
+    #[test]
+    fn ignores_text_outside_literals() {
+        let source = "let Customer = 1\nprint Customer\n";
+        assert!(find(source, "customer", false).is_empty());
+    }
+
+    #[test]
+    fn cp437_folding_matches_accented_letters() {
+        let source = "let A$ = \"Müller\"\n";
+        assert!(find(source, "muller", false).is_empty());
+        // 'ü' is two bytes in UTF-8, so the match ends one column further
+        assert_eq!(find(source, "muller", true), vec![(0, 10, 17)]);
+    }
+}
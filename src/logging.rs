@@ -0,0 +1,138 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tower_lsp::lsp_types::MessageType;
+use tower_lsp::Client;
+
+/// How long LOG messages are held so they reach the client as one message.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Most verbose kind of message sent to the client, from `br-lsp.logLevel`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warning,
+    Info,
+    Log,
+}
+
+impl LogLevel {
+    /// Parse the setting; anything unrecognised means full logging.
+    pub fn from_setting(value: Option<&str>) -> Self {
+        match value.map(str::to_ascii_lowercase).as_deref() {
+            Some("off") => Self::Off,
+            Some("error") => Self::Error,
+            Some("warning") => Self::Warning,
+            Some("info") => Self::Info,
+            _ => Self::Log,
+        }
+    }
+
+    fn of(kind: MessageType) -> Self {
+        if kind == MessageType::ERROR {
+            Self::Error
+        } else if kind == MessageType::WARNING {
+            Self::Warning
+        } else if kind == MessageType::INFO {
+            Self::Info
+        } else {
+            Self::Log
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Self::Off,
+            1 => Self::Error,
+            2 => Self::Warning,
+            3 => Self::Info,
+            _ => Self::Log,
+        }
+    }
+}
+
+/// Sends `window/logMessage` notifications. Messages more verbose than the
+/// configured level are dropped. LOG messages, which most requests send, are
+/// batched and flushed together after [`FLUSH_INTERVAL`] so they neither
+/// flood the output channel nor hold up the request; anything more
+/// important flushes the batch and goes out at once.
+pub struct Logger {
+    client: Client,
+    level: AtomicU8,
+    pending: Mutex<Vec<String>>,
+}
+
+impl Logger {
+    pub fn new(client: Client) -> Arc<Self> {
+        Arc::new(Self {
+            client,
+            level: AtomicU8::new(LogLevel::Log as u8),
+            pending: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub fn level(&self) -> LogLevel {
+        LogLevel::from_u8(self.level.load(Ordering::Relaxed))
+    }
+
+    pub fn set_level(&self, level: LogLevel) {
+        self.level.store(level as u8, Ordering::Relaxed);
+    }
+
+    pub async fn log(self: &Arc<Self>, kind: MessageType, message: impl Into<String>) {
+        let level = LogLevel::of(kind);
+        if level > self.level() {
+            return;
+        }
+        if level < LogLevel::Log {
+            self.flush().await;
+            self.client.log_message(kind, message.into()).await;
+            return;
+        }
+
+        let first = {
+            let mut pending = self.pending.lock().unwrap();
+            pending.push(message.into());
+            pending.len() == 1
+        };
+        if first {
+            let logger = Arc::clone(self);
+            tokio::spawn(async move {
+                tokio::time::sleep(FLUSH_INTERVAL).await;
+                logger.flush().await;
+            });
+        }
+    }
+
+    /// Send any batched LOG messages now.
+    pub async fn flush(&self) {
+        let batch = std::mem::take(&mut *self.pending.lock().unwrap());
+        if !batch.is_empty() {
+            self.client
+                .log_message(MessageType::LOG, batch.join("\n"))
+                .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_order_by_verbosity() {
+        assert_eq!(LogLevel::from_setting(Some("Off")), LogLevel::Off);
+        assert_eq!(LogLevel::from_setting(Some("info")), LogLevel::Info);
+        assert_eq!(LogLevel::from_setting(None), LogLevel::Log);
+        assert_eq!(LogLevel::from_setting(Some("verbose")), LogLevel::Log);
+
+        assert!(LogLevel::of(MessageType::LOG) > LogLevel::Info);
+        assert!(LogLevel::of(MessageType::ERROR) > LogLevel::Off);
+        assert_eq!(LogLevel::of(MessageType::WARNING), LogLevel::Warning);
+        for level in [LogLevel::Off, LogLevel::Warning, LogLevel::Log] {
+            assert_eq!(LogLevel::from_u8(level as u8), level);
+        }
+    }
+}
@@ -1,26 +1,58 @@
+mod analysis_cache;
 mod backend;
 mod builtins;
+mod call_graph;
 mod check;
 mod code_action;
 mod completions;
+mod config_change;
+mod constants;
 mod definition;
 mod diagnostics;
+mod diagnostics_queue;
+mod dialect;
+mod document_links;
+mod error_conditions;
+mod error_handling;
+mod export;
+mod external_checker;
 mod extract;
+mod files;
+mod folding;
+mod generated;
+mod handlers;
+mod header;
+mod inlay_hints;
+mod inline_values;
 mod layout;
+mod library_deps;
+mod line_numbers;
+mod literals;
+mod logging;
+mod metrics;
+mod on_type_formatting;
+mod organize_libraries;
+mod orphans;
+mod parse_health;
 mod parser;
+mod pic;
+mod profiles;
+mod published;
 mod references;
 mod rename;
+mod replace;
+mod rules;
 mod semantic_tokens;
+mod shadowing;
+mod snippets;
+mod statements;
+mod structure;
+mod style;
 mod symbols;
 mod workspace;
 
-use std::sync::Arc;
-
 use backend::Backend;
-use dashmap::DashMap;
-use tokio::sync::RwLock;
 use tower_lsp::{LspService, Server};
-use workspace::WorkspaceIndex;
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
@@ -59,19 +91,11 @@ async fn run_lsp() {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
-    let (service, socket) = LspService::build(|client| Backend {
-        client,
-        document_map: Arc::new(DashMap::new()),
-        parser: std::sync::Mutex::new(parser::new_parser()),
-        workspace_index: Arc::new(RwLock::new(WorkspaceIndex::new())),
-        layout_index: Arc::new(RwLock::new(layout::LayoutIndex::new())),
-        workspace_folders: Arc::new(RwLock::new(Vec::new())),
-        indexing_complete: Arc::new(std::sync::atomic::AtomicBool::new(false)),
-        diagnostics_generation: Arc::new(DashMap::new()),
-        diagnostics_config: Arc::new(RwLock::new(backend::DiagnosticsConfig::default())),
-        symbol_cache: DashMap::new(),
-    })
-    .finish();
+    let (service, socket) = LspService::build(Backend::new)
+        .custom_method("br-lsp/status", Backend::status)
+        .custom_method("br-lsp/functionInfo", Backend::function_info)
+        .custom_method("br-lsp/peekDefinition", Backend::peek_definition)
+        .finish();
 
     Server::new(stdin, stdout, socket).serve(service).await;
 }
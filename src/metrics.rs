@@ -0,0 +1,225 @@
+//! Size and complexity of each function: lines from DEF to FNEND,
+//! parameters, and a cyclomatic complexity approximated as one plus the
+//! IF, DO, FOR and GOTO statements in the body. Shown in code lenses and
+//! `br-lsp.metricsReport`; functions past the `br-lsp.metrics` limits get a
+//! hint.
+
+use serde_json::Value;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Range};
+use tree_sitter::{Node, Tree};
+
+use crate::check::csv_escape;
+use crate::{definition, extract};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionMetrics {
+    pub name: String,
+    /// The function's name in its DEF statement
+    pub range: Range,
+    pub lines: usize,
+    pub complexity: usize,
+    pub parameters: usize,
+}
+
+impl FunctionMetrics {
+    /// One-line summary for lenses and logs.
+    pub fn summary(&self) -> String {
+        let plural = |n: usize, word: &str| {
+            if n == 1 {
+                format!("1 {word}")
+            } else {
+                format!("{n} {word}s")
+            }
+        };
+        format!(
+            "{} · complexity {} · {}",
+            plural(self.lines, "line"),
+            self.complexity,
+            plural(self.parameters, "parameter")
+        )
+    }
+}
+
+/// Limits from `br-lsp.metrics`; 0 turns a limit off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetricsLimits {
+    pub max_lines: usize,
+    pub max_complexity: usize,
+    pub max_parameters: usize,
+}
+
+impl Default for MetricsLimits {
+    fn default() -> Self {
+        Self {
+            max_lines: 200,
+            max_complexity: 20,
+            max_parameters: 10,
+        }
+    }
+}
+
+impl MetricsLimits {
+    pub fn from_settings(settings: Option<&Value>) -> Self {
+        let defaults = Self::default();
+        let limit = |key: &str, default: usize| {
+            settings
+                .and_then(|s| s.get(key))
+                .and_then(|v| v.as_u64())
+                .map_or(default, |n| n as usize)
+        };
+        Self {
+            max_lines: limit("maxLines", defaults.max_lines),
+            max_complexity: limit("maxComplexity", defaults.max_complexity),
+            max_parameters: limit("maxParameters", defaults.max_parameters),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_lines > 0 || self.max_complexity > 0 || self.max_parameters > 0
+    }
+
+    /// The limits `metrics` goes past, described as `240 lines (limit 200)`.
+    pub fn exceeded(&self, metrics: &FunctionMetrics) -> Vec<String> {
+        [
+            (metrics.lines, self.max_lines, "lines"),
+            (metrics.complexity, self.max_complexity, "complexity"),
+            (metrics.parameters, self.max_parameters, "parameters"),
+        ]
+        .into_iter()
+        .filter(|&(value, limit, _)| limit > 0 && value > limit)
+        .map(|(value, limit, what)| match what {
+            "complexity" => format!("complexity {value} (limit {limit})"),
+            _ => format!("{value} {what} (limit {limit})"),
+        })
+        .collect()
+    }
+}
+
+/// Metrics for every function defined in the file, in source order.
+pub fn function_metrics(tree: &Tree, source: &str) -> Vec<FunctionMetrics> {
+    let mut decisions = Vec::new();
+    collect_decisions(tree.root_node(), &mut decisions);
+
+    extract::extract_definitions(tree, source)
+        .into_iter()
+        .filter(|def| !def.is_import_only)
+        .map(|def| {
+            let extent = definition::function_extent(tree, source, def.range);
+            let (first, last) = (extent.start.line as usize, extent.end.line as usize);
+            let branches = decisions
+                .partition_point(|&row| row <= last)
+                .saturating_sub(decisions.partition_point(|&row| row < first));
+            FunctionMetrics {
+                name: def.name.clone(),
+                range: def.selection_range,
+                lines: last - first + 1,
+                complexity: 1 + branches,
+                parameters: def.visible_params().len(),
+            }
+        })
+        .collect()
+}
+
+/// Rows of the statements that add a path through the code, in order.
+fn collect_decisions(node: Node, rows: &mut Vec<usize>) {
+    if matches!(
+        node.kind(),
+        "if_statement" | "do_statement" | "for_statement" | "goto_statement"
+    ) {
+        rows.push(node.start_position().row);
+    }
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        collect_decisions(child, rows);
+    }
+}
+
+/// Hints on the functions past one of `limits`.
+pub fn check_metrics(tree: &Tree, source: &str, limits: &MetricsLimits) -> Vec<Diagnostic> {
+    function_metrics(tree, source)
+        .into_iter()
+        .filter_map(|metrics| {
+            let exceeded = limits.exceeded(&metrics);
+            (!exceeded.is_empty()).then(|| Diagnostic {
+                range: metrics.range,
+                severity: Some(DiagnosticSeverity::HINT),
+                code: Some(NumberOrString::String("function-metrics".to_string())),
+                message: format!("{} has {}", metrics.name, exceeded.join(", ")),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Format function metrics as CSV with a header row. Lines are 1-based.
+pub fn format_csv(rows: &[(String, FunctionMetrics)]) -> String {
+    let mut out = String::from("file,line,function,lines,complexity,parameters\n");
+    for (file, metrics) in rows {
+        out.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            csv_escape(file),
+            metrics.range.start.line + 1,
+            metrics.name,
+            metrics.lines,
+            metrics.complexity,
+            metrics.parameters
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    const SOURCE: &str = "\
+def fnPick(A, B$; C)
+  if A > 0 then
+    do while A > 0
+      let A -= 1
+    loop
+  end if
+  for I = 1 to 3
+    if I = 2 then goto Done
+  next I
+  Done: let fnPick = A
+fnend
+def fnOne = 1
+";
+
+    fn metrics() -> Vec<FunctionMetrics> {
+        let tree = parser::parse(&mut parser::new_parser(), SOURCE, None).unwrap();
+        function_metrics(&tree, SOURCE)
+    }
+
+    #[test]
+    fn counts_lines_branches_and_parameters() {
+        let found = metrics();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].name, "fnPick");
+        assert_eq!(
+            (found[0].lines, found[0].complexity, found[0].parameters),
+            (11, 6, 3)
+        );
+        assert_eq!(found[0].summary(), "11 lines · complexity 6 · 3 parameters");
+        assert_eq!((found[1].lines, found[1].complexity), (1, 1));
+    }
+
+    #[test]
+    fn hints_name_the_limits_passed() {
+        let tree = parser::parse(&mut parser::new_parser(), SOURCE, None).unwrap();
+        let limits = MetricsLimits::from_settings(Some(&serde_json::json!({
+            "maxLines": 10,
+            "maxComplexity": 5,
+            "maxParameters": 0,
+        })));
+        let hints = check_metrics(&tree, SOURCE, &limits);
+        assert_eq!(hints.len(), 1);
+        assert_eq!(
+            hints[0].message,
+            "fnPick has 11 lines (limit 10), complexity 6 (limit 5)"
+        );
+        assert!(check_metrics(&tree, SOURCE, &MetricsLimits::default()).is_empty());
+    }
+}
@@ -0,0 +1,236 @@
+use serde_json::Value;
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+use tree_sitter::Tree;
+
+use crate::line_numbers::{padded_width, MAX_LINE_NUMBER};
+use crate::statements::{line_statements, opened_block, Block};
+use crate::structure::Structure;
+
+/// Numbering of new lines from `br.autoLineNumbers`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutoLineNumbers {
+    pub enabled: bool,
+    /// Added to the last line number in the file
+    pub increment: u32,
+}
+
+impl Default for AutoLineNumbers {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            increment: 10,
+        }
+    }
+}
+
+impl AutoLineNumbers {
+    pub fn from_settings(settings: Option<&Value>) -> Self {
+        let defaults = Self::default();
+        let Some(settings) = settings else {
+            return defaults;
+        };
+        Self {
+            enabled: settings
+                .get("enabled")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(defaults.enabled),
+            increment: settings
+                .get("increment")
+                .and_then(|v| v.as_u64())
+                .filter(|&n| n > 0)
+                .map_or(defaults.increment, |n| n as u32),
+        }
+    }
+}
+
+/// Edit inserting the matching FNEND, LOOP or END IF after a newline typed
+/// at `position`, when the line just ended opens a block that isn't closed
+/// yet. The closer goes on the line below the cursor, at the opener's
+/// indentation and in the opener's keyword case. Lines carrying line
+/// numbers are left alone, since the closer would need one too.
+pub fn block_closer_edit(tree: &Tree, source: &str, position: Position) -> Option<TextEdit> {
+    let row = (position.line as usize).checked_sub(1)?;
+    let lines: Vec<&str> = source.split('\n').collect();
+    let opener_text = lines.get(row)?.trim_end_matches('\r');
+    let cursor_line = lines.get(position.line as usize)?.trim_end_matches('\r');
+
+    // Only when the cursor sits on an otherwise empty new line
+    if !cursor_line.trim().is_empty() {
+        return None;
+    }
+    let indent: String = opener_text
+        .chars()
+        .take_while(|c| c.is_whitespace())
+        .collect();
+    if opener_text
+        .trim_start()
+        .starts_with(|c: char| c.is_ascii_digit())
+    {
+        return None;
+    }
+
+    let root = tree.root_node();
+    let mut cursor = root.walk();
+    let line = root
+        .children(&mut cursor)
+        .find(|n| n.kind() == "line" && n.start_position().row == row)?;
+    let stmt = *line_statements(line).last()?;
+    // NEXT names the loop variable, which is left to the user
+    let block = opened_block(stmt).filter(|&b| b != Block::For)?;
+    if Structure::from_root(root, source).is_closed(block, row as u32) {
+        return None;
+    }
+
+    let keyword = stmt.child(0)?.utf8_text(source.as_bytes()).ok()?;
+    let closer = if keyword.chars().any(|c| c.is_ascii_lowercase()) {
+        block.closer().to_string()
+    } else {
+        block.closer().to_ascii_uppercase()
+    };
+
+    let end = Position::new(position.line, cursor_line.len() as u32);
+    Some(TextEdit {
+        range: Range::new(end, end),
+        new_text: format!("\n{indent}{closer}"),
+    })
+}
+
+/// Edit numbering the new line after a newline typed at `position`, when
+/// the line just ended has a line number. The number is midway to the next
+/// numbered line, or the previous one plus the increment when none follows,
+/// zero-padded like the line before. Lines ending in a `!:` continuation,
+/// and gaps with no number left between their lines, get none.
+pub fn line_number_edit(
+    source: &str,
+    position: Position,
+    options: AutoLineNumbers,
+) -> Option<TextEdit> {
+    if !options.enabled {
+        return None;
+    }
+    let row = (position.line as usize).checked_sub(1)?;
+    let lines: Vec<&str> = source
+        .split('\n')
+        .map(|l| l.trim_end_matches('\r'))
+        .collect();
+    let cursor_line = *lines.get(position.line as usize)?;
+    if !cursor_line.trim().is_empty() {
+        return None;
+    }
+    let ended = lines.get(row)?;
+    if ended.trim_end().ends_with("!:") {
+        return None;
+    }
+    let (previous, text) = leading_number(ended)?;
+    let next = lines[position.line as usize + 1..]
+        .iter()
+        .find_map(|l| leading_number(l));
+
+    let value = match next {
+        Some((next, _)) if next <= previous + 1 => return None,
+        Some((next, _)) => previous + (next - previous) / 2,
+        None => previous
+            .checked_add(options.increment)
+            .filter(|&v| v <= MAX_LINE_NUMBER)?,
+    };
+    let width = padded_width(text);
+    Some(TextEdit {
+        range: Range::new(
+            Position::new(position.line, 0),
+            Position::new(position.line, cursor_line.len() as u32),
+        ),
+        new_text: format!("{value:0width$} "),
+    })
+}
+
+/// The line number a line starts with, and its text.
+fn leading_number(line: &str) -> Option<(u32, &str)> {
+    let digits = line.bytes().take_while(u8::is_ascii_digit).count();
+    let rest = &line[digits..];
+    if digits == 0 || !(rest.is_empty() || rest.starts_with(char::is_whitespace)) {
+        return None;
+    }
+    Some((line[..digits].parse().ok()?, &line[..digits]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn edit(source: &str, line: u32) -> Option<String> {
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        block_closer_edit(&tree, source, Position::new(line, 0)).map(|e| e.new_text)
+    }
+
+    #[test]
+    fn inserts_fnend_after_def() {
+        assert_eq!(edit("def fnAdd(A, B)\n\n", 1), Some("\nfnend".to_string()));
+    }
+
+    #[test]
+    fn no_closer_for_inline_def() {
+        assert_eq!(edit("def fnAdd(A, B) = A + B\n\n", 1), None);
+    }
+
+    #[test]
+    fn loop_keeps_indentation_and_case() {
+        assert_eq!(edit("  DO WHILE X\n\n", 1), Some("\n  LOOP".to_string()));
+    }
+
+    #[test]
+    fn end_if_for_multi_line_if_only() {
+        assert_eq!(edit("if a then\n\n", 1), Some("\nend if".to_string()));
+        assert_eq!(edit("if a then print 1\n\n", 1), None);
+    }
+
+    #[test]
+    fn skips_blocks_already_closed() {
+        assert_eq!(edit("do\n\nloop\n", 1), None);
+        assert_eq!(edit("def fnA\n\nfnend\n", 1), None);
+        // An inner DO still needs its own LOOP
+        assert_eq!(edit("do\n  do\n\nloop\n", 2), Some("\n  loop".to_string()));
+    }
+
+    #[test]
+    fn skips_numbered_lines() {
+        assert_eq!(edit("00100 do\n\n", 1), None);
+    }
+
+    fn numbered(source: &str, line: u32) -> Option<String> {
+        line_number_edit(source, Position::new(line, 0), AutoLineNumbers::default())
+            .map(|e| e.new_text)
+    }
+
+    #[test]
+    fn numbers_new_lines_after_the_last() {
+        assert_eq!(
+            numbered("00100 print 1\n00110 print 2\n\n", 2),
+            Some("00120 ".to_string())
+        );
+        assert_eq!(numbered("90 print 1\n", 1), Some("100 ".to_string()));
+        assert_eq!(numbered("99995 print 1\n", 1), None);
+    }
+
+    #[test]
+    fn numbers_inserted_lines_midway() {
+        let source = "00100 print 1\n  \n\n00110 print 2\n";
+        let edit = line_number_edit(source, Position::new(1, 2), AutoLineNumbers::default());
+        let edit = edit.unwrap();
+        assert_eq!(edit.new_text, "00105 ");
+        assert_eq!(edit.range.end, Position::new(1, 2));
+        assert_eq!(numbered("00100 print 1\n\n00101 print 2\n", 1), None);
+    }
+
+    #[test]
+    fn leaves_continuations_and_unnumbered_lines_alone() {
+        assert_eq!(numbered("00100 print 1, !:\n\n", 1), None);
+        assert_eq!(numbered("print 1\n\n", 1), None);
+        let off = AutoLineNumbers {
+            enabled: false,
+            ..Default::default()
+        };
+        assert!(line_number_edit("00100 print 1\n\n", Position::new(1, 0), off).is_none());
+    }
+}
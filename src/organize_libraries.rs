@@ -0,0 +1,369 @@
+use std::collections::HashSet;
+
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+use tree_sitter::{Node, Tree};
+
+use crate::extract;
+
+/// A LIBRARY statement alone on its line, naming a literal path or none.
+/// Only these are merged and moved; a computed path may depend on code
+/// before the statement.
+struct Movable<'t> {
+    line: Node<'t>,
+    /// Options and normalized path, lowercase
+    key: String,
+    /// `LIBRARY` as written
+    keyword: String,
+    /// Source between the keyword and the function list's `:`
+    header: String,
+    functions: Vec<String>,
+}
+
+/// Edits that organize a program's LIBRARY statements: statements for the
+/// same library (same options and path) are merged, imported functions the
+/// file never calls are dropped, function lists are sorted, and the result
+/// is placed after the program's leading comment lines. In numbered
+/// programs the new lines get numbers in the gap there. `None` when the
+/// statements are already organized or the numbers don't fit.
+pub fn organize_libraries(tree: &Tree, source: &str) -> Option<Vec<TextEdit>> {
+    let root = tree.root_node();
+    let mut cursor = root.walk();
+    let lines: Vec<Node> = root
+        .children(&mut cursor)
+        .filter(|c| c.kind() == "line")
+        .collect();
+    let movables: Vec<Movable> = lines
+        .iter()
+        .filter_map(|&line| movable(line, source))
+        .collect();
+    if movables.is_empty() {
+        return None;
+    }
+    let removed: HashSet<usize> = movables.iter().map(|m| m.line.id()).collect();
+
+    let mut used = HashSet::new();
+    collect_called(root, source, &mut used);
+
+    // Merge by library, keeping the first statement's spelling
+    let mut groups: Vec<(&Movable, Vec<String>)> = Vec::new();
+    for m in &movables {
+        let index = match groups.iter().position(|(g, _)| g.key == m.key) {
+            Some(i) => i,
+            None => {
+                groups.push((m, Vec::new()));
+                groups.len() - 1
+            }
+        };
+        let functions = &mut groups[index].1;
+        for name in &m.functions {
+            let lower = name.to_ascii_lowercase();
+            if used.contains(&lower) && !functions.iter().any(|f| f.eq_ignore_ascii_case(name)) {
+                functions.push(name.clone());
+            }
+        }
+    }
+    groups.retain(|(_, functions)| !functions.is_empty());
+    for (_, functions) in &mut groups {
+        functions.sort_by_key(|f| f.to_ascii_lowercase());
+    }
+    let statements: Vec<String> = groups
+        .iter()
+        .map(|(m, functions)| format!("{}{}: {}", m.keyword, m.header, functions.join(", ")))
+        .collect();
+
+    // The block goes before the first line that isn't a comment
+    let anchor = lines
+        .iter()
+        .position(|line| !removed.contains(&line.id()) && !is_comment_line(*line));
+    let insert_at = anchor.unwrap_or(lines.len());
+    let run: Vec<Node> = lines[..insert_at]
+        .iter()
+        .rev()
+        .take_while(|line| removed.contains(&line.id()))
+        .copied()
+        .collect();
+
+    let numbers = if lines
+        .iter()
+        .any(|line| line_number(*line, source).is_some())
+    {
+        let mut run_numbers: Vec<u64> = run
+            .iter()
+            .filter_map(|line| line_number(*line, source))
+            .collect();
+        run_numbers.sort_unstable();
+        let prev = lines[..insert_at]
+            .iter()
+            .filter(|line| !removed.contains(&line.id()))
+            .filter_map(|line| line_number(*line, source))
+            .next_back()
+            .unwrap_or(0);
+        let next = anchor.and_then(|i| line_number(lines[i], source));
+        Some(if run_numbers.len() >= statements.len() {
+            run_numbers.truncate(statements.len());
+            run_numbers
+        } else {
+            line_numbers_between(prev, next, statements.len())?
+        })
+    } else {
+        None
+    };
+    let width = lines
+        .iter()
+        .find_map(|line| line_number_node(*line))
+        .and_then(|n| n.utf8_text(source.as_bytes()).ok())
+        .map_or(5, |text| text.trim().len());
+
+    let mut block = String::new();
+    for (i, statement) in statements.iter().enumerate() {
+        if let Some(numbers) = &numbers {
+            block.push_str(&format!("{:0width$} ", numbers[i]));
+        }
+        block.push_str(statement);
+        block.push('\n');
+    }
+
+    if run.len() == movables.len() {
+        let current: String = run
+            .iter()
+            .rev()
+            .filter_map(|line| line.utf8_text(source.as_bytes()).ok())
+            .collect();
+        if current == block {
+            return None;
+        }
+    }
+
+    let insert_pos = match anchor {
+        Some(i) => Position::new(lines[i].start_position().row as u32, 0),
+        None => {
+            if !source.is_empty() && !source.ends_with('\n') {
+                block.insert(0, '\n');
+            }
+            let end = root.end_position();
+            Position::new(end.row as u32, end.column as u32)
+        }
+    };
+
+    let mut edits: Vec<TextEdit> = movables
+        .iter()
+        .map(|m| TextEdit {
+            range: Range::new(
+                Position::new(m.line.start_position().row as u32, 0),
+                Position::new(
+                    m.line.end_position().row as u32,
+                    m.line.end_position().column as u32,
+                ),
+            ),
+            new_text: String::new(),
+        })
+        .collect();
+    // Replace the line just before the insertion point rather than insert
+    // at the end of its deletion
+    match edits.iter_mut().find(|e| e.range.end == insert_pos) {
+        Some(edit) => edit.new_text = block,
+        None => edits.push(TextEdit {
+            range: Range::new(insert_pos, insert_pos),
+            new_text: block,
+        }),
+    }
+    Some(edits)
+}
+
+/// Whether any line holds a LIBRARY statement: a cheap test for whether
+/// [`organize_libraries`] is worth offering.
+pub fn has_library_statements(tree: &Tree) -> bool {
+    let root = tree.root_node();
+    let mut cursor = root.walk();
+    let found = root
+        .children(&mut cursor)
+        .filter(|c| c.kind() == "line")
+        .any(|line| {
+            let mut cursor = line.walk();
+            let found = line
+                .named_children(&mut cursor)
+                .any(|c| c.kind() == "library_statement");
+            found
+        });
+    found
+}
+
+fn movable<'t>(line: Node<'t>, source: &str) -> Option<Movable<'t>> {
+    let mut cursor = line.walk();
+    let mut statements = line
+        .named_children(&mut cursor)
+        .filter(|c| c.kind() != "line_number");
+    let statement = statements
+        .next()
+        .filter(|s| s.kind() == "library_statement")?;
+    if statements.next().is_some() {
+        return None;
+    }
+
+    let bytes = source.as_bytes();
+    let mut keyword = None;
+    let mut options = Vec::new();
+    let mut path = String::new();
+    let mut list = None;
+    let mut cursor = statement.walk();
+    for child in statement.children(&mut cursor) {
+        match child.kind() {
+            "statement" => keyword = Some(child),
+            "keyword" => options.push(child.utf8_text(bytes).ok()?.to_ascii_lowercase()),
+            "," => {}
+            "string_expression" => {
+                let text = literal_text(child, source)?;
+                path = extract::normalize_library_path(text);
+            }
+            "library_function_list" => list = Some(child),
+            _ => return None,
+        }
+    }
+    let keyword = keyword?;
+    let list = list?;
+
+    let mut cursor = list.walk();
+    let functions = list
+        .children(&mut cursor)
+        .filter(|c| c.kind() == "function_name")
+        .filter_map(|c| c.utf8_text(bytes).ok().map(str::to_string))
+        .collect();
+    Some(Movable {
+        line,
+        key: format!("{}|{path}", options.join(",")),
+        keyword: keyword.utf8_text(bytes).ok()?.to_string(),
+        header: source
+            .get(keyword.end_byte()..list.start_byte())?
+            .to_string(),
+        functions,
+    })
+}
+
+/// Contents of a string expression that is a single string literal.
+fn literal_text<'s>(expr: Node, source: &'s str) -> Option<&'s str> {
+    let primary = expr
+        .named_child(0)
+        .filter(|p| expr.named_child_count() == 1 && p.kind() == "string_primary_expression")?;
+    let string = primary
+        .named_child(0)
+        .filter(|s| primary.named_child_count() == 1 && s.kind() == "string")?;
+    let text = string.utf8_text(source.as_bytes()).ok()?;
+    text.get(1..text.len().checked_sub(1)?)
+}
+
+/// Lowercase names of the functions referenced outside LIBRARY statements.
+fn collect_called(node: Node, source: &str, used: &mut HashSet<String>) {
+    if node.kind() == "library_statement" {
+        return;
+    }
+    if node.kind() == "function_name" {
+        if let Ok(text) = node.utf8_text(source.as_bytes()) {
+            used.insert(text.to_ascii_lowercase());
+        }
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_called(child, source, used);
+    }
+}
+
+fn is_comment_line(line: Node) -> bool {
+    let mut cursor = line.walk();
+    let only_comments = line
+        .named_children(&mut cursor)
+        .all(|c| matches!(c.kind(), "line_number" | "comment" | "doc_comment"));
+    only_comments
+}
+
+fn line_number_node(line: Node) -> Option<Node> {
+    let mut cursor = line.walk();
+    let found = line
+        .named_children(&mut cursor)
+        .find(|c| c.kind() == "line_number");
+    found
+}
+
+fn line_number(line: Node, source: &str) -> Option<u64> {
+    line_number_node(line)?
+        .utf8_text(source.as_bytes())
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// `count` evenly spaced line numbers after `prev` and before `next`, at
+/// most 10 apart.
+fn line_numbers_between(prev: u64, next: Option<u64>, count: usize) -> Option<Vec<u64>> {
+    let count = count as u64;
+    let step = match next {
+        Some(next) => ((next.checked_sub(prev)?) / (count + 1)).min(10),
+        None => 10,
+    };
+    if step == 0 {
+        return None;
+    }
+    Some((1..=count).map(|i| prev + step * i).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn organize(source: &str) -> Option<String> {
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        let mut edits = organize_libraries(&tree, source)?;
+        let offset = |pos: Position| {
+            source
+                .split_inclusive('\n')
+                .take(pos.line as usize)
+                .map(str::len)
+                .sum::<usize>()
+                + pos.character as usize
+        };
+        edits.sort_by_key(|e| std::cmp::Reverse(offset(e.range.start)));
+        let mut result = source.to_string();
+        for edit in edits {
+            result.replace_range(
+                offset(edit.range.start)..offset(edit.range.end),
+                &edit.new_text,
+            );
+        }
+        Some(result)
+    }
+
+    #[test]
+    fn merges_prunes_and_sorts() {
+        let source = "! header\nlet x = 1\nlibrary \"utils\": fnB, fnA\nlibrary \"UTILS.brs\": fnA, fnC, fnUnused\nprint fnA + fnB + fnC\n";
+        assert_eq!(
+            organize(source).unwrap(),
+            "! header\nlibrary \"utils\": fnA, fnB, fnC\nlet x = 1\nprint fnA + fnB + fnC\n"
+        );
+    }
+
+    #[test]
+    fn numbers_new_lines_in_the_gap() {
+        let source = "00100 ! header\n00200 let x = 1\n00300 library \"utils\": fnB\n00400 library release,nofiles,\"utils\": fnA\n00500 print fnA + fnB\n";
+        assert_eq!(
+            organize(source).unwrap(),
+            "00100 ! header\n00110 library \"utils\": fnB\n00120 library release,nofiles,\"utils\": fnA\n00200 let x = 1\n00500 print fnA + fnB\n"
+        );
+    }
+
+    #[test]
+    fn organized_and_computed_paths_are_left_alone() {
+        let source = "00100 library \"utils\": fnA, fnB\n00110 library lib$: fnC\n00120 print fnA + fnB + fnC\n";
+        assert_eq!(organize(source), None);
+    }
+
+    #[test]
+    fn detects_library_statements() {
+        let mut p = parser::new_parser();
+        let with = parser::parse(&mut p, "print 1\nlibrary \"utils\": fnA\n", None).unwrap();
+        assert!(has_library_statements(&with));
+        let without = parser::parse(&mut p, "print 1\n", None).unwrap();
+        assert!(!has_library_statements(&without));
+    }
+}
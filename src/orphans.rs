@@ -0,0 +1,93 @@
+//! Programs nothing else in the workspace CHAINs to, RUNs or loads as a
+//! LIBRARY, for `br-lsp.orphanReport`. Only literal program names count,
+//! so a program reached through a name built at runtime, or started from
+//! outside BR like a menu entry point, shows up here too; the report is a
+//! list of candidates to check, not of files safe to delete.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use tower_lsp::lsp_types::Url;
+
+use crate::check::csv_escape;
+
+/// The path a file is compared by: canonical where it exists, so case and
+/// `..` differences don't hide a reference.
+fn file_key(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The `programs` no reference in `referenced` names, given as the
+/// referring file and the source file its name resolves to. A program
+/// naming itself doesn't count. Sorted by URI.
+pub fn find_orphans(programs: &[Url], referenced: &[(Url, PathBuf)]) -> Vec<Url> {
+    let mut referrers: HashMap<PathBuf, Vec<&Url>> = HashMap::new();
+    for (from, path) in referenced {
+        referrers.entry(file_key(path)).or_default().push(from);
+    }
+    let mut orphans: Vec<Url> = programs
+        .iter()
+        .filter(|uri| {
+            let Ok(path) = uri.to_file_path() else {
+                return false;
+            };
+            !referrers
+                .get(&file_key(&path))
+                .is_some_and(|from| from.iter().any(|f| f != uri))
+        })
+        .cloned()
+        .collect();
+    orphans.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    orphans
+}
+
+/// Orphans as CSV, one row per file with whether it defines DEF LIBRARY
+/// functions.
+pub fn format_csv(rows: &[(String, bool)]) -> String {
+    let mut out = String::from("file,library\n");
+    for (file, library) in rows {
+        out.push_str(&format!("{},{library}\n", csv_escape(file)));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn programs_only_they_name_are_orphans() {
+        let dir = tempfile::tempdir().unwrap();
+        let uri = |name: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, "").unwrap();
+            Url::from_file_path(path).unwrap()
+        };
+        let (menu, report, lib, old) = (
+            uri("menu.brs"),
+            uri("report.brs"),
+            uri("lib.brs"),
+            uri("old.brs"),
+        );
+        let programs = vec![old.clone(), lib.clone(), report.clone(), menu.clone()];
+        let referenced = vec![
+            (menu.clone(), dir.path().join("report.brs")),
+            (report.clone(), dir.path().join(".").join("lib.brs")),
+            (old.clone(), dir.path().join("old.brs")),
+        ];
+
+        assert_eq!(find_orphans(&programs, &referenced), vec![menu, old]);
+    }
+
+    #[test]
+    fn csv_flags_libraries() {
+        let rows = vec![
+            ("/ws/a,b.brs".to_string(), true),
+            ("/ws/c.brs".to_string(), false),
+        ];
+        assert_eq!(
+            format_csv(&rows),
+            "file,library\n\"/ws/a,b.brs\",true\n/ws/c.brs,false\n"
+        );
+    }
+}
@@ -0,0 +1,198 @@
+//! Syntax error counts of open documents. A program with hundreds of syntax
+//! errors is rarely broken line by line: it's usually the wrong dialect or
+//! not BR at all. The client is sent `br-lsp/parseHealth` when a document's
+//! count crosses one of `br-lsp.parseHealthThresholds`, up or back down, so
+//! it can say so once instead of leaving it to the inline diagnostics.
+//!
+//! Closed files the workspace scan can't read or parse at all are left out
+//! of the index; they're summarized in `br-lsp/indexParseFailures` once the
+//! scan is done, so they aren't just missing from navigation.
+
+use std::path::PathBuf;
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tower_lsp::lsp_types::notification::Notification;
+use tower_lsp::lsp_types::Url;
+use tree_sitter::Tree;
+
+/// Thresholds when the setting is absent
+pub const DEFAULT_THRESHOLDS: [usize; 2] = [50, 200];
+
+pub enum ParseHealthNotification {}
+
+impl Notification for ParseHealthNotification {
+    type Params = ParseHealthParams;
+    const METHOD: &'static str = "br-lsp/parseHealth";
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParseHealthParams {
+    pub uri: Url,
+    pub syntax_errors: usize,
+    /// Highest threshold the count has reached; `None` once it is back
+    /// under the lowest
+    pub threshold: Option<usize>,
+    /// Whether the count went up past a threshold rather than back down
+    pub rising: bool,
+}
+
+pub enum IndexParseFailuresNotification {}
+
+impl Notification for IndexParseFailuresNotification {
+    type Params = IndexParseFailuresParams;
+    const METHOD: &'static str = "br-lsp/indexParseFailures";
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexParseFailuresParams {
+    pub count: usize,
+    /// The first [`SUMMARY_PATHS`]; `br-lsp/status` lists them all
+    pub paths: Vec<String>,
+}
+
+/// How many paths the notification names
+pub const SUMMARY_PATHS: usize = 10;
+
+/// A file the workspace scan left out of the index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseFailure {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Whether a scanned file's tree holds nothing but errors, as binary files
+/// and ones in another encoding tend to.
+pub fn unparsable(tree: &Tree) -> bool {
+    let root = tree.root_node();
+    let mut cursor = root.walk();
+    let mut children = root.named_children(&mut cursor).peekable();
+    root.is_error() || (children.peek().is_some() && children.all(|c| c.is_error()))
+}
+
+/// The notification for a finished scan's failures; `None` when there are
+/// none.
+pub fn failure_summary(failures: &[ParseFailure]) -> Option<IndexParseFailuresParams> {
+    (!failures.is_empty()).then(|| IndexParseFailuresParams {
+        count: failures.len(),
+        paths: failures
+            .iter()
+            .take(SUMMARY_PATHS)
+            .map(|f| f.path.display().to_string())
+            .collect(),
+    })
+}
+
+/// Read `parseHealthThresholds`: ascending, without duplicates or zero. An
+/// empty list turns the notification off.
+pub fn thresholds_from_settings(value: Option<&Value>) -> Vec<usize> {
+    let Some(values) = value.and_then(|v| v.as_array()) else {
+        return DEFAULT_THRESHOLDS.to_vec();
+    };
+    let mut thresholds: Vec<usize> = values
+        .iter()
+        .filter_map(|v| v.as_u64())
+        .filter(|&t| t > 0)
+        .map(|t| t as usize)
+        .collect();
+    thresholds.sort_unstable();
+    thresholds.dedup();
+    thresholds
+}
+
+/// How many thresholds each open document has reached.
+#[derive(Debug, Default)]
+pub struct ParseHealth {
+    reached: DashMap<String, usize>,
+}
+
+impl ParseHealth {
+    /// Record `uri`'s latest count. Returns the notification to send when
+    /// it moved to another band of `thresholds` (sorted ascending).
+    pub fn update(
+        &self,
+        uri: &Url,
+        syntax_errors: usize,
+        thresholds: &[usize],
+    ) -> Option<ParseHealthParams> {
+        let reached = thresholds.iter().filter(|&&t| syntax_errors >= t).count();
+        let previous = if reached == 0 {
+            self.reached.remove(uri.as_str()).map(|(_, r)| r)
+        } else {
+            self.reached.insert(uri.to_string(), reached)
+        }
+        .unwrap_or(0);
+
+        (reached != previous).then(|| ParseHealthParams {
+            uri: uri.clone(),
+            syntax_errors,
+            threshold: reached.checked_sub(1).map(|i| thresholds[i]),
+            rising: reached > previous,
+        })
+    }
+
+    pub fn forget(&self, uri: &str) {
+        self.reached.remove(uri);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notifies_only_when_a_threshold_is_crossed() {
+        let health = ParseHealth::default();
+        let uri = Url::parse("file:///src/prog.brs").unwrap();
+        let thresholds = [10, 100];
+
+        assert_eq!(health.update(&uri, 3, &thresholds), None);
+        let up = health.update(&uri, 12, &thresholds).unwrap();
+        assert_eq!((up.threshold, up.rising), (Some(10), true));
+        assert_eq!(health.update(&uri, 40, &thresholds), None);
+        let up = health.update(&uri, 312, &thresholds).unwrap();
+        assert_eq!((up.syntax_errors, up.threshold), (312, Some(100)));
+        let down = health.update(&uri, 2, &thresholds).unwrap();
+        assert_eq!((down.threshold, down.rising), (None, false));
+
+        health.update(&uri, 20, &thresholds);
+        health.forget(uri.as_str());
+        assert!(health.update(&uri, 20, &thresholds).unwrap().rising);
+    }
+
+    #[test]
+    fn failure_summary_names_the_first_paths() {
+        assert_eq!(failure_summary(&[]), None);
+        let failures: Vec<ParseFailure> = (0..SUMMARY_PATHS + 2)
+            .map(|i| ParseFailure {
+                path: PathBuf::from(format!("/ws/prog{i}.brs")),
+                reason: "unreadable".to_string(),
+            })
+            .collect();
+        let summary = failure_summary(&failures).unwrap();
+        assert_eq!(summary.count, SUMMARY_PATHS + 2);
+        assert_eq!(summary.paths.len(), SUMMARY_PATHS);
+        assert_eq!(summary.paths[0], "/ws/prog0.brs");
+    }
+
+    #[test]
+    fn only_trees_of_nothing_but_errors_are_unparsable() {
+        let parse = |source: &str| {
+            crate::parser::parse(&mut crate::parser::new_parser(), source, None).unwrap()
+        };
+        assert!(!unparsable(&parse("print 1\nlet x = (\n")));
+        assert!(!unparsable(&parse("")));
+        assert!(unparsable(&parse("\u{1}\u{2} ))) ((\n")));
+    }
+
+    #[test]
+    fn thresholds_are_sorted_and_default_when_unset() {
+        assert_eq!(thresholds_from_settings(None), DEFAULT_THRESHOLDS.to_vec());
+        let value = serde_json::json!([300, 0, 25, 300]);
+        assert_eq!(thresholds_from_settings(Some(&value)), vec![25, 300]);
+        assert!(thresholds_from_settings(Some(&serde_json::json!([]))).is_empty());
+    }
+}
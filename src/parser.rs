@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use std::sync::{Arc, LazyLock, Mutex};
 
-use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, Position, Range};
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Position, Range};
 use tree_sitter::{Language, Node, Parser, Point, Query, QueryCursor, StreamingIterator, Tree};
 
 static QUERY_CACHE: LazyLock<Mutex<HashMap<String, Arc<Query>>>> =
@@ -19,6 +19,13 @@ pub fn parse(parser: &mut Parser, source: &str, old_tree: Option<&Tree>) -> Opti
     parser.parse(source, old_tree)
 }
 
+/// Full parse with a newly constructed parser, for recovering a document
+/// whose last parse produced no tree. Avoids any state left behind in the
+/// shared parser by an earlier failed or cancelled parse.
+pub fn parse_fresh(source: &str) -> Option<Tree> {
+    new_parser().parse(source, None)
+}
+
 pub fn node_at_position(tree: &Tree, row: usize, col: usize) -> Option<Node> {
     let point = Point::new(row, col);
     tree.root_node()
@@ -78,13 +85,14 @@ pub struct CallContext {
 /// produces ERROR nodes (e.g. unbalanced parentheses while typing).
 ///
 /// Scans backward from the cursor position to find the unmatched opening `(`
-/// and extracts the function name preceding it.
+/// and extracts the function name preceding it. Parentheses and commas in
+/// string literals and comments are skipped.
 pub fn find_function_call_context(source: &str, row: usize, col: usize) -> Option<CallContext> {
     // Convert (row, col) to byte offset
     let offset = source
-        .lines()
+        .split_inclusive('\n')
         .take(row)
-        .map(|line| line.len() + 1) // +1 for newline
+        .map(str::len)
         .sum::<usize>()
         + col;
 
@@ -93,32 +101,20 @@ pub fn find_function_call_context(source: &str, row: usize, col: usize) -> Optio
     }
 
     let bytes = source.as_bytes();
+    let is_code = code_mask(&bytes[..offset]);
     let mut depth: i32 = 0;
     let mut comma_count: u32 = 0;
-    let mut in_string = false;
     let mut i = offset;
 
     // Scan backward from cursor
     while i > 0 {
         i -= 1;
-        let ch = bytes[i] as char;
-
-        if in_string {
-            if ch == '"' {
-                // Check for BR escaped quote ""
-                if i > 0 && bytes[i - 1] == b'"' {
-                    i -= 1; // skip the escaped quote
-                } else {
-                    in_string = false;
-                }
-            }
+        if !is_code[i] {
             continue;
         }
-
-        match ch {
-            '"' => in_string = true,
-            ')' => depth += 1,
-            '(' => {
+        match bytes[i] {
+            b')' => depth += 1,
+            b'(' => {
                 depth -= 1;
                 if depth < 0 {
                     // Found the unmatched opening paren — extract function name
@@ -142,7 +138,7 @@ pub fn find_function_call_context(source: &str, row: usize, col: usize) -> Optio
                     });
                 }
             }
-            ',' if depth == 0 => comma_count += 1,
+            b',' if depth == 0 => comma_count += 1,
             _ => {}
         }
     }
@@ -150,7 +146,56 @@ pub fn find_function_call_context(source: &str, row: usize, col: usize) -> Optio
     None
 }
 
+/// Which bytes of `text` are code rather than part of a string literal or
+/// comment. Strings are quoted with `"` or `'`, a doubled quote standing for
+/// one; a string left open (as while typing) ends with its line. `!` starts
+/// a comment, except in the `!:` statement separator.
+fn code_mask(text: &[u8]) -> Vec<bool> {
+    let mut mask = Vec::with_capacity(text.len());
+    let mut quote: Option<u8> = None;
+    let mut comment = false;
+    let mut i = 0;
+    while i < text.len() {
+        let b = text[i];
+        i += 1;
+        if b == b'\n' {
+            quote = None;
+            comment = false;
+            mask.push(true);
+            continue;
+        }
+        if comment {
+            mask.push(false);
+            continue;
+        }
+        match quote {
+            Some(q) => {
+                mask.push(false);
+                if b == q {
+                    if text.get(i) == Some(&q) {
+                        mask.push(false);
+                        i += 1;
+                    } else {
+                        quote = None;
+                    }
+                }
+            }
+            None => {
+                match b {
+                    b'"' | b'\'' => quote = Some(b),
+                    b'!' if text.get(i) != Some(&b':') => comment = true,
+                    _ => {}
+                }
+                mask.push(quote.is_none() && !comment);
+            }
+        }
+    }
+    mask
+}
+
+/// The nodes the diagnostic checks read, each list in source order.
 pub struct DiagnosticNodes<'tree> {
+    pub root: Node<'tree>,
     pub def_statements: Vec<Node<'tree>>,
     pub fnend_statements: Vec<Node<'tree>>,
     pub end_def_statements: Vec<Node<'tree>>,
@@ -186,13 +231,15 @@ static DIAGNOSTIC_QUERY: LazyLock<Query> = LazyLock::new(|| {
          (parameter (numeric_parameter (numberreference name: (numberidentifier) @node)))
          (parameter (string_parameter (stringreference name: (stringidentifier) @node)))
          (parameter (number_array_parameter (numberarray name: (numberidentifier) @node)))
-         (parameter (string_array_parameter (stringarray name: (stringidentifier) @node)))",
+         (parameter (string_array_parameter (stringarray name: (stringidentifier) @node)))
+         (ERROR) @node",
     )
     .expect("failed to compile diagnostic query")
 });
 
 pub fn collect_diagnostic_nodes<'tree>(tree: &'tree Tree, source: &str) -> DiagnosticNodes<'tree> {
     let mut nodes = DiagnosticNodes {
+        root: tree.root_node(),
         def_statements: Vec::new(),
         fnend_statements: Vec::new(),
         end_def_statements: Vec::new(),
@@ -220,13 +267,56 @@ pub fn collect_diagnostic_nodes<'tree>(tree: &'tree Tree, source: &str) -> Diagn
             9..=12 => nodes.var_ref_names.push(node),
             13..=16 => nodes.dim_var_ref_names.push(node),
             17..=20 => nodes.param_ident_names.push(node),
+            21 => {
+                for (name, _) in misparsed_dim_entries(node, source) {
+                    nodes.var_ref_names.push(name);
+                    nodes.dim_var_ref_names.push(name);
+                }
+            }
             _ => {}
         }
     }
+    // Misparsed DIM entries were added out of order
+    nodes.var_ref_names.sort_by_key(|n| n.start_byte());
+    nodes.dim_var_ref_names.sort_by_key(|n| n.start_byte());
 
     nodes
 }
 
+/// Entries of a DIM after a `:` or `!:` separator, which the grammar reads
+/// as a LET of a variable named `dim` followed by an ERROR holding the
+/// entries. Each entry is its name and its last node, from the ERROR;
+/// empty for any other ERROR.
+pub fn misparsed_dim_entries<'tree>(
+    error: Node<'tree>,
+    source: &str,
+) -> Vec<(Node<'tree>, Node<'tree>)> {
+    let bytes = source.as_bytes();
+    let follows_dim = error.prev_sibling().is_some_and(|prev| {
+        prev.kind() == "let_statement"
+            && prev
+                .utf8_text(bytes)
+                .is_ok_and(|t| t.eq_ignore_ascii_case("dim"))
+            && prev.prev_sibling().is_some_and(is_statement_separator)
+    });
+    if !error.is_error() || !follows_dim {
+        return Vec::new();
+    }
+    let mut cursor = error.walk();
+    let children: Vec<Node> = error.children(&mut cursor).collect();
+    children
+        .split(|c| c.kind() == ",")
+        .filter_map(|entry| {
+            let (first, last) = (*entry.first()?, *entry.last()?);
+            let name = first.utf8_text(bytes).ok()?;
+            let stem = name.strip_suffix('$').unwrap_or(name);
+            let is_name = stem.starts_with(|c: char| c.is_ascii_alphabetic())
+                && stem.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            is_name.then_some((first, last))
+        })
+        .collect()
+}
+
 pub fn collect_diagnostics(tree: &Tree, source: &str) -> Vec<Diagnostic> {
     let mut diagnostics = Vec::new();
     collect_errors(tree.root_node(), source, &mut diagnostics);
@@ -235,18 +325,21 @@ pub fn collect_diagnostics(tree: &Tree, source: &str) -> Vec<Diagnostic> {
 
 fn collect_errors(node: Node, source: &str, diagnostics: &mut Vec<Diagnostic>) {
     if node.is_error() {
-        let text = node
-            .utf8_text(source.as_bytes())
-            .unwrap_or("")
-            .chars()
-            .take(50)
-            .collect::<String>();
-        diagnostics.push(Diagnostic {
-            range: node_range(node),
-            severity: Some(DiagnosticSeverity::ERROR),
-            message: format!("Syntax error: unexpected `{text}`"),
-            ..Default::default()
-        });
+        for (start, end) in error_segments(node) {
+            let text = source
+                .get(start.start_byte()..end.end_byte())
+                .unwrap_or("")
+                .chars()
+                .take(50)
+                .collect::<String>();
+            diagnostics.push(Diagnostic {
+                range: Range::new(node_range(start).start, node_range(end).end),
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String("syntax-error".to_string())),
+                message: format!("Syntax error: unexpected `{text}`"),
+                ..Default::default()
+            });
+        }
         return;
     }
 
@@ -255,6 +348,7 @@ fn collect_errors(node: Node, source: &str, diagnostics: &mut Vec<Diagnostic>) {
         diagnostics.push(Diagnostic {
             range: node_range(node),
             severity: Some(DiagnosticSeverity::ERROR),
+            code: Some(NumberOrString::String("syntax-error".to_string())),
             message: format!("Syntax error: missing `{kind}`"),
             ..Default::default()
         });
@@ -271,6 +365,64 @@ fn collect_errors(node: Node, source: &str, diagnostics: &mut Vec<Diagnostic>) {
     }
 }
 
+/// Whether `node` separates statements on a line: `:` or the `!:`
+/// continuation.
+pub fn is_statement_separator(node: Node) -> bool {
+    matches!(node.kind(), "statement_separator" | "continuation")
+}
+
+/// The parts of an ERROR node to report, as first and last node. An error
+/// that swallowed several statements of a line is reported only on the
+/// statements that don't parse on their own, so a mistake in the second
+/// statement doesn't mark the first.
+fn error_segments(error: Node) -> Vec<(Node, Node)> {
+    let mut cursor = error.walk();
+    let children: Vec<Node> = error.children(&mut cursor).collect();
+    if !children.iter().any(|c| is_statement_separator(*c)) {
+        return vec![(error, error)];
+    }
+
+    children
+        .split(|c| is_statement_separator(*c))
+        .filter_map(|segment| {
+            let segment: Vec<Node> = segment
+                .iter()
+                .copied()
+                .filter(|c| c.kind() != "line_number")
+                .collect();
+            let clean = segment.iter().all(|c| {
+                c.is_named()
+                    && !c.has_error()
+                    && (c.kind().ends_with("_statement") || c.kind() == "comment")
+            });
+            if clean {
+                return None;
+            }
+            Some((*segment.first()?, *segment.last()?))
+        })
+        .collect()
+}
+
+/// The source text of `node`, or "" if it isn't valid UTF-8.
+pub fn node_text<'a>(node: Node, source: &'a str) -> &'a str {
+    node.utf8_text(source.as_bytes()).unwrap_or("")
+}
+
+/// The first node of `kind` at or under `node`, depth first.
+pub fn find_descendant<'tree>(node: Node<'tree>, kind: &str) -> Option<Node<'tree>> {
+    let mut stack = vec![node];
+    while let Some(n) = stack.pop() {
+        if n.kind() == kind {
+            return Some(n);
+        }
+        let mut cursor = n.walk();
+        for child in n.children(&mut cursor) {
+            stack.push(child);
+        }
+    }
+    None
+}
+
 pub fn node_range(node: Node) -> Range {
     let start = node.start_position();
     let end = node.end_position();
@@ -290,6 +442,15 @@ pub fn node_range(node: Node) -> Range {
 mod tests {
     use super::*;
 
+    #[test]
+    fn parse_fresh_matches_shared_parser() {
+        let source = "let x = 1\nprint x\n";
+        let mut parser = new_parser();
+        let shared = parse(&mut parser, source, None).unwrap();
+        let fresh = parse_fresh(source).unwrap();
+        assert_eq!(fresh.root_node().to_sexp(), shared.root_node().to_sexp());
+    }
+
     #[test]
     fn valid_parse_no_errors() {
         let mut parser = new_parser();
@@ -310,6 +471,23 @@ mod tests {
         assert_eq!(diags[0].severity, Some(DiagnosticSeverity::ERROR));
     }
 
+    #[test]
+    fn parse_error_marks_only_its_statement() {
+        let mut parser = new_parser();
+        for source in [
+            "00010 let x = 1 : foo bar baz\n",
+            "00010 let x = 1 !: let y = (\n",
+        ] {
+            let tree = parse(&mut parser, source, None).unwrap();
+            let diags = collect_diagnostics(&tree, source);
+            assert!(!diags.is_empty(), "{source}");
+            let second = source.find(':').unwrap() as u32;
+            for diag in &diags {
+                assert!(diag.range.start.character > second, "{source}: {diag:?}");
+            }
+        }
+    }
+
     #[test]
     fn empty_source() {
         let mut parser = new_parser();
@@ -378,6 +556,41 @@ mod tests {
         assert!(find_function_call_context(source, 0, source.len()).is_none());
     }
 
+    #[test]
+    fn call_context_commas_and_parens_in_strings() {
+        let source = "let x = fnFoo(\"a,b\", X";
+        let ctx = find_function_call_context(source, 0, source.len()).unwrap();
+        assert_eq!(ctx.name, "fnFoo");
+        assert_eq!(ctx.active_param, 1);
+
+        let source = "let x = fnFoo('a,(b', ";
+        let ctx = find_function_call_context(source, 0, source.len()).unwrap();
+        assert_eq!(ctx.name, "fnFoo");
+        assert_eq!(ctx.active_param, 1);
+    }
+
+    #[test]
+    fn call_context_inside_unterminated_string() {
+        let source = "let x = fnFoo(A, \"b,(c";
+        let ctx = find_function_call_context(source, 0, source.len()).unwrap();
+        assert_eq!(ctx.name, "fnFoo");
+        assert_eq!(ctx.active_param, 1);
+    }
+
+    #[test]
+    fn call_context_ignores_comments() {
+        let source = "let x = 1 ! see fnBar(A,\nB, ";
+        assert!(find_function_call_context(source, 1, 3).is_none());
+    }
+
+    #[test]
+    fn call_context_after_statement_separator() {
+        let source = "print X !: let Y = fnFoo(A, ";
+        let ctx = find_function_call_context(source, 0, source.len()).unwrap();
+        assert_eq!(ctx.name, "fnFoo");
+        assert_eq!(ctx.active_param, 1);
+    }
+
     #[test]
     fn call_context_multiline() {
         let source = "let x = fnFoo(A,\nB, ";
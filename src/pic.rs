@@ -0,0 +1,368 @@
+//! PIC format masks, as in `FORM PIC(ZZZ,ZZ#.##)` and layout field specs:
+//! validation, and rendering sample numbers for hover.
+
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Position, Range};
+
+/// Significant digits a BR number holds.
+const MAX_DIGITS: usize = 15;
+
+/// Values rendered in a PIC hover.
+const SAMPLE_VALUES: &[f64] = &[1234.56, -1234.56, 0.0];
+
+/// A problem with a mask, at a char range within it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PicProblem {
+    pub start: usize,
+    pub end: usize,
+    pub severity: DiagnosticSeverity,
+    pub message: String,
+}
+
+impl PicProblem {
+    /// The problem as a diagnostic, for a mask starting at `mask_start`.
+    pub fn to_diagnostic(&self, mask_start: Position) -> Diagnostic {
+        let at =
+            |offset: usize| Position::new(mask_start.line, mask_start.character + offset as u32);
+        Diagnostic {
+            range: Range::new(at(self.start), at(self.end)),
+            severity: Some(self.severity),
+            code: Some(NumberOrString::String("invalid-pic-mask".to_string())),
+            message: self.message.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// The mask inside a `PIC(...)` spec and its byte offset in `spec`.
+pub fn mask_of(spec: &str) -> Option<(usize, &str)> {
+    let open = spec.find('(')?;
+    if !spec[..open].trim().eq_ignore_ascii_case("pic") {
+        return None;
+    }
+    let close = spec.rfind(')').filter(|&c| c > open).unwrap_or(spec.len());
+    Some((open + 1, &spec[open + 1..close]))
+}
+
+/// One mask character, classified.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Slot {
+    /// `#` (zero), `Z` (blank) or `*` (asterisk): what shows for a leading zero
+    Digit(char),
+    /// `$`, `+` or `-` repeated: a digit position that shows the symbol
+    /// left of the number when unused
+    Floating(char),
+    /// `$`, `+` or `-` on its own
+    Symbol(char),
+    /// `,` `.` `B` `/` `:`
+    Insert(char),
+    /// Trailing `CR` or `DB`, shown for negative numbers
+    Credit(&'static str),
+}
+
+fn slots(mask: &str) -> Result<Vec<Slot>, PicProblem> {
+    let chars: Vec<char> = mask.chars().map(|c| c.to_ascii_uppercase()).collect();
+    let mut slots = Vec::with_capacity(chars.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '#' | 'Z' | '*' => slots.push(Slot::Digit(c)),
+            '$' | '+' | '-' => {
+                let run = chars[i..].iter().take_while(|&&r| r == c).count();
+                let slot = if run > 1 {
+                    Slot::Floating(c)
+                } else {
+                    Slot::Symbol(c)
+                };
+                slots.extend(std::iter::repeat_n(slot, run));
+                i += run;
+                continue;
+            }
+            ',' | '.' | 'B' | '/' | ':' => slots.push(Slot::Insert(c)),
+            'C' | 'D' if i + 2 == chars.len() => {
+                let credit = match (c, chars[i + 1]) {
+                    ('C', 'R') => "CR",
+                    ('D', 'B') => "DB",
+                    _ => return Err(invalid_char(i + 1, mask.chars().nth(i + 1).unwrap_or(c))),
+                };
+                slots.push(Slot::Credit(credit));
+                i += 2;
+                continue;
+            }
+            _ => return Err(invalid_char(i, mask.chars().nth(i).unwrap_or(c))),
+        }
+        i += 1;
+    }
+    Ok(slots)
+}
+
+fn invalid_char(index: usize, c: char) -> PicProblem {
+    PicProblem {
+        start: index,
+        end: index + 1,
+        severity: DiagnosticSeverity::ERROR,
+        message: format!("`{c}` is not a PIC mask character"),
+    }
+}
+
+/// Digit positions the slots hold. A floating run's leftmost position is
+/// kept for its symbol.
+fn digit_capacity(slots: &[Slot]) -> usize {
+    let mut count = 0;
+    let mut previous = None;
+    for slot in slots {
+        match slot {
+            Slot::Digit(_) => count += 1,
+            Slot::Floating(c) if previous == Some(Slot::Floating(*c)) => count += 1,
+            _ => {}
+        }
+        previous = Some(*slot);
+    }
+    count
+}
+
+/// Problems with a mask: characters PIC doesn't know, a second decimal
+/// point, no digit positions, or more digits than a BR number holds.
+pub fn validate(mask: &str) -> Vec<PicProblem> {
+    let width = mask.chars().count();
+    if mask.trim().is_empty() {
+        return vec![PicProblem {
+            start: 0,
+            end: width,
+            severity: DiagnosticSeverity::ERROR,
+            message: "PIC mask is empty".to_string(),
+        }];
+    }
+    let slots = match slots(mask) {
+        Ok(slots) => slots,
+        Err(problem) => return vec![problem],
+    };
+
+    let mut problems = Vec::new();
+    if let Some(second) = mask.chars().enumerate().filter(|(_, c)| *c == '.').nth(1) {
+        problems.push(PicProblem {
+            start: second.0,
+            end: second.0 + 1,
+            severity: DiagnosticSeverity::ERROR,
+            message: "PIC mask has more than one decimal point".to_string(),
+        });
+    }
+    let digits = digit_capacity(&slots);
+    if digits == 0 {
+        problems.push(PicProblem {
+            start: 0,
+            end: width,
+            severity: DiagnosticSeverity::ERROR,
+            message: "PIC mask has no digit positions".to_string(),
+        });
+    } else if digits > MAX_DIGITS {
+        problems.push(PicProblem {
+            start: 0,
+            end: width,
+            severity: DiagnosticSeverity::WARNING,
+            message: format!(
+                "PIC mask has {digits} digit positions, more than the {MAX_DIGITS} significant digits a BR number holds"
+            ),
+        });
+    }
+    problems
+}
+
+/// `value` formatted by `mask`, or `None` for a mask with errors. Numbers
+/// too wide for the mask show as asterisks.
+pub fn render(mask: &str, value: f64) -> Option<String> {
+    if validate(mask)
+        .iter()
+        .any(|p| p.severity == DiagnosticSeverity::ERROR)
+    {
+        return None;
+    }
+    let slots = slots(mask).ok()?;
+    let width = mask.chars().count();
+    let point = slots
+        .iter()
+        .position(|s| *s == Slot::Insert('.'))
+        .unwrap_or(slots.len());
+    let (int_slots, frac_slots) = slots.split_at(point);
+    let decimals = digit_capacity(frac_slots);
+
+    let negative = value < 0.0;
+    let scaled = (value.abs() * 10f64.powi(decimals as i32)).round();
+    let all_digits = format!("{scaled:0width$.0}", width = decimals + 1);
+    let (int_digits, frac_digits) = all_digits.split_at(all_digits.len() - decimals);
+    let int_digits = int_digits.trim_start_matches('0');
+    if int_digits.len() > digit_capacity(int_slots) {
+        return Some("*".repeat(width));
+    }
+
+    let sign = |symbol: char| match symbol {
+        '-' if negative => '-',
+        '-' => ' ',
+        '+' if negative => '-',
+        '+' => '+',
+        _ => symbol,
+    };
+
+    // Integer part, right to left
+    let mut remaining = int_digits.chars().rev().peekable();
+    let mut fill = ' ';
+    let mut symbol_placed = false;
+    let mut int_out = Vec::with_capacity(int_slots.len());
+    for slot in int_slots.iter().rev() {
+        let c = match *slot {
+            Slot::Digit(d) => match remaining.next() {
+                Some(digit) => digit,
+                None => {
+                    fill = match d {
+                        '#' => ',',
+                        'Z' => ' ',
+                        _ => '*',
+                    };
+                    if d == '#' {
+                        '0'
+                    } else {
+                        fill
+                    }
+                }
+            },
+            Slot::Floating(symbol) => match remaining.next() {
+                Some(digit) => digit,
+                None if !symbol_placed => {
+                    symbol_placed = true;
+                    fill = ' ';
+                    sign(symbol)
+                }
+                None => ' ',
+            },
+            Slot::Symbol(symbol) => sign(symbol),
+            Slot::Insert(c) if remaining.peek().is_some() => {
+                if c == 'B' {
+                    ' '
+                } else {
+                    c
+                }
+            }
+            Slot::Insert(c) => {
+                if fill == ',' {
+                    if c == 'B' {
+                        ' '
+                    } else {
+                        c
+                    }
+                } else {
+                    fill
+                }
+            }
+            Slot::Credit(_) => ' ',
+        };
+        int_out.push(c);
+    }
+    int_out.reverse();
+
+    // Fraction, left to right
+    let mut digits = frac_digits.chars();
+    let mut out: String = int_out.into_iter().collect();
+    for slot in frac_slots {
+        match *slot {
+            Slot::Digit(_) | Slot::Floating(_) => out.push(digits.next().unwrap_or('0')),
+            Slot::Symbol(symbol) => out.push(sign(symbol)),
+            Slot::Insert('B') => out.push(' '),
+            Slot::Insert(c) => out.push(c),
+            Slot::Credit(credit) if negative => out.push_str(credit),
+            Slot::Credit(_) => out.push_str("  "),
+        }
+    }
+    Some(out)
+}
+
+/// Hover text for a PIC mask: its width and digits, and how sample numbers
+/// display, or the first problem with it.
+pub fn format_hover(mask: &str) -> String {
+    let mut md = format!("**PIC({mask})**");
+    if let Some(problem) = validate(mask)
+        .into_iter()
+        .find(|p| p.severity == DiagnosticSeverity::ERROR)
+    {
+        md.push_str(&format!("\n\n{}", problem.message));
+        return md;
+    }
+    let Ok(slots) = slots(mask) else {
+        return md;
+    };
+    let point = slots
+        .iter()
+        .position(|s| *s == Slot::Insert('.'))
+        .unwrap_or(slots.len());
+    md.push_str(&format!(
+        " \u{2014} {} characters, {} digits ({} after the decimal point)",
+        mask.chars().count(),
+        digit_capacity(&slots),
+        digit_capacity(&slots[point..])
+    ));
+    md.push_str("\n\n| Value | Displays |\n|---|---|\n");
+    for &value in SAMPLE_VALUES {
+        if let Some(shown) = render(mask, value) {
+            md.push_str(&format!("| `{value}` | `{shown}` |\n"));
+        }
+    }
+    md
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_suppression_and_insertion() {
+        assert_eq!(render("ZZZ,ZZ#.##", 1234.56).unwrap(), "  1,234.56");
+        assert_eq!(render("ZZZ,ZZ#.##", 0.5).unwrap(), "      0.50");
+        assert_eq!(render("***,**#.##", 42.0).unwrap(), "*****42.00");
+        assert_eq!(render("###,###", 1234.0).unwrap(), "001,234");
+        assert_eq!(render("ZZ#", 12345.0).unwrap(), "***");
+    }
+
+    #[test]
+    fn renders_floating_symbols_and_signs() {
+        assert_eq!(render("$$$,$$#.##", 1234.5).unwrap(), " $1,234.50");
+        assert_eq!(render("$$$,$$#.##", 12.0).unwrap(), "    $12.00");
+        assert_eq!(render("---,--#.##", -12.0).unwrap(), "    -12.00");
+        assert_eq!(render("---,--#.##", 12.0).unwrap(), "     12.00");
+        assert_eq!(render("ZZ#.##CR", -5.0).unwrap(), "  5.00CR");
+        assert_eq!(render("ZZ#.##CR", 5.0).unwrap(), "  5.00  ");
+        assert_eq!(render("+ZZ#", 7.0).unwrap(), "+  7");
+    }
+
+    #[test]
+    fn validates_masks() {
+        assert!(validate("zzz,zz#.##").is_empty());
+        let problems = validate("ZZQ.##");
+        assert_eq!(problems.len(), 1);
+        assert_eq!((problems[0].start, problems[0].end), (2, 3));
+        assert_eq!(problems[0].message, "`Q` is not a PIC mask character");
+        assert_eq!(
+            validate("ZZ.#.#")[0].message,
+            "PIC mask has more than one decimal point"
+        );
+        assert_eq!(validate(",.")[0].message, "PIC mask has no digit positions");
+        assert_eq!(validate("")[0].message, "PIC mask is empty");
+        let wide = validate(&"#".repeat(16));
+        assert_eq!(wide[0].severity, DiagnosticSeverity::WARNING);
+        assert!(render("ZZQ", 1.0).is_none());
+    }
+
+    #[test]
+    fn finds_mask_in_spec() {
+        assert_eq!(mask_of("pic(ZZ#.##)"), Some((4, "ZZ#.##")));
+        assert_eq!(mask_of("PIC (ZZ#"), Some((5, "ZZ#")));
+        assert_eq!(mask_of("C 10"), None);
+    }
+
+    #[test]
+    fn hover_shows_samples() {
+        let md = format_hover("ZZZ,ZZ#.##");
+        assert!(md.starts_with("**PIC(ZZZ,ZZ#.##)** \u{2014} 10 characters, 8 digits (2 after"));
+        assert!(md.contains("| `1234.56` | `  1,234.56` |"));
+        assert!(md.contains("| `0` | `      0.00` |"));
+        assert!(format_hover("ZQ").contains("`Q` is not a PIC mask character"));
+    }
+}
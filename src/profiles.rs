@@ -0,0 +1,116 @@
+use crate::backend::DiagnosticsConfig;
+
+/// A named set of diagnostic rules, from `br.diagnostics.profile`, so a
+/// large old codebase can start with the essentials and tighten up later.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LintProfile {
+    /// Only syntax errors and DEFs missing their FNEND
+    Legacy,
+    /// The individual `br.diagnostics.*` switches decide
+    #[default]
+    Default,
+    /// Every rule, including the naming style rules
+    Strict,
+}
+
+impl LintProfile {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "legacy" => Some(Self::Legacy),
+            "default" => Some(Self::Default),
+            "strict" => Some(Self::Strict),
+            _ => None,
+        }
+    }
+
+    /// Map the profile onto the per-rule switches, after those have been
+    /// read from the settings. `Legacy` and `Strict` take precedence over
+    /// the individual switches; the keyword case rule still needs
+    /// `br-lsp.style.keywordCase` to pick a convention.
+    pub fn apply(self, config: &mut DiagnosticsConfig) {
+        config.profile = self;
+        match self {
+            Self::Default => {}
+            Self::Legacy => {
+                config.syntax = true;
+                config.missing_fnend = true;
+                config.functions = false;
+                config.undefined_functions = false;
+                config.unused_variables = false;
+                config.unused_parameters = false;
+                config.file_numbers = false;
+                config.value_param_assignment = false;
+                config.error_conditions = false;
+                config.pic_masks = false;
+                config.line_numbers = false;
+                config.shadowing = false;
+                config.br_version = None;
+                config.keyword_case = None;
+                config.dialects = Default::default();
+                config.naming = Default::default();
+            }
+            Self::Strict => {
+                config.syntax = true;
+                config.missing_fnend = true;
+                config.functions = true;
+                config.undefined_functions = true;
+                config.unused_variables = true;
+                config.unused_parameters = true;
+                config.file_numbers = true;
+                config.value_param_assignment = true;
+                config.error_conditions = true;
+                config.pic_masks = true;
+                config.line_numbers = true;
+                config.shadowing = true;
+                config.naming = std::mem::take(&mut config.naming).with_all_rules();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_profile_names() {
+        assert_eq!(LintProfile::parse("Legacy"), Some(LintProfile::Legacy));
+        assert_eq!(LintProfile::parse("strict"), Some(LintProfile::Strict));
+        assert_eq!(LintProfile::parse("lenient"), None);
+    }
+
+    #[test]
+    fn legacy_keeps_only_syntax_and_missing_fnend() {
+        let mut config = DiagnosticsConfig {
+            keyword_case: Some(crate::style::KeywordCase::Upper),
+            ..Default::default()
+        };
+        LintProfile::Legacy.apply(&mut config);
+        assert!(config.syntax && config.missing_fnend);
+        assert!(!config.functions && !config.unused_variables && !config.file_numbers);
+        assert!(config.keyword_case.is_none());
+        assert_eq!(config.profile, LintProfile::Legacy);
+    }
+
+    #[test]
+    fn strict_overrides_disabled_rules() {
+        let mut config = DiagnosticsConfig {
+            unused_variables: false,
+            ..Default::default()
+        };
+        LintProfile::Strict.apply(&mut config);
+        assert!(config.unused_variables);
+        assert!(config.naming.is_enabled());
+    }
+
+    #[test]
+    fn default_leaves_switches_alone() {
+        let mut config = DiagnosticsConfig {
+            file_numbers: false,
+            ..Default::default()
+        };
+        LintProfile::Default.apply(&mut config);
+        assert!(!config.file_numbers);
+        assert!(!config.missing_fnend);
+    }
+}
@@ -0,0 +1,79 @@
+//! The diagnostics last published for each document, kept as a hash so
+//! that a republish after a configuration or index change only sends the
+//! files whose diagnostics actually changed, and the client's gutters don't
+//! redraw for the rest. Every publication has to go through here: one sent
+//! around it leaves the record out of date, and a later identical set would
+//! wrongly be skipped.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use dashmap::DashMap;
+use tower_lsp::lsp_types::{Diagnostic, Url};
+use tower_lsp::Client;
+
+#[derive(Debug, Default)]
+pub struct PublishedDiagnostics {
+    /// Document URI -> hash of the diagnostics last sent for it
+    hashes: DashMap<String, u64>,
+}
+
+impl PublishedDiagnostics {
+    /// Record `diagnostics` as published for `uri`. False when they're the
+    /// set already published, which needn't be sent again.
+    pub fn record(&self, uri: &Url, diagnostics: &[Diagnostic]) -> bool {
+        let hash = hash(diagnostics);
+        self.hashes.insert(uri.to_string(), hash) != Some(hash)
+    }
+
+    /// Forget a closed or deleted document, after clearing its diagnostics.
+    pub fn forget(&self, uri: &str) {
+        self.hashes.remove(uri);
+    }
+
+    /// Publish `diagnostics` for `uri` unless they're what was last sent.
+    pub async fn publish(&self, client: &Client, uri: Url, diagnostics: Vec<Diagnostic>) {
+        if self.record(&uri, &diagnostics) {
+            client.publish_diagnostics(uri, diagnostics, None).await;
+        }
+    }
+}
+
+fn hash(diagnostics: &[Diagnostic]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_vec(diagnostics)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::{Position, Range};
+
+    fn diagnostic(message: &str) -> Diagnostic {
+        Diagnostic {
+            range: Range::new(Position::new(0, 0), Position::new(0, 1)),
+            message: message.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn only_changed_sets_need_sending() {
+        let published = PublishedDiagnostics::default();
+        let uri = Url::parse("file:///ws/prog.brs").unwrap();
+        let other = Url::parse("file:///ws/other.brs").unwrap();
+
+        assert!(published.record(&uri, &[diagnostic("a")]));
+        assert!(!published.record(&uri, &[diagnostic("a")]));
+        assert!(published.record(&other, &[diagnostic("a")]));
+        assert!(published.record(&uri, &[diagnostic("b")]));
+        assert!(published.record(&uri, &[]));
+        assert!(!published.record(&uri, &[]));
+
+        published.forget(uri.as_str());
+        assert!(published.record(&uri, &[]));
+    }
+}
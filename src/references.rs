@@ -1,6 +1,9 @@
-use tower_lsp::lsp_types::Range;
+use std::collections::HashSet;
+
+use tower_lsp::lsp_types::{DocumentHighlight, DocumentHighlightKind, Range};
 use tree_sitter::Tree;
 
+use crate::extract;
 use crate::parser::{node_at_position, run_query, QueryResult};
 
 const SUPPORTED_KINDS: &[&str] = &[
@@ -63,6 +66,44 @@ pub fn find_references(tree: &Tree, source: &str, line: usize, character: usize)
     }
 }
 
+/// References to the symbol at (line, character) as document highlights.
+/// A function's DEF is a write and its calls (and LIBRARY imports) are
+/// reads; other symbols are plain text.
+pub fn find_highlights(
+    tree: &Tree,
+    source: &str,
+    line: usize,
+    character: usize,
+) -> Vec<DocumentHighlight> {
+    let refs = find_references(tree, source, line, character);
+    let is_function = resolve_function_name_at(tree, source, line, character).is_some();
+    let def_sites: Vec<Range> = if is_function {
+        extract::extract_definitions(tree, source)
+            .into_iter()
+            .filter(|def| !def.is_import_only)
+            .map(|def| def.selection_range)
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    refs.into_iter()
+        .map(|range| {
+            let kind = if def_sites.contains(&range) {
+                DocumentHighlightKind::WRITE
+            } else if is_function {
+                DocumentHighlightKind::READ
+            } else {
+                DocumentHighlightKind::TEXT
+            };
+            DocumentHighlight {
+                range,
+                kind: Some(kind),
+            }
+        })
+        .collect()
+}
+
 pub(crate) fn escape_for_query(name: &str) -> String {
     let mut result = String::new();
     for ch in name.chars() {
@@ -146,6 +187,15 @@ fn find_line_refs(node: &tree_sitter::Node, tree: &Tree, source: &str) -> Vec<Ra
         .collect()
 }
 
+/// Line numbers that GOTO, GOSUB, error conditions and other statements
+/// refer to.
+pub(crate) fn referenced_lines(tree: &Tree, source: &str) -> HashSet<u32> {
+    run_query("((line_reference) @lr)", tree.root_node(), source)
+        .into_iter()
+        .filter_map(|r| r.text.trim().parse().ok())
+        .collect()
+}
+
 pub(crate) fn find_variable_refs(
     node: &tree_sitter::Node,
     tree: &Tree,
@@ -382,6 +432,7 @@ fn filter_by_scope(
 mod tests {
     use super::*;
     use crate::parser;
+    use tower_lsp::lsp_types::Position;
 
     fn parse_and_find(source: &str, line: usize, character: usize) -> Vec<Range> {
         let mut p = parser::new_parser();
@@ -396,6 +447,19 @@ mod tests {
         assert_eq!(refs.len(), 2);
     }
 
+    #[test]
+    fn references_on_a_multi_statement_line() {
+        let source = "let X = 1 !: print X : print Y\n";
+        let refs = parse_and_find(source, 0, 19);
+        assert_eq!(
+            refs,
+            vec![
+                Range::new(Position::new(0, 4), Position::new(0, 5)),
+                Range::new(Position::new(0, 19), Position::new(0, 20)),
+            ]
+        );
+    }
+
     #[test]
     fn function_references() {
         let source = "def fnTest(x)\nlet y = fnTest(1)\nfnend\n";
@@ -411,6 +475,35 @@ mod tests {
         assert_eq!(refs.len(), 2);
     }
 
+    #[test]
+    fn function_highlights_mark_the_def_as_a_write() {
+        let source = "library \"util\": fnUpper$\ndef fnTest(x)\nlet y = fnTest(1)\nfnend\nprint fnUpper$(\"a\")\n";
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+
+        let kinds: Vec<_> = find_highlights(&tree, source, 2, 9)
+            .into_iter()
+            .map(|h| (h.range.start.line, h.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (1, Some(DocumentHighlightKind::WRITE)),
+                (2, Some(DocumentHighlightKind::READ)),
+            ]
+        );
+
+        // Imported functions have no DEF here, only reads
+        let imported = find_highlights(&tree, source, 4, 7);
+        assert_eq!(imported.len(), 2);
+        assert!(imported
+            .iter()
+            .all(|h| h.kind == Some(DocumentHighlightKind::READ)));
+
+        let variable = find_highlights(&tree, source, 2, 4);
+        assert_eq!(variable[0].kind, Some(DocumentHighlightKind::TEXT));
+    }
+
     #[test]
     fn label_references() {
         let source = "MYLOOP:\nlet x = 1\ngoto MYLOOP\n";
@@ -1,9 +1,18 @@
-use tower_lsp::lsp_types::{Position, Range, TextEdit};
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use tower_lsp::lsp_types::{
+    AnnotatedTextEdit, ChangeAnnotation, DocumentChanges, OneOf,
+    OptionalVersionedTextDocumentIdentifier, Position, Range, TextDocumentEdit, TextEdit, Url,
+    WorkspaceEdit,
+};
 use tree_sitter::Tree;
 
+use crate::backend::file_display_name;
 use crate::builtins;
-use crate::parser::{node_at_position, node_range};
+use crate::parser::{node_at_position, node_range, run_query};
 use crate::references;
+use crate::workspace::WorkspaceIndex;
 
 const SUPPORTED_KINDS: &[&str] = &[
     "function_name",
@@ -16,6 +25,84 @@ const SUPPORTED_KINDS: &[&str] = &[
 pub struct PrepareRenameResult {
     pub range: Range,
     pub placeholder: String,
+    /// What the new name must look like
+    pub kind: NameKind,
+}
+
+/// The kinds of renamable name, each with its own shape. A rename can't
+/// move a name from one kind to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameKind {
+    NumericFunction,
+    StringFunction,
+    NumericVariable,
+    StringVariable,
+    Label,
+}
+
+impl NameKind {
+    fn of(node: tree_sitter::Node, text: &str) -> Option<Self> {
+        let string = text.ends_with('$');
+        Some(match node.kind() {
+            "function_name" if string => Self::StringFunction,
+            "function_name" => Self::NumericFunction,
+            "stringidentifier" => Self::StringVariable,
+            "numberidentifier" => Self::NumericVariable,
+            "label" | "label_reference" => Self::Label,
+            _ => return None,
+        })
+    }
+
+    /// Pattern valid names match, ignoring case.
+    pub fn pattern(self) -> &'static str {
+        match self {
+            Self::NumericFunction => r"^fn[A-Za-z_][A-Za-z0-9_]*$",
+            Self::StringFunction => r"^fn[A-Za-z_][A-Za-z0-9_]*\$$",
+            Self::NumericVariable | Self::Label => r"^[A-Za-z_][A-Za-z0-9_]*$",
+            Self::StringVariable => r"^[A-Za-z_][A-Za-z0-9_]*\$$",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Self::NumericFunction => "numeric function",
+            Self::StringFunction => "string function",
+            Self::NumericVariable => "numeric variable",
+            Self::StringVariable => "string variable",
+            Self::Label => "label",
+        }
+    }
+
+    fn is_string(self) -> bool {
+        matches!(self, Self::StringFunction | Self::StringVariable)
+    }
+
+    fn is_function(self) -> bool {
+        matches!(self, Self::NumericFunction | Self::StringFunction)
+    }
+
+    fn matches(self, name: &str) -> bool {
+        let name = if self.is_string() {
+            match name.strip_suffix('$') {
+                Some(stem) => stem,
+                None => return false,
+            }
+        } else {
+            name
+        };
+        let name = if self.is_function() {
+            match name.get(..2) {
+                Some(prefix) if prefix.eq_ignore_ascii_case("fn") => &name[2..],
+                _ => return false,
+            }
+        } else {
+            name
+        };
+        name.chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
 }
 
 fn resolve_node<'a>(
@@ -50,6 +137,7 @@ pub fn prepare_rename(
 ) -> Option<PrepareRenameResult> {
     let node = resolve_node(tree, source, line, character)?;
     let text = node.utf8_text(source.as_bytes()).ok()?;
+    let kind = NameKind::of(node, text)?;
 
     match node.kind() {
         "function_name" => {
@@ -60,11 +148,13 @@ pub fn prepare_rename(
             Some(PrepareRenameResult {
                 range: node_range(node),
                 placeholder: text.to_string(),
+                kind,
             })
         }
         "stringidentifier" | "numberidentifier" => Some(PrepareRenameResult {
             range: node_range(node),
             placeholder: text.to_string(),
+            kind,
         }),
         "label" => {
             // Exclude trailing `:` from range and placeholder
@@ -79,16 +169,75 @@ pub fn prepare_rename(
                     },
                 },
                 placeholder: name.to_string(),
+                kind,
             })
         }
         "label_reference" => Some(PrepareRenameResult {
             range: node_range(node),
             placeholder: text.to_string(),
+            kind,
         }),
         _ => None,
     }
 }
 
+/// Check that `new_name` is usable for the symbol at the cursor: it must
+/// be the same kind of name (see [`NameKind`]), must not be a system
+/// function's name, and a label must not collide with another label in the
+/// file.
+pub fn validate_new_name(
+    tree: &Tree,
+    source: &str,
+    line: usize,
+    character: usize,
+    new_name: &str,
+) -> Result<(), String> {
+    let Some(node) = resolve_node(tree, source, line, character) else {
+        return Ok(());
+    };
+    let current = node
+        .utf8_text(source.as_bytes())
+        .unwrap_or("")
+        .trim_end_matches(':');
+    let Some(kind) = NameKind::of(node, current) else {
+        return Ok(());
+    };
+
+    let what = kind.description();
+    if kind != NameKind::Label && new_name.ends_with('$') != kind.is_string() {
+        return Err(if kind.is_string() {
+            format!("{current} is a {what}; its new name must end in $")
+        } else {
+            format!("{current} is a {what}; '{new_name}' would make it a string")
+        });
+    }
+    if !kind.matches(new_name) {
+        return Err(format!(
+            "'{new_name}' is not a valid {what} name (expected {})",
+            kind.pattern()
+        ));
+    }
+    if current.eq_ignore_ascii_case(new_name) {
+        return Ok(());
+    }
+    if !builtins::lookup(new_name).is_empty() {
+        return Err(format!("'{new_name}' is the name of a system function"));
+    }
+    if kind != NameKind::Label {
+        return Ok(());
+    }
+
+    let taken = run_query("(label) @label", tree.root_node(), source)
+        .iter()
+        .any(|r| r.text.trim_end_matches(':').eq_ignore_ascii_case(new_name));
+    if taken {
+        return Err(format!(
+            "Label '{new_name}' is already defined in this file"
+        ));
+    }
+    Ok(())
+}
+
 pub fn compute_renames(
     tree: &Tree,
     source: &str,
@@ -129,6 +278,111 @@ pub fn compute_renames(
         .collect()
 }
 
+/// Files in a cross-file rename whose edits need a second look, with the
+/// reason: the file already has a different function by the new name, or
+/// it's closed and changed on disk since it was indexed.
+pub fn rename_conflicts(
+    changes: &HashMap<Url, Vec<TextEdit>>,
+    index: &WorkspaceIndex,
+    old_name: &str,
+    new_name: &str,
+    is_open: impl Fn(&Url) -> bool,
+    modified_at: impl Fn(&Url) -> Option<SystemTime>,
+) -> HashMap<Url, String> {
+    let mut conflicts = HashMap::new();
+    for uri in changes.keys() {
+        let existing = index
+            .lookup(new_name)
+            .iter()
+            .filter(|_| !old_name.eq_ignore_ascii_case(new_name))
+            .find(|e| &e.uri == uri);
+        if let Some(existing) = existing {
+            let verb = if existing.def.is_import_only {
+                "imports"
+            } else {
+                "defines"
+            };
+            conflicts.insert(
+                uri.clone(),
+                format!(
+                    "{} already {verb} a different {}",
+                    file_display_name(uri),
+                    existing.def.name
+                ),
+            );
+            continue;
+        }
+        let stale = !is_open(uri)
+            && index
+                .indexed_at(uri)
+                .zip(modified_at(uri))
+                .is_some_and(|(indexed, modified)| modified > indexed);
+        if stale {
+            conflicts.insert(
+                uri.clone(),
+                format!(
+                    "{} changed on disk since it was indexed",
+                    file_display_name(uri)
+                ),
+            );
+        }
+    }
+    conflicts
+}
+
+/// Build the workspace edit for a rename. Edits in conflicting files are
+/// annotated as needing confirmation, so the client previews them before
+/// applying; without conflicts it's a plain `changes` map.
+pub fn annotated_rename_edit(
+    changes: HashMap<Url, Vec<TextEdit>>,
+    conflicts: &HashMap<Url, String>,
+) -> WorkspaceEdit {
+    if conflicts.is_empty() {
+        return WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        };
+    }
+
+    let mut files: Vec<(Url, Vec<TextEdit>)> = changes.into_iter().collect();
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut annotations = HashMap::new();
+    let mut document_edits = Vec::new();
+    for (uri, edits) in files {
+        let annotation = conflicts.get(&uri).map(|reason| {
+            let id = format!("rename-conflict-{}", annotations.len());
+            annotations.insert(
+                id.clone(),
+                ChangeAnnotation {
+                    label: reason.clone(),
+                    needs_confirmation: Some(true),
+                    description: Some(format!("Review the rename in {}", file_display_name(&uri))),
+                },
+            );
+            id
+        });
+        let edits = edits
+            .into_iter()
+            .map(|text_edit| match &annotation {
+                Some(id) => OneOf::Right(AnnotatedTextEdit {
+                    text_edit,
+                    annotation_id: id.clone(),
+                }),
+                None => OneOf::Left(text_edit),
+            })
+            .collect();
+        document_edits.push(TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier { uri, version: None },
+            edits,
+        });
+    }
+    WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Edits(document_edits)),
+        change_annotations: Some(annotations),
+        ..Default::default()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -245,4 +499,154 @@ let Z = X + 2
         // Should only rename module-level X refs (line 0 and line 4)
         assert_eq!(edits.len(), 2);
     }
+
+    #[test]
+    fn rename_label_updates_using_and_error_clauses() {
+        let source = "\
+read #1,using FRM: X eof DONE
+print using FRM: X
+on error goto DONE
+FRM: form pos 1,c 10
+DONE: stop
+";
+        let tree = parse(source);
+        // Cursor on the FORM statement's label
+        let edits = compute_renames(&tree, source, 3, 0, "CUSTFORM");
+        assert_eq!(edits.len(), 3);
+
+        let edits = compute_renames(&tree, source, 4, 0, "FINISH");
+        assert_eq!(edits.len(), 3);
+    }
+
+    #[test]
+    fn validate_label_name_unique() {
+        let source = "A: print 1\nB: print 2\ngoto A\n";
+        let tree = parse(source);
+        assert!(validate_new_name(&tree, source, 2, 5, "C").is_ok());
+        // Changing only the case of the same label is fine
+        assert!(validate_new_name(&tree, source, 0, 0, "a").is_ok());
+        assert_eq!(
+            validate_new_name(&tree, source, 2, 5, "b"),
+            Err("Label 'b' is already defined in this file".to_string())
+        );
+        assert!(validate_new_name(&tree, source, 0, 0, "1ABC").is_err());
+        assert!(validate_new_name(&tree, source, 0, 0, "NEW LABEL").is_err());
+    }
+
+    fn def(name: &str, is_import_only: bool) -> crate::extract::FunctionDef {
+        let source = format!("def library {name}(X)\nfnend\n");
+        let mut def = crate::extract::extract_definitions(&parse(&source), &source).remove(0);
+        def.is_import_only = is_import_only;
+        def
+    }
+
+    #[test]
+    fn conflicts_with_existing_function_and_stale_files() {
+        let lib = Url::parse("file:///ws/lib.brs").unwrap();
+        let main = Url::parse("file:///ws/main.brs").unwrap();
+        let other = Url::parse("file:///ws/other.brs").unwrap();
+        let mut index = WorkspaceIndex::new();
+        index.add_file(&lib, vec![def("fnOld", false)]);
+        index.add_file(&main, vec![def("fnOld", true), def("fnNew", false)]);
+        index.add_file(&other, vec![def("fnOld", true)]);
+        let changes: HashMap<Url, Vec<TextEdit>> = [&lib, &main, &other]
+            .into_iter()
+            .map(|u| (u.clone(), vec![]))
+            .collect();
+
+        let later = SystemTime::now() + std::time::Duration::from_secs(60);
+        let conflicts = rename_conflicts(
+            &changes,
+            &index,
+            "fnOld",
+            "FNNEW",
+            |u| *u == lib,
+            |_| Some(later),
+        );
+        assert_eq!(conflicts.len(), 2);
+        assert_eq!(
+            conflicts[&main],
+            "main.brs already defines a different fnNew"
+        );
+        assert_eq!(
+            conflicts[&other],
+            "other.brs changed on disk since it was indexed"
+        );
+
+        let unchanged = rename_conflicts(&changes, &index, "fnOld", "fnOther", |_| false, |_| None);
+        assert!(unchanged.is_empty());
+    }
+
+    #[test]
+    fn conflicting_edits_need_confirmation() {
+        let a = Url::parse("file:///ws/a.brs").unwrap();
+        let b = Url::parse("file:///ws/b.brs").unwrap();
+        let edit = TextEdit {
+            range: Range::default(),
+            new_text: "fnNew".to_string(),
+        };
+        let changes: HashMap<Url, Vec<TextEdit>> =
+            [(a.clone(), vec![edit.clone()]), (b.clone(), vec![edit])]
+                .into_iter()
+                .collect();
+
+        let plain = annotated_rename_edit(changes.clone(), &HashMap::new());
+        assert_eq!(plain.changes, Some(changes.clone()));
+
+        let conflicts = HashMap::from([(b.clone(), "b.brs changed".to_string())]);
+        let edit = annotated_rename_edit(changes, &conflicts);
+        let Some(DocumentChanges::Edits(documents)) = edit.document_changes else {
+            panic!("expected document edits");
+        };
+        assert_eq!(documents[0].text_document.uri, a);
+        assert!(matches!(documents[0].edits[0], OneOf::Left(_)));
+        let OneOf::Right(annotated) = &documents[1].edits[0] else {
+            panic!("expected an annotated edit");
+        };
+        let annotation = &edit.change_annotations.unwrap()[&annotated.annotation_id];
+        assert_eq!(annotation.label, "b.brs changed");
+        assert_eq!(annotation.needs_confirmation, Some(true));
+    }
+
+    #[test]
+    fn validate_keeps_names_of_the_same_kind() {
+        let source = "def fnName$(X)\nlet fnName$ = str$(X)\nfnend\nlet N = 1\nlet S$ = \"\"\n";
+        let tree = parse(source);
+
+        assert!(validate_new_name(&tree, source, 0, 5, "FNTitle$").is_ok());
+        assert_eq!(
+            validate_new_name(&tree, source, 0, 5, "fnTitle"),
+            Err("fnName$ is a string function; its new name must end in $".to_string())
+        );
+        assert_eq!(
+            validate_new_name(&tree, source, 0, 5, "Title$"),
+            Err("'Title$' is not a valid string function name (expected ^fn[A-Za-z_][A-Za-z0-9_]*\\$$)".to_string())
+        );
+
+        assert_eq!(
+            validate_new_name(&tree, source, 3, 4, "N$"),
+            Err("N is a numeric variable; 'N$' would make it a string".to_string())
+        );
+        assert!(validate_new_name(&tree, source, 3, 4, "Count").is_ok());
+        assert_eq!(
+            validate_new_name(&tree, source, 3, 4, "Val"),
+            Err("'Val' is the name of a system function".to_string())
+        );
+        assert_eq!(
+            validate_new_name(&tree, source, 4, 4, "Str$"),
+            Err("'Str$' is the name of a system function".to_string())
+        );
+        assert!(validate_new_name(&tree, source, 4, 4, "2S$").is_err());
+
+        let result = prepare_rename(&tree, source, 0, 5).unwrap();
+        assert_eq!(result.kind, NameKind::StringFunction);
+        assert_eq!(result.kind.pattern(), r"^fn[A-Za-z_][A-Za-z0-9_]*\$$");
+    }
+
+    #[test]
+    fn validate_ignores_non_labels() {
+        let source = "let X = 1\nA:\n";
+        let tree = parse(source);
+        assert!(validate_new_name(&tree, source, 0, 4, "A").is_ok());
+    }
 }
@@ -0,0 +1,172 @@
+//! Find and replace across workspace BR files, for `br-lsp.replaceAll`.
+
+use std::ops::Range as ByteRange;
+
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+
+/// Arguments of `br-lsp.replaceAll`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ReplaceOptions {
+    pub search: String,
+    pub replace: String,
+    /// Treat `search` as a regular expression; `replace` may then use
+    /// `$1`/`${name}` groups
+    pub regex: bool,
+    /// BR is case-insensitive, so matching ignores case unless this is set
+    pub match_case: bool,
+    /// List the matches without changing anything
+    pub dry_run: bool,
+    /// Write the replacements to files that aren't open. Without this,
+    /// closed files are only listed.
+    pub write_closed_files: bool,
+}
+
+impl ReplaceOptions {
+    pub fn matcher(&self) -> Result<Regex, regex::Error> {
+        let pattern = if self.regex {
+            self.search.clone()
+        } else {
+            regex::escape(&self.search)
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!self.match_case)
+            .multi_line(true)
+            .build()
+    }
+}
+
+/// One match in a file and what replaces it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replacement {
+    pub range: Range,
+    pub bytes: ByteRange<usize>,
+    pub matched: String,
+    pub new_text: String,
+}
+
+impl Replacement {
+    pub fn text_edit(&self) -> TextEdit {
+        TextEdit {
+            range: self.range,
+            new_text: self.new_text.clone(),
+        }
+    }
+}
+
+/// Every non-empty match of `matcher` in `source`, with its replacement.
+/// Empty matches (`^`, `x*`) are skipped: replacing them inserts text all
+/// over the workspace, which is never what a rename-style replace wants.
+pub fn find_replacements(
+    source: &str,
+    matcher: &Regex,
+    options: &ReplaceOptions,
+) -> Vec<Replacement> {
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    // Byte columns, as `parser::node_range` reports them
+    let position = |offset: usize| {
+        let line = line_starts.partition_point(|&start| start <= offset) - 1;
+        Position::new(line as u32, (offset - line_starts[line]) as u32)
+    };
+
+    matcher
+        .captures_iter(source)
+        .filter_map(|caps| {
+            let whole = caps.get(0)?;
+            if whole.is_empty() {
+                return None;
+            }
+            let new_text = if options.regex {
+                let mut expanded = String::new();
+                caps.expand(&options.replace, &mut expanded);
+                expanded
+            } else {
+                options.replace.clone()
+            };
+            Some(Replacement {
+                range: Range::new(position(whole.start()), position(whole.end())),
+                bytes: whole.range(),
+                matched: whole.as_str().to_string(),
+                new_text,
+            })
+        })
+        .collect()
+}
+
+/// `source` with the replacements made.
+pub fn apply(source: &str, replacements: &[Replacement]) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut last = 0;
+    for r in replacements {
+        out.push_str(&source[last..r.bytes.start]);
+        out.push_str(&r.new_text);
+        last = r.bytes.end;
+    }
+    out.push_str(&source[last..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(search: &str, replace: &str, regex: bool) -> ReplaceOptions {
+        ReplaceOptions {
+            search: search.to_string(),
+            replace: replace.to_string(),
+            regex,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn literal_replace_ignores_case_and_regex_syntax() {
+        let source = "00010 let a$=fnName$(1)\n00020 print FNNAME$(2)\n";
+        let opts = options("fnname$(", "fnTitle$(", false);
+        let found = find_replacements(source, &opts.matcher().unwrap(), &opts);
+        assert_eq!(found.len(), 2);
+        assert_eq!(
+            found[1].range,
+            Range::new(Position::new(1, 12), Position::new(1, 20))
+        );
+        assert_eq!(found[1].matched, "FNNAME$(");
+        assert_eq!(
+            apply(source, &found),
+            "00010 let a$=fnTitle$(1)\n00020 print fnTitle$(2)\n"
+        );
+
+        let opts = ReplaceOptions {
+            match_case: true,
+            ..opts
+        };
+        assert_eq!(
+            find_replacements(source, &opts.matcher().unwrap(), &opts).len(),
+            0
+        );
+    }
+
+    #[test]
+    fn regex_replace_expands_groups() {
+        let source = "00010 goto 100\n00020 gosub 200\n";
+        let opts = options(r"(goto|gosub) (\d+)", "$1 L$2", true);
+        let found = find_replacements(source, &opts.matcher().unwrap(), &opts);
+        assert_eq!(found[1].new_text, "gosub L200");
+        assert_eq!(apply(source, &found), "00010 goto L100\n00020 gosub L200\n");
+    }
+
+    #[test]
+    fn positions_use_byte_columns_and_skip_empty_matches() {
+        let source = "00010 print \"\u{2551}\u{2551}\": x = 1\n";
+        let opts = options("x", "y", false);
+        let found = find_replacements(source, &opts.matcher().unwrap(), &opts);
+        assert_eq!(found[0].range.start, Position::new(0, 22));
+
+        let opts = options("z*", "q", true);
+        assert!(find_replacements(source, &opts.matcher().unwrap(), &opts).is_empty());
+        assert!(options("(", "", true).matcher().is_err());
+    }
+}
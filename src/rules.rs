@@ -0,0 +1,573 @@
+//! Running diagnostic rules on their own, for `br-lsp.runRule` and for
+//! settings changes that only switch some rules on.
+
+use tower_lsp::lsp_types::{Diagnostic, NumberOrString, Url};
+use tree_sitter::Tree;
+
+use crate::backend::DiagnosticsConfig;
+use crate::dialect::{self, Dialect};
+use crate::layout::LayoutIndex;
+use crate::parser::DiagnosticNodes;
+use crate::structure::Structure;
+use crate::workspace::WorkspaceIndex;
+use crate::{
+    diagnostics, extract, files, library_deps, line_numbers, metrics, parser, shadowing, style,
+};
+
+/// The check that reports a rule. Several rules share a check; its
+/// results are filtered down to the rule asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Check {
+    Syntax,
+    MissingFnend,
+    Functions,
+    UndefinedFunctions,
+    DeprecatedCalls,
+    Versions,
+    LibraryFunctions,
+    UnusedVariables,
+    UnusedParameters,
+    FileNumbers,
+    RecordLengths,
+    KeyFields,
+    ValueParamAssignments,
+    ErrorConditions,
+    PicMasks,
+    LineNumbers,
+    BuiltinShadowing,
+    LibraryShadowing,
+    Naming,
+    Metrics,
+    KeywordCase,
+    Dialect,
+}
+
+/// What a rule finds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    /// Code that won't run as meant
+    Correctness,
+    /// Style and dead code, which generated files are spared
+    Style,
+}
+
+/// Whether a rule's switch is on.
+pub type Switch = fn(&DiagnosticsConfig) -> bool;
+
+struct RuleInfo {
+    code: &'static str,
+    check: Check,
+    /// The `br.diagnostics.*` switch the rule runs under. Rules without one
+    /// run whenever the setting they check against (a naming pattern, a
+    /// target BR version, a keyword case, dialects) is set.
+    switch: Option<Switch>,
+    category: Category,
+}
+
+/// Rule codes `br-lsp.runRule` accepts, with the check behind each.
+/// Cross-file rules (`library-cycle`) need the whole workspace at once and
+/// only run from `br.scanAll`.
+const RULES: &[RuleInfo] = &[
+    RuleInfo {
+        code: "syntax-error",
+        check: Check::Syntax,
+        switch: Some(|c| c.syntax),
+        category: Category::Correctness,
+    },
+    RuleInfo {
+        code: "missing-fnend",
+        check: Check::MissingFnend,
+        switch: Some(|c| c.functions || c.missing_fnend),
+        category: Category::Correctness,
+    },
+    RuleInfo {
+        code: "duplicate-function",
+        check: Check::Functions,
+        switch: Some(|c| c.functions),
+        category: Category::Correctness,
+    },
+    RuleInfo {
+        code: "parameter-count",
+        check: Check::Functions,
+        switch: Some(|c| c.functions),
+        category: Category::Correctness,
+    },
+    RuleInfo {
+        code: "argument-type",
+        check: Check::Functions,
+        switch: Some(|c| c.functions),
+        category: Category::Correctness,
+    },
+    RuleInfo {
+        code: "doc-param-unknown",
+        check: Check::Functions,
+        switch: Some(|c| c.functions),
+        category: Category::Correctness,
+    },
+    RuleInfo {
+        code: "doc-param-missing",
+        check: Check::Functions,
+        switch: Some(|c| c.functions),
+        category: Category::Correctness,
+    },
+    RuleInfo {
+        code: "undefined-function",
+        check: Check::UndefinedFunctions,
+        switch: Some(|c| c.undefined_functions),
+        category: Category::Correctness,
+    },
+    RuleInfo {
+        code: "deprecated-function",
+        check: Check::DeprecatedCalls,
+        switch: Some(|c| c.functions),
+        category: Category::Correctness,
+    },
+    RuleInfo {
+        code: "unavailable-in-version",
+        check: Check::Versions,
+        switch: None,
+        category: Category::Correctness,
+    },
+    RuleInfo {
+        code: "library-missing-function",
+        check: Check::LibraryFunctions,
+        switch: Some(|c| c.functions),
+        category: Category::Correctness,
+    },
+    RuleInfo {
+        code: "unused-variable",
+        check: Check::UnusedVariables,
+        switch: Some(|c| c.unused_variables),
+        category: Category::Style,
+    },
+    RuleInfo {
+        code: "unused-import",
+        check: Check::UnusedVariables,
+        switch: Some(|c| c.unused_variables),
+        category: Category::Style,
+    },
+    RuleInfo {
+        code: "unused-parameter",
+        check: Check::UnusedParameters,
+        switch: Some(|c| c.unused_parameters),
+        category: Category::Style,
+    },
+    RuleInfo {
+        code: "file-already-open",
+        check: Check::FileNumbers,
+        switch: Some(|c| c.file_numbers),
+        category: Category::Correctness,
+    },
+    RuleInfo {
+        code: "file-not-opened",
+        check: Check::FileNumbers,
+        switch: Some(|c| c.file_numbers),
+        category: Category::Correctness,
+    },
+    RuleInfo {
+        code: "record-length-mismatch",
+        check: Check::RecordLengths,
+        switch: Some(|c| c.file_numbers),
+        category: Category::Correctness,
+    },
+    RuleInfo {
+        code: "key-mismatch",
+        check: Check::KeyFields,
+        switch: Some(|c| c.file_numbers),
+        category: Category::Correctness,
+    },
+    RuleInfo {
+        code: "value-param-assignment",
+        check: Check::ValueParamAssignments,
+        switch: Some(|c| c.value_param_assignment),
+        category: Category::Correctness,
+    },
+    RuleInfo {
+        code: "undefined-condition-target",
+        check: Check::ErrorConditions,
+        switch: Some(|c| c.error_conditions),
+        category: Category::Correctness,
+    },
+    RuleInfo {
+        code: "invalid-pic-mask",
+        check: Check::PicMasks,
+        switch: Some(|c| c.pic_masks),
+        category: Category::Correctness,
+    },
+    RuleInfo {
+        code: "duplicate-line-number",
+        check: Check::LineNumbers,
+        switch: Some(|c| c.line_numbers),
+        category: Category::Correctness,
+    },
+    RuleInfo {
+        code: "line-number-order",
+        check: Check::LineNumbers,
+        switch: Some(|c| c.line_numbers),
+        category: Category::Correctness,
+    },
+    RuleInfo {
+        code: "builtin-shadowing",
+        check: Check::BuiltinShadowing,
+        switch: Some(|c| c.shadowing),
+        category: Category::Style,
+    },
+    RuleInfo {
+        code: "library-shadowing",
+        check: Check::LibraryShadowing,
+        switch: Some(|c| c.shadowing),
+        category: Category::Correctness,
+    },
+    RuleInfo {
+        code: "naming-function",
+        check: Check::Naming,
+        switch: None,
+        category: Category::Style,
+    },
+    RuleInfo {
+        code: "naming-library-function",
+        check: Check::Naming,
+        switch: None,
+        category: Category::Style,
+    },
+    RuleInfo {
+        code: "naming-global-variable",
+        check: Check::Naming,
+        switch: None,
+        category: Category::Style,
+    },
+    RuleInfo {
+        code: "function-metrics",
+        check: Check::Metrics,
+        switch: None,
+        category: Category::Style,
+    },
+    RuleInfo {
+        code: "keyword-case",
+        check: Check::KeywordCase,
+        switch: None,
+        category: Category::Style,
+    },
+    RuleInfo {
+        code: "wrong-dialect",
+        check: Check::Dialect,
+        switch: None,
+        category: Category::Correctness,
+    },
+];
+
+/// Every rule code, for listing in errors and UIs.
+pub fn codes() -> impl Iterator<Item = &'static str> {
+    RULES.iter().map(|info| info.code)
+}
+
+/// Whether the rule with this code finds style or dead-code problems.
+pub fn is_style(code: &str) -> bool {
+    RULES
+        .iter()
+        .any(|info| info.code == code && info.category == Category::Style)
+}
+
+/// The rules that have a switch, with it.
+pub fn switched() -> impl Iterator<Item = (Rule, Switch)> {
+    RULES
+        .iter()
+        .filter_map(|info| Some((Rule::from_info(info), info.switch?)))
+}
+
+/// A rule picked by its code (case-insensitive).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rule {
+    pub code: &'static str,
+    check: Check,
+}
+
+impl Rule {
+    pub fn parse(code: &str) -> Option<Self> {
+        let code = code.trim();
+        RULES
+            .iter()
+            .find(|info| info.code.eq_ignore_ascii_case(code))
+            .map(Self::from_info)
+    }
+
+    fn from_info(info: &RuleInfo) -> Self {
+        Rule {
+            code: info.code,
+            check: info.check,
+        }
+    }
+
+    /// Whether the rule needs the workspace index to say anything.
+    pub fn needs_index(self) -> bool {
+        matches!(
+            self.check,
+            Check::UndefinedFunctions | Check::LibraryFunctions | Check::LibraryShadowing
+        )
+    }
+
+    /// Run the rule on one file. The rule's on/off switch is ignored, since
+    /// asking for it is the point; settings it can't run without (a target
+    /// BR version, a keyword case) still apply, and naming patterns that
+    /// aren't configured use the strict profile's defaults.
+    pub fn run(
+        self,
+        uri: &Url,
+        tree: &Tree,
+        source: &str,
+        config: &DiagnosticsConfig,
+        index: Option<&WorkspaceIndex>,
+        layouts: Option<&LayoutIndex>,
+    ) -> Vec<Diagnostic> {
+        run_all(&[self], uri, tree, source, config, index, layouts)
+    }
+}
+
+/// Run several rules on one file as [`Rule::run`] does, running each check
+/// behind them once.
+pub fn run_all(
+    rules: &[Rule],
+    uri: &Url,
+    tree: &Tree,
+    source: &str,
+    config: &DiagnosticsConfig,
+    index: Option<&WorkspaceIndex>,
+    layouts: Option<&LayoutIndex>,
+) -> Vec<Diagnostic> {
+    let mut checks: Vec<Check> = Vec::new();
+    for rule in rules {
+        if !checks.contains(&rule.check) {
+            checks.push(rule.check);
+        }
+    }
+    let nodes = parser::collect_diagnostic_nodes(tree, source);
+    let mut found: Vec<Diagnostic> = checks
+        .into_iter()
+        .flat_map(|check| check.run(uri, tree, &nodes, source, config, index, layouts))
+        .collect();
+    found.retain(
+        |d| matches!(&d.code, Some(NumberOrString::String(c)) if rules.iter().any(|r| r.code == c)),
+    );
+    diagnostics::stamp_source(&mut found);
+    found
+}
+
+impl Check {
+    #[allow(clippy::too_many_arguments)]
+    fn run(
+        self,
+        uri: &Url,
+        tree: &Tree,
+        nodes: &DiagnosticNodes,
+        source: &str,
+        config: &DiagnosticsConfig,
+        index: Option<&WorkspaceIndex>,
+        layouts: Option<&LayoutIndex>,
+    ) -> Vec<Diagnostic> {
+        let structure = || Structure::from_root(nodes.root, source);
+        let defs = || {
+            extract::extract_definitions_from_nodes(
+                &nodes.def_statements,
+                &nodes.library_statements,
+                source,
+            )
+        };
+
+        match self {
+            Check::Syntax => parser::collect_diagnostics(tree, source),
+            Check::MissingFnend => diagnostics::check_missing_fnend(&structure(), source),
+            Check::Functions => {
+                diagnostics::collect_function_diagnostics(nodes, &structure(), source, &defs(), uri)
+            }
+            Check::UndefinedFunctions => match index {
+                Some(index) => diagnostics::check_undefined_functions(
+                    &nodes.function_calls,
+                    source,
+                    index,
+                    &defs(),
+                ),
+                None => Vec::new(),
+            },
+            Check::DeprecatedCalls => diagnostics::check_deprecated_calls(
+                &nodes.function_calls,
+                source,
+                &defs(),
+                index,
+                uri,
+            ),
+            Check::Versions => match config.br_version {
+                Some(version) => {
+                    let mut found =
+                        diagnostics::check_builtin_versions(&nodes.function_calls, source, version);
+                    found.extend(diagnostics::check_statement_versions(tree, source, version));
+                    found
+                }
+                None => Vec::new(),
+            },
+            Check::LibraryFunctions => match index {
+                Some(index) => library_deps::check_library_functions(
+                    tree,
+                    source,
+                    index,
+                    &config.drive_mappings,
+                ),
+                None => Vec::new(),
+            },
+            Check::UnusedVariables => {
+                let mut found =
+                    diagnostics::check_unused_variables(nodes, &structure(), source, &defs());
+                if let Some(index) = index {
+                    library_deps::link_unused_imports(
+                        &mut found,
+                        tree,
+                        source,
+                        index,
+                        &config.drive_mappings,
+                    );
+                }
+                found
+            }
+            Check::UnusedParameters => {
+                diagnostics::check_unused_parameters(nodes, &structure(), source)
+            }
+            Check::FileNumbers => {
+                diagnostics::check_file_numbers(&files::collect_file_usage(tree, source), uri)
+            }
+            Check::RecordLengths => match layouts {
+                Some(layouts) => diagnostics::check_record_lengths(
+                    &files::collect_file_usage(tree, source),
+                    layouts,
+                ),
+                None => Vec::new(),
+            },
+            Check::KeyFields => match layouts {
+                Some(layouts) => {
+                    diagnostics::check_key_fields(&files::collect_file_usage(tree, source), layouts)
+                }
+                None => Vec::new(),
+            },
+            Check::ValueParamAssignments => {
+                diagnostics::check_value_param_assignments(nodes, &structure(), source)
+            }
+            Check::ErrorConditions => diagnostics::check_error_condition_targets(tree, source),
+            Check::PicMasks => diagnostics::check_pic_masks(tree, source),
+            Check::LineNumbers => line_numbers::check_line_numbers(tree, source, uri),
+            Check::BuiltinShadowing => shadowing::check_builtin_shadowing(nodes, source, &defs()),
+            Check::LibraryShadowing => match index {
+                Some(index) => shadowing::check_library_shadowing(&defs(), index, uri),
+                None => Vec::new(),
+            },
+            Check::Naming => {
+                let naming = config.naming.clone().with_all_rules();
+                style::check_naming(tree, source, &defs(), &naming)
+            }
+            Check::Metrics => metrics::check_metrics(tree, source, &config.metrics),
+            Check::KeywordCase => match config.keyword_case {
+                Some(case) => style::check_keyword_case(tree, source, case),
+                None => Vec::new(),
+            },
+            Check::Dialect => {
+                dialect::check_dialect(tree, source, Dialect::of(uri.path()), &config.dialects)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(code: &str, source: &str) -> Vec<Diagnostic> {
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        let uri = Url::parse("file:///test.brs").unwrap();
+        Rule::parse(code).unwrap().run(
+            &uri,
+            &tree,
+            source,
+            &DiagnosticsConfig::default(),
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn runs_only_the_requested_rule() {
+        let source = "\
+def fnAdd(a, b)
+  let fnAdd = a + b
+fnend
+let x = fnAdd(1)
+let y = fnAdd(1, \"two\")
+dim unused$*10
+";
+        let counts = run("parameter-count", source);
+        assert_eq!(counts.len(), 1);
+        assert_eq!(
+            counts[0].message,
+            "Function 'fnAdd' expects 2 parameter(s), but 1 provided"
+        );
+        assert_eq!(counts[0].source.as_deref(), Some(diagnostics::SOURCE));
+
+        let types = run("Argument-Type", source);
+        assert_eq!(types.len(), 1);
+        assert_eq!(types[0].range.start.line, 4);
+
+        assert_eq!(run("unused-variable", source).len(), 1);
+
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        let uri = Url::parse("file:///test.brs").unwrap();
+        let rules: Vec<Rule> = ["unused-variable", "argument-type", "parameter-count"]
+            .iter()
+            .filter_map(|c| Rule::parse(c))
+            .collect();
+        let found = run_all(
+            &rules,
+            &uri,
+            &tree,
+            source,
+            &DiagnosticsConfig::default(),
+            None,
+            None,
+        );
+        let mut lines: Vec<u32> = found.iter().map(|d| d.range.start.line).collect();
+        lines.sort();
+        assert_eq!(lines, vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn rules_run_even_when_switched_off_or_unconfigured() {
+        let source = "def FNDOUBLE(a)\n  let FNDOUBLE = a * 2\nfnend\n";
+        let naming = run("naming-function", source);
+        assert_eq!(naming.len(), 1);
+        assert_eq!(
+            naming[0].message,
+            "Function name 'FNDOUBLE' does not match '^fn'"
+        );
+
+        // Keyword case has no default convention to check against, nor
+        // metrics a default limit
+        assert!(run("keyword-case", "PRINT 1\n").is_empty());
+        assert!(run("function-metrics", source).is_empty());
+    }
+
+    #[test]
+    fn unknown_rules_are_rejected() {
+        assert!(Rule::parse("library-cycle").is_none());
+        assert!(Rule::parse("no-such-rule").is_none());
+        assert_eq!(
+            Rule::parse(" Missing-FNEND ").unwrap().code,
+            "missing-fnend"
+        );
+        assert!(codes().any(|c| c == "invalid-pic-mask"));
+    }
+
+    #[test]
+    fn style_rules_are_categorized() {
+        assert!(is_style("unused-variable"));
+        assert!(is_style("function-metrics"));
+        assert!(!is_style("parameter-count"));
+        assert!(!is_style("library-cycle"));
+    }
+}
@@ -1,8 +1,12 @@
+use std::collections::HashSet;
+
 use tower_lsp::lsp_types::{
     SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokensLegend,
 };
 use tree_sitter::Tree;
 
+use crate::references;
+
 pub const TOKEN_TYPES: &[SemanticTokenType] = &[
     SemanticTokenType::FUNCTION,          // 0
     SemanticTokenType::VARIABLE,          // 1
@@ -23,6 +27,7 @@ pub const TOKEN_MODIFIERS: &[SemanticTokenModifier] = &[
     SemanticTokenModifier::DEFAULT_LIBRARY,    // bit 1
     SemanticTokenModifier::DEFINITION,         // bit 2
     SemanticTokenModifier::new("controlFlow"), // bit 3
+    SemanticTokenModifier::new("faded"),       // bit 4
 ];
 
 pub fn legend() -> SemanticTokensLegend {
@@ -42,8 +47,16 @@ pub(crate) struct RawToken {
 
 pub fn collect_tokens(tree: &Tree, source: &str) -> Vec<SemanticToken> {
     let mut raw = Vec::new();
-    walk_node(tree.root_node(), source, false, false, &mut raw);
-    encode_deltas(&mut raw)
+    let referenced = references::referenced_lines(tree, source);
+    walk_node(
+        tree.root_node(),
+        source,
+        false,
+        false,
+        &referenced,
+        &mut raw,
+    );
+    encode_deltas(&mut raw, source)
 }
 
 fn walk_node(
@@ -51,6 +64,7 @@ fn walk_node(
     source: &str,
     in_parameter: bool,
     in_dim: bool,
+    referenced: &HashSet<u32>,
     tokens: &mut Vec<RawToken>,
 ) {
     let kind = node.kind();
@@ -70,8 +84,33 @@ fn walk_node(
         emit_mat_keyword(node, source, tokens);
     }
 
-    if let Some((token_type, modifiers)) = classify_node(kind, is_named, node, in_parameter, in_dim)
+    // `!:` continues the statement on the next line. The node runs through
+    // the line break, so only the marker itself is highlighted.
+    if kind == "continuation" {
+        let start = node.start_position();
+        tokens.push(RawToken {
+            line: start.row as u32,
+            start: start.column as u32,
+            length: 2,
+            token_type: 9, // operator
+            modifiers: 0,
+        });
+        return;
+    }
+
+    // Remarks and trailing comments the grammar folds into other nodes
+    if matches!(kind, "rem_statement" | "unquoted_data") {
+        emit_embedded_comment(node, source, tokens);
+    }
+
+    if let Some((token_type, mut modifiers)) =
+        classify_node(kind, is_named, node, in_parameter, in_dim)
     {
+        // Leading line numbers nothing jumps to are faded
+        if kind == "line_number" && !is_referenced(node, source, referenced) {
+            modifiers |= 1 << 4; // faded
+        }
+
         // String/template_string nodes with a range child (e.g. "test"(1:2)) —
         // emit the string token only for the quoted portion, then recurse so the
         // range children get their own (number) tokens.
@@ -101,7 +140,14 @@ fn walk_node(
             // Recurse into children (range will emit number tokens)
             let mut cursor = node.walk();
             for child in node.children(&mut cursor) {
-                walk_node(child, source, child_in_parameter, child_in_dim, tokens);
+                walk_node(
+                    child,
+                    source,
+                    child_in_parameter,
+                    child_in_dim,
+                    referenced,
+                    tokens,
+                );
             }
             return;
         }
@@ -134,10 +180,24 @@ fn walk_node(
 
     let mut cursor = node.walk();
     for child in node.children(&mut cursor) {
-        walk_node(child, source, child_in_parameter, child_in_dim, tokens);
+        walk_node(
+            child,
+            source,
+            child_in_parameter,
+            child_in_dim,
+            referenced,
+            tokens,
+        );
     }
 }
 
+fn is_referenced(node: tree_sitter::Node, source: &str, referenced: &HashSet<u32>) -> bool {
+    node.utf8_text(source.as_bytes())
+        .ok()
+        .and_then(|text| text.trim().parse().ok())
+        .is_some_and(|n: u32| referenced.contains(&n))
+}
+
 /// Returns true for node kinds where we should NOT recurse into children
 /// after emitting a token (the entire node text is one semantic unit).
 fn is_leaf_token(kind: &str) -> bool {
@@ -157,6 +217,8 @@ fn is_leaf_token(kind: &str) -> bool {
     )
 }
 
+/// Token type and modifiers for a node on its own. Modifiers that depend on
+/// the rest of the file (`faded` line numbers) are added by `collect_tokens`.
 pub(crate) fn classify_node(
     kind: &str,
     is_named: bool,
@@ -195,9 +257,10 @@ pub(crate) fn classify_node(
         "number" | "int" => Some((6, 0)),         // number
         "0" | "1" if !is_named && is_inside(node, "option_statement") => Some((6, 0)), // option base 0/1
         "line_number" => Some((10, 0)),                                                // lineNumber
-        "label" => Some((7, 1 << 2)), // property + definition
-        "label_reference" | "line_reference" => Some((7, 0)), // property
-        "error_condition" => Some((8, 0)), // enumMember
+        "label" => Some((7, 1 << 2)),           // property + definition
+        "label_reference" => Some((7, 0)),      // property
+        "line_reference" => Some((10, 1 << 3)), // lineNumber + controlFlow
+        "error_condition" => Some((8, 0)),      // enumMember
         "*" if !is_named && in_dim => Some((9, 0)), // operator (length modifier)
         _ => None,
     }
@@ -241,6 +304,50 @@ fn emit_mat_keyword(node: tree_sitter::Node, source: &str, tokens: &mut Vec<RawT
     }
 }
 
+/// Emit a comment token for text the grammar keeps inside another node: the
+/// remark of a REM statement, or a `! comment` after unquoted DATA items.
+/// The comment runs to the end of the node's first line or to a `!:` that
+/// starts the next statement.
+fn emit_embedded_comment(node: tree_sitter::Node, source: &str, tokens: &mut Vec<RawToken>) {
+    let Ok(text) = node.utf8_text(source.as_bytes()) else {
+        return;
+    };
+    let text = text.lines().next().unwrap_or_default();
+    let comment_start = if node.kind() == "rem_statement" {
+        // After the REM keyword, which is the node's first child
+        let Some(keyword) = node.child(0) else {
+            return;
+        };
+        let after = keyword.end_byte() - node.start_byte();
+        let Some(rest) = text.get(after..) else {
+            return;
+        };
+        after + (rest.len() - rest.trim_start().len())
+    } else {
+        let Some(bang) = text
+            .match_indices('!')
+            .map(|(i, _)| i)
+            .find(|&i| !text[i + 1..].starts_with(':'))
+        else {
+            return;
+        };
+        bang
+    };
+    let rest = &text[comment_start..];
+    let end = rest.find("!:").unwrap_or(rest.len());
+    let length = rest[..end].trim_end().len();
+    if length > 0 {
+        let start = node.start_position();
+        tokens.push(RawToken {
+            line: start.row as u32,
+            start: (start.column + comment_start) as u32,
+            length: length as u32,
+            token_type: 4, // comment
+            modifiers: 0,
+        });
+    }
+}
+
 fn emit_multiline_token(
     source: &str,
     start: tree_sitter::Point,
@@ -273,7 +380,21 @@ fn emit_multiline_token(
     }
 }
 
-pub(crate) fn encode_deltas(tokens: &mut [RawToken]) -> Vec<SemanticToken> {
+/// Delta-encode tokens whose `start` and `length` are byte offsets within
+/// their line of `source`. The client counts UTF-16 code units, which
+/// differ from bytes after CP437 characters and no-break spaces.
+pub(crate) fn encode_deltas(tokens: &mut [RawToken], source: &str) -> Vec<SemanticToken> {
+    let lines: Vec<&str> = source.lines().collect();
+    for tok in tokens.iter_mut() {
+        let Some(line) = lines.get(tok.line as usize) else {
+            continue;
+        };
+        let start = tok.start as usize;
+        let end = start + tok.length as usize;
+        tok.start = utf16_len(line, 0, start);
+        tok.length = utf16_len(line, start, end);
+    }
+
     // Sort by line, then by start column
     tokens.sort_by(|a, b| a.line.cmp(&b.line).then(a.start.cmp(&b.start)));
 
@@ -304,6 +425,23 @@ pub(crate) fn encode_deltas(tokens: &mut [RawToken]) -> Vec<SemanticToken> {
     result
 }
 
+/// UTF-16 length of `line[from..to]`, clamped to the line; offsets inside
+/// a character round down to its start.
+fn utf16_len(line: &str, from: usize, to: usize) -> u32 {
+    let boundary = |mut i: usize| {
+        i = i.min(line.len());
+        while !line.is_char_boundary(i) {
+            i -= 1;
+        }
+        i
+    };
+    let (from, to) = (boundary(from), boundary(to));
+    if from >= to {
+        return 0;
+    }
+    line[from..to].encode_utf16().count() as u32
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -410,6 +548,25 @@ mod tests {
         assert!(has_number, "option base 1 should have a number token");
     }
 
+    #[test]
+    fn line_references_and_unreferenced_line_numbers() {
+        let tokens = parse_and_collect("00100 goto 300\n00200 print \"x\"\n00300 stop\n");
+        let line_numbers: Vec<(u32, u32)> = tokens
+            .iter()
+            .filter(|t| t.token_type == 10)
+            .map(|t| (t.length, t.token_modifiers_bitset))
+            .collect();
+        assert_eq!(
+            line_numbers,
+            vec![
+                (6, 1 << 4), // "00100 ", faded
+                (3, 1 << 3), // 300, controlFlow
+                (6, 1 << 4), // "00200 ", faded
+                (6, 0),      // "00300 ", jumped to
+            ]
+        );
+    }
+
     #[test]
     fn string_with_range_splits_tokens() {
         // "test"(1:2) — the range numbers should NOT be string tokens
@@ -424,4 +581,95 @@ mod tests {
             number_tokens.len()
         );
     }
+
+    /// Absolute (line, start, length, type) of each encoded token.
+    fn absolute(tokens: &[SemanticToken]) -> Vec<(u32, u32, u32, u32)> {
+        let (mut line, mut start) = (0, 0);
+        tokens
+            .iter()
+            .map(|t| {
+                if t.delta_line > 0 {
+                    start = 0;
+                }
+                line += t.delta_line;
+                start += t.delta_start;
+                (line, start, t.length, t.token_type)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn continuation_markers_and_embedded_comments() {
+        let source = include_str!("../tests/fixtures/semantic_tokens/continuations.brs");
+        let lines: Vec<&str> = source.lines().collect();
+        let tokens = absolute(&parse_and_collect(source));
+        let text = |&(line, start, length, _): &(u32, u32, u32, u32)| {
+            lines[line as usize][start as usize..(start + length) as usize].trim_end()
+        };
+
+        let comments: Vec<&str> = tokens.iter().filter(|t| t.3 == 4).map(text).collect();
+        assert_eq!(
+            comments,
+            vec![
+                "! trailing",
+                "! before continuation",
+                "! after continued line",
+                "! note",
+                "! second",
+                "! whole comment line",
+                "! tail",
+                "! data comment",
+                "remark text",
+                "! form comment",
+            ]
+        );
+
+        let markers: Vec<(u32, &str)> = tokens
+            .iter()
+            .filter(|t| t.3 == 9)
+            .map(|t| (t.0, text(t)))
+            .collect();
+        assert_eq!(markers, vec![(1, "!:"), (3, "!:"), (4, "!:"), (7, "!:")]);
+
+        // Nothing overlaps, and no token spills past the end of its line
+        for pair in tokens.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            assert!(a.0 < b.0 || a.1 + a.2 <= b.1, "{a:?} overlaps {b:?}");
+        }
+        for t in &tokens {
+            assert!((t.1 + t.2) as usize <= lines[t.0 as usize].len(), "{t:?}");
+        }
+    }
+
+    #[test]
+    fn tab_indented_lines() {
+        let source = include_str!("../tests/fixtures/semantic_tokens/tabs.brs");
+        let lines: Vec<&str> = source.lines().collect();
+        let tokens = absolute(&parse_and_collect(source));
+        // A tab is one column, like any other character
+        let text = |&(line, start, length, _): &(u32, u32, u32, u32)| {
+            lines[line as usize][start as usize..(start + length) as usize].to_string()
+        };
+
+        let comments: Vec<String> = tokens.iter().filter(|t| t.3 == 4).map(text).collect();
+        assert_eq!(comments, vec!["! sum", "! nested comment"]);
+        let keywords: Vec<(u32, String)> = tokens
+            .iter()
+            .filter(|t| t.3 == 3)
+            .map(|t| (t.0, text(t)))
+            .collect();
+        assert!(keywords.contains(&(1, "let".to_string())));
+        assert!(keywords.contains(&(2, "then".to_string())));
+        assert_eq!(tokens.iter().find(|t| t.0 == 1).unwrap().1, 1);
+    }
+
+    #[test]
+    fn columns_count_utf16_units_after_cp437_characters() {
+        // '║' is three bytes in UTF-8 but one column for the client
+        let tokens = absolute(&parse_and_collect("print \"║\", X\n"));
+        let string = tokens.iter().find(|t| t.3 == 5).unwrap();
+        assert_eq!((string.1, string.2), (6, 3));
+        let variable = tokens.iter().find(|t| t.3 == 1).unwrap();
+        assert_eq!((variable.1, variable.2), (11, 1));
+    }
 }
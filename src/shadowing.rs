@@ -0,0 +1,196 @@
+//! Names that hide others. A user function or variable named after a
+//! builtin (`def fnVal`, `dim Len`) reads like the builtin at every use,
+//! and a DEF LIBRARY function with the same name as another library's but
+//! a different signature breaks whichever caller loads the other one.
+
+use std::collections::HashSet;
+
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Url};
+
+use crate::builtins;
+use crate::diagnostics::related;
+use crate::extract::FunctionDef;
+use crate::parser::{self, DiagnosticNodes};
+use crate::workspace::WorkspaceIndex;
+
+/// The builtin a user name matches, by its own casing.
+fn builtin_named(name: &str) -> Option<&'static str> {
+    builtins::lookup(name).first().map(|f| f.name.as_str())
+}
+
+/// Functions whose name after `fn` is a builtin's, and variables, arrays
+/// and parameters named like one. Each variable is reported where it first
+/// appears.
+pub fn check_builtin_shadowing(
+    nodes: &DiagnosticNodes,
+    source: &str,
+    defs: &[FunctionDef],
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for def in defs.iter().filter(|d| !d.is_import_only) {
+        let Some(builtin) = def
+            .name
+            .get(..2)
+            .filter(|prefix| prefix.eq_ignore_ascii_case("fn"))
+            .and_then(|_| builtin_named(&def.name[2..]))
+        else {
+            continue;
+        };
+        diagnostics.push(Diagnostic {
+            range: def.selection_range,
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String("builtin-shadowing".to_string())),
+            message: format!(
+                "Function '{}' is named after the builtin '{builtin}'",
+                def.name
+            ),
+            ..Default::default()
+        });
+    }
+
+    let mut names: Vec<_> = nodes
+        .var_ref_names
+        .iter()
+        .chain(&nodes.dim_var_ref_names)
+        .chain(&nodes.param_ident_names)
+        .collect();
+    names.sort_by_key(|n| n.start_byte());
+    let mut seen = HashSet::new();
+    for node in names {
+        let Ok(name) = node.utf8_text(source.as_bytes()) else {
+            continue;
+        };
+        if !seen.insert(name.to_ascii_lowercase()) {
+            continue;
+        }
+        let Some(builtin) = builtin_named(name) else {
+            continue;
+        };
+        diagnostics.push(Diagnostic {
+            range: parser::node_range(*node),
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String("builtin-shadowing".to_string())),
+            message: format!("Variable '{name}' has the same name as the builtin '{builtin}'"),
+            ..Default::default()
+        });
+    }
+
+    diagnostics
+}
+
+/// Whether two functions take the same parameters: the same kinds, in the
+/// same order, optional and by reference in the same places.
+fn same_signature(a: &FunctionDef, b: &FunctionDef) -> bool {
+    a.params.len() == b.params.len()
+        && a.params.iter().zip(&b.params).all(|(a, b)| {
+            a.kind == b.kind && a.is_optional == b.is_optional && a.is_reference == b.is_reference
+        })
+}
+
+/// DEF LIBRARY functions in this file (`uri`) that another indexed file
+/// also exports under the same name with different parameters.
+pub fn check_library_shadowing(
+    defs: &[FunctionDef],
+    index: &WorkspaceIndex,
+    uri: &Url,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for def in defs.iter().filter(|d| d.is_library && !d.is_import_only) {
+        let Some(other) = index.lookup(&def.name).iter().find(|other| {
+            &other.uri != uri
+                && other.def.is_library
+                && !other.def.is_import_only
+                && !same_signature(def, &other.def)
+        }) else {
+            continue;
+        };
+        let file = other
+            .uri
+            .path_segments()
+            .and_then(|mut s| s.next_back())
+            .unwrap_or_default();
+        diagnostics.push(Diagnostic {
+            range: def.selection_range,
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String("library-shadowing".to_string())),
+            message: format!(
+                "Library function '{}' differs from '{}' exported by {file}",
+                def.format_signature(),
+                other.def.format_signature()
+            ),
+            related_information: Some(related(
+                &other.uri,
+                other.def.selection_range,
+                format!("'{}' is defined here", other.def.format_signature()),
+            )),
+            ..Default::default()
+        });
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract;
+
+    fn messages(diagnostics: &[Diagnostic]) -> Vec<(u32, String)> {
+        diagnostics
+            .iter()
+            .map(|d| (d.range.start.line, d.message.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn flags_functions_and_variables_named_after_builtins() {
+        let source = "dim Len, Name$*20\nVal = 5\nlet Len = Len + 1\ndef fnVal(Len$) = 1\ndef fnTotal(X) = X\n";
+        let tree = parser::parse(&mut parser::new_parser(), source, None).unwrap();
+        let nodes = parser::collect_diagnostic_nodes(&tree, source);
+        let defs = extract::extract_definitions(&tree, source);
+        let found = check_builtin_shadowing(&nodes, source, &defs);
+        assert_eq!(
+            messages(&found),
+            vec![
+                (
+                    3,
+                    "Function 'fnVal' is named after the builtin 'Val'".to_string()
+                ),
+                (
+                    0,
+                    "Variable 'Len' has the same name as the builtin 'Len'".to_string()
+                ),
+                (
+                    1,
+                    "Variable 'Val' has the same name as the builtin 'Val'".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn flags_library_functions_exported_elsewhere_with_other_parameters() {
+        let parse = |source: &str| {
+            let tree = parser::parse(&mut parser::new_parser(), source, None).unwrap();
+            extract::extract_definitions(&tree, source)
+        };
+        let uri = Url::parse("file:///ws/mine.brs").unwrap();
+        let same = Url::parse("file:///ws/same.brs").unwrap();
+        let other = Url::parse("file:///ws/other.brs").unwrap();
+        let mut index = WorkspaceIndex::new();
+        index.add_file(&same, parse("def library fnA(X)\nfnend\n"));
+        index.add_file(&other, parse("def library fnB(X, &Y$)\nfnend\n"));
+
+        let defs = parse("def library fnA(Y)\nfnend\ndef library fnB(X)\nfnend\n");
+        index.add_file(&uri, defs.clone());
+        let found = check_library_shadowing(&defs, &index, &uri);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].range.start.line, 2);
+        assert_eq!(
+            found[0].message,
+            "Library function 'fnB(X)' differs from 'fnB(X, &Y$)' exported by other.brs"
+        );
+        let related = found[0].related_information.as_ref().unwrap();
+        assert_eq!(related[0].location.uri, other);
+    }
+}
@@ -0,0 +1,201 @@
+//! Snippets a workspace shares through `.br-lsp/snippets.json`, in the
+//! format VS Code uses for its own snippet files:
+//!
+//! ```json
+//! {
+//!   "Open customer file": {
+//!     "prefix": ["opencust", "oc"],
+//!     "body": ["open #${1:1}: \"name=${2:customer.dat},kfname=${3:customer.idx},shr\", internal, outin, keyed", "$0"],
+//!     "description": "Open the customer file by key"
+//!   }
+//! }
+//! ```
+//!
+//! They are offered as completions wherever statements are.
+
+use std::path::Path;
+
+use serde::Deserialize;
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, Documentation, InsertTextFormat, MarkupContent, MarkupKind,
+    Url,
+};
+
+/// Where a workspace folder keeps its snippets
+pub const SNIPPETS_FILE: &str = ".br-lsp/snippets.json";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snippet {
+    /// The snippet's key in the file
+    pub name: String,
+    /// Words that complete to it
+    pub prefixes: Vec<String>,
+    /// Text to insert, in LSP snippet syntax
+    pub body: String,
+    pub description: Option<String>,
+}
+
+/// A string or a list of strings, as `prefix` and `body` may be given.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            Self::One(s) => vec![s],
+            Self::Many(v) => v,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SnippetEntry {
+    prefix: Option<OneOrMany>,
+    body: OneOrMany,
+    description: Option<String>,
+}
+
+/// Parse a snippets file. Entries without a prefix are skipped, since
+/// nothing could complete to them.
+pub fn parse(text: &str) -> Result<Vec<Snippet>, String> {
+    let entries: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let mut snippets = Vec::new();
+    for (name, value) in entries {
+        let entry: SnippetEntry =
+            serde_json::from_value(value).map_err(|e| format!("\"{name}\": {e}"))?;
+        let prefixes: Vec<String> = entry
+            .prefix
+            .map(OneOrMany::into_vec)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|p| !p.trim().is_empty())
+            .collect();
+        if prefixes.is_empty() {
+            continue;
+        }
+        snippets.push(Snippet {
+            name,
+            prefixes,
+            body: entry.body.into_vec().join("\n"),
+            description: entry.description,
+        });
+    }
+    Ok(snippets)
+}
+
+/// Snippets from every workspace folder that has a snippets file, with an
+/// error message for each file that couldn't be read or parsed.
+pub fn load(folders: &[Url]) -> (Vec<Snippet>, Vec<String>) {
+    let mut snippets = Vec::new();
+    let mut errors = Vec::new();
+    for folder in folders {
+        let Ok(root) = folder.to_file_path() else {
+            continue;
+        };
+        let path = root.join(SNIPPETS_FILE);
+        if !path.is_file() {
+            continue;
+        }
+        match std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| parse(&text))
+        {
+            Ok(found) => snippets.extend(found),
+            Err(e) => errors.push(format!("{}: {e}", path.display())),
+        }
+    }
+    (snippets, errors)
+}
+
+/// Whether `path` is a workspace snippets file.
+pub fn is_snippets_file(path: &Path) -> bool {
+    path.ends_with(SNIPPETS_FILE)
+}
+
+/// One completion per prefix of each snippet.
+pub fn completion_items(snippets: &[Snippet]) -> Vec<CompletionItem> {
+    snippets
+        .iter()
+        .flat_map(|snippet| {
+            snippet.prefixes.iter().map(|prefix| CompletionItem {
+                label: prefix.clone(),
+                kind: Some(CompletionItemKind::SNIPPET),
+                detail: Some(snippet.name.clone()),
+                documentation: Some(Documentation::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: documentation(snippet),
+                })),
+                insert_text: Some(snippet.body.clone()),
+                insert_text_format: Some(InsertTextFormat::SNIPPET),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+fn documentation(snippet: &Snippet) -> String {
+    let body = format!("```br\n{}\n```", snippet.body);
+    match &snippet.description {
+        Some(description) => format!("{description}\n\n{body}"),
+        None => body,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_single_and_list_forms() {
+        let text = r#"{
+            "Error handler": {
+                "prefix": "onerr",
+                "body": "on error goto ${1:ErrHandler}",
+                "description": "Route errors to a handler"
+            },
+            "Open customer": {
+                "prefix": ["opencust", "oc"],
+                "body": ["open #${1:1}: \"name=customer.dat\", internal, input", "$0"]
+            },
+            "No prefix": { "body": "print" }
+        }"#;
+        let snippets = parse(text).unwrap();
+        assert_eq!(snippets.len(), 2);
+        let open = snippets.iter().find(|s| s.name == "Open customer").unwrap();
+        assert_eq!(open.prefixes, vec!["opencust", "oc"]);
+        assert_eq!(
+            open.body,
+            "open #${1:1}: \"name=customer.dat\", internal, input\n$0"
+        );
+
+        let items = completion_items(&snippets);
+        assert_eq!(items.len(), 3);
+        assert!(items
+            .iter()
+            .all(|i| i.insert_text_format == Some(InsertTextFormat::SNIPPET)));
+        let onerr = items.iter().find(|i| i.label == "onerr").unwrap();
+        assert_eq!(onerr.detail.as_deref(), Some("Error handler"));
+        assert_eq!(
+            onerr.insert_text.as_deref(),
+            Some("on error goto ${1:ErrHandler}")
+        );
+    }
+
+    #[test]
+    fn malformed_files_are_errors() {
+        assert!(parse("[1, 2]").is_err());
+        let err = parse(r#"{"Broken": {"prefix": "x"}}"#).unwrap_err();
+        assert!(err.starts_with("\"Broken\""), "{err}");
+    }
+
+    #[test]
+    fn recognizes_the_snippets_path() {
+        assert!(is_snippets_file(Path::new("/ws/.br-lsp/snippets.json")));
+        assert!(!is_snippets_file(Path::new("/ws/snippets.json")));
+    }
+}
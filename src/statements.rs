@@ -0,0 +1,892 @@
+//! What the server knows about each BR statement: its keyword, grammar
+//! node, category, the block it opens, the clauses it takes and its
+//! documentation. Completion, hover, folding, block auto-close and keyword
+//! formatting all read it from here.
+
+use tree_sitter::Node;
+
+use crate::builtins::BrVersion;
+use crate::diagnostics::is_inline_def;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Control,
+    Function,
+    FileIo,
+    Screen,
+    Data,
+    ErrorHandling,
+    Program,
+    Debugging,
+}
+
+impl Category {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Control => "Control flow",
+            Self::Function => "Functions",
+            Self::FileIo => "File I/O",
+            Self::Screen => "Screen I/O",
+            Self::Data => "Data",
+            Self::ErrorHandling => "Error handling",
+            Self::Program => "Program control",
+            Self::Debugging => "Debugging",
+        }
+    }
+}
+
+/// A statement that opens a block, closed by a later statement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Block {
+    Function,
+    Do,
+    For,
+    If,
+}
+
+impl Block {
+    /// The statement that closes the block, as typed
+    pub fn closer(self) -> &'static str {
+        match self {
+            Self::Function => "fnend",
+            Self::Do => "loop",
+            Self::For => "next",
+            Self::If => "end if",
+        }
+    }
+
+    /// The block a statement of `kind` closes.
+    pub fn closed_by(kind: &str) -> Option<Self> {
+        match kind {
+            "fnend_statement" | "end_def_statement" => Some(Self::Function),
+            "loop_statement" => Some(Self::Do),
+            "next_statement" => Some(Self::For),
+            "end_if_statement" => Some(Self::If),
+            _ => None,
+        }
+    }
+}
+
+/// Statements of a `line` node, skipping separators and line numbers.
+pub fn line_statements(line: Node) -> Vec<Node> {
+    let mut cursor = line.walk();
+    line.named_children(&mut cursor)
+        .filter(|n| n.kind().ends_with("_statement"))
+        .collect()
+}
+
+/// The block opened by `stmt`, if it starts one spanning following lines:
+/// a DEF without `=`, DO, FOR, or an IF with nothing after THEN.
+pub fn opened_block(stmt: Node) -> Option<Block> {
+    let block = for_node_kind(stmt.kind())?.opens?;
+    match block {
+        Block::Function if is_inline_def(stmt) => None,
+        Block::If => {
+            let last = stmt.child(stmt.child_count().checked_sub(1)?)?;
+            (last.kind() == "keyword").then_some(Block::If)
+        }
+        _ => Some(block),
+    }
+}
+
+pub struct Statement {
+    /// Keyword as offered in completions
+    pub keyword: &'static str,
+    /// Grammar node of the statement, when the grammar has one
+    pub node_kind: Option<&'static str>,
+    pub category: Category,
+    pub signature: &'static str,
+    pub documentation: &'static str,
+    pub doc_url: &'static str,
+    pub example: &'static str,
+    /// Block the statement can open
+    pub opens: Option<Block>,
+    /// Keywords valid in the statement's clause list, with descriptions
+    pub clauses: &'static [(&'static str, &'static str)],
+    /// First BR version with the statement (e.g. `"4.2"`); `None` means
+    /// always available
+    pub since: Option<&'static str>,
+}
+
+impl Statement {
+    pub fn since_version(&self) -> Option<BrVersion> {
+        self.since.and_then(BrVersion::parse)
+    }
+
+    /// Whether the statement exists in `version`, like
+    /// [`BuiltinFunction::available_in`](crate::builtins::BuiltinFunction::available_in).
+    pub fn available_in(&self, version: Option<BrVersion>) -> bool {
+        match (version, self.since_version()) {
+            (Some(version), Some(since)) => since <= version,
+            _ => true,
+        }
+    }
+
+    /// Documentation, version, link and example as markdown; `None` when
+    /// the entry has none of them.
+    pub fn markdown(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if !self.documentation.is_empty() {
+            parts.push(self.documentation.to_string());
+        }
+        if let Some(since) = self.since_version() {
+            parts.push(format!("*Available since BR {since}*"));
+        }
+        if !self.doc_url.is_empty() {
+            parts.push(format!("[Documentation]({})", self.doc_url));
+        }
+        if !self.example.is_empty() {
+            parts.push(format!("```br\n{}\n```", self.example));
+        }
+        (!parts.is_empty()).then(|| parts.join("\n\n"))
+    }
+
+    /// Hover text: signature, category and documentation.
+    pub fn hover_markdown(&self) -> String {
+        let mut md = format!(
+            "```br\n{}\n```\n\n*{} statement*",
+            self.signature,
+            self.category.label()
+        );
+        if let Some(docs) = self.markdown() {
+            md.push_str("\n\n---\n\n");
+            md.push_str(&docs);
+        }
+        md
+    }
+}
+
+/// The first statement parsed as `kind`. Several entries share a node
+/// (INPUT, INPUT FIELDS and INPUT SELECT), and the first is the general one.
+pub fn for_node_kind(kind: &str) -> Option<&'static Statement> {
+    STATEMENTS.iter().find(|s| s.node_kind == Some(kind))
+}
+
+/// The statement written `keyword`, ignoring case and spacing.
+pub fn for_keyword(keyword: &str) -> Option<&'static Statement> {
+    let words: Vec<&str> = keyword.split_whitespace().collect();
+    STATEMENTS.iter().find(|s| {
+        let mut expected = s.keyword.split_whitespace();
+        words.len() == expected.clone().count()
+            && words
+                .iter()
+                .all(|w| expected.next().is_some_and(|e| e.eq_ignore_ascii_case(w)))
+    })
+}
+
+/// The statements `node` may be. Several entries share a node, and a
+/// statement the grammar has no node for, like SCR_FREEZE, parses as an
+/// implicit LET of a variable named like it.
+pub fn for_statement_node(node: Node, source: &str) -> Vec<&'static Statement> {
+    if node.kind() == "let_statement" {
+        let text = node.utf8_text(source.as_bytes()).unwrap_or("");
+        return for_keyword(text)
+            .filter(|s| s.node_kind.is_none())
+            .into_iter()
+            .collect();
+    }
+    STATEMENTS
+        .iter()
+        .filter(|s| s.node_kind == Some(node.kind()))
+        .collect()
+}
+
+/// Keywords valid in the clause list of a statement parsed as `kind`.
+pub fn clauses(kind: &str) -> &'static [(&'static str, &'static str)] {
+    for_node_kind(kind).map_or(&[], |s| s.clauses)
+}
+
+pub const STATEMENTS: &[Statement] = &[
+    Statement {
+        keyword: "do",
+        node_kind: Some("do_statement"),
+        category: Category::Control,
+        signature: "Do [While|Until <condition>]",
+        documentation: "Starts a loop that runs until its LOOP statement, optionally while or until a condition holds.",
+        doc_url: "http://brwiki2.brulescorp.com/index.php?title=Do",
+        example: "do while X < 10\n\tlet X += 1\nloop",
+        opens: Some(Block::Do),
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "loop",
+        node_kind: Some("loop_statement"),
+        category: Category::Control,
+        signature: "Loop [While|Until <condition>]",
+        documentation: "Ends a DO loop, optionally repeating only while or until a condition holds.",
+        doc_url: "http://brwiki2.brulescorp.com/index.php?title=Loop",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "if",
+        node_kind: Some("if_statement"),
+        category: Category::Control,
+        signature: "If <condition> Then ... [Else ...] [End If]",
+        documentation: "Runs statements when a condition is true. With nothing after THEN, the block runs to its END IF.",
+        doc_url: "http://brwiki2.brulescorp.com/index.php?title=If",
+        example: "if X > 0 then\n\tprint X\nend if",
+        opens: Some(Block::If),
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "end if",
+        node_kind: Some("end_if_statement"),
+        category: Category::Control,
+        signature: "End If",
+        documentation: "Ends a multi-line IF block.",
+        doc_url: "http://brwiki2.brulescorp.com/index.php?title=End_If",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "def",
+        node_kind: Some("def_statement"),
+        category: Category::Function,
+        signature: "Def Statement",
+        documentation: "Defines function.",
+        doc_url: "http://www.brwiki.com/index.php?title=Def",
+        example: "def fnfoo(bar)\n\t! body\nfnend",
+        opens: Some(Block::Function),
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "def library",
+        node_kind: Some("def_statement"),
+        category: Category::Function,
+        signature: "Def Library Fn ... fnend",
+        documentation: "Define library function",
+        doc_url: "http://www.brwiki.com/index.php?title=Def",
+        example: "",
+        opens: Some(Block::Function),
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Chain",
+        node_kind: Some("chain_statement"),
+        category: Category::Program,
+        signature: "Chain {<program name>|\"PROC=<name>\"|\"SUPROC=<name>\"} ...",
+        documentation: "Loads and Runs the target program, immediately ending the current program. Optionally passes variables and files into the called program.",
+        doc_url: "http://brwiki2.brulescorp.com/index.php?title=Chain",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Close",
+        node_kind: Some("close_statement"),
+        category: Category::FileIo,
+        signature: "Close {#<file/window number>} [,Free|Drop] [, ...] :",
+        documentation: "The Close (CL) statement deactivates access to a data or window file for input or output.",
+        doc_url: "http://www.brwiki.com/index.php?search=Close",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Continue",
+        node_kind: Some("continue_statement"),
+        category: Category::ErrorHandling,
+        signature: "Continue Statement",
+        documentation: "Jumps to the line following the line that had the most recent error. Used to continue in an Error Handler.",
+        doc_url: "http://www.brwiki.com/index.php?search=Continue",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Data",
+        node_kind: Some("data_statement"),
+        category: Category::Data,
+        signature: "Data {\"<string constant>\"|<numeric constant>}[,...]",
+        documentation: "The Data statement can be used to populate the values of variables.",
+        doc_url: "http://www.brwiki.com/index.php?search=Data",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Delete",
+        node_kind: Some("delete_statement"),
+        category: Category::FileIo,
+        signature: "Delete",
+        documentation: "Deletes the currently locked record from the identified data file..",
+        doc_url: "http://brwiki2.brulescorp.com/index.php?title=Delete_(statement)",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Dim",
+        node_kind: Some("dim_statement"),
+        category: Category::Data,
+        signature: "Dim",
+        documentation: "Declares Variables and Arrays. Arrays must be declared if they have other then 10 messages.",
+        doc_url: "http://www.brwiki.com/index.php?search=Dim",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Display",
+        node_kind: Some("display_statement"),
+        category: Category::Screen,
+        signature: "Display [Menu|Buttons] ...",
+        documentation: "Display or Update the Windows Menu, or the Button Rows.",
+        doc_url: "http://www.brwiki.com/index.php?search=Display",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "End",
+        node_kind: Some("end_statement"),
+        category: Category::Program,
+        signature: "End",
+        documentation: "Ends your program (continuing with any proc files that ran your program, or stopping if your program wasn't run from a proc.)",
+        doc_url: "http://www.brwiki.com/index.php?search=End",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Execute",
+        node_kind: Some("execute_statement"),
+        category: Category::Program,
+        signature: "Execute \"BR Command\"",
+        documentation: "Executes a Command from within one of your programs.",
+        doc_url: "http://www.brwiki.com/index.php?search=Execute",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Exit",
+        node_kind: Some("exit_statement"),
+        category: Category::ErrorHandling,
+        signature: "Exit <error condition line ref>[,...]",
+        documentation: "Works in conjunction with the Exit error condition to list a bunch of error handlers in one place.",
+        doc_url: "http://brwiki2.brulescorp.com/index.php?title=Exit",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Exit Do",
+        node_kind: Some("exit_do_statement"),
+        category: Category::Control,
+        signature: "Exit Do Statement",
+        documentation: "Jumps out of a do loop to the line following the loop.",
+        doc_url: "http://brwiki2.brulescorp.com/index.php?title=Exit_do",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Fnend",
+        node_kind: Some("fnend_statement"),
+        category: Category::Function,
+        signature: "Fnend Statement",
+        documentation: "The FnEnd (FN) and End Def statements indicates the end of a definition of a multi-lined user defined function.",
+        doc_url: "http://brwiki2.brulescorp.com/index.php?title=Fnend",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Print",
+        node_kind: Some("print_statement"),
+        category: Category::Screen,
+        signature: "Print Statement",
+        documentation: "Prints a line to the console, or to a specific file.",
+        doc_url: "http://www.brwiki.com/index.php?search=Print",
+        example: "",
+        opens: None,
+        clauses: PRINT_KEYWORDS,
+        since: None,
+    },
+    Statement {
+        keyword: "Input",
+        node_kind: Some("input_statement"),
+        category: Category::Screen,
+        signature: "Input <Variables>",
+        documentation: "Reads text from the user or from a display file (like a text file). It can also read text from a proc file, if the program is called from a proc.",
+        doc_url: "http://www.brwiki.com/index.php?search=Input",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Linput",
+        node_kind: Some("linput_statement"),
+        category: Category::FileIo,
+        signature: "Linput <StringVariable>",
+        documentation: "Reads a line of text from a display file. This is useful for parsing CSV files and other files generated by external applications.",
+        doc_url: "http://www.brwiki.com/index.php?search=Linput",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Input",
+        node_kind: Some("input_statement"),
+        category: Category::Screen,
+        signature: "Input Fields",
+        documentation: "Activates a bunch of controls on the screen and pauses execution, allowing the user to interact with them. This is the primary way that BR programs interact with the User.",
+        doc_url: "http://brwiki2.brulescorp.com/index.php?title=Input_Fields",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Rinput",
+        node_kind: Some("rinput_statement"),
+        category: Category::Screen,
+        signature: "Rinput Fields",
+        documentation: "Updates and then activates a bunch of controls on the screen and pauses execution, allowing the user to interact with them. This is the primary way that BR programs interact with the User.",
+        doc_url: "http://www.brwiki.com/index.php?search=Rinput",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Input",
+        node_kind: Some("input_statement"),
+        category: Category::Screen,
+        signature: "Input Select",
+        documentation: "Activates a bunch of controls and allows the user to select one of them.",
+        doc_url: "http://brwiki2.brulescorp.com/index.php?title=Input_Select",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Rinput",
+        node_kind: Some("rinput_statement"),
+        category: Category::Screen,
+        signature: "Rinput Select",
+        documentation: "Activates and Displays a bunch of controls and allows the user to select one of them.",
+        doc_url: "http://brwiki2.brulescorp.com/index.php?title=Rinput_select",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Form",
+        node_kind: Some("form_statement"),
+        category: Category::FileIo,
+        signature: "Form",
+        documentation: "The Form statement is used in conjunction with PRINT, WRITE, REWRITE, READ or REREAD statements to format input or output. FORM controls the size, location, field length and format of input or output.",
+        doc_url: "http://www.brwiki.com/index.php?search=Form",
+        example: "",
+        opens: None,
+        clauses: FORM_SPECS,
+        since: None,
+    },
+    Statement {
+        keyword: "Gosub",
+        node_kind: Some("gosub_statement"),
+        category: Category::Control,
+        signature: "Gosub <LineLabel/LineNumber>",
+        documentation: "Calls a subroutine, which runs until it encounters a return statement, at which point it returns here.",
+        doc_url: "http://www.brwiki.com/index.php?search=Gosub",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Goto",
+        node_kind: Some("goto_statement"),
+        category: Category::Control,
+        signature: "Goto <LineLabel/LineNumber>",
+        documentation: "Jumps to the target line and continues running from there. (Try not to use Goto Statements. This is not the 80s.).",
+        doc_url: "http://www.brwiki.com/index.php?search=Goto",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Library",
+        node_kind: Some("library_statement"),
+        category: Category::Function,
+        signature: "Library \"<Library>\" : <fnFunction1> [, fnFunction2] [, ...]",
+        documentation: "Loads a BR Libary, allowing access to the library functions in it.",
+        doc_url: "http://www.brwiki.com/index.php?search=Library",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Mat",
+        node_kind: Some("mat_statement"),
+        category: Category::Data,
+        signature: "Mat <array name> [(<dimension>[,...])] = ....",
+        documentation: "The Mat statement is used for working with Arrays. Its used to resize arrays, sort them (in conjunction with AIDX or DIDX), copy them, and process them in lots of other ways.",
+        doc_url: "http://www.brwiki.com/index.php?search=Mat",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "On",
+        node_kind: Some("on_statement"),
+        category: Category::ErrorHandling,
+        signature: "On {<error condition>|Fkey <n>} {Goto|Gosub|Ignore|System} ...",
+        documentation: "Sets what happens when an error condition or function key occurs, or jumps to one of several lines depending on a value.",
+        doc_url: "http://brwiki2.brulescorp.com/index.php?title=On",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Open",
+        node_kind: Some("open_statement"),
+        category: Category::FileIo,
+        signature: "Open #<FileNumber> \"Name=...\"",
+        documentation: "Opens a file or window or http connection or comm port.",
+        doc_url: "http://www.brwiki.com/index.php?search=Open",
+        example: "",
+        opens: None,
+        clauses: OPEN_CLAUSES,
+        since: None,
+    },
+    Statement {
+        keyword: "Pause",
+        node_kind: Some("pause_statement"),
+        category: Category::Debugging,
+        signature: "Pause",
+        documentation: "Pauses program execution allows the programmer to interact with the program in the Command Console.",
+        doc_url: "http://brwiki2.brulescorp.com/index.php?title=Pause",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Randomize",
+        node_kind: Some("randomize_statement"),
+        category: Category::Data,
+        signature: "Randomize",
+        documentation: "Generates a new Random Number Seed for the Random Number Generator (based on the system clock so as to be truly random).",
+        doc_url: "http://www.brwiki.com/index.php?search=Randomize",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Read",
+        node_kind: Some("read_statement"),
+        category: Category::FileIo,
+        signature: "Read Statement",
+        documentation: "Reads data",
+        doc_url: "http://www.brwiki.com/index.php?search=Read",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Reread",
+        node_kind: Some("reread_statement"),
+        category: Category::FileIo,
+        signature: "Reread  #<file number> [, USING {<formStatement>}] : <Variables>",
+        documentation: "Rereads the previous record read again, in the selected data file or data statements, storing the information in the variables provided.",
+        doc_url: "http://www.brwiki.com/index.php?search=Reread",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Write",
+        node_kind: Some("write_statement"),
+        category: Category::FileIo,
+        signature: "Write  #<file number> [, USING {<formStatement>}] : <Variables>",
+        documentation: "Adds a record to the file containing the information from the variables you list.",
+        doc_url: "http://www.brwiki.com/index.php?search=Write",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Rewrite",
+        node_kind: Some("rewrite_statement"),
+        category: Category::FileIo,
+        signature: "Rewrite  #<file number> [, USING {<formStatement>}] : <Variables>",
+        documentation: "Updates the record that is locked in the file (usually the last record read), with the data in the variables now.",
+        doc_url: "http://www.brwiki.com/index.php?search=Rewrite",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Restore",
+        node_kind: Some("restore_statement"),
+        category: Category::FileIo,
+        signature: "Restore  #<file number> [,<Key|Rec|Pos|Search> = <SearchValue|Position>:",
+        documentation: "Jumps to the beginning (or other specified point) in the targeted file.",
+        doc_url: "http://www.brwiki.com/index.php?search=Restore",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Retry",
+        node_kind: Some("retry_statement"),
+        category: Category::ErrorHandling,
+        signature: "Retry",
+        documentation: "Jumps to the line that had the most recent error. Used to try again in an Error Handler.",
+        doc_url: "http://www.brwiki.com/index.php?search=Retry",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Return",
+        node_kind: Some("return_statement"),
+        category: Category::Control,
+        signature: "Return",
+        documentation: "Exits a Subroutine and returns control back up to the code following the Gosub statement.",
+        doc_url: "http://www.brwiki.com/index.php?search=Return",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Scr_Freeze",
+        node_kind: None,
+        category: Category::Screen,
+        signature: "Scr_Freeze",
+        documentation: "Stops the screen from updating, significantly increasing the speed of the programs. The screen starts running again at the next Input Statement or Scr_Thaw statement.",
+        doc_url: "http://www.brwiki.com/index.php?search=Scr_freeze",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: Some("4.2"),
+    },
+    Statement {
+        keyword: "Scr_Thaw",
+        node_kind: None,
+        category: Category::Screen,
+        signature: "Scr_Thaw",
+        documentation: "Causes the screen to refresh and begin updating again after it was frozen with a Scr_Freeze command.",
+        doc_url: "http://www.brwiki.com/index.php?search=Scr_thaw",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: Some("4.2"),
+    },
+    Statement {
+        keyword: "Stop",
+        node_kind: Some("stop_statement"),
+        category: Category::Program,
+        signature: "Stop",
+        documentation: "Ends your program (continuing with any proc files that ran your program, or stopping if your program wasn't run from a proc.)",
+        doc_url: "http://www.brwiki.com/index.php?search=Stop",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Trace",
+        node_kind: Some("trace_statement"),
+        category: Category::Debugging,
+        signature: "Trace [On|Off|Print]",
+        documentation: "Displays or outputs the line numbers as they're executed. Used for debugging code, but the modern debugging tools are much better.",
+        doc_url: "http://www.brwiki.com/index.php?search=Trace",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "For",
+        node_kind: Some("for_statement"),
+        category: Category::Control,
+        signature: "For <variable> = <start> To <end> [Step <increment>]",
+        documentation: "Repeats the statements up to its NEXT, stepping a variable from a start value to an end value.",
+        doc_url: "http://brwiki2.brulescorp.com/index.php?title=For",
+        example: "for I = 1 to 10\n\tprint I\nnext I",
+        opens: Some(Block::For),
+        clauses: &[],
+        since: None,
+    },
+    Statement {
+        keyword: "Next",
+        node_kind: Some("next_statement"),
+        category: Category::Control,
+        signature: "Next <variable>",
+        documentation: "Ends a FOR loop, stepping its variable and repeating while it hasn't passed the end value.",
+        doc_url: "http://brwiki2.brulescorp.com/index.php?title=Next",
+        example: "",
+        opens: None,
+        clauses: &[],
+        since: None,
+    },
+];
+
+const OPEN_CLAUSES: &[(&str, &str)] = &[
+    ("internal", "Record-oriented file access"),
+    ("external", "Byte-oriented file access"),
+    ("input", "Open for reading only"),
+    ("output", "Open for writing only"),
+    ("outin", "Open for reading and writing"),
+    ("relative", "Access records by record number"),
+    ("keyed", "Access records through a key file"),
+];
+
+const FORM_SPECS: &[(&str, &str)] = &[
+    ("pos", "Position to a column"),
+    ("skip", "Skip lines or records"),
+    ("x", "Skip bytes"),
+    ("c", "Character string"),
+    ("v", "Character string, trailing blanks trimmed"),
+    ("g", "General numeric"),
+    ("gz", "General numeric with leading zeros"),
+    ("n", "Numeric display"),
+    ("nz", "Numeric display, blank when zero"),
+    ("pic", "Picture format"),
+    ("pd", "Packed decimal"),
+    ("zd", "Zoned decimal"),
+    ("b", "Binary"),
+    ("bh", "Binary, high-order byte first"),
+    ("bl", "Binary, low-order byte first"),
+    ("d", "Double-precision floating point"),
+    ("s", "Single-precision floating point"),
+    ("l", "Long floating point"),
+];
+
+const PRINT_KEYWORDS: &[(&str, &str)] = &[
+    ("using", "Format the output with a FORM statement"),
+    ("border", "Draw a border around the window"),
+    ("fields", "Write to screen fields"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn lookups_by_node_and_keyword() {
+        assert_eq!(
+            for_node_kind("input_statement").unwrap().signature,
+            "Input <Variables>"
+        );
+        assert_eq!(for_keyword("EXIT  do").unwrap().keyword, "Exit Do");
+        assert!(for_keyword("exit").is_some_and(|s| s.keyword == "Exit"));
+        assert!(for_keyword("frobnicate").is_none());
+        assert_eq!(clauses("form_statement"), FORM_SPECS);
+        assert!(clauses("goto_statement").is_empty());
+
+        let hover = for_node_kind("do_statement").unwrap().hover_markdown();
+        assert!(hover
+            .starts_with("```br\nDo [While|Until <condition>]\n```\n\n*Control flow statement*"));
+        assert!(hover.contains("[Documentation]("));
+    }
+
+    #[test]
+    fn statements_by_node() {
+        let source = "do\nloop\nscr_freeze\nlet X = 1\ninput X\n";
+        let tree = parser::parse(&mut parser::new_parser(), source, None).unwrap();
+        let root = tree.root_node();
+        let mut cursor = root.walk();
+        let keywords: Vec<Vec<&str>> = root
+            .children(&mut cursor)
+            .filter_map(|line| line.named_child(0))
+            .map(|stmt| {
+                for_statement_node(stmt, source)
+                    .iter()
+                    .map(|s| s.keyword)
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            keywords,
+            vec![
+                vec!["do"],
+                vec!["loop"],
+                vec!["Scr_Freeze"],
+                vec![],
+                vec!["Input", "Input", "Input"],
+            ]
+        );
+    }
+
+    #[test]
+    fn statements_gated_by_version() {
+        let freeze = for_keyword("scr_freeze").unwrap();
+        assert!(freeze.available_in(None));
+        assert!(!freeze.available_in(BrVersion::parse("4.1")));
+        assert!(freeze.available_in(BrVersion::parse("4.2")));
+        assert!(freeze
+            .markdown()
+            .unwrap()
+            .contains("*Available since BR 4.2*"));
+        assert!(for_keyword("print")
+            .unwrap()
+            .available_in(BrVersion::parse("4.1")));
+    }
+
+    #[test]
+    fn blocks_open_only_across_lines() {
+        let source = "def fnA = 1\ndef fnB\nif X then print 1\nif X then\nfor I = 1 to 3\n";
+        let tree = parser::parse(&mut parser::new_parser(), source, None).unwrap();
+        let root = tree.root_node();
+        let mut cursor = root.walk();
+        let blocks: Vec<Option<Block>> = root
+            .children(&mut cursor)
+            .filter_map(|line| line.named_child(0))
+            .map(opened_block)
+            .collect();
+        assert_eq!(
+            blocks,
+            vec![
+                None,
+                Some(Block::Function),
+                None,
+                Some(Block::If),
+                Some(Block::For)
+            ]
+        );
+        assert_eq!(Block::closed_by("end_def_statement"), Some(Block::Function));
+    }
+}
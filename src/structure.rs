@@ -0,0 +1,261 @@
+//! The block structure of a program, read in one pass over its lines:
+//! functions from DEF to FNEND, the DO, FOR and IF blocks inside them and
+//! the main program, and labels. Missing-FNEND diagnostics, folding,
+//! document symbols and block auto-close all pair openers and closers from
+//! here, so they agree on where a block ends.
+//!
+//! BR doesn't nest functions: a multi-line DEF while another is open leaves
+//! the first without an FNEND. DO, FOR and IF blocks pair innermost first
+//! and never cross a function's DEF or FNEND; blocks still open there, or
+//! inside a block that closes, are left unclosed.
+
+use tower_lsp::lsp_types::{Position, Range};
+use tree_sitter::{Node, Tree};
+
+use crate::parser::node_range;
+use crate::statements::{line_statements, opened_block, Block};
+
+/// A DEF statement and the FNEND or END DEF closing it.
+#[derive(Debug, Clone, Copy)]
+pub struct Function<'tree> {
+    pub def: Node<'tree>,
+    /// `None` for a one-line DEF, or a multi-line one never closed
+    pub end: Option<Node<'tree>>,
+    /// Defined on one line with `=`
+    pub inline: bool,
+}
+
+impl Function<'_> {
+    /// From the DEF through its FNEND; just the DEF statement when it has
+    /// none.
+    pub fn range(&self) -> Range {
+        let def = node_range(self.def);
+        match self.end {
+            Some(end) => Range::new(def.start, node_range(end).end),
+            None => def,
+        }
+    }
+
+    /// The bytes from the DEF through its FNEND, like [`range`](Self::range).
+    pub fn bytes(&self) -> std::ops::Range<usize> {
+        let def = self.def.byte_range();
+        match self.end {
+            Some(end) => def.start..end.end_byte(),
+            None => def,
+        }
+    }
+
+    /// A multi-line DEF with nothing closing it.
+    pub fn is_missing_end(&self) -> bool {
+        !self.inline && self.end.is_none()
+    }
+}
+
+/// A DO, FOR or IF block from its opening line to its closing line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockSpan {
+    pub block: Block,
+    pub start_line: u32,
+    pub end_line: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    /// Without the colon
+    pub name: String,
+    /// The label with its colon
+    pub range: Range,
+}
+
+#[derive(Debug, Default)]
+pub struct Structure<'tree> {
+    /// Every DEF, in source order
+    pub functions: Vec<Function<'tree>>,
+    /// Closed DO, FOR and IF blocks, in the order they close
+    pub blocks: Vec<BlockSpan>,
+    pub labels: Vec<Label>,
+    /// Openers left without a closer, with their line
+    unclosed: Vec<(Block, u32)>,
+    /// Closers with nothing open to close, with their line
+    stray: Vec<(Block, u32)>,
+    /// Lines of multi-line DEFs and their closers, which bound blocks
+    boundaries: Vec<u32>,
+}
+
+impl<'tree> Structure<'tree> {
+    pub fn of(tree: &'tree Tree, source: &str) -> Self {
+        Self::from_root(tree.root_node(), source)
+    }
+
+    pub fn from_root(root: Node<'tree>, source: &str) -> Self {
+        let mut structure = Self::default();
+        let mut open: Vec<(Block, u32)> = Vec::new();
+        let mut open_function: Option<usize> = None;
+
+        let mut cursor = root.walk();
+        for line in root.children(&mut cursor) {
+            let row = line.start_position().row as u32;
+            structure.collect_labels(line, source);
+            for stmt in line_statements(line) {
+                let opens = opened_block(stmt);
+                let closes = Block::closed_by(stmt.kind());
+
+                if closes == Some(Block::Function) || opens == Some(Block::Function) {
+                    structure.boundaries.push(row);
+                    structure.unclosed.append(&mut open);
+                    match open_function.take() {
+                        Some(i) if closes.is_some() => structure.functions[i].end = Some(stmt),
+                        _ if closes.is_some() => structure.stray.push((Block::Function, row)),
+                        _ => {}
+                    }
+                    if opens.is_some() {
+                        open_function = Some(structure.functions.len());
+                        structure.functions.push(Function {
+                            def: stmt,
+                            end: None,
+                            inline: false,
+                        });
+                    }
+                    continue;
+                }
+                if stmt.kind() == "def_statement" {
+                    structure.functions.push(Function {
+                        def: stmt,
+                        end: None,
+                        inline: true,
+                    });
+                }
+
+                if let Some(block) = closes {
+                    match open.iter().rposition(|&(b, _)| b == block) {
+                        Some(at) => {
+                            let (_, start_line) = open[at];
+                            structure.unclosed.extend(open.drain(at + 1..));
+                            open.truncate(at);
+                            structure.blocks.push(BlockSpan {
+                                block,
+                                start_line,
+                                end_line: row,
+                            });
+                        }
+                        None => structure.stray.push((block, row)),
+                    }
+                }
+                if let Some(block) = opens {
+                    open.push((block, row));
+                }
+            }
+        }
+        structure.unclosed.append(&mut open);
+        structure
+    }
+
+    fn collect_labels(&mut self, line: Node, source: &str) {
+        let mut cursor = line.walk();
+        for label in line.children(&mut cursor).filter(|n| n.kind() == "label") {
+            let Ok(text) = label.utf8_text(source.as_bytes()) else {
+                continue;
+            };
+            let name = text.trim_end_matches(':');
+            if !name.is_empty() {
+                self.labels.push(Label {
+                    name: name.to_string(),
+                    range: node_range(label),
+                });
+            }
+        }
+    }
+
+    /// The function whose DEF starts at `position`.
+    pub fn function_at(&self, position: Position) -> Option<&Function<'tree>> {
+        self.functions
+            .iter()
+            .find(|f| node_range(f.def).start == position)
+    }
+
+    /// Whether the `block` opened on `line` already has a closer, for
+    /// deciding whether a newly typed opener needs one. A function needs its
+    /// own FNEND. DO and IF blocks are counted between the surrounding
+    /// function boundaries and are closed once closers there at least
+    /// match openers, so a new opener typed above an existing closer still
+    /// gets its own.
+    pub fn is_closed(&self, block: Block, line: u32) -> bool {
+        if block == Block::Function {
+            return self.functions.iter().any(|f| {
+                !f.inline && f.def.start_position().row as u32 == line && f.end.is_some()
+            });
+        }
+        let from = self
+            .boundaries
+            .iter()
+            .rev()
+            .find(|&&b| b <= line)
+            .copied()
+            .unwrap_or(0);
+        let to = self
+            .boundaries
+            .iter()
+            .find(|&&b| b > line)
+            .copied()
+            .unwrap_or(u32::MAX);
+        let count = |entries: &[(Block, u32)]| {
+            entries
+                .iter()
+                .filter(|&&(b, row)| b == block && (from..to).contains(&row))
+                .count()
+        };
+        count(&self.unclosed) <= count(&self.stray)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    const SOURCE: &str = "\
+Start: do
+  for I = 1 to 3 : next I
+  if X then
+    print X
+loop
+def fnA(X)
+  if X then
+fnend
+def fnB = 1
+def fnC(Y)
+";
+
+    #[test]
+    fn pairs_functions_and_blocks() {
+        let tree = parser::parse(&mut parser::new_parser(), SOURCE, None).unwrap();
+        let structure = Structure::of(&tree, SOURCE);
+
+        let functions: Vec<(u32, u32, bool)> = structure
+            .functions
+            .iter()
+            .map(|f| (f.range().start.line, f.range().end.line, f.is_missing_end()))
+            .collect();
+        assert_eq!(functions, vec![(5, 7, false), (8, 8, false), (9, 9, true)]);
+
+        let blocks: Vec<(Block, u32, u32)> = structure
+            .blocks
+            .iter()
+            .map(|b| (b.block, b.start_line, b.end_line))
+            .collect();
+        // The IF left open inside the DO is dropped when the DO closes
+        assert_eq!(blocks, vec![(Block::For, 1, 1), (Block::Do, 0, 4)]);
+        assert_eq!(structure.labels.len(), 1);
+        assert_eq!(structure.labels[0].name, "Start");
+    }
+
+    #[test]
+    fn openers_count_against_closers_within_a_function() {
+        let source = "do\n  do\nloop\ndef fnA\n  if X then\nend if\nfnend\n";
+        let tree = parser::parse(&mut parser::new_parser(), source, None).unwrap();
+        let structure = Structure::of(&tree, source);
+        assert!(!structure.is_closed(Block::Do, 1));
+        assert!(structure.is_closed(Block::If, 4));
+        assert!(structure.is_closed(Block::Function, 3));
+    }
+}
@@ -0,0 +1,465 @@
+use std::collections::HashSet;
+
+use log::warn;
+use regex::Regex;
+use serde_json::Value;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Range, TextEdit};
+use tree_sitter::{Node, Tree};
+
+use crate::diagnostics::{is_inline_def, Fix};
+use crate::extract::FunctionDef;
+use crate::parser::node_range;
+
+/// House style for statements, keywords and builtin function names,
+/// configured by `br-lsp.style.keywordCase`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeywordCase {
+    Upper,
+    Lower,
+    Capitalized,
+}
+
+impl KeywordCase {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "upper" => Some(Self::Upper),
+            "lower" => Some(Self::Lower),
+            "capitalized" => Some(Self::Capitalized),
+            _ => None,
+        }
+    }
+
+    pub fn apply(self, word: &str) -> String {
+        match self {
+            Self::Upper => word.to_ascii_uppercase(),
+            Self::Lower => word.to_ascii_lowercase(),
+            Self::Capitalized => {
+                let lower = word.to_ascii_lowercase();
+                let mut chars = lower.chars();
+                match chars.next() {
+                    Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                    None => lower,
+                }
+            }
+        }
+    }
+}
+
+/// A statement, keyword or builtin name whose case differs from the style.
+struct CaseViolation {
+    range: Range,
+    text: String,
+    expected: String,
+}
+
+fn collect_violations(tree: &Tree, source: &str, case: KeywordCase) -> Vec<CaseViolation> {
+    let mut violations = Vec::new();
+    walk(tree.root_node(), source, case, &mut violations);
+    violations
+}
+
+fn walk(node: Node, source: &str, case: KeywordCase, out: &mut Vec<CaseViolation>) {
+    if is_styled_token(node) {
+        if let Ok(text) = node.utf8_text(source.as_bytes()) {
+            let expected = case.apply(text);
+            if expected != text {
+                out.push(CaseViolation {
+                    range: node_range(node),
+                    text: text.to_string(),
+                    expected,
+                });
+            }
+        }
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        walk(child, source, case, out);
+    }
+}
+
+/// Statement and keyword tokens are anonymous nodes aliased to `statement`
+/// and `keyword`; error conditions (`EOF`, `NOKEY`, ...) are named leaves and
+/// builtins are the names of system function calls.
+pub(crate) fn is_styled_token(node: Node) -> bool {
+    match node.kind() {
+        "statement" | "keyword" => !node.is_named(),
+        "error_condition" => node.child_count() == 0,
+        "function_name" => node.parent().is_some_and(|p| {
+            matches!(
+                p.kind(),
+                "numeric_system_function" | "string_system_function"
+            )
+        }),
+        _ => false,
+    }
+}
+
+/// Hint diagnostics for every token that doesn't follow `case`.
+pub fn check_keyword_case(tree: &Tree, source: &str, case: KeywordCase) -> Vec<Diagnostic> {
+    collect_violations(tree, source, case)
+        .into_iter()
+        .map(|v| {
+            let mut diagnostic = Diagnostic {
+                range: v.range,
+                severity: Some(DiagnosticSeverity::HINT),
+                code: Some(NumberOrString::String("keyword-case".to_string())),
+                message: format!("'{}' should be written '{}'", v.text, v.expected),
+                ..Default::default()
+            };
+            Fix {
+                title: format!("Change to '{}'", v.expected),
+                edits: vec![TextEdit {
+                    range: v.range,
+                    new_text: v.expected,
+                }],
+            }
+            .attach(&mut diagnostic);
+            diagnostic
+        })
+        .collect()
+}
+
+/// Edits that rewrite every token to follow `case` (used by formatting).
+pub fn keyword_case_edits(tree: &Tree, source: &str, case: KeywordCase) -> Vec<TextEdit> {
+    collect_violations(tree, source, case)
+        .into_iter()
+        .map(|v| TextEdit {
+            range: v.range,
+            new_text: v.expected,
+        })
+        .collect()
+}
+
+/// Naming rules from `br-lsp.style.naming`. Each rule is `None` unless
+/// enabled, and holds the pattern names must match.
+#[derive(Debug, Clone)]
+pub struct NamingConfig {
+    pub functions: Option<Regex>,
+    pub library_functions: Option<Regex>,
+    pub global_variables: Option<Regex>,
+    pub severity: DiagnosticSeverity,
+}
+
+/// Patterns compare by their source text, which is all a setting holds.
+impl PartialEq for NamingConfig {
+    fn eq(&self, other: &Self) -> bool {
+        fn pattern(rule: &Option<Regex>) -> Option<&str> {
+            rule.as_ref().map(Regex::as_str)
+        }
+        pattern(&self.functions) == pattern(&other.functions)
+            && pattern(&self.library_functions) == pattern(&other.library_functions)
+            && pattern(&self.global_variables) == pattern(&other.global_variables)
+            && self.severity == other.severity
+    }
+}
+
+impl Default for NamingConfig {
+    fn default() -> Self {
+        Self {
+            functions: None,
+            library_functions: None,
+            global_variables: None,
+            severity: DiagnosticSeverity::HINT,
+        }
+    }
+}
+
+const DEFAULT_FUNCTION_PATTERN: &str = r"^fn";
+const DEFAULT_LIBRARY_FUNCTION_PATTERN: &str = r"^fn[A-Z][A-Za-z0-9]*\$?$";
+const DEFAULT_GLOBAL_VARIABLE_PATTERN: &str = r"^[A-Z][A-Z0-9_]*\$?$";
+
+impl NamingConfig {
+    /// Read the `naming` object of the `br-lsp.style` section.
+    pub fn from_settings(naming: Option<&Value>) -> Self {
+        let Some(naming) = naming else {
+            return Self::default();
+        };
+        let rule = |key: &str, default: &str| -> Option<Regex> {
+            let rule = naming.get(key)?;
+            if !rule
+                .get("enabled")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false)
+            {
+                return None;
+            }
+            let pattern = rule
+                .get("pattern")
+                .and_then(|v| v.as_str())
+                .filter(|p| !p.is_empty())
+                .unwrap_or(default);
+            match Regex::new(pattern) {
+                Ok(re) => Some(re),
+                Err(e) => {
+                    warn!("Invalid br-lsp.style.naming.{key}.pattern: {e}");
+                    None
+                }
+            }
+        };
+        Self {
+            functions: rule("functions", DEFAULT_FUNCTION_PATTERN),
+            library_functions: rule("libraryFunctions", DEFAULT_LIBRARY_FUNCTION_PATTERN),
+            global_variables: rule("globalVariables", DEFAULT_GLOBAL_VARIABLE_PATTERN),
+            severity: match naming.get("severity").and_then(|v| v.as_str()) {
+                Some("warning") => DiagnosticSeverity::WARNING,
+                _ => DiagnosticSeverity::HINT,
+            },
+        }
+    }
+
+    /// Every rule enabled, keeping configured patterns and using the
+    /// defaults for the rest. Used by the strict lint profile.
+    pub fn with_all_rules(self) -> Self {
+        let or_default =
+            |rule: Option<Regex>, default: &str| rule.or_else(|| Regex::new(default).ok());
+        Self {
+            functions: or_default(self.functions, DEFAULT_FUNCTION_PATTERN),
+            library_functions: or_default(self.library_functions, DEFAULT_LIBRARY_FUNCTION_PATTERN),
+            global_variables: or_default(self.global_variables, DEFAULT_GLOBAL_VARIABLE_PATTERN),
+            severity: self.severity,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.functions.is_some()
+            || self.library_functions.is_some()
+            || self.global_variables.is_some()
+    }
+}
+
+/// Check function and global variable names against the enabled naming
+/// rules. Functions are checked at their definition; variables once, at
+/// their first use outside the parameter list of the enclosing function.
+pub fn check_naming(
+    tree: &Tree,
+    source: &str,
+    defs: &[FunctionDef],
+    config: &NamingConfig,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let diagnostic = |range: Range, code: &str, message: String| Diagnostic {
+        range,
+        severity: Some(config.severity),
+        code: Some(NumberOrString::String(code.to_string())),
+        message,
+        ..Default::default()
+    };
+
+    for def in defs.iter().filter(|d| !d.is_import_only) {
+        if let Some(re) = &config.functions {
+            if !re.is_match(&def.name) {
+                diagnostics.push(diagnostic(
+                    def.selection_range,
+                    "naming-function",
+                    format!(
+                        "Function name '{}' does not match '{}'",
+                        def.name,
+                        re.as_str()
+                    ),
+                ));
+            }
+        }
+        if let Some(re) = config.library_functions.as_ref().filter(|_| def.is_library) {
+            if !re.is_match(&def.name) {
+                diagnostics.push(diagnostic(
+                    def.selection_range,
+                    "naming-library-function",
+                    format!(
+                        "Library function name '{}' does not match '{}'",
+                        def.name,
+                        re.as_str()
+                    ),
+                ));
+            }
+        }
+    }
+
+    let Some(re) = &config.global_variables else {
+        return diagnostics;
+    };
+
+    let root = tree.root_node();
+    let mut seen = HashSet::new();
+    let mut params: HashSet<String> = HashSet::new();
+    let mut cursor = root.walk();
+    for line in root.children(&mut cursor) {
+        let mut line_cursor = line.walk();
+        let children: Vec<Node> = line.children(&mut line_cursor).collect();
+        let def_node = children.iter().find(|c| c.kind() == "def_statement");
+        if let Some(&def_node) = def_node {
+            let range = node_range(def_node);
+            params = defs
+                .iter()
+                .find(|d| d.range == range)
+                .map(|d| {
+                    d.params
+                        .iter()
+                        .map(|p| p.name.to_ascii_lowercase())
+                        .collect()
+                })
+                .unwrap_or_default();
+        }
+
+        let mut names = Vec::new();
+        collect_variable_names(line, &mut names);
+        for name_node in names {
+            let Ok(name) = name_node.utf8_text(source.as_bytes()) else {
+                continue;
+            };
+            let key = name.to_ascii_lowercase();
+            if params.contains(&key) || !seen.insert(key) {
+                continue;
+            }
+            if !re.is_match(name) {
+                diagnostics.push(diagnostic(
+                    node_range(name_node),
+                    "naming-global-variable",
+                    format!("Variable name '{name}' does not match '{}'", re.as_str()),
+                ));
+            }
+        }
+
+        let ends_function = def_node.is_some_and(|&d| is_inline_def(d))
+            || children.iter().any(|c| c.kind() == "fnend_statement");
+        if ends_function {
+            params.clear();
+        }
+    }
+
+    diagnostics
+}
+
+fn collect_variable_names<'a>(node: Node<'a>, out: &mut Vec<Node<'a>>) {
+    if matches!(
+        node.kind(),
+        "stringreference" | "numberreference" | "stringarray" | "numberarray"
+    ) {
+        if let Some(name) = node.child_by_field_name("name") {
+            out.push(name);
+        }
+        return;
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_variable_names(child, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn parse(source: &str) -> Tree {
+        let mut p = parser::new_parser();
+        parser::parse(&mut p, source, None).unwrap()
+    }
+
+    #[test]
+    fn apply_case() {
+        assert_eq!(KeywordCase::Upper.apply("print"), "PRINT");
+        assert_eq!(KeywordCase::Lower.apply("Str$"), "str$");
+        assert_eq!(KeywordCase::Capitalized.apply("FNEND"), "Fnend");
+        assert_eq!(
+            KeywordCase::parse("Capitalized"),
+            Some(KeywordCase::Capitalized)
+        );
+        assert_eq!(KeywordCase::parse("off"), None);
+    }
+
+    #[test]
+    fn statements_and_builtins_flagged() {
+        let source = "print str$(1)\nLET X = 1\n";
+        let tree = parse(source);
+        let diags = check_keyword_case(&tree, source, KeywordCase::Upper);
+        let messages: Vec<&str> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "'print' should be written 'PRINT'",
+                "'str$' should be written 'STR$'"
+            ]
+        );
+        assert!(diags
+            .iter()
+            .all(|d| d.severity == Some(DiagnosticSeverity::HINT)));
+    }
+
+    #[test]
+    fn variables_and_user_functions_untouched() {
+        let source = "LET x = fnFoo(y)\n";
+        let tree = parse(source);
+        assert!(keyword_case_edits(&tree, source, KeywordCase::Upper).is_empty());
+    }
+
+    #[test]
+    fn formatting_edits() {
+        let source = "Print \"a\"\n";
+        let tree = parse(source);
+        let edits = keyword_case_edits(&tree, source, KeywordCase::Lower);
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].new_text, "print");
+        assert_eq!(edits[0].range.end.character, 5);
+    }
+
+    fn naming_diags(source: &str, settings: serde_json::Value) -> Vec<Diagnostic> {
+        let tree = parse(source);
+        let defs = crate::extract::extract_definitions(&tree, source);
+        let config = NamingConfig::from_settings(Some(&settings));
+        check_naming(&tree, source, &defs, &config)
+    }
+
+    #[test]
+    fn naming_rules_disabled_by_default() {
+        let config = NamingConfig::from_settings(Some(&serde_json::json!({
+            "functions": { "pattern": "^x" }
+        })));
+        assert!(!config.is_enabled());
+    }
+
+    #[test]
+    fn function_naming_rules() {
+        let source = "def FNADD(A) = A\ndef library fnget_name$(X$)\nfnend\ndef library fnGetName$(X$)\nfnend\n";
+        let diags = naming_diags(
+            source,
+            serde_json::json!({
+                "functions": { "enabled": true },
+                "libraryFunctions": { "enabled": true },
+                "severity": "warning"
+            }),
+        );
+        let codes: Vec<_> = diags.iter().map(|d| d.code.clone().unwrap()).collect();
+        assert_eq!(
+            codes,
+            vec![
+                NumberOrString::String("naming-function".to_string()),
+                NumberOrString::String("naming-library-function".to_string()),
+            ]
+        );
+        assert!(diags[1].message.contains("fnget_name$"));
+        assert!(diags
+            .iter()
+            .all(|d| d.severity == Some(DiagnosticSeverity::WARNING)));
+    }
+
+    #[test]
+    fn global_variables_skip_parameters() {
+        let source = "let TOTAL = 0\nlet count = 1\ndef fnAdd(a, b)\nlet TOTAL = a + b + count\nfnend\nlet b = 2\n";
+        let diags = naming_diags(
+            source,
+            serde_json::json!({ "globalVariables": { "enabled": true } }),
+        );
+        let messages: Vec<&str> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Variable name 'count' does not match '^[A-Z][A-Z0-9_]*\\$?$'",
+                "Variable name 'b' does not match '^[A-Z][A-Z0-9_]*\\$?$'",
+            ]
+        );
+        assert_eq!(diags[0].severity, Some(DiagnosticSeverity::HINT));
+        assert_eq!(diags[1].range.start.line, 5);
+    }
+}
@@ -1,17 +1,134 @@
+use std::collections::{HashMap, HashSet};
+
 use tower_lsp::lsp_types::{DocumentSymbol, Position, Range, SymbolKind};
 use tree_sitter::{Node, Tree, TreeCursor};
 
-use crate::parser::node_range;
+use crate::diagnostics;
+use crate::parser::{self, node_range};
+use crate::structure::{Label, Structure};
+
+/// Name of the group GOSUB targets are listed under
+const SUBROUTINES: &str = "Subroutines";
 
 #[allow(deprecated)]
 pub fn collect_document_symbols(tree: &Tree, source: &str) -> Vec<DocumentSymbol> {
     let mut symbols = Vec::new();
     let mut cursor = tree.walk();
     walk_symbols(&mut cursor, source, &mut symbols);
+    let structure = Structure::of(tree, source);
+    extend_to_fnend(&structure, &mut symbols);
+    symbols.extend(structure.labels.iter().map(make_label_symbol));
+    symbols.sort_by_key(|s| (s.range.start.line, s.range.start.character));
+    group_subroutines(tree, source, symbols)
+}
+
+/// Stretch each multi-line function's symbol from its DEF, continuation
+/// lines included, through the FNEND that closes it. A DEF without one
+/// keeps the DEF statement's range.
+fn extend_to_fnend(structure: &Structure, symbols: &mut [DocumentSymbol]) {
+    for symbol in symbols
+        .iter_mut()
+        .filter(|s| s.kind == SymbolKind::FUNCTION)
+    {
+        if let Some(function) = structure.function_at(symbol.range.start) {
+            symbol.range = function.range();
+        }
+    }
+}
+
+/// Move the labels GOSUB calls into a "Subroutines" group, each spanning
+/// from its label to the first RETURN after it.
+#[allow(deprecated)]
+fn group_subroutines(
+    tree: &Tree,
+    source: &str,
+    symbols: Vec<DocumentSymbol>,
+) -> Vec<DocumentSymbol> {
+    let targets = gosub_targets(tree, source);
+    if targets.is_empty() {
+        return symbols;
+    }
+    let returns = return_lines(tree);
+
+    let (mut subroutines, mut symbols): (Vec<_>, Vec<_>) = symbols.into_iter().partition(|s| {
+        s.detail.as_deref() == Some("label") && targets.contains(&s.name.to_ascii_lowercase())
+    });
+    if subroutines.is_empty() {
+        return symbols;
+    }
+    for sub in &mut subroutines {
+        sub.kind = SymbolKind::FUNCTION;
+        sub.detail = Some("subroutine".to_string());
+        let start = sub.range.start.line;
+        if let Some(&(line, end)) = returns.iter().find(|&&(line, _)| line >= start) {
+            sub.range.end = Position::new(line, end);
+        }
+    }
+    let first = subroutines[0].range.start;
+    let last = subroutines
+        .iter()
+        .map(|s| s.range.end)
+        .max()
+        .unwrap_or(first);
+    symbols.push(DocumentSymbol {
+        name: SUBROUTINES.to_string(),
+        detail: None,
+        kind: SymbolKind::NAMESPACE,
+        tags: None,
+        deprecated: None,
+        range: Range::new(first, last),
+        selection_range: subroutines[0].selection_range,
+        children: Some(subroutines),
+    });
     symbols.sort_by_key(|s| (s.range.start.line, s.range.start.character));
     symbols
 }
 
+/// Lowercase names of the labels GOSUB and ON ... GOSUB jump to.
+pub fn gosub_targets(tree: &Tree, source: &str) -> HashSet<String> {
+    let mut targets = HashSet::new();
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        let mut cursor = node.walk();
+        let children: Vec<Node> = node.children(&mut cursor).collect();
+        let mut after_gosub = node.kind() == "gosub_statement";
+        for child in children {
+            match child.kind() {
+                // `ON X GOTO A, B GOSUB C` isn't BR, but a keyword resets it
+                "statement" if node.kind() == "on_statement" => {
+                    after_gosub = child
+                        .utf8_text(source.as_bytes())
+                        .is_ok_and(|k| k.eq_ignore_ascii_case("gosub"));
+                }
+                "label_reference" if after_gosub => {
+                    if let Ok(name) = child.utf8_text(source.as_bytes()) {
+                        targets.insert(name.to_ascii_lowercase());
+                    }
+                }
+                _ => stack.push(child),
+            }
+        }
+    }
+    targets
+}
+
+/// Line and end column of each RETURN statement, in order.
+fn return_lines(tree: &Tree) -> Vec<(u32, u32)> {
+    let mut lines = Vec::new();
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        if node.kind() == "return_statement" {
+            let range = node_range(node);
+            lines.push((range.end.line, range.end.character));
+            continue;
+        }
+        let mut cursor = node.walk();
+        stack.extend(node.named_children(&mut cursor));
+    }
+    lines.sort_unstable();
+    lines
+}
+
 #[allow(deprecated)]
 fn walk_symbols(cursor: &mut TreeCursor, source: &str, symbols: &mut Vec<DocumentSymbol>) {
     loop {
@@ -35,15 +152,6 @@ fn walk_symbols(cursor: &mut TreeCursor, source: &str, symbols: &mut Vec<Documen
                 }
                 continue;
             }
-            "label" => {
-                if let Some(sym) = make_label_symbol(node, source) {
-                    symbols.push(sym);
-                }
-                if !cursor.goto_next_sibling() {
-                    return;
-                }
-                continue;
-            }
             _ => {}
         }
 
@@ -61,7 +169,7 @@ fn walk_symbols(cursor: &mut TreeCursor, source: &str, symbols: &mut Vec<Documen
 
 #[allow(deprecated)]
 fn make_function_symbol(node: Node, source: &str) -> Option<DocumentSymbol> {
-    let fn_name_node = find_child_by_kind(node, "function_name")?;
+    let fn_name_node = parser::find_descendant(node, "function_name")?;
     let name = fn_name_node.utf8_text(source.as_bytes()).ok()?;
     if name.is_empty() {
         return None;
@@ -80,26 +188,16 @@ fn make_function_symbol(node: Node, source: &str) -> Option<DocumentSymbol> {
 
 #[allow(deprecated)]
 fn collect_dim_vars(node: Node, source: &str, symbols: &mut Vec<DocumentSymbol>) {
-    let mut child_cursor = node.walk();
-    for child in node.children(&mut child_cursor) {
-        let detail = match child.kind() {
+    for entry in dim_entries(node, source) {
+        let detail = match entry.kind {
             "stringreference" => "string",
             "numberreference" => "number",
             "stringarray" => "stringarray",
-            "numberarray" => "numberarray",
-            _ => continue,
-        };
-        let name_node = match child.child_by_field_name("name") {
-            Some(n) => n,
-            None => continue,
-        };
-        let name = match name_node.utf8_text(source.as_bytes()) {
-            Ok(s) if !s.is_empty() => s.to_string(),
-            _ => continue,
+            _ => "numberarray",
         };
-        let range = node_range(name_node);
+        let range = node_range(entry.name_node);
         symbols.push(DocumentSymbol {
-            name,
+            name: entry.name,
             detail: Some(detail.to_string()),
             kind: SymbolKind::VARIABLE,
             tags: None,
@@ -111,14 +209,145 @@ fn collect_dim_vars(node: Node, source: &str, symbols: &mut Vec<DocumentSymbol>)
     }
 }
 
-#[allow(deprecated)]
-fn make_label_symbol(node: Node, source: &str) -> Option<DocumentSymbol> {
-    let text = node.utf8_text(source.as_bytes()).ok()?;
-    let name = text.trim_end_matches(':').to_string();
-    if name.is_empty() {
-        return None;
+/// A variable dimensioned by a DIM statement.
+pub struct DimEntry<'t> {
+    pub name: String,
+    pub name_node: Node<'t>,
+    /// `stringreference`, `numberreference`, `stringarray` or `numberarray`
+    pub kind: &'static str,
+    /// The entry as written, without spaces: `A$(10)*30`
+    pub declaration: String,
+}
+
+pub fn dim_entries<'t>(dim: Node<'t>, source: &str) -> Vec<DimEntry<'t>> {
+    let mut cursor = dim.walk();
+    let entries = dim
+        .children(&mut cursor)
+        .filter_map(|child| {
+            let kind = match child.kind() {
+                "stringreference" => "stringreference",
+                "numberreference" => "numberreference",
+                "stringarray" => "stringarray",
+                "numberarray" => "numberarray",
+                _ => return None,
+            };
+            let name_node = child.child_by_field_name("name")?;
+            let name = name_node.utf8_text(source.as_bytes()).ok()?;
+            if name.is_empty() {
+                return None;
+            }
+            let declaration = child.utf8_text(source.as_bytes()).ok()?;
+            Some(DimEntry {
+                name: name.to_string(),
+                name_node,
+                kind,
+                declaration: declaration.split_whitespace().collect(),
+            })
+        })
+        .collect();
+    entries
+}
+
+/// DIM declarations in the program, keyed by lowercase name and entry
+/// kind. The first DIM of a variable wins.
+pub fn dim_declarations(tree: &Tree, source: &str) -> HashMap<(String, &'static str), String> {
+    let root = tree.root_node();
+    let mut declarations = HashMap::new();
+    let mut cursor = root.walk();
+    for line in root.children(&mut cursor).filter(|c| c.kind() == "line") {
+        let mut line_cursor = line.walk();
+        for dim in line
+            .named_children(&mut line_cursor)
+            .filter(|c| c.kind() == "dim_statement")
+        {
+            for entry in dim_entries(dim, source) {
+                declarations
+                    .entry((entry.name.to_ascii_lowercase(), entry.kind))
+                    .or_insert(entry.declaration);
+            }
+        }
+    }
+    declarations
+}
+
+/// How a variable is declared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeclarationKind {
+    Parameter,
+    Dim,
+    /// Not dimensioned; first given a value by LET, an implicit LET or a
+    /// FOR loop
+    Assignment,
+}
+
+/// Where a variable is declared, for go to definition and hover.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariableDeclaration {
+    pub kind: DeclarationKind,
+    /// The variable's name in the declaration
+    pub range: Range,
+    /// The DIM entry as written (`A$(10)*30`)
+    pub dim: Option<String>,
+}
+
+/// Where `name` is declared in the program: its first DIM, or without one
+/// the first statement that assigns it. Parameters are left to the caller,
+/// which knows the function the name is used in.
+pub fn variable_declaration(tree: &Tree, source: &str, name: &str) -> Option<VariableDeclaration> {
+    let root = tree.root_node();
+    let mut cursor = root.walk();
+    for line in root.children(&mut cursor).filter(|c| c.kind() == "line") {
+        let mut line_cursor = line.walk();
+        for dim in line
+            .named_children(&mut line_cursor)
+            .filter(|c| c.kind() == "dim_statement")
+        {
+            if let Some(entry) = dim_entries(dim, source)
+                .into_iter()
+                .find(|e| e.name.eq_ignore_ascii_case(name))
+            {
+                return Some(VariableDeclaration {
+                    kind: DeclarationKind::Dim,
+                    range: node_range(entry.name_node),
+                    dim: Some(entry.declaration),
+                });
+            }
+        }
     }
-    let range = node_range(node);
+
+    first_assignment(root, source, name).map(|node| VariableDeclaration {
+        kind: DeclarationKind::Assignment,
+        range: node_range(node),
+        dim: None,
+    })
+}
+
+/// The first name node, in source order, that assigns `name`.
+fn first_assignment<'t>(node: Node<'t>, source: &str, name: &str) -> Option<Node<'t>> {
+    if matches!(node.kind(), "stringidentifier" | "numberidentifier")
+        && node
+            .utf8_text(source.as_bytes())
+            .is_ok_and(|text| text.eq_ignore_ascii_case(name))
+    {
+        let loop_variable = node
+            .parent()
+            .filter(|r| r.kind() == "numberreference")
+            .and_then(|r| r.parent())
+            .is_some_and(|p| p.kind() == "for_statement");
+        if loop_variable || diagnostics::assignment_target_of(node).is_some() {
+            return Some(node);
+        }
+    }
+    let mut cursor = node.walk();
+    let children: Vec<Node<'t>> = node.named_children(&mut cursor).collect();
+    children
+        .into_iter()
+        .find_map(|child| first_assignment(child, source, name))
+}
+
+#[allow(deprecated)]
+fn make_label_symbol(label: &Label) -> DocumentSymbol {
+    let range = label.range;
     let selection_range = Range {
         start: range.start,
         end: Position {
@@ -126,8 +355,8 @@ fn make_label_symbol(node: Node, source: &str) -> Option<DocumentSymbol> {
             character: range.end.character.saturating_sub(1),
         },
     };
-    Some(DocumentSymbol {
-        name,
+    DocumentSymbol {
+        name: label.name.clone(),
         detail: Some("label".to_string()),
         kind: SymbolKind::NULL,
         tags: None,
@@ -135,21 +364,7 @@ fn make_label_symbol(node: Node, source: &str) -> Option<DocumentSymbol> {
         range,
         selection_range,
         children: None,
-    })
-}
-
-fn find_child_by_kind<'a>(node: Node<'a>, kind: &str) -> Option<Node<'a>> {
-    let mut stack = vec![node];
-    while let Some(n) = stack.pop() {
-        if n.kind() == kind {
-            return Some(n);
-        }
-        let mut cursor = n.walk();
-        for child in n.children(&mut cursor) {
-            stack.push(child);
-        }
     }
-    None
 }
 
 #[cfg(test)]
@@ -177,6 +392,67 @@ mod tests {
         assert_eq!(funcs[0].detail.as_deref(), Some("function"));
     }
 
+    #[test]
+    fn function_symbols_span_def_through_fnend() {
+        let source = "def fnFoo(A, !:\n  B)\n  let fnFoo=A+B\nfnend\ndef fnBar(X)\nlet Y=X\n";
+        let symbols = parse_and_collect(source);
+        assert_eq!(symbols[0].name, "fnFoo");
+        assert_eq!(symbols[0].range.start, Position::new(0, 0));
+        assert_eq!(symbols[0].range.end, Position::new(3, 5));
+        assert_eq!(symbols[0].selection_range.start, Position::new(0, 4));
+        // Without an FNEND the DEF statement is all there is
+        let bar = symbols.iter().find(|s| s.name == "fnBar").unwrap();
+        assert_eq!(bar.range.end.line, 4);
+    }
+
+    #[test]
+    fn dim_declarations_by_name_and_kind() {
+        let source = "dim A$(10) * 30, B(5,3), C$*20, D\ndim a$(20)\n";
+        let mut p = parser::new_parser();
+        let tree = parser::parse(&mut p, source, None).unwrap();
+        let dims = dim_declarations(&tree, source);
+        assert_eq!(dims.len(), 4);
+        assert_eq!(dims[&("a$".to_string(), "stringarray")], "A$(10)*30");
+        assert_eq!(dims[&("b".to_string(), "numberarray")], "B(5,3)");
+        assert_eq!(dims[&("c$".to_string(), "stringreference")], "C$*20");
+        assert_eq!(dims[&("d".to_string(), "numberreference")], "D");
+    }
+
+    #[test]
+    fn variable_declared_by_dim_or_first_assignment() {
+        let source = "print Total\nlet Count = 1\nfor I = 1 to 3\nnext I\nTotal = 5\ndim Name$*30\nlet Name$ = \"x\"\n";
+        let tree = parser::parse(&mut parser::new_parser(), source, None).unwrap();
+        let dim = variable_declaration(&tree, source, "name$").unwrap();
+        assert_eq!(dim.kind, DeclarationKind::Dim);
+        assert_eq!(dim.range.start, Position::new(5, 4));
+        assert_eq!(dim.dim.as_deref(), Some("Name$*30"));
+
+        let total = variable_declaration(&tree, source, "TOTAL").unwrap();
+        assert_eq!(total.kind, DeclarationKind::Assignment);
+        assert_eq!(total.range.start, Position::new(4, 0));
+        let loop_variable = variable_declaration(&tree, source, "I").unwrap();
+        assert_eq!(loop_variable.range.start, Position::new(2, 4));
+        assert!(variable_declaration(&tree, source, "Missing").is_none());
+    }
+
+    #[test]
+    fn gosub_targets_grouped_as_subroutines() {
+        let source = "gosub Calc\non X gosub Show\ngoto Done\nCalc: let Y = 1\nreturn\nShow: print Y\nreturn\nDone: stop\n";
+        let syms = parse_and_collect(source);
+        let names: Vec<&str> = syms.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["Subroutines", "Done"]);
+
+        let group = &syms[0];
+        assert_eq!(group.range.start.line, 3);
+        assert_eq!(group.range.end.line, 6);
+        let subs = group.children.as_ref().unwrap();
+        let names: Vec<&str> = subs.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["Calc", "Show"]);
+        assert_eq!(subs[0].kind, SymbolKind::FUNCTION);
+        assert_eq!(subs[0].range.end, Position::new(4, 6));
+        assert_eq!(syms[1].detail.as_deref(), Some("label"));
+    }
+
     #[test]
     fn dim_variable_symbols() {
         let source = "dim X$*30, Y, Z$(10)*20\n";
@@ -1,14 +1,23 @@
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
+use ignore::WalkBuilder;
+use rayon::prelude::*;
 use tower_lsp::lsp_types::Url;
 
+use crate::call_graph::Call;
 use crate::extract::FunctionDef;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct WorkspaceIndex {
     /// Lowercase function name -> Vec<FunctionDef with uri>
     definitions: HashMap<String, Vec<IndexedFunctionDef>>,
+    /// Every indexed file, including those that define nothing, and when it
+    /// was indexed
+    files: HashMap<Url, SystemTime>,
+    /// The user function calls in each file, for the call graph
+    calls: HashMap<Url, Vec<Call>>,
 }
 
 #[derive(Debug, Clone)]
@@ -24,6 +33,7 @@ impl WorkspaceIndex {
     }
 
     pub fn add_file(&mut self, uri: &Url, defs: Vec<FunctionDef>) {
+        self.files.insert(uri.clone(), SystemTime::now());
         for def in defs {
             let key = def.name.to_ascii_lowercase();
             self.definitions
@@ -37,6 +47,8 @@ impl WorkspaceIndex {
     }
 
     pub fn remove_file(&mut self, uri: &Url) {
+        self.files.remove(uri);
+        self.calls.remove(uri);
         self.definitions.retain(|_, entries| {
             entries.retain(|e| &e.uri != uri);
             !entries.is_empty()
@@ -48,6 +60,28 @@ impl WorkspaceIndex {
         self.add_file(uri, defs);
     }
 
+    /// Record the calls made in `uri`, replacing any recorded before.
+    pub fn set_calls(&mut self, uri: &Url, calls: Vec<Call>) {
+        self.calls.insert(uri.clone(), calls);
+    }
+
+    /// The calls recorded for each file.
+    pub fn calls(&self) -> impl Iterator<Item = (&Url, &[Call])> {
+        self.calls
+            .iter()
+            .map(|(uri, calls)| (uri, calls.as_slice()))
+    }
+
+    /// When `uri` was last added to the index.
+    pub fn indexed_at(&self, uri: &Url) -> Option<SystemTime> {
+        self.files.get(uri).copied()
+    }
+
+    /// Every indexed file with when it was added.
+    pub fn indexed_files(&self) -> impl Iterator<Item = (&Url, SystemTime)> {
+        self.files.iter().map(|(uri, at)| (uri, *at))
+    }
+
     pub fn lookup(&self, name: &str) -> &[IndexedFunctionDef] {
         self.definitions
             .get(&name.to_ascii_lowercase())
@@ -120,6 +154,53 @@ impl WorkspaceIndex {
         self.definitions.values().flatten().collect()
     }
 
+    /// Indexed files whose path matches `path_glob`, sorted. The pattern
+    /// matches the end of the path, ignoring case, backslashes and the source
+    /// or compiled extension, so `vol002/rtflib` finds `.../vol002/rtflib.brs`.
+    /// `*` and `?` match within one path component, `**` any number of
+    /// components.
+    pub fn files_matching(&self, path_glob: &str) -> Vec<&Url> {
+        let Some(pattern) = PathPattern::new(path_glob) else {
+            return Vec::new();
+        };
+        let mut found: Vec<&Url> = self.files.keys().filter(|u| pattern.matches(u)).collect();
+        found.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        found
+    }
+
+    /// Functions defined (not just imported) in the files
+    /// [`files_matching`](Self::files_matching) `path_glob`, sorted by file
+    /// and position.
+    pub fn functions_in(&self, path_glob: &str) -> Vec<&IndexedFunctionDef> {
+        let files: HashSet<&Url> = self.files_matching(path_glob).into_iter().collect();
+        let mut found: Vec<&IndexedFunctionDef> = self
+            .definitions
+            .values()
+            .flatten()
+            .filter(|e| !e.def.is_import_only && files.contains(&e.uri))
+            .collect();
+        found.sort_by(|a, b| {
+            a.uri
+                .as_str()
+                .cmp(b.uri.as_str())
+                .then(a.def.range.start.line.cmp(&b.def.range.start.line))
+        });
+        found
+    }
+
+    /// The lowercase names of the functions `uris` define (not just import).
+    pub fn names_defined_in(&self, uris: &HashSet<Url>) -> HashSet<String> {
+        self.definitions
+            .iter()
+            .filter(|(_, entries)| {
+                entries
+                    .iter()
+                    .any(|e| !e.def.is_import_only && uris.contains(&e.uri))
+            })
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
     /// Returns one representative `IndexedFunctionDef` per unique function name,
     /// excluding entries from `exclude_uri` and import-only entries.
     /// Prefers entries with `is_library: true` when available.
@@ -162,8 +243,40 @@ pub fn read_br_file(path: &Path) -> std::io::Result<String> {
     Ok(output)
 }
 
+/// Write a BR source file to disk, encoding from UTF-8 back to CP437. Fails
+/// without touching the file when `text` has a character CP437 can't hold.
+/// A DOS EOF marker at the end of the existing file is kept.
+pub fn write_br_file(path: &Path, text: &str) -> std::io::Result<()> {
+    let mut bytes = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        let Some(byte) = char_to_cp437(c) else {
+            let line = bytes.iter().filter(|&&b| b == b'\n').count() + 1;
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("line {line}: `{c}` has no CP437 encoding"),
+            ));
+        };
+        bytes.push(byte);
+    }
+    if std::fs::read(path).is_ok_and(|old| old.last() == Some(&0x1A)) {
+        bytes.push(0x1A);
+    }
+    std::fs::write(path, bytes)
+}
+
+/// Map a Unicode character to its CP437 byte, if it has one.
+pub(crate) fn char_to_cp437(c: char) -> Option<u8> {
+    if c.is_ascii() {
+        return Some(c as u8);
+    }
+    CP437_HIGH
+        .iter()
+        .position(|&h| h == c)
+        .map(|i| i as u8 + 128)
+}
+
 /// Map a CP437 byte to its Unicode character.
-fn cp437_to_char(byte: u8) -> char {
+pub(crate) fn cp437_to_char(byte: u8) -> char {
     if byte < 128 {
         byte as char
     } else {
@@ -191,6 +304,270 @@ const CP437_HIGH: [char; 128] = [
     '\u{00B0}', '\u{2219}', '\u{00B7}', '\u{221A}', '\u{207F}', '\u{00B2}', '\u{25A0}', '\u{00A0}',
 ];
 
+/// Virtual drive and volume prefixes from `br-lsp.driveMappings`, such as
+/// `VOL002` or `C:`, mapped to real directories. Relative targets are taken
+/// from each workspace folder.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DriveMappings {
+    /// (prefix, target) with forward slashes and no trailing slash, longest prefix first
+    entries: Vec<(String, String)>,
+}
+
+impl DriveMappings {
+    pub fn from_settings(settings: Option<&serde_json::Value>) -> Self {
+        let Some(map) = settings.and_then(|s| s.as_object()) else {
+            return Self::default();
+        };
+        let mut entries: Vec<(String, String)> = map
+            .iter()
+            .filter_map(|(prefix, target)| {
+                let prefix = prefix.replace('\\', "/").trim_end_matches('/').to_string();
+                let target = target.as_str()?.replace('\\', "/");
+                let target = match target.trim_end_matches('/') {
+                    "" if target.starts_with('/') => "/".to_string(),
+                    trimmed => trimmed.to_string(),
+                };
+                (!prefix.is_empty() && !target.is_empty()).then_some((prefix, target))
+            })
+            .collect();
+        entries.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+        Self { entries }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Split a path into its mapped target and the rest of the path, if it
+    /// starts with a mapped prefix. Backslashes and case are ignored.
+    pub fn split(&self, path: &str) -> Option<(&str, String)> {
+        let path = path.replace('\\', "/");
+        self.entries.iter().find_map(|(prefix, target)| {
+            let head = path.get(..prefix.len())?;
+            if !head.eq_ignore_ascii_case(prefix) {
+                return None;
+            }
+            let rest = &path[prefix.len()..];
+            if !prefix.ends_with(':') && !rest.is_empty() && !rest.starts_with('/') {
+                return None;
+            }
+            Some((target.as_str(), rest.trim_start_matches('/').to_string()))
+        })
+    }
+
+    /// Directories a mapped target stands for.
+    pub fn target_dirs(target: &str, workspace_folders: &[PathBuf]) -> Vec<PathBuf> {
+        let path = Path::new(target);
+        if path.is_absolute() {
+            vec![path.to_path_buf()]
+        } else {
+            workspace_folders.iter().map(|f| f.join(path)).collect()
+        }
+    }
+
+    /// Rewrite a normalized library path (see `extract::normalize_library_path`)
+    /// into the workspace link path of the file it maps to, so it can be
+    /// compared with [`uri_to_link_path`]. Paths that aren't mapped, or that
+    /// map outside every workspace folder, are returned unchanged.
+    pub fn map_link_path(&self, link_path: &str, workspace_folders: &[Url]) -> String {
+        let Some((target, rest)) = self.split(link_path) else {
+            return link_path.to_string();
+        };
+        let join = |dir: &str| {
+            let dir = dir.trim_end_matches('/').to_ascii_lowercase();
+            if dir.is_empty() {
+                rest.clone()
+            } else {
+                format!("{dir}/{rest}")
+            }
+        };
+        if !Path::new(target).is_absolute() {
+            return join(target);
+        }
+        workspace_folders
+            .iter()
+            .filter_map(|folder| folder.to_file_path().ok())
+            .find_map(|folder| {
+                let relative = Path::new(target).strip_prefix(&folder).ok()?;
+                let relative: Vec<_> = relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                    .collect();
+                Some(join(&relative.join("/")))
+            })
+            .unwrap_or_else(|| link_path.to_string())
+    }
+
+    /// Apply [`DriveMappings::map_link_path`] to every path in a
+    /// function-name → library-path map.
+    pub fn map_library_links(
+        &self,
+        library_links: &mut HashMap<String, String>,
+        workspace_folders: &[Url],
+    ) {
+        if self.is_empty() {
+            return;
+        }
+        for path in library_links.values_mut() {
+            *path = self.map_link_path(path, workspace_folders);
+        }
+    }
+}
+
+/// A `files_matching` pattern split into lowercase path components.
+pub(crate) struct PathPattern(Vec<String>);
+
+impl PathPattern {
+    pub(crate) fn new(glob: &str) -> Option<Self> {
+        let glob = glob.replace('\\', "/").to_ascii_lowercase();
+        let components: Vec<String> = strip_program_extension(&glob)
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect();
+        (!components.is_empty()).then_some(Self(components))
+    }
+
+    /// Whether the end of `uri`'s path matches, ignoring case and program
+    /// extensions.
+    pub(crate) fn matches(&self, uri: &Url) -> bool {
+        let path = uri
+            .to_file_path()
+            .map(|p| p.to_string_lossy().replace('\\', "/"))
+            .unwrap_or_else(|()| uri.path().to_string())
+            .to_ascii_lowercase();
+        let segments: Vec<&str> = strip_program_extension(&path)
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .collect();
+        let pattern: Vec<&str> = self.0.iter().map(String::as_str).collect();
+        (0..segments.len()).any(|i| glob_segments(&pattern, &segments[i..]))
+    }
+}
+
+fn strip_program_extension(path: &str) -> &str {
+    [".brs", ".wbs", ".br", ".wb"]
+        .iter()
+        .find_map(|ext| path.strip_suffix(ext))
+        .unwrap_or(path)
+}
+
+/// Whether path components match pattern components, all of them.
+fn glob_segments(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|i| glob_segments(rest, &path[i..])),
+        Some((first, rest)) => path.split_first().is_some_and(|(segment, tail)| {
+            glob_component(first, segment) && glob_segments(rest, tail)
+        }),
+    }
+}
+
+/// Match one component against a pattern with `*` and `?` wildcards.
+fn glob_component(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Files whose diagnostics are never published, from `br-lsp.mutedFiles`:
+/// paths relative to a workspace folder, or absolute. For generated or
+/// vendored programs kept open only for reference.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MutedFiles {
+    /// Forward slashes, as the setting lists them
+    entries: Vec<String>,
+}
+
+impl MutedFiles {
+    pub fn from_settings(settings: Option<&serde_json::Value>) -> Self {
+        let entries = settings
+            .and_then(|s| s.as_array())
+            .map(|paths| {
+                paths
+                    .iter()
+                    .filter_map(|p| p.as_str())
+                    .map(|p| p.trim().replace('\\', "/"))
+                    .filter(|p| !p.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { entries }
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Whether `uri` is muted. Relative entries match the end of its path
+    /// on a directory boundary; case is ignored, as BR ignores it.
+    pub fn contains(&self, uri: &Url) -> bool {
+        slashed_path(uri).is_some_and(|path| self.entries.iter().any(|e| mutes(e, &path)))
+    }
+
+    /// Mute `uri`, listed relative to the workspace folder holding it.
+    /// Returns whether the list changed.
+    pub fn insert(&mut self, uri: &Url, workspace_folders: &[Url]) -> bool {
+        let Some(path) = slashed_path(uri) else {
+            return false;
+        };
+        if self.entries.iter().any(|e| mutes(e, &path)) {
+            return false;
+        }
+        let relative = workspace_folders.iter().find_map(|folder| {
+            let folder = slashed_path(folder)?;
+            let rest = path.strip_prefix(folder.trim_end_matches('/'))?;
+            rest.strip_prefix('/').map(str::to_string)
+        });
+        self.entries.push(relative.unwrap_or(path));
+        true
+    }
+
+    /// Unmute `uri`, dropping every entry that matches it. Returns whether
+    /// the list changed.
+    pub fn remove(&mut self, uri: &Url) -> bool {
+        let Some(path) = slashed_path(uri) else {
+            return false;
+        };
+        let before = self.entries.len();
+        self.entries.retain(|e| !mutes(e, &path));
+        self.entries.len() != before
+    }
+}
+
+fn slashed_path(uri: &Url) -> Option<String> {
+    let path = uri.to_file_path().ok()?;
+    Some(path.to_string_lossy().replace('\\', "/"))
+}
+
+fn mutes(entry: &str, path: &str) -> bool {
+    let path = path.to_ascii_lowercase();
+    let entry = entry.to_ascii_lowercase();
+    path == entry || path.ends_with(&format!("/{}", entry.trim_start_matches('/')))
+}
+
 /// Strip workspace folder prefix from a URI, strip extension, lowercase, forward slashes.
 /// Returns `None` if the URI doesn't start with any workspace folder.
 pub fn uri_to_link_path(uri: &Url, workspace_folders: &[Url]) -> Option<String> {
@@ -215,6 +592,20 @@ pub fn uri_to_link_path(uri: &Url, workspace_folders: &[Url]) -> Option<String>
     None
 }
 
+/// The path of `uri` relative to the workspace folder holding it, with
+/// forward slashes. Returns `None` if no workspace folder holds it.
+pub fn relative_path(uri: &Url, workspace_folders: &[Url]) -> Option<String> {
+    let path = uri.to_file_path().ok()?;
+    workspace_folders.iter().find_map(|folder| {
+        let relative = path.strip_prefix(folder.to_file_path().ok()?).ok()?;
+        let parts: Vec<_> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect();
+        Some(parts.join("/"))
+    })
+}
+
 /// Check if a file path has a BR extension (.brs or .wbs), case-insensitive.
 pub fn is_br_file(path: &Path) -> bool {
     path.extension()
@@ -223,6 +614,529 @@ pub fn is_br_file(path: &Path) -> bool {
         .unwrap_or(false)
 }
 
+/// Bytes read from an extensionless file to decide whether it is BR source.
+const SNIFF_BYTES: usize = 512;
+
+/// Version-control directories never searched for extensionless programs.
+const VCS_DIRS: &[&str] = &[".git", ".hg", ".svn"];
+
+/// Which files under a workspace folder are BR source. Files without an
+/// extension are recognised from `br-lsp.extensionlessFiles`; files with a
+/// `.brs`/`.wbs` extension always are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrFileDetection {
+    /// Look inside extensionless files at all
+    pub enabled: bool,
+    /// Text the first line must contain (case-insensitive), such as a
+    /// `#!` line or a `! BR` comment. Without one, a first line that starts
+    /// with a BR line number is accepted.
+    pub marker: Option<String>,
+    /// Skip files `.gitignore` excludes, from `br-lsp.respectGitignore`
+    pub respect_gitignore: bool,
+}
+
+impl Default for BrFileDetection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            marker: None,
+            respect_gitignore: true,
+        }
+    }
+}
+
+impl BrFileDetection {
+    pub fn from_settings(settings: Option<&serde_json::Value>) -> Self {
+        let Some(settings) = settings else {
+            return Self::default();
+        };
+        Self {
+            enabled: settings
+                .get("enabled")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false),
+            marker: settings
+                .get("marker")
+                .and_then(|v| v.as_str())
+                .map(str::trim)
+                .filter(|m| !m.is_empty())
+                .map(str::to_string),
+            ..Self::default()
+        }
+    }
+
+    /// Whether `path` is BR source: by extension, or for an extensionless
+    /// file by its first line.
+    pub fn is_br_file(&self, path: &Path) -> bool {
+        if is_br_file(path) {
+            return true;
+        }
+        if !self.enabled || path.extension().is_some() {
+            return false;
+        }
+        let hidden = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_none_or(|n| n.starts_with('.'));
+        let in_vcs = path
+            .components()
+            .any(|c| VCS_DIRS.iter().any(|d| c.as_os_str() == *d));
+        if hidden || in_vcs {
+            return false;
+        }
+        let Ok(head) = read_head(path) else {
+            return false;
+        };
+        looks_like_br_source(&head, self.marker.as_deref())
+    }
+}
+
+fn read_head(path: &Path) -> std::io::Result<Vec<u8>> {
+    use std::io::Read;
+    let mut buf = Vec::with_capacity(SNIFF_BYTES);
+    std::fs::File::open(path)?
+        .take(SNIFF_BYTES as u64)
+        .read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// Decide from the start of a file whether it holds BR source. With a
+/// marker the first line must contain it; otherwise the first non-blank
+/// line must begin with a line number followed by a statement or comment.
+pub fn looks_like_br_source(head: &[u8], marker: Option<&str>) -> bool {
+    if head.contains(&0) {
+        return false;
+    }
+    let text: String = head.iter().map(|&b| cp437_to_char(b)).collect();
+    match marker {
+        Some(marker) => text.lines().next().is_some_and(|line| {
+            line.to_ascii_lowercase()
+                .contains(&marker.to_ascii_lowercase())
+        }),
+        None => {
+            let Some(line) = text.lines().map(str::trim_start).find(|l| !l.is_empty()) else {
+                return false;
+            };
+            let digits = line.bytes().take_while(u8::is_ascii_digit).count();
+            let rest = &line[digits..];
+            let after = rest.trim_start();
+            (1..=5).contains(&digits)
+                && after.len() < rest.len()
+                && after
+                    .chars()
+                    .next()
+                    .is_some_and(|c| c.is_ascii_alphabetic() || c == '!')
+        }
+    }
+}
+
+/// Limits on a workspace walk, so a deep or enormous tree (a mounted
+/// drive, a symlink into `/`) can't stall indexing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalkBudget {
+    /// Directories nested deeper than this under the root aren't entered
+    pub max_depth: usize,
+    /// The walk stops after this many files
+    pub max_files: usize,
+}
+
+impl Default for WalkBudget {
+    fn default() -> Self {
+        Self {
+            max_depth: 32,
+            max_files: 200_000,
+        }
+    }
+}
+
+/// Finds indexed files that changed on disk by their modification times,
+/// for `br-lsp.index.pollIntervalSeconds` where the client's watcher misses
+/// changes (network shares).
+#[derive(Debug, Default)]
+pub struct ChangePoller {
+    /// Modification time each file had at the last poll
+    seen: HashMap<Url, SystemTime>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PolledChanges {
+    pub changed: Vec<Url>,
+    pub deleted: Vec<Url>,
+}
+
+impl ChangePoller {
+    /// Compare `files` (with when each was indexed) against the disk. A
+    /// file is changed when its modification time moved since the last
+    /// poll, or on its first poll when it's newer than the index entry.
+    /// Files that can't be checked for another reason (the share being
+    /// unreachable) are left alone. Blocks.
+    pub fn poll(&mut self, files: &[(Url, SystemTime)]) -> PolledChanges {
+        let mut changes = PolledChanges::default();
+        let mut seen = HashMap::with_capacity(files.len());
+        for (uri, indexed_at) in files {
+            let Ok(path) = uri.to_file_path() else {
+                continue;
+            };
+            let modified = match std::fs::metadata(&path).and_then(|m| m.modified()) {
+                Ok(modified) => modified,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    changes.deleted.push(uri.clone());
+                    continue;
+                }
+                Err(_) => {
+                    if let Some(&previous) = self.seen.get(uri) {
+                        seen.insert(uri.clone(), previous);
+                    }
+                    continue;
+                }
+            };
+            let changed = match self.seen.get(uri) {
+                Some(&previous) => previous != modified,
+                None => modified > *indexed_at,
+            };
+            if changed {
+                changes.changed.push(uri.clone());
+            }
+            seen.insert(uri.clone(), modified);
+        }
+        self.seen = seen;
+        changes
+    }
+}
+
+/// How hard the workspace scan works, from `br-lsp.indexing`. Files are
+/// read and parsed a batch at a time on a pool of `threads()` threads, with
+/// a pause between batches, so a scan over a network share doesn't swamp
+/// the share or the machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexingOptions {
+    /// Threads reading and parsing files; 0 for one per CPU, or one in
+    /// low-impact mode
+    pub parallelism: usize,
+    /// Files handed to the pool at a time
+    pub batch_size: usize,
+    /// Fewer threads and a pause between batches by default
+    pub low_impact: bool,
+    /// Pause between batches; `None` for the mode's default
+    pub batch_delay: Option<Duration>,
+}
+
+impl Default for IndexingOptions {
+    fn default() -> Self {
+        Self {
+            parallelism: 0,
+            batch_size: 256,
+            low_impact: false,
+            batch_delay: None,
+        }
+    }
+}
+
+/// Pause between batches in low-impact mode unless one is set
+const LOW_IMPACT_DELAY: Duration = Duration::from_millis(200);
+
+impl IndexingOptions {
+    pub fn from_settings(settings: Option<&serde_json::Value>) -> Self {
+        let defaults = Self::default();
+        let Some(settings) = settings else {
+            return defaults;
+        };
+        let number = |key: &str| settings.get(key).and_then(|v| v.as_u64());
+        Self {
+            parallelism: number("parallelism").map_or(defaults.parallelism, |n| n as usize),
+            batch_size: number("batchSize")
+                .filter(|&n| n > 0)
+                .map_or(defaults.batch_size, |n| n as usize),
+            low_impact: settings
+                .get("lowImpact")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(defaults.low_impact),
+            batch_delay: number("batchDelayMs").map(Duration::from_millis),
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        match self.parallelism {
+            0 if self.low_impact => 1,
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        }
+    }
+
+    pub fn batch_delay(&self) -> Duration {
+        self.batch_delay.unwrap_or(if self.low_impact {
+            LOW_IMPACT_DELAY
+        } else {
+            Duration::ZERO
+        })
+    }
+
+    /// Run `scan` on every path, a batch at a time on a pool of
+    /// [`threads`](Self::threads) threads, pausing between batches.
+    /// Results keep the order of `paths`; `None`s are dropped. Blocks.
+    pub fn scan<T: Send>(
+        &self,
+        paths: &[PathBuf],
+        scan: impl Fn(&Path) -> Option<T> + Sync,
+    ) -> Vec<T> {
+        let pool = match rayon::ThreadPoolBuilder::new()
+            .num_threads(self.threads())
+            .build()
+        {
+            Ok(pool) => pool,
+            Err(e) => {
+                log::warn!("Indexing on the global pool: {e}");
+                return paths.par_iter().filter_map(|p| scan(p)).collect();
+            }
+        };
+        let delay = self.batch_delay();
+        let mut results = Vec::new();
+        for (i, batch) in paths.chunks(self.batch_size.max(1)).enumerate() {
+            if i > 0 && !delay.is_zero() {
+                std::thread::sleep(delay);
+            }
+            results.extend(
+                pool.install(|| batch.par_iter().filter_map(|p| scan(p)).collect::<Vec<_>>()),
+            );
+        }
+        results
+    }
+}
+
+/// Why a walk left a path out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    /// A symlink back to a directory the walk is already inside
+    SymlinkLoop(PathBuf),
+    PermissionDenied,
+    /// A directory nested deeper than [`WalkBudget::max_depth`]
+    TooDeep,
+    Error(String),
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::SymlinkLoop(ancestor) => {
+                write!(f, "symlink loop back to {}", ancestor.display())
+            }
+            SkipReason::PermissionDenied => f.write_str("permission denied"),
+            SkipReason::TooDeep => f.write_str("nested too deep"),
+            SkipReason::Error(message) => f.write_str(message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedPath {
+    pub path: PathBuf,
+    pub reason: SkipReason,
+}
+
+/// Paths a walk couldn't or wouldn't enter, reported rather than dropped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WalkReport {
+    pub skipped: Vec<SkippedPath>,
+    /// Roots whose walk stopped at [`WalkBudget::max_files`]
+    pub truncated: Vec<PathBuf>,
+}
+
+impl WalkReport {
+    pub fn is_empty(&self) -> bool {
+        self.skipped.is_empty() && self.truncated.is_empty()
+    }
+
+    pub fn extend(&mut self, other: WalkReport) {
+        self.skipped.extend(other.skipped);
+        self.truncated.extend(other.truncated);
+    }
+
+    /// Drop what was reported for paths under `root`, for a workspace
+    /// folder that's gone or about to be walked again.
+    pub fn forget_under(&mut self, root: &Path) {
+        self.skipped.retain(|s| !s.path.starts_with(root));
+        self.truncated.retain(|t| !t.starts_with(root));
+    }
+
+    /// One line for a window message, e.g. "2 paths skipped (1 symlink
+    /// loop, 1 permission denied)".
+    pub fn summary(&self) -> String {
+        let mut kinds: Vec<(&str, usize)> = Vec::new();
+        for skipped in &self.skipped {
+            let kind = match skipped.reason {
+                SkipReason::SymlinkLoop(_) => "symlink loop",
+                SkipReason::PermissionDenied => "permission denied",
+                SkipReason::TooDeep => "too deep",
+                SkipReason::Error(_) => "unreadable",
+            };
+            match kinds.iter_mut().find(|(k, _)| *k == kind) {
+                Some((_, n)) => *n += 1,
+                None => kinds.push((kind, 1)),
+            }
+        }
+        let parts: Vec<String> = kinds
+            .iter()
+            .map(|(kind, n)| match (*kind, n) {
+                ("symlink loop", 2..) => format!("{n} symlink loops"),
+                _ => format!("{n} {kind}"),
+            })
+            .collect();
+
+        let mut out = match self.skipped.len() {
+            0 => String::new(),
+            1 => format!("1 path skipped ({})", parts.join(", ")),
+            n => format!("{n} paths skipped ({})", parts.join(", ")),
+        };
+        if !self.truncated.is_empty() {
+            if !out.is_empty() {
+                out.push_str("; ");
+            }
+            out.push_str(&format!(
+                "stopped early in {} (too many files)",
+                self.truncated
+                    .iter()
+                    .map(|t| t.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        out
+    }
+
+    fn record_error(&mut self, err: &ignore::Error) {
+        let Some(path) = error_path(err) else {
+            // Errors without a path come from parsing ignore files, which
+            // skip nothing
+            return;
+        };
+        let reason = if let Some(ancestor) = loop_ancestor(err) {
+            SkipReason::SymlinkLoop(ancestor.to_path_buf())
+        } else if err
+            .io_error()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::PermissionDenied)
+        {
+            SkipReason::PermissionDenied
+        } else {
+            SkipReason::Error(
+                err.io_error()
+                    .map(|e| e.to_string())
+                    .unwrap_or_else(|| err.to_string()),
+            )
+        };
+        self.skipped.push(SkippedPath {
+            path: path.to_path_buf(),
+            reason,
+        });
+    }
+}
+
+fn error_path(err: &ignore::Error) -> Option<&Path> {
+    match err {
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::Loop { child, .. } => Some(child),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+            error_path(err)
+        }
+        _ => None,
+    }
+}
+
+fn loop_ancestor(err: &ignore::Error) -> Option<&Path> {
+    match err {
+        ignore::Error::Loop { ancestor, .. } => Some(ancestor),
+        ignore::Error::WithPath { err, .. }
+        | ignore::Error::WithDepth { err, .. }
+        | ignore::Error::WithLineNumber { err, .. } => loop_ancestor(err),
+        _ => None,
+    }
+}
+
+/// Every BR source file under `root`, following symlinks.
+pub fn walk_br_files(root: &Path, detection: &BrFileDetection) -> Vec<PathBuf> {
+    walk_br_files_reporting(root, detection, &mut WalkReport::default())
+}
+
+/// [`walk_br_files`], adding what the walk skipped to `report`.
+pub fn walk_br_files_reporting(
+    root: &Path,
+    detection: &BrFileDetection,
+    report: &mut WalkReport,
+) -> Vec<PathBuf> {
+    walk_files_reporting(
+        root,
+        detection.respect_gitignore,
+        WalkBudget::default(),
+        report,
+    )
+    .into_iter()
+    .filter(|path| detection.is_br_file(path))
+    .collect()
+}
+
+/// Every file under `root`, following symlinks. With `respect_gitignore`,
+/// files and directories that `.gitignore` or `.ignore` files exclude are
+/// skipped, whether or not `root` is in a git repository.
+pub fn walk_files(root: &Path, respect_gitignore: bool) -> impl Iterator<Item = PathBuf> {
+    walk_files_reporting(
+        root,
+        respect_gitignore,
+        WalkBudget::default(),
+        &mut WalkReport::default(),
+    )
+    .into_iter()
+}
+
+/// [`walk_files`] within `budget`, adding symlink loops, unreadable
+/// directories and anything the budget cut off to `report`.
+pub fn walk_files_reporting(
+    root: &Path,
+    respect_gitignore: bool,
+    budget: WalkBudget,
+    report: &mut WalkReport,
+) -> Vec<PathBuf> {
+    let walk = WalkBuilder::new(root)
+        .follow_links(true)
+        .standard_filters(false)
+        .git_ignore(respect_gitignore)
+        .git_exclude(respect_gitignore)
+        .ignore(respect_gitignore)
+        .parents(respect_gitignore)
+        .require_git(false)
+        .max_depth(Some(budget.max_depth))
+        .build();
+
+    let mut files = Vec::new();
+    for entry in walk {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                report.record_error(&err);
+                continue;
+            }
+        };
+        let Some(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_file() {
+            if files.len() == budget.max_files {
+                report.truncated.push(root.to_path_buf());
+                break;
+            }
+            files.push(entry.into_path());
+        } else if file_type.is_dir()
+            && entry.depth() == budget.max_depth
+            && entry.depth() > 0
+            && std::fs::read_dir(entry.path()).is_ok_and(|mut d| d.next().is_some())
+        {
+            report.skipped.push(SkippedPath {
+                path: entry.into_path(),
+                reason: SkipReason::TooDeep,
+            });
+        }
+    }
+    files
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -258,6 +1172,7 @@ mod tests {
             has_param_substitution: false,
             documentation: None,
             return_documentation: None,
+            deprecated: None,
         }
     }
 
@@ -265,6 +1180,71 @@ mod tests {
         Url::parse(&format!("file:///workspace/{name}")).unwrap()
     }
 
+    #[test]
+    fn functions_in_matches_path_suffix_and_globs() {
+        let mut index = WorkspaceIndex::new();
+        index.add_file(
+            &test_url("vol002/RTFLib.brs"),
+            vec![make_def("fnRtf", true), make_def("fnHelper", false)],
+        );
+        index.add_file(&test_url("vol002/menu.brs"), vec![make_def("fnMenu", true)]);
+        let mut import = make_def("fnRtf", true);
+        import.is_import_only = true;
+        index.add_file(&test_url("main.brs"), vec![import]);
+
+        let names = |glob: &str| -> Vec<String> {
+            index
+                .functions_in(glob)
+                .iter()
+                .map(|e| e.def.name.clone())
+                .collect()
+        };
+        let mut rtflib = names("VOL002\\rtflib.br");
+        rtflib.sort();
+        assert_eq!(rtflib, vec!["fnHelper", "fnRtf"]);
+        assert_eq!(names("**/m*"), vec!["fnMenu"]);
+        assert_eq!(names("vol002/*").len(), 3);
+        assert!(names("lib").is_empty());
+        assert!(names("main").is_empty());
+    }
+
+    #[test]
+    fn names_defined_in_skips_imports_and_other_files() {
+        let mut index = WorkspaceIndex::new();
+        let lib = test_url("lib/rtflib.brs");
+        index.add_file(
+            &lib,
+            vec![make_def("fnRtf", true), make_def("FnHelper", false)],
+        );
+        index.add_file(&test_url("menu.brs"), vec![make_def("fnMenu", true)]);
+        let mut import = make_def("fnMenu", true);
+        import.is_import_only = true;
+        let main = test_url("main.brs");
+        index.add_file(&main, vec![import]);
+
+        let mut names: Vec<String> = index
+            .names_defined_in(&HashSet::from([lib, main]))
+            .into_iter()
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["fnhelper", "fnrtf"]);
+    }
+
+    #[test]
+    fn files_matching_includes_files_without_definitions() {
+        let mut index = WorkspaceIndex::new();
+        index.add_file(&test_url("lib/empty.brs"), Vec::new());
+        index.add_file(&test_url("lib/utils.brs"), vec![make_def("fnA", true)]);
+        assert_eq!(
+            index.files_matching("lib/empty"),
+            vec![&test_url("lib/empty.brs")]
+        );
+        assert_eq!(index.files_matching("lib/*").len(), 2);
+
+        index.remove_file(&test_url("lib/empty.brs"));
+        assert!(index.files_matching("empty").is_empty());
+    }
+
     #[test]
     fn add_and_lookup() {
         let mut index = WorkspaceIndex::new();
@@ -362,6 +1342,25 @@ mod tests {
         assert_eq!(cp437_to_char(0xFE), '\u{25A0}'); // ■
     }
 
+    #[test]
+    fn cp437_write_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("prog.brs");
+        std::fs::write(&path, b"00010 print \"\x80\xBA\"\r\n\x1A").unwrap();
+        let source = read_br_file(&path).unwrap();
+        assert_eq!(source, "00010 print \"\u{00C7}\u{2551}\"\r\n");
+
+        write_br_file(&path, &source.replace("print", "PRINT")).unwrap();
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            b"00010 PRINT \"\x80\xBA\"\r\n\x1A"
+        );
+
+        let err = write_br_file(&path, "00010 print \"\u{20AC}\"\n").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(std::fs::read(&path).unwrap().starts_with(b"00010 PRINT"));
+    }
+
     #[test]
     fn is_br_file_checks() {
         assert!(is_br_file(Path::new("foo.brs")));
@@ -372,6 +1371,196 @@ mod tests {
         assert!(!is_br_file(Path::new("foo")));
     }
 
+    #[test]
+    fn extensionless_first_line_heuristic() {
+        assert!(looks_like_br_source(b"00010 print \"hi\"\r\n", None));
+        assert!(looks_like_br_source(b"\n  100 ! menu program\n", None));
+        assert!(!looks_like_br_source(b"2024 was a year\n", Some("#!br")));
+        assert!(!looks_like_br_source(b"1234567 print\n", None));
+        assert!(!looks_like_br_source(b"#!/bin/sh\necho hi\n", None));
+        assert!(!looks_like_br_source(b"00010 print\0\0", None));
+    }
+
+    #[test]
+    fn extensionless_marker_on_first_line() {
+        assert!(looks_like_br_source(
+            b"#!/usr/bin/BR\n00010 print\n",
+            Some("#!/usr/bin/br")
+        ));
+        assert!(!looks_like_br_source(
+            b"00010 print\n! #!/usr/bin/br\n",
+            Some("#!/usr/bin/br")
+        ));
+    }
+
+    #[test]
+    fn detection_only_reads_extensionless_files_when_enabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = dir.path().join("MENU");
+        std::fs::write(&program, "00010 print \"menu\"\n").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "00010 print\n").unwrap();
+        std::fs::create_dir(dir.path().join(".git")).unwrap();
+        std::fs::write(dir.path().join(".git").join("HEAD"), "00010 ref\n").unwrap();
+
+        let off = BrFileDetection::default();
+        assert!(!off.is_br_file(&program));
+
+        let on = BrFileDetection::from_settings(Some(&serde_json::json!({ "enabled": true })));
+        assert!(on.is_br_file(&program));
+        assert_eq!(walk_br_files(dir.path(), &on), vec![program]);
+    }
+
+    #[test]
+    fn walk_skips_gitignored_files_unless_disabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = dir.path().join("menu.brs");
+        std::fs::write(&program, "print\n").unwrap();
+        std::fs::create_dir(dir.path().join("build")).unwrap();
+        let built = dir.path().join("build").join("menu.brs");
+        std::fs::write(&built, "print\n").unwrap();
+        std::fs::write(dir.path().join(".gitignore"), "build/\n").unwrap();
+
+        let detection = BrFileDetection::default();
+        assert_eq!(walk_br_files(dir.path(), &detection), vec![program.clone()]);
+
+        let all = BrFileDetection {
+            respect_gitignore: false,
+            ..BrFileDetection::default()
+        };
+        let mut found = walk_br_files(dir.path(), &all);
+        found.sort();
+        assert_eq!(found, vec![built, program]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn walk_reports_symlink_loops() {
+        let dir = tempfile::tempdir().unwrap();
+        let lib = dir.path().join("lib");
+        std::fs::create_dir(&lib).unwrap();
+        std::fs::write(lib.join("util.brs"), "print\n").unwrap();
+        std::os::unix::fs::symlink(dir.path(), lib.join("up")).unwrap();
+
+        let mut report = WalkReport::default();
+        let found = walk_br_files_reporting(dir.path(), &BrFileDetection::default(), &mut report);
+        assert_eq!(found, vec![lib.join("util.brs")]);
+        assert_eq!(
+            report.skipped,
+            vec![SkippedPath {
+                path: lib.join("up"),
+                reason: SkipReason::SymlinkLoop(dir.path().to_path_buf()),
+            }]
+        );
+        assert_eq!(report.summary(), "1 path skipped (1 symlink loop)");
+
+        report.forget_under(&lib);
+        assert!(report.is_empty());
+    }
+
+    #[test]
+    fn change_poller_finds_changed_and_deleted_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lib.brs");
+        std::fs::write(&path, "def fnA = 1\n").unwrap();
+        let uri = Url::from_file_path(&path).unwrap();
+        let gone = Url::from_file_path(dir.path().join("gone.brs")).unwrap();
+        let indexed_at = SystemTime::now() + Duration::from_secs(60);
+
+        let mut poller = ChangePoller::default();
+        let files = vec![(uri.clone(), indexed_at), (gone.clone(), indexed_at)];
+        let first = poller.poll(&files);
+        assert!(first.changed.is_empty());
+        assert_eq!(first.deleted, vec![gone]);
+        assert_eq!(poller.poll(&files[..1]), PolledChanges::default());
+
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(120))
+            .unwrap();
+        assert_eq!(poller.poll(&files[..1]).changed, vec![uri.clone()]);
+
+        // Indexed before its last modification, and not yet seen
+        let stale = vec![(uri.clone(), SystemTime::UNIX_EPOCH)];
+        assert_eq!(ChangePoller::default().poll(&stale).changed, vec![uri]);
+    }
+
+    #[test]
+    fn indexing_options_from_settings() {
+        let options = IndexingOptions::from_settings(Some(&serde_json::json!({
+            "lowImpact": true,
+            "batchSize": 0,
+        })));
+        assert_eq!(options.threads(), 1);
+        assert_eq!(options.batch_size, 256);
+        assert_eq!(options.batch_delay(), LOW_IMPACT_DELAY);
+
+        let options = IndexingOptions::from_settings(Some(&serde_json::json!({
+            "parallelism": 3,
+            "lowImpact": true,
+            "batchDelayMs": 0,
+        })));
+        assert_eq!(options.threads(), 3);
+        assert_eq!(options.batch_delay(), Duration::ZERO);
+        assert!(IndexingOptions::default().threads() >= 1);
+    }
+
+    #[test]
+    fn indexing_scan_keeps_order_across_batches() {
+        let paths: Vec<PathBuf> = (0..10)
+            .map(|i| PathBuf::from(format!("f{i}.brs")))
+            .collect();
+        let options = IndexingOptions {
+            parallelism: 2,
+            batch_size: 3,
+            low_impact: true,
+            batch_delay: Some(Duration::from_millis(1)),
+        };
+        let scanned = options.scan(&paths, |path| {
+            let name = path.to_str()?;
+            (name != "f4.brs").then(|| name.to_string())
+        });
+        assert_eq!(scanned.len(), 9);
+        assert_eq!(scanned[0], "f0.brs");
+        assert_eq!(scanned[4], "f5.brs");
+        assert_eq!(scanned[8], "f9.brs");
+    }
+
+    #[test]
+    fn walk_budget_reports_deep_directories_and_stops_at_max_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let deep = dir.path().join("a").join("b").join("c");
+        std::fs::create_dir_all(&deep).unwrap();
+        std::fs::create_dir(dir.path().join("a").join("b").join("empty")).unwrap();
+        std::fs::write(deep.join("deep.brs"), "print\n").unwrap();
+        std::fs::write(dir.path().join("a").join("top.brs"), "print\n").unwrap();
+
+        let budget = WalkBudget {
+            max_depth: 2,
+            max_files: 10,
+        };
+        let mut report = WalkReport::default();
+        let found = walk_files_reporting(dir.path(), false, budget, &mut report);
+        assert_eq!(found, vec![dir.path().join("a").join("top.brs")]);
+        assert_eq!(
+            report.skipped,
+            vec![SkippedPath {
+                path: dir.path().join("a").join("b"),
+                reason: SkipReason::TooDeep,
+            }]
+        );
+
+        let budget = WalkBudget {
+            max_depth: 32,
+            max_files: 1,
+        };
+        let mut report = WalkReport::default();
+        assert_eq!(
+            walk_files_reporting(dir.path(), false, budget, &mut report).len(),
+            1
+        );
+        assert_eq!(report.truncated, vec![dir.path().to_path_buf()]);
+        assert!(report.summary().starts_with("stopped early in "));
+    }
+
     fn make_def_full(name: &str, is_library: bool, is_import_only: bool) -> FunctionDef {
         FunctionDef {
             name: name.to_string(),
@@ -383,6 +1572,7 @@ mod tests {
             has_param_substitution: false,
             documentation: None,
             return_documentation: None,
+            deprecated: None,
         }
     }
 
@@ -468,6 +1658,7 @@ mod tests {
             has_param_substitution: false,
             documentation: None,
             return_documentation: None,
+            deprecated: None,
         };
         index.add_file(&uri, vec![def]);
 
@@ -576,6 +1767,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn relative_path_keeps_case_and_extension() {
+        let folders = vec![Url::parse("file:///workspace").unwrap()];
+        let uri = Url::parse("file:///workspace/AR/Menu%20Main.brs").unwrap();
+        assert_eq!(
+            relative_path(&uri, &folders),
+            Some("AR/Menu Main.brs".to_string())
+        );
+        let outside = Url::parse("file:///other/menu.brs").unwrap();
+        assert_eq!(relative_path(&outside, &folders), None);
+    }
+
     #[test]
     fn lookup_prioritized_with_links_prefers_linked() {
         let mut index = WorkspaceIndex::new();
@@ -627,4 +1830,83 @@ mod tests {
         assert_eq!(results.len(), 2);
         assert_eq!(results[0].uri, local_uri, "local def should still win");
     }
+
+    #[test]
+    fn drive_mappings_split_on_prefix_boundaries() {
+        let mappings = DriveMappings::from_settings(Some(&serde_json::json!({
+            "VOL002\\": "shared/vol002",
+            "C:": "/srv/br",
+        })));
+        assert_eq!(
+            mappings.split("vol002\\RTFLib"),
+            Some(("shared/vol002", "RTFLib".to_string()))
+        );
+        assert_eq!(
+            mappings.split("c:\\menu\\main"),
+            Some(("/srv/br", "menu/main".to_string()))
+        );
+        assert_eq!(
+            mappings.split("c:menu"),
+            Some(("/srv/br", "menu".to_string()))
+        );
+        assert_eq!(mappings.split("vol0021/x"), None);
+        assert_eq!(mappings.split("other/x"), None);
+    }
+
+    #[test]
+    fn muted_files_match_relative_and_absolute_entries() {
+        let folders = [Url::parse("file:///ws/").unwrap()];
+        let uri = |path: &str| Url::from_file_path(path).unwrap();
+        let mut muted = MutedFiles::from_settings(Some(&serde_json::json!([
+            "vendor\\Gen.brs",
+            "/opt/lib/rtflib.brs",
+        ])));
+        assert!(muted.contains(&uri("/ws/vendor/gen.brs")));
+        assert!(muted.contains(&uri("/ws/sub/vendor/gen.brs")));
+        assert!(!muted.contains(&uri("/ws/myvendor/gen.brs")));
+        assert!(muted.contains(&uri("/opt/lib/rtflib.brs")));
+
+        assert!(muted.insert(&uri("/ws/src/menu.brs"), &folders));
+        assert!(!muted.insert(&uri("/ws/src/menu.brs"), &folders));
+        assert!(muted.insert(&uri("/other/x.brs"), &folders));
+        assert_eq!(
+            muted.entries(),
+            [
+                "vendor/Gen.brs",
+                "/opt/lib/rtflib.brs",
+                "src/menu.brs",
+                "/other/x.brs"
+            ]
+        );
+
+        assert!(muted.remove(&uri("/ws/vendor/gen.brs")));
+        assert!(!muted.remove(&uri("/ws/vendor/gen.brs")));
+        assert!(!muted.contains(&uri("/ws/vendor/gen.brs")));
+    }
+
+    #[test]
+    fn drive_mappings_rewrite_link_paths() {
+        let folder = Url::parse("file:///ws/").unwrap();
+        let mappings = DriveMappings::from_settings(Some(&serde_json::json!({
+            "vol002": "Shared/Vol002",
+            "lib": "/ws/common",
+            "ext": "/elsewhere",
+        })));
+        let folders = [folder];
+        assert_eq!(
+            mappings.map_link_path("vol002/rtflib", &folders),
+            "shared/vol002/rtflib"
+        );
+        assert_eq!(
+            mappings.map_link_path("lib/dates", &folders),
+            "common/dates"
+        );
+        assert_eq!(mappings.map_link_path("ext/dates", &folders), "ext/dates");
+        assert_eq!(mappings.map_link_path("custlib", &folders), "custlib");
+
+        let mut links = HashMap::new();
+        links.insert("fnrtf".to_string(), "vol002/rtflib".to_string());
+        mappings.map_library_links(&mut links, &folders);
+        assert_eq!(links["fnrtf"], "shared/vol002/rtflib");
+    }
 }